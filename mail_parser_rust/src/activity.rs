@@ -0,0 +1,258 @@
+//! Weekday × hour activity matrices.
+//!
+//! Each dated message is placed in a 7×24 grid by the local weekday and hour
+//! it was sent, in a chosen IANA zone, a fixed offset, or the sender's own
+//! offset from the `Date` header. Messages from the caller's own addresses
+//! count as sent and everything else as received, so the sent grid shows
+//! when a person actually works. Per-year grids come from the same pass.
+
+use crate::date::parse_date;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{first_address, Message};
+use crate::progress::Progress;
+use jiff::tz::{Offset, TimeZone};
+use jiff::Timestamp;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Rows are weekdays (Monday first), columns hours 0-23
+pub type Grid = Vec<Vec<usize>>;
+
+fn empty_grid() -> Grid {
+    vec![vec![0; 24]; 7]
+}
+
+/// Where local time is taken from
+enum Zone {
+    /// One zone for every message
+    Fixed(TimeZone),
+    /// Each message's own `Date` offset
+    Sender,
+}
+
+impl Zone {
+    fn parse(tz: &str) -> PyResult<Self> {
+        let error = |detail: String| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid time zone {tz:?}: {detail}"
+            ))
+        };
+        let trimmed = tz.trim();
+        if trimmed.eq_ignore_ascii_case("sender") {
+            return Ok(Self::Sender);
+        }
+        if trimmed.eq_ignore_ascii_case("utc") {
+            return Ok(Self::Fixed(TimeZone::UTC));
+        }
+        if let Some(minutes) = parse_offset(trimmed) {
+            let offset = Offset::from_seconds(minutes * 60).map_err(|e| error(e.to_string()))?;
+            return Ok(Self::Fixed(TimeZone::fixed(offset)));
+        }
+        TimeZone::get(trimmed)
+            .map(Self::Fixed)
+            .map_err(|e| error(e.to_string()))
+    }
+
+    /// (year, weekday from Monday = 0, hour) of a message
+    fn local(&self, epoch: i64, sender_offset: i32) -> Option<(i32, usize, usize)> {
+        let timestamp = Timestamp::from_second(epoch).ok()?;
+        let civil = match self {
+            Self::Fixed(tz) => tz.to_datetime(timestamp),
+            Self::Sender => {
+                let offset = Offset::from_seconds(sender_offset * 60).ok()?;
+                offset.to_datetime(timestamp)
+            },
+        };
+        let weekday = usize::try_from(civil.weekday().to_monday_zero_offset()).ok()?;
+        let hour = usize::try_from(civil.hour()).ok()?;
+        Some((i32::from(civil.year()), weekday, hour))
+    }
+}
+
+/// `+HH:MM`, `-HHMM` or `+HH` as minutes east of UTC
+fn parse_offset(value: &str) -> Option<i32> {
+    let sign = match value.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = value[1..].chars().filter(|&c| c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || !matches!(digits.len(), 2 | 4) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits.get(2..).map_or(Ok(0), str::parse).ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Send/receive counts by local weekday and hour
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ActivityMatrix {
+    /// 7×24 counts of messages from `identities` (Monday first)
+    #[pyo3(get)]
+    pub sent: Grid,
+    /// 7×24 counts of all other messages (Monday first)
+    #[pyo3(get)]
+    pub received: Grid,
+    /// `sent` per local calendar year
+    #[pyo3(get)]
+    pub sent_by_year: HashMap<i32, Grid>,
+    /// `received` per local calendar year
+    #[pyo3(get)]
+    pub received_by_year: HashMap<i32, Grid>,
+    /// Messages without a parseable `Date`
+    #[pyo3(get)]
+    pub undated: usize,
+    /// The zone used, as given
+    #[pyo3(get)]
+    pub tz: String,
+}
+
+/// Count (sent, year, weekday, hour) observations; `None` is undated
+fn tally(observations: Vec<Option<(bool, i32, usize, usize)>>, tz: &str) -> ActivityMatrix {
+    let mut matrix = ActivityMatrix {
+        sent: empty_grid(),
+        received: empty_grid(),
+        tz: tz.to_string(),
+        ..ActivityMatrix::default()
+    };
+    for observation in observations {
+        let Some((sent, year, weekday, hour)) = observation else {
+            matrix.undated += 1;
+            continue;
+        };
+        let (total, by_year) = if sent {
+            (&mut matrix.sent, &mut matrix.sent_by_year)
+        } else {
+            (&mut matrix.received, &mut matrix.received_by_year)
+        };
+        total[weekday][hour] += 1;
+        by_year.entry(year).or_insert_with(empty_grid)[weekday][hour] += 1;
+    }
+    matrix
+}
+
+/// Weekday × hour send/receive matrix for an mbox archive
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `tz` - IANA zone ("Europe/Berlin"), "UTC", a fixed offset ("+05:30"),
+///   or "sender" to use each message's own `Date` offset (default "UTC")
+/// * `identities` - The person's own addresses; their messages count as
+///   sent (default: none, everything counts as received)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `ActivityMatrix` with 7×24 `sent` and `received` grids (rows Monday
+///   to Sunday, columns hours 0-23), per-year variants and `undated`
+///
+/// # Raises
+/// * `ValueError` for an unknown time zone
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import activity_matrix
+/// m = activity_matrix("me.mbox", "America/New_York", identities=["me@example.com"])
+/// late = sum(m.sent[day][hour] for day in range(7) for hour in range(22, 24))
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, tz="UTC", identities=None, from_line_mode="standard", progress=None))]
+pub fn activity_matrix(
+    py: Python<'_>,
+    mbox_path: &str,
+    tz: &str,
+    identities: Option<Vec<String>>,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<ActivityMatrix> {
+    py.allow_threads(|| {
+        let zone = Zone::parse(tz)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let identities: HashSet<String> = identities
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| a.trim().to_lowercase())
+            .collect();
+        let mmap = map_file(mbox_path)?;
+        let data = &mmap[..];
+        let progress = Progress::new(progress, data.len());
+        let observations = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| {
+                let msg = Message::parse(&data[span]);
+                let (epoch, offset) = parse_date(msg.header("Date")?)?;
+                let (year, weekday, hour) = zone.local(epoch, offset)?;
+                let sent = msg
+                    .header("From")
+                    .and_then(first_address)
+                    .is_some_and(|from| identities.contains(&from));
+                Some((sent, year, weekday, hour))
+            })
+            .collect();
+        progress.check("activity_matrix")?;
+        mmap.verify(&mode)?;
+        Ok(tally(observations, tz))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_local() {
+        // 2024-01-01 23:30 UTC, a Monday
+        let epoch = 1_704_151_800;
+        let utc = Zone::parse("UTC").unwrap();
+        assert_eq!(utc.local(epoch, 0), Some((2024, 0, 23)));
+        let east = Zone::parse("+05:30").unwrap();
+        assert_eq!(east.local(epoch, 0), Some((2024, 1, 5)));
+        let west = Zone::parse("-0800").unwrap();
+        assert_eq!(west.local(epoch, 0), Some((2024, 0, 15)));
+        assert_eq!(Zone::parse("sender").unwrap().local(epoch, -60), Some((2024, 0, 22)));
+        // 2023-12-31 in New York
+        let new_york = Zone::parse("America/New_York").unwrap();
+        assert_eq!(new_york.local(1_704_067_200, 0), Some((2023, 6, 19)));
+        assert!(Zone::parse("Mars/Olympus").is_err());
+        assert_eq!(parse_offset("+0530"), Some(330));
+        assert_eq!(parse_offset("+5"), None);
+    }
+
+    #[test]
+    fn test_activity_matrix() {
+        crate::with_py(|py| {
+            let path =
+                std::env::temp_dir().join(format!("mpr_activity_{}.mbox", std::process::id()));
+            let mbox = "From a Mon Jan  1 00:00:00 2024\nFrom: Me <ME@example.com>\n\
+                    Date: Mon, 1 Jan 2024 09:15:00 +0100\n\nx\n\n\
+                    From b Mon Jan  1 00:00:00 2024\nFrom: you@example.com\n\
+                    Date: Sun, 31 Dec 2023 23:00:00 +0000\n\ny\n\n\
+                    From c Mon Jan  1 00:00:00 2024\nFrom: you@example.com\n\nz\n";
+            std::fs::write(&path, mbox).unwrap();
+            let m = activity_matrix(
+                py,
+                path.to_str().unwrap(),
+                "sender",
+                Some(vec!["me@example.com".into()]),
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert_eq!(m.sent[0][9], 1);
+            assert_eq!(m.received[6][23], 1);
+            assert_eq!(m.undated, 1);
+            assert_eq!(m.sent_by_year[&2024][0][9], 1);
+            assert_eq!(m.received_by_year[&2023][6][23], 1);
+            assert!(!m.sent_by_year.contains_key(&2023));
+            std::fs::remove_file(path).unwrap();
+        });
+    }
+}
@@ -0,0 +1,354 @@
+//! RFC 5322 address-list parsing.
+//!
+//! Header values are tokenized into atoms, quoted strings, comments, domain
+//! literals and specials, then read as a list of mailboxes and groups. This
+//! keeps display names intact ("Doe, John" stays one name), finds the
+//! address inside angle brackets or next to comments, and drops obsolete
+//! source routes (`<@relay:user@host>`). Display names are RFC 2047 decoded;
+//! without a phrase, a trailing comment (`user@host (Name)`) supplies the
+//! name. Entries that carry no `@` (e.g. `undisclosed-recipients:;`) yield
+//! nothing.
+
+use crate::mime::decode_encoded_words;
+use pyo3::prelude::*;
+use serde::Serialize;
+
+/// One mailbox from an address header
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[pyclass]
+pub struct Address {
+    /// Decoded display name, if any
+    #[pyo3(get)]
+    pub name: Option<String>,
+    /// `local@domain` with the local part as written (quoted if needed)
+    /// and the domain lowercased
+    #[pyo3(get)]
+    pub email: String,
+    /// Lowercased domain
+    #[pyo3(get)]
+    pub domain: String,
+    /// Name of the group the mailbox was listed in (`Team: a@x, b@y;`)
+    #[pyo3(get)]
+    pub group: Option<String>,
+}
+
+#[pymethods]
+impl Address {
+    fn __repr__(&self) -> String {
+        self.name.as_ref().map_or_else(
+            || format!("Address(<{}>)", self.email),
+            |name| format!("Address({name:?} <{}>)", self.email),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    /// Run of atext and dots, or a `[domain literal]`
+    Atom(String),
+    /// Unescaped contents of a quoted string
+    Quoted(String),
+    /// Unescaped contents of a (possibly nested) comment
+    Comment(String),
+    Special(char),
+}
+
+/// Read a delimited run after its opening character, honouring backslash
+/// escapes and, for comments, nesting; returns the contents and the index
+/// after the closing delimiter (or the end of input)
+fn delimited(chars: &[char], mut i: usize, open: char, close: char) -> (String, usize) {
+    let mut out = String::new();
+    let mut depth = 1;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '\\' if i < chars.len() => {
+                out.push(chars[i]);
+                i += 1;
+            },
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return (out, i);
+                }
+                out.push(c);
+            },
+            c if c == open && open != close => {
+                depth += 1;
+                out.push(c);
+            },
+            c => out.push(c),
+        }
+    }
+    (out, i)
+}
+
+fn tokenize(value: &str) -> Vec<Token> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '"' => {
+                let (text, next) = delimited(&chars, i + 1, '"', '"');
+                tokens.push(Token::Quoted(text));
+                i = next;
+            },
+            '(' => {
+                let (text, next) = delimited(&chars, i + 1, '(', ')');
+                tokens.push(Token::Comment(text));
+                i = next;
+            },
+            '[' => {
+                let (text, next) = delimited(&chars, i + 1, '[', ']');
+                tokens.push(Token::Atom(format!("[{text}]")));
+                i = next;
+            },
+            '<' | '>' | '@' | ',' | ';' | ':' => {
+                tokens.push(Token::Special(c));
+                i += 1;
+            },
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !"\"()[]<>@,;:\\".contains(chars[i])
+                {
+                    i += 1;
+                }
+                // A stray backslash or bracket is kept as text
+                i = i.max(start + 1);
+                tokens.push(Token::Atom(chars[start..i].iter().collect()));
+            },
+        }
+    }
+    tokens
+}
+
+/// Words of a display name or group label, encoded-words decoded
+fn phrase(tokens: &[Token]) -> Option<String> {
+    let words: Vec<&str> = tokens
+        .iter()
+        .filter_map(|t| match t {
+            Token::Atom(s) | Token::Quoted(s) => Some(s.as_str()),
+            Token::Special(_) | Token::Comment(_) => None,
+        })
+        .collect();
+    let decoded = decode_encoded_words(&words.join(" "));
+    let name = decoded.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!name.is_empty()).then_some(name)
+}
+
+/// A quoted-string local part needs its quotes unless it is a dot-atom
+fn quote_local(text: &str) -> String {
+    let dot_atom = !text.is_empty()
+        && !text.starts_with('.')
+        && !text.ends_with('.')
+        && !text.contains("..")
+        && text
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && !"\"()[]<>@,;:\\".contains(c));
+    if dot_atom {
+        text.to_string()
+    } else {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// `local@domain` from addr-spec tokens (comments already removed)
+fn addr_spec(tokens: &[&Token]) -> Option<(String, String)> {
+    let at = tokens
+        .iter()
+        .rposition(|t| matches!(t, Token::Special('@')))?;
+    let mut local = String::new();
+    for token in &tokens[..at] {
+        match token {
+            Token::Atom(s) => local.push_str(s),
+            Token::Quoted(s) => local.push_str(&quote_local(s)),
+            Token::Special(c) => local.push(*c),
+            Token::Comment(_) => {},
+        }
+    }
+    let domain: String = tokens[at + 1..]
+        .iter()
+        .filter_map(|t| match t {
+            Token::Atom(s) => Some(s.as_str()),
+            _ => None,
+        })
+        .collect::<String>()
+        .to_lowercase();
+    (!local.is_empty() && !domain.is_empty()).then_some((local, domain))
+}
+
+/// Name and addr-spec tokens of `phrase <addr>`, `<` being at `open`
+fn angle_addr(tokens: &[Token], open: usize) -> (Option<String>, Vec<&Token>) {
+    let close = tokens[open..]
+        .iter()
+        .position(|t| *t == Token::Special('>'))
+        .map_or(tokens.len(), |p| open + p);
+    let mut inner: Vec<&Token> = tokens[open + 1..close]
+        .iter()
+        .filter(|t| !matches!(t, Token::Comment(_)))
+        .collect();
+    // Obsolete source route: "@relay1,@relay2:" before the address
+    if let Some(colon) = inner.iter().rposition(|t| **t == Token::Special(':')) {
+        inner.drain(..=colon);
+    }
+    (phrase(&tokens[..open]), inner)
+}
+
+/// Name (from the last comment) and addr-spec tokens of a bare address
+fn bare_addr(tokens: &[Token]) -> (Option<String>, Vec<&Token>) {
+    let comment = tokens.iter().rev().find_map(|t| match t {
+        Token::Comment(c) => phrase(&[Token::Atom(c.clone())]),
+        _ => None,
+    });
+    let spec = tokens
+        .iter()
+        .filter(|t| !matches!(t, Token::Comment(_)))
+        .collect();
+    (comment, spec)
+}
+
+/// One mailbox (`phrase <addr>` or bare `addr (comment)`)
+fn mailbox(tokens: &[Token], group: Option<&String>) -> Option<Address> {
+    let (name, spec) = tokens
+        .iter()
+        .position(|t| *t == Token::Special('<'))
+        .map_or_else(|| bare_addr(tokens), |open| angle_addr(tokens, open));
+    let (local, domain) = addr_spec(&spec)?;
+    Some(Address {
+        name,
+        email: format!("{local}@{domain}"),
+        domain,
+        group: group.cloned(),
+    })
+}
+
+/// Every mailbox in an address header value, in order
+pub fn parse_addresses(value: &str) -> Vec<Address> {
+    let tokens = tokenize(value);
+    let mut addresses = Vec::new();
+    let mut group: Option<String> = None;
+    let mut entry: Vec<Token> = Vec::new();
+    let mut angle = false;
+    for token in tokens {
+        match token {
+            Token::Special('<') => {
+                angle = true;
+                entry.push(token);
+            },
+            Token::Special('>') => {
+                angle = false;
+                entry.push(token);
+            },
+            // A colon before any '@' outside brackets opens a group
+            Token::Special(':') if !angle && !entry.contains(&Token::Special('@')) => {
+                group = phrase(&entry);
+                entry.clear();
+            },
+            Token::Special(c @ (',' | ';')) if !angle => {
+                addresses.extend(mailbox(&entry, group.as_ref()));
+                entry.clear();
+                if c == ';' {
+                    group = None;
+                }
+            },
+            token => entry.push(token),
+        }
+    }
+    addresses.extend(mailbox(&entry, group.as_ref()));
+    addresses
+}
+
+/// Parse an address header value into structured mailboxes (RFC 5322)
+///
+/// Handles quoted display names with commas, comments, address groups,
+/// angle brackets, quoted local parts and obsolete source routes. Names are
+/// RFC 2047 decoded; a bare address followed by a comment
+/// (`jdoe@example.com (John Doe)`) takes the comment as its name.
+///
+/// # Arguments
+/// * `header_value` - Value of a From/To/Cc/Reply-To style header
+///
+/// # Returns
+/// * List of `Address` objects with `name` (or None), `email`, `domain` and
+///   `group` (the enclosing group's name, or None)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_addresses_fast
+/// addrs = parse_addresses_fast('"Doe, John" <jdoe@Example.COM>, Team: ann@x.org (Ann);')
+/// print([(a.name, a.email, a.group) for a in addrs])
+/// # [('Doe, John', 'jdoe@example.com', None), ('Ann', 'ann@x.org', 'Team')]
+/// ```
+#[pyfunction]
+pub fn parse_addresses_fast(header_value: &str) -> Vec<Address> {
+    parse_addresses(header_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(value: &str) -> Vec<(Option<String>, String)> {
+        parse_addresses(value)
+            .into_iter()
+            .map(|a| (a.name, a.email))
+            .collect()
+    }
+
+    fn named(name: &str, email: &str) -> (Option<String>, String) {
+        (Some(name.to_string()), email.to_string())
+    }
+
+    #[test]
+    fn test_names_and_comments() {
+        assert_eq!(
+            pairs(r#""Doe, John" <John.Doe@Example.COM>, Jane Q. Public <jane@x.org>"#),
+            vec![
+                named("Doe, John", "John.Doe@example.com"),
+                named("Jane Q. Public", "jane@x.org")
+            ]
+        );
+        assert_eq!(
+            pairs("jdoe@example.com (John (Johnny) Doe), bare@x.org"),
+            vec![
+                named("John (Johnny) Doe", "jdoe@example.com"),
+                (None, "bare@x.org".into())
+            ]
+        );
+        assert_eq!(
+            pairs("Pete(A nice \\) chap) <pete(his account)@silly.test(his host)>"),
+            vec![named("Pete", "pete@silly.test")]
+        );
+        assert_eq!(
+            pairs(r#"=?utf-8?Q?Ren=C3=A9?= <rene@x.fr>, "quoted \"name\"" <q@x.fr>"#),
+            vec![
+                named("Ren\u{e9}", "rene@x.fr"),
+                named("quoted \"name\"", "q@x.fr")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_groups_routes_and_local_parts() {
+        let addrs = parse_addresses("Team: ann@x.org, Bob <bob@y.org>; carol@z.org");
+        assert_eq!(addrs.len(), 3);
+        assert_eq!(addrs[1].group.as_deref(), Some("Team"));
+        assert_eq!(addrs[1].domain, "y.org");
+        assert_eq!(addrs[2].group, None);
+        assert!(parse_addresses("undisclosed-recipients:;").is_empty());
+        assert_eq!(
+            pairs(r#"<@relay.test,@mx.test:"john doe"@[192.0.2.1]>, "a.b"@x.org"#),
+            vec![
+                (None, "\"john doe\"@[192.0.2.1]".into()),
+                (None, "a.b@x.org".into())
+            ]
+        );
+        assert_eq!(pairs("a+tag@x.org; <>; Nobody"), vec![(None, "a+tag@x.org".into())]);
+    }
+}
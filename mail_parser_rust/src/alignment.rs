@@ -0,0 +1,354 @@
+//! DMARC-style identifier alignment: From domain vs DKIM `d=` and SPF domain.
+//!
+//! Verdicts are taken from the receiving server's records (the topmost
+//! `Authentication-Results` and `Received-SPF` fields); nothing is verified
+//! again here. A message passes when SPF passed for a domain aligned with the
+//! From domain, or a DKIM signature from an aligned domain passed. Relaxed
+//! alignment compares registrable domains (`mail.acme.com` ~ `acme.com`),
+//! strict alignment requires the exact domain.
+
+use crate::auth_results;
+use crate::batch::par_map;
+use crate::folders::base_domain;
+use crate::mime::{first_address, Message};
+use crate::patterns::compile;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+lazy_static! {
+    /// Identity properties of a `Received-SPF` (`envelope-from=`, `smtp.mailfrom=`)
+    pub static ref PROPERTY: Regex = compile(
+        "alignment.property",
+        r#"(?i)\b(smtp\.mailfrom|envelope-from)\s*=\s*"?([^\s;"()]+)"#
+    );
+    /// Signing domain tag of a `DKIM-Signature`
+    pub static ref DKIM_DOMAIN: Regex =
+        compile("alignment.dkim_domain", r"(?i)(?:^|;)\s*d\s*=\s*([^\s;]+)");
+}
+
+/// Alignment verdict for one message
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct MessageAlignment {
+    /// Domain of the From address
+    #[pyo3(get)]
+    pub from_domain: Option<String>,
+    /// SPF result recorded by the receiver ("pass", "fail", "softfail", ..., "none")
+    #[pyo3(get)]
+    pub spf: String,
+    /// Domain SPF was evaluated for (envelope sender)
+    #[pyo3(get)]
+    pub spf_domain: Option<String>,
+    /// SPF passed for a domain aligned with the From domain
+    #[pyo3(get)]
+    pub spf_aligned: bool,
+    /// Best DKIM result recorded by the receiver ("pass" if any signature passed)
+    #[pyo3(get)]
+    pub dkim: String,
+    /// Signing domains (`d=`) seen in signatures and results
+    #[pyo3(get)]
+    pub dkim_domains: Vec<String>,
+    /// A passing DKIM signature came from a domain aligned with the From domain
+    #[pyo3(get)]
+    pub dkim_aligned: bool,
+    /// "pass" (SPF or DKIM aligned), "fail", or "none" when the message
+    /// carries no authentication results at all
+    #[pyo3(get)]
+    pub dmarc: String,
+}
+
+/// Alignment rates for one From domain
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct DomainAlignment {
+    /// From domain
+    #[pyo3(get)]
+    pub domain: String,
+    #[pyo3(get)]
+    pub message_count: usize,
+    /// Messages with DMARC "pass", "fail" and "none"
+    #[pyo3(get)]
+    pub passed: usize,
+    #[pyo3(get)]
+    pub failed: usize,
+    #[pyo3(get)]
+    pub unauthenticated: usize,
+    /// Messages with aligned SPF / aligned DKIM
+    #[pyo3(get)]
+    pub spf_aligned: usize,
+    #[pyo3(get)]
+    pub dkim_aligned: usize,
+    /// `passed / message_count`
+    #[pyo3(get)]
+    pub pass_rate: f64,
+}
+
+/// Per-message verdicts plus per-domain aggregates
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct AlignmentReport {
+    /// One entry per input, in input order
+    #[pyo3(get)]
+    pub messages: Vec<MessageAlignment>,
+    /// One entry per From domain, lowest pass rate first
+    #[pyo3(get)]
+    pub domains: Vec<DomainAlignment>,
+}
+
+/// Lowercased domain of an address or bare domain (`@x.org` and `u@x.org` -> `x.org`)
+pub fn identity_domain(value: &str) -> String {
+    value
+        .rsplit('@')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+fn aligned(domain: &str, from_domain: &str, strict: bool) -> bool {
+    if strict {
+        domain == from_domain
+    } else {
+        base_domain(domain) == base_domain(from_domain)
+    }
+}
+
+fn property<'v>(clause: &'v str, names: &[&str]) -> Option<&'v str> {
+    PROPERTY
+        .captures_iter(clause)
+        .find(|caps| names.iter().any(|n| caps[1].eq_ignore_ascii_case(n)))
+        .and_then(|caps| caps.get(2))
+        .map(|m| m.as_str())
+}
+
+fn check(raw: &[u8], strict: bool) -> MessageAlignment {
+    let msg = Message::parse(raw);
+    let from_domain = msg
+        .decoded_header("From")
+        .as_deref()
+        .and_then(first_address)
+        .map(|address| identity_domain(&address));
+
+    let mut spf: Option<(String, Option<String>)> = None;
+    let mut dkim_results: Vec<(String, Option<String>)> = Vec::new();
+    let results = msg
+        .header("Authentication-Results")
+        .map(auth_results::parse_value)
+        .unwrap_or_default()
+        .results;
+    for clause in results {
+        let property = |name: &str| clause.properties.get(name).map(|v| identity_domain(v));
+        match clause.method.as_str() {
+            "spf" => {
+                let domain = property("smtp.mailfrom");
+                spf.get_or_insert((clause.result, domain));
+            },
+            "dkim" => {
+                let domain = property("header.d").or_else(|| property("header.i"));
+                dkim_results.push((clause.result, domain));
+            },
+            _ => {},
+        }
+    }
+    if spf.is_none() {
+        spf = msg.header("Received-SPF").map(|value| {
+            let result = value
+                .split_whitespace()
+                .next()
+                .unwrap_or("none")
+                .to_ascii_lowercase();
+            let domain = property(value, &["envelope-from", "smtp.mailfrom"]).map(identity_domain);
+            (result, domain)
+        });
+    }
+    // Without a usable SPF identity, DMARC falls back to the Return-Path
+    let (spf_result, spf_domain) = spf.unwrap_or_else(|| ("none".to_string(), None));
+    let spf_domain = spf_domain.or_else(|| {
+        msg.header("Return-Path")
+            .and_then(first_address)
+            .map(|address| identity_domain(&address))
+    });
+
+    let mut dkim_domains: Vec<String> = msg
+        .headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case("DKIM-Signature"))
+        .filter_map(|h| {
+            DKIM_DOMAIN
+                .captures(&h.value)
+                .map(|caps| identity_domain(&caps[1]))
+        })
+        .chain(dkim_results.iter().filter_map(|(_, d)| d.clone()))
+        .collect();
+    dkim_domains.sort();
+    dkim_domains.dedup();
+
+    let from = from_domain.as_deref().unwrap_or("");
+    let spf_aligned = !from.is_empty()
+        && spf_result == "pass"
+        && spf_domain
+            .as_deref()
+            .is_some_and(|d| aligned(d, from, strict));
+    let dkim_aligned = !from.is_empty()
+        && dkim_results.iter().any(|(result, domain)| {
+            result == "pass" && domain.as_deref().is_some_and(|d| aligned(d, from, strict))
+        });
+    let dkim = if dkim_results.iter().any(|(result, _)| result == "pass") {
+        "pass".to_string()
+    } else {
+        dkim_results
+            .first()
+            .map_or_else(|| "none".to_string(), |(result, _)| result.clone())
+    };
+    let authenticated = spf_result != "none" || !dkim_results.is_empty();
+    let dmarc = if spf_aligned || dkim_aligned {
+        "pass"
+    } else if authenticated {
+        "fail"
+    } else {
+        "none"
+    };
+    MessageAlignment {
+        from_domain,
+        spf: spf_result,
+        spf_domain,
+        spf_aligned,
+        dkim,
+        dkim_domains,
+        dkim_aligned,
+        dmarc: dmarc.to_string(),
+    }
+}
+
+/// `part / whole`, or 0 for an empty whole
+#[allow(clippy::cast_precision_loss)]
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+fn aggregate(messages: &[MessageAlignment]) -> Vec<DomainAlignment> {
+    let mut domains: BTreeMap<&str, DomainAlignment> = BTreeMap::new();
+    for message in messages {
+        let Some(domain) = message.from_domain.as_deref() else {
+            continue;
+        };
+        let entry = domains.entry(domain).or_insert_with(|| DomainAlignment {
+            domain: domain.to_string(),
+            ..DomainAlignment::default()
+        });
+        entry.message_count += 1;
+        match message.dmarc.as_str() {
+            "pass" => entry.passed += 1,
+            "fail" => entry.failed += 1,
+            _ => entry.unauthenticated += 1,
+        }
+        entry.spf_aligned += usize::from(message.spf_aligned);
+        entry.dkim_aligned += usize::from(message.dkim_aligned);
+    }
+    let mut domains: Vec<DomainAlignment> = domains
+        .into_values()
+        .map(|mut d| {
+            d.pass_rate = ratio(d.passed, d.message_count);
+            d
+        })
+        .collect();
+    domains.sort_by(|a, b| a.pass_rate.total_cmp(&b.pass_rate));
+    domains
+}
+
+/// Check DMARC-style alignment of From vs DKIM and SPF domains
+///
+/// Uses the verdicts the receiving server recorded in the topmost
+/// `Authentication-Results` (falling back to `Received-SPF` for SPF and the
+/// `Return-Path` for the SPF domain). A message passes when SPF passed for a
+/// domain aligned with the From domain, or a passing DKIM signature's `d=` is
+/// aligned with it; "none" means no authentication results were recorded.
+///
+/// # Arguments
+/// * `headers` - Header blocks (or whole raw messages), one per message
+/// * `strict` - Require exact domain matches instead of the same
+///   registrable domain (default false, DMARC's relaxed mode)
+///
+/// # Returns
+/// * `AlignmentReport` with one `MessageAlignment` per input and one
+///   `DomainAlignment` per From domain, worst pass rate first
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import alignment_check
+/// report = alignment_check(header_blocks)
+/// for d in report.domains[:10]:
+///     print(f"{d.domain}: {d.pass_rate:.0%} of {d.message_count} aligned")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (headers, strict=false))]
+pub fn alignment_check(headers: Vec<String>, strict: bool) -> AlignmentReport {
+    let messages = par_map(headers, |block| check(block.as_bytes(), strict));
+    let domains = aggregate(&messages);
+    AlignmentReport { messages, domains }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let signed = "Authentication-Results: mx.example.net; spf=softfail \
+                      smtp.mailfrom=bounce@esp.net; dkim=pass header.d=news.acme.com; \
+                      dkim=pass header.d=esp.net\n\
+                      DKIM-Signature: v=1; a=rsa-sha256; d=news.acme.com; s=k1\n\
+                      From: Acme <hello@acme.com>\n";
+        let relaxed = check(signed.as_bytes(), false);
+        assert_eq!(relaxed.from_domain.as_deref(), Some("acme.com"));
+        assert_eq!(relaxed.spf, "softfail");
+        assert_eq!(relaxed.spf_domain.as_deref(), Some("esp.net"));
+        assert!(!relaxed.spf_aligned);
+        assert!(relaxed.dkim_aligned);
+        assert_eq!(relaxed.dkim_domains, ["esp.net", "news.acme.com"]);
+        assert_eq!(relaxed.dmarc, "pass");
+        assert_eq!(check(signed.as_bytes(), true).dmarc, "fail");
+
+        let spf_only = "Received-SPF: pass (mx: domain of a@mail.shop.com designates 1.2.3.4) \
+                        envelope-from=\"a@mail.shop.com\";\nFrom: shop@shop.com\n";
+        let spf = check(spf_only.as_bytes(), false);
+        assert!(spf.spf_aligned);
+        assert_eq!(spf.dkim, "none");
+        assert_eq!(spf.dmarc, "pass");
+
+        let bare = check(b"Return-Path: <x@y.org>\nFrom: x@y.org\n", false);
+        assert_eq!(bare.dmarc, "none");
+        assert_eq!(bare.spf_domain.as_deref(), Some("y.org"));
+    }
+
+    #[test]
+    fn test_alignment_check() {
+        let pass = "Authentication-Results: mx; dkim=pass header.d=acme.com\nFrom: a@acme.com\n";
+        let fail = "Authentication-Results: mx; dkim=fail header.d=acme.com\nFrom: b@acme.com\n";
+        let other = "Authentication-Results: mx; spf=pass smtp.mailfrom=x@ok.org\nFrom: x@ok.org\n";
+        let report = alignment_check(
+            vec![
+                pass.into(),
+                fail.into(),
+                other.into(),
+                "Subject: none\n".into(),
+            ],
+            false,
+        );
+        assert_eq!(report.messages.len(), 4);
+        let domains: Vec<(&str, usize, usize)> = report
+            .domains
+            .iter()
+            .map(|d| (d.domain.as_str(), d.message_count, d.passed))
+            .collect();
+        assert_eq!(domains, [("acme.com", 2, 1), ("ok.org", 1, 1)]);
+        assert!((report.domains[0].pass_rate - 0.5).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,274 @@
+//! Header anomaly detection: structural constructs forged or broken mail
+//! tends to have and well-behaved software does not produce.
+//!
+//! Every check is local to the header block, so the result is a compact
+//! signal set for forensic triage:
+//!
+//! * `duplicate_header` - a field RFC 5322 allows once (From, Date, ...) twice
+//! * `missing_header` - no From, Date or Message-ID
+//! * `unparseable_date` - a Date no known format reads
+//! * `date_after_received` - Date later than the first relay received it
+//! * `received_out_of_order` - a relay received the message before the
+//!   relay that handed it over did
+//! * `nonstandard_encoding` - an RFC 2047 encoded-word where the syntax
+//!   forbids one (an address, a message ID, a date) or raw 8-bit text in a
+//!   structured field
+//!
+//! Clock comparisons allow [`CLOCK_SKEW_SECS`] of disagreement between hosts.
+
+use crate::batch::par_map;
+use crate::date::parse_date;
+use crate::mime::{Message, ENCODED_WORD};
+use crate::received::chain;
+use pyo3::prelude::*;
+use serde::Serialize;
+
+/// Fields RFC 5322 (section 3.6) allows at most once
+const SINGULAR: &[&str] = &[
+    "From",
+    "Sender",
+    "Reply-To",
+    "To",
+    "Cc",
+    "Bcc",
+    "Date",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+    "Subject",
+];
+
+/// Fields every message should carry
+const REQUIRED: &[&str] = &["From", "Date", "Message-ID"];
+
+/// Structured fields with no place for an encoded-word or 8-bit text
+const STRUCTURED: &[&str] = &[
+    "Date",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+    "Return-Path",
+    "MIME-Version",
+    "Content-Transfer-Encoding",
+];
+
+/// Address fields: encoded-words are allowed in display names only
+const ADDRESS: &[&str] = &["From", "Sender", "Reply-To", "To", "Cc", "Bcc"];
+
+/// Clock disagreement between hosts tolerated before times count as
+/// impossible
+pub const CLOCK_SKEW_SECS: i64 = 10 * 60;
+
+/// One suspicious construct in a header block
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct HeaderAnomaly {
+    /// What was found (see the module list, e.g. `"duplicate_header"`)
+    #[pyo3(get)]
+    pub kind: String,
+    /// Field it concerns, as written (None when not tied to one field)
+    #[pyo3(get)]
+    pub header: Option<String>,
+    /// Human-readable specifics
+    #[pyo3(get)]
+    pub detail: String,
+}
+
+#[pymethods]
+impl HeaderAnomaly {
+    fn __repr__(&self) -> String {
+        format!("HeaderAnomaly({}: {})", self.kind, self.detail)
+    }
+}
+
+fn anomaly(kind: &str, header: Option<&str>, detail: String) -> HeaderAnomaly {
+    HeaderAnomaly {
+        kind: kind.to_string(),
+        header: header.map(str::to_string),
+        detail,
+    }
+}
+
+/// Whether an address field puts an encoded-word inside an address rather
+/// than the display name (`<=?utf-8?q?ceo?=@corp.com>`)
+fn encoded_address(value: &str) -> bool {
+    value
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | '<' | '>'))
+        .any(|token| token.contains('@') && ENCODED_WORD.is_match(token))
+}
+
+fn detect(headers: &[u8]) -> Vec<HeaderAnomaly> {
+    let msg = Message::parse(headers);
+    let mut found = Vec::new();
+
+    for name in SINGULAR {
+        let count = msg
+            .headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(name))
+            .count();
+        if count > 1 {
+            found.push(anomaly(
+                "duplicate_header",
+                Some(name),
+                format!("{name} appears {count} times"),
+            ));
+        }
+    }
+    for name in REQUIRED {
+        if msg.header(name).is_none() {
+            found.push(anomaly("missing_header", Some(name), format!("no {name} field")));
+        }
+    }
+
+    for header in &msg.headers {
+        let is = |names: &[&str]| names.iter().any(|n| header.name.eq_ignore_ascii_case(n));
+        let detail = if is(STRUCTURED) && ENCODED_WORD.is_match(&header.value) {
+            "encoded-word in a structured field"
+        } else if is(STRUCTURED) && !header.value.is_ascii() {
+            "raw 8-bit text in a structured field"
+        } else if is(ADDRESS) && encoded_address(&header.value) {
+            "encoded-word inside an address"
+        } else {
+            continue;
+        };
+        found.push(anomaly("nonstandard_encoding", Some(&header.name), detail.to_string()));
+    }
+
+    let date = msg.header("Date");
+    let sent = date.and_then(parse_date).map(|(epoch, _)| epoch);
+    if let (Some(date), None) = (date, sent) {
+        found.push(anomaly("unparseable_date", Some("Date"), format!("cannot read {date:?}")));
+    }
+
+    let hops = chain(headers);
+    let first_received = hops.iter().find_map(|hop| hop.timestamp);
+    if let (Some(sent), Some(received)) = (sent, first_received) {
+        if sent - received > CLOCK_SKEW_SECS {
+            found.push(anomaly(
+                "date_after_received",
+                Some("Date"),
+                format!("dated {}s after the first relay received it", sent - received),
+            ));
+        }
+    }
+    for (i, hop) in hops.iter().enumerate() {
+        if let Some(delay) = hop.delay_secs.filter(|&d| d < -CLOCK_SKEW_SECS) {
+            found.push(anomaly(
+                "received_out_of_order",
+                Some("Received"),
+                format!(
+                    "hop {} ({}) received it {}s before hop {} did",
+                    i + 1,
+                    hop.by_host.as_deref().unwrap_or("unknown host"),
+                    -delay,
+                    i
+                ),
+            ));
+        }
+    }
+    found
+}
+
+/// Flag suspicious constructs in a header block
+///
+/// A compact forensic signal set: duplicated singular fields (two From),
+/// missing From/Date/Message-ID, an unreadable Date, a Date later than the
+/// first relay's receipt, `Received` times running backwards, and
+/// encoded-words or 8-bit text where structured fields do not allow them.
+/// Times may disagree by up to 10 minutes before they count.
+///
+/// # Arguments
+/// * `headers` - Header block (or whole raw message)
+///
+/// # Returns
+/// * `HeaderAnomaly` list with `kind` (`"duplicate_header"`,
+///   `"missing_header"`, `"unparseable_date"`, `"date_after_received"`,
+///   `"received_out_of_order"` or `"nonstandard_encoding"`), the `header`
+///   concerned and a `detail` text; empty for a clean block
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import header_anomalies
+/// for a in header_anomalies(header_block):
+///     print(a.kind, a.header, a.detail)
+/// ```
+#[pyfunction]
+pub fn header_anomalies(headers: &str) -> Vec<HeaderAnomaly> {
+    detect(headers.as_bytes())
+}
+
+/// Batch version of [`header_anomalies`], processed in parallel
+///
+/// # Arguments
+/// * `header_blocks` - Header blocks (or whole raw messages)
+///
+/// # Returns
+/// * One anomaly list per block
+#[pyfunction]
+pub fn header_anomalies_batch(
+    py: Python<'_>,
+    header_blocks: Vec<String>,
+) -> Vec<Vec<HeaderAnomaly>> {
+    py.allow_threads(|| par_map(header_blocks, |block| detect(block.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(headers: &str) -> Vec<(String, Option<String>)> {
+        header_anomalies(headers)
+            .into_iter()
+            .map(|a| (a.kind, a.header))
+            .collect()
+    }
+
+    #[test]
+    fn test_header_anomalies() {
+        let clean = "Received: from b by c; Mon, 1 Jan 2024 12:00:30 +0000\r\n\
+                     Received: from a by b; Mon, 1 Jan 2024 12:00:10 +0000\r\n\
+                     From: =?utf-8?q?Ann_B=C3=A9?= <ann@example.org>\r\n\
+                     Date: Mon, 1 Jan 2024 12:00:00 +0000\r\n\
+                     Message-ID: <1@example.org>\r\n\r\nbody\r\n";
+        assert!(header_anomalies(clean).is_empty());
+
+        let forged = "Received: from b by c.example; Mon, 1 Jan 2024 11:00:00 +0000\r\n\
+                      Received: from a by b; Mon, 1 Jan 2024 12:00:10 +0000\r\n\
+                      From: Ann <ann@example.org>\r\n\
+                      from: <=?utf-8?q?ceo?=@example.org>\r\n\
+                      Date: Tue, 2 Jan 2024 12:00:00 +0000\r\n\
+                      Message-ID: =?utf-8?b?PDFAeD4=?=\r\n\
+                      References: <1@x> <2@caf\u{e9}>\r\n\r\n";
+        let found = kinds(forged);
+        let expect = |kind: &str, header: &str| (kind.to_string(), Some(header.to_string()));
+        assert_eq!(
+            found,
+            [
+                expect("duplicate_header", "From"),
+                expect("nonstandard_encoding", "from"),
+                expect("nonstandard_encoding", "Message-ID"),
+                expect("nonstandard_encoding", "References"),
+                expect("date_after_received", "Date"),
+                expect("received_out_of_order", "Received"),
+            ]
+        );
+        let out_of_order = header_anomalies(forged).pop().unwrap();
+        assert_eq!(out_of_order.detail, "hop 2 (c.example) received it 3610s before hop 1 did");
+
+        let bare = kinds("Subject: hi\r\nDate: yesterday\r\n");
+        assert_eq!(
+            bare,
+            [
+                expect("missing_header", "From"),
+                expect("missing_header", "Message-ID"),
+                expect("unparseable_date", "Date"),
+            ]
+        );
+
+        crate::with_py(|py| {
+            let batch = header_anomalies_batch(py, vec![clean.into(), forged.into()]);
+            assert_eq!((batch[0].len(), batch[1].len()), (0, 6));
+        });
+    }
+}
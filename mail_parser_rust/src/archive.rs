@@ -0,0 +1,452 @@
+//! Bounded extraction of zip attachments.
+//!
+//! Archives are untrusted input, so every entry is checked before anything is
+//! written: names that are absolute or climb out with `..` are refused,
+//! symlinks are never created, and entry count plus per-entry and total
+//! uncompressed sizes are capped. Sizes are enforced on the bytes actually
+//! inflated, not the sizes the archive claims. Encrypted entries are reported
+//! and skipped instead of failing the whole archive.
+
+use crate::mime::{Message, Part};
+use crate::output::{Compression, Output, OutputReport};
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use zip::ZipArchive;
+
+/// What an extraction produced and what it refused
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ZipReport {
+    /// Extracted files, relative to `dest` with `/` separators
+    #[pyo3(get)]
+    pub extracted: Vec<String>,
+    /// Entries not extracted, as `"name: reason"`
+    #[pyo3(get)]
+    pub skipped: Vec<String>,
+    /// Whether any entry was encrypted (those are listed in `skipped`)
+    #[pyo3(get)]
+    pub encrypted: bool,
+    /// Whether `max_entries` or `max_total_bytes` stopped extraction early
+    #[pyo3(get)]
+    pub truncated: bool,
+    /// File and byte totals (`dry_run` reports what would have been written)
+    #[pyo3(get)]
+    pub output: OutputReport,
+}
+
+#[pymethods]
+impl ZipReport {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Caps applied to one extraction call
+#[derive(Clone, Copy, Debug)]
+pub struct ZipLimits {
+    pub entries: usize,
+    pub entry_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Why an entry was not extracted; `Stop` also ends the archive
+enum Refusal {
+    Skip(String),
+    Stop(String),
+}
+
+/// Per-call state: limits, running totals and the report being built
+struct Extractor<'a> {
+    limits: ZipLimits,
+    output: &'a Output,
+    dest: &'a Path,
+    entries: usize,
+    total: u64,
+    report: ZipReport,
+}
+
+/// Relative, sanitized destination for an entry name, or `None` if unsafe
+fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => {
+                let part = crate::sanitize_filename_fast(&part.to_string_lossy()).ok()?;
+                if part.is_empty() {
+                    return None;
+                }
+                path.push(part);
+            },
+            Component::CurDir => {},
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (path.components().next().is_some()).then_some(path)
+}
+
+fn display(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+impl<'a> Extractor<'a> {
+    fn new(limits: ZipLimits, output: &'a Output, dest: &'a Path) -> Self {
+        Self {
+            limits,
+            output,
+            dest,
+            entries: 0,
+            total: 0,
+            report: ZipReport::default(),
+        }
+    }
+
+    /// Extract one archive under `dest/prefix`
+    fn extract(&mut self, data: &[u8], prefix: &Path) -> zip::result::ZipResult<()> {
+        let mut archive = ZipArchive::new(Cursor::new(data))?;
+        for index in 0..archive.len() {
+            let name = archive
+                .name_for_index(index)
+                .map_or_else(|| format!("#{index}"), ToString::to_string);
+            let name = display(&prefix.join(&name));
+            match self.extract_entry(&mut archive, index, prefix) {
+                Ok(Some(path)) => self.report.extracted.push(display(&path)),
+                Ok(None) => {},
+                Err(Refusal::Skip(reason)) => self.report.skipped.push(format!("{name}: {reason}")),
+                Err(Refusal::Stop(reason)) => {
+                    self.report.skipped.push(format!("{name}: {reason}"));
+                    self.report.truncated = true;
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracted path relative to `dest`, `None` for directories
+    fn extract_entry(
+        &mut self,
+        archive: &mut ZipArchive<Cursor<&[u8]>>,
+        index: usize,
+        prefix: &Path,
+    ) -> Result<Option<PathBuf>, Refusal> {
+        let skip = |reason: &str| Refusal::Skip(reason.to_string());
+        // Metadata only: `by_index` would fail outright on encrypted entries
+        let (relative, is_dir, declared) = {
+            let entry = archive
+                .by_index_raw(index)
+                .map_err(|e| Refusal::Skip(format!("unreadable entry ({e})")))?;
+            if entry.encrypted() {
+                self.report.encrypted = true;
+                return Err(skip("encrypted"));
+            }
+            if entry.is_symlink() {
+                return Err(skip("symlink"));
+            }
+            let relative = safe_relative_path(entry.name()).ok_or_else(|| skip("unsafe path"))?;
+            (prefix.join(relative), entry.is_dir(), entry.size())
+        };
+        if is_dir {
+            return Ok(None);
+        }
+
+        if self.entries >= self.limits.entries {
+            return Err(Refusal::Stop("entry limit reached".to_string()));
+        }
+        if declared > self.limits.entry_bytes {
+            return Err(skip("exceeds max_entry_bytes"));
+        }
+        let budget = self.limits.total_bytes - self.total;
+        if declared > budget {
+            return Err(Refusal::Stop("total size limit reached".to_string()));
+        }
+        let limit = self.limits.entry_bytes.min(budget);
+
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| Refusal::Skip(format!("unsupported entry ({e})")))?;
+        let path = self.dest.join(&relative);
+        let write = || -> io::Result<Option<u64>> {
+            if !self.output.is_dry_run() {
+                if let Some(parent) = path.parent() {
+                    crate::paths::create_dir_all(parent)?;
+                }
+            }
+            let mut file = self.output.create(&path)?;
+            // One byte past the limit tells a lying size header from an exact fit
+            let copied = io::copy(&mut entry.take(limit + 1), &mut file)?;
+            if copied > limit {
+                return Ok(None);
+            }
+            file.finish().map(Some)
+        };
+        match write() {
+            Ok(Some(bytes)) => {
+                self.entries += 1;
+                self.total += bytes;
+                Ok(Some(relative))
+            },
+            Ok(None) if limit < self.limits.entry_bytes => {
+                Err(Refusal::Stop("total size limit reached".to_string()))
+            },
+            Ok(None) => Err(skip("exceeds max_entry_bytes")),
+            Err(e) => Err(Refusal::Skip(format!("extraction failed ({e})"))),
+        }
+    }
+}
+
+/// Whether an attachment is a zip archive, by MIME type, extension or magic bytes
+fn is_zip(part: &Part<'_>, data: &[u8]) -> bool {
+    matches!(
+        part.mime_type(),
+        "application/zip" | "application/x-zip-compressed" | "application/x-zip"
+    ) || part
+        .filename()
+        .is_some_and(|name| name.to_ascii_lowercase().ends_with(".zip"))
+        || data.starts_with(b"PK\x03\x04")
+}
+
+fn prepare(
+    dest: &str,
+    max_entries: usize,
+    max_entry_bytes: u64,
+    max_total_bytes: u64,
+    dry_run: bool,
+    fsync: &str,
+) -> PyResult<(ZipLimits, Output)> {
+    // Entries are written verbatim: a `.gz` member must not be recompressed
+    let output = Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+    if !dry_run {
+        crate::paths::create_dir_all(Path::new(dest)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to create output directory: {e}"
+            ))
+        })?;
+    }
+    let limits = ZipLimits {
+        entries: max_entries,
+        entry_bytes: max_entry_bytes,
+        total_bytes: max_total_bytes,
+    };
+    Ok((limits, output))
+}
+
+/// Safely extract a zip archive
+///
+/// Entries with absolute or `..` paths and symlinks are refused, encrypted
+/// entries are skipped and flagged, and extraction is bounded by entry count
+/// and by actual (not declared) uncompressed sizes.
+///
+/// # Arguments
+/// * `data` - Zip archive bytes
+/// * `dest` - Output directory (created if missing)
+/// * `max_entries` - Maximum files extracted (default 1000)
+/// * `max_entry_bytes` - Maximum uncompressed size of one file (default 50 MiB)
+/// * `max_total_bytes` - Maximum uncompressed bytes overall (default 200 MiB)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+///
+/// # Returns
+/// * `ZipReport` with extracted paths, skipped entries and reasons, and the
+///   `encrypted`/`truncated` flags
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import extract_zip_fast
+/// report = extract_zip_fast(data, "unpacked/", max_total_bytes=10_000_000)
+/// if report.encrypted:
+///     print("password-protected entries:", report.skipped)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    data,
+    dest,
+    max_entries=1000,
+    max_entry_bytes=50 * 1024 * 1024,
+    max_total_bytes=200 * 1024 * 1024,
+    dry_run=false,
+    fsync="file"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn extract_zip_fast(
+    py: Python<'_>,
+    data: &[u8],
+    dest: &str,
+    max_entries: usize,
+    max_entry_bytes: u64,
+    max_total_bytes: u64,
+    dry_run: bool,
+    fsync: &str,
+) -> PyResult<ZipReport> {
+    py.allow_threads(|| {
+        let (limits, output) =
+            prepare(dest, max_entries, max_entry_bytes, max_total_bytes, dry_run, fsync)?;
+        let mut extractor = Extractor::new(limits, &output, Path::new(dest));
+        extractor.extract(data, Path::new("")).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid zip archive: {e}"))
+        })?;
+        extractor.report.output = output.report();
+        Ok(extractor.report)
+    })
+}
+
+/// Safely extract every zip attachment of a raw message
+///
+/// Each archive is unpacked into its own directory named after the
+/// attachment; limits apply to the message as a whole. Attachments that are
+/// not readable archives are listed in `skipped`.
+///
+/// # Arguments
+/// * `raw_bytes` - Complete RFC 822 message
+/// * `dest` - Output directory (created if missing)
+/// * `max_entries`, `max_entry_bytes`, `max_total_bytes`, `dry_run`, `fsync` -
+///   As for `extract_zip_fast`
+///
+/// # Returns
+/// * `ZipReport` covering all archives, paths prefixed by the archive directory
+#[pyfunction]
+#[pyo3(signature = (
+    raw_bytes,
+    dest,
+    max_entries=1000,
+    max_entry_bytes=50 * 1024 * 1024,
+    max_total_bytes=200 * 1024 * 1024,
+    dry_run=false,
+    fsync="file"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn extract_zip_attachments(
+    py: Python<'_>,
+    raw_bytes: &[u8],
+    dest: &str,
+    max_entries: usize,
+    max_entry_bytes: u64,
+    max_total_bytes: u64,
+    dry_run: bool,
+    fsync: &str,
+) -> PyResult<ZipReport> {
+    py.allow_threads(|| {
+        let (limits, output) =
+            prepare(dest, max_entries, max_entry_bytes, max_total_bytes, dry_run, fsync)?;
+        let mut extractor = Extractor::new(limits, &output, Path::new(dest));
+        let msg = Message::parse(raw_bytes);
+        for (n, part) in msg.parts().iter().filter(|p| p.is_attachment()).enumerate() {
+            let data = part.decoded();
+            if !is_zip(part, &data) {
+                continue;
+            }
+            let name = part
+                .filename()
+                .unwrap_or_else(|| format!("attachment-{}.zip", n + 1));
+            let stem = name
+                .strip_suffix(".zip")
+                .or_else(|| name.strip_suffix(".ZIP"))
+                .unwrap_or(&name);
+            let dir = safe_relative_path(stem)
+                .filter(|dir| dir.components().count() == 1)
+                .unwrap_or_else(|| PathBuf::from(format!("attachment-{}", n + 1)));
+            if let Err(e) = extractor.extract(&data, &dir) {
+                extractor
+                    .report
+                    .skipped
+                    .push(format!("{name}: not a readable zip archive ({e})"));
+            }
+            if extractor.report.truncated {
+                break;
+            }
+        }
+        extractor.report.output = output.report();
+        Ok(extractor.report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn sample_zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert_eq!(safe_relative_path("docs/./a.txt"), Some(PathBuf::from("docs/a.txt")));
+        assert_eq!(safe_relative_path("../etc/passwd"), None);
+        assert_eq!(safe_relative_path("/abs"), None);
+        assert_eq!(safe_relative_path("a\\..\\..\\b"), None);
+        assert_eq!(safe_relative_path("a/b:c?.txt"), Some(PathBuf::from("a/b_c_.txt")));
+    }
+
+    #[test]
+    fn test_extract_zip_limits() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_zip_{}", std::process::id()));
+            let dest = dir.to_str().unwrap();
+            let data = sample_zip(&[
+                ("docs/readme.txt", b"hello"),
+                ("../escape.txt", b"nope"),
+                ("big.bin", &[0u8; 64]),
+                ("last.txt", b"world"),
+            ]);
+
+            let report = extract_zip_fast(py, &data, dest, 10, 32, 1000, false, "none").unwrap();
+            assert_eq!(report.extracted, vec!["docs/readme.txt", "last.txt"]);
+            assert_eq!(
+                report.skipped,
+                vec![
+                    "../escape.txt: unsafe path",
+                    "big.bin: exceeds max_entry_bytes"
+                ]
+            );
+            assert!(!report.truncated && !report.encrypted);
+            assert_eq!(std::fs::read(dir.join("docs/readme.txt")).unwrap(), b"hello");
+            assert!(!dir.parent().unwrap().join("escape.txt").exists());
+
+            let report = extract_zip_fast(py, &data, dest, 1, 1000, 1000, true, "none").unwrap();
+            assert_eq!(report.extracted, vec!["docs/readme.txt"]);
+            assert!(report.truncated);
+            assert!(report.output.dry_run);
+
+            assert!(extract_zip_fast(py, b"not a zip", dest, 10, 10, 10, true, "none").is_err());
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_extract_zip_attachments() {
+        crate::with_py(|py| {
+            use base64::Engine;
+            let dir = std::env::temp_dir().join(format!("mpr_zip_att_{}", std::process::id()));
+            let dest = dir.to_str().unwrap();
+            let zip = base64::engine::general_purpose::STANDARD
+                .encode(sample_zip(&[("report.txt", b"numbers")]));
+            let raw = format!(
+                "Subject: files\nContent-Type: multipart/mixed; boundary=\"b\"\n\n\
+             --b\nContent-Type: text/plain\n\nsee attached\n\
+             --b\nContent-Type: application/zip; name=\"q3 data.zip\"\n\
+             Content-Disposition: attachment; filename=\"q3 data.zip\"\n\
+             Content-Transfer-Encoding: base64\n\n{zip}\n\
+             --b\nContent-Type: application/zip\n\
+             Content-Disposition: attachment; filename=\"broken.zip\"\n\nPK garbage\n--b--\n"
+            );
+            let report =
+                extract_zip_attachments(py, raw.as_bytes(), dest, 10, 1000, 1000, false, "none")
+                    .unwrap();
+            assert_eq!(report.extracted, vec!["q3 data/report.txt"]);
+            assert_eq!(report.skipped.len(), 1);
+            assert!(report.skipped[0].starts_with("broken.zip: not a readable zip archive"));
+            assert_eq!(std::fs::read(dir.join("q3 data/report.txt")).unwrap(), b"numbers");
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
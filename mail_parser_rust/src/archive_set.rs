@@ -0,0 +1,889 @@
+//! One search/lookup handle over many mbox files and Maildirs.
+//!
+//! Each registered source is scanned once into a small in-memory index
+//! (location, UID, date, sender, subject per message). Queries then run over
+//! every source in parallel and results say which source a message came
+//! from, so callers never route between archives themselves. Messages are
+//! addressed by their stable UID (see [`crate::uid`]). Attachment text (see
+//! [`crate::doc_text`]) is extracted on the first search that asks for it
+//! and kept with the entry. Custom fields (labels, headers, extracted
+//! entities) are declared up front with [`crate::index_schema`]. Maildir
+//! entries also keep their Dovecot IMAP UID and keywords (see
+//! [`crate::dovecot`]); the keywords count as labels.
+
+use crate::date::{format_utc, parse_date};
+use crate::doc_text::attachment_texts;
+use crate::dovecot::DovecotFolder;
+use crate::index_schema::{labels, IndexField, Schema, BUILTIN_FIELDS};
+use crate::maildir::{message_files, split_info};
+use crate::mbox::{map_file, FromLineMode, MappedFile};
+use crate::mime::{first_address, Message};
+use crate::progress::Progress;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Where a message lives inside its source
+enum Location {
+    /// Byte range in a memory-mapped mbox
+    Span(Range<usize>),
+    /// One file in a Maildir
+    File(PathBuf),
+}
+
+/// Index entry for one message
+struct Entry {
+    location: Location,
+    uid: String,
+    epoch: Option<i64>,
+    from: String,
+    subject: String,
+    /// Lowercased `(filename, text)` of attachments, once a search needed them
+    attachments: OnceLock<Vec<(String, String)>>,
+    /// Values of the custom schema fields, in schema order
+    values: Vec<Vec<String>>,
+    /// IMAP UID from a Maildir's `dovecot-uidlist`
+    imap_uid: Option<u32>,
+    /// Dovecot keywords of a Maildir file
+    keywords: Vec<String>,
+}
+
+impl Entry {
+    fn parse(
+        raw: &[u8],
+        location: Location,
+        schema: &Schema,
+        dovecot: Option<&DovecotFolder>,
+    ) -> Self {
+        let msg = Message::parse(raw);
+        let (imap_uid, keywords) = match (&location, dovecot) {
+            (Location::File(path), Some(dovecot)) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let flags = split_info(&name).1.unwrap_or_default();
+                (dovecot.uid_of(path), dovecot.keywords.names(flags))
+            },
+            _ => (None, Vec::new()),
+        };
+        Self {
+            values: schema.values(raw, &msg, &keywords),
+            imap_uid,
+            keywords,
+            location,
+            uid: message_uid(raw),
+            epoch: msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch),
+            from: msg
+                .header("From")
+                .and_then(first_address)
+                .unwrap_or_default(),
+            subject: msg.decoded_header("Subject").unwrap_or_default(),
+            attachments: OnceLock::new(),
+        }
+    }
+}
+
+/// A registered archive and its index
+struct Source {
+    name: String,
+    kind: &'static str,
+    path: String,
+    mmap: Option<MappedFile>,
+    entries: Vec<Entry>,
+}
+
+impl Source {
+    /// Raw bytes of one indexed message
+    fn raw(&self, entry: &Entry) -> std::io::Result<Cow<'_, [u8]>> {
+        match (&entry.location, &self.mmap) {
+            (Location::Span(span), Some(mmap)) => Ok(Cow::Borrowed(&mmap[span.clone()])),
+            (Location::File(path), _) => fs::read(path).map(Cow::Owned),
+            (Location::Span(_), None) => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+/// One search result
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ArchiveHit {
+    /// Name of the source the message is in
+    #[pyo3(get)]
+    pub source: String,
+    /// Stable message UID; pass to `ArchiveSet.get_message`
+    #[pyo3(get)]
+    pub uid: String,
+    /// IMAP UID from the Maildir's `dovecot-uidlist` (None for mbox sources
+    /// and unlisted files)
+    #[pyo3(get)]
+    pub imap_uid: Option<u32>,
+    /// Position of the message within its source (0-based)
+    #[pyo3(get)]
+    pub position: usize,
+    /// Date as a Unix timestamp, when parseable
+    #[pyo3(get)]
+    pub date: Option<i64>,
+    /// Sender address
+    #[pyo3(get)]
+    pub from_addr: String,
+    /// Decoded subject
+    #[pyo3(get)]
+    pub subject: String,
+    /// Attachments whose text contains a query term
+    #[pyo3(get)]
+    pub attachments: Vec<String>,
+    /// Values of the stored custom fields, by field name
+    #[pyo3(get)]
+    pub fields: HashMap<String, Vec<String>>,
+}
+
+#[pymethods]
+impl ArchiveHit {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Size and date range of one registered source
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct SourceStats {
+    /// Source name
+    #[pyo3(get)]
+    pub name: String,
+    /// "mbox" or "maildir"
+    #[pyo3(get)]
+    pub kind: String,
+    /// Path the source was registered with
+    #[pyo3(get)]
+    pub path: String,
+    /// Number of indexed messages
+    #[pyo3(get)]
+    pub message_count: usize,
+    /// Earliest message date (Unix timestamp)
+    #[pyo3(get)]
+    pub first_date: Option<i64>,
+    /// Latest message date (Unix timestamp)
+    #[pyo3(get)]
+    pub last_date: Option<i64>,
+}
+
+/// Built-in `aggregate` facets
+const BUILT_IN_FACETS: &[&str] = &["from_domain", "year", "month", "source", "label"];
+
+/// What `aggregate` buckets messages by
+#[derive(Clone, Copy)]
+enum Facet {
+    FromDomain,
+    Year,
+    Month,
+    Source,
+    Label,
+    /// Custom schema field, by position
+    Custom(usize),
+}
+
+impl Facet {
+    /// Bucket keys of one message (none when the value is unknown)
+    fn keys(self, source: &Source, entry: &Entry, raw: &[u8]) -> Vec<String> {
+        match self {
+            Self::FromDomain => entry
+                .from
+                .rsplit_once('@')
+                .map(|(_, domain)| vec![domain.to_ascii_lowercase()])
+                .unwrap_or_default(),
+            Self::Year => entry
+                .epoch
+                .map(|e| vec![format_utc(e)[..4].to_string()])
+                .unwrap_or_default(),
+            Self::Month => entry
+                .epoch
+                .map(|e| vec![format_utc(e)[..7].to_string()])
+                .unwrap_or_default(),
+            Self::Source => vec![source.name.clone()],
+            Self::Label => {
+                let mut labels = labels(&Message::parse(raw));
+                for keyword in &entry.keywords {
+                    if !labels.contains(keyword) {
+                        labels.push(keyword.clone());
+                    }
+                }
+                labels
+            },
+            Self::Custom(index) => entry.values[index].clone(),
+        }
+    }
+}
+
+/// Fields a search looks at: built-in names and custom field positions
+struct Selection {
+    builtin: Vec<&'static str>,
+    custom: Vec<usize>,
+}
+
+/// Whether every term occurs (case-insensitively) somewhere in the selected
+/// fields; on a match, the attachments containing any term
+fn matches(
+    raw: &[u8],
+    terms: &[String],
+    selection: &Selection,
+    entry: &Entry,
+) -> Option<Vec<String>> {
+    let fields = &selection.builtin;
+    let msg = Message::parse(raw);
+    let mut haystack = String::new();
+    for &index in &selection.custom {
+        for value in &entry.values[index] {
+            haystack.push_str(value);
+            haystack.push('\n');
+        }
+    }
+    for name in ["Subject", "From", "To", "Cc"] {
+        if !fields.contains(&name.to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        if let Some(value) = msg.decoded_header(name) {
+            haystack.push_str(&value);
+            haystack.push('\n');
+        }
+    }
+    if fields.contains(&"body") {
+        haystack.push_str(&msg.text_body());
+    }
+    let haystack = haystack.to_lowercase();
+    let attachments: &[(String, String)] = if fields.contains(&"attachments") {
+        entry.attachments.get_or_init(|| {
+            attachment_texts(raw)
+                .into_iter()
+                .map(|(name, text)| (name, text.to_lowercase()))
+                .collect()
+        })
+    } else {
+        &[]
+    };
+    let found = terms.iter().all(|term| {
+        haystack.contains(term.as_str())
+            || attachments.iter().any(|(_, t)| t.contains(term.as_str()))
+    });
+    found.then(|| {
+        attachments
+            .iter()
+            .filter(|(_, text)| terms.iter().any(|term| text.contains(term.as_str())))
+            .map(|(name, _)| name.clone())
+            .collect()
+    })
+}
+
+/// Search and fetch messages across many mbox files and Maildirs
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import ArchiveSet
+/// archives = ArchiveSet()
+/// archives.add_mbox("2019.mbox")
+/// archives.add_maildir("~/Maildir/INBOX", name="inbox")
+/// for hit in archives.search("invoice overdue", limit=20):
+///     raw = archives.get_message(hit.uid)
+/// ```
+#[pyclass]
+#[derive(Default)]
+pub struct ArchiveSet {
+    sources: Vec<Source>,
+    schema: Schema,
+}
+
+impl ArchiveSet {
+    fn register(&mut self, source: Source) -> PyResult<usize> {
+        if self.sources.iter().any(|s| s.name == source.name) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Source {} is already registered",
+                source.name
+            )));
+        }
+        let count = source.entries.len();
+        self.sources.push(source);
+        Ok(count)
+    }
+
+    /// Resolve `search(fields=...)`; `None` selects every searchable field
+    fn selection(&self, fields: Option<Vec<String>>) -> PyResult<Selection> {
+        let Some(names) = fields else {
+            return Ok(Selection {
+                builtin: BUILTIN_FIELDS.to_vec(),
+                custom: (0..self.schema.fields.len())
+                    .filter(|&i| self.schema.fields[i].indexed)
+                    .collect(),
+            });
+        };
+        let mut selection = Selection {
+            builtin: Vec::new(),
+            custom: Vec::new(),
+        };
+        for name in names {
+            if let Some(builtin) = BUILTIN_FIELDS.iter().find(|f| **f == name) {
+                selection.builtin.push(builtin);
+            } else if let Some(index) = self
+                .schema
+                .position(&name)
+                .filter(|&i| self.schema.fields[i].indexed)
+            {
+                selection.custom.push(index);
+            } else {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown or unindexed search field: {name}"
+                )));
+            }
+        }
+        Ok(selection)
+    }
+
+    /// Apply `f` to every message matching `query` (see `search`), in parallel
+    fn find<T: Send>(
+        &self,
+        query: &str,
+        sources: Option<Vec<String>>,
+        fields: Option<Vec<String>>,
+        f: impl Fn(&Source, usize, &Entry, &[u8], Vec<String>) -> T + Sync,
+    ) -> PyResult<Vec<T>> {
+        let selection = self.selection(fields)?;
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let wanted: Option<HashSet<String>> = sources.map(|names| names.into_iter().collect());
+        Ok(self
+            .sources
+            .iter()
+            .filter(|s| {
+                wanted
+                    .as_ref()
+                    .map_or(true, |names| names.contains(&s.name))
+            })
+            .flat_map(|s| s.entries.iter().enumerate().map(move |(i, e)| (s, i, e)))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|(source, position, entry)| {
+                let raw = source.raw(entry).ok()?;
+                let attachments = if terms.is_empty() {
+                    Vec::new()
+                } else {
+                    matches(&raw, &terms, &selection, entry)?
+                };
+                Some(f(source, position, entry, &raw, attachments))
+            })
+            .collect())
+    }
+
+    /// Resolve an `aggregate` facet name
+    fn facet(&self, name: &str) -> PyResult<Facet> {
+        Ok(match name {
+            "from_domain" => Facet::FromDomain,
+            "year" => Facet::Year,
+            "month" => Facet::Month,
+            "source" => Facet::Source,
+            "label" => Facet::Label,
+            _ => Facet::Custom(
+                self.schema
+                    .position(name)
+                    .filter(|&i| self.schema.fields[i].facet)
+                    .ok_or_else(|| {
+                        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                            "Unknown facet: {name} (expected one of {} or a custom field \
+                             declared with facet=True)",
+                            BUILT_IN_FACETS.join(", ")
+                        ))
+                    })?,
+            ),
+        })
+    }
+
+    /// Stored custom field values of an entry, by name
+    fn stored_fields(&self, entry: &Entry) -> HashMap<String, Vec<String>> {
+        self.schema
+            .fields
+            .iter()
+            .zip(&entry.values)
+            .filter(|(field, _)| field.stored)
+            .map(|(field, values)| (field.name.clone(), values.clone()))
+            .collect()
+    }
+
+    /// Raw bytes of the message with `uid`, from the first source holding it
+    fn message_bytes(&self, uid: &str) -> PyResult<Cow<'_, [u8]>> {
+        let found = self
+            .sources
+            .iter()
+            .find_map(|s| s.entries.iter().find(|e| e.uid == uid).map(|e| (s, e)));
+        let (source, entry) = found.ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("No message with UID {uid}"))
+        })?;
+        source.raw(entry).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to read message from {}: {e}",
+                source.name
+            ))
+        })
+    }
+}
+
+#[pymethods]
+impl ArchiveSet {
+    /// Create an empty set
+    ///
+    /// # Arguments
+    /// * `schema` - Custom `IndexField`s to index in addition to the built-in
+    ///   fields
+    ///
+    /// # Raises
+    /// * `ValueError` for duplicate field names or unknown extractor kinds
+    #[new]
+    #[pyo3(signature = (schema=None))]
+    fn new(schema: Option<Vec<IndexField>>) -> PyResult<Self> {
+        Ok(Self {
+            sources: Vec::new(),
+            schema: Schema::new(schema.unwrap_or_default())?,
+        })
+    }
+
+    /// The custom fields declared when the set was created
+    #[getter]
+    fn schema(&self) -> Vec<IndexField> {
+        self.schema.fields.clone()
+    }
+
+    /// Register and index an mbox file
+    ///
+    /// # Arguments
+    /// * `path` - Path to the mbox file
+    /// * `name` - Source name used in results (default: `path`)
+    /// * `from_line_mode` - Message separator rule: "standard" (default),
+    ///   "strict", "lenient", "permissive" or a custom regex (see
+    ///   `count_messages_fast`)
+    /// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+    ///   while messages are read; returning False cancels the walk
+    ///
+    /// # Returns
+    /// * Number of messages indexed
+    ///
+    /// # Raises
+    /// * `ScanCancelled` if `progress` returned False (or what it raised)
+    #[pyo3(signature = (path, name=None, from_line_mode="standard", progress=None))]
+    fn add_mbox(
+        &mut self,
+        py: Python<'_>,
+        path: &str,
+        name: Option<String>,
+        from_line_mode: &str,
+        progress: Option<PyObject>,
+    ) -> PyResult<usize> {
+        py.allow_threads(|| {
+            let mode = FromLineMode::parse(from_line_mode)?;
+            let mmap = map_file(path)?;
+            let progress = Progress::new(progress, mmap.len());
+            let entries = mmap
+                .spans(&mode)
+                .into_par_iter()
+                .filter(|span| progress.message(span))
+                .map(|span| {
+                    Entry::parse(&mmap[span.clone()], Location::Span(span), &self.schema, None)
+                })
+                .collect();
+            progress.check("add_mbox")?;
+            mmap.verify(&mode)?;
+            self.register(Source {
+                name: name.unwrap_or_else(|| path.to_string()),
+                kind: "mbox",
+                path: path.to_string(),
+                mmap: Some(mmap),
+                entries,
+            })
+        })
+    }
+
+    /// Register and index a Maildir (messages in `new/` and `cur/`)
+    ///
+    /// IMAP UIDs and keywords recorded by Dovecot (`dovecot-uidlist`,
+    /// `dovecot-keywords`) are kept: hits carry `imap_uid`, and keywords are
+    /// labels for the "label" facet and `labels` fields.
+    ///
+    /// # Arguments
+    /// * `path` - Maildir directory
+    /// * `name` - Source name used in results (default: `path`)
+    ///
+    /// # Returns
+    /// * Number of messages indexed
+    #[pyo3(signature = (path, name=None))]
+    fn add_maildir(&mut self, py: Python<'_>, path: &str, name: Option<String>) -> PyResult<usize> {
+        py.allow_threads(|| {
+            let read_error = |e: std::io::Error| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {path}: {e}"))
+            };
+            let files = message_files(Path::new(path), false).map_err(read_error)?;
+            let dovecot = DovecotFolder::load(Path::new(path)).map_err(read_error)?;
+            let entries = files
+                .into_par_iter()
+                .filter_map(|file| {
+                    let raw = fs::read(&file).ok()?;
+                    Some(Entry::parse(&raw, Location::File(file), &self.schema, Some(&dovecot)))
+                })
+                .collect();
+            self.register(Source {
+                name: name.unwrap_or_else(|| path.to_string()),
+                kind: "maildir",
+                path: path.to_string(),
+                mmap: None,
+                entries,
+            })
+        })
+    }
+
+    /// Names of the registered sources, in registration order
+    #[getter]
+    fn sources(&self) -> Vec<String> {
+        self.sources.iter().map(|s| s.name.clone()).collect()
+    }
+
+    /// Find messages containing every word of `query`
+    ///
+    /// Matching is case-insensitive over the decoded subject, From/To/Cc,
+    /// readable body text and the text of attachments (PDF, Office, ODF,
+    /// text and HTML files). All sources are searched in
+    /// parallel; attachment text is extracted once per message and reused.
+    ///
+    /// # Arguments
+    /// * `query` - Whitespace-separated terms; an empty query matches everything
+    /// * `sources` - Restrict the search to these source names
+    /// * `limit` - Return at most this many hits
+    /// * `fields` - Search only these of "subject", "from", "to", "cc", "body",
+    ///   "attachments" and the indexed custom fields (default: all)
+    ///
+    /// # Returns
+    /// * `ArchiveHit`s, newest first (undated messages last); `attachments`
+    ///   names the attachments a term was found in, `fields` holds the stored
+    ///   custom fields
+    ///
+    /// # Raises
+    /// * `ValueError` for an unknown or unindexed field name
+    #[pyo3(signature = (query, sources=None, limit=None, fields=None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        sources: Option<Vec<String>>,
+        limit: Option<usize>,
+        fields: Option<Vec<String>>,
+    ) -> PyResult<Vec<ArchiveHit>> {
+        py.allow_threads(|| {
+            let mut hits =
+                self.find(query, sources, fields, |source, position, entry, _, attachments| {
+                    ArchiveHit {
+                        source: source.name.clone(),
+                        uid: entry.uid.clone(),
+                        imap_uid: entry.imap_uid,
+                        position,
+                        date: entry.epoch,
+                        from_addr: entry.from.clone(),
+                        subject: entry.subject.clone(),
+                        attachments,
+                        fields: self.stored_fields(entry),
+                    }
+                })?;
+            hits.sort_by_key(|hit| std::cmp::Reverse(hit.date));
+            hits.truncate(limit.unwrap_or(usize::MAX));
+            Ok(hits)
+        })
+    }
+
+    /// Count the messages matching `query` per facet bucket
+    ///
+    /// Built-in facets are `"from_domain"` (sender domain), "year" and "month"
+    /// (UTC, "2024" / "2024-03"), "source" and "label" (X-Gmail-Labels,
+    /// X-Keywords, Keywords, X-Label, and the Dovecot keywords of Maildir
+    /// messages). Custom fields declared with `facet=True` can be named too. A message with several labels or field
+    /// values counts once in each bucket; one without a value is not counted.
+    ///
+    /// # Arguments
+    /// * `query` - Search terms as for `search` (empty: every message)
+    /// * `facet` - Facet name (default `"from_domain"`)
+    /// * `sources` - Restrict to these source names
+    /// * `fields` - Fields the query is matched against, as for `search`
+    /// * `limit` - Return at most this many buckets
+    ///
+    /// # Returns
+    /// * `(bucket, count)` pairs, largest count first (ties by bucket)
+    ///
+    /// # Raises
+    /// * `ValueError` for an unknown facet or field name
+    ///
+    /// # Example
+    /// ```python
+    /// for year, count in archives.aggregate("invoice", facet="year"):
+    ///     print(year, count)
+    /// ```
+    #[pyo3(signature = (query, facet="from_domain", sources=None, fields=None, limit=None))]
+    fn aggregate(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        facet: &str,
+        sources: Option<Vec<String>>,
+        fields: Option<Vec<String>>,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<(String, usize)>> {
+        py.allow_threads(|| {
+            let facet = self.facet(facet)?;
+            let keys = self.find(query, sources, fields, |source, _, entry, raw, _| {
+                facet.keys(source, entry, raw)
+            })?;
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for key in keys.into_iter().flatten() {
+                *counts.entry(key).or_default() += 1;
+            }
+            let mut buckets: Vec<(String, usize)> = counts.into_iter().collect();
+            buckets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            buckets.truncate(limit.unwrap_or(usize::MAX));
+            Ok(buckets)
+        })
+    }
+
+    /// Per-source message counts and date ranges
+    ///
+    /// # Returns
+    /// * One `SourceStats` per source, in registration order
+    fn stats(&self) -> Vec<SourceStats> {
+        self.sources
+            .iter()
+            .map(|s| SourceStats {
+                name: s.name.clone(),
+                kind: s.kind.to_string(),
+                path: s.path.clone(),
+                message_count: s.entries.len(),
+                first_date: s.entries.iter().filter_map(|e| e.epoch).min(),
+                last_date: s.entries.iter().filter_map(|e| e.epoch).max(),
+            })
+            .collect()
+    }
+
+    /// Raw bytes of a message by UID, from whichever source holds it
+    ///
+    /// # Arguments
+    /// * `uid` - Message UID from `search` or `compute_message_uid`
+    ///
+    /// # Returns
+    /// * The message exactly as stored (mbox messages include their `From ` line)
+    ///
+    /// # Raises
+    /// * `KeyError` if no registered source holds the message
+    fn get_message<'py>(&self, py: Python<'py>, uid: &str) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.message_bytes(uid)?))
+    }
+
+    fn __len__(&self) -> usize {
+        self.sources.iter().map(|s| s.entries.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_set() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_archive_set_{}", std::process::id()));
+            let maildir = dir.join("Maildir");
+            for sub in ["new", "cur", "tmp"] {
+                fs::create_dir_all(maildir.join(sub)).unwrap();
+            }
+            let mbox = dir.join("old.mbox");
+            fs::write(
+                &mbox,
+                "From a Mon Jan  1 00:00:00 2024\nFrom: ann@example.com\nSubject: Invoice 42\n\
+             Date: Mon, 1 Jan 2024 10:00:00 +0000\nMessage-ID: <1@x>\n\nPlease pay.\n\n\
+             From b Mon Jan  1 00:00:00 2024\nFrom: bob@example.com\nSubject: Lunch\n\
+             Date: Tue, 2 Jan 2024 10:00:00 +0000\nMessage-ID: <2@x>\n\nNoon?\n",
+            )
+            .unwrap();
+            fs::write(
+                maildir.join("cur").join("1700000000.1.host:2,Sa"),
+                "From: carol@example.com\r\nSubject: Re: invoice 42\r\n\
+             Date: Wed, 3 Jan 2024 10:00:00 +0000\r\nMessage-ID: <3@x>\r\n\r\nPaid today.\r\n",
+            )
+            .unwrap();
+            fs::write(maildir.join("new").join(".hidden"), "junk").unwrap();
+            fs::write(maildir.join("dovecot-uidlist"), "3 V1 N8\n7 :1700000000.1.host\n").unwrap();
+            fs::write(maildir.join("dovecot-keywords"), "0 Paid\n").unwrap();
+
+            let mut set = ArchiveSet::new(None).unwrap();
+            assert_eq!(
+                set.add_mbox(py, mbox.to_str().unwrap(), Some("old".into()), "standard", None)
+                    .unwrap(),
+                2
+            );
+            assert_eq!(
+                set.add_maildir(py, maildir.to_str().unwrap(), Some("inbox".into()))
+                    .unwrap(),
+                1
+            );
+            assert!(set
+                .add_mbox(py, mbox.to_str().unwrap(), Some("old".into()), "standard", None)
+                .is_err());
+            assert_eq!(set.__len__(), 3);
+
+            let hits = set.search(py, "INVOICE 42", None, None, None).unwrap();
+            let found: Vec<(&str, usize)> = hits
+                .iter()
+                .map(|h| (h.source.as_str(), h.position))
+                .collect();
+            assert_eq!(found, [("inbox", 0), ("old", 0)]);
+            assert_eq!(hits[0].from_addr, "carol@example.com");
+            assert_eq!((hits[0].imap_uid, hits[1].imap_uid), (Some(7), None));
+            assert_eq!(
+                set.aggregate(py, "", "label", None, None, None).unwrap(),
+                [("Paid".into(), 1)]
+            );
+            assert_eq!(
+                set.search(py, "pay", Some(vec!["inbox".into()]), None, None)
+                    .unwrap()
+                    .len(),
+                0
+            );
+            assert_eq!(set.search(py, "", None, Some(2), None).unwrap().len(), 2);
+            assert_eq!(
+                set.aggregate(py, "", "from_domain", None, None, None)
+                    .unwrap(),
+                [("example.com".into(), 3)]
+            );
+            assert_eq!(
+                set.aggregate(py, "invoice", "month", None, None, Some(1))
+                    .unwrap(),
+                [("2024-01".into(), 2)]
+            );
+
+            let stats = set.stats();
+            assert_eq!(stats[0].message_count, 2);
+            assert_eq!(stats[1].kind, "maildir");
+            assert!(stats[0].first_date < stats[0].last_date);
+
+            assert!(set
+                .message_bytes(&hits[1].uid)
+                .unwrap()
+                .starts_with(b"From a "));
+            assert!(set
+                .message_bytes(&hits[0].uid)
+                .unwrap()
+                .ends_with(b"Paid today.\r\n"));
+            assert!(set.message_bytes("missing").is_err());
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_search_attachments() {
+        crate::with_py(|py| {
+            let dir =
+                std::env::temp_dir().join(format!("mpr_archive_set_att_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let mbox = dir.join("a.mbox");
+            fs::write(
+                &mbox,
+                "From a Mon Jan  1 00:00:00 2024\nSubject: Statement\nMessage-ID: <1@x>\n\
+             Content-Type: multipart/mixed; boundary=b\n\n--b\nContent-Type: text/plain\n\n\
+             See attached.\n--b\nContent-Type: text/csv; name=\"q3.csv\"\n\n\
+             account,remittance\n42,100\n--b--\n\n\
+             From b Mon Jan  1 00:00:00 2024\nSubject: Remittance advice\nMessage-ID: <2@x>\n\n\
+             Nothing attached.\n",
+            )
+            .unwrap();
+            let mut set = ArchiveSet::new(None).unwrap();
+            set.add_mbox(py, mbox.to_str().unwrap(), None, "standard", None)
+                .unwrap();
+
+            let mut hits = set.search(py, "remittance", None, None, None).unwrap();
+            hits.sort_by_key(|h| h.position);
+            assert_eq!(hits.len(), 2);
+            assert_eq!(hits[0].attachments, ["q3.csv"]);
+            assert!(hits[1].attachments.is_empty());
+            // Terms may be split between body and attachment
+            assert_eq!(
+                set.search(py, "attached 42", None, None, None)
+                    .unwrap()
+                    .len(),
+                1
+            );
+
+            let only = Some(vec!["attachments".to_string()]);
+            let hits = set.search(py, "remittance", None, None, only).unwrap();
+            assert_eq!(hits.len(), 1);
+            assert_eq!(hits[0].position, 0);
+            let subject = Some(vec!["subject".to_string()]);
+            assert_eq!(set.search(py, "remittance", None, None, subject).unwrap()[0].position, 1);
+            assert!(set
+                .search(py, "x", None, None, Some(vec!["headers".into()]))
+                .is_err());
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_schema_fields() {
+        crate::with_py(|py| {
+            let dir =
+                std::env::temp_dir().join(format!("mpr_archive_set_schema_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let mbox = dir.join("a.mbox");
+            fs::write(
+                &mbox,
+                "From a Mon Jan  1 00:00:00 2024\nSubject: Deploy\nX-Team: Platform\n\
+             X-Gmail-Labels: Ops,Urgent\nMessage-ID: <1@x>\n\nFixes ABC-12.\n\n\
+             From b Mon Jan  1 00:00:00 2024\nSubject: Platform news\nX-Team: Sales\n\
+             Message-ID: <2@x>\n\nNothing.\n",
+            )
+            .unwrap();
+            let schema = vec![
+                IndexField::new("team", "header:X-Team", true, true, true).unwrap(),
+                IndexField::new("labels", "labels", true, false, true).unwrap(),
+                IndexField::new("tickets", "extract:ticket", false, true, false).unwrap(),
+            ];
+            let mut set = ArchiveSet::new(Some(schema)).unwrap();
+            set.add_mbox(py, mbox.to_str().unwrap(), None, "standard", None)
+                .unwrap();
+            assert_eq!(set.schema().len(), 3);
+
+            let team = Some(vec!["team".to_string()]);
+            let hits = set.search(py, "platform", None, None, team).unwrap();
+            assert_eq!(hits.len(), 1);
+            assert_eq!(hits[0].position, 0);
+            assert_eq!(hits[0].fields["team"], ["Platform"]);
+            assert_eq!(hits[0].fields["tickets"], ["ABC-12"]);
+            assert!(!hits[0].fields.contains_key("labels"));
+            assert_eq!(set.search(py, "platform", None, None, None).unwrap().len(), 2);
+            assert_eq!(set.search(py, "urgent", None, None, None).unwrap().len(), 1);
+            // Unindexed fields are stored but not searchable
+            assert!(set
+                .search(py, "abc", None, None, Some(vec!["tickets".into()]))
+                .is_err());
+
+            let count = |query: &str, facet: &str| {
+                set.aggregate(py, query, facet, None, None, None).unwrap()
+            };
+            assert_eq!(count("", "team"), [("Platform".into(), 1), ("Sales".into(), 1)]);
+            assert_eq!(count("", "label"), [("Ops".into(), 1), ("Urgent".into(), 1)]);
+            assert_eq!(count("platform", "year"), []);
+            assert_eq!(count("", "source"), [(mbox.to_str().unwrap().into(), 2)]);
+            assert!(set.aggregate(py, "", "tickets", None, None, None).is_err());
+            assert!(ArchiveSet::new(Some(vec![
+                IndexField::new("a", "labels", true, true, false).unwrap(),
+                IndexField::new("a", "labels", true, true, false).unwrap(),
+            ]))
+            .is_err());
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,1138 @@
+//! Arrow export of per-message metadata.
+//!
+//! `mbox_to_arrow` parses every message once, in parallel, into typed
+//! columns and returns them as an `ArrowBatch`. The batch implements the
+//! Arrow `PyCapsule` interface (`__arrow_c_schema__`, `__arrow_c_array__`
+//! and `__arrow_c_stream__`), so pyarrow, polars and duckdb import the columns
+//! without copying them and without a Python object per row; no Arrow
+//! library is needed on this side.
+//!
+//! The exported structures follow the Arrow C data and C stream interfaces.
+//! Every exported node holds a reference to the batch's buffers, so they
+//! stay valid until the consumer releases the last node that uses them,
+//! even after the `ArrowBatch` itself is gone.
+
+use crate::date::parse_date;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{address_list, first_address, Message, Part};
+use crate::progress::Progress;
+use crate::uid::uid_of;
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+use rayon::prelude::*;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ops::Range;
+use std::ptr;
+use std::sync::Arc;
+
+/// `ARROW_FLAG_NULLABLE` of the C data interface
+const FLAG_NULLABLE: i64 = 2;
+
+/// A metadata column of `mbox_to_arrow`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    MessageIndex,
+    Offset,
+    Size,
+    DateEpoch,
+    From,
+    To,
+    Subject,
+    MessageId,
+    HasAttachments,
+    Body,
+    Uid,
+}
+
+impl Column {
+    /// Every column but `body`, in the default order
+    pub const DEFAULT: [Self; 10] = [
+        Self::MessageIndex,
+        Self::Offset,
+        Self::Size,
+        Self::DateEpoch,
+        Self::From,
+        Self::To,
+        Self::Subject,
+        Self::MessageId,
+        Self::HasAttachments,
+        Self::Uid,
+    ];
+
+    /// Every column; a column's position is its id in snapshots, so new
+    /// columns go at the end
+    pub const ALL: [Self; 11] = [
+        Self::MessageIndex,
+        Self::Offset,
+        Self::Size,
+        Self::DateEpoch,
+        Self::From,
+        Self::To,
+        Self::Subject,
+        Self::MessageId,
+        Self::HasAttachments,
+        Self::Body,
+        Self::Uid,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::MessageIndex => "message_index",
+            Self::Offset => "offset",
+            Self::Size => "size",
+            Self::DateEpoch => "date_epoch",
+            Self::From => "from",
+            Self::To => "to",
+            Self::Subject => "subject",
+            Self::MessageId => "message_id",
+            Self::HasAttachments => "has_attachments",
+            Self::Body => "body",
+            Self::Uid => "uid",
+        }
+    }
+
+    /// Whether the column can hold nulls (a header the message lacks)
+    pub const fn nullable(self) -> bool {
+        matches!(self, Self::DateEpoch | Self::From | Self::To | Self::Subject | Self::MessageId)
+    }
+
+    /// The columns called `names` in that order, the default ones for None
+    pub fn select(names: Option<Vec<String>>) -> PyResult<Vec<Self>> {
+        let Some(names) = names else {
+            return Ok(Self::DEFAULT.to_vec());
+        };
+        let mut columns = Vec::with_capacity(names.len());
+        for name in names {
+            let column = Self::ALL
+                .into_iter()
+                .find(|column| column.name() == name)
+                .ok_or_else(|| {
+                    let known: Vec<_> = Self::ALL.iter().map(|c| c.name()).collect();
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Unknown column: {name:?} (expected one of {})",
+                        known.join(", ")
+                    ))
+                })?;
+            if columns.contains(&column) {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Duplicate column: {name:?}"
+                )));
+            }
+            columns.push(column);
+        }
+        Ok(columns)
+    }
+}
+
+/// Values of one column, one per message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Values {
+    Int(Vec<Option<i64>>),
+    Text(Vec<Option<String>>),
+    Bool(Vec<bool>),
+}
+
+/// Header-derived metadata of one message
+#[derive(Default)]
+struct Row {
+    date_epoch: Option<i64>,
+    from: Option<String>,
+    to: Option<String>,
+    subject: Option<String>,
+    message_id: Option<String>,
+    has_attachments: bool,
+    body: Option<String>,
+    uid: Option<String>,
+}
+
+impl Row {
+    /// Metadata of `raw`; attachments and the body are only looked at when
+    /// `columns` asks for them, since that walks (and decodes) the MIME tree
+    fn new(raw: &[u8], columns: &[Column]) -> Self {
+        let msg = Message::parse(raw);
+        let to = msg.decoded_header("To").map(|value| {
+            let addresses: Vec<String> = address_list(&value)
+                .into_iter()
+                .map(|(_, address)| address)
+                .collect();
+            addresses.join(", ")
+        });
+        Self {
+            date_epoch: msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch),
+            from: msg.header("From").and_then(first_address),
+            to: to.filter(|to| !to.is_empty()),
+            subject: msg.decoded_header("Subject"),
+            message_id: msg.header("Message-ID").map(|id| id.trim().to_string()),
+            has_attachments: columns.contains(&Column::HasAttachments)
+                && msg.parts().iter().any(Part::is_attachment),
+            body: columns.contains(&Column::Body).then(|| msg.text_body()),
+            uid: columns.contains(&Column::Uid).then(|| uid_of(&msg)),
+        }
+    }
+}
+
+fn to_i64(value: usize) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+/// One field of every row, moved out
+fn take<T>(rows: &mut [Row], field: fn(&mut Row) -> &mut Option<T>) -> Vec<Option<T>> {
+    rows.iter_mut().map(|row| field(row).take()).collect()
+}
+
+/// `columns` of the messages at `spans` of `data`, parsed in parallel; the
+/// first span is message `first` of the archive
+///
+/// Each message counts into `progress`; once it is cancelled the remaining
+/// rows are left empty, for the caller to discard.
+pub fn extract(
+    data: &[u8],
+    spans: &[Range<usize>],
+    first: usize,
+    columns: &[Column],
+    progress: &Progress,
+) -> Vec<Values> {
+    let mut rows: Vec<Row> = spans
+        .par_iter()
+        .map(|span| {
+            if progress.message(span) {
+                Row::new(&data[span.clone()], columns)
+            } else {
+                Row::default()
+            }
+        })
+        .collect();
+    // Each column is requested once, so its values are moved out of the rows
+    columns
+        .iter()
+        .map(|column| match column {
+            Column::MessageIndex => Values::Int(
+                (first..first + spans.len())
+                    .map(|i| Some(to_i64(i)))
+                    .collect(),
+            ),
+            Column::Offset => Values::Int(spans.iter().map(|s| Some(to_i64(s.start))).collect()),
+            Column::Size => Values::Int(spans.iter().map(|s| Some(to_i64(s.len()))).collect()),
+            Column::DateEpoch => Values::Int(take(&mut rows, |row| &mut row.date_epoch)),
+            Column::From => Values::Text(take(&mut rows, |row| &mut row.from)),
+            Column::To => Values::Text(take(&mut rows, |row| &mut row.to)),
+            Column::Subject => Values::Text(take(&mut rows, |row| &mut row.subject)),
+            Column::MessageId => Values::Text(take(&mut rows, |row| &mut row.message_id)),
+            Column::Body => Values::Text(take(&mut rows, |row| &mut row.body)),
+            Column::Uid => Values::Text(take(&mut rows, |row| &mut row.uid)),
+            Column::HasAttachments => {
+                Values::Bool(rows.iter().map(|row| row.has_attachments).collect())
+            },
+        })
+        .collect()
+}
+
+/// An Arrow buffer, kept in its element type so it is suitably aligned
+enum Buffer {
+    Int64(Vec<i64>),
+    Bytes(Vec<u8>),
+}
+
+impl Buffer {
+    fn as_ptr(&self) -> *const c_void {
+        match self {
+            Self::Int64(values) => values.as_ptr().cast(),
+            Self::Bytes(bytes) => bytes.as_ptr().cast(),
+        }
+    }
+}
+
+/// Bitmap of `bits`, least significant bit first
+pub fn bitmap(bits: impl ExactSizeIterator<Item = bool>) -> Vec<u8> {
+    let mut bytes = vec![0_u8; (bits.len() + 7) / 8];
+    for (i, bit) in bits.enumerate() {
+        bytes[i / 8] |= u8::from(bit) << (i % 8);
+    }
+    bytes
+}
+
+/// Validity bitmap and null count of `values`; no bitmap without nulls
+fn validity<T>(values: &[Option<T>]) -> (Option<Buffer>, usize) {
+    let nulls = values.iter().filter(|v| v.is_none()).count();
+    let bitmap = (nulls > 0).then(|| Buffer::Bytes(bitmap(values.iter().map(Option::is_some))));
+    (bitmap, nulls)
+}
+
+/// One column laid out as an Arrow array
+struct ArrowColumn {
+    name: CString,
+    /// NUL-terminated format string: `l` (int64), `U` (large utf8) or `b`
+    format: &'static [u8],
+    nullable: bool,
+    length: usize,
+    null_count: usize,
+    /// Validity bitmap, then the data buffers
+    buffers: Vec<Option<Buffer>>,
+}
+
+impl ArrowColumn {
+    fn new(column: Column, values: Values) -> Self {
+        let (format, length, null_count, buffers) = match values {
+            Values::Int(values) => {
+                let (valid, nulls) = validity(&values);
+                let data = values.iter().map(|v| v.unwrap_or(0)).collect();
+                (&b"l\0"[..], values.len(), nulls, vec![valid, Some(Buffer::Int64(data))])
+            },
+            Values::Text(values) => {
+                let (valid, nulls) = validity(&values);
+                let mut offsets = Vec::with_capacity(values.len() + 1);
+                let mut data = Vec::new();
+                offsets.push(0);
+                for value in &values {
+                    data.extend_from_slice(value.as_deref().unwrap_or_default().as_bytes());
+                    offsets.push(to_i64(data.len()));
+                }
+                let buffers = vec![
+                    valid,
+                    Some(Buffer::Int64(offsets)),
+                    Some(Buffer::Bytes(data)),
+                ];
+                (&b"U\0"[..], values.len(), nulls, buffers)
+            },
+            Values::Bool(values) => {
+                let data = Buffer::Bytes(bitmap(values.iter().copied()));
+                (&b"b\0"[..], values.len(), 0, vec![None, Some(data)])
+            },
+        };
+        Self {
+            name: CString::new(column.name()).unwrap_or_default(),
+            format,
+            nullable: column.nullable(),
+            length,
+            null_count,
+            buffers,
+        }
+    }
+}
+
+/// Columns ready for export as one Arrow record batch
+struct Batch {
+    columns: Vec<ArrowColumn>,
+    rows: usize,
+}
+
+/// One field of an exported schema: a column of the batch and whether it
+/// is declared nullable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Field {
+    column: usize,
+    nullable: bool,
+}
+
+impl Batch {
+    /// Every column, in order, as the batch declares it
+    fn fields(&self) -> Vec<Field> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(column, c)| Field {
+                column,
+                nullable: c.nullable,
+            })
+            .collect()
+    }
+
+    /// The fields of `requested`, a struct schema naming columns of the
+    /// batch: those columns in its order and with its nullability
+    fn fields_for(&self, requested: &ArrowSchema) -> PyResult<Vec<Field>> {
+        let value_error = PyErr::new::<pyo3::exceptions::PyValueError, String>;
+        if requested.format.is_null() || c_str(requested.format) != "+s" {
+            return Err(value_error("Requested schema is not a struct schema".into()));
+        }
+        let n_children = usize::try_from(requested.n_children).unwrap_or_default();
+        (0..n_children)
+            .map(|i| {
+                // Safety: a valid struct schema has `n_children` children
+                #[allow(unsafe_code)]
+                let child = unsafe { &**requested.children.add(i) };
+                let name = if child.name.is_null() {
+                    String::new()
+                } else {
+                    c_str(child.name)
+                };
+                let column = self
+                    .columns
+                    .iter()
+                    .position(|c| c.name.to_str() == Ok(name.as_str()))
+                    .ok_or_else(|| value_error(format!("ArrowBatch has no column {name:?}")))?;
+                let ours = &self.columns[column];
+                let format = c_str(child.format);
+                if format.as_bytes() != ours.format.strip_suffix(b"\0").unwrap_or(ours.format) {
+                    return Err(value_error(format!(
+                        "Column {name:?} cannot be exported as Arrow format {format:?}"
+                    )));
+                }
+                let nullable = child.flags & FLAG_NULLABLE != 0;
+                if !nullable && ours.null_count > 0 {
+                    return Err(value_error(format!(
+                        "Column {name:?} has nulls but was requested non-nullable"
+                    )));
+                }
+                Ok(Field { column, nullable })
+            })
+            .collect()
+    }
+}
+
+/// A NUL-terminated string of the C data interface, lossily decoded
+#[allow(unsafe_code)]
+fn c_str(ptr: *const c_char) -> String {
+    // Safety: callers pass non-null pointers to NUL-terminated strings
+    unsafe { CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `ArrowSchema` of the Arrow C data interface
+#[repr(C)]
+struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut Self,
+    dictionary: *mut Self,
+    release: Option<unsafe extern "C" fn(*mut Self)>,
+    private_data: *mut c_void,
+}
+
+/// `ArrowArray` of the Arrow C data interface
+#[repr(C)]
+struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut Self,
+    dictionary: *mut Self,
+    release: Option<unsafe extern "C" fn(*mut Self)>,
+    private_data: *mut c_void,
+}
+
+impl ArrowArray {
+    /// The released array a stream returns once it has no more batches
+    const fn end() -> Self {
+        Self {
+            length: 0,
+            null_count: 0,
+            offset: 0,
+            n_buffers: 0,
+            n_children: 0,
+            buffers: ptr::null_mut(),
+            children: ptr::null_mut(),
+            dictionary: ptr::null_mut(),
+            release: None,
+            private_data: ptr::null_mut(),
+        }
+    }
+}
+
+/// `ArrowArrayStream` of the Arrow C stream interface
+#[repr(C)]
+struct ArrowArrayStream {
+    get_schema: Option<unsafe extern "C" fn(*mut Self, *mut ArrowSchema) -> c_int>,
+    get_next: Option<unsafe extern "C" fn(*mut Self, *mut ArrowArray) -> c_int>,
+    get_last_error: Option<unsafe extern "C" fn(*mut Self) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut Self)>,
+    private_data: *mut c_void,
+}
+
+/// What an exported schema or array node owns: a share of the batch its
+/// pointers lead into, and its children
+struct Private<T> {
+    _batch: Arc<Batch>,
+    _buffers: Vec<*const c_void>,
+    children: Vec<T>,
+    _pointers: Vec<*mut T>,
+}
+
+impl<T> Private<T> {
+    /// Box the node's private data; returns it with the children pointer
+    /// array and the buffer pointer array (both owned by the box)
+    fn export(
+        batch: &Arc<Batch>,
+        mut buffers: Vec<*const c_void>,
+        mut children: Vec<T>,
+    ) -> (*mut c_void, *mut *mut T, *mut *const c_void) {
+        let mut pointers: Vec<*mut T> = children.iter_mut().map(|c| c as *mut T).collect();
+        let (children_ptr, buffers_ptr) = (pointers.as_mut_ptr(), buffers.as_mut_ptr());
+        let private = Box::new(Self {
+            _batch: Arc::clone(batch),
+            _buffers: buffers,
+            children,
+            _pointers: pointers,
+        });
+        (Box::into_raw(private).cast(), children_ptr, buffers_ptr)
+    }
+}
+
+/// A C structure that ends its life through its own `release` callback
+trait Release {
+    fn release(&mut self);
+}
+
+impl Release for ArrowSchema {
+    #[allow(unsafe_code)]
+    fn release(&mut self) {
+        if let Some(release) = self.release {
+            // Safety: `release` belongs to this still unreleased structure
+            unsafe { release(self) };
+        }
+    }
+}
+
+impl Release for ArrowArray {
+    #[allow(unsafe_code)]
+    fn release(&mut self) {
+        if let Some(release) = self.release {
+            // Safety: as for `ArrowSchema`
+            unsafe { release(self) };
+        }
+    }
+}
+
+impl Release for ArrowArrayStream {
+    #[allow(unsafe_code)]
+    fn release(&mut self) {
+        if let Some(release) = self.release {
+            // Safety: as for `ArrowSchema`
+            unsafe { release(self) };
+        }
+    }
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    // Safety: consumers only call this on a schema exported here, whose
+    // private data is a boxed `Private<ArrowSchema>`
+    let Some(schema) = (unsafe { schema.as_mut() }) else {
+        return;
+    };
+    let private = unsafe { Box::from_raw(schema.private_data.cast::<Private<ArrowSchema>>()) };
+    // Children moved out by the consumer have been marked released
+    for mut child in private.children {
+        child.release();
+    }
+    schema.release = None;
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    // Safety: as for `release_schema`, with a `Private<ArrowArray>`
+    let Some(array) = (unsafe { array.as_mut() }) else {
+        return;
+    };
+    let private = unsafe { Box::from_raw(array.private_data.cast::<Private<ArrowArray>>()) };
+    for mut child in private.children {
+        child.release();
+    }
+    array.release = None;
+}
+
+fn schema_node(
+    batch: &Arc<Batch>,
+    format: &'static [u8],
+    name: *const c_char,
+    flags: i64,
+    children: Vec<ArrowSchema>,
+) -> ArrowSchema {
+    let n_children = to_i64(children.len());
+    let (private_data, children, _) = Private::export(batch, Vec::new(), children);
+    ArrowSchema {
+        format: format.as_ptr().cast(),
+        name,
+        metadata: ptr::null(),
+        flags,
+        n_children,
+        children,
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data,
+    }
+}
+
+/// The batch's schema: a struct with a child per field
+fn export_schema(batch: &Arc<Batch>, fields: &[Field]) -> ArrowSchema {
+    let children = fields
+        .iter()
+        .map(|field| {
+            let column = &batch.columns[field.column];
+            let flags = if field.nullable { FLAG_NULLABLE } else { 0 };
+            schema_node(batch, column.format, column.name.as_ptr(), flags, Vec::new())
+        })
+        .collect();
+    schema_node(batch, b"+s\0", b"\0".as_ptr().cast(), 0, children)
+}
+
+fn array_node(
+    batch: &Arc<Batch>,
+    length: usize,
+    null_count: usize,
+    buffers: Vec<*const c_void>,
+    children: Vec<ArrowArray>,
+) -> ArrowArray {
+    let (n_buffers, n_children) = (to_i64(buffers.len()), to_i64(children.len()));
+    let (private_data, children, buffers) = Private::export(batch, buffers, children);
+    ArrowArray {
+        length: to_i64(length),
+        null_count: to_i64(null_count),
+        offset: 0,
+        n_buffers,
+        n_children,
+        buffers,
+        children,
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data,
+    }
+}
+
+/// The batch's data as a struct array matching [`export_schema`]
+fn export_array(batch: &Arc<Batch>, fields: &[Field]) -> ArrowArray {
+    let children = fields
+        .iter()
+        .map(|field| {
+            let column = &batch.columns[field.column];
+            let buffers = column
+                .buffers
+                .iter()
+                .map(|buffer| buffer.as_ref().map_or(ptr::null(), Buffer::as_ptr))
+                .collect();
+            array_node(batch, column.length, column.null_count, buffers, Vec::new())
+        })
+        .collect();
+    array_node(batch, batch.rows, 0, vec![ptr::null()], children)
+}
+
+/// State of an exported stream: the batch and its fields, until it was
+/// handed out
+struct StreamPrivate {
+    batch: Arc<Batch>,
+    fields: Vec<Field>,
+    done: bool,
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn stream_schema(stream: *mut ArrowArrayStream, out: *mut ArrowSchema) -> c_int {
+    // Safety: consumers pass a live stream exported here and room for a schema
+    unsafe {
+        let private = &*(*stream).private_data.cast::<StreamPrivate>();
+        out.write(export_schema(&private.batch, &private.fields));
+    }
+    0
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn stream_next(stream: *mut ArrowArrayStream, out: *mut ArrowArray) -> c_int {
+    // Safety: as for `stream_schema`
+    unsafe {
+        let private = &mut *(*stream).private_data.cast::<StreamPrivate>();
+        let array = if private.done {
+            ArrowArray::end()
+        } else {
+            private.done = true;
+            export_array(&private.batch, &private.fields)
+        };
+        out.write(array);
+    }
+    0
+}
+
+#[allow(unsafe_code)]
+const unsafe extern "C" fn stream_error(_stream: *mut ArrowArrayStream) -> *const c_char {
+    ptr::null()
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn release_stream(stream: *mut ArrowArrayStream) {
+    // Safety: as for `release_schema`, with a `StreamPrivate`
+    let Some(stream) = (unsafe { stream.as_mut() }) else {
+        return;
+    };
+    drop(unsafe { Box::from_raw(stream.private_data.cast::<StreamPrivate>()) });
+    stream.release = None;
+}
+
+/// The batch as a stream of one record batch
+fn export_stream(batch: &Arc<Batch>, fields: Vec<Field>) -> ArrowArrayStream {
+    let private = Box::new(StreamPrivate {
+        batch: Arc::clone(batch),
+        fields,
+        done: false,
+    });
+    ArrowArrayStream {
+        get_schema: Some(stream_schema),
+        get_next: Some(stream_next),
+        get_last_error: Some(stream_error),
+        release: Some(release_stream),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+/// A C structure inside a capsule; released with the capsule unless the
+/// consumer moved it out (which marks the capsule's copy released)
+struct Exported<T>(T);
+
+// Safety: the structures only point into immutable, shared buffers and
+// their private data, which the release callbacks free from any thread
+#[allow(unsafe_code, clippy::non_send_fields_in_send_ty)]
+unsafe impl<T> Send for Exported<T> {}
+
+fn capsule<'py, T: Release + 'static>(
+    py: Python<'py>,
+    value: T,
+    name: &str,
+) -> PyResult<Bound<'py, PyCapsule>> {
+    let name = CString::new(name).unwrap_or_default();
+    PyCapsule::new_with_destructor(py, Exported(value), Some(name), |mut exported, _| {
+        exported.0.release();
+    })
+}
+
+/// Message metadata as Arrow columns, importable without copying
+///
+/// Pass it to anything that accepts Arrow `PyCapsule` objects:
+/// `pyarrow.table(batch)`, `polars.DataFrame(batch)`, or duckdb queries
+/// naming it. `to_pandas()` is then one call on the pyarrow table.
+#[pyclass]
+pub struct ArrowBatch {
+    batch: Arc<Batch>,
+}
+
+impl ArrowBatch {
+    /// Fields to export: every column, or those of `requested_schema` (an
+    /// `arrow_schema` capsule)
+    fn fields(&self, requested_schema: Option<&Bound<'_, PyAny>>) -> PyResult<Vec<Field>> {
+        let Some(requested) = requested_schema else {
+            return Ok(self.batch.fields());
+        };
+        let capsule = requested.downcast::<PyCapsule>()?;
+        if capsule.name()?.and_then(|name| name.to_str().ok()) != Some("arrow_schema") {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "requested_schema must be an arrow_schema capsule",
+            ));
+        }
+        // Safety: an `arrow_schema` capsule holds an `ArrowSchema`
+        #[allow(unsafe_code)]
+        let schema = unsafe { &*capsule.pointer().cast::<ArrowSchema>() };
+        self.batch.fields_for(schema)
+    }
+
+    /// A batch of `rows` rows holding `columns`
+    pub fn new(columns: impl IntoIterator<Item = (Column, Values)>, rows: usize) -> Self {
+        let columns = columns
+            .into_iter()
+            .map(|(column, values)| ArrowColumn::new(column, values))
+            .collect();
+        Self {
+            batch: Arc::new(Batch { columns, rows }),
+        }
+    }
+}
+
+#[pymethods]
+impl ArrowBatch {
+    /// Number of rows (messages)
+    fn __len__(&self) -> usize {
+        self.batch.rows
+    }
+
+    /// Number of rows (messages)
+    #[getter]
+    fn num_rows(&self) -> usize {
+        self.batch.rows
+    }
+
+    /// Column names in order
+    #[getter]
+    fn column_names(&self) -> Vec<String> {
+        self.batch
+            .columns
+            .iter()
+            .map(|column| column.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Schema capsule (Arrow `PyCapsule` interface)
+    fn __arrow_c_schema__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyCapsule>> {
+        capsule(py, export_schema(&self.batch, &self.batch.fields()), "arrow_schema")
+    }
+
+    /// Schema and struct array capsules (Arrow `PyCapsule` interface)
+    ///
+    /// A `requested_schema` may select and reorder columns and declare
+    /// them nullable or not; `ValueError` if it names an unknown column,
+    /// another type, or a column with nulls as non-nullable.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_array__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<(Bound<'py, PyCapsule>, Bound<'py, PyCapsule>)> {
+        let fields = self.fields(requested_schema)?;
+        Ok((
+            capsule(py, export_schema(&self.batch, &fields), "arrow_schema")?,
+            capsule(py, export_array(&self.batch, &fields), "arrow_array")?,
+        ))
+    }
+
+    /// Stream capsule of one record batch (Arrow `PyCapsule` interface);
+    /// `requested_schema` as for `__arrow_c_array__`
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyCapsule>> {
+        let fields = self.fields(requested_schema)?;
+        capsule(py, export_stream(&self.batch, fields), "arrow_array_stream")
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ArrowBatch(rows={}, columns={:?})", self.batch.rows, self.column_names())
+    }
+}
+
+/// Parsed message metadata as Arrow columns
+///
+/// Every message is parsed once, in parallel, and the requested columns are
+/// laid out as Arrow arrays in Rust. The result converts to a pyarrow table,
+/// a polars frame or a duckdb relation without copying the data and without
+/// creating a Python object per message.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `columns` - Column names to include, in this order (None: all but
+///   `body`): `message_index` (position in the archive), `offset` (byte
+///   offset of the `From ` line), `size` (bytes), `date_epoch` (Unix time
+///   of the Date header), `from` (sender address), `to` (recipient
+///   addresses, comma separated), `subject` (decoded), `message_id`,
+///   `has_attachments`, `uid` (see `compute_message_uid`) and `body` (the
+///   first text/plain part, else the HTML flattened to text)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `ArrowBatch` with int64, large string and boolean columns; `date_epoch`,
+///   `from`, `to`, `subject` and `message_id` are null where the header is
+///   missing (or the date unparseable)
+///
+/// # Raises
+/// * `ValueError` - An unknown or repeated column name
+/// * `IOError` - The mbox cannot be read
+/// * `FileChangedError` - The mbox changed during the scan
+/// * `ScanCancelled` - `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// import pyarrow as pa
+/// from mail_parser_rust import mbox_to_arrow
+/// table = pa.table(mbox_to_arrow("inbox.mbox", ["date_epoch", "from", "size"]))
+/// df = table.to_pandas()
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, columns=None, from_line_mode="standard", progress=None))]
+pub fn mbox_to_arrow(
+    py: Python<'_>,
+    mbox_path: &str,
+    columns: Option<Vec<String>>,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<ArrowBatch> {
+    let columns = Column::select(columns)?;
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        let values = extract(&mmap, &spans, 0, &columns, &progress);
+        progress.check("mbox_to_arrow")?;
+        mmap.verify(&mode)?;
+        Ok(ArrowBatch::new(columns.into_iter().zip(values), spans.len()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From a Mon Jan  1 00:00:00 2024\n\
+                        From: Jane <Jane@Example.com>\nTo: a@x.org, \"B\" <b@x.org>\n\
+                        Subject: =?utf-8?q?caf=C3=A9?=\nDate: Mon, 1 Jan 2024 00:00:00 +0000\n\
+                        Message-ID: <1@x>\n\nHello\n\n\
+                        From b Mon Jan  1 00:00:00 2024\n\
+                        Content-Type: multipart/mixed; boundary=\"b\"\n\n--b\n\
+                        Content-Type: text/plain\n\nSee attached\n--b\n\
+                        Content-Type: application/pdf\n\
+                        Content-Disposition: attachment; filename=\"a.pdf\"\n\nPDF\n--b--\n";
+
+    fn batch(columns: &[Column]) -> Arc<Batch> {
+        let spans = crate::mbox::message_spans_with(MBOX.as_bytes(), &FromLineMode::Standard);
+        let values = extract(MBOX.as_bytes(), &spans, 0, columns, &Progress::new(None, 0));
+        let columns = columns
+            .iter()
+            .zip(values)
+            .map(|(&column, values)| ArrowColumn::new(column, values))
+            .collect();
+        Arc::new(Batch {
+            columns,
+            rows: spans.len(),
+        })
+    }
+
+    #[test]
+    fn test_extract() {
+        let spans = crate::mbox::message_spans_with(MBOX.as_bytes(), &FromLineMode::Standard);
+        let values = extract(MBOX.as_bytes(), &spans, 0, &Column::ALL, &Progress::new(None, 0));
+        let second = i64::try_from(spans[1].start).unwrap();
+        assert_eq!(values[0], Values::Int(vec![Some(0), Some(1)]));
+        assert_eq!(values[1], Values::Int(vec![Some(0), Some(second)]));
+        assert_eq!(values[3], Values::Int(vec![Some(1_704_067_200), None]));
+        assert_eq!(values[4], Values::Text(vec![Some("jane@example.com".into()), None]));
+        assert_eq!(values[5], Values::Text(vec![Some("a@x.org, b@x.org".into()), None]));
+        assert_eq!(values[6], Values::Text(vec![Some("café".into()), None]));
+        assert_eq!(values[7], Values::Text(vec![Some("<1@x>".into()), None]));
+        assert_eq!(values[8], Values::Bool(vec![false, true]));
+        assert_eq!(
+            values[9],
+            Values::Text(vec![Some("Hello\n\n".into()), Some("See attached".into())])
+        );
+        let uids = spans
+            .iter()
+            .map(|span| Some(crate::uid::message_uid(&MBOX.as_bytes()[span.clone()])))
+            .collect();
+        assert_eq!(values[10], Values::Text(uids));
+
+        let tail = extract(
+            MBOX.as_bytes(),
+            &spans[1..],
+            1,
+            &[Column::MessageIndex],
+            &Progress::new(None, 0),
+        );
+        assert_eq!(tail, [Values::Int(vec![Some(1)])]);
+        assert_eq!(Column::select(None).unwrap(), Column::DEFAULT);
+        let picked = Column::select(Some(vec!["subject".into(), "size".into()])).unwrap();
+        assert_eq!(picked, [Column::Subject, Column::Size]);
+        assert!(Column::select(Some(vec!["nope".into()])).is_err());
+        assert!(Column::select(Some(vec!["size".into(), "size".into()])).is_err());
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_export() {
+        let batch = batch(&[Column::Size, Column::Subject, Column::HasAttachments]);
+        let mut schema = export_schema(&batch, &batch.fields());
+        let mut array = export_array(&batch, &batch.fields());
+        // The exports keep the buffers alive on their own
+        let weak = Arc::downgrade(&batch);
+        drop(batch);
+        // Safety: reading back structures exported above, before release
+        unsafe {
+            let format = |s: &ArrowSchema| std::ffi::CStr::from_ptr(s.format).to_str().unwrap();
+            assert_eq!(format(&schema), "+s");
+            assert_eq!(schema.n_children, 3);
+            let subject = &**schema.children.add(1);
+            assert_eq!(format(subject), "U");
+            assert_eq!(std::ffi::CStr::from_ptr(subject.name).to_str(), Ok("subject"));
+            assert_eq!(subject.flags, FLAG_NULLABLE);
+            assert_eq!((**schema.children).flags, 0);
+
+            assert_eq!((array.length, array.n_children, array.n_buffers), (2, 3, 1));
+            let size = &**array.children;
+            let sizes = std::slice::from_raw_parts((*size.buffers.add(1)).cast::<i64>(), 2);
+            assert_eq!(sizes[0], i64::try_from(MBOX.find("\nFrom b").unwrap() + 1).unwrap());
+            assert!((*size.buffers).is_null());
+
+            let subject = &**array.children.add(1);
+            assert_eq!((subject.length, subject.null_count), (2, 1));
+            let valid = *(*subject.buffers).cast::<u8>();
+            assert_eq!(valid & 0b11, 0b01);
+            let offsets = std::slice::from_raw_parts((*subject.buffers.add(1)).cast::<i64>(), 3);
+            assert_eq!(offsets, [0, 5, 5]);
+            let text = std::slice::from_raw_parts((*subject.buffers.add(2)).cast::<u8>(), 5);
+            assert_eq!(text, "café".as_bytes());
+
+            let flags = &**array.children.add(2);
+            assert_eq!(*(*flags.buffers.add(1)).cast::<u8>() & 0b11, 0b10);
+        }
+        schema.release();
+        array.release();
+        assert!(schema.release.is_none() && array.release.is_none());
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_stream() {
+        let batch = batch(&[Column::MessageIndex]);
+        let mut stream = export_stream(&batch, batch.fields());
+        // Safety: driving the stream the way a consumer does
+        unsafe {
+            let mut schema = std::mem::MaybeUninit::<ArrowSchema>::uninit();
+            assert_eq!((stream.get_schema.unwrap())(&mut stream, schema.as_mut_ptr()), 0);
+            let mut schema = schema.assume_init();
+            assert_eq!(schema.n_children, 1);
+            schema.release();
+
+            let mut next = std::mem::MaybeUninit::<ArrowArray>::uninit();
+            assert_eq!((stream.get_next.unwrap())(&mut stream, next.as_mut_ptr()), 0);
+            let mut first = next.assume_init_read();
+            assert_eq!(first.length, 2);
+            assert_eq!((stream.get_next.unwrap())(&mut stream, next.as_mut_ptr()), 0);
+            assert!(next.assume_init().release.is_none());
+            first.release();
+        }
+        stream.release();
+        assert_eq!(Arc::strong_count(&batch), 1);
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_arrow_crate_imports() {
+        use arrow::array::{Array, AsArray, RecordBatch, StructArray};
+        use arrow::datatypes::{DataType, Int64Type, Schema};
+        use arrow::ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+        use arrow::ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream};
+
+        let batch = batch(&[Column::Size, Column::Subject, Column::HasAttachments]);
+        let fields = batch.fields();
+        // Safety: the structures are laid out as arrow-rs lays out its own;
+        // the transmutes move ownership of the exports to arrow-rs
+        let schema: FFI_ArrowSchema =
+            unsafe { std::mem::transmute(export_schema(&batch, &fields)) };
+        let array: FFI_ArrowArray = unsafe { std::mem::transmute(export_array(&batch, &fields)) };
+        let imported = Schema::try_from(&schema).unwrap();
+        let types: Vec<(&str, &DataType, bool)> = imported
+            .fields()
+            .iter()
+            .map(|f| (f.name().as_str(), f.data_type(), f.is_nullable()))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("size", &DataType::Int64, false),
+                ("subject", &DataType::LargeUtf8, true),
+                ("has_attachments", &DataType::Boolean, false),
+            ]
+        );
+        let data = unsafe { from_ffi(array, &schema) }.unwrap();
+        data.validate_full().unwrap();
+        let rows = StructArray::from(data);
+        assert_eq!(rows.len(), 2);
+        let first = i64::try_from(MBOX.find("\nFrom b").unwrap() + 1).unwrap();
+        assert_eq!(rows.column(0).as_primitive::<Int64Type>().value(0), first);
+        let subjects = rows.column(1).as_string::<i64>();
+        assert_eq!((subjects.value(0), subjects.is_null(1)), ("café", true));
+        let attached = rows.column(2).as_boolean();
+        assert_eq!((attached.value(0), attached.value(1)), (false, true));
+
+        let stream: FFI_ArrowArrayStream =
+            unsafe { std::mem::transmute(export_stream(&batch, fields)) };
+        let reader = ArrowArrayStreamReader::try_new(stream).unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 2);
+        assert_eq!(batches[0].schema().as_ref(), &imported);
+        // Released by arrow-rs once its arrays are gone
+        drop((rows, schema, batches));
+        assert_eq!(Arc::strong_count(&batch), 1);
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_requested_schema() {
+        use arrow::array::{AsArray, StructArray};
+        use arrow::datatypes::{DataType, Field as ArrowField, Schema};
+        use arrow::ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+
+        crate::with_py(|py| {
+            let batch = Bound::new(
+                py,
+                ArrowBatch {
+                    batch: batch(&[Column::Size, Column::Subject]),
+                },
+            )
+            .unwrap();
+            let request = |fields: Vec<ArrowField>| {
+                let schema = FFI_ArrowSchema::try_from(&Schema::new(fields)).unwrap();
+                let name = CString::new("arrow_schema").unwrap();
+                let capsule = PyCapsule::new(py, schema, Some(name)).unwrap();
+                batch.call_method1("__arrow_c_array__", (capsule,))
+            };
+            let reordered = request(vec![
+                ArrowField::new("subject", DataType::LargeUtf8, true),
+                ArrowField::new("size", DataType::Int64, true),
+            ])
+            .unwrap();
+            let (schema, array): (Bound<'_, PyCapsule>, Bound<'_, PyCapsule>) =
+                reordered.extract().unwrap();
+            // Safety: moving the exports out of their capsules, as consumers
+            // do, leaves released structures behind
+            let (schema, array) = unsafe {
+                (
+                    ptr::replace(schema.pointer().cast(), FFI_ArrowSchema::empty()),
+                    ptr::replace(array.pointer().cast(), FFI_ArrowArray::empty()),
+                )
+            };
+            let imported = Schema::try_from(&schema).unwrap();
+            assert_eq!(imported.field(0).name(), "subject");
+            assert!(imported.field(1).is_nullable());
+            let data = unsafe { from_ffi(array, &schema) }.unwrap();
+            data.validate_full().unwrap();
+            let rows = StructArray::from(data);
+            assert_eq!(rows.column(0).as_string::<i64>().value(0), "café");
+
+            let unknown = request(vec![ArrowField::new("nope", DataType::Int64, true)]);
+            let retyped = request(vec![ArrowField::new("subject", DataType::Utf8, true)]);
+            let required = request(vec![ArrowField::new("subject", DataType::LargeUtf8, false)]);
+            for result in [unknown, retyped, required] {
+                assert!(result
+                    .unwrap_err()
+                    .is_instance_of::<pyo3::exceptions::PyValueError>(py));
+            }
+            let stream = batch.call_method1("__arrow_c_stream__", (1,));
+            assert!(stream.is_err());
+        });
+    }
+
+    #[test]
+    fn test_mbox_to_arrow() {
+        let dir = std::env::temp_dir().join(format!("mpr_arrow_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.mbox");
+        std::fs::write(&path, MBOX).unwrap();
+        let path = path.to_str().unwrap();
+        crate::with_py(|py| {
+            let batch =
+                mbox_to_arrow(py, path, Some(vec!["from".into()]), "standard", None).unwrap();
+            assert_eq!(batch.num_rows(), 2);
+            assert_eq!(batch.column_names(), ["from"]);
+            let batch = Bound::new(py, batch).unwrap();
+            let (schema, array): (Bound<'_, PyCapsule>, Bound<'_, PyCapsule>) = batch
+                .call_method0("__arrow_c_array__")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(schema.name().unwrap().unwrap().to_str(), Ok("arrow_schema"));
+            assert_eq!(array.name().unwrap().unwrap().to_str(), Ok("arrow_array"));
+            let stream = batch.call_method0("__arrow_c_stream__").unwrap();
+            let stream = stream.downcast::<PyCapsule>().unwrap();
+            assert_eq!(stream.name().unwrap().unwrap().to_str(), Ok("arrow_array_stream"));
+            assert!(mbox_to_arrow(py, path, Some(vec!["x".into()]), "standard", None).is_err());
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,695 @@
+//! `Authentication-Results` (RFC 8601) and ARC (RFC 8617) header parsing.
+//!
+//! Authentication-Results values are read with the RFC 8601 grammar rather
+//! than patterns: comments (nested, escaped, holding `;` or `=` as receivers
+//! like to write them) and quoted strings are skipped or unquoted, so
+//! `spf=pass (sender IP is 1.2.3.4; smtp.mailfrom=x) smtp.mailfrom=y` yields
+//! one SPF result for `y`. ARC-Authentication-Results is the same payload
+//! after an `i=` instance; ARC-Seal and ARC-Message-Signature are DKIM-style
+//! tag lists. Nothing is verified cryptographically.
+
+use crate::alignment::identity_domain;
+use crate::batch::par_map;
+use crate::dkim::tags;
+use crate::mime::Message;
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Properties naming the identity a result is about, most specific first
+const DOMAIN_PROPERTIES: &[&str] = &[
+    "header.d",
+    "header.from",
+    "smtp.mailfrom",
+    "header.i",
+    "smtp.helo",
+];
+
+/// One `method=result` clause
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct AuthResult {
+    /// Method, lowercased ("spf", "dkim", "dmarc", "arc", ...)
+    #[pyo3(get)]
+    pub method: String,
+    /// Method version (`dkim/1`), if given
+    #[pyo3(get)]
+    pub version: Option<u32>,
+    /// Result, lowercased ("pass", "fail", "softfail", "none", ...)
+    #[pyo3(get)]
+    pub result: String,
+    /// `reason=` text
+    #[pyo3(get)]
+    pub reason: Option<String>,
+    /// Properties by lowercased name (`smtp.mailfrom`, `header.d`, ...)
+    #[pyo3(get)]
+    pub properties: BTreeMap<String, String>,
+    /// Domain the result is about: `header.d`, `header.from`, the
+    /// `smtp.mailfrom` domain, the `header.i` domain or `smtp.helo`
+    #[pyo3(get)]
+    pub domain: Option<String>,
+    /// DKIM selector (`header.s`)
+    #[pyo3(get)]
+    pub selector: Option<String>,
+    /// Comments of the clause, joined by spaces
+    #[pyo3(get)]
+    pub comment: Option<String>,
+}
+
+#[pymethods]
+impl AuthResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "AuthResult({}={}, domain={:?})",
+            self.method,
+            self.result,
+            self.domain.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// One `Authentication-Results` or `ARC-Authentication-Results` value
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct AuthenticationResults {
+    /// Host that recorded the results
+    #[pyo3(get)]
+    pub authserv_id: String,
+    /// Header version after the authserv-id, if given
+    #[pyo3(get)]
+    pub version: Option<u32>,
+    /// ARC instance (`i=`), for ARC-Authentication-Results
+    #[pyo3(get)]
+    pub instance: Option<u32>,
+    /// Results in header order; empty for `none`
+    #[pyo3(get)]
+    pub results: Vec<AuthResult>,
+}
+
+#[pymethods]
+impl AuthenticationResults {
+    /// Result of the first clause for `method` (e.g. "dmarc"), or None
+    fn result_for(&self, method: &str) -> Option<String> {
+        self.results
+            .iter()
+            .find(|r| r.method.eq_ignore_ascii_case(method))
+            .map(|r| r.result.clone())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "AuthenticationResults(authserv_id={:?}, results={})",
+            self.authserv_id,
+            self.results.len()
+        )
+    }
+}
+
+/// One `ARC-Seal`
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ArcSeal {
+    #[pyo3(get)]
+    pub instance: Option<u32>,
+    /// Signature algorithm (`a=`)
+    #[pyo3(get)]
+    pub algorithm: Option<String>,
+    /// Chain validation status the sealer found (`cv=`: "none", "pass", "fail")
+    #[pyo3(get)]
+    pub chain_validation: Option<String>,
+    /// Sealing domain (`d=`) and selector (`s=`)
+    #[pyo3(get)]
+    pub domain: Option<String>,
+    #[pyo3(get)]
+    pub selector: Option<String>,
+    /// Sealing time (`t=`, Unix timestamp)
+    #[pyo3(get)]
+    pub timestamp: Option<i64>,
+}
+
+/// One `ARC-Message-Signature`
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ArcMessageSignature {
+    #[pyo3(get)]
+    pub instance: Option<u32>,
+    /// Signature algorithm (`a=`)
+    #[pyo3(get)]
+    pub algorithm: Option<String>,
+    /// Canonicalization (`c=`)
+    #[pyo3(get)]
+    pub canonicalization: Option<String>,
+    /// Signing domain (`d=`) and selector (`s=`)
+    #[pyo3(get)]
+    pub domain: Option<String>,
+    #[pyo3(get)]
+    pub selector: Option<String>,
+    /// Signed header field names (`h=`)
+    #[pyo3(get)]
+    pub signed_headers: Vec<String>,
+    /// Signing time (`t=`, Unix timestamp)
+    #[pyo3(get)]
+    pub timestamp: Option<i64>,
+}
+
+/// The three ARC header fields of one instance
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ArcSet {
+    #[pyo3(get)]
+    pub instance: u32,
+    #[pyo3(get)]
+    pub seal: Option<ArcSeal>,
+    #[pyo3(get)]
+    pub message_signature: Option<ArcMessageSignature>,
+    #[pyo3(get)]
+    pub authentication_results: Option<AuthenticationResults>,
+}
+
+/// Authentication headers of one message
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct AuthHeaders {
+    /// `Authentication-Results` fields, topmost (most recent) first
+    #[pyo3(get)]
+    pub authentication_results: Vec<AuthenticationResults>,
+    /// ARC sets by ascending instance
+    #[pyo3(get)]
+    pub arc_sets: Vec<ArcSet>,
+    /// Structural state of the ARC chain: "none" without ARC fields, "pass"
+    /// for complete sets numbered from 1 with `cv=none` on the first and
+    /// `cv=pass` on the rest, "fail" otherwise (signatures are not checked)
+    #[pyo3(get)]
+    pub arc_chain: String,
+}
+
+/// Position in a header value, collecting the comments it skips
+struct Cursor<'a> {
+    text: &'a str,
+    pos: usize,
+    comments: Vec<&'a str>,
+}
+
+impl<'a> Cursor<'a> {
+    const fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            comments: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    /// Skip whitespace and (nested) comments
+    fn skip_cfws(&mut self) {
+        let bytes = self.text.as_bytes();
+        while let Some(b) = self.peek() {
+            if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else if b == b'(' {
+                let start = self.pos + 1;
+                let mut depth = 0;
+                while self.pos < bytes.len() {
+                    match bytes[self.pos] {
+                        b'\\' => self.pos += 1,
+                        b'(' => depth += 1,
+                        b')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        },
+                        _ => {},
+                    }
+                    self.pos += 1;
+                }
+                // An unclosed comment runs to the end
+                let end = self.pos.min(bytes.len());
+                let comment = self.text[start..end].trim();
+                if !comment.is_empty() {
+                    self.comments.push(comment);
+                }
+                self.pos = (end + 1).min(bytes.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Skip whitespace, then consume `byte` if it is next
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_cfws();
+        let found = self.peek() == Some(byte);
+        self.pos += usize::from(found);
+        found
+    }
+
+    /// A token or quoted string at the cursor; a token ends at whitespace,
+    /// `;`, parentheses, a quote and, with `stop_at_eq`, `=`
+    fn value(&mut self, stop_at_eq: bool) -> Option<String> {
+        let bytes = self.text.as_bytes();
+        if self.peek() == Some(b'"') {
+            let start = self.pos + 1;
+            let mut out = String::new();
+            let mut chars = self.text[start..].char_indices();
+            // An unclosed string runs to the end
+            self.pos = self.text.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => out.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        self.pos = start + i + 1;
+                        break;
+                    },
+                    _ => out.push(c),
+                }
+            }
+            return Some(out);
+        }
+        let start = self.pos;
+        while let Some(&b) = bytes.get(self.pos) {
+            if b.is_ascii_whitespace()
+                || matches!(b, b';' | b'(' | b')' | b'"')
+                || (stop_at_eq && b == b'=')
+            {
+                break;
+            }
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| self.text[start..self.pos].to_string())
+    }
+
+    /// Skip to the next `;` outside comments and quoted strings
+    fn skip_to_semicolon(&mut self) {
+        loop {
+            self.skip_cfws();
+            match self.peek() {
+                None | Some(b';') => break,
+                Some(b'"') => {
+                    self.value(false);
+                },
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+}
+
+/// The clause after a `;`: `method[/version] = result` and its reason and
+/// properties
+fn parse_clause(cursor: &mut Cursor) -> Option<AuthResult> {
+    cursor.comments.clear();
+    cursor.skip_cfws();
+    let method = cursor.value(true)?;
+    // Anything else, `none` included, is not a result
+    if !cursor.eat(b'=') {
+        return None;
+    }
+    cursor.skip_cfws();
+    let result = cursor.value(true)?;
+    let (method, version) = method
+        .split_once('/')
+        .map_or((method.as_str(), None), |(m, v)| (m, v.trim().parse().ok()));
+    let mut clause = AuthResult {
+        method: method.trim().to_ascii_lowercase(),
+        version,
+        result: result.to_ascii_lowercase(),
+        ..AuthResult::default()
+    };
+    loop {
+        cursor.skip_cfws();
+        if matches!(cursor.peek(), None | Some(b';')) {
+            break;
+        }
+        let Some(name) = cursor.value(true) else {
+            // A stray `=`, `)` or other separator
+            cursor.pos += 1;
+            continue;
+        };
+        if !cursor.eat(b'=') {
+            continue;
+        }
+        cursor.skip_cfws();
+        let value = cursor.value(false).unwrap_or_default();
+        if name.eq_ignore_ascii_case("reason") {
+            clause.reason = Some(value);
+        } else {
+            clause.properties.insert(name.to_ascii_lowercase(), value);
+        }
+    }
+    clause.domain = DOMAIN_PROPERTIES
+        .iter()
+        .find_map(|name| clause.properties.get(*name))
+        .map(|value| identity_domain(value))
+        .filter(|domain| !domain.is_empty());
+    clause.selector = clause.properties.get("header.s").cloned();
+    clause.comment = (!cursor.comments.is_empty()).then(|| cursor.comments.join(" "));
+    Some(clause)
+}
+
+/// Parse an `Authentication-Results` value, or an ARC-Authentication-Results
+/// one with its leading `i=`
+pub fn parse_value(value: &str) -> AuthenticationResults {
+    let mut cursor = Cursor::new(value);
+    let mut parsed = AuthenticationResults::default();
+    cursor.skip_cfws();
+    let start = cursor.pos;
+    if cursor
+        .value(true)
+        .is_some_and(|name| name.eq_ignore_ascii_case("i"))
+        && cursor.eat(b'=')
+    {
+        cursor.skip_cfws();
+        parsed.instance = cursor.value(false).and_then(|i| i.parse().ok());
+        cursor.skip_to_semicolon();
+        cursor.eat(b';');
+        cursor.skip_cfws();
+    } else {
+        cursor.pos = start;
+    }
+    let start = cursor.pos;
+    match cursor.value(true) {
+        // No authserv-id: the value starts with a result
+        Some(_) if cursor.eat(b'=') => {
+            cursor.pos = start;
+            parsed.results.extend(parse_clause(&mut cursor));
+        },
+        Some(id) => {
+            parsed.authserv_id = id;
+            cursor.skip_cfws();
+            if cursor.peek().is_some_and(|b| b.is_ascii_digit()) {
+                parsed.version = cursor.value(false).and_then(|v| v.parse().ok());
+            }
+        },
+        None => {},
+    }
+    loop {
+        cursor.skip_to_semicolon();
+        if !cursor.eat(b';') {
+            break;
+        }
+        parsed.results.extend(parse_clause(&mut cursor));
+    }
+    parsed
+}
+
+/// Tag value of a DKIM-style tag list
+fn tag(tags: &[(String, String)], name: &str) -> Option<String> {
+    tags.iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+fn parse_seal(value: &str) -> ArcSeal {
+    let tags = tags(value);
+    ArcSeal {
+        instance: tag(&tags, "i").and_then(|i| i.parse().ok()),
+        algorithm: tag(&tags, "a"),
+        chain_validation: tag(&tags, "cv").map(|cv| cv.to_ascii_lowercase()),
+        domain: tag(&tags, "d"),
+        selector: tag(&tags, "s"),
+        timestamp: tag(&tags, "t").and_then(|t| t.parse().ok()),
+    }
+}
+
+fn parse_message_signature(value: &str) -> ArcMessageSignature {
+    let tags = tags(value);
+    ArcMessageSignature {
+        instance: tag(&tags, "i").and_then(|i| i.parse().ok()),
+        algorithm: tag(&tags, "a"),
+        canonicalization: tag(&tags, "c"),
+        domain: tag(&tags, "d"),
+        selector: tag(&tags, "s"),
+        signed_headers: tag(&tags, "h")
+            .map(|h| {
+                h.split(':')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        timestamp: tag(&tags, "t").and_then(|t| t.parse().ok()),
+    }
+}
+
+/// Whether `sets` form a well-formed chain: instances 1..=n, each set
+/// complete, the first sealed with `cv=none` and the rest with `cv=pass`
+fn chain_intact(sets: &[ArcSet]) -> bool {
+    sets.iter().enumerate().all(|(i, set)| {
+        let expected = if i == 0 { "none" } else { "pass" };
+        usize::try_from(set.instance).is_ok_and(|instance| instance == i + 1)
+            && set.message_signature.is_some()
+            && set.authentication_results.is_some()
+            && set
+                .seal
+                .as_ref()
+                .and_then(|seal| seal.chain_validation.as_deref())
+                == Some(expected)
+    })
+}
+
+/// The set of ARC `instance`, created on first use; None for instance 0 or
+/// a missing one
+fn set(sets: &mut BTreeMap<u32, ArcSet>, instance: Option<u32>) -> Option<&mut ArcSet> {
+    let instance = instance.filter(|&i| i > 0)?;
+    Some(sets.entry(instance).or_insert_with(|| ArcSet {
+        instance,
+        ..ArcSet::default()
+    }))
+}
+
+/// Authentication-Results and ARC fields of a header block
+fn parse_headers(headers: &[u8]) -> AuthHeaders {
+    let msg = Message::parse(headers);
+    let mut parsed = AuthHeaders::default();
+    let mut sets: BTreeMap<u32, ArcSet> = BTreeMap::new();
+    // Fields without a usable instance, or repeating one, break the chain
+    let mut malformed = false;
+    for header in &msg.headers {
+        let name = header.name.to_ascii_lowercase();
+        match name.as_str() {
+            "authentication-results" => {
+                parsed
+                    .authentication_results
+                    .push(parse_value(&header.value));
+            },
+            "arc-authentication-results" => {
+                let results = parse_value(&header.value);
+                match set(&mut sets, results.instance) {
+                    Some(set) if set.authentication_results.is_none() => {
+                        set.authentication_results = Some(results);
+                    },
+                    _ => malformed = true,
+                }
+            },
+            "arc-message-signature" => {
+                let signature = parse_message_signature(&header.value);
+                match set(&mut sets, signature.instance) {
+                    Some(set) if set.message_signature.is_none() => {
+                        set.message_signature = Some(signature);
+                    },
+                    _ => malformed = true,
+                }
+            },
+            "arc-seal" => {
+                let seal = parse_seal(&header.value);
+                match set(&mut sets, seal.instance) {
+                    Some(set) if set.seal.is_none() => set.seal = Some(seal),
+                    _ => malformed = true,
+                }
+            },
+            _ => {},
+        }
+    }
+    parsed.arc_sets = sets.into_values().collect();
+    parsed.arc_chain = if parsed.arc_sets.is_empty() && !malformed {
+        "none"
+    } else if !malformed && chain_intact(&parsed.arc_sets) {
+        "pass"
+    } else {
+        "fail"
+    }
+    .to_string();
+    parsed
+}
+
+/// Parse one `Authentication-Results` header value (RFC 8601)
+///
+/// Also takes `ARC-Authentication-Results` values, whose leading `i=` is
+/// returned as `instance`. Comments and quoted strings are handled by the
+/// grammar, so text inside comments never turns into results.
+///
+/// # Arguments
+/// * `value` - Header value, without the field name
+///
+/// # Returns
+/// * `AuthenticationResults` with `authserv_id`, `version`, `instance` and
+///   one `AuthResult` per clause (`method`, `version`, `result`, `reason`,
+///   `properties`, `domain`, `selector`, `comment`)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_authentication_results_fast
+/// ar = parse_authentication_results_fast(
+///     "mx.google.com; dkim=pass header.i=@acme.com header.s=k1; "
+///     "spf=pass (google.com: domain of x@acme.com designates 1.2.3.4) "
+///     "smtp.mailfrom=x@acme.com; dmarc=pass (p=REJECT) header.from=acme.com")
+/// for r in ar.results:
+///     print(r.method, r.result, r.domain, r.selector)
+/// ```
+#[pyfunction]
+pub fn parse_authentication_results_fast(value: &str) -> AuthenticationResults {
+    parse_value(value)
+}
+
+/// Parse a message's Authentication-Results and ARC header fields
+///
+/// # Arguments
+/// * `headers` - Header block (or whole raw message)
+///
+/// # Returns
+/// * `AuthHeaders` with `authentication_results` (topmost first),
+///   `arc_sets` (one `ArcSet` per instance, ascending, each with its
+///   `seal`, `message_signature` and `authentication_results`) and
+///   `arc_chain`: "none", "pass" or "fail" by the chain's structure and
+///   `cv=` values (signatures are not verified)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_auth_headers_fast
+/// auth = parse_auth_headers_fast(header_block)
+/// for s in auth.arc_sets:
+///     print(s.instance, s.seal.domain, s.authentication_results.result_for("dmarc"))
+/// ```
+#[pyfunction]
+pub fn parse_auth_headers_fast(headers: &str) -> AuthHeaders {
+    parse_headers(headers.as_bytes())
+}
+
+/// Batch version of [`parse_auth_headers_fast`], processed in parallel
+///
+/// # Arguments
+/// * `header_blocks` - Header blocks (or whole raw messages)
+///
+/// # Returns
+/// * One `AuthHeaders` per block
+#[pyfunction]
+pub fn parse_auth_headers_batch(py: Python<'_>, header_blocks: Vec<String>) -> Vec<AuthHeaders> {
+    py.allow_threads(|| par_map(header_blocks, |block| parse_headers(block.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value() {
+        let ar = parse_value(
+            "mx.google.com 1;\r\n       dkim=pass header.i=@news.acme.com header.s=k1 \
+             header.b=\"Ab+/c=\";\r\n       spf=pass (google.com: domain of x@acme.com; \
+             smtp.mailfrom=evil@spoof.net (nested)) smtp.mailfrom=x@Acme.COM;\r\n       \
+             dmarc=fail reason=\"p=REJECT; sp=none\" header.from=acme.com; iprev/2=pass \
+             policy.iprev=1.2.3.4",
+        );
+        assert_eq!(
+            (ar.authserv_id.as_str(), ar.version, ar.instance),
+            ("mx.google.com", Some(1), None)
+        );
+        let summary: Vec<(&str, &str, Option<&str>)> = ar
+            .results
+            .iter()
+            .map(|r| (r.method.as_str(), r.result.as_str(), r.domain.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("dkim", "pass", Some("news.acme.com")),
+                ("spf", "pass", Some("acme.com")),
+                ("dmarc", "fail", Some("acme.com")),
+                ("iprev", "pass", None),
+            ]
+        );
+        let dkim = &ar.results[0];
+        assert_eq!(dkim.selector.as_deref(), Some("k1"));
+        assert_eq!(dkim.properties["header.b"], "Ab+/c=");
+        let spf = &ar.results[1];
+        assert_eq!(
+            spf.comment.as_deref(),
+            Some("google.com: domain of x@acme.com; smtp.mailfrom=evil@spoof.net (nested)")
+        );
+        assert_eq!(spf.properties.len(), 1);
+        assert_eq!(ar.results[2].reason.as_deref(), Some("p=REJECT; sp=none"));
+        assert_eq!(ar.results[3].version, Some(2));
+        assert_eq!(ar.result_for("DMARC").as_deref(), Some("fail"));
+
+        let none = parse_value("mx.example.org; none");
+        assert_eq!((none.authserv_id.as_str(), none.results.len()), ("mx.example.org", 0));
+        let arc = parse_value("i=2; relay.net; arc=pass (as.1.acme.com=pass)");
+        assert_eq!((arc.instance, arc.authserv_id.as_str()), (Some(2), "relay.net"));
+        assert_eq!(arc.result_for("arc").as_deref(), Some("pass"));
+        // No authserv-id, and junk that must not loop or panic
+        assert_eq!(parse_value("spf=pass smtp.mailfrom=a@b.org").results[0].result, "pass");
+        for junk in [
+            "",
+            ";;;",
+            "x; =; a=\"unclosed",
+            "x; dkim=pass (open",
+            "(((",
+            "x; a=b ) c=d",
+        ] {
+            parse_value(junk);
+        }
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let headers = "ARC-Seal: i=2; a=rsa-sha256; cv=pass; d=relay.net; s=arc; t=1700000100; b=xyz\r\n\
+                       ARC-Message-Signature: i=2; a=rsa-sha256; c=relaxed/relaxed; d=relay.net;\r\n \
+                       s=arc; h=From : To:Subject; bh=abc; b=def\r\n\
+                       ARC-Authentication-Results: i=2; relay.net; dmarc=pass header.from=acme.com\r\n\
+                       Authentication-Results: mx.final.org; arc=pass; dkim=fail header.d=acme.com\r\n\
+                       ARC-Seal: i=1; a=rsa-sha256; cv=none; d=list.org; s=s1; b=xyz\r\n\
+                       ARC-Message-Signature: i=1; a=rsa-sha256; d=list.org; s=s1; h=from; b=x\r\n\
+                       ARC-Authentication-Results: i=1; mx.list.org; spf=pass smtp.mailfrom=acme.com\r\n\
+                       Authentication-Results: mx.list.org; spf=pass smtp.mailfrom=acme.com\r\n\
+                       From: a@acme.com\r\n\r\nbody\r\n";
+        let auth = parse_auth_headers_fast(headers);
+        assert_eq!(auth.authentication_results.len(), 2);
+        assert_eq!(auth.authentication_results[0].authserv_id, "mx.final.org");
+        assert_eq!(auth.arc_chain, "pass");
+        let instances: Vec<u32> = auth.arc_sets.iter().map(|s| s.instance).collect();
+        assert_eq!(instances, [1, 2]);
+        let second = &auth.arc_sets[1];
+        let seal = second.seal.as_ref().unwrap();
+        assert_eq!(seal.chain_validation.as_deref(), Some("pass"));
+        assert_eq!(seal.timestamp, Some(1_700_000_100));
+        let signature = second.message_signature.as_ref().unwrap();
+        assert_eq!(signature.signed_headers, ["From", "To", "Subject"]);
+        assert_eq!(signature.selector.as_deref(), Some("arc"));
+        let aar = second.authentication_results.as_ref().unwrap();
+        assert_eq!(aar.result_for("dmarc").as_deref(), Some("pass"));
+
+        // A missing set member, a gap or a wrong cv breaks the chain
+        let without = |needle: &str| {
+            let kept: Vec<&str> = headers
+                .split("\r\n")
+                .filter(|l| !l.starts_with(needle))
+                .collect();
+            parse_auth_headers_fast(&kept.join("\r\n")).arc_chain
+        };
+        assert_eq!(without("ARC-Authentication-Results: i=1"), "fail");
+        assert_eq!(parse_auth_headers_fast(&headers.replace("i=1", "i=3")).arc_chain, "fail");
+        assert_eq!(
+            parse_auth_headers_fast(&headers.replace("cv=pass", "cv=fail")).arc_chain,
+            "fail"
+        );
+        assert_eq!(parse_auth_headers_fast("From: a@b.org\r\n\r\n").arc_chain, "none");
+
+        crate::with_py(|py| {
+            let parsed = parse_auth_headers_batch(py, vec![headers.into(), String::new()]);
+            assert_eq!((parsed[0].arc_sets.len(), parsed[1].arc_chain.as_str()), (2, "none"));
+        });
+    }
+}
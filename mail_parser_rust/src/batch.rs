@@ -0,0 +1,58 @@
+//! Parallel map shared by the `*_batch` functions.
+//!
+//! Result `i` always belongs to input `i`: rayon's indexed `collect` writes
+//! each result into its input's slot no matter which thread finishes first.
+
+use rayon::prelude::*;
+
+/// Map `items` in parallel, keeping results in input order
+pub fn par_map<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync + Send,
+{
+    items.into_par_iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs whose cost varies wildly, so threads finish out of order
+    fn skewed_texts() -> Vec<String> {
+        (0..600)
+            .map(|i| {
+                let filler = "lorem ipsum ".repeat((i * 7919) % 64);
+                format!("item {i} mail{i}@example.com 10.0.{}.{} {filler}", i / 256, i % 256)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_par_map_order() {
+        let items: Vec<usize> = (0..10_000).collect();
+        let doubled = par_map(items, |n| n * 2);
+        assert!(doubled.iter().enumerate().all(|(i, &n)| n == i * 2));
+    }
+
+    #[test]
+    fn test_batch_functions_keep_input_order() {
+        crate::with_py(|py| {
+            let texts = skewed_texts();
+            let tokens =
+                crate::tokens::estimate_tokens_batch(py, texts.clone(), "cl100k", None).unwrap();
+            let found =
+                crate::extract::extract_tokens_batch(py, texts.clone(), None, None).unwrap();
+            let payments = crate::dlp::detect_payment_data_batch(py, texts.clone(), None).unwrap();
+            let ips = crate::geoip::extract_ip_info_batch(py, texts.clone()).unwrap();
+            for (i, text) in texts.iter().enumerate() {
+                let model = crate::tokens::Model::Cl100k;
+                assert_eq!(tokens[i], crate::tokens::count(text, model, None));
+                assert_eq!(found[i]["email"], vec![format!("mail{i}@example.com")]);
+                assert!(payments[i].is_empty());
+                assert_eq!(ips[i][0].ip, format!("10.0.{}.{}", i / 256, i % 256));
+            }
+        });
+    }
+}
@@ -0,0 +1,360 @@
+//! Throughput measurements on the caller's own files and hardware.
+//!
+//! Each run loads the file with the requested `io_mode`, scans it for
+//! separators and applies the operation to every message on a dedicated
+//! rayon pool of the requested size, so io modes and thread counts can be
+//! compared without a criterion harness. Timings include the load, which is
+//! what a real pipeline pays.
+
+use crate::mbox::{map_file, message_spans_with, FromLineMode, MappedFile};
+use crate::mime::Message;
+use crate::tuning::DEFAULT_CHUNK;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::ops::Range;
+use std::time::Instant;
+
+/// Supported operations, cheapest first
+pub const OPERATIONS: &[&str] = &["scan", "headers", "text", "uid", "parse"];
+
+/// Supported ways of loading the file
+pub const IO_MODES: &[&str] = &["mmap", "read"];
+
+/// Timings and throughput of one operation, io mode and thread count
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct BenchmarkResult {
+    /// Operation name (see `OPERATIONS`)
+    #[pyo3(get)]
+    pub operation: String,
+    /// How the file was loaded: "mmap" or "read"
+    #[pyo3(get)]
+    pub io_mode: String,
+    /// Worker threads in the pool
+    #[pyo3(get)]
+    pub threads: usize,
+    /// Timed repetitions
+    #[pyo3(get)]
+    pub repeat: usize,
+    /// Fastest repetition, in seconds
+    #[pyo3(get)]
+    pub best_seconds: f64,
+    /// Mean over all repetitions, in seconds
+    #[pyo3(get)]
+    pub mean_seconds: f64,
+    /// File size
+    #[pyo3(get)]
+    pub bytes: u64,
+    /// Messages processed per repetition
+    #[pyo3(get)]
+    pub messages: usize,
+    /// Throughput of the fastest repetition, in MB (10^6 bytes) per second
+    #[pyo3(get)]
+    pub mb_per_s: f64,
+    /// Messages per second in the fastest repetition
+    #[pyo3(get)]
+    pub messages_per_s: f64,
+    /// Peak resident set size of the process during the runs, in bytes
+    /// (None where the platform does not expose it)
+    #[pyo3(get)]
+    pub peak_rss_bytes: Option<u64>,
+    /// Chunk size of the parallel separator scan, as tuned for the file's
+    /// storage (see `scan_tuning`)
+    #[pyo3(get)]
+    pub chunk_bytes: usize,
+}
+
+#[pymethods]
+impl BenchmarkResult {
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    fn __repr__(&self) -> String {
+        format!(
+            "BenchmarkResult(operation={:?}, io_mode={:?}, threads={}, mb_per_s={:.1}, messages_per_s={:.0})",
+            self.operation, self.io_mode, self.threads, self.mb_per_s, self.messages_per_s
+        )
+    }
+}
+
+/// File contents, however they were loaded
+enum Loaded {
+    Mapped(MappedFile),
+    Read(Vec<u8>),
+}
+
+impl Loaded {
+    fn open(path: &str, io_mode: &str) -> PyResult<Self> {
+        if io_mode == "mmap" {
+            return map_file(path).map(Self::Mapped);
+        }
+        std::fs::read(crate::paths::extended(std::path::Path::new(path)))
+            .map(Self::Read)
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read file: {e}"))
+            })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Read(data) => data,
+        }
+    }
+}
+
+/// Apply `operation` to every message; the folded length keeps the
+/// optimizer from discarding the work
+fn run_operation(data: &[u8], spans: &[Range<usize>], operation: &str) -> usize {
+    let each = |span: &Range<usize>| {
+        let raw = &data[span.clone()];
+        match operation {
+            "headers" => {
+                let message = Message::parse(raw);
+                ["Subject", "From", "Date"]
+                    .iter()
+                    .filter_map(|name| message.decoded_header(name))
+                    .map(|value| value.len())
+                    .sum()
+            },
+            "text" => Message::parse(raw).text_body().len(),
+            "uid" => crate::uid::message_uid(raw).len(),
+            "parse" => crate::parse::parse(raw).text_bodies.len(),
+            _ => raw.len(),
+        }
+    };
+    spans.par_iter().map(each).sum()
+}
+
+/// One timed repetition, returning the messages seen and the scan's chunk
+/// size
+fn repetition(
+    path: &str,
+    io_mode: &str,
+    operation: &str,
+    mode: &FromLineMode,
+) -> PyResult<(usize, usize)> {
+    let loaded = Loaded::open(path, io_mode)?;
+    let data = loaded.bytes();
+    // A file read into memory is past its storage; only mappings are tuned
+    let (spans, chunk) = match &loaded {
+        Loaded::Mapped(map) => (map.spans(mode), map.chunk_bytes()),
+        Loaded::Read(_) => (message_spans_with(data, mode), DEFAULT_CHUNK),
+    };
+    if operation != "scan" {
+        std::hint::black_box(run_operation(data, &spans, operation));
+    }
+    Ok((spans.len(), chunk))
+}
+
+/// Reset the kernel's peak RSS counter so the next reading covers only
+/// what follows (Linux; a no-op elsewhere)
+fn reset_peak_rss() {
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
+
+/// Peak resident set size since the last reset, from `VmHWM`
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn check_choice(kind: &str, value: &str, allowed: &[&str]) -> PyResult<()> {
+    if allowed.contains(&value) {
+        return Ok(());
+    }
+    Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+        "Unknown {kind}: {value} (expected one of {})",
+        allowed.join(", ")
+    )))
+}
+
+/// Measure throughput of common operations on a real mbox
+///
+/// For every combination of operation, io mode and thread count the file
+/// is loaded, scanned and processed `repeat` times on a rayon pool of that
+/// size. The first repetition is not discarded, so a cold page cache shows
+/// up in `mean_seconds` but rarely in `best_seconds`.
+///
+/// Operations:
+/// - `scan`: find message separators only
+/// - `headers`: parse headers and decode Subject, From and Date
+/// - `text`: extract the plain-text body
+/// - `uid`: compute the stable message UID
+/// - `parse`: full `parse_message_fast`
+///
+/// # Arguments
+/// * `path` - Path to the mbox file
+/// * `operations` - Operations to run (default: all)
+/// * `repeat` - Timed repetitions per combination (default 3)
+/// * `io_modes` - "mmap" and/or "read" (read the whole file into memory)
+///   (default: ["mmap"])
+/// * `threads` - Pool sizes to try (default: the number of CPUs)
+/// * `from_line_mode` - Separator mode, as in `count_messages_fast`
+///
+/// # Returns
+/// * List of `BenchmarkResult`, one per combination, with `best_seconds`,
+///   `mean_seconds`, `mb_per_s`, `messages_per_s` and `peak_rss_bytes`
+///   (process-wide, reset before each combination on Linux; None on other
+///   platforms)
+///
+/// # Raises
+/// * `ValueError` for an unknown operation or io mode, `repeat=0` or a
+///   thread count of 0
+/// * `IOError` if the file cannot be read
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import benchmark
+/// for r in benchmark("emails.mbox", ["scan", "parse"], io_modes=["mmap", "read"], threads=[1, 4, 8]):
+///     print(f"{r.operation:8} {r.io_mode:5} {r.threads:2}t {r.mb_per_s:8.1f} MB/s {r.messages_per_s:10.0f} msg/s")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (path, operations=None, repeat=3, io_modes=None, threads=None, from_line_mode="standard"))]
+pub fn benchmark(
+    py: Python<'_>,
+    path: &str,
+    operations: Option<Vec<String>>,
+    repeat: usize,
+    io_modes: Option<Vec<String>>,
+    threads: Option<Vec<usize>>,
+    from_line_mode: &str,
+) -> PyResult<Vec<BenchmarkResult>> {
+    let operations =
+        operations.unwrap_or_else(|| OPERATIONS.iter().map(|o| (*o).to_string()).collect());
+    let io_modes = io_modes.unwrap_or_else(|| vec!["mmap".to_string()]);
+    let threads = threads.unwrap_or_else(|| vec![rayon::current_num_threads()]);
+    for operation in &operations {
+        check_choice("operation", operation, OPERATIONS)?;
+    }
+    for io_mode in &io_modes {
+        check_choice("io mode", io_mode, IO_MODES)?;
+    }
+    if repeat == 0 || threads.contains(&0) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "repeat and thread counts must be at least 1",
+        ));
+    }
+    let mode = FromLineMode::parse(from_line_mode)?;
+    let bytes = std::fs::metadata(crate::paths::extended(std::path::Path::new(path)))
+        .map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open file: {e}"))
+        })?
+        .len();
+
+    py.allow_threads(|| {
+        let mut results = Vec::new();
+        for &size in &threads {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(size)
+                .build()
+                .map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                        "Failed to start {size} threads: {e}"
+                    ))
+                })?;
+            for io_mode in &io_modes {
+                for operation in &operations {
+                    reset_peak_rss();
+                    let mut timings = Vec::with_capacity(repeat);
+                    let mut scanned = (0, DEFAULT_CHUNK);
+                    for _ in 0..repeat {
+                        let started = Instant::now();
+                        scanned = pool.install(|| repetition(path, io_mode, operation, &mode))?;
+                        timings.push(started.elapsed().as_secs_f64());
+                    }
+                    results.push(summarize(operation, io_mode, size, &timings, bytes, scanned));
+                }
+            }
+        }
+        Ok(results)
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn summarize(
+    operation: &str,
+    io_mode: &str,
+    threads: usize,
+    timings: &[f64],
+    bytes: u64,
+    (messages, chunk_bytes): (usize, usize),
+) -> BenchmarkResult {
+    let best = timings.iter().copied().fold(f64::INFINITY, f64::min);
+    let mean = timings.iter().sum::<f64>() / timings.len() as f64;
+    let rate = |amount: f64| if best > 0.0 { amount / best } else { 0.0 };
+    BenchmarkResult {
+        operation: operation.to_string(),
+        io_mode: io_mode.to_string(),
+        threads,
+        repeat: timings.len(),
+        best_seconds: best,
+        mean_seconds: mean,
+        bytes,
+        messages,
+        mb_per_s: rate(bytes as f64 / 1e6),
+        messages_per_s: rate(messages as f64),
+        peak_rss_bytes: peak_rss(),
+        chunk_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_benchmark_runs_every_combination() {
+        let dir = std::env::temp_dir().join(format!("mpr_benchmark_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bench.mbox");
+        let mut data = Vec::new();
+        for n in 0..20 {
+            data.extend_from_slice(
+                format!(
+                    "From a@example.com Mon Jan  1 00:00:00 2024\nFrom: a@example.com\nSubject: {n}\n\nbody {n}\n\n"
+                )
+                .as_bytes(),
+            );
+        }
+        std::fs::write(&path, &data).unwrap();
+        let path = path.to_str().unwrap();
+
+        let results = crate::with_py(|py| {
+            benchmark(
+                py,
+                path,
+                None,
+                2,
+                Some(vec!["mmap".into(), "read".into()]),
+                Some(vec![1, 2]),
+                "standard",
+            )
+        })
+        .unwrap();
+        assert_eq!(results.len(), OPERATIONS.len() * 2 * 2);
+        for r in &results {
+            assert_eq!(r.messages, 20, "{r:?}");
+            assert_eq!(r.bytes, data.len() as u64);
+            assert_eq!(r.repeat, 2);
+            assert!(r.best_seconds <= r.mean_seconds);
+            assert!(r.mb_per_s >= 0.0 && r.messages_per_s >= 0.0);
+            if cfg!(target_os = "linux") {
+                assert!(r.peak_rss_bytes.unwrap() > 0);
+            }
+        }
+
+        let run = |ops: Vec<String>, repeat: usize, threads: Vec<usize>| {
+            crate::with_py(|py| {
+                benchmark(py, path, Some(ops), repeat, None, Some(threads), "standard")
+            })
+        };
+        assert!(run(vec!["bogus".into()], 1, vec![1]).is_err());
+        assert!(run(vec!["scan".into()], 0, vec![1]).is_err());
+        assert!(run(vec!["scan".into()], 1, vec![0]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
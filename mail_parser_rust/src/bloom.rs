@@ -0,0 +1,383 @@
+//! Bloom filter over Message-IDs for fast "already archived?" checks.
+//!
+//! Building reads an archive once; afterwards a membership test is a few
+//! hashed bit lookups, so a multi-gigabyte master archive can be screened
+//! without its index. A negative answer is certain; a positive one is wrong
+//! with the configured false-positive rate and should be confirmed against
+//! the archive when it matters.
+//!
+//! IDs are normalized (angle brackets and surrounding space removed,
+//! lowercased) and hashed with SHA-256, split into two 64-bit halves for
+//! double hashing. The hash is fixed, so saved filters stay valid across
+//! builds and platforms.
+
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::output::Output;
+use crate::progress::Progress;
+use crate::thread::parse_ids;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+/// Serialized filter header
+const MAGIC: &[u8; 8] = b"MPRBLOOM";
+
+/// Serialization format version
+const VERSION: u8 = 1;
+
+/// Header length: magic, version, hash count, bit count, item count
+const HEADER_LEN: usize = 8 + 1 + 1 + 8 + 8;
+
+/// Canonical form of a Message-ID: no brackets, lowercased
+fn normalize_id(message_id: &str) -> String {
+    message_id
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim()
+        .to_lowercase()
+}
+
+/// The two base hashes of an ID
+fn base_hashes(id: &str) -> (u64, u64) {
+    let digest = Sha256::digest(id.as_bytes());
+    let mut first = [0u8; 8];
+    let mut second = [0u8; 8];
+    first.copy_from_slice(&digest[..8]);
+    second.copy_from_slice(&digest[8..16]);
+    // An odd step visits distinct bits for every probe
+    (u64::from_le_bytes(first), u64::from_le_bytes(second) | 1)
+}
+
+/// Lossy float view of a count, good to well past any archive size
+#[allow(clippy::cast_precision_loss)]
+const fn as_f64(n: u64) -> f64 {
+    n as f64
+}
+
+/// Bit and hash counts for `items` at false-positive rate `rate`
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn dimensions(items: u64, rate: f64) -> (u64, u8) {
+    let n = as_f64(items.max(1));
+    let ln2 = std::f64::consts::LN_2;
+    let bits = (-n * rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+    let hashes = (bits / n * ln2).round().clamp(1.0, 32.0);
+    (bits as u64, hashes as u8)
+}
+
+/// Message-ID filter for cross-archive membership tests
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import IdBloom, build_id_bloom
+/// bloom = build_id_bloom("master.mbox")
+/// bloom.save("master.bloom")
+/// bloom = IdBloom.load("master.bloom")
+/// if "<abc@example.com>" not in bloom:
+///     print("definitely new")
+/// ```
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct IdBloom {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hashes: u8,
+    count: u64,
+}
+
+impl IdBloom {
+    pub fn with_rate(items: u64, rate: f64) -> Self {
+        let (bit_count, hashes) = dimensions(items, rate);
+        let words = usize::try_from((bit_count + 63) / 64).unwrap_or(usize::MAX);
+        Self {
+            bits: vec![0; words],
+            bit_count,
+            hashes,
+            count: 0,
+        }
+    }
+
+    fn positions(&self, id: &str) -> impl Iterator<Item = u64> {
+        let (first, second) = base_hashes(id);
+        let bit_count = self.bit_count;
+        (0..u64::from(self.hashes))
+            .map(move |i| first.wrapping_add(i.wrapping_mul(second)) % bit_count)
+    }
+
+    pub fn insert(&mut self, message_id: &str) {
+        let id = normalize_id(message_id);
+        if id.is_empty() {
+            return;
+        }
+        for bit in self.positions(&id) {
+            self.bits[usize::try_from(bit / 64).unwrap_or(0)] |= 1 << (bit % 64);
+        }
+        self.count += 1;
+    }
+
+    pub fn contains(&self, message_id: &str) -> bool {
+        let id = normalize_id(message_id);
+        !id.is_empty()
+            && self.positions(&id).all(|bit| {
+                self.bits[usize::try_from(bit / 64).unwrap_or(0)] & (1 << (bit % 64)) != 0
+            })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.bits.len() * 8);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(self.hashes);
+        out.extend_from_slice(&self.bit_count.to_le_bytes());
+        out.extend_from_slice(&self.count.to_le_bytes());
+        for word in &self.bits {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || &data[..8] != MAGIC || data[8] != VERSION {
+            return None;
+        }
+        let hashes = data[9];
+        let read_u64 = |at: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&data[at..at + 8]);
+            u64::from_le_bytes(word)
+        };
+        let bit_count = read_u64(10);
+        let count = read_u64(18);
+        let body = &data[HEADER_LEN..];
+        let words = usize::try_from((bit_count + 63) / 64).ok()?;
+        if hashes == 0 || bit_count == 0 || body.len() != words.checked_mul(8)? {
+            return None;
+        }
+        let bits = (0..words).map(|i| read_u64(HEADER_LEN + i * 8)).collect();
+        Some(Self {
+            bits,
+            bit_count,
+            hashes,
+            count,
+        })
+    }
+}
+
+fn format_error() -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>("Not a serialized IdBloom filter")
+}
+
+#[pymethods]
+impl IdBloom {
+    /// Create an empty filter sized for `capacity` IDs
+    ///
+    /// # Arguments
+    /// * `capacity` - Expected number of IDs
+    /// * `false_positive_rate` - Target rate at that capacity (default 0.001)
+    #[new]
+    #[pyo3(signature = (capacity, false_positive_rate=0.001))]
+    fn new(capacity: u64, false_positive_rate: f64) -> PyResult<Self> {
+        check_rate(false_positive_rate)?;
+        Ok(Self::with_rate(capacity, false_positive_rate))
+    }
+
+    /// Add a Message-ID (with or without angle brackets)
+    fn add(&mut self, message_id: &str) {
+        self.insert(message_id);
+    }
+
+    /// Whether the ID may have been added (False is certain)
+    fn probably_contains(&self, message_id: &str) -> bool {
+        self.contains(message_id)
+    }
+
+    fn __contains__(&self, message_id: &str) -> bool {
+        self.contains(message_id)
+    }
+
+    /// IDs added
+    #[getter]
+    const fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Filter size in bits
+    #[getter]
+    const fn bits(&self) -> u64 {
+        self.bit_count
+    }
+
+    /// Hash probes per ID
+    #[getter]
+    const fn hashes(&self) -> u8 {
+        self.hashes
+    }
+
+    /// Expected false-positive rate at the current count
+    #[getter]
+    fn false_positive_rate(&self) -> f64 {
+        let k = f64::from(self.hashes);
+        let fill = 1.0 - (-k * as_f64(self.count) / as_f64(self.bit_count)).exp();
+        fill.powf(k)
+    }
+
+    /// Serialized filter
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.encode())
+    }
+
+    /// Filter from `to_bytes` output
+    ///
+    /// # Raises
+    /// * `ValueError` if the data is not a serialized filter
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        Self::decode(data).ok_or_else(format_error)
+    }
+
+    /// Write the filter to a file (atomically replaced)
+    fn save(&self, path: &str) -> PyResult<()> {
+        let io_error = |e: std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {path}: {e}"))
+        };
+        let output = Output::new(false);
+        let mut file = output.create(Path::new(path)).map_err(io_error)?;
+        file.write_all(&self.encode()).map_err(io_error)?;
+        file.finish().map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Read a filter written by `save`
+    ///
+    /// # Raises
+    /// * `IOError` if unreadable, `ValueError` if not a filter
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        let data = std::fs::read(crate::paths::extended(Path::new(path))).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {path}: {e}"))
+        })?;
+        Self::decode(&data).ok_or_else(format_error)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("IdBloom(count={}, bits={}, hashes={})", self.count, self.bit_count, self.hashes)
+    }
+}
+
+fn check_rate(rate: f64) -> PyResult<()> {
+    if rate > 0.0 && rate < 1.0 {
+        Ok(())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "false_positive_rate must be between 0 and 1, got {rate}"
+        )))
+    }
+}
+
+/// Build a Message-ID Bloom filter from an mbox archive
+///
+/// Messages without a Message-ID are skipped. The filter is sized for the
+/// archive's message count at the requested false-positive rate (about 1.8
+/// bytes per message at 0.001).
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `false_positive_rate` - Target false-positive rate (default 0.001)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `IdBloom`; test with `probably_contains` or `in`, persist with `save`
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import build_id_bloom, probably_contains
+/// bloom = build_id_bloom("master.mbox")
+/// if not probably_contains(bloom, msg["Message-ID"]):
+///     import_message(msg)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, false_positive_rate=0.001, from_line_mode="standard", progress=None))]
+pub fn build_id_bloom(
+    py: Python<'_>,
+    mbox_path: &str,
+    false_positive_rate: f64,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<IdBloom> {
+    py.allow_threads(|| {
+        check_rate(false_positive_rate)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let spans = mmap.spans(&mode);
+        let ids: Vec<String> = spans
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .filter_map(|span| {
+                let msg = Message::parse(&mmap[span]);
+                msg.header("Message-ID")
+                    .and_then(|value| parse_ids(value).into_iter().next())
+            })
+            .collect();
+        progress.check("build_id_bloom")?;
+        mmap.verify(&mode)?;
+        let mut bloom =
+            IdBloom::with_rate(u64::try_from(ids.len()).unwrap_or(u64::MAX), false_positive_rate);
+        for id in ids {
+            bloom.insert(&id);
+        }
+        Ok(bloom)
+    })
+}
+
+/// Whether a Message-ID may be in a filter (False is certain)
+///
+/// # Arguments
+/// * `bloom` - Filter from `build_id_bloom` or `IdBloom.load`
+/// * `message_id` - ID with or without angle brackets; case-insensitive
+#[pyfunction]
+pub fn probably_contains(bloom: &IdBloom, message_id: &str) -> bool {
+    bloom.contains(message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom() {
+        let mut bloom = IdBloom::with_rate(1000, 0.01);
+        for i in 0..1000 {
+            bloom.insert(&format!("<msg{i}@example.com>"));
+        }
+        assert!((0..1000).all(|i| bloom.contains(&format!("MSG{i}@example.com"))));
+        let false_positives = (0..10_000)
+            .filter(|i| bloom.contains(&format!("other{i}@example.com")))
+            .count();
+        assert!(false_positives < 250, "{false_positives}");
+        assert!(!bloom.contains(""));
+        assert!(bloom.false_positive_rate() < 0.02);
+
+        let decoded = IdBloom::decode(&bloom.encode()).unwrap();
+        assert_eq!(decoded.bits, bloom.bits);
+        assert_eq!(decoded.count, 1000);
+        assert!(IdBloom::decode(b"MPRBLOOM").is_none());
+        assert!(IdBloom::decode(&bloom.encode()[..100]).is_none());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let (bits, hashes) = dimensions(1_000_000, 0.001);
+        assert!((14_000_000..14_500_000).contains(&bits), "{bits}");
+        assert_eq!(hashes, 10);
+    }
+}
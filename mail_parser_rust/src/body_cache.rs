@@ -0,0 +1,543 @@
+//! Persistent cache of decoded message bodies.
+//!
+//! Decoding a body (transfer encoding, charset, HTML to text) is the most
+//! expensive part of most analytics passes, and repeated passes over the
+//! same archive redo it for every message. A [`BodyCache`] keeps the decoded
+//! text bodies of one mbox zstd-compressed in a single file, keyed by message
+//! UID (see [`crate::uid`]), so later passes only decode the messages they
+//! have not seen.
+//!
+//! The file starts with the source size and fingerprint, like a message
+//! index sidecar: a grown mbox keeps its entries, a truncated or rewritten
+//! one drops them all. New entries are appended on `flush`; once the cache
+//! exceeds its size limit the least recently used entries are evicted and
+//! the file is rewritten with the remaining ones, oldest first, so recency
+//! survives reopening. A record cut short by a crash is dropped on open.
+
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::offset_index::{fingerprint, freshness, Freshness};
+use crate::output::Output;
+use crate::paths::extended;
+use crate::progress::Progress;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// First bytes of a cache file
+const MAGIC: &[u8; 8] = b"MPRBDY01";
+
+/// zstd level of cached bodies
+const LEVEL: i32 = 3;
+
+/// Default size limit of a cache file
+const DEFAULT_MAX_BYTES: u64 = 256 << 20;
+
+/// Bytes of a record besides its UID and data (two length prefixes)
+const RECORD_OVERHEAD: usize = 8;
+
+fn to_u64(value: usize) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+/// One cached body
+struct Entry {
+    /// zstd-compressed UTF-8 text
+    data: Vec<u8>,
+    /// Use clock reading of the last hit or insert
+    used: u64,
+}
+
+/// The next `n` bytes of `rest`, None if it is shorter
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if rest.len() < n {
+        return None;
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Some(head)
+}
+
+fn take_len(rest: &mut &[u8]) -> Option<usize> {
+    let mut word = [0; 4];
+    word.copy_from_slice(take(rest, 4)?);
+    usize::try_from(u32::from_le_bytes(word)).ok()
+}
+
+fn encode_record(out: &mut Vec<u8>, uid: &str, data: &[u8]) {
+    for field in [uid.as_bytes(), data] {
+        out.extend_from_slice(&u32::try_from(field.len()).unwrap_or(u32::MAX).to_le_bytes());
+        out.extend_from_slice(field);
+    }
+}
+
+fn record_size(uid: &str, entry: &Entry) -> u64 {
+    to_u64(uid.len() + entry.data.len() + RECORD_OVERHEAD)
+}
+
+/// A body found in the cache, or decoded (and compressed for it)
+enum Lookup {
+    Hit(String),
+    Miss { text: String, data: Vec<u8> },
+}
+
+/// Decoded text bodies of one mbox, zstd-compressed on disk
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import BodyCache
+/// cache = BodyCache("inbox.bodies", "inbox.mbox", max_bytes=1 << 30)
+/// bodies = cache.body_texts()  # decodes everything the first time
+/// bodies = cache.body_texts()  # served from the cache
+/// print(cache.hits, cache.misses)
+/// ```
+#[pyclass]
+pub struct BodyCache {
+    path: PathBuf,
+    mbox_path: String,
+    max_bytes: u64,
+    /// Size and fingerprint of the mbox the entries were decoded from
+    source_len: u64,
+    fingerprint: u64,
+    entries: HashMap<String, Entry>,
+    /// Bytes the entries take in the file
+    size: u64,
+    clock: u64,
+    /// Inserted since the last flush, in order
+    pending: Vec<String>,
+    /// Whether the file must be written anew rather than appended to
+    rewrite: bool,
+    hits: u64,
+    misses: u64,
+}
+
+impl BodyCache {
+    pub fn open(path: &Path, mbox_path: &str, max_bytes: u64) -> io::Result<Self> {
+        let mut cache = Self {
+            path: path.to_path_buf(),
+            mbox_path: mbox_path.to_string(),
+            max_bytes,
+            source_len: 0,
+            fingerprint: 0,
+            entries: HashMap::new(),
+            size: 0,
+            clock: 0,
+            pending: Vec::new(),
+            rewrite: true,
+            hits: 0,
+            misses: 0,
+        };
+        match fs::read(extended(path)) {
+            Ok(bytes) => cache.decode(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        Ok(cache)
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a body cache");
+        let mut rest = bytes.strip_prefix(MAGIC).ok_or_else(invalid)?;
+        let mut word = [0; 8];
+        for field in [&mut self.source_len, &mut self.fingerprint] {
+            word.copy_from_slice(take(&mut rest, 8).ok_or_else(invalid)?);
+            *field = u64::from_le_bytes(word);
+        }
+        self.rewrite = false;
+        loop {
+            let record = take_len(&mut rest)
+                .and_then(|n| take(&mut rest, n))
+                .and_then(|uid| Some((uid, take_len(&mut rest).and_then(|n| take(&mut rest, n))?)));
+            let Some((uid, data)) = record else {
+                break;
+            };
+            let uid = String::from_utf8_lossy(uid).into_owned();
+            self.insert(uid, data.to_vec());
+        }
+        // A torn last record is rewritten away by the next flush
+        self.rewrite |= !rest.is_empty();
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Record `data` for `uid` as the most recently used entry
+    fn insert(&mut self, uid: String, data: Vec<u8>) {
+        self.clock += 1;
+        let entry = Entry {
+            data,
+            used: self.clock,
+        };
+        self.size += record_size(&uid, &entry);
+        if let Some(old) = self.entries.insert(uid.clone(), entry) {
+            self.size -= record_size(&uid, &old);
+            // Replaced records stay in the file until it is rewritten
+            self.rewrite = true;
+        }
+        self.pending.push(uid);
+    }
+
+    fn touch(&mut self, uid: &str) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(uid) {
+            entry.used = self.clock;
+        }
+    }
+
+    /// Drop least recently used entries until the cache fits its limit
+    fn evict(&mut self) {
+        if self.size <= self.max_bytes {
+            return;
+        }
+        let mut by_age: Vec<(u64, String)> = self
+            .entries
+            .iter()
+            .map(|(uid, entry)| (entry.used, uid.clone()))
+            .collect();
+        by_age.sort_unstable();
+        for (_, uid) in by_age {
+            if self.size <= self.max_bytes {
+                break;
+            }
+            if let Some(entry) = self.entries.remove(&uid) {
+                self.size -= record_size(&uid, &entry);
+            }
+        }
+        self.rewrite = true;
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.pending.clear();
+        self.size = 0;
+        self.rewrite = true;
+    }
+
+    /// Match the cache to the current contents of its mbox, dropping every
+    /// entry if it was truncated or rewritten
+    fn sync_source(&mut self, data: &[u8]) {
+        let state = freshness(data, self.source_len, self.fingerprint);
+        if state == Freshness::Stale {
+            self.clear();
+        }
+        if state != Freshness::Current {
+            self.source_len = to_u64(data.len());
+            self.fingerprint = fingerprint(data);
+            self.rewrite = true;
+        }
+    }
+
+    /// The cached body of `uid`, None on a miss (or an unreadable entry)
+    fn cached(&self, uid: &str) -> Option<String> {
+        let entry = self.entries.get(uid)?;
+        let text = zstd::decode_all(&entry.data[..]).ok()?;
+        String::from_utf8(text).ok()
+    }
+
+    /// Decoded text bodies of `raw_messages`, from the cache where possible;
+    /// the rest are decoded in parallel and added
+    pub fn texts(&mut self, raw_messages: &[&[u8]]) -> Vec<String> {
+        let looked_up: Vec<(String, Lookup)> = raw_messages
+            .par_iter()
+            .map(|raw| {
+                let uid = message_uid(raw);
+                let found = self.cached(&uid).map_or_else(
+                    || {
+                        let text = Message::parse(raw).text_body();
+                        let data = zstd::bulk::compress(text.as_bytes(), LEVEL).unwrap_or_default();
+                        Lookup::Miss { text, data }
+                    },
+                    Lookup::Hit,
+                );
+                (uid, found)
+            })
+            .collect();
+        let mut texts = Vec::with_capacity(looked_up.len());
+        let mut added = HashSet::new();
+        for (uid, found) in looked_up {
+            match found {
+                Lookup::Hit(text) => {
+                    self.hits += 1;
+                    self.touch(&uid);
+                    texts.push(text);
+                },
+                Lookup::Miss { text, data } => {
+                    self.misses += 1;
+                    // A message repeated in the batch is stored once; an
+                    // unreadable entry is replaced
+                    if added.insert(uid.clone()) {
+                        self.insert(uid, data);
+                    } else {
+                        self.touch(&uid);
+                    }
+                    texts.push(text);
+                },
+            }
+        }
+        self.evict();
+        texts
+    }
+
+    /// Write what changed since the last flush
+    pub fn write(&mut self) -> io::Result<()> {
+        if self.rewrite {
+            let mut by_age: Vec<(&String, &Entry)> = self.entries.iter().collect();
+            by_age.sort_unstable_by_key(|(_, entry)| entry.used);
+            let mut out = Vec::with_capacity(24 + usize::try_from(self.size).unwrap_or(0));
+            out.extend_from_slice(MAGIC);
+            out.extend_from_slice(&self.source_len.to_le_bytes());
+            out.extend_from_slice(&self.fingerprint.to_le_bytes());
+            for (uid, entry) in by_age {
+                encode_record(&mut out, uid, &entry.data);
+            }
+            let mut file = Output::new(false).create(&self.path)?;
+            file.write_all(&out)?;
+            file.finish()?;
+        } else if !self.pending.is_empty() {
+            let mut out = Vec::new();
+            for uid in &self.pending {
+                if let Some(entry) = self.entries.get(uid) {
+                    encode_record(&mut out, uid, &entry.data);
+                }
+            }
+            let mut file = OpenOptions::new().append(true).open(extended(&self.path))?;
+            file.write_all(&out)?;
+        }
+        self.pending.clear();
+        self.rewrite = false;
+        Ok(())
+    }
+
+    fn io_error(&self, e: &io::Error) -> PyErr {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Body cache {}: {e}",
+            self.path.display()
+        ))
+    }
+}
+
+#[pymethods]
+impl BodyCache {
+    /// Open the body cache of an mbox, or start an empty one
+    ///
+    /// # Arguments
+    /// * `path` - Cache file (created by the first flush)
+    /// * `mbox_path` - The mbox whose bodies it holds
+    /// * `max_bytes` - Size limit of the cache file (default 256 MiB); the
+    ///   least recently used bodies are evicted beyond it
+    ///
+    /// # Raises
+    /// * `IOError` if the file is unreadable or not a body cache
+    #[new]
+    #[pyo3(signature = (path, mbox_path, max_bytes=DEFAULT_MAX_BYTES))]
+    fn new(path: &str, mbox_path: &str, max_bytes: u64) -> PyResult<Self> {
+        Self::open(Path::new(path), mbox_path, max_bytes).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Body cache {path}: {e}"))
+        })
+    }
+
+    /// Path of the cache file
+    #[getter]
+    fn path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    /// Number of cached bodies
+    fn __len__(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Bytes the cached bodies take on disk (once flushed)
+    #[getter]
+    const fn size_bytes(&self) -> u64 {
+        self.size
+    }
+
+    /// Bodies served from the cache since it was opened
+    #[getter]
+    const fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Bodies decoded since it was opened
+    #[getter]
+    const fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Decoded text body of every message of the mbox, in order
+    ///
+    /// A body is the message's first text part, decoded (HTML converted to
+    /// text when there is no plain part). Cached bodies are decompressed instead
+    /// of decoded; the others are decoded in parallel, added and written
+    /// out before returning. If the mbox was truncated or rewritten since
+    /// the cache was filled, every entry is dropped first.
+    ///
+    /// # Arguments
+    /// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+    /// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+    ///   during the scan; returning False cancels it before anything is
+    ///   written
+    ///
+    /// # Raises
+    /// * `IOError` - The mbox or cache file cannot be read or written
+    /// * `FileChangedError` - The mbox changed during the scan
+    /// * `ScanCancelled` - `progress` returned False (or what it raised)
+    #[pyo3(signature = (from_line_mode="standard", progress=None))]
+    fn body_texts(
+        &mut self,
+        py: Python<'_>,
+        from_line_mode: &str,
+        progress: Option<PyObject>,
+    ) -> PyResult<Vec<String>> {
+        py.allow_threads(|| {
+            let mode = FromLineMode::parse(from_line_mode)?;
+            let mmap = map_file(&self.mbox_path)?;
+            let data = &mmap[..];
+            let progress = Progress::new(progress, mmap.len());
+            self.sync_source(data);
+            let raw_messages: Vec<&[u8]> = mmap
+                .spans_reporting(&mode, &progress)
+                .into_iter()
+                .map(|span| &data[span])
+                .collect();
+            if progress.cancelled() {
+                return Err(progress.error("body_texts"));
+            }
+            let texts = self.texts(&raw_messages);
+            progress.check("body_texts")?;
+            mmap.verify(&mode)?;
+            self.write().map_err(|e| self.io_error(&e))?;
+            Ok(texts)
+        })
+    }
+
+    /// Decoded text body of one raw message, through the cache
+    ///
+    /// New bodies are kept in memory until `flush`.
+    fn body_text(&mut self, raw_bytes: &[u8]) -> String {
+        self.texts(&[raw_bytes]).pop().unwrap_or_default()
+    }
+
+    /// Write the bodies added since the last flush
+    ///
+    /// # Raises
+    /// * `IOError` if the cache file cannot be written
+    fn flush(&mut self) -> PyResult<()> {
+        self.write().map_err(|e| self.io_error(&e))
+    }
+
+    /// Drop every cached body (the file is emptied by the next flush)
+    #[pyo3(name = "clear")]
+    fn py_clear(&mut self) {
+        self.clear();
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "BodyCache(path={:?}, entries={}, size_bytes={})",
+            self.path.display().to_string(),
+            self.entries.len(),
+            self.size
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_cache() {
+        let dir = std::env::temp_dir().join(format!("mpr_bodies_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mbox = dir.join("a.mbox");
+        let first = "From a Mon Jan  1 00:00:00 2024\nMessage-ID: <1@x>\n\
+                     Content-Type: text/plain; charset=utf-8\n\
+                     Content-Transfer-Encoding: quoted-printable\n\nCaf=C3=A9 time\n";
+        let second = "From b Mon Jan  1 00:00:00 2024\nMessage-ID: <2@x>\n\
+                      Content-Type: text/html\n\n<p>Hello <b>there</b></p>\n";
+        fs::write(&mbox, format!("{first}\n{second}")).unwrap();
+        let path = dir.join("a.bodies");
+        let mbox = mbox.to_str().unwrap();
+
+        crate::with_py(|py| {
+            let mut cache = BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES).unwrap();
+            let texts = cache.body_texts(py, "standard", None).unwrap();
+            assert!(texts[0].starts_with("Café time"));
+            assert!(texts[1].contains("Hello there"));
+            assert_eq!((cache.hits, cache.misses, cache.entries.len()), (0, 2, 2));
+            assert_eq!(fs::metadata(&path).unwrap().len(), cache.size + 24);
+
+            // Reopened, everything is served from the file
+            let mut cache = BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES).unwrap();
+            assert_eq!(cache.body_texts(py, "standard", None).unwrap(), texts);
+            assert_eq!((cache.hits, cache.misses), (2, 0));
+
+            // Appended messages keep the entries; new ones are appended
+            let third = "From c Mon Jan  1 00:00:00 2024\nMessage-ID: <3@x>\n\nThird\n";
+            let mut file = OpenOptions::new().append(true).open(mbox).unwrap();
+            file.write_all(format!("\n{third}").as_bytes()).unwrap();
+            drop(file);
+            assert_eq!(cache.body_texts(py, "standard", None).unwrap()[2], "Third\n");
+            assert_eq!((cache.hits, cache.misses), (4, 1));
+
+            // A one-off body stays in memory until flushed
+            let extra = b"From d Mon Jan  1 00:00:00 2024\nMessage-ID: <4@x>\n\nFour\n";
+            assert_eq!(cache.body_text(extra), "Four\n");
+            assert_eq!(
+                BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES)
+                    .unwrap()
+                    .entries
+                    .len(),
+                3
+            );
+            cache.flush().unwrap();
+            assert_eq!(
+                BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES)
+                    .unwrap()
+                    .entries
+                    .len(),
+                4
+            );
+
+            // A torn record is dropped
+            let len = fs::metadata(&path).unwrap().len();
+            OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_len(len - 3)
+                .unwrap();
+            let mut torn = BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES).unwrap();
+            assert_eq!(torn.entries.len(), 3);
+            torn.flush().unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().len(), torn.size + 24);
+
+            // A rewritten mbox invalidates every entry
+            fs::write(mbox, third).unwrap();
+            let mut cache = BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES).unwrap();
+            assert_eq!(cache.body_texts(py, "standard", None).unwrap(), ["Third\n"]);
+            assert_eq!((cache.hits, cache.misses, cache.entries.len()), (0, 1, 1));
+
+            // Over the limit, the least recently used bodies go first
+            fs::write(mbox, format!("{first}\n{second}\n{third}")).unwrap();
+            let mut small = BodyCache::open(&path, mbox, 1).unwrap();
+            assert_eq!(small.body_texts(py, "standard", None).unwrap().len(), 3);
+            assert_eq!((small.entries.len(), small.size), (0, 0));
+            let mut cache = BodyCache::open(&path, mbox, DEFAULT_MAX_BYTES).unwrap();
+            cache.body_texts(py, "standard", None).unwrap();
+            cache.max_bytes = cache.size - 1;
+            cache.body_text(third.as_bytes());
+            assert_eq!(cache.entries.len(), 2);
+            assert!(cache.cached(&message_uid(third.as_bytes())).is_some());
+            assert!(cache.cached(&message_uid(first.as_bytes())).is_none());
+
+            assert!(BodyCache::open(Path::new(mbox), mbox, 1).is_err());
+        });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+//! iCalendar (RFC 5545) extraction from messages and merged `.ics` export.
+//!
+//! Invitations arrive as `text/calendar` parts (or `.ics` attachments), often
+//! several times per meeting as it is updated or cancelled. Events are keyed
+//! by UID plus RECURRENCE-ID, and of several copies the one with the highest
+//! SEQUENCE (then latest DTSTAMP, then latest in the archive) wins.
+
+use crate::contacts::content_line;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::output::{Output, OutputReport};
+use crate::progress::Progress;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::path::Path;
+
+/// Scheduling methods whose VEVENTs are attendee responses, not the schedule
+const RESPONSE_METHODS: &[&str] = &["REPLY", "COUNTER", "REFRESH", "DECLINECOUNTER"];
+
+/// One VEVENT, unfolded, including its BEGIN/END lines
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// UID and RECURRENCE-ID (or DTSTART and SUMMARY when there is no UID)
+    pub key: String,
+    pub sequence: i64,
+    /// DTSTAMP value; UTC basic format, so it sorts as text
+    pub stamp: String,
+    pub start: String,
+    pub lines: Vec<String>,
+}
+
+/// Events and time zone definitions found in one calendar object
+#[derive(Default, Debug)]
+pub struct Calendar {
+    pub events: Vec<Event>,
+    /// VTIMEZONE components by TZID
+    pub timezones: Vec<(String, Vec<String>)>,
+}
+
+/// Join folded continuation lines (a leading space or tab continues the line)
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if line.is_empty() => {},
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Property name (uppercased) and value of a content line; the value starts
+/// at the first `:` outside a quoted parameter
+fn split_line(line: &str) -> Option<(String, &str)> {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => {
+                let head = &line[..i];
+                let name = head.split(';').next().unwrap_or(head);
+                return Some((name.trim().to_ascii_uppercase(), &line[i + 1..]));
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Value of a top-level property of a component (nested VALARMs are skipped)
+fn property<'a>(lines: &'a [String], name: &str) -> Option<&'a str> {
+    let mut depth = 0usize;
+    for line in lines {
+        let Some((prop, value)) = split_line(line) else {
+            continue;
+        };
+        match prop.as_str() {
+            "BEGIN" => depth += 1,
+            "END" => depth = depth.saturating_sub(1),
+            _ if depth == 1 && prop == name => return Some(value.trim()),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Events and time zones of an iCalendar text
+pub fn parse_calendar(text: &str) -> Calendar {
+    let lines = unfold(text);
+    let mut calendar = Calendar::default();
+    let method = lines
+        .iter()
+        .filter_map(|l| split_line(l))
+        .find(|(name, _)| name == "METHOD")
+        .map(|(_, value)| value.trim().to_ascii_uppercase());
+    if method
+        .as_deref()
+        .is_some_and(|m| RESPONSE_METHODS.contains(&m))
+    {
+        return calendar;
+    }
+
+    let mut current: Option<(String, Vec<String>)> = None;
+    let mut depth = 0usize;
+    for line in lines {
+        let (prop, value) = split_line(&line).unwrap_or_default();
+        let value = value.trim().to_ascii_uppercase();
+        if current.is_none() {
+            if prop != "BEGIN" || !(value == "VEVENT" || value == "VTIMEZONE") {
+                continue;
+            }
+            current = Some((value, Vec::new()));
+        }
+        match prop.as_str() {
+            "BEGIN" => depth += 1,
+            "END" => depth = depth.saturating_sub(1),
+            _ => {},
+        }
+        if let Some((_, body)) = current.as_mut() {
+            body.push(line);
+        }
+        if depth == 0 {
+            if let Some((kind, body)) = current.take() {
+                finish_component(&mut calendar, &kind, body, method.as_deref());
+            }
+        }
+    }
+    calendar
+}
+
+fn finish_component(
+    calendar: &mut Calendar,
+    kind: &str,
+    mut lines: Vec<String>,
+    method: Option<&str>,
+) {
+    if kind == "VTIMEZONE" {
+        if let Some(tzid) = property(&lines, "TZID") {
+            calendar.timezones.push((tzid.to_string(), lines));
+        }
+        return;
+    }
+    if method == Some("CANCEL") && property(&lines, "STATUS").is_none() {
+        let end = lines.len() - 1;
+        lines.insert(end, "STATUS:CANCELLED".to_string());
+    }
+    let start = property(&lines, "DTSTART").unwrap_or_default().to_string();
+    let key = property(&lines, "UID").map_or_else(
+        || format!("{start}|{}", property(&lines, "SUMMARY").unwrap_or_default()),
+        |uid| format!("{uid}|{}", property(&lines, "RECURRENCE-ID").unwrap_or_default()),
+    );
+    calendar.events.push(Event {
+        key,
+        sequence: property(&lines, "SEQUENCE")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0),
+        stamp: property(&lines, "DTSTAMP").unwrap_or_default().to_string(),
+        start,
+        lines,
+    });
+}
+
+/// Calendar objects carried by a message: `text/calendar` and
+/// `application/ics` parts and `.ics` attachments
+pub fn message_calendars(raw: &[u8]) -> Vec<Calendar> {
+    Message::parse(raw)
+        .parts()
+        .iter()
+        .filter(|p| {
+            matches!(p.mime_type(), "text/calendar" | "application/ics")
+                || p.filename()
+                    .is_some_and(|f| f.to_ascii_lowercase().ends_with(".ics"))
+        })
+        .map(|p| parse_calendar(&p.text()))
+        .filter(|c| !c.events.is_empty())
+        .collect()
+}
+
+/// Keep the newest copy of every event (input in archive order) and every
+/// time zone definition first seen; events are sorted by start
+fn merge(calendars: Vec<Calendar>) -> (Vec<Event>, Vec<Vec<String>>) {
+    let mut events: HashMap<String, Event> = HashMap::new();
+    let mut timezones: Vec<(String, Vec<String>)> = Vec::new();
+    for calendar in calendars {
+        for event in calendar.events {
+            let newer = events.get(&event.key).map_or(true, |kept| {
+                (event.sequence, &event.stamp) >= (kept.sequence, &kept.stamp)
+            });
+            if newer {
+                events.insert(event.key.clone(), event);
+            }
+        }
+        for (tzid, lines) in calendar.timezones {
+            if !timezones.iter().any(|(seen, _)| *seen == tzid) {
+                timezones.push((tzid, lines));
+            }
+        }
+    }
+    let mut events: Vec<Event> = events.into_values().collect();
+    events.sort_by(|a, b| (&a.start, &a.key).cmp(&(&b.start, &b.key)));
+    (events, timezones.into_iter().map(|(_, lines)| lines).collect())
+}
+
+fn render(events: &[Event], timezones: &[Vec<String>]) -> String {
+    let mut out = String::from(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//mail_parser_rust//export_calendar//EN\r\n\
+         CALSCALE:GREGORIAN\r\n",
+    );
+    for line in timezones
+        .iter()
+        .flatten()
+        .chain(events.iter().flat_map(|e| &e.lines))
+    {
+        content_line(&mut out, line);
+    }
+    out.push_str("END:VCALENDAR\r\n");
+    out
+}
+
+/// Merge every calendar event found in an mbox into one `.ics` file
+///
+/// Invitations, updates and cancellations (`text/calendar` parts and `.ics`
+/// attachments) are collected and deduplicated by UID and RECURRENCE-ID,
+/// keeping the copy with the highest SEQUENCE (then the latest DTSTAMP, then
+/// the latest in the archive). Cancelled meetings are kept with
+/// `STATUS:CANCELLED`; attendee replies are ignored. Time zone definitions
+/// are included once per TZID.
+///
+/// # Arguments
+/// * `src` - Path to the mbox file
+/// * `out_path` - Output `.ics` file (`.gz` / `.zst` are compressed)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport`; `message_count` is the number of messages that carried
+///   calendar events
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import export_calendar
+/// report = export_calendar("inbox.mbox", "meetings.ics")
+/// print(f"events from {report.message_count} invitations")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (src, out_path, dry_run=false, fsync="file", from_line_mode="standard", progress=None))]
+pub fn export_calendar(
+    py: Python<'_>,
+    src: &str,
+    out_path: &str,
+    dry_run: bool,
+    fsync: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?;
+        let mmap = map_file(src)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let found: Vec<Vec<Calendar>> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| message_calendars(&data[span]))
+            .collect();
+        progress.check("export_calendar")?;
+        mmap.verify(&mode)?;
+        for _ in found.iter().filter(|calendars| !calendars.is_empty()) {
+            output.note_message();
+        }
+        let (events, timezones) = merge(found.into_iter().flatten().collect());
+
+        let io_error = |e: std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {out_path}: {e}"))
+        };
+        let mut file = output.create(Path::new(out_path)).map_err(io_error)?;
+        file.write_all(render(&events, &timezones).as_bytes())
+            .map_err(io_error)?;
+        file.finish().map_err(io_error)?;
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(method: &str, sequence: u32, summary: &str) -> String {
+        format!(
+            "From organizer Mon Jan  1 00:00:00 2024\n\
+             From: org@example.com\nSubject: {summary}\n\
+             Content-Type: multipart/alternative; boundary=B\n\n\
+             --B\nContent-Type: text/plain\n\nSee invite.\n\
+             --B\nContent-Type: text/calendar; method={method}\n\n\
+             BEGIN:VCALENDAR\nMETHOD:{method}\nBEGIN:VTIMEZONE\nTZID:Europe/Berlin\n\
+             END:VTIMEZONE\nBEGIN:VEVENT\nUID:m1@example.com\nSEQUENCE:{sequence}\n\
+             DTSTAMP:2024010{sequence}T000000Z\nDTSTART;TZID=Europe/Berlin:20240110T100000\n\
+             SUMMARY:{summary}\nATTENDEE;CN=\"Doe: Jane\":mailto:jane@example.com\n\
+             BEGIN:VALARM\nACTION:DISPLAY\nSUMMARY:alarm\nEND:VALARM\nEND:VEVENT\n\
+             END:VCALENDAR\n--B--\n\n"
+        )
+    }
+
+    #[test]
+    fn test_parse_calendar() {
+        let text = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:a\r\nSUMMARY:Long\r\n  title\r\n\
+                    DTSTART:20240101\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let calendar = parse_calendar(text);
+        assert_eq!(calendar.events.len(), 1);
+        assert_eq!(calendar.events[0].key, "a|");
+        assert_eq!(property(&calendar.events[0].lines, "SUMMARY"), Some("Long title"));
+        assert_eq!(
+            split_line("ATTENDEE;CN=\"a:b\":mailto:x@y"),
+            Some(("ATTENDEE".to_string(), "mailto:x@y"))
+        );
+        let reply = text.replace("BEGIN:VEVENT", "METHOD:REPLY\r\nBEGIN:VEVENT");
+        assert!(parse_calendar(&reply).events.is_empty());
+    }
+
+    #[test]
+    fn test_export_calendar() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_calendar_{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            let mbox = [
+                invite("REQUEST", 1, "Planning"),
+                invite("CANCEL", 3, "Planning"),
+                invite("REQUEST", 2, "Planning (moved)"),
+                "From x Mon Jan  1 00:00:00 2024\nSubject: plain\n\nNo calendar here.\n\n"
+                    .to_string(),
+            ]
+            .concat();
+            std::fs::write(&src, mbox).unwrap();
+            let out = dir.join("meetings.ics");
+
+            let report = export_calendar(
+                py,
+                src.to_str().unwrap(),
+                out.to_str().unwrap(),
+                false,
+                "none",
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert_eq!(report.message_count, 3);
+            let ics = std::fs::read_to_string(&out).unwrap();
+            assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+            assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+            assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+            assert_eq!(ics.matches("BEGIN:VTIMEZONE").count(), 1);
+            assert!(ics.contains("SEQUENCE:3\r\n"));
+            assert!(ics.contains("STATUS:CANCELLED\r\nEND:VEVENT"));
+            assert!(ics.contains("BEGIN:VALARM\r\nACTION:DISPLAY"));
+            assert!(!ics.contains("METHOD"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,452 @@
+//! Resumable-operation checkpoints.
+//!
+//! Long-running export and conversion passes (`mbox_to_jsonl`, `repair_mbox`)
+//! periodically record the byte offset of the first message they have *not*
+//! finished yet, together with the length of whatever output they produced up
+//! to that point. After a crash or interrupt the operation reloads the
+//! checkpoint, truncates its output back to the recorded length and continues
+//! scanning from the recorded offset.
+//!
+//! A checkpoint is only honoured when it was written by the same operation for
+//! the same source file: the source must be at least as long as when the
+//! checkpoint was saved, and both its first bytes and the bytes just before
+//! `offset` must hash the same. An mbox that merely grew (appends) resumes,
+//! while a replaced file, or one rewritten in place (e.g. expunged), starts
+//! over.
+
+use crate::output::{Output, OutputFile};
+use crate::paths::extended;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Source bytes covered by each fingerprint: the first ones and the ones
+/// just before the offset.
+const FINGERPRINT_LEN: u64 = 64 * 1024;
+
+/// Default minimum interval between two checkpoint writes.
+const DEFAULT_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Persisted progress of a resumable operation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[pyclass]
+pub struct Checkpoint {
+    /// Name of the operation that wrote the checkpoint (e.g. `mbox_to_jsonl`)
+    #[pyo3(get)]
+    pub operation: String,
+    /// Source file the offsets refer to
+    #[pyo3(get)]
+    pub source: String,
+    /// Byte offset of the first message that still has to be processed
+    #[pyo3(get)]
+    pub offset: u64,
+    /// Number of messages completed so far
+    #[pyo3(get)]
+    pub items_done: u64,
+    /// Length of the operation's output when the checkpoint was taken
+    #[pyo3(get)]
+    pub output_len: u64,
+    /// FNV-1a hash of the first `min(offset, 64 KiB)` source bytes
+    fingerprint: u64,
+    /// FNV-1a hash of the (up to 64 KiB) source bytes ending at `offset`
+    #[serde(default)]
+    tail_fingerprint: u64,
+    /// Source size when the checkpoint was taken
+    #[serde(default)]
+    source_len: u64,
+}
+
+impl Checkpoint {
+    /// Fresh checkpoint positioned at the start of `source`
+    pub fn new(operation: &str, source: &Path) -> Self {
+        Self {
+            operation: operation.to_string(),
+            source: source.to_string_lossy().into_owned(),
+            offset: 0,
+            items_done: 0,
+            output_len: 0,
+            fingerprint: fnv1a(&[]),
+            tail_fingerprint: fnv1a(&[]),
+            source_len: 0,
+        }
+    }
+
+    /// Read a checkpoint file, returning `None` when it does not exist
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let data = match fs::read(extended(path)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Load the checkpoint at `path` if it belongs to `operation` over `source`
+    /// and still matches the source contents, otherwise start from scratch.
+    pub fn resume(path: &Path, operation: &str, source: &Path) -> io::Result<Self> {
+        if let Some(saved) = Self::load(path)? {
+            if saved.operation == operation
+                && Path::new(&saved.source) == source
+                && saved.matches_source(source)?
+            {
+                return Ok(saved);
+            }
+        }
+        Ok(Self::new(operation, source))
+    }
+
+    /// Whether the source still holds the bytes this checkpoint saw
+    fn matches_source(&self, source: &Path) -> io::Result<bool> {
+        let len = fs::metadata(extended(source))?.len();
+        if len < self.source_len.max(self.offset) {
+            return Ok(false);
+        }
+        Ok(source_fingerprints(source, self.offset)? == (self.fingerprint, self.tail_fingerprint))
+    }
+
+    /// Record progress up to `offset` of `source` (the whole source; only the
+    /// first 64 KiB and the 64 KiB before `offset` are hashed)
+    pub fn advance(&mut self, source: &[u8], offset: u64, items_done: u64, output_len: u64) {
+        let end = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(source.len());
+        let (head, tail) = fingerprint_windows(end as u64);
+        let window = |range: Range<u64>| {
+            let range =
+                usize::try_from(range.start).unwrap_or(0)..usize::try_from(range.end).unwrap_or(0);
+            fnv1a(&source[range])
+        };
+        self.fingerprint = window(head);
+        self.tail_fingerprint = window(tail);
+        self.source_len = source.len() as u64;
+        self.offset = offset;
+        self.items_done = items_done;
+        self.output_len = output_len;
+    }
+
+    /// Atomically persist the checkpoint (write to a sibling temp file, then rename)
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let path = extended(path);
+        let tmp = tmp_path(&path);
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&json)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &path)
+    }
+
+    /// Remove a checkpoint file once the operation completed successfully
+    pub fn clear(path: &Path) -> io::Result<()> {
+        match fs::remove_file(extended(path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Throttled checkpoint writer used inside scan loops
+///
+/// `record` is cheap to call after every batch; the output is flushed and the
+/// file rewritten only when the save interval elapsed. `finish` removes the
+/// checkpoint.
+pub struct Checkpointer {
+    path: Option<PathBuf>,
+    state: Checkpoint,
+    interval: Duration,
+    last_saved: Instant,
+    /// Progress recorded since the last save: offset, items done
+    pending: (u64, u64),
+}
+
+impl Checkpointer {
+    /// Open (or start) the checkpoint for `operation`; `path == None` disables
+    /// checkpointing and makes every call a no-op.
+    pub fn open(path: Option<&Path>, operation: &str, source: &Path) -> io::Result<Self> {
+        let state = match path {
+            Some(p) => Checkpoint::resume(p, operation, source)?,
+            None => Checkpoint::new(operation, source),
+        };
+        Ok(Self {
+            path: path.map(Path::to_path_buf),
+            pending: (state.offset, state.items_done),
+            state,
+            interval: DEFAULT_SAVE_INTERVAL,
+            last_saved: Instant::now(),
+        })
+    }
+
+    /// Override the minimum time between checkpoint writes
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// State to resume from (offset 0 when starting fresh)
+    pub const fn state(&self) -> &Checkpoint {
+        &self.state
+    }
+
+    /// Open the operation's output at `path`, continuing the partial output
+    /// of an interrupted run when checkpointing is enabled
+    pub fn create(&self, output: &Output, path: &Path) -> io::Result<OutputFile> {
+        if self.path.is_some() {
+            output.resume(path, self.state.output_len)
+        } else {
+            output.create(path)
+        }
+    }
+
+    /// Number of leading `spans` the interrupted run already wrote to `file`
+    ///
+    /// When the checkpoint offset is not a message boundary or the partial
+    /// output did not survive, `file` is emptied and the scan starts over.
+    pub fn resume_point(
+        &mut self,
+        spans: &[Range<usize>],
+        file: &mut OutputFile,
+    ) -> io::Result<usize> {
+        let offset = usize::try_from(self.state.offset).unwrap_or(usize::MAX);
+        let done = match spans.binary_search_by_key(&offset, |span| span.start) {
+            Ok(n) => Some(n),
+            Err(n) if n == spans.len() && spans.last().map_or(0, |span| span.end) == offset => {
+                Some(n)
+            },
+            Err(_) => None,
+        };
+        match done {
+            Some(n) if file.len() == self.state.output_len => Ok(n),
+            _ => {
+                file.restart()?;
+                self.state = Checkpoint::new(&self.state.operation, Path::new(&self.state.source));
+                self.pending = (0, 0);
+                Ok(0)
+            },
+        }
+    }
+
+    /// Note that everything in `source` before `offset` is done and in
+    /// `output`, saving if due
+    pub fn record(
+        &mut self,
+        source: &[u8],
+        offset: usize,
+        items_done: usize,
+        output: &mut OutputFile,
+    ) -> io::Result<()> {
+        self.pending = (
+            u64::try_from(offset).unwrap_or(u64::MAX),
+            u64::try_from(items_done).unwrap_or(u64::MAX),
+        );
+        if self.last_saved.elapsed() >= self.interval {
+            self.save(source, output)?;
+        }
+        Ok(())
+    }
+
+    /// Save the recorded progress now, e.g. when the scan is cancelled
+    pub fn save(&mut self, source: &[u8], output: &mut OutputFile) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // The checkpoint must never claim output that is still buffered
+        output.flush()?;
+        let (offset, items_done) = self.pending;
+        self.state.advance(source, offset, items_done, output.len());
+        self.state.save(path)?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Operation completed: drop the checkpoint file
+    pub fn finish(self) -> io::Result<()> {
+        self.path.as_deref().map_or(Ok(()), Checkpoint::clear)
+    }
+}
+
+/// `IOError` for a checkpoint that cannot be read or written
+pub fn error(e: &io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Checkpoint failed: {e}"))
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Byte ranges hashed for a checkpoint at `offset`: the start of the source
+/// and the bytes just before `offset`
+fn fingerprint_windows(offset: u64) -> (Range<u64>, Range<u64>) {
+    (0..offset.min(FINGERPRINT_LEN), offset.saturating_sub(FINGERPRINT_LEN)..offset)
+}
+
+fn source_fingerprints(source: &Path, offset: u64) -> io::Result<(u64, u64)> {
+    let mut file = File::open(extended(source))?;
+    let mut hash = |range: Range<u64>| -> io::Result<u64> {
+        let mut buf = Vec::new();
+        file.seek(SeekFrom::Start(range.start))?;
+        (&mut file)
+            .take(range.end - range.start)
+            .read_to_end(&mut buf)?;
+        Ok(fnv1a(&buf))
+    };
+    let (head, tail) = fingerprint_windows(offset);
+    Ok((hash(head)?, hash(tail)?))
+}
+
+/// 64-bit FNV-1a; stable across Rust releases unlike `DefaultHasher`
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Inspect the checkpoint left behind by an interrupted operation
+///
+/// # Arguments
+/// * `path` - Checkpoint file passed as `checkpoint=` to a resumable function
+///
+/// # Returns
+/// * `Checkpoint` with `operation`, `source`, `offset`, `items_done` and
+///   `output_len`, or `None` when no checkpoint exists
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import load_checkpoint
+/// cp = load_checkpoint("export.ckpt")
+/// if cp is not None:
+///     print(f"{cp.operation} will resume at byte {cp.offset}")
+/// ```
+#[pyfunction]
+pub fn load_checkpoint(path: &str) -> PyResult<Option<Checkpoint>> {
+    Checkpoint::load(Path::new(path)).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read checkpoint: {e}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mpr_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_checkpoint_roundtrip_and_resume() {
+        let dir = scratch_dir("ckpt_resume");
+        let source = dir.join("in.mbox");
+        let data =
+            b"From a@b Mon Jan 1 00:00:00 2024\n\nbody\n\nFrom c@d Mon Jan 1 00:00:00 2024\n";
+        fs::write(&source, data).unwrap();
+        let ckpt = dir.join("run.ckpt");
+
+        let mut cp = Checkpoint::new("export", &source);
+        cp.advance(data, 40, 1, 123);
+        cp.save(&ckpt).unwrap();
+
+        let resumed = Checkpoint::resume(&ckpt, "export", &source).unwrap();
+        assert_eq!(resumed, cp);
+
+        // A different operation must not pick up the checkpoint
+        let other = Checkpoint::resume(&ckpt, "index", &source).unwrap();
+        assert_eq!(other.offset, 0);
+
+        // Appending to the source keeps the checkpoint valid
+        let mut grown = data.to_vec();
+        grown.extend_from_slice(b"\nmore\n");
+        fs::write(&source, &grown).unwrap();
+        assert_eq!(Checkpoint::resume(&ckpt, "export", &source).unwrap().offset, 40);
+
+        // Rewriting the prefix invalidates it
+        fs::write(&source, b"From x@y Tue Feb 2 00:00:00 2021\n\nother body text here\n").unwrap();
+        assert_eq!(Checkpoint::resume(&ckpt, "export", &source).unwrap().offset, 0);
+
+        // Past the first 64 KiB: an in-place rewrite before the offset, or a
+        // source that shrank, invalidates it too
+        let big = vec![b'x'; 200_000];
+        fs::write(&source, &big).unwrap();
+        let mut cp = Checkpoint::new("export", &source);
+        cp.advance(&big, 150_000, 1, 0);
+        cp.save(&ckpt).unwrap();
+        assert_eq!(Checkpoint::resume(&ckpt, "export", &source).unwrap().offset, 150_000);
+        let mut edited = big.clone();
+        edited[100_000] = b'y';
+        fs::write(&source, &edited).unwrap();
+        assert_eq!(Checkpoint::resume(&ckpt, "export", &source).unwrap().offset, 0);
+        fs::write(&source, &big[..180_000]).unwrap();
+        assert_eq!(Checkpoint::resume(&ckpt, "export", &source).unwrap().offset, 0);
+
+        Checkpoint::clear(&ckpt).unwrap();
+        assert!(Checkpoint::load(&ckpt).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpointer() {
+        let dir = scratch_dir("ckpt_writer");
+        let source = dir.join("in.mbox");
+        let data = b"From a@b\n\nx\nFrom c@d\n\ny\n";
+        fs::write(&source, data).unwrap();
+        let spans = [0..13, 13..26];
+        let output = Output::new(false);
+        let out = dir.join("out.txt");
+
+        // Disabled: nothing is saved and the output starts fresh
+        let mut cp = Checkpointer::open(None, "export", &source).unwrap();
+        let mut file = cp.create(&output, &out).unwrap();
+        assert_eq!(cp.resume_point(&spans, &mut file).unwrap(), 0);
+        cp.record(data, 13, 1, &mut file).unwrap();
+        assert_eq!(cp.state().offset, 0);
+        drop(file);
+        cp.finish().unwrap();
+
+        let ckpt = dir.join("run.ckpt");
+        let mut cp = Checkpointer::open(Some(&ckpt), "export", &source)
+            .unwrap()
+            .with_interval(Duration::ZERO);
+        let mut file = cp.create(&output, &out).unwrap();
+        file.write_all(b"one\n").unwrap();
+        cp.record(data, 13, 1, &mut file).unwrap();
+        file.write_all(b"tw").unwrap();
+        drop(file);
+
+        // The interrupted output is cut back to the first message
+        let mut cp = Checkpointer::open(Some(&ckpt), "export", &source).unwrap();
+        assert_eq!((cp.state().offset, cp.state().output_len), (13, 4));
+        let mut file = cp.create(&output, &out).unwrap();
+        assert_eq!(cp.resume_point(&spans, &mut file).unwrap(), 1);
+        file.write_all(b"two\n").unwrap();
+        file.finish().unwrap();
+        cp.finish().unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"one\ntwo\n");
+        assert!(Checkpoint::load(&ckpt).unwrap().is_none());
+
+        // An offset inside a message restarts the scan and the output
+        let mut cp = Checkpointer::open(Some(&ckpt), "export", &source)
+            .unwrap()
+            .with_interval(Duration::ZERO);
+        let mut file = cp.create(&output, &out).unwrap();
+        file.write_all(b"one\n").unwrap();
+        cp.record(data, 5, 1, &mut file).unwrap();
+        drop(file);
+        let mut cp = Checkpointer::open(Some(&ckpt), "export", &source).unwrap();
+        let mut file = cp.create(&output, &out).unwrap();
+        assert_eq!(file.len(), 4);
+        assert_eq!(cp.resume_point(&spans, &mut file).unwrap(), 0);
+        assert_eq!((file.len(), cp.state().offset), (0, 0));
+        drop(file);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
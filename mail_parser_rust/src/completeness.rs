@@ -0,0 +1,446 @@
+//! Archive completeness checks against an external manifest.
+//!
+//! E-discovery productions ship with a list of the messages they are supposed
+//! to contain. The manifest may name messages by Message-ID or by the stable
+//! UID from `compute_message_uid`; every archive message is keyed both ways,
+//! so a manifest can mix the two. CSV manifests need an ID column (found by
+//! name, or the only column); JSON manifests are an array of IDs or of
+//! objects, and JSON Lines manifests (such as the one `quarantine_mbox`
+//! writes) hold one ID or object per line.
+
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::paths::extended;
+use crate::progress::Progress;
+use crate::thread::parse_ids;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Column or key names recognized as the ID field, compared without case,
+/// `-`, `_` or spaces
+const ID_FIELDS: &[&str] = &["messageid", "uid", "id"];
+
+/// Message-ID or UID as compared: trimmed, without angle brackets, lowercase
+fn normalize_id(value: &str) -> String {
+    let trimmed = value.trim();
+    parse_ids(trimmed)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| trimmed.to_string())
+        .to_lowercase()
+}
+
+fn field_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether a header `name` is the ID column
+fn is_id_field(name: &str, id_field: Option<&str>) -> bool {
+    let key = field_key(name);
+    id_field.map_or_else(|| ID_FIELDS.contains(&key.as_str()), |f| field_key(f) == key)
+}
+
+/// Whether a bare value looks like a Message-ID or UID rather than a header
+fn looks_like_id(value: &str) -> bool {
+    let value = value.trim();
+    value.contains('@') || (value.len() == 32 && value.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+fn manifest_error(detail: &str) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid manifest: {detail}"))
+}
+
+/// Split CSV text into rows of fields (RFC 4180 quoting)
+fn csv_rows(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {},
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    rows
+}
+
+fn csv_ids(text: &str, id_field: Option<&str>) -> PyResult<Vec<String>> {
+    let rows = csv_rows(text);
+    let Some(header) = rows.first() else {
+        return Ok(Vec::new());
+    };
+    let (column, skip) = if let Some(column) = header.iter().position(|h| is_id_field(h, id_field))
+    {
+        (column, 1)
+    } else if id_field.is_none() && header.len() == 1 && looks_like_id(&header[0]) {
+        (0, 0)
+    } else {
+        return Err(manifest_error(&format!(
+            "no {} column in CSV header",
+            id_field.unwrap_or("message_id or uid")
+        )));
+    };
+    Ok(rows
+        .iter()
+        .skip(skip)
+        .filter_map(|row| row.get(column))
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| normalize_id(v))
+        .collect())
+}
+
+/// The ID in one JSON manifest entry
+fn json_id(entry: &Value, id_field: Option<&str>) -> Option<String> {
+    match entry {
+        Value::String(id) => Some(normalize_id(id)),
+        Value::Object(fields) => fields
+            .iter()
+            .filter(|(name, _)| is_id_field(name, id_field))
+            // An explicit Message-ID wins over a UID in the same record
+            .min_by_key(|(name, _)| field_key(name) != "messageid")
+            .and_then(|(_, v)| v.as_str())
+            .map(normalize_id),
+        _ => None,
+    }
+}
+
+fn json_ids(entries: &[Value], id_field: Option<&str>) -> PyResult<Vec<String>> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            json_id(entry, id_field)
+                .ok_or_else(|| manifest_error(&format!("entry {} has no message ID", i + 1)))
+        })
+        .collect()
+}
+
+/// Expected IDs from manifest text, by format
+fn manifest_ids(text: &str, format: &str, id_field: Option<&str>) -> PyResult<Vec<String>> {
+    match format {
+        "json" => {
+            let value: Value =
+                serde_json::from_str(text).map_err(|e| manifest_error(&e.to_string()))?;
+            let entries = match &value {
+                Value::Array(entries) => entries,
+                Value::Object(fields) => fields
+                    .get("messages")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| manifest_error("expected an array or a \"messages\" array"))?,
+                _ => return Err(manifest_error("expected an array of IDs or records")),
+            };
+            json_ids(entries, id_field)
+        },
+        "jsonl" => {
+            let entries = text
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<Value>, _>>()
+                .map_err(|e| manifest_error(&e.to_string()))?;
+            json_ids(&entries, id_field)
+        },
+        "csv" => csv_ids(text, id_field),
+        other => Err(manifest_error(&format!(
+            "unknown format {other:?} (expected \"csv\", \"json\" or \"jsonl\")"
+        ))),
+    }
+}
+
+/// Format from the file extension, falling back to the first character
+fn detect_format(path: &str, text: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("json") => "json",
+        Some("jsonl" | "ndjson") => "jsonl",
+        Some("csv" | "txt") => "csv",
+        _ => match text.trim_start().chars().next() {
+            Some('[') => "json",
+            Some('{') => "jsonl",
+            _ => "csv",
+        },
+    }
+}
+
+/// An archive message the manifest does not list
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[pyclass]
+pub struct UnlistedMessage {
+    /// Position in the archive (0-based)
+    #[pyo3(get)]
+    pub index: usize,
+    /// Byte offset in the archive
+    #[pyo3(get)]
+    pub offset: usize,
+    /// Message-ID without angle brackets, if any
+    #[pyo3(get)]
+    pub message_id: Option<String>,
+    /// Stable message UID (see `compute_message_uid`)
+    #[pyo3(get)]
+    pub uid: String,
+}
+
+#[pymethods]
+impl UnlistedMessage {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Result of comparing an archive with its manifest
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ManifestComparison {
+    /// Distinct IDs in the manifest
+    #[pyo3(get)]
+    pub expected: usize,
+    /// Messages in the archive
+    #[pyo3(get)]
+    pub messages: usize,
+    /// Manifest IDs found in the archive
+    #[pyo3(get)]
+    pub matched: usize,
+    /// Manifest IDs not found in the archive, in manifest order
+    #[pyo3(get)]
+    pub missing: Vec<String>,
+    /// Archive messages not in the manifest
+    #[pyo3(get)]
+    pub extra: Vec<UnlistedMessage>,
+    /// Manifest IDs listed more than once
+    #[pyo3(get)]
+    pub manifest_duplicates: Vec<String>,
+    /// Archive messages whose ID an earlier message already matched
+    #[pyo3(get)]
+    pub archive_duplicates: usize,
+    /// True when nothing is missing and nothing is extra
+    #[pyo3(get)]
+    pub complete: bool,
+}
+
+#[pymethods]
+impl ManifestComparison {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Compare expected IDs with (offset, Message-ID, UID) per archive message
+fn compare(
+    expected_ids: Vec<String>,
+    messages: Vec<(usize, Option<String>, String)>,
+) -> ManifestComparison {
+    let mut expected = HashSet::new();
+    let mut listed = Vec::new();
+    let mut manifest_duplicates = Vec::new();
+    for id in expected_ids {
+        if expected.insert(id.clone()) {
+            listed.push(id);
+        } else if !manifest_duplicates.contains(&id) {
+            manifest_duplicates.push(id);
+        }
+    }
+    let mut report = ManifestComparison {
+        expected: listed.len(),
+        messages: messages.len(),
+        manifest_duplicates,
+        ..ManifestComparison::default()
+    };
+    let mut found: HashSet<String> = HashSet::new();
+    for (index, (offset, message_id, uid)) in messages.into_iter().enumerate() {
+        let keys = message_id.iter().chain(std::iter::once(&uid));
+        let hits: Vec<&String> = keys.filter(|k| expected.contains(*k)).collect();
+        if hits.is_empty() {
+            report.extra.push(UnlistedMessage {
+                index,
+                offset,
+                message_id,
+                uid,
+            });
+        } else if hits.iter().all(|k| found.contains(*k)) {
+            report.archive_duplicates += 1;
+        } else {
+            found.extend(hits.into_iter().cloned());
+        }
+    }
+    report.missing = listed
+        .into_iter()
+        .filter(|id| !found.contains(id))
+        .collect();
+    report.matched = report.expected - report.missing.len();
+    report.complete = report.missing.is_empty() && report.extra.is_empty();
+    report
+}
+
+/// Check an mbox against a manifest of the messages it should contain
+///
+/// Manifest entries may be Message-IDs (with or without angle brackets) or
+/// UIDs from `compute_message_uid`; matching ignores case. A message matches
+/// when either its Message-ID or its UID is listed.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `manifest` - Path to a CSV, JSON or JSON Lines manifest
+/// * `id_field` - CSV column or JSON key holding the ID (default:
+///   `message_id`, `Message-ID`, `uid` or `id`; a headerless single-column
+///   CSV also works)
+/// * `format` - "csv", "json" or "jsonl" (default: from the extension)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `ManifestComparison` with `missing` IDs, `extra` messages (index,
+///   offset, Message-ID, UID), duplicate counts and `complete`
+///
+/// # Raises
+/// * `IOError` if a file cannot be read
+/// * `ValueError` for a manifest without a recognizable ID field
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import verify_against_manifest
+/// check = verify_against_manifest("production.mbox", "production_manifest.csv")
+/// if not check.complete:
+///     print(f"{len(check.missing)} missing, {len(check.extra)} not listed")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, manifest, id_field=None, format=None, from_line_mode="standard", progress=None))]
+pub fn verify_against_manifest(
+    py: Python<'_>,
+    mbox_path: &str,
+    manifest: &str,
+    id_field: Option<&str>,
+    format: Option<&str>,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<ManifestComparison> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let text = std::fs::read_to_string(extended(Path::new(manifest))).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {manifest}: {e}"))
+        })?;
+        let text = text.trim_start_matches('\u{feff}');
+        let format =
+            format.map_or_else(|| detect_format(manifest, text).to_string(), str::to_lowercase);
+        let expected = manifest_ids(text, &format, id_field)?;
+
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let messages = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| {
+                let raw = &data[span.clone()];
+                let message_id = Message::parse(raw)
+                    .header("Message-ID")
+                    .and_then(|v| parse_ids(v).into_iter().next())
+                    .map(|id| id.to_lowercase());
+                (span.start, message_id, message_uid(raw))
+            })
+            .collect();
+        progress.check("verify_against_manifest")?;
+        mmap.verify(&mode)?;
+        Ok(compare(expected, messages))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_formats() {
+        let csv = "Custodian,Message-ID\r\nann,\"<A@x>\"\nbob,b@x\n\n";
+        assert_eq!(manifest_ids(csv, "csv", None).unwrap(), ["a@x", "b@x"]);
+        assert_eq!(manifest_ids("a@x\nb@x\n", "csv", None).unwrap(), ["a@x", "b@x"]);
+        assert!(manifest_ids("name,size\nx,1\n", "csv", None).is_err());
+        let json = r#"{"messages": [{"uid": "00FF", "message_id": "<c@x>"}, "d@x"]}"#;
+        assert_eq!(manifest_ids(json, "json", None).unwrap(), ["c@x", "d@x"]);
+        let jsonl = "{\"uid\": \"ABC\", \"reasons\": []}\n{\"uid\": \"def\"}\n";
+        assert_eq!(manifest_ids(jsonl, "jsonl", None).unwrap(), ["abc", "def"]);
+        assert_eq!(manifest_ids("doc,key\n1,e@x\n", "csv", Some("key")).unwrap(), ["e@x"]);
+        assert_eq!(detect_format("m.ndjson", ""), "jsonl");
+        assert_eq!(detect_format("manifest", "[\"a@x\"]"), "json");
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = ["a@x", "b@x", "uid2", "a@x", "gone@x"]
+            .map(String::from)
+            .to_vec();
+        let messages = vec![
+            (0, Some("a@x".to_string()), "uid0".to_string()),
+            (10, None, "uid2".to_string()),
+            (20, Some("a@x".to_string()), "uid0".to_string()),
+            (30, Some("new@x".to_string()), "uid3".to_string()),
+        ];
+        let report = compare(expected, messages);
+        assert_eq!((report.expected, report.messages, report.matched), (4, 4, 2));
+        assert_eq!(report.missing, ["b@x", "gone@x"]);
+        assert_eq!(report.extra.len(), 1);
+        assert_eq!((report.extra[0].index, report.extra[0].offset), (3, 30));
+        assert_eq!(report.manifest_duplicates, ["a@x"]);
+        assert_eq!(report.archive_duplicates, 1);
+        assert!(!report.complete);
+    }
+
+    #[test]
+    fn test_verify_against_manifest() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_manifest_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let mbox = dir.join("in.mbox");
+            std::fs::write(
+                &mbox,
+                "From a Mon Jan  1 00:00:00 2024\nMessage-ID: <One@x>\n\nx\n\n\
+             From b Mon Jan  1 00:00:00 2024\nMessage-ID: <two@x>\n\ny\n",
+            )
+            .unwrap();
+            let manifest = dir.join("expected.csv");
+            std::fs::write(&manifest, "message_id\none@x\ntwo@x\n").unwrap();
+            let report = verify_against_manifest(
+                py,
+                mbox.to_str().unwrap(),
+                manifest.to_str().unwrap(),
+                None,
+                None,
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert!(report.complete, "{report:?}");
+            assert_eq!(report.matched, 2);
+            std::fs::remove_dir_all(dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,293 @@
+//! Transparent reading of gzip, zstd and xz compressed mboxes.
+//!
+//! The codec is recognized by its magic bytes, not the file name, and the
+//! archive is decompressed as a stream: messages are split off line by line
+//! as the data arrives, so memory stays bounded by the largest message and
+//! nothing is written to a temporary file. Offsets and spans are positions
+//! in the decompressed data.
+//!
+//! Separators are found with the same per-line rules as the memory-mapped
+//! scan. `Recover` mode needs lookahead over the whole archive and is not
+//! available for compressed input.
+
+use crate::mbox::{FromLineMode, MappedFile};
+use pyo3::prelude::*;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Decompressed bytes read from the decoder at a time
+const STREAM_BUFFER: usize = 1 << 20;
+
+/// Compression format of an archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Codec {
+    /// The format `data` starts with, None for an uncompressed archive
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        [
+            (Self::Gzip, &b"\x1f\x8b"[..]),
+            (Self::Zstd, &b"\x28\xb5\x2f\xfd"[..]),
+            (Self::Xz, &b"\xfd7zXZ\x00"[..]),
+        ]
+        .into_iter()
+        .find_map(|(codec, magic)| data.starts_with(magic).then_some(codec))
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Xz => "xz",
+        }
+    }
+
+    /// A decompressing reader over `input`; concatenated members (`cat
+    /// a.gz b.gz`, multi-frame zstd, multi-stream xz) read as one archive
+    pub fn decoder<'a>(
+        self,
+        input: impl Read + Send + Sync + 'a,
+    ) -> PyResult<Box<dyn Read + Send + Sync + 'a>> {
+        Ok(match self {
+            Self::Gzip => Box::new(flate2::read::MultiGzDecoder::new(input)),
+            Self::Zstd => {
+                Box::new(zstd::stream::read::Decoder::new(input).map_err(|e| self.error(&e))?)
+            },
+            Self::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(input)),
+        })
+    }
+
+    fn error(self, e: &io::Error) -> PyErr {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to decompress {} data: {e}",
+            self.name()
+        ))
+    }
+}
+
+/// The mapping as an owned reader, for streams that outlive a borrow
+struct Shared(Arc<MappedFile>);
+
+impl AsRef<[u8]> for Shared {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Messages of a decompressed archive, split off as the data arrives
+pub struct MessageStream<'a> {
+    reader: BufReader<Box<dyn Read + Send + Sync + 'a>>,
+    codec: Codec,
+    mode: FromLineMode,
+    /// Separator line of the next message, already read
+    pending: Option<Vec<u8>>,
+    /// Decompressed bytes read so far
+    offset: usize,
+    prev_blank: bool,
+}
+
+impl<'a> MessageStream<'a> {
+    /// Stream the messages of `data`, compressed with `codec`
+    pub fn new(data: &'a [u8], codec: Codec, mode: FromLineMode) -> PyResult<Self> {
+        Self::open(codec.decoder(data)?, codec, mode)
+    }
+
+    fn open(
+        decoder: Box<dyn Read + Send + Sync + 'a>,
+        codec: Codec,
+        mode: FromLineMode,
+    ) -> PyResult<Self> {
+        if matches!(mode, FromLineMode::Recover) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "from_line_mode=\"recover\" is not supported for compressed archives",
+            ));
+        }
+        let mut stream = Self {
+            reader: BufReader::with_capacity(STREAM_BUFFER, decoder),
+            codec,
+            mode,
+            pending: None,
+            offset: 0,
+            prev_blank: true,
+        };
+        // Bytes before the first separator are not part of any message
+        let mut line = Vec::new();
+        while stream.read_line(&mut line)? {
+            if stream.separates(&line) {
+                stream.pending = Some(line);
+                break;
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Next line into `line` (cleared first); false at the end of the data
+    fn read_line(&mut self, line: &mut Vec<u8>) -> PyResult<bool> {
+        line.clear();
+        let read = self
+            .reader
+            .read_until(b'\n', line)
+            .map_err(|e| self.codec.error(&e))?;
+        Ok(read > 0)
+    }
+
+    /// Whether `line`, the next line of the data, starts a message
+    fn separates(&mut self, line: &[u8]) -> bool {
+        let starts = self.mode.starts_message(line, self.prev_blank);
+        self.prev_blank = crate::mbox::is_blank(line);
+        self.offset += line.len();
+        starts
+    }
+
+    /// Decompressed offset the next message starts at (the decompressed size
+    /// when done)
+    pub fn position(&self) -> usize {
+        self.offset - self.pending.as_ref().map_or(0, Vec::len)
+    }
+
+    /// The next message's span and bytes, `From ` line included
+    pub fn next_message(&mut self) -> PyResult<Option<(Range<usize>, Vec<u8>)>> {
+        let Some(mut message) = self.pending.take() else {
+            return Ok(None);
+        };
+        let start = self.position() - message.len();
+        let mut line = Vec::new();
+        while self.read_line(&mut line)? {
+            if self.separates(&line) {
+                self.pending = Some(line);
+                break;
+            }
+            message.extend_from_slice(&line);
+        }
+        Ok(Some((start..start + message.len(), message)))
+    }
+}
+
+impl MessageStream<'static> {
+    /// Stream the messages of a compressed mapped archive, None if it is
+    /// not compressed
+    pub fn shared(mmap: &Arc<MappedFile>, mode: &FromLineMode) -> PyResult<Option<Self>> {
+        let Some(codec) = Codec::detect(mmap) else {
+            return Ok(None);
+        };
+        let input = io::Cursor::new(Shared(Arc::clone(mmap)));
+        Self::open(codec.decoder(input)?, codec, mode.clone()).map(Some)
+    }
+}
+
+/// Spans of the messages of `data`, compressed with `codec`
+pub fn message_spans(
+    data: &[u8],
+    codec: Codec,
+    mode: &FromLineMode,
+) -> PyResult<Vec<Range<usize>>> {
+    let mut stream = MessageStream::new(data, codec, mode.clone())?;
+    let mut spans = Vec::new();
+    while let Some((span, _)) = stream.next_message()? {
+        spans.push(span);
+    }
+    Ok(spans)
+}
+
+/// Call `f` with the messages of `data`, compressed with `codec`, `size` at
+/// a time (span and bytes); only one batch is held in memory
+pub fn for_each_batch(
+    data: &[u8],
+    codec: Codec,
+    mode: &FromLineMode,
+    size: usize,
+    mut f: impl FnMut(&[(Range<usize>, &[u8])]) -> PyResult<()>,
+) -> PyResult<()> {
+    let mut stream = MessageStream::new(data, codec, mode.clone())?;
+    let mut batch = Vec::with_capacity(size);
+    loop {
+        let next = stream.next_message()?;
+        let done = next.is_none();
+        batch.extend(next);
+        if batch.len() == size || (done && !batch.is_empty()) {
+            let borrowed: Vec<_> = batch
+                .iter()
+                .map(|(span, raw)| (span.clone(), &raw[..]))
+                .collect();
+            f(&borrowed)?;
+            batch.clear();
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Decompressed bytes `range` of `data`, compressed with `codec`
+pub fn read_range(data: &[u8], codec: Codec, range: Range<usize>) -> PyResult<Vec<u8>> {
+    let mut decoder = codec.decoder(data)?;
+    let skip = u64::try_from(range.start).unwrap_or(u64::MAX);
+    io::copy(&mut (&mut decoder).take(skip), &mut io::sink()).map_err(|e| codec.error(&e))?;
+    let mut bytes = vec![0; range.len()];
+    decoder
+        .read_exact(&mut bytes)
+        .map_err(|e| codec.error(&e))?;
+    Ok(bytes)
+}
+
+/// `data` compressed with `codec`, for tests
+#[cfg(test)]
+pub fn compress(data: &[u8], codec: Codec) -> Vec<u8> {
+    match codec {
+        Codec::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            io::Write::write_all(&mut encoder, data).unwrap();
+            encoder.finish().unwrap()
+        },
+        Codec::Zstd => zstd::encode_all(data, 1).unwrap(),
+        Codec::Xz => {
+            let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 1);
+            io::Write::write_all(&mut encoder, data).unwrap();
+            encoder.finish().unwrap()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mbox::message_spans_with;
+
+    #[test]
+    fn test_message_stream() {
+        let mbox = "preamble\n\nFrom a Mon Jan  1 00:00:00 2024\nSubject: 1\n\nFrom me\n\n\
+                    From b Mon Jan  1 00:00:00 2024\r\nSubject: 2\r\n\r\nlast";
+        let mode = FromLineMode::Standard;
+        let expected = message_spans_with(mbox.as_bytes(), &mode);
+        assert_eq!(expected.len(), 2);
+        for codec in [Codec::Gzip, Codec::Zstd, Codec::Xz] {
+            let data = compress(mbox.as_bytes(), codec);
+            assert_eq!(Codec::detect(&data), Some(codec));
+            assert_eq!(message_spans(&data, codec, &mode).unwrap(), expected);
+
+            let mut stream = MessageStream::new(&data, codec, mode.clone()).unwrap();
+            assert_eq!(stream.position(), 10);
+            while let Some((span, bytes)) = stream.next_message().unwrap() {
+                assert_eq!(bytes, &mbox.as_bytes()[span.clone()]);
+                assert_eq!(read_range(&data, codec, span.clone()).unwrap(), bytes);
+            }
+            assert_eq!(stream.position(), mbox.len());
+        }
+
+        // Concatenated members read as one archive
+        let mut twice = compress(mbox.as_bytes(), Codec::Gzip);
+        twice.extend(compress(b"\n\nFrom c Mon Jan  1 00:00:00 2024\n\nx\n", Codec::Gzip));
+        assert_eq!(message_spans(&twice, Codec::Gzip, &mode).unwrap().len(), 3);
+
+        assert_eq!(Codec::detect(mbox.as_bytes()), None);
+        let data = compress(mbox.as_bytes(), Codec::Zstd);
+        assert!(MessageStream::new(&data, Codec::Zstd, FromLineMode::Recover).is_err());
+        assert!(message_spans(&data[..data.len() / 2], Codec::Zstd, &mode).is_err());
+    }
+}
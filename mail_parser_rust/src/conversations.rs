@@ -0,0 +1,404 @@
+//! Threaded, quote-stripped conversation transcripts for LLM ingestion.
+//!
+//! Each thread of an mbox becomes one or more plain-text files. Every file
+//! opens with YAML front matter describing the thread, followed by the
+//! messages in chronological order with quoted replies and signatures removed
+//! so the same text is not embedded over and over.
+
+use crate::date::{format_utc, parse_date};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::output::{Output, OutputReport};
+use crate::progress::Progress;
+use crate::quotes::strip_quotes;
+use crate::thread::{chronological_threads, normalize_subject, ThreadKey};
+use crate::tokens::estimate;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+
+/// Longest subject slug used in transcript filenames
+const MAX_SLUG_LEN: usize = 60;
+
+/// One message reduced to what a transcript needs
+struct Entry {
+    key: ThreadKey,
+    epoch: Option<i64>,
+    author: String,
+    text: String,
+}
+
+impl Entry {
+    fn parse(raw: &[u8]) -> Self {
+        let msg = Message::parse(raw);
+        let key = ThreadKey::from_headers(
+            msg.header("Message-ID"),
+            msg.header("In-Reply-To"),
+            msg.header("References"),
+            msg.decoded_header("Subject").as_deref(),
+        );
+        let author = msg
+            .decoded_header("From")
+            .map(|from| from.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|from| !from.is_empty())
+            .unwrap_or_else(|| "(unknown sender)".to_string());
+        Self {
+            key,
+            epoch: msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch),
+            author,
+            text: strip_quotes(&msg.text_body()),
+        }
+    }
+
+    fn render(&self) -> String {
+        let when = self
+            .epoch
+            .map_or_else(|| "unknown date".to_string(), |e| format_utc(e)[..16].replace('T', " "));
+        let text = if self.text.is_empty() {
+            "(no text)"
+        } else {
+            &self.text
+        };
+        format!("[{when}] {}:\n{text}", self.author)
+    }
+}
+
+/// Lowercase ASCII slug of a subject for use in filenames
+fn slug(subject: &str) -> String {
+    let mut out = String::new();
+    for c in normalize_subject(subject).chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c);
+        } else if !out.ends_with('-') && !out.is_empty() {
+            out.push('-');
+        }
+        if out.len() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let out = out.trim_end_matches('-');
+    if out.is_empty() {
+        "no-subject".to_string()
+    } else {
+        out.to_string()
+    }
+}
+
+/// Split `text` into pieces of at most `budget` estimated tokens, on line
+/// boundaries where possible and word boundaries otherwise
+fn split_to_budget(text: &str, budget: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    let mut push = |unit: &str, sep: char, pieces: &mut Vec<String>| {
+        let tokens = estimate(unit).max(1);
+        if current_tokens + tokens > budget && !current.is_empty() {
+            pieces.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        if !current.is_empty() {
+            current.push(sep);
+        }
+        current.push_str(unit);
+        current_tokens += tokens;
+    };
+    for line in text.lines() {
+        if estimate(line) > budget {
+            for word in line.split_whitespace() {
+                push(word, ' ', &mut pieces);
+            }
+        } else {
+            push(line, '\n', &mut pieces);
+        }
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+/// Pack rendered messages into chunks of at most `budget` estimated tokens
+fn pack_chunks(rendered: &[String], budget: usize) -> Vec<String> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    for message in rendered {
+        for piece in split_to_budget(message, budget) {
+            let tokens = estimate(&piece);
+            if current_tokens + tokens > budget && !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&piece);
+            current_tokens += tokens;
+        }
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// YAML front matter for one chunk; strings are JSON-quoted, which is valid YAML
+fn front_matter(thread_id: usize, thread: &[&Entry], chunk: usize, total: usize) -> String {
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+    let mut participants: Vec<&str> = Vec::new();
+    for entry in thread {
+        if !participants.contains(&entry.author.as_str()) {
+            participants.push(&entry.author);
+        }
+    }
+    let dates: Vec<i64> = thread.iter().filter_map(|e| e.epoch).collect();
+    let subject = thread
+        .first()
+        .map(|e| e.key.subject.trim())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("(no subject)");
+
+    let mut out = String::from("---\n");
+    let _ = writeln!(out, "thread_id: {thread_id}");
+    let _ = writeln!(out, "subject: {}", quote(subject));
+    let _ = writeln!(
+        out,
+        "participants: [{}]",
+        participants
+            .iter()
+            .map(|p| quote(p))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if let (Some(start), Some(end)) = (dates.iter().min(), dates.iter().max()) {
+        let _ = writeln!(out, "start: {}", format_utc(*start));
+        let _ = writeln!(out, "end: {}", format_utc(*end));
+    }
+    let _ = writeln!(out, "messages: {}", thread.len());
+    let _ = writeln!(out, "chunk: {chunk}/{total}");
+    out.push_str("---\n\n");
+    out
+}
+
+/// Export an mbox as threaded plain-text conversation transcripts
+///
+/// Messages are grouped into threads (Message-ID / In-Reply-To / References,
+/// with a subject fallback for replies missing those headers), sorted
+/// chronologically, stripped of quoted text and signatures, and written to
+/// `dest` as `NNNNN-subject-slug-CC.txt` files. Long threads are split into
+/// chunks of at most `max_tokens_per_chunk` estimated tokens (front matter
+/// excluded), so each file can be embedded as-is.
+///
+/// # Arguments
+/// * `src` - Path to the mbox file
+/// * `dest` - Output directory (created if missing)
+/// * `max_tokens_per_chunk` - Token budget per transcript file (default 2000)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` with message, file and byte totals
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import export_conversations_text
+/// report = export_conversations_text("inbox.mbox", "transcripts/", max_tokens_per_chunk=1000)
+/// print(f"{report.file_count} chunks from {report.message_count} messages")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    src,
+    dest,
+    max_tokens_per_chunk=2000,
+    dry_run=false,
+    fsync="file",
+    from_line_mode="standard",
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn export_conversations_text(
+    py: Python<'_>,
+    src: &str,
+    dest: &str,
+    max_tokens_per_chunk: usize,
+    dry_run: bool,
+    fsync: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        if max_tokens_per_chunk == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "max_tokens_per_chunk must be positive",
+            ));
+        }
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?;
+        let mmap = map_file(src)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let entries: Vec<Entry> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| Entry::parse(&data[span]))
+            .collect();
+        progress.check("export_conversations_text")?;
+        mmap.verify(&mode)?;
+
+        let keys: Vec<ThreadKey> = entries.iter().map(|e| e.key.clone()).collect();
+        let epochs: Vec<Option<i64>> = entries.iter().map(|e| e.epoch).collect();
+        let threads = chronological_threads(&keys, &epochs);
+
+        let dest = Path::new(dest);
+        if !output.is_dry_run() {
+            crate::paths::create_dir_all(dest).map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                    "Failed to create output directory: {e}"
+                ))
+            })?;
+        }
+
+        for (n, thread) in threads.iter().enumerate() {
+            let thread: Vec<&Entry> = thread.iter().map(|&i| &entries[i]).collect();
+            let rendered: Vec<String> = thread.iter().map(|e| e.render()).collect();
+            let chunks = pack_chunks(&rendered, max_tokens_per_chunk);
+            let name = slug(&thread[0].key.subject);
+            for (c, chunk) in chunks.iter().enumerate() {
+                let path = dest.join(format!("{:05}-{name}-{:02}.txt", n + 1, c + 1));
+                let write = || -> std::io::Result<()> {
+                    let mut file = output.create(&path)?;
+                    file.write_all(front_matter(n + 1, &thread, c + 1, chunks.len()).as_bytes())?;
+                    file.write_all(chunk.as_bytes())?;
+                    file.write_all(b"\n")?;
+                    file.finish().map(|_| ())
+                };
+                write().map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                        "Failed to write {}: {e}",
+                        path.display()
+                    ))
+                })?;
+            }
+            for _ in &thread {
+                output.note_message();
+            }
+        }
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From alice Mon Jan  1 00:00:00 2024
+From: Alice <alice@example.com>
+Subject: Lunch plans
+Message-ID: <1@x>
+Date: Mon, 1 Jan 2024 12:00:00 +0000
+
+Lunch on Friday?
+
+From bob Mon Jan  1 00:00:00 2024
+From: Carol <carol@example.com>
+Subject: Unrelated
+Message-ID: <2@x>
+Date: Mon, 1 Jan 2024 11:00:00 +0000
+
+Different topic.
+
+From bob Mon Jan  1 00:00:00 2024
+From: Bob <bob@example.com>
+Subject: Re: Lunch plans
+Message-ID: <3@x>
+In-Reply-To: <1@x>
+Date: Mon, 1 Jan 2024 13:30:00 +0000
+
+Friday works.
+
+On Mon, 1 Jan 2024, Alice wrote:
+> Lunch on Friday?
+
+--
+Bob
+";
+
+    #[test]
+    fn test_export_conversations_text() {
+        crate::with_py(|py| {
+            let dir =
+                std::env::temp_dir().join(format!("mpr_conversations_{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            std::fs::write(&src, MBOX).unwrap();
+            let out = dir.join("out");
+
+            let report = export_conversations_text(
+                py,
+                src.to_str().unwrap(),
+                out.to_str().unwrap(),
+                2000,
+                false,
+                "none",
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert_eq!(report.message_count, 3);
+            assert_eq!(report.file_count, 2);
+
+            // The earlier "Unrelated" thread comes first
+            let lunch = std::fs::read_to_string(out.join("00002-lunch-plans-01.txt")).unwrap();
+            assert!(lunch.starts_with("---\nthread_id: 2\nsubject: \"Lunch plans\"\n"));
+            assert!(lunch.contains(
+                "participants: [\"Alice <alice@example.com>\", \"Bob <bob@example.com>\"]"
+            ));
+            assert!(lunch.contains("start: 2024-01-01T12:00:00Z\nend: 2024-01-01T13:30:00Z\n"));
+            assert!(lunch.contains("chunk: 1/1\n---\n\n[2024-01-01 12:00] Alice"));
+            assert!(lunch.contains("[2024-01-01 13:30] Bob <bob@example.com>:\nFriday works.\n"));
+            assert_eq!(lunch.matches("Lunch on Friday?").count(), 1);
+            assert!(!lunch.contains("wrote:"));
+            assert!(out.join("00001-unrelated-01.txt").exists());
+
+            let small = export_conversations_text(
+                py,
+                src.to_str().unwrap(),
+                out.to_str().unwrap(),
+                6,
+                true,
+                "none",
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert!(small.dry_run);
+            assert!(small.file_count > 2);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_chunking_respects_budget() {
+        let long = "word ".repeat(50);
+        let chunks = pack_chunks(&[long.trim().to_string(), "tail".to_string()], 10);
+        assert!(chunks.len() >= 5);
+        assert!(chunks.iter().all(|c| estimate(c) <= 10));
+        assert_eq!(slug("Re: Q3 -- results!!"), "q3-results");
+        assert_eq!(slug(""), "no-subject");
+    }
+}
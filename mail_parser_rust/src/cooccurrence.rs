@@ -0,0 +1,311 @@
+//! Entity co-occurrence across an mbox, for link analysis.
+//!
+//! Every message is reduced to the set of entities it mentions (addresses,
+//! their domains, URLs, and the other extractor kinds); two entities
+//! co-occur when they appear in the same message. Counts are returned as a
+//! sparse upper-triangular matrix in columnar (row, col, count) triples,
+//! which load directly into `scipy.sparse.coo_matrix` or a pandas frame.
+
+use crate::extract::TokenExtractor;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{address_list, Message};
+use crate::progress::Progress;
+use crate::trackers::url_host;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+
+/// Entity kinds: (name, extractor kind feeding it)
+const KINDS: &[(&str, &str)] = &[
+    ("emails", "email"),
+    ("domains", "email"),
+    ("urls", "url"),
+    ("hashtags", "hashtag"),
+    ("tickets", "ticket"),
+    ("invoices", "invoice"),
+];
+
+/// Kinds used when the caller names none
+const DEFAULT_KINDS: [&str; 3] = ["emails", "domains", "urls"];
+
+/// Headers whose addresses count as `emails`
+const ADDRESS_HEADERS: &[&str] = &["From", "To", "Cc", "Bcc", "Reply-To", "Sender"];
+
+/// Requested kinds (positions in [`KINDS`]) and the extractor they need
+struct Plan {
+    kinds: Vec<usize>,
+    extractor: TokenExtractor,
+}
+
+impl Plan {
+    fn new(entities: &[String]) -> PyResult<Self> {
+        let mut kinds = Vec::new();
+        for name in entities {
+            let kind = KINDS
+                .iter()
+                .position(|(kind, _)| kind == name)
+                .ok_or_else(|| {
+                    let names: Vec<&str> = KINDS.iter().map(|(kind, _)| *kind).collect();
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Unknown entity kind: {name:?} (expected one of {names:?})"
+                    ))
+                })?;
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        let mut extract: Vec<String> = Vec::new();
+        for &kind in &kinds {
+            let source = KINDS[kind].1.to_string();
+            if !extract.contains(&source) {
+                extract.push(source);
+            }
+        }
+        // Domains also come from URL hosts
+        if kinds.iter().any(|&k| KINDS[k].0 == "domains") && !extract.iter().any(|k| k == "url") {
+            extract.push("url".to_string());
+        }
+        Ok(Self {
+            kinds,
+            extractor: TokenExtractor::new(Some(extract), &[])?,
+        })
+    }
+
+    /// Distinct (kind, value) entities of one message
+    fn entities(&self, raw: &[u8]) -> BTreeSet<(usize, String)> {
+        let msg = Message::parse(raw);
+        let text =
+            format!("{}\n{}", msg.decoded_header("Subject").unwrap_or_default(), msg.text_body());
+        let found = self.extractor.extract(&text);
+        let matches = |kind: &str| found.get(kind).into_iter().flatten();
+        let addresses: Vec<String> = ADDRESS_HEADERS
+            .iter()
+            .filter_map(|header| msg.decoded_header(header))
+            .flat_map(|value| address_list(&value))
+            .map(|(_, address)| address)
+            .chain(matches("email").map(|email| email.to_lowercase()))
+            .collect();
+
+        let mut out = BTreeSet::new();
+        for &kind in &self.kinds {
+            match KINDS[kind].0 {
+                "emails" => out.extend(addresses.iter().map(|a| (kind, a.clone()))),
+                "domains" => {
+                    let domains = addresses
+                        .iter()
+                        .filter_map(|a| a.rsplit_once('@').map(|(_, domain)| domain.to_string()))
+                        .chain(matches("url").filter_map(|url| url_host(url)));
+                    out.extend(
+                        domains
+                            .map(|d| d.trim_end_matches('.').to_string())
+                            .filter(|d| !d.is_empty())
+                            .map(|d| (kind, d)),
+                    );
+                },
+                _ => out.extend(matches(KINDS[kind].1).map(|v| (kind, v.clone()))),
+            }
+        }
+        out
+    }
+}
+
+/// Entity table and pair counts
+#[derive(Default)]
+struct Matrix {
+    entities: Vec<String>,
+    kinds: Vec<&'static str>,
+    /// Messages mentioning each entity
+    message_counts: Vec<u64>,
+    row: Vec<u32>,
+    col: Vec<u32>,
+    count: Vec<u64>,
+}
+
+/// Count co-occurrences over per-message entity sets; entity ids follow
+/// first appearance, triples are sorted by (row, col) with row < col
+fn build(per_message: Vec<BTreeSet<(usize, String)>>, min_count: u64) -> Matrix {
+    let mut matrix = Matrix::default();
+    let mut ids: HashMap<(usize, String), u32> = HashMap::new();
+    let mut pairs: HashMap<(u32, u32), u64> = HashMap::new();
+    for entities in per_message {
+        let mut message_ids: Vec<u32> = entities
+            .into_iter()
+            .map(|(kind, value)| {
+                let next = u32::try_from(ids.len()).unwrap_or(u32::MAX);
+                let id = *ids.entry((kind, value.clone())).or_insert_with(|| {
+                    matrix.entities.push(value);
+                    matrix.kinds.push(KINDS[kind].0);
+                    matrix.message_counts.push(0);
+                    next
+                });
+                matrix.message_counts[id as usize] += 1;
+                id
+            })
+            .collect();
+        message_ids.sort_unstable();
+        for (i, &a) in message_ids.iter().enumerate() {
+            for &b in &message_ids[i + 1..] {
+                *pairs.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut triples: Vec<((u32, u32), u64)> = pairs
+        .into_iter()
+        .filter(|&(_, count)| count >= min_count)
+        .collect();
+    triples.sort_unstable();
+    for ((row, col), count) in triples {
+        matrix.row.push(row);
+        matrix.col.push(col);
+        matrix.count.push(count);
+    }
+    matrix
+}
+
+/// Sparse co-occurrence matrix of entities mentioned in the same message
+///
+/// Entities are read from each message's address headers (From, To, Cc,
+/// Bcc, Reply-To, Sender), subject and readable body in one pass over the
+/// mbox; a message counts each distinct entity once. `domains` are the
+/// domains of those addresses and of URL hosts.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `entities` - Kinds to include: "emails", "domains", "urls",
+///   "hashtags", "tickets", "invoices" (default: the first three)
+/// * `min_count` - Drop pairs seen in fewer messages
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * Dict of columns. Per entity (ids in order of first appearance):
+///   `entities` (value), `kinds` and `message_counts`. Per pair, with
+///   `row < col` and sorted: `row`, `col` (entity ids) and `count`
+///   (messages mentioning both)
+///
+/// # Raises
+/// * `ValueError` - Unknown entity kind
+/// * `ScanCancelled` - `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from scipy.sparse import coo_matrix
+/// from mail_parser_rust import entity_cooccurrence
+/// m = entity_cooccurrence("inbox.mbox", entities=["emails", "domains"])
+/// n = len(m["entities"])
+/// matrix = coo_matrix((m["count"], (m["row"], m["col"])), shape=(n, n))
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    mbox_path,
+    entities=None,
+    min_count=1,
+    from_line_mode="standard",
+    progress=None,
+))]
+pub fn entity_cooccurrence<'py>(
+    py: Python<'py>,
+    mbox_path: &str,
+    entities: Option<Vec<String>>,
+    min_count: u64,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let matrix = py.allow_threads(|| -> PyResult<_> {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let plan = match entities {
+            Some(entities) => Plan::new(&entities)?,
+            None => Plan::new(&DEFAULT_KINDS.map(String::from))?,
+        };
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let per_message: Vec<BTreeSet<(usize, String)>> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| plan.entities(&data[span]))
+            .collect();
+        progress.check("entity_cooccurrence")?;
+        mmap.verify(&mode)?;
+        Ok(build(per_message, min_count))
+    })?;
+    let dict = PyDict::new(py);
+    dict.set_item("entities", matrix.entities)?;
+    dict.set_item("kinds", matrix.kinds)?;
+    dict.set_item("message_counts", matrix.message_counts)?;
+    dict.set_item("row", matrix.row)?;
+    dict.set_item("col", matrix.col)?;
+    dict.set_item("count", matrix.count)?;
+    Ok(dict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(names: &[&str]) -> Plan {
+        Plan::new(&names.iter().map(ToString::to_string).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_entities() {
+        let raw = b"From: Ann <Ann@Acme.com>\nTo: bob@example.org\nSubject: see #launch\n\n\
+                    Details at https://docs.acme.com/x?y=1 and http://Files.Example.org:8080/z\n\
+                    ping ann@acme.com\n";
+        let plan = kinds(&["emails", "domains", "urls", "hashtags"]);
+        let found: Vec<(&str, String)> = plan
+            .entities(raw)
+            .into_iter()
+            .map(|(kind, value)| (KINDS[kind].0, value))
+            .collect();
+        let values = |kind: &str| {
+            found
+                .iter()
+                .filter(|(k, _)| *k == kind)
+                .map(|(_, v)| v.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values("emails"), ["ann@acme.com", "bob@example.org"]);
+        assert_eq!(
+            values("domains"),
+            [
+                "acme.com",
+                "docs.acme.com",
+                "example.org",
+                "files.example.org"
+            ]
+        );
+        assert_eq!(
+            values("urls"),
+            [
+                "http://Files.Example.org:8080/z",
+                "https://docs.acme.com/x?y=1"
+            ]
+        );
+        assert_eq!(values("hashtags"), ["#launch"]);
+        assert!(Plan::new(&["phones".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_build() {
+        let plan = kinds(&["emails"]);
+        let messages: [&[u8]; 3] = [
+            b"From: a@x.com\nTo: b@x.com, c@x.com\n\n.\n",
+            b"From: b@x.com\nTo: a@x.com\n\n.\n",
+            b"From: d@x.com\n\n.\n",
+        ];
+        let matrix = build(messages.iter().map(|raw| plan.entities(raw)).collect(), 1);
+        assert_eq!(matrix.entities, ["a@x.com", "b@x.com", "c@x.com", "d@x.com"]);
+        assert_eq!(matrix.kinds, ["emails"; 4]);
+        assert_eq!(matrix.message_counts, [2, 2, 1, 1]);
+        assert_eq!(matrix.row, [0, 0, 1]);
+        assert_eq!(matrix.col, [1, 2, 2]);
+        assert_eq!(matrix.count, [2, 1, 1]);
+
+        let matrix = build(messages.iter().map(|raw| plan.entities(raw)).collect(), 2);
+        assert_eq!((matrix.row, matrix.col, matrix.count), (vec![0], vec![1], vec![2]));
+    }
+}
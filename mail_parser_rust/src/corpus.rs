@@ -0,0 +1,521 @@
+//! Synthetic mbox corpora with known pathologies.
+//!
+//! Every message is built from a seeded generator of its own, so a spec
+//! always produces the same bytes (on any thread count) and a failing
+//! pipeline run can be reproduced from its seed. Each pathology is applied
+//! to a message with probability `rate`; the report says how many messages
+//! carry each, which gives the ground truth a pipeline should recover:
+//! `messages` separators, of which `missing_blank` ones lack the empty line
+//! `standard` mode requires.
+
+use crate::date::{format_asctime, format_rfc2822};
+use crate::output::{Output, OutputReport};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Messages generated in parallel before they are written in order
+const BATCH_SIZE: usize = 4096;
+
+/// Supported pathologies, in the order they are rolled for each message
+pub const PATHOLOGIES: &[&str] = &[
+    "from_trap",
+    "missing_blank",
+    "mixed_encoding",
+    "huge_headers",
+    "crlf",
+    "bad_date",
+];
+
+/// 2020-01-01T00:00:00Z; message dates fall in the four years after it
+const FIRST_EPOCH: i64 = 1_577_836_800;
+
+const NAMES: &[&str] = &[
+    "Alice", "Bob", "Carol", "Dave", "Erin", "Frank", "Grace", "Heidi",
+];
+const DOMAINS: &[&str] = &["example.com", "example.org", "mail.example.net"];
+const TOPICS: &[&str] = &[
+    "Quarterly numbers",
+    "Lunch on Friday?",
+    "Re: contract draft",
+    "Invoice 2024-117",
+    "Fwd: travel plans",
+    "Build is red again",
+];
+const FILLER: &[&str] = &[
+    "Thanks for the quick turnaround on this.",
+    "I have attached the notes from the call.",
+    "Let me know if anything is unclear.",
+    "We should revisit the numbers next week.",
+    "The team agreed on the second option.",
+    "Please review before the end of the day.",
+];
+/// Body lines that start with "From " after an empty line but are no
+/// envelope: lenient parsers split on them, `standard` must not
+const FROM_TRAPS: &[&str] = &[
+    "From the desk of the managing director:",
+    "From 2019 onward we changed suppliers.",
+    "From here on, everything is plain text.",
+    "From my phone",
+];
+/// Date values no parser should accept
+const BAD_DATES: &[&str] = &[
+    "yesterday afternoon",
+    "Tue, 31 Feb 2021 10:00:00 +0000",
+    "32 Foo 2020 25:61:00",
+    "",
+];
+
+/// `SplitMix64`: tiny, seedable and identical on every platform
+struct Rng(u64);
+
+impl Rng {
+    /// Generator for message `index` of a corpus seeded with `seed`
+    fn for_message(seed: u64, index: usize) -> Self {
+        let mut rng = Self(seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        rng.next();
+        rng
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n` (`n > 0`)
+    fn below(&mut self, n: usize) -> usize {
+        usize::try_from(self.next() % n as u64).unwrap_or(0)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+
+    /// True with probability `p`
+    #[allow(clippy::cast_precision_loss)]
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+/// What to generate
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import CorpusSpec
+/// spec = CorpusSpec(messages=10_000, seed=7, pathologies=["crlf", "from_trap"], rate=0.3)
+/// ```
+#[derive(Serialize, Clone, Debug)]
+#[pyclass]
+pub struct CorpusSpec {
+    /// Number of messages
+    #[pyo3(get)]
+    pub messages: usize,
+    /// Seed; equal specs produce identical files
+    #[pyo3(get)]
+    pub seed: u64,
+    /// Pathologies to plant (see `PATHOLOGIES`)
+    #[pyo3(get)]
+    pub pathologies: Vec<String>,
+    /// Probability that a message carries a given pathology
+    #[pyo3(get)]
+    pub rate: f64,
+}
+
+impl Default for CorpusSpec {
+    fn default() -> Self {
+        Self {
+            messages: 1000,
+            seed: 0,
+            pathologies: PATHOLOGIES.iter().map(|p| (*p).to_string()).collect(),
+            rate: 0.1,
+        }
+    }
+}
+
+#[pymethods]
+impl CorpusSpec {
+    /// Describe a corpus
+    ///
+    /// # Arguments
+    /// * `messages` - Number of messages (default 1000)
+    /// * `seed` - Generator seed (default 0)
+    /// * `pathologies` - Any of `from_trap`, `missing_blank`,
+    ///   `mixed_encoding`, `huge_headers`, `crlf`, `bad_date` (default: all)
+    /// * `rate` - Probability that a message carries each pathology,
+    ///   0.0-1.0 (default 0.1)
+    ///
+    /// # Raises
+    /// * `ValueError` for an unknown pathology or a rate outside 0.0-1.0
+    #[new]
+    #[pyo3(signature = (messages=1000, seed=0, pathologies=None, rate=0.1))]
+    pub fn new(
+        messages: usize,
+        seed: u64,
+        pathologies: Option<Vec<String>>,
+        rate: f64,
+    ) -> PyResult<Self> {
+        let pathologies = pathologies.unwrap_or_else(|| Self::default().pathologies);
+        if let Some(unknown) = pathologies
+            .iter()
+            .find(|p| !PATHOLOGIES.contains(&p.as_str()))
+        {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown pathology: {unknown} (expected one of {})",
+                PATHOLOGIES.join(", ")
+            )));
+        }
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "rate must be between 0.0 and 1.0, got {rate}"
+            )));
+        }
+        Ok(Self {
+            messages,
+            seed,
+            pathologies,
+            rate,
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "CorpusSpec(messages={}, seed={}, pathologies={:?}, rate={})",
+            self.messages, self.seed, self.pathologies, self.rate
+        )
+    }
+}
+
+/// What `generate_test_mbox` wrote
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct CorpusReport {
+    /// Messages written, i.e. the number a correct parser finds
+    #[pyo3(get)]
+    pub messages: usize,
+    /// Messages carrying each pathology
+    #[pyo3(get)]
+    pub pathologies: HashMap<String, usize>,
+    /// Files and bytes written
+    #[pyo3(get)]
+    pub output: OutputReport,
+}
+
+/// One generated message and the pathologies it carries
+struct Generated {
+    bytes: Vec<u8>,
+    /// The empty line before this message's separator is left out
+    missing_blank: bool,
+    crlf: bool,
+    pathologies: Vec<&'static str>,
+}
+
+fn body_text(rng: &mut Rng, name: &str, index: usize) -> String {
+    let mut body = format!("Hi {name},\n\nThis is message {index} of the corpus.\n");
+    for _ in 0..=rng.below(12) {
+        body.push_str(rng.pick(FILLER));
+        body.push('\n');
+    }
+    body
+}
+
+/// Body and content headers in one of several encodings that trip up
+/// parsers: raw Latin-1, base64 and quoted-printable UTF-8, undeclared
+/// windows-1252 with invalid UTF-8
+fn mixed_encoding(rng: &mut Rng, text: &str) -> (String, Vec<u8>) {
+    match rng.below(4) {
+        0 => (
+            "Subject: =?iso-8859-1?q?Caf=E9_r=E9union?=\n\
+             Content-Type: text/plain; charset=iso-8859-1\n"
+                .to_string(),
+            [text.as_bytes(), b"Caf\xe9 au lait, \xe0 bient\xf4t.\n"].concat(),
+        ),
+        1 => {
+            let utf8 =
+                format!("{text}Gr\u{fc}\u{df}e aus M\u{fc}nchen \u{2014} \u{6771}\u{4eac}\n");
+            let encoded = STANDARD.encode(utf8);
+            let mut body = String::new();
+            for chunk in encoded.as_bytes().chunks(76) {
+                body.push_str(&String::from_utf8_lossy(chunk));
+                body.push('\n');
+            }
+            (
+                "Subject: =?utf-8?b?R3LDvMOfZSBhdXMgTcO8bmNoZW4=?=\n\
+                 Content-Type: text/plain; charset=utf-8\n\
+                 Content-Transfer-Encoding: base64\n"
+                    .to_string(),
+                body.into_bytes(),
+            )
+        },
+        2 => (
+            "Subject: Gr=C3=BC=C3=9Fe\n\
+             Content-Type: text/plain; charset=utf-8\n\
+             Content-Transfer-Encoding: quoted-printable\n"
+                .to_string(),
+            [
+                text.as_bytes(),
+                b"Gr=C3=BC=C3=9Fe aus M=C3=BCnchen, a soft=\nly wrapped line.\n",
+            ]
+            .concat(),
+        ),
+        _ => (
+            "Subject: \u{201c}Smart\u{201d} quotes\n".to_string(),
+            [
+                text.as_bytes(),
+                b"He said \x93hello\x94 \x96 then \xff\xfe garbage.\n",
+            ]
+            .concat(),
+        ),
+    }
+}
+
+/// Dozens of `Received:` hops, a References chain hundreds of IDs long and
+/// a very long single-line header
+fn huge_headers(rng: &mut Rng, index: usize, date: &str) -> String {
+    let mut headers = String::new();
+    for hop in 0..20 + rng.below(30) {
+        let _ = write!(
+            headers,
+            "Received: from relay{hop}.example.net (relay{hop}.example.net [10.0.{}.{}])\n\
+             \tby mx{hop}.example.com with ESMTPS id {:016x}; {date}\n",
+            hop % 256,
+            rng.below(255) + 1,
+            rng.next()
+        );
+    }
+    headers.push_str("References:");
+    for n in 0..100 + rng.below(200) {
+        let _ = write!(headers, "\n <{index}.{n}.thread@example.com>");
+    }
+    headers.push('\n');
+    headers.push_str("X-Long-Header: ");
+    headers.push_str(&"abcdefghij".repeat(400 + rng.below(400)));
+    headers.push('\n');
+    headers
+}
+
+fn generate(spec: &CorpusSpec, index: usize) -> Generated {
+    let mut rng = Rng::for_message(spec.seed, index);
+    let mut applied: Vec<&'static str> = Vec::new();
+    for &pathology in PATHOLOGIES {
+        let enabled = spec.pathologies.iter().any(|p| p == pathology);
+        // The first message has no separator gap to lose
+        let possible = pathology != "missing_blank" || index > 0;
+        if rng.chance(spec.rate) && enabled && possible {
+            applied.push(pathology);
+        }
+    }
+    let has = |pathology: &str| applied.contains(&pathology);
+
+    let name = rng.pick(NAMES);
+    let sender = format!("{}@{}", name.to_ascii_lowercase(), rng.pick(DOMAINS));
+    let recipient = format!("{}@{}", rng.pick(NAMES).to_ascii_lowercase(), rng.pick(DOMAINS));
+    let epoch = FIRST_EPOCH + i64::try_from(rng.below(4 * 365 * 86_400)).unwrap_or(0);
+    let date = format_rfc2822(epoch);
+
+    let mut headers = format!("From {sender} {}\n", format_asctime(epoch));
+    if has("huge_headers") {
+        headers.push_str(&huge_headers(&mut rng, index, &date));
+    }
+    let _ = write!(headers, "From: {name} <{sender}>\nTo: {recipient}\n");
+    let date = if has("bad_date") {
+        rng.pick(BAD_DATES).to_string()
+    } else {
+        date
+    };
+    let _ = writeln!(headers, "Date: {date}");
+    let _ = writeln!(headers, "Message-ID: <{index}.{}@corpus.example.com>", spec.seed);
+
+    let mut text = body_text(&mut rng, name, index);
+    if has("from_trap") {
+        for _ in 0..=rng.below(3) {
+            text.push('\n');
+            text.push_str(rng.pick(FROM_TRAPS));
+            text.push('\n');
+        }
+    }
+    let body = if has("mixed_encoding") {
+        let (content_headers, body) = mixed_encoding(&mut rng, &text);
+        headers.push_str(&content_headers);
+        body
+    } else {
+        let _ = writeln!(headers, "Subject: {}", rng.pick(TOPICS));
+        text.into_bytes()
+    };
+    headers.push('\n');
+
+    let mut bytes = [headers.as_bytes(), &body].concat();
+    if has("crlf") {
+        let mut crlf = Vec::with_capacity(bytes.len() + bytes.len() / 32);
+        for &b in &bytes {
+            if b == b'\n' {
+                crlf.push(b'\r');
+            }
+            crlf.push(b);
+        }
+        bytes = crlf;
+    }
+    Generated {
+        bytes,
+        missing_blank: has("missing_blank"),
+        crlf: has("crlf"),
+        pathologies: applied,
+    }
+}
+
+/// Write the corpus to `out`, returning messages and pathology counts
+fn write_corpus(
+    spec: &CorpusSpec,
+    out: &mut impl std::io::Write,
+) -> std::io::Result<(usize, HashMap<String, usize>)> {
+    let mut counts: HashMap<String, usize> =
+        spec.pathologies.iter().map(|p| (p.clone(), 0)).collect();
+    let mut previous_crlf = None;
+    for start in (0..spec.messages).step_by(BATCH_SIZE) {
+        let end = (start + BATCH_SIZE).min(spec.messages);
+        let batch: Vec<Generated> = (start..end)
+            .into_par_iter()
+            .map(|index| generate(spec, index))
+            .collect();
+        for message in batch {
+            if let Some(crlf) = previous_crlf {
+                if !message.missing_blank {
+                    out.write_all(if crlf { b"\r\n" } else { b"\n" })?;
+                }
+            }
+            out.write_all(&message.bytes)?;
+            for pathology in message.pathologies {
+                *counts.entry(pathology.to_string()).or_default() += 1;
+            }
+            previous_crlf = Some(message.crlf);
+        }
+    }
+    Ok((spec.messages, counts))
+}
+
+/// Generate an mbox full of realistic hostile input
+///
+/// Messages have plausible senders, dates, subjects and bodies. On top,
+/// each pathology of `spec` is planted in a share of them:
+///
+/// - `from_trap`: body lines starting "From " after an empty line
+/// - `missing_blank`: no empty line before the `From ` separator
+/// - `mixed_encoding`: Latin-1, base64 or quoted-printable UTF-8, or
+///   undeclared windows-1252 with invalid UTF-8
+/// - `huge_headers`: dozens of `Received:` hops, hundreds of References,
+///   a header line of several KiB
+/// - `crlf`: CRLF line endings
+/// - `bad_date`: an unparseable `Date:`
+///
+/// The same spec always yields the same file. A correct `standard` scan
+/// finds `messages - pathologies["missing_blank"]` messages; `recover`
+/// finds all of them.
+///
+/// # Arguments
+/// * `path` - Destination mbox (`.gz`/`.zst` compress on the fly)
+/// * `spec` - `CorpusSpec` (default: 1000 messages, every pathology at 10%)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+///
+/// # Returns
+/// * `CorpusReport` with `messages`, per-pathology `pathologies` counts and
+///   the `output` totals
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import CorpusSpec, count_messages_fast, generate_test_mbox
+/// report = generate_test_mbox("hostile.mbox", CorpusSpec(messages=5000, seed=42))
+/// expected = report.messages - report.pathologies["missing_blank"]
+/// assert count_messages_fast("hostile.mbox") == expected
+/// assert count_messages_fast("hostile.mbox", from_line_mode="recover") == report.messages
+/// ```
+#[pyfunction]
+#[pyo3(signature = (path, spec=None, dry_run=false, fsync="file"))]
+pub fn generate_test_mbox(
+    py: Python<'_>,
+    path: &str,
+    spec: Option<CorpusSpec>,
+    dry_run: bool,
+    fsync: &str,
+) -> PyResult<CorpusReport> {
+    py.allow_threads(|| {
+        let spec = spec.unwrap_or_default();
+        let output = Output::from_args(dry_run, fsync, None)?;
+        let io_error = |e: std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {path}: {e}"))
+        };
+        let mut file = output.create(Path::new(path)).map_err(io_error)?;
+        let (messages, pathologies) = write_corpus(&spec, &mut file).map_err(io_error)?;
+        file.finish().map_err(io_error)?;
+        for _ in 0..messages {
+            output.note_message();
+        }
+        Ok(CorpusReport {
+            messages,
+            pathologies,
+            output: output.report(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mbox::{message_spans_with, FromLineMode};
+    use crate::mime::Message;
+    use proptest::prelude::*;
+
+    fn corpus(spec: &CorpusSpec) -> (Vec<u8>, HashMap<String, usize>) {
+        let mut data = Vec::new();
+        let (_, counts) = write_corpus(spec, &mut data).unwrap();
+        (data, counts)
+    }
+
+    #[test]
+    fn test_spec_validation() {
+        assert!(CorpusSpec::new(10, 0, Some(vec!["bogus".into()]), 0.1).is_err());
+        assert!(CorpusSpec::new(10, 0, None, 1.5).is_err());
+        assert_eq!(CorpusSpec::new(10, 0, None, 0.1).unwrap().pathologies.len(), 6);
+    }
+
+    #[test]
+    fn test_corpus_is_deterministic() {
+        let spec = CorpusSpec::new(200, 9, None, 0.5).unwrap();
+        let (first, counts) = corpus(&spec);
+        assert_eq!(first, corpus(&spec).0);
+        assert!(counts.values().all(|&n| n > 0), "{counts:?}");
+        let other = CorpusSpec { seed: 10, ..spec };
+        assert_ne!(first, corpus(&other).0);
+        assert!(std::str::from_utf8(&first).is_err());
+
+        let clean = CorpusSpec::new(50, 1, Some(Vec::new()), 1.0).unwrap();
+        let (data, counts) = corpus(&clean);
+        assert!(counts.is_empty());
+        let spans = message_spans_with(&data, &FromLineMode::Strict);
+        assert_eq!(spans.len(), 50);
+        assert!(spans
+            .iter()
+            .all(|s| Message::parse(&data[s.clone()]).header("Date").is_some()));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        #[test]
+        fn prop_ground_truth(seed in any::<u64>(), messages in 1usize..60, rate in 0.0f64..=1.0) {
+            let spec = CorpusSpec::new(messages, seed, None, rate).unwrap();
+            let (data, counts) = corpus(&spec);
+            let standard = message_spans_with(&data, &FromLineMode::Standard).len();
+            prop_assert_eq!(standard, messages - counts["missing_blank"]);
+            prop_assert_eq!(message_spans_with(&data, &FromLineMode::Recover).len(), messages);
+        }
+    }
+}
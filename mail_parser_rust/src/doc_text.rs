@@ -0,0 +1,243 @@
+//! Searchable text of attachments, whatever their format.
+//!
+//! Dispatches on the attachment type: PDFs go to [`crate::pdf`] (when built
+//! with the `pdf` feature), Office Open XML (docx/xlsx/pptx) and ODF
+//! (OpenOffice/LibreOffice) files are unzipped and their XML text collected, and text, CSV and HTML
+//! attachments are decoded directly. Everything else (images, archives,
+//! binaries) has no text.
+
+use crate::embedding::normalize_whitespace;
+use crate::html::{decode_entities, html_to_text};
+use crate::mime::{Message, Part};
+use pyo3::prelude::*;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// Largest XML entry inflated from an Office file
+const MAX_XML_BYTES: u64 = 32 * 1024 * 1024;
+
+/// XML elements that end a paragraph, cell or slide line
+const LINE_BREAKS: &[&str] = &["w:p", "a:p", "text:p", "text:h", "si", "row", "w:tr"];
+
+/// XML elements that stand for a space
+const SPACES: &[&str] = &[
+    "w:tab",
+    "w:br",
+    "text:s",
+    "text:tab",
+    "text:line-break",
+    "a:br",
+];
+
+/// Text of an XML document: tags removed, paragraphs on their own lines
+fn xml_text(xml: &str) -> String {
+    let mut out = String::with_capacity(xml.len() / 4);
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&decode_entities(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        if closing && LINE_BREAKS.contains(&name) {
+            out.push('\n');
+        } else if SPACES.contains(&name) {
+            out.push(' ');
+        }
+    }
+    out.push_str(&decode_entities(rest));
+    out
+}
+
+/// Slide number in `ppt/slides/slideN.xml`, for ordering
+fn slide_number(name: &str) -> Option<u32> {
+    name.strip_prefix("ppt/slides/slide")?
+        .strip_suffix(".xml")?
+        .parse()
+        .ok()
+}
+
+/// Text of a docx, xlsx, pptx or ODF (odt, ods, odp) file
+pub fn office_text(data: &[u8]) -> Option<String> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).ok()?;
+    let names: Vec<String> = archive.file_names().map(ToString::to_string).collect();
+    let mut parts: Vec<String> = if names.iter().any(|n| n == "word/document.xml") {
+        vec!["word/document.xml".to_string()]
+    } else if names.iter().any(|n| n == "xl/sharedStrings.xml") {
+        vec!["xl/sharedStrings.xml".to_string()]
+    } else if names.iter().any(|n| n == "content.xml") {
+        vec!["content.xml".to_string()]
+    } else {
+        names
+            .into_iter()
+            .filter(|n| slide_number(n).is_some())
+            .collect()
+    };
+    parts.sort_by_key(|n| slide_number(n));
+    let mut text = String::new();
+    for name in parts {
+        let Ok(entry) = archive.by_name(&name) else {
+            continue;
+        };
+        let mut xml = Vec::new();
+        if entry.take(MAX_XML_BYTES).read_to_end(&mut xml).is_err() {
+            continue;
+        }
+        text.push_str(&xml_text(&String::from_utf8_lossy(&xml)));
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    let text = normalize_whitespace(&text);
+    (!text.is_empty()).then_some(text)
+}
+
+/// Lowercased extension of an attachment filename
+fn extension(part: &Part<'_>) -> String {
+    part.filename()
+        .and_then(|name| {
+            name.rsplit_once('.')
+                .map(|(_, ext)| ext.to_ascii_lowercase())
+        })
+        .unwrap_or_default()
+}
+
+/// Searchable text of one attachment, or `None` if it has none
+pub fn part_text(part: &Part<'_>) -> Option<String> {
+    let mime = part.mime_type();
+    let ext = extension(part);
+    let text = if mime == "text/html" || matches!(ext.as_str(), "html" | "htm") {
+        html_to_text(&part.text())
+    } else if mime.starts_with("text/") || matches!(ext.as_str(), "txt" | "csv" | "md" | "log") {
+        part.text()
+    } else {
+        let data = part.decoded();
+        if data.starts_with(b"%PDF-") || mime == "application/pdf" || ext == "pdf" {
+            return pdf(&data);
+        }
+        if !data.starts_with(b"PK\x03\x04") {
+            return None;
+        }
+        return office_text(&data);
+    };
+    let text = normalize_whitespace(&text);
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(feature = "pdf")]
+fn pdf(data: &[u8]) -> Option<String> {
+    crate::pdf::pdf_text(data)
+}
+
+#[cfg(not(feature = "pdf"))]
+const fn pdf(_data: &[u8]) -> Option<String> {
+    None
+}
+
+/// `(filename, text)` of every attachment of a message that has text
+pub fn attachment_texts(raw: &[u8]) -> Vec<(String, String)> {
+    let msg = Message::parse(raw);
+    msg.parts()
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.is_attachment())
+        .filter_map(|(i, p)| {
+            let text = part_text(p)?;
+            Some((p.filename().unwrap_or_else(|| format!("part{i}")), text))
+        })
+        .collect()
+}
+
+/// Extract the text of every attachment of a raw message
+///
+/// PDFs need the `pdf` build feature; Office (docx, xlsx, pptx), ODF (odt, ods,
+/// odp), plain text, CSV and HTML attachments are always read. Attachments without
+/// text are left out.
+///
+/// # Arguments
+/// * `raw_bytes` - Complete RFC 822 message
+///
+/// # Returns
+/// * `(filename, text)` pairs in message order (unnamed parts are called
+///   `part<N>`)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import attachment_text_fast
+/// for name, text in attachment_text_fast(raw):
+///     print(name, len(text))
+/// ```
+#[pyfunction]
+pub fn attachment_text_fast(raw_bytes: &[u8]) -> Vec<(String, String)> {
+    attachment_texts(raw_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn zip_file(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_office_text() {
+        let docx = zip_file(&[(
+            "word/document.xml",
+            "<w:document><w:body><w:p><w:r><w:t>Quarterly</w:t></w:r><w:r><w:tab/>\
+             <w:t>report &amp; forecast</w:t></w:r></w:p><w:p><w:r><w:t>Page 2</w:t></w:r>\
+             </w:p></w:body></w:document>",
+        )]);
+        assert_eq!(office_text(&docx).unwrap(), "Quarterly report & forecast\nPage 2");
+        let pptx = zip_file(&[
+            ("ppt/slides/slide10.xml", "<p:sld><a:p><a:t>Ten</a:t></a:p></p:sld>"),
+            ("ppt/slides/slide2.xml", "<p:sld><a:p><a:t>Two</a:t></a:p></p:sld>"),
+        ]);
+        assert_eq!(office_text(&pptx).unwrap(), "Two\nTen");
+        assert_eq!(office_text(&zip_file(&[("a.txt", "x")])), None);
+        assert_eq!(office_text(b"not a zip"), None);
+    }
+
+    #[test]
+    fn test_attachment_texts() {
+        use base64::Engine;
+        let xlsx = zip_file(&[(
+            "xl/sharedStrings.xml",
+            "<sst><si><t>Invoice</t></si><si><t>Total</t></si></sst>",
+        )]);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(xlsx);
+        let raw = format!(
+            "Content-Type: multipart/mixed; boundary=b\n\n--b\nContent-Type: text/plain\n\n\
+             Body text\n--b\nContent-Type: application/octet-stream; name=\"sheet.xlsx\"\n\
+             Content-Transfer-Encoding: base64\n\n{encoded}\n--b\n\
+             Content-Type: text/html\nContent-Disposition: attachment; filename=page.html\n\n\
+             <p>Hello <b>there</b></p>\n--b\nContent-Type: image/png; name=x.png\n\n\
+             PNGDATA\n--b--\n"
+        );
+        let texts = attachment_texts(raw.as_bytes());
+        assert_eq!(
+            texts,
+            [
+                ("sheet.xlsx".to_string(), "Invoice\nTotal".to_string()),
+                ("page.html".to_string(), "Hello there".to_string()),
+            ]
+        );
+    }
+}
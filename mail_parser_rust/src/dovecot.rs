@@ -0,0 +1,229 @@
+//! Dovecot Maildir metadata: IMAP UIDs and keyword names.
+//!
+//! Dovecot numbers the messages of every folder with IMAP UIDs and records
+//! them in the folder's `dovecot-uidlist`: a header line, then one line per
+//! message with its UID and file name. Version 1 headers are
+//! `1 <uidvalidity> <nextuid>` and lines `<uid> <file name>`; versions 2 and
+//! 3 use `3 V<uidvalidity> N<nextuid> G<guid>` and
+//! `<uid> [<ext>...] :<file name>`, with extension fields such as `S<size>`
+//! and `W<vsize>` before the name. Names are the file's unique part, without
+//! the `:2,` flag suffix, so a UID survives flag changes. Together with the
+//! keyword names of `dovecot-keywords` (see [`crate::maildirpp`]) this is
+//! what a live server's Maildir knows beyond the message files themselves.
+
+use crate::maildir::split_info;
+use crate::maildirpp::{folder_dir, Keywords};
+use crate::paths::extended;
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// UID list of a Dovecot Maildir folder
+const UIDLIST_FILE: &str = "dovecot-uidlist";
+
+/// Header and UIDs of a `dovecot-uidlist`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct UidList {
+    uid_validity: Option<u32>,
+    next_uid: Option<u32>,
+    /// UID by file name (unique part)
+    uids: HashMap<String, u32>,
+}
+
+impl UidList {
+    /// Parse `dovecot-uidlist` contents; malformed lines are skipped
+    fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect();
+        let mut list = Self::default();
+        let version = header.first().copied().unwrap_or_default();
+        if version == "1" {
+            list.uid_validity = header.get(1).and_then(|v| v.parse().ok());
+            list.next_uid = header.get(2).and_then(|v| v.parse().ok());
+        } else {
+            for field in header.iter().skip(1) {
+                if let Some(value) = field.strip_prefix('V') {
+                    list.uid_validity = value.parse().ok();
+                } else if let Some(value) = field.strip_prefix('N') {
+                    list.next_uid = value.parse().ok();
+                }
+            }
+        }
+        for line in lines {
+            let Some((uid, rest)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(uid) = uid.parse::<u32>() else {
+                continue;
+            };
+            let name = if version == "1" {
+                rest.trim()
+            } else {
+                rest.split_once(':').map_or(rest, |(_, name)| name).trim()
+            };
+            if !name.is_empty() {
+                list.uids.insert(split_info(name).0.to_string(), uid);
+            }
+        }
+        list
+    }
+}
+
+/// What Dovecot records about one Maildir folder: IMAP UIDs from
+/// `dovecot-uidlist` and keyword names from `dovecot-keywords`
+///
+/// Both files are optional; without them there are no UIDs or keywords.
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import dovecot_folder, MaildirReader
+/// meta = dovecot_folder("/home/me/Maildir", folder="Work")
+/// print(meta.uid_validity, len(meta), "UIDs", meta.keywords)
+/// for msg in MaildirReader("/home/me/Maildir", folder="Work"):
+///     print(msg.imap_uid, msg.keywords)
+/// ```
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DovecotFolder {
+    uids: UidList,
+    /// Keyword names, by flag letter
+    pub keywords: Keywords,
+}
+
+impl DovecotFolder {
+    /// Read the metadata of the Maildir folder at `dir`
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let uids = match fs::read(extended(&dir.join(UIDLIST_FILE))) {
+            Ok(bytes) => UidList::parse(&String::from_utf8_lossy(&bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => UidList::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            uids,
+            keywords: Keywords::load(dir)?,
+        })
+    }
+
+    /// IMAP UID of the message file at `path`
+    pub fn uid_of(&self, path: &Path) -> Option<u32> {
+        self.uid(&path.file_name()?.to_string_lossy())
+    }
+}
+
+#[pymethods]
+impl DovecotFolder {
+    /// UIDVALIDITY of the folder (None without a uidlist)
+    #[getter]
+    const fn uid_validity(&self) -> Option<u32> {
+        self.uids.uid_validity
+    }
+
+    /// UID the server assigns next (None without a uidlist)
+    #[getter]
+    const fn next_uid(&self) -> Option<u32> {
+        self.uids.next_uid
+    }
+
+    /// Keyword names by flag letter, e.g. `{"a": "$Label1"}`
+    #[getter]
+    fn keywords(&self) -> HashMap<char, String> {
+        self.keywords
+            .assigned()
+            .map(|(letter, name)| (letter, name.to_string()))
+            .collect()
+    }
+
+    /// IMAP UID of a message, by file name (with or without flags)
+    pub fn uid(&self, name: &str) -> Option<u32> {
+        self.uids.uids.get(split_info(name).0).copied()
+    }
+
+    /// Every recorded UID, by file name without flags
+    fn uids(&self) -> HashMap<String, u32> {
+        self.uids.uids.clone()
+    }
+
+    /// Number of messages in the uidlist
+    fn __len__(&self) -> usize {
+        self.uids.uids.len()
+    }
+
+    fn __repr__(&self) -> String {
+        let show = |value: Option<u32>| value.map_or_else(|| "None".to_string(), |v| v.to_string());
+        format!(
+            "DovecotFolder(uid_validity={}, next_uid={}, messages={})",
+            show(self.uids.uid_validity),
+            show(self.uids.next_uid),
+            self.uids.uids.len()
+        )
+    }
+}
+
+/// Read the Dovecot UID list and keyword names of a Maildir folder
+///
+/// # Arguments
+/// * `path` - Maildir directory (the root of a Maildir++ tree)
+/// * `folder` - Maildir++ folder under `path` (default: `path` itself)
+///
+/// # Returns
+/// * `DovecotFolder`, empty when the folder has no Dovecot files
+///
+/// # Raises
+/// * `ValueError` for an invalid folder name
+/// * `IOError` if a file exists but cannot be read
+#[pyfunction]
+#[pyo3(signature = (path, folder=None))]
+pub fn dovecot_folder(py: Python<'_>, path: &str, folder: Option<&str>) -> PyResult<DovecotFolder> {
+    py.allow_threads(|| {
+        let dir = folder_dir(Path::new(path), folder)?;
+        DovecotFolder::load(&dir).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to read {}: {e}",
+                dir.display()
+            ))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uidlist() {
+        let v3 = UidList::parse(
+            "3 V1276528487 N4 G3085f01b7f11094c501100008c4a11c1\n\
+             1 W2232 S2181 :1276528487.M364837P9451.kurkku,S=2181,W=2232\n\
+             3 :1276528490.M1P1.kurkku:2,S\n\
+             bogus line\n",
+        );
+        assert_eq!((v3.uid_validity, v3.next_uid), (Some(1_276_528_487), Some(4)));
+        assert_eq!(v3.uids.len(), 2);
+        assert_eq!(v3.uids["1276528487.M364837P9451.kurkku,S=2181,W=2232"], 1);
+        assert_eq!(v3.uids["1276528490.M1P1.kurkku"], 3);
+        let v1 = UidList::parse("1 1000 12\n10 1000.1.host:2,RS\n11 1001.2.host\n");
+        assert_eq!((v1.uid_validity, v1.next_uid), (Some(1000), Some(12)));
+        assert_eq!(v1.uids["1000.1.host"], 10);
+        assert_eq!(UidList::parse(""), UidList::default());
+
+        let dir = std::env::temp_dir().join(format!("mpr_dovecot_{}", std::process::id()));
+        fs::create_dir_all(dir.join(".Work")).unwrap();
+        assert_eq!(DovecotFolder::load(&dir).unwrap(), DovecotFolder::default());
+        fs::write(dir.join(".Work").join(UIDLIST_FILE), "3 V7 N3\n2 :1000.1.host\n").unwrap();
+        fs::write(dir.join(".Work").join("dovecot-keywords"), "1 todo\n").unwrap();
+        let work =
+            crate::with_py(|py| dovecot_folder(py, dir.to_str().unwrap(), Some("Work"))).unwrap();
+        assert_eq!(work.uid("1000.1.host:2,Sb"), Some(2));
+        assert_eq!(work.uid_of(Path::new("cur/1000.1.host:2,")), Some(2));
+        assert_eq!(work.uid("1000.2.host"), None);
+        assert_eq!(work.keywords(), HashMap::from([('b', "todo".to_string())]));
+        assert_eq!(work.__repr__(), "DovecotFolder(uid_validity=7, next_uid=3, messages=1)");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
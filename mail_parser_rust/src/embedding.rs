@@ -0,0 +1,226 @@
+//! One-call body preparation for embedding pipelines.
+
+use crate::html::html_to_text;
+use crate::mime::Message;
+use crate::quotes::strip_quotes;
+use crate::tokens::{count, truncate, Model};
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Text ready to embed, plus how it was derived from the message
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[pyclass]
+pub struct EmbeddingText {
+    /// The normalized (and possibly truncated) text
+    #[pyo3(get)]
+    pub text: String,
+    /// MIME type the body came from: "text/plain", "text/html" or "none"
+    #[pyo3(get)]
+    pub body_source: String,
+    /// Whether the body was converted from HTML
+    #[pyo3(get)]
+    pub html_converted: bool,
+    /// Whether quoted replies or a signature were removed
+    #[pyo3(get)]
+    pub quotes_stripped: bool,
+    /// Whether `max_chars` or `max_tokens` cut the text short
+    #[pyo3(get)]
+    pub truncated: bool,
+    /// Length in characters before truncation
+    #[pyo3(get)]
+    pub original_chars: usize,
+    /// Token count of `text` for the chosen model
+    #[pyo3(get)]
+    pub token_count: usize,
+}
+
+/// Characters that render as nothing and only fragment tokens
+const fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' | '\u{ad}')
+}
+
+/// Collapse runs of spaces inside lines, trim lines, keep at most one blank
+/// line in a row and drop zero-width characters
+pub fn normalize_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_pending = false;
+    for line in text.lines() {
+        let mut words = line
+            .split(|c: char| c.is_whitespace())
+            .map(|w| w.chars().filter(|&c| !is_invisible(c)).collect::<String>())
+            .filter(|w| !w.is_empty())
+            .peekable();
+        if words.peek().is_none() {
+            blank_pending = !out.is_empty();
+            continue;
+        }
+        if blank_pending {
+            out.push('\n');
+            blank_pending = false;
+        }
+        for (i, word) in words.enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            out.push_str(&word);
+        }
+        out.push('\n');
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+/// Cut `text` to at most `max_chars` characters, backing off to a word boundary
+fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    let Some((cut, _)) = text.char_indices().nth(max_chars) else {
+        return text;
+    };
+    let head = &text[..cut];
+    head.rfind(char::is_whitespace)
+        .map_or(head, |space| head[..space].trim_end())
+}
+
+/// Build the embedding text for one raw message
+pub fn prepare(
+    raw: &[u8],
+    strip: bool,
+    include_subject: bool,
+    max_chars: Option<usize>,
+    max_tokens: Option<usize>,
+    model: Model,
+) -> EmbeddingText {
+    let msg = Message::parse(raw);
+    let part = msg.body_part();
+    let body_source = part.as_ref().map_or("none", |p| p.mime_type()).to_string();
+    let html_converted = body_source == "text/html";
+    let body = part.map_or_else(String::new, |p| {
+        if html_converted {
+            html_to_text(&p.text())
+        } else {
+            p.text()
+        }
+    });
+
+    let normalized_body = normalize_whitespace(&body);
+    let body = if strip {
+        normalize_whitespace(&strip_quotes(&body))
+    } else {
+        normalized_body.clone()
+    };
+    let quotes_stripped = body != normalized_body;
+
+    let mut text = String::new();
+    if include_subject {
+        if let Some(subject) = msg.decoded_header("Subject") {
+            let subject = normalize_whitespace(&subject);
+            if !subject.is_empty() {
+                text.push_str(&subject);
+                text.push_str("\n\n");
+            }
+        }
+    }
+    text.push_str(&body);
+    let text = text.trim_end().to_string();
+
+    let original_chars = text.chars().count();
+    let mut cut: &str = &text;
+    if let Some(max_chars) = max_chars {
+        cut = truncate_chars(cut, max_chars);
+    }
+    if let Some(max_tokens) = max_tokens {
+        cut = truncate(cut, max_tokens, model).trim_end();
+    }
+    let truncated = cut.len() < text.len();
+    EmbeddingText {
+        token_count: count(cut, model, None),
+        text: cut.to_string(),
+        body_source,
+        html_converted,
+        quotes_stripped,
+        truncated,
+        original_chars,
+    }
+}
+
+/// Turn a raw message into embedding-ready text in one call (replaces five round-trips)
+///
+/// Selects the body (text/plain, else HTML converted to text), strips quoted
+/// replies and signatures, normalizes whitespace, optionally prefixes the
+/// subject, and truncates to a character and/or token budget.
+///
+/// # Arguments
+/// * `raw_bytes` - Complete RFC 822 message
+/// * `strip_quotes` - Remove quoted replies and signatures (default true)
+/// * `include_subject` - Put the subject on the first line (default true)
+/// * `max_chars` - Optional character limit (cut on a word boundary)
+/// * `max_tokens` - Optional token limit for `model`
+/// * `model` - Tokenizer used for `max_tokens` and `token_count` (default "cl100k")
+///
+/// # Returns
+/// * `EmbeddingText` with the text and provenance flags (`body_source`,
+///   `html_converted`, `quotes_stripped`, `truncated`, `original_chars`, `token_count`)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import prepare_for_embedding
+/// prepared = prepare_for_embedding(raw, max_tokens=512)
+/// vector = embed(prepared.text)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    raw_bytes,
+    strip_quotes=true,
+    include_subject=true,
+    max_chars=None,
+    max_tokens=None,
+    model="cl100k"
+))]
+pub fn prepare_for_embedding(
+    raw_bytes: &[u8],
+    strip_quotes: bool,
+    include_subject: bool,
+    max_chars: Option<usize>,
+    max_tokens: Option<usize>,
+    model: &str,
+) -> PyResult<EmbeddingText> {
+    let model = Model::parse(model)?;
+    Ok(prepare(raw_bytes, strip_quotes, include_subject, max_chars, max_tokens, model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_html_body() {
+        let raw = b"Subject:  Weekly   update\n\
+Content-Type: text/html\n\
+\n\
+<p>Shipped  the\xc2\xa0release.</p><p>Next: docs.</p>\n\
+<blockquote>&gt; old text</blockquote>";
+        let prepared = prepare_for_embedding(raw, true, true, None, None, "cl100k").unwrap();
+        assert_eq!(prepared.body_source, "text/html");
+        assert!(prepared.html_converted);
+        assert!(prepared.quotes_stripped);
+        assert!(!prepared.truncated);
+        assert_eq!(prepared.text, "Weekly update\n\nShipped the release.\n\nNext: docs.");
+    }
+
+    #[test]
+    fn test_prepare_truncation() {
+        let raw = b"Subject: s\n\nalpha beta gamma delta epsilon\n\n\n\n-- \nsig\n";
+        let kept = prepare_for_embedding(raw, false, false, None, None, "cl100k").unwrap();
+        assert_eq!(kept.text, "alpha beta gamma delta epsilon\n\n--\nsig");
+        assert!(!kept.quotes_stripped);
+
+        let cut = prepare_for_embedding(raw, true, false, Some(13), None, "cl100k").unwrap();
+        assert_eq!(cut.text, "alpha beta");
+        assert!(cut.truncated);
+        assert_eq!(cut.original_chars, 30);
+
+        let by_tokens = prepare_for_embedding(raw, true, false, None, Some(3), "cl100k").unwrap();
+        assert_eq!(by_tokens.text, "alpha beta gamma");
+        assert_eq!(by_tokens.token_count, 3);
+        assert_eq!(normalize_whitespace("\u{200b}\n a \t b \n\n\n c"), "a b\n\nc");
+    }
+}
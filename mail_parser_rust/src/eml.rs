@@ -0,0 +1,311 @@
+//! Export of an mbox as one `.eml` file per message.
+//!
+//! File names come from a template over a few header-derived fields. Every
+//! field is passed through [`sanitize_component`], so a subject full of
+//! slashes or a device name cannot escape `out_dir`, and names that still
+//! collide (case-insensitively, as on Windows and macOS) get a `_2`, `_3`,
+//! ... suffix in archive order, which keeps reruns deterministic.
+
+use crate::date::{format_utc, parse_date};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{address_list, strip_envelope, Message};
+use crate::output::{Compression, Output, OutputReport};
+use crate::paths::sanitize_component;
+use crate::progress::Progress;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::io::Write as _;
+use std::path::Path;
+
+/// Default `naming_template`
+pub const DEFAULT_TEMPLATE: &str = "{index}_{date}_{sender}_{subject}";
+
+/// Longest value one field contributes to a name, in bytes; leaves room for
+/// the other fields and the collision suffix within a path component
+const MAX_FIELD_BYTES: usize = 60;
+
+/// Placeholders a template may use
+const FIELDS: &[&str] = &["index", "date", "sender", "subject", "message_id"];
+
+/// A parsed `naming_template`
+#[derive(Debug, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(&'static str),
+}
+
+fn parse_template(template: &str) -> PyResult<Vec<Piece>> {
+    let invalid = |reason: String| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Invalid naming_template {template:?}: {reason}"
+        ))
+    };
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            pieces.push(Piece::Text(rest[..open].to_string()));
+        }
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| invalid("unclosed '{'".to_string()))?;
+        let name = &rest[open + 1..open + close];
+        let field = FIELDS.iter().find(|f| **f == name).ok_or_else(|| {
+            invalid(format!("unknown field {{{name}}} (expected one of {})", FIELDS.join(", ")))
+        })?;
+        pieces.push(Piece::Field(field));
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest.to_string()));
+    }
+    if !pieces.iter().any(|p| matches!(p, Piece::Field(_))) {
+        return Err(invalid("no field placeholder".to_string()));
+    }
+    Ok(pieces)
+}
+
+/// `value` sanitized and cut to [`MAX_FIELD_BYTES`] on a character boundary
+fn field_value(value: &str) -> String {
+    let mut value = sanitize_component(value);
+    if value.len() > MAX_FIELD_BYTES {
+        let mut end = MAX_FIELD_BYTES;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+    }
+    value
+}
+
+/// File stem for message `index` (0-based) according to `pieces`
+fn render_name(pieces: &[Piece], raw: &[u8], index: usize) -> String {
+    let msg = Message::parse(raw);
+    let mut name = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => name.push_str(text),
+            Piece::Field(field) => {
+                let value = match *field {
+                    "index" => format!("{:06}", index + 1),
+                    "date" => msg.header("Date").and_then(parse_date).map_or_else(
+                        || "undated".to_string(),
+                        |(epoch, _)| format_utc(epoch).replace('T', "_").replace([':', 'Z'], ""),
+                    ),
+                    "sender" => msg
+                        .decoded_header("From")
+                        .and_then(|v| address_list(&v).into_iter().next())
+                        .map_or_else(|| "unknown".to_string(), |(_, address)| address),
+                    "subject" => msg
+                        .decoded_header("Subject")
+                        .filter(|s| !s.trim().is_empty())
+                        .unwrap_or_else(|| "no subject".to_string()),
+                    _ => msg
+                        .header("Message-ID")
+                        .map(|id| id.trim().trim_matches(['<', '>']).to_string())
+                        .unwrap_or_default(),
+                };
+                name.push_str(&field_value(&value));
+            },
+        }
+    }
+    let name = sanitize_component(&name);
+    if name.is_empty() {
+        "message".to_string()
+    } else {
+        name
+    }
+}
+
+/// Make `stems` unique ignoring case by suffixing `_2`, `_3`, ... to later
+/// duplicates, and append the `.eml` extension
+fn unique_names(stems: Vec<String>) -> Vec<String> {
+    let mut taken: HashSet<String> = HashSet::with_capacity(stems.len());
+    stems
+        .into_iter()
+        .map(|stem| {
+            let mut name = format!("{stem}.eml");
+            let mut n = 1;
+            while !taken.insert(name.to_lowercase()) {
+                n += 1;
+                name = format!("{stem}_{n}.eml");
+            }
+            name
+        })
+        .collect()
+}
+
+/// Split an mbox into one RFC 822 `.eml` file per message
+///
+/// Messages are named and written by parallel workers. Each file holds the
+/// message exactly as stored, minus the mbox `From ` envelope line.
+///
+/// # Arguments
+/// * `path` - Path to the mbox file
+/// * `out_dir` - Output directory (created if missing)
+/// * `naming_template` - File name pattern; placeholders `{index}` (1-based,
+///   zero-padded), `{date}` (UTC `YYYY-MM-DD_HHMMSS` or "undated"),
+///   `{sender}` (From address), `{subject}` and `{message_id}` (default
+///   "{index}_{date}_{sender}_{subject}"). Each value is sanitized with
+///   `sanitize_filename_fast` rules and cut to 60 bytes; duplicates get a
+///   `_2`, `_3`, ... suffix
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` with message, file and byte totals
+///
+/// # Raises
+/// * `ValueError` for an unknown placeholder or a template without any
+///   placeholder
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import split_mbox_to_eml
+/// report = split_mbox_to_eml("inbox.mbox", "eml/", "{date}_{subject}")
+/// print(report.example_filenames[:3])
+/// ```
+#[pyfunction]
+#[pyo3(signature = (path, out_dir, naming_template=DEFAULT_TEMPLATE, from_line_mode="standard", dry_run=false, fsync="file", progress=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn split_mbox_to_eml(
+    py: Python<'_>,
+    path: &str,
+    out_dir: &str,
+    naming_template: &str,
+    from_line_mode: &str,
+    dry_run: bool,
+    fsync: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        let pieces = parse_template(naming_template)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        // Messages are written verbatim, never compressed by extension
+        let output =
+            Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+        let out_dir = Path::new(out_dir);
+        let io_error = |path: &Path, e: &std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to write {}: {e}",
+                path.display()
+            ))
+        };
+        if !output.is_dry_run() {
+            crate::paths::create_dir_all(out_dir).map_err(|e| io_error(out_dir, &e))?;
+        }
+        let mmap = map_file(path)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        let stems: Vec<String> = spans
+            .par_iter()
+            .enumerate()
+            .map(|(index, span)| render_name(&pieces, &data[span.clone()], index))
+            .collect();
+        let names = unique_names(stems);
+        spans
+            .par_iter()
+            .zip(names.par_iter())
+            .try_for_each(|(span, name)| -> PyResult<()> {
+                if !progress.message(span) {
+                    return Ok(());
+                }
+                let target = out_dir.join(name);
+                let attempt = || -> std::io::Result<()> {
+                    let mut file = output.create(&target)?;
+                    file.write_all(strip_envelope(&data[span.clone()]))?;
+                    file.finish().map(|_| ())
+                };
+                attempt().map_err(|e| io_error(&target, &e))?;
+                output.note_message();
+                Ok(())
+            })?;
+        progress.check("split_mbox_to_eml")?;
+        mmap.verify(&mode)?;
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(
+            parse_template("x{date}-{subject}").unwrap(),
+            vec![
+                Piece::Text("x".into()),
+                Piece::Field("date"),
+                Piece::Text("-".into()),
+                Piece::Field("subject")
+            ]
+        );
+        assert!(parse_template("{nope}").is_err());
+        assert!(parse_template("{date").is_err());
+        assert!(parse_template("static").is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        let pieces = parse_template(DEFAULT_TEMPLATE).unwrap();
+        let raw = b"From a@b Mon Jan  1 00:00:00 2024\nFrom: Al <al@example.com>\n\
+            Date: Tue, 2 Jan 2024 03:04:05 +0100\nSubject: Re: a/b <c>?\n\nbody\n";
+        assert_eq!(
+            render_name(&pieces, raw, 4),
+            "000005_2024-01-02_020405_al@example.com_Re_ a_b _c__"
+        );
+        let bare = parse_template("{subject}").unwrap();
+        assert_eq!(render_name(&bare, b"\n\nbody\n", 0), "no subject");
+        assert_eq!(render_name(&bare, b"Subject: CON\n\nx\n", 0), "_CON");
+        let long = format!("Subject: {}\n\nx\n", "\u{e9}".repeat(100));
+        assert_eq!(render_name(&bare, long.as_bytes(), 0).len(), MAX_FIELD_BYTES);
+
+        assert_eq!(
+            unique_names(vec!["a".into(), "A".into(), "a".into(), "a_2".into()]),
+            vec!["a.eml", "A_2.eml", "a_3.eml", "a_2_2.eml"]
+        );
+    }
+
+    #[test]
+    fn test_split_mbox_to_eml() {
+        let dir = std::env::temp_dir().join(format!("mpr_eml_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("in.mbox");
+        std::fs::write(
+            &src,
+            "From a@example.com Mon Jan  1 00:00:00 2024\nSubject: Hello\n\none\n\n\
+             From a@example.com Mon Jan  1 00:00:00 2024\nSubject: hello\n\ntwo\n",
+        )
+        .unwrap();
+        let out = dir.join("eml");
+        let (src, out_str) = (src.to_str().unwrap(), out.to_str().unwrap());
+        let run = |dry_run| {
+            crate::with_py(|py| {
+                split_mbox_to_eml(py, src, out_str, "{subject}", "standard", dry_run, "none", None)
+            })
+            .unwrap()
+        };
+        let dry = run(true);
+        assert_eq!(dry.message_count, 2);
+        assert!(!out.exists());
+
+        let report = run(false);
+        assert_eq!(report.file_count, 2);
+        assert_eq!(
+            std::fs::read_to_string(out.join("Hello.eml")).unwrap(),
+            "Subject: Hello\n\none\n\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("hello_2.eml")).unwrap(),
+            "Subject: hello\n\ntwo\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
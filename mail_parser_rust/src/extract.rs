@@ -0,0 +1,483 @@
+//! Single-pass token extraction: built-in kinds plus caller-defined patterns.
+//!
+//! All requested kinds are compiled into one alternation, so a text is scanned
+//! once no matter how many extractors run. Where two kinds could match at the
+//! same position, caller patterns win over built-ins, and built-ins follow
+//! [`BUILTIN_KINDS`] order (or the order requested).
+//!
+//! Extractors registered with `register_extractor` behave like built-in kinds
+//! for the rest of the process. The default extractor (every registered and
+//! built-in kind, no caller patterns) is compiled once and cached until the
+//! registry changes.
+
+use crate::batch::par_map;
+use crate::patterns::compile;
+use crate::{EMAIL_PATTERN, URL_PATTERN};
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Built-in kinds in their default (priority) order
+pub const BUILTIN_KINDS: &[&str] = &["url", "email", "hashtag", "ticket", "invoice"];
+
+/// Hashtag not glued to a preceding word (skips `page#anchor`)
+const HASHTAG: &str = r"\B#[\p{L}_][\p{L}\p{N}_]{0,138}";
+/// JIRA-style issue key, e.g. `ABC-1234`
+const TICKET: &str = r"\b[A-Z][A-Z0-9]{1,9}-[1-9][0-9]{0,6}\b";
+/// Invoice reference introduced by a keyword; the number is the value
+const INVOICE: &str = r"(?i)\b(?:invoice|inv|bill)\s*(?:no\.?|number|num|#)?\s*[:#]?\s*([A-Z0-9][A-Z0-9/-]*[0-9][A-Z0-9]*)\b";
+
+/// Uppercase prefixes that look like ticket keys but are standards names
+const NON_TICKET_PREFIXES: &[&str] = &["UTF", "ISO", "SHA", "AES", "RSA", "CP", "KOI8", "WIN"];
+
+lazy_static! {
+    pub static ref HASHTAG_PATTERN: Regex = compile("extract.hashtag", HASHTAG);
+    pub static ref TICKET_PATTERN: Regex = compile("extract.ticket", TICKET);
+    pub static ref INVOICE_PATTERN: Regex = compile("extract.invoice", INVOICE);
+    pub static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::default());
+}
+
+/// How a registered extractor normalizes its matches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Postprocess {
+    None,
+    Lower,
+}
+
+impl Postprocess {
+    pub fn parse(value: &str) -> PyResult<Self> {
+        match value {
+            "none" => Ok(Self::None),
+            "lower" => Ok(Self::Lower),
+            other => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown postprocess: {other:?} (expected \"lower\" or \"none\")"
+            ))),
+        }
+    }
+
+    fn apply(self, value: &str) -> String {
+        match self {
+            Self::None => value.to_string(),
+            Self::Lower => value.to_lowercase(),
+        }
+    }
+}
+
+/// Process-wide extractors added with `register_extractor`
+#[derive(Default)]
+pub struct Registry {
+    /// (name, pattern, postprocess) in registration order
+    extractors: Vec<(String, String, Postprocess)>,
+    /// Bumped on every change, so a default built from an outdated snapshot
+    /// is not cached
+    generation: u64,
+    /// Compiled default extractor, dropped whenever `extractors` changes
+    default: Option<Arc<TokenExtractor>>,
+}
+
+impl Registry {
+    fn changed(&mut self) {
+        self.generation += 1;
+        self.default = None;
+    }
+}
+
+fn registered() -> (u64, Vec<(String, String, Postprocess)>) {
+    REGISTRY
+        .read()
+        .map(|registry| (registry.generation, registry.extractors.clone()))
+        .unwrap_or_default()
+}
+
+/// The extractor for every registered and built-in kind, compiled on first use
+pub fn default_extractor() -> PyResult<Arc<TokenExtractor>> {
+    if let Some(cached) = REGISTRY.read().ok().and_then(|r| r.default.clone()) {
+        return Ok(cached);
+    }
+    let extractor = Arc::new(TokenExtractor::new(None, &[])?);
+    if let Ok(mut registry) = REGISTRY.write() {
+        if registry.generation == extractor.generation {
+            registry.default = Some(Arc::clone(&extractor));
+        }
+    }
+    Ok(extractor)
+}
+
+fn builtin_pattern(kind: &str) -> Option<&'static str> {
+    match kind {
+        "email" => Some(EMAIL_PATTERN.as_str()),
+        "url" => Some(URL_PATTERN.as_str()),
+        "hashtag" => Some(HASHTAG),
+        "ticket" => Some(TICKET),
+        "invoice" => Some(INVOICE),
+        _ => None,
+    }
+}
+
+fn is_ticket(value: &str) -> bool {
+    value
+        .split('-')
+        .next()
+        .is_some_and(|prefix| !NON_TICKET_PREFIXES.contains(&prefix))
+}
+
+/// Post-processing shared by the combined and single-kind extractors
+fn finish(kind: &str, value: &str) -> Option<String> {
+    match kind {
+        "email" => Some(value.to_lowercase()),
+        "ticket" if !is_ticket(value) => None,
+        "invoice" => Some(value.to_uppercase()),
+        _ => Some(value.to_string()),
+    }
+}
+
+/// A compiled set of extractors sharing one regex
+pub struct TokenExtractor {
+    regex: Regex,
+    /// (kind name, whole-match group, value group, registered postprocess)
+    kinds: Vec<(String, usize, usize, Option<Postprocess>)>,
+    /// Registry generation the extractor was built from
+    generation: u64,
+}
+
+impl TokenExtractor {
+    /// Compile `kinds` (every registered and built-in kind when `None`) plus
+    /// named patterns
+    pub fn new(kinds: Option<Vec<String>>, patterns: &[(String, String)]) -> PyResult<Self> {
+        let value_error = |msg: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(msg);
+        let (generation, registered) = registered();
+        // Caller patterns come first: they are usually more specific
+        let mut sources: Vec<(String, String, Option<Postprocess>)> = Vec::new();
+        for (name, pattern) in patterns {
+            if sources.iter().any(|(existing, _, _)| existing == name) {
+                return Err(value_error(format!("Duplicate extractor name: {name:?}")));
+            }
+            sources.push((name.clone(), pattern.clone(), None));
+        }
+        // Registered extractors rank before built-ins for the same reason
+        let kinds = kinds.unwrap_or_else(|| {
+            registered
+                .iter()
+                .map(|(name, _, _)| name.clone())
+                .chain(BUILTIN_KINDS.iter().map(ToString::to_string))
+                .collect()
+        });
+        for kind in kinds {
+            let found = registered.iter().find(|(name, _, _)| *name == kind);
+            let (pattern, postprocess) = if let Some((_, pattern, postprocess)) = found {
+                (pattern.clone(), Some(*postprocess))
+            } else {
+                let pattern = builtin_pattern(&kind).ok_or_else(|| {
+                    value_error(format!(
+                        "Unknown extractor kind: {kind:?} (expected one of {BUILTIN_KINDS:?} \
+                         or a registered extractor)"
+                    ))
+                })?;
+                (pattern.to_string(), None)
+            };
+            if sources.iter().any(|(existing, _, _)| *existing == kind) {
+                return Err(value_error(format!("Duplicate extractor name: {kind:?}")));
+            }
+            sources.push((kind, pattern, postprocess));
+        }
+
+        // Group numbers follow opening-paren order, so each pattern's own
+        // groups sit directly after its wrapper group
+        let mut combined = Vec::with_capacity(sources.len());
+        let mut layout = Vec::with_capacity(sources.len());
+        let mut next_group = 1;
+        for (name, pattern, postprocess) in sources {
+            let own_groups = Regex::new(&pattern)
+                .map_err(|e| value_error(format!("Invalid pattern for {name:?}: {e}")))?
+                .captures_len()
+                - 1;
+            let value_group = if own_groups > 0 {
+                next_group + 1
+            } else {
+                next_group
+            };
+            layout.push((name, next_group, value_group, postprocess));
+            combined.push(format!("({pattern})"));
+            next_group += 1 + own_groups;
+        }
+        let regex = Regex::new(&combined.join("|"))
+            .map_err(|e| value_error(format!("Failed to combine patterns: {e}")))?;
+        Ok(Self {
+            regex,
+            kinds: layout,
+            generation,
+        })
+    }
+
+    /// Every match in `text`, grouped by kind (every kind present, possibly empty)
+    pub fn extract(&self, text: &str) -> HashMap<String, Vec<String>> {
+        let mut out: HashMap<String, Vec<String>> = self
+            .kinds
+            .iter()
+            .map(|(name, _, _, _)| (name.clone(), Vec::new()))
+            .collect();
+        for caps in self.regex.captures_iter(text) {
+            let Some((name, _, value_group, postprocess)) = self
+                .kinds
+                .iter()
+                .find(|(_, group, _, _)| caps.get(*group).is_some())
+            else {
+                continue;
+            };
+            let value = caps
+                .get(*value_group)
+                .or_else(|| caps.get(0))
+                .map_or("", |m| m.as_str());
+            let value = postprocess.map_or_else(|| finish(name, value), |p| Some(p.apply(value)));
+            if let Some(value) = value {
+                if let Some(values) = out.get_mut(name) {
+                    values.push(value);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn extract_single(pattern: &Regex, kind: &str, text: &str) -> Vec<String> {
+    pattern
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let value = caps.get(1).or_else(|| caps.get(0))?;
+            finish(kind, value.as_str())
+        })
+        .collect()
+}
+
+/// Fast hashtag extraction (`#topic`, not URL fragments like `page#anchor`)
+///
+/// # Arguments
+/// * `text` - Text to search
+///
+/// # Returns
+/// * List of hashtags including the leading `#`
+#[pyfunction]
+pub fn extract_hashtags_fast(text: &str) -> Vec<String> {
+    extract_single(&HASHTAG_PATTERN, "hashtag", text)
+}
+
+/// Fast JIRA/ticket-style ID extraction (`ABC-1234`)
+///
+/// Standards names that share the shape (`UTF-8`, `ISO-8859`, `SHA-256`) are skipped.
+///
+/// # Arguments
+/// * `text` - Text to search
+///
+/// # Returns
+/// * List of ticket IDs
+#[pyfunction]
+pub fn extract_ticket_ids_fast(text: &str) -> Vec<String> {
+    extract_single(&TICKET_PATTERN, "ticket", text)
+}
+
+/// Fast invoice-number extraction (`Invoice #INV-2024-001`, `inv no. 88123`)
+///
+/// # Arguments
+/// * `text` - Text to search
+///
+/// # Returns
+/// * List of invoice numbers (uppercased, without the keyword)
+#[pyfunction]
+pub fn extract_invoice_numbers_fast(text: &str) -> Vec<String> {
+    extract_single(&INVOICE_PATTERN, "invoice", text)
+}
+
+/// Run several extractors over one text in a single regex pass
+///
+/// # Arguments
+/// * `text` - Text to search
+/// * `kinds` - Kinds to run: "url", "email", "hashtag", "ticket", "invoice"
+///   or a registered extractor (default: all registered and built-in kinds)
+/// * `patterns` - Extra named regex patterns; a pattern's first capture group,
+///   if any, is reported instead of the whole match
+///
+/// # Returns
+/// * Dict mapping each kind/pattern name to its matches (in text order)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import extract_tokens_fast
+/// found = extract_tokens_fast(body, patterns={"po": r"PO-(\d{6})"})
+/// print(found["ticket"], found["po"])
+/// ```
+#[pyfunction]
+#[pyo3(signature = (text, kinds=None, patterns=None))]
+pub fn extract_tokens_fast(
+    text: &str,
+    kinds: Option<Vec<String>>,
+    patterns: Option<HashMap<String, String>>,
+) -> PyResult<HashMap<String, Vec<String>>> {
+    Ok(extractor_for(kinds, patterns)?.extract(text))
+}
+
+/// Batch version of [`extract_tokens_fast`], processed in parallel
+///
+/// # Arguments
+/// * `texts` - Texts to search
+/// * `kinds` - Kinds to run (default: all registered and built-in kinds)
+/// * `patterns` - Extra named regex patterns
+///
+/// # Returns
+/// * One dict per text, in input order
+#[pyfunction]
+#[pyo3(signature = (texts, kinds=None, patterns=None))]
+pub fn extract_tokens_batch(
+    py: Python<'_>,
+    texts: Vec<String>,
+    kinds: Option<Vec<String>>,
+    patterns: Option<HashMap<String, String>>,
+) -> PyResult<Vec<HashMap<String, Vec<String>>>> {
+    py.allow_threads(|| {
+        let extractor = extractor_for(kinds, patterns)?;
+        Ok(par_map(texts, |text| extractor.extract(&text)))
+    })
+}
+
+/// The cached default extractor, or a fresh one for a custom selection
+fn extractor_for(
+    kinds: Option<Vec<String>>,
+    patterns: Option<HashMap<String, String>>,
+) -> PyResult<Arc<TokenExtractor>> {
+    let patterns = sorted_patterns(patterns);
+    if kinds.is_none() && patterns.is_empty() {
+        return default_extractor();
+    }
+    Ok(Arc::new(TokenExtractor::new(kinds, &patterns)?))
+}
+
+/// Register a named extractor that runs alongside the built-in kinds
+///
+/// The pattern is validated now and compiled into the shared default
+/// extractor once, instead of once per call. Registered names are accepted
+/// in `kinds` and run by default in `extract_tokens_fast` /
+/// `extract_tokens_batch`, ahead of the built-ins. Registering an existing
+/// name replaces it.
+///
+/// # Arguments
+/// * `name` - Result key (must not be a built-in kind)
+/// * `pattern` - Regex; its first capture group, if any, is the value
+/// * `postprocess` - "none" (default) or "lower" to lowercase matches
+///
+/// # Returns
+/// * Number of registered extractors
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import register_extractor, extract_tokens_batch
+/// register_extractor("order", r"(?i)order\s+#?(\d{6,})")
+/// register_extractor("sku", r"SKU-[A-Z0-9]+", postprocess="lower")
+/// results = extract_tokens_batch(bodies)
+/// print(results[0]["order"], results[0]["sku"])
+/// ```
+#[pyfunction]
+#[pyo3(signature = (name, pattern, postprocess="none"))]
+pub fn register_extractor(name: &str, pattern: &str, postprocess: &str) -> PyResult<usize> {
+    let value_error = |msg: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(msg);
+    let postprocess = Postprocess::parse(postprocess)?;
+    if name.is_empty() || BUILTIN_KINDS.contains(&name) {
+        return Err(value_error(format!("Invalid extractor name: {name:?}")));
+    }
+    Regex::new(pattern).map_err(|e| value_error(format!("Invalid pattern for {name:?}: {e}")))?;
+    let mut registry = REGISTRY
+        .write()
+        .map_err(|_| value_error("Extractor registry is unavailable".to_string()))?;
+    let entry = (name.to_string(), pattern.to_string(), postprocess);
+    match registry
+        .extractors
+        .iter_mut()
+        .find(|(existing, _, _)| existing == name)
+    {
+        Some(slot) => *slot = entry,
+        None => registry.extractors.push(entry),
+    }
+    registry.changed();
+    Ok(registry.extractors.len())
+}
+
+/// Remove a registered extractor
+///
+/// # Returns
+/// * Whether an extractor with that name was registered
+#[pyfunction]
+pub fn unregister_extractor(name: &str) -> bool {
+    let Ok(mut registry) = REGISTRY.write() else {
+        return false;
+    };
+    let before = registry.extractors.len();
+    registry
+        .extractors
+        .retain(|(existing, _, _)| existing != name);
+    let removed = registry.extractors.len() < before;
+    if removed {
+        registry.changed();
+    }
+    removed
+}
+
+/// Caller patterns in name order, so priority does not depend on dict hashing
+fn sorted_patterns(patterns: Option<HashMap<String, String>>) -> Vec<(String, String)> {
+    let mut patterns: Vec<(String, String)> = patterns.unwrap_or_default().into_iter().collect();
+    patterns.sort();
+    patterns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Re PROJ-42 and UTF-8: invoice #inv-2024-007 from Bob@Example.com, \
+see https://example.com/page#anchor #release_1 PO-123456";
+
+    #[test]
+    fn test_single_pass_extraction() {
+        let patterns = vec![("po".to_string(), r"PO-(\d{6})".to_string())];
+        let found = TokenExtractor::new(None, &patterns).unwrap().extract(TEXT);
+        assert_eq!(found["ticket"], vec!["PROJ-42"]);
+        assert_eq!(found["invoice"], vec!["INV-2024-007"]);
+        assert_eq!(found["email"], vec!["bob@example.com"]);
+        assert_eq!(found["url"], vec!["https://example.com/page#anchor"]);
+        assert_eq!(found["hashtag"], vec!["#release_1"]);
+        assert_eq!(found["po"], vec!["123456"]);
+    }
+
+    #[test]
+    fn test_single_kind_extractors() {
+        assert_eq!(extract_ticket_ids_fast(TEXT), vec!["PROJ-42", "PO-123456"]);
+        assert_eq!(extract_hashtags_fast("#a b#c (#d)"), vec!["#a", "#d"]);
+        assert_eq!(extract_invoice_numbers_fast("Invoice No. 88123"), vec!["88123"]);
+        assert!(TokenExtractor::new(Some(vec!["phone".to_string()]), &[]).is_err());
+        let bad = vec![("x".to_string(), "(".to_string())];
+        assert!(TokenExtractor::new(None, &bad).is_err());
+    }
+
+    #[test]
+    fn test_registered_extractor() {
+        // Registration is process-wide: use a name no other test looks for
+        register_extractor("test_order", r"(?i)ORDER\s+#?(\d{6,})", "none").unwrap();
+        register_extractor("test_sku", r"SKU-[A-Z0-9]+", "lower").unwrap();
+        let text = "order #123456 ships with SKU-AB12; ticket ABC-1";
+        let found = extract_tokens_fast(text, None, None).unwrap();
+        assert_eq!(found["test_order"], vec!["123456"]);
+        assert_eq!(found["test_sku"], vec!["sku-ab12"]);
+        assert_eq!(found["ticket"], vec!["ABC-1"]);
+        let only = extract_tokens_fast(text, Some(vec!["test_sku".to_string()]), None).unwrap();
+        assert_eq!(only.len(), 1);
+
+        assert!(register_extractor("email", "x", "none").is_err());
+        assert!(register_extractor("test_bad", "(", "none").is_err());
+        assert!(register_extractor("test_bad", "x", "upper").is_err());
+        assert!(unregister_extractor("test_order"));
+        assert!(unregister_extractor("test_sku"));
+        assert!(!unregister_extractor("test_sku"));
+        assert!(!extract_tokens_fast(text, None, None)
+            .unwrap()
+            .contains_key("test_sku"));
+    }
+}
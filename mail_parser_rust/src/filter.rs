@@ -0,0 +1,426 @@
+//! Boolean row filtering over columnar extraction results.
+//!
+//! Expressions are small: comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`)
+//! between columns and literals (numbers, `"strings"`, `true`, `false`,
+//! `null`), combined with `AND`, `OR`, `NOT` and parentheses. A bare column
+//! is true when its value is truthy (non-zero, non-empty, not null).
+
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// One cell of a metadata column
+#[derive(FromPyObject, Clone, Debug, PartialEq)]
+pub enum Cell {
+    // Bool first: Python `bool` is an `int` subclass
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl Cell {
+    const fn number(&self) -> Option<f64> {
+        match self {
+            #[allow(clippy::cast_precision_loss)]
+            Self::Int(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            Self::Bool(_) | Self::Str(_) => None,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Self::Bool(b) => *b,
+            Self::Int(i) => *i != 0,
+            Self::Float(f) => *f != 0.0,
+            Self::Str(s) => !s.is_empty(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Option<Cell>),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug)]
+enum Operand {
+    Column(usize),
+    Literal(Option<Cell>),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    Not(Box<Self>),
+    Compare(Operand, Op, Operand),
+    Truthy(Operand),
+}
+
+fn syntax_error(msg: &str) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid filter expression: {msg}"))
+}
+
+fn tokenize(expression: &str) -> PyResult<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' | ')' => {
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+                i += 1;
+            },
+            '=' | '!' | '<' | '>' => {
+                let (op, width) = match (c, next) {
+                    ('=', Some('=')) => (Op::Eq, 2),
+                    ('=', _) => (Op::Eq, 1),
+                    ('!', Some('=')) | ('<', Some('>')) => (Op::Ne, 2),
+                    ('<', Some('=')) => (Op::Le, 2),
+                    ('<', _) => (Op::Lt, 1),
+                    ('>', Some('=')) => (Op::Ge, 2),
+                    ('>', _) => (Op::Gt, 1),
+                    _ => return Err(syntax_error(&format!("unexpected {c:?} at {i}"))),
+                };
+                tokens.push(Token::Op(op));
+                i += width;
+            },
+            '"' | '\'' => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(syntax_error(&format!("unterminated string at {i}"))),
+                        Some('\\') if j + 1 < chars.len() => {
+                            value.push(chars[j + 1]);
+                            j += 2;
+                        },
+                        Some(&q) if q == c => break,
+                        Some(&other) => {
+                            value.push(other);
+                            j += 1;
+                        },
+                    }
+                }
+                tokens.push(Token::Literal(Some(Cell::Str(value))));
+                i = j + 1;
+            },
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let cell = text
+                    .parse::<i64>()
+                    .map(Cell::Int)
+                    .or_else(|_| text.parse::<f64>().map(Cell::Float))
+                    .map_err(|_| syntax_error(&format!("bad number {text:?}")))?;
+                tokens.push(Token::Literal(Some(cell)));
+            },
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || "_.".contains(chars[i])) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Literal(Some(Cell::Bool(true))),
+                    "false" => Token::Literal(Some(Cell::Bool(false))),
+                    "null" | "none" => Token::Literal(None),
+                    _ => Token::Ident(word),
+                });
+            },
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                i += 2;
+            },
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            },
+            _ => return Err(syntax_error(&format!("unexpected {c:?} at {i}"))),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser resolving column names to indices
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    columns: &'a [String],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> PyResult<Expr> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> PyResult<Expr> {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> PyResult<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> PyResult<Expr> {
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let inner = self.or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(inner),
+                _ => Err(syntax_error("missing ')'")),
+            };
+        }
+        let left = self.operand()?;
+        if let Some(Token::Op(op)) = self.peek().cloned() {
+            self.pos += 1;
+            return Ok(Expr::Compare(left, op, self.operand()?));
+        }
+        Ok(Expr::Truthy(left))
+    }
+
+    fn operand(&mut self) -> PyResult<Operand> {
+        match self.next() {
+            Some(Token::Literal(cell)) => Ok(Operand::Literal(cell)),
+            Some(Token::Ident(name)) => self
+                .columns
+                .iter()
+                .position(|c| *c == name)
+                .map(Operand::Column)
+                .ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Unknown column in filter expression: {name:?}"
+                    ))
+                }),
+            Some(token) => Err(syntax_error(&format!("unexpected {token:?}"))),
+            None => Err(syntax_error("unexpected end of expression")),
+        }
+    }
+}
+
+/// Parse `expression` against the given column names
+fn parse(expression: &str, columns: &[String]) -> PyResult<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        pos: 0,
+        columns,
+    };
+    let expr = parser.or()?;
+    parser
+        .next()
+        .map_or(Ok(expr), |token| Err(syntax_error(&format!("unexpected {token:?}"))))
+}
+
+fn compare(left: Option<&Cell>, op: Op, right: Option<&Cell>) -> bool {
+    let ordering = match (left, right) {
+        (None, None) => Some(Ordering::Equal),
+        (Some(Cell::Str(a)), Some(Cell::Str(b))) => Some(a.cmp(b)),
+        (Some(Cell::Bool(a)), Some(Cell::Bool(b))) => Some(a.cmp(b)),
+        (Some(a), Some(b)) => a
+            .number()
+            .zip(b.number())
+            .and_then(|(a, b)| a.partial_cmp(&b)),
+        _ => None,
+    };
+    match (op, ordering) {
+        (Op::Ne, None) => true,
+        (_, None) => false,
+        (Op::Eq, Some(o)) => o == Ordering::Equal,
+        (Op::Ne, Some(o)) => o != Ordering::Equal,
+        (Op::Lt, Some(o)) => o == Ordering::Less,
+        (Op::Le, Some(o)) => o != Ordering::Greater,
+        (Op::Gt, Some(o)) => o == Ordering::Greater,
+        (Op::Ge, Some(o)) => o != Ordering::Less,
+    }
+}
+
+fn eval(expr: &Expr, columns: &[Vec<Option<Cell>>], row: usize) -> bool {
+    let value = |operand: &Operand| -> Option<Cell> {
+        match operand {
+            Operand::Column(c) => columns[*c][row].clone(),
+            Operand::Literal(cell) => cell.clone(),
+        }
+    };
+    match expr {
+        Expr::And(a, b) => eval(a, columns, row) && eval(b, columns, row),
+        Expr::Or(a, b) => eval(a, columns, row) || eval(b, columns, row),
+        Expr::Not(inner) => !eval(inner, columns, row),
+        Expr::Compare(a, op, b) => compare(value(a).as_ref(), *op, value(b).as_ref()),
+        Expr::Truthy(a) => value(a).as_ref().is_some_and(Cell::truthy),
+    }
+}
+
+/// Row indices of columnar metadata matching a boolean expression
+///
+/// Evaluates the filter in Rust so streaming pipelines do not need a pandas
+/// round-trip just to select rows. Numbers compare numerically (ints and
+/// floats mix), strings compare exactly, and comparing mismatched types or
+/// null with anything but `== null` / `!= null` is false (`!=` is true).
+///
+/// # Arguments
+/// * `metadata_columns` - Dict of column name to equally long lists of
+///   bool / int / float / str / None values
+/// * `expression` - Filter such as
+///   `url_count > 0 AND (from_domain == "example.com" OR NOT has_attachment)`
+///
+/// # Returns
+/// * Matching row indices in ascending order
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import filter_indices
+/// columns = {"url_count": [0, 3, 1], "from_domain": ["a.org", "example.com", "b.org"]}
+/// filter_indices(columns, 'url_count > 0 AND from_domain == "example.com"')
+/// # Returns: [1]
+/// ```
+#[pyfunction]
+pub fn filter_indices(
+    py: Python<'_>,
+    metadata_columns: HashMap<String, Vec<Option<Cell>>>,
+    expression: &str,
+) -> PyResult<Vec<usize>> {
+    py.allow_threads(|| {
+        let (names, columns): (Vec<String>, Vec<Vec<Option<Cell>>>) =
+            metadata_columns.into_iter().unzip();
+        let rows = columns.first().map_or(0, Vec::len);
+        if let Some((name, column)) = names.iter().zip(&columns).find(|(_, c)| c.len() != rows) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Column {name:?} has {} rows, expected {rows}",
+                column.len()
+            )));
+        }
+        let expr = parse(expression, &names)?;
+        Ok((0..rows)
+            .into_par_iter()
+            .filter(|&row| eval(&expr, &columns, row))
+            .collect())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> HashMap<String, Vec<Option<Cell>>> {
+        let mut columns = HashMap::new();
+        columns.insert(
+            "url_count".to_string(),
+            vec![
+                Some(Cell::Int(0)),
+                Some(Cell::Int(3)),
+                Some(Cell::Int(1)),
+                None,
+            ],
+        );
+        columns.insert(
+            "from_domain".to_string(),
+            ["a.org", "example.com", "example.com", "b.org"]
+                .iter()
+                .map(|d| Some(Cell::Str((*d).to_string())))
+                .collect(),
+        );
+        columns.insert(
+            "spam_score".to_string(),
+            vec![
+                Some(Cell::Float(0.5)),
+                Some(Cell::Float(7.2)),
+                None,
+                Some(Cell::Float(-1.0)),
+            ],
+        );
+        columns.insert(
+            "has_attachment".to_string(),
+            vec![
+                Some(Cell::Bool(true)),
+                Some(Cell::Bool(false)),
+                Some(Cell::Bool(true)),
+                None,
+            ],
+        );
+        columns
+    }
+
+    #[test]
+    fn test_filter_indices() {
+        crate::with_py(|py| {
+            let run = |expression: &str| filter_indices(py, columns(), expression).unwrap();
+            assert_eq!(run(r#"url_count > 0 AND from_domain == "example.com""#), [1, 2]);
+            assert_eq!(run("spam_score >= 5 or has_attachment"), [0, 1, 2]);
+            assert_eq!(run("NOT (url_count > 0) && spam_score < 1"), [0, 3]);
+            assert_eq!(run("spam_score == null"), [2]);
+            assert_eq!(run("url_count != null AND url_count <= 1"), [0, 2]);
+            assert_eq!(run("has_attachment == false"), [1]);
+            assert_eq!(run("spam_score > -0.5 and from_domain != 'b.org'"), [0, 1]);
+
+            for bad in [
+                "url_count >",
+                "(url_count > 0",
+                "nope == 1",
+                "url_count ~ 1",
+                "'open",
+            ] {
+                assert!(filter_indices(py, columns(), bad).is_err(), "{bad}");
+            }
+            let mut ragged = columns();
+            ragged.insert("short".to_string(), vec![None]);
+            assert!(filter_indices(py, ragged, "short").is_err());
+            assert!(filter_indices(py, HashMap::new(), "true")
+                .unwrap()
+                .is_empty());
+        });
+    }
+}
@@ -0,0 +1,351 @@
+//! Rule-based folder taxonomy suggestions for flat archives.
+//!
+//! Every message is assigned by the first rule that applies: mailing list
+//! (`List-Id`), automated sender (no-reply style addresses, `Precedence:
+//! bulk`, `Auto-Submitted`), then sender organisation (registrable domain) or,
+//! for freemail domains, the individual sender. Inside each of those clusters
+//! a recurring numbered subject ("Invoice 1043", "Invoice 1051") becomes a
+//! subfolder. Clusters below the size threshold are pooled in `Unsorted`.
+
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{first_address, Message};
+use crate::patterns::compile;
+use crate::progress::Progress;
+use crate::thread::normalize_subject;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Example subjects listed per suggested folder
+const MAX_EXAMPLES: usize = 3;
+
+/// Consumer mail providers: senders there are people, not organisations
+const FREEMAIL_DOMAINS: &[&str] = &[
+    "aol.com",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "googlemail.com",
+    "hotmail.com",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "web.de",
+    "yahoo.com",
+    "yandex.ru",
+];
+
+/// Local parts used by machines rather than people
+const AUTOMATED_LOCAL_PARTS: &[&str] = &[
+    "alert",
+    "alerts",
+    "bounce",
+    "bounces",
+    "daemon",
+    "do-not-reply",
+    "donotreply",
+    "mailer-daemon",
+    "newsletter",
+    "no-reply",
+    "noreply",
+    "notification",
+    "notifications",
+    "notify",
+    "postmaster",
+];
+
+lazy_static! {
+    /// Numbers, dates and IDs that vary between otherwise identical subjects
+    pub static ref NUMBER_RUN: Regex = compile("folders.number_run", r"\d(?:[\d,./:-]*\d)?");
+}
+
+/// One proposed folder
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[pyclass]
+pub struct FolderSuggestion {
+    /// Folder path, e.g. `Lists/dev.example.org` or `Organizations/acme.com/invoice #`
+    #[pyo3(get)]
+    pub folder: String,
+    /// Rule that produced it: "list-id", "automated", "domain", "sender",
+    /// "subject" or "unsorted"
+    #[pyo3(get)]
+    pub rule: String,
+    /// Messages that would be filed here (each message is counted once)
+    #[pyo3(get)]
+    pub message_count: usize,
+    /// A few subjects from the folder
+    #[pyo3(get)]
+    pub example_subjects: Vec<String>,
+}
+
+/// The facts about one message the rules look at
+struct Features {
+    parent: (String, &'static str),
+    template: Option<String>,
+    subject: String,
+}
+
+/// Registrable part of a host name (`mail.eu.acme.co.uk` -> `acme.co.uk`)
+pub fn base_domain(host: &str) -> String {
+    let labels: Vec<&str> = host.trim_end_matches('.').split('.').collect();
+    // Two-letter country TLDs usually register under a short second level
+    let keep = match labels.as_slice() {
+        [.., second, tld] if tld.len() == 2 && second.len() <= 3 => 3,
+        _ => 2,
+    };
+    labels[labels.len().saturating_sub(keep)..].join(".")
+}
+
+/// Subject with its varying numbers replaced by `#`, if it has any
+fn subject_template(subject: &str) -> Option<String> {
+    let normalized = normalize_subject(subject);
+    NUMBER_RUN
+        .is_match(&normalized)
+        .then(|| NUMBER_RUN.replace_all(&normalized, "#").into_owned())
+}
+
+/// Whether a message comes from a machine (no-reply style address or
+/// bulk/auto-submitted headers)
+pub fn is_automated(msg: &Message<'_>, address: &str) -> bool {
+    let local = address.split('@').next().unwrap_or("");
+    AUTOMATED_LOCAL_PARTS.contains(&local)
+        || msg
+            .header("Precedence")
+            .is_some_and(|p| p.trim().eq_ignore_ascii_case("bulk"))
+        || msg
+            .header("Auto-Submitted")
+            .is_some_and(|v| !v.trim().eq_ignore_ascii_case("no"))
+}
+
+/// Label of a List-Id header: the `<list.id>` part, else the whole value
+fn list_label(value: &str) -> Option<String> {
+    let id = value
+        .rsplit_once('<')
+        .and_then(|(_, rest)| rest.split('>').next())
+        .unwrap_or(value)
+        .trim()
+        .to_lowercase();
+    (!id.is_empty()).then_some(id)
+}
+
+impl Features {
+    fn parse(raw: &[u8]) -> Self {
+        let msg = Message::parse(raw);
+        let subject = msg.decoded_header("Subject").unwrap_or_default();
+        let address = msg
+            .decoded_header("From")
+            .as_deref()
+            .and_then(first_address);
+        let parent = match (msg.header("List-Id").and_then(list_label), address) {
+            (Some(list), _) => (format!("Lists/{list}"), "list-id"),
+            (None, Some(address)) => {
+                let domain = base_domain(address.rsplit('@').next().unwrap_or(""));
+                if is_automated(&msg, &address) {
+                    (format!("Notifications/{domain}"), "automated")
+                } else if FREEMAIL_DOMAINS.contains(&domain.as_str()) {
+                    (format!("People/{address}"), "sender")
+                } else {
+                    (format!("Organizations/{domain}"), "domain")
+                }
+            },
+            (None, None) => ("Unsorted".to_string(), "unsorted"),
+        };
+        Self {
+            parent,
+            template: subject_template(&subject),
+            subject: subject.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+/// Accumulates the messages filed under one folder
+#[derive(Default)]
+struct Bucket {
+    rule: &'static str,
+    count: usize,
+    examples: Vec<String>,
+}
+
+impl Bucket {
+    fn add(&mut self, rule: &'static str, subject: &str) {
+        self.rule = rule;
+        self.count += 1;
+        if self.examples.len() < MAX_EXAMPLES
+            && !subject.is_empty()
+            && !self.examples.iter().any(|e| e == subject)
+        {
+            self.examples.push(subject.to_string());
+        }
+    }
+}
+
+/// Assign every message to one folder and return the folders, largest first
+fn suggest(messages: &[Features], min_messages: usize) -> Vec<FolderSuggestion> {
+    // Ordered so "Unsorted" examples do not depend on hashing
+    let mut parents: BTreeMap<&str, Vec<&Features>> = BTreeMap::new();
+    for message in messages {
+        parents.entry(&message.parent.0).or_default().push(message);
+    }
+
+    let mut buckets: HashMap<String, Bucket> = HashMap::new();
+    for (folder, members) in parents {
+        if members.len() < min_messages || folder == "Unsorted" {
+            let bucket = buckets.entry("Unsorted".to_string()).or_default();
+            for message in members {
+                bucket.add("unsorted", &message.subject);
+            }
+            continue;
+        }
+        let mut templates: HashMap<&str, usize> = HashMap::new();
+        for message in &members {
+            if let Some(template) = &message.template {
+                *templates.entry(template).or_default() += 1;
+            }
+        }
+        for message in members {
+            let sub = message
+                .template
+                .as_deref()
+                .filter(|t| templates.get(t).is_some_and(|&n| n >= min_messages));
+            match sub {
+                Some(template) => buckets
+                    .entry(format!("{folder}/{template}"))
+                    .or_default()
+                    .add("subject", &message.subject),
+                None => buckets
+                    .entry(folder.to_string())
+                    .or_default()
+                    .add(message.parent.1, &message.subject),
+            }
+        }
+    }
+
+    let mut suggestions: Vec<FolderSuggestion> = buckets
+        .into_iter()
+        .map(|(folder, bucket)| FolderSuggestion {
+            folder,
+            rule: bucket.rule.to_string(),
+            message_count: bucket.count,
+            example_subjects: bucket.examples,
+        })
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.message_count
+            .cmp(&a.message_count)
+            .then_with(|| a.folder.cmp(&b.folder))
+    });
+    suggestions
+}
+
+/// Propose a folder taxonomy for a flat mbox archive
+///
+/// Messages are clustered by mailing list (`List-Id`), automated sender,
+/// sender organisation (registrable domain) or individual freemail sender,
+/// and recurring numbered subjects within a cluster ("Invoice 1043") become
+/// subfolders. Each message is counted in exactly one folder; clusters with
+/// fewer than `min_messages` messages are pooled in "Unsorted".
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `min_messages` - Smallest cluster that gets its own folder (default 5)
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * List of `FolderSuggestion` (`folder`, `rule`, `message_count`,
+///   `example_subjects`), largest first
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import suggest_folders
+/// for s in suggest_folders("archive.mbox", min_messages=20):
+///     print(f"{s.message_count:6}  {s.folder}")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, min_messages=5, from_line_mode="standard", progress=None))]
+pub fn suggest_folders(
+    py: Python<'_>,
+    mbox_path: &str,
+    min_messages: usize,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<FolderSuggestion>> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let messages: Vec<Features> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| Features::parse(&data[span]))
+            .collect();
+        progress.check("suggest_folders")?;
+        mmap.verify(&mode)?;
+        Ok(suggest(&messages, min_messages.max(1)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(from: &str, subject: &str, extra: &str) -> Features {
+        let raw = format!("From: {from}\nSubject: {subject}\n{extra}\nbody\n");
+        Features::parse(raw.as_bytes())
+    }
+
+    #[test]
+    fn test_base_domain_and_template() {
+        assert_eq!(base_domain("mail.eu.acme.co.uk"), "acme.co.uk");
+        assert_eq!(base_domain("billing.acme.com"), "acme.com");
+        assert_eq!(base_domain("localhost"), "localhost");
+        assert_eq!(subject_template("Re: Invoice 2024-0042 ready").unwrap(), "invoice # ready");
+        assert!(subject_template("Lunch?").is_none());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let mut messages = Vec::new();
+        for n in 0..3 {
+            messages.push(message("billing@acme.com", &format!("Invoice {n}"), ""));
+            messages.push(message("Ann <ann@sales.acme.com>", "Quarterly plan", ""));
+            messages.push(message("x@y.org", "Patch", "List-Id: Dev list <dev.y.org>"));
+            messages.push(message("noreply@shop.com", "Your order", ""));
+        }
+        messages.push(message("pal@gmail.com", "Hi", ""));
+
+        let found = suggest(&messages, 3);
+        let summary: Vec<(&str, &str, usize)> = found
+            .iter()
+            .map(|s| (s.folder.as_str(), s.rule.as_str(), s.message_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Lists/dev.y.org", "list-id", 3),
+                ("Notifications/shop.com", "automated", 3),
+                ("Organizations/acme.com", "domain", 3),
+                ("Organizations/acme.com/invoice #", "subject", 3),
+                ("Unsorted", "unsorted", 1),
+            ]
+        );
+        assert_eq!(found[3].example_subjects, vec!["Invoice 0", "Invoice 1", "Invoice 2"]);
+    }
+}
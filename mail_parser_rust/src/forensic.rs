@@ -0,0 +1,412 @@
+//! Byte-exact message extraction with a provenance record.
+//!
+//! Evidence copies must be the original bytes, not a re-encoded rendering:
+//! [`extract_original`] copies a span of the archive verbatim and writes a
+//! JSON sidecar describing where it came from, so the copy can later be
+//! checked against the source.
+//!
+//! Re-exports of the same message rarely keep its bytes: transfer encodings,
+//! line endings, folding and MIME boundaries all change. [`canonical_text`]
+//! renders what such tools preserve (key headers, the decoded body and the
+//! attachment contents as digests) in one fixed layout, so its hash matches
+//! across copies of a message where the raw hash does not.
+
+use crate::address::parse_addresses;
+use crate::date::{format_utc, parse_date};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::output::{Compression, Output};
+use crate::redact::sha256_hex;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where an extracted message came from
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct Provenance {
+    /// Absolute path of the source archive
+    #[pyo3(get)]
+    pub source: String,
+    /// Size of the source archive in bytes at extraction time
+    #[pyo3(get)]
+    pub source_size: u64,
+    /// SHA-256 of the whole source archive
+    #[pyo3(get)]
+    pub source_sha256: String,
+    /// Byte offset of the message in the source
+    #[pyo3(get)]
+    pub offset: u64,
+    /// Length of the message in bytes
+    #[pyo3(get)]
+    pub length: u64,
+    /// SHA-256 of the extracted bytes
+    #[pyo3(get)]
+    pub sha256: String,
+    /// Stable message UID (see `compute_message_uid`)
+    #[pyo3(get)]
+    pub uid: String,
+    /// Path the bytes were written to
+    #[pyo3(get)]
+    pub out_path: String,
+    /// Path of the JSON provenance sidecar
+    #[pyo3(get)]
+    pub sidecar_path: String,
+    /// Extraction time, `YYYY-MM-DDTHH:MM:SSZ`
+    #[pyo3(get)]
+    pub extracted_at: String,
+    /// `mail_parser_rust` version that performed the extraction
+    #[pyo3(get)]
+    pub tool_version: String,
+}
+
+#[pymethods]
+impl Provenance {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// The record as JSON, exactly as written to the sidecar
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(self).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Serialization error: {e}"))
+        })
+    }
+}
+
+/// Copy the original bytes of one message out of an archive, with provenance
+///
+/// The bytes at `offset..offset + length` are written unchanged (no
+/// decoding, line-ending conversion or compression, whatever the file
+/// extension), and a JSON record with the source path, size and SHA-256, the
+/// span, the SHA-256 of the extracted bytes, the message UID and the UTC
+/// extraction time is written to `<out_path>.provenance.json`.
+///
+/// # Arguments
+/// * `path` - Source archive
+/// * `offset` - Byte offset of the message (e.g. a `redact_mbox` audit `offset`)
+/// * `length` - Length of the message in bytes
+/// * `out_path` - Destination file for the message bytes
+/// * `dry_run` - Compute the record without writing anything
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+///
+/// # Returns
+/// * `Provenance` record (also written next to `out_path`)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import extract_original
+/// record = extract_original("inbox.mbox", 48213, 5120, "exhibit-7.eml")
+/// print(record.sha256, record.sidecar_path)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (path, offset, length, out_path, dry_run=false, fsync="file"))]
+pub fn extract_original(
+    py: Python<'_>,
+    path: &str,
+    offset: u64,
+    length: u64,
+    out_path: &str,
+    dry_run: bool,
+    fsync: &str,
+) -> PyResult<Provenance> {
+    py.allow_threads(|| {
+        let output =
+            Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+        let mmap = map_file(path)?;
+        let data = &mmap[..];
+        let span = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(length).ok())
+            .and_then(|(start, len)| Some(start..start.checked_add(len)?))
+            .filter(|span| span.end <= data.len())
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Span {offset}+{length} is outside {path} ({} bytes)",
+                    data.len()
+                ))
+            })?;
+        let bytes = &data[span];
+
+        let source = std::fs::canonicalize(path)
+            .map_or_else(|_| path.to_string(), |p| p.to_string_lossy().into_owned());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| i64::try_from(d.as_secs()).unwrap_or(i64::MAX));
+        let sidecar_path = format!("{out_path}.provenance.json");
+        let record = Provenance {
+            source,
+            source_size: data.len() as u64,
+            source_sha256: sha256_hex(data),
+            offset,
+            length,
+            sha256: sha256_hex(bytes),
+            uid: message_uid(bytes),
+            out_path: out_path.to_string(),
+            sidecar_path: sidecar_path.clone(),
+            extracted_at: format_utc(now),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let json = record.to_json()?;
+        mmap.verify(&FromLineMode::Standard)?;
+
+        let write = |dest: &str, contents: &[u8]| -> std::io::Result<()> {
+            let mut file = output.create(Path::new(dest))?;
+            file.write_all(contents)?;
+            file.finish().map(|_| ())
+        };
+        write(out_path, bytes)
+            .and_then(|()| write(&sidecar_path, json.as_bytes()))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                    "Failed to write {out_path}: {e}"
+                ))
+            })?;
+        output.note_message();
+        Ok(record)
+    })
+}
+
+/// Revision of the `canonical_text` layout, written on its first line;
+/// bumped whenever the rendering changes (which changes every hash)
+const CANONICAL_FORM: u32 = 1;
+
+/// Headers kept in the canonical form, in output order
+const CANONICAL_HEADERS: &[&str] = &[
+    "From",
+    "Sender",
+    "To",
+    "Cc",
+    "Bcc",
+    "Date",
+    "Subject",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+];
+
+/// Header value in its canonical spelling: addresses as `Name <email>`,
+/// dates in UTC, message IDs without folding, other text decoded with
+/// whitespace runs collapsed
+fn canonical_header(name: &str, value: &str) -> String {
+    let collapse = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match name {
+        "From" | "Sender" | "To" | "Cc" | "Bcc" => {
+            let addresses = parse_addresses(value);
+            if addresses.is_empty() {
+                return collapse(&crate::mime::decode_encoded_words(value));
+            }
+            addresses
+                .iter()
+                .map(|a| {
+                    a.name.as_ref().map_or_else(
+                        || format!("<{}>", a.email),
+                        |name| format!("{name} <{}>", a.email),
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        },
+        "Date" => parse_date(value).map_or_else(|| collapse(value), |(epoch, _)| format_utc(epoch)),
+        "Message-ID" | "In-Reply-To" | "References" => collapse(value),
+        _ => collapse(&crate::mime::decode_encoded_words(value)),
+    }
+}
+
+/// Body text with LF line endings, trailing whitespace removed from each
+/// line and leading/trailing blank lines dropped
+fn canonical_body(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let start = lines
+        .iter()
+        .position(|l| !l.is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|l| !l.is_empty())
+        .map_or(start, |i| i + 1);
+    lines[start..end].join("\n")
+}
+
+/// Deterministic plain-text rendering of a message for content hashing
+pub fn canonical(raw: &[u8]) -> String {
+    let msg = Message::parse(raw);
+    let mut out = format!("Canonical-Form: {CANONICAL_FORM}\n");
+    for &name in CANONICAL_HEADERS {
+        if let Some(value) = msg.header(name) {
+            let _ = writeln!(out, "{name}: {}", canonical_header(name, value));
+        }
+    }
+    out.push('\n');
+    let body = canonical_body(&msg.text_body());
+    if !body.is_empty() {
+        out.push_str(&body);
+        out.push('\n');
+    }
+    for part in msg.parts().iter().filter(|p| p.is_attachment()) {
+        let data = part.decoded();
+        let _ = writeln!(
+            out,
+            "\nAttachment: {} {} {}",
+            sha256_hex(&data),
+            data.len(),
+            part.filename().unwrap_or_default()
+        );
+    }
+    out
+}
+
+/// Render a message in a canonical text form whose hash survives re-export
+///
+/// Copies of one message produced by different tools differ in transfer
+/// encoding, charset labels, line endings, header folding, MIME boundaries
+/// and trace headers, so their raw hashes never match. The canonical form
+/// keeps only what such copies share, in a fixed layout:
+///
+/// * a `Canonical-Form: 1` line naming the layout revision
+/// * From, Sender, To, Cc, Bcc, Date, Subject, Message-ID, In-Reply-To and
+///   References, in that order: addresses as `Name <email>` with lowercased
+///   domains, the date in UTC, text RFC 2047 decoded and whitespace collapsed
+/// * a blank line, then the body text (first text/plain part, else
+///   flattened HTML) with LF endings and trailing whitespace removed
+/// * one `Attachment: <sha256> <size> <filename>` line per attachment,
+///   hashing the decoded content
+///
+/// # Arguments
+/// * `raw_bytes` - Raw RFC 822 message (a leading mbox `From ` line is skipped)
+///
+/// # Returns
+/// * Canonical text; hash its UTF-8 bytes (or use `canonical_sha256`)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import canonical_text
+/// print(canonical_text(raw))
+/// ```
+#[pyfunction]
+pub fn canonical_text(raw_bytes: &[u8]) -> String {
+    canonical(raw_bytes)
+}
+
+/// SHA-256 (hex) of `canonical_text(raw_bytes)`
+///
+/// Equal for copies of a message that differ only in encoding, folding,
+/// line endings or trace headers; use it as the evidence deduplication key.
+///
+/// # Arguments
+/// * `raw_bytes` - Raw RFC 822 message
+///
+/// # Returns
+/// * Lowercase hex digest
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import canonical_sha256
+/// assert canonical_sha256(original) == canonical_sha256(reexported)
+/// ```
+#[pyfunction]
+pub fn canonical_sha256(raw_bytes: &[u8]) -> String {
+    sha256_hex(canonical(raw_bytes).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_original() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_forensic_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let first = "From a Mon Jan  1 00:00:00 2024\r\nSubject: one\r\n\r\nBody\r\n\r\n";
+            let second = "From b Mon Jan  1 00:00:00 2024\nMessage-ID: <b@x>\n\n=E9t=E9\n";
+            let src = dir.join("in.mbox");
+            std::fs::write(&src, format!("{first}{second}")).unwrap();
+            let out = dir.join("exhibit.eml.gz");
+            let out_str = out.to_str().unwrap();
+
+            let record = extract_original(
+                py,
+                src.to_str().unwrap(),
+                first.len() as u64,
+                second.len() as u64,
+                out_str,
+                false,
+                "file",
+            )
+            .unwrap();
+            assert_eq!(std::fs::read(&out).unwrap(), second.as_bytes());
+            assert_eq!(record.sha256, sha256_hex(second.as_bytes()));
+            assert_eq!(record.uid, message_uid(second.as_bytes()));
+            assert_eq!(record.source_size, (first.len() + second.len()) as u64);
+            assert_eq!(record.extracted_at.len(), 20);
+            let sidecar = std::fs::read_to_string(&record.sidecar_path).unwrap();
+            let saved: serde_json::Value = serde_json::from_str(&sidecar).unwrap();
+            assert_eq!(saved["source_sha256"], record.source_sha256.as_str());
+            assert_eq!(saved["offset"], first.len());
+
+            assert!(extract_original(py, src.to_str().unwrap(), 10, 1000, out_str, true, "file")
+                .is_err());
+            assert!(extract_original(
+                py,
+                src.to_str().unwrap(),
+                u64::MAX,
+                1,
+                out_str,
+                true,
+                "file"
+            )
+            .is_err());
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_canonical_survives_reencoding() {
+        let original = "From: =?utf-8?Q?Ren=C3=A9?= <rene@Example.COM>\n\
+                        To: a@x.org,\n b@y.org\n\
+                        Date: Mon, 1 Jan 2024 10:00:00 +0100\n\
+                        Subject: Caf\u{e9}\n\
+                        Message-ID: <m1@x>\n\
+                        Received: from a by b\n\
+                        Content-Type: multipart/mixed; boundary=one\n\n\
+                        --one\nContent-Type: text/plain; charset=utf-8\n\
+                        Content-Transfer-Encoding: quoted-printable\n\n\
+                        Caf=C3=A9 au lait  \nsecond line\n\
+                        --one\nContent-Type: application/pdf; name=a.pdf\n\
+                        Content-Transfer-Encoding: base64\n\nJVBERi0=\n--one--\n";
+        let reexported = "Received: from c by d\r\n\
+                          Subject: =?iso-8859-1?Q?Caf=E9?=\r\n\
+                          From: \"Ren\u{e9}\" <rene@example.com>\r\n\
+                          To: a@x.org, b@y.org\r\n\
+                          Date: Mon, 01 Jan 2024 09:00:00 GMT\r\n\
+                          Message-ID: <m1@x>\r\n\
+                          Content-Type: multipart/mixed; boundary=\"two\"\r\n\r\n\
+                          --two\r\nContent-Type: text/plain; charset=iso-8859-1\r\n\
+                          Content-Transfer-Encoding: 8bit\r\n\r\n";
+        let mut reexported = reexported.as_bytes().to_vec();
+        reexported.extend_from_slice(b"Caf\xe9 au lait\r\nsecond line\r\n\r\n");
+        reexported.extend_from_slice(
+            b"--two\r\nContent-Type: application/octet-stream\r\n\
+              Content-Disposition: attachment; filename=\"a.pdf\"\r\n\r\n%PDF-\r\n--two--\r\n",
+        );
+
+        let text = canonical(original.as_bytes());
+        assert!(text.starts_with("Canonical-Form: 1\nFrom: Ren\u{e9} <rene@example.com>\n"));
+        assert!(text.contains("\nTo: <a@x.org>, <b@y.org>\nDate: 2024-01-01T09:00:00Z\n"));
+        assert!(text.contains("\n\nCaf\u{e9} au lait\nsecond line\n"));
+        assert!(text.ends_with(&format!("Attachment: {} 5 a.pdf\n", sha256_hex(b"%PDF-"))));
+        assert!(!text.contains("Received"));
+        assert_eq!(text, canonical(&reexported));
+        assert_eq!(canonical_sha256(original.as_bytes()), canonical_sha256(&reexported));
+        assert_ne!(
+            canonical_sha256(original.as_bytes()),
+            canonical_sha256(original.replace("second", "2nd").as_bytes())
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Traffic gap detection.
+//!
+//! Messages are counted per UTC day between the first and last dated
+//! message. Two kinds of gap are reported: `silent` runs of days with no
+//! messages at all, and `low` runs of weeks whose volume falls far below the
+//! median of the surrounding half year. Silent gaps inside an otherwise busy
+//! archive usually mean a botched export (a missing month); low periods are
+//! holidays, leave or a changed role.
+
+use crate::date::{format_utc, parse_date};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::progress::Progress;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// Weeks on each side of a week that form its baseline
+const BASELINE_WEEKS: usize = 13;
+
+/// Dates before 1980 are clock errors, not traffic
+const MIN_PLAUSIBLE_EPOCH: i64 = 315_532_800;
+
+/// A period of missing or unusually light traffic
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[pyclass]
+pub struct TrafficGap {
+    /// "silent" (no messages) or "low" (far below baseline)
+    #[pyo3(get)]
+    pub kind: String,
+    /// First day of the period, `YYYY-MM-DD` (UTC)
+    #[pyo3(get)]
+    pub start: String,
+    /// Last day of the period, inclusive
+    #[pyo3(get)]
+    pub end: String,
+    #[pyo3(get)]
+    pub days: usize,
+    /// Messages in the period
+    #[pyo3(get)]
+    pub observed: usize,
+    /// Messages the surrounding baseline predicts for the period
+    #[pyo3(get)]
+    pub expected: f64,
+}
+
+#[pymethods]
+impl TrafficGap {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn gaps_in(counts: &[usize], first_day: i64, min_days: usize, low_ratio: f64) -> Vec<TrafficGap> {
+    // Weekly volume, a trailing partial week scaled up to seven days
+    let weekly: Vec<f64> = counts
+        .chunks(7)
+        .map(|week| week.iter().sum::<usize>() as f64 * 7.0 / week.len() as f64)
+        .collect();
+    let per_day: Vec<f64> = (0..weekly.len())
+        .map(|w| {
+            let lo = w.saturating_sub(BASELINE_WEEKS);
+            let hi = (w + BASELINE_WEEKS + 1).min(weekly.len());
+            let mut around: Vec<f64> = (lo..hi).filter(|&i| i != w).map(|i| weekly[i]).collect();
+            median(&mut around) / 7.0
+        })
+        .collect();
+    let day_name = |offset: usize| {
+        let day = first_day + i64::try_from(offset).unwrap_or(i64::MAX / 86_400);
+        format_utc(day * 86_400)[..10].to_string()
+    };
+    let gap = |kind: &str, from: usize, to: usize| TrafficGap {
+        kind: kind.to_string(),
+        start: day_name(from),
+        end: day_name(to - 1),
+        days: to - from,
+        observed: counts[from..to].iter().sum(),
+        expected: (from..to).map(|d| per_day[d / 7]).sum(),
+    };
+
+    let mut gaps = Vec::new();
+    let mut day = 0;
+    while day < counts.len() {
+        let run = counts[day..].iter().take_while(|&&c| c == 0).count();
+        if run >= min_days.max(1) {
+            gaps.push(gap("silent", day, day + run));
+        }
+        day += run.max(1);
+    }
+    // Weeks without any message belong to silent gaps
+    let low = |w: usize| weekly[w] > 0.0 && weekly[w] < low_ratio * per_day[w] * 7.0;
+    let mut week = 0;
+    while week < weekly.len() {
+        let run = (week..weekly.len()).take_while(|&w| low(w)).count();
+        if run > 0 {
+            let (from, to) = (week * 7, ((week + run) * 7).min(counts.len()));
+            if to - from >= min_days {
+                gaps.push(gap("low", from, to));
+            }
+        }
+        week += run.max(1);
+    }
+    gaps.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.kind.cmp(&b.kind)));
+    gaps
+}
+
+/// Find periods with no or unusually little traffic in an mbox archive
+///
+/// Days run from the first to the last dated message (UTC). A `silent` gap
+/// is at least `min_days` consecutive days without a message. A `low`
+/// period is a run of weeks, together at least `min_days` long, each below
+/// `low_ratio` times the median week of the surrounding half year (weeks
+/// without any message belong to silent gaps instead). Dates before 1980
+/// are ignored as clock errors.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `min_days` - Shortest period reported (default 7)
+/// * `low_ratio` - Share of the baseline below which a week is low (default 0.25)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * List of `TrafficGap` (`kind`, `start`, `end`, `days`, `observed`,
+///   `expected`), in date order
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import detect_gaps
+/// for gap in detect_gaps("export.mbox", min_days=14):
+///     if gap.kind == "silent" and gap.expected > 50:
+///         print(f"missing? {gap.start}..{gap.end}: expected ~{gap.expected:.0f}")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, min_days=7, low_ratio=0.25, from_line_mode="standard", progress=None))]
+pub fn detect_gaps(
+    py: Python<'_>,
+    mbox_path: &str,
+    min_days: usize,
+    low_ratio: f64,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<TrafficGap>> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let days: Vec<i64> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .filter_map(|span| {
+                let msg = Message::parse(&data[span]);
+                let (epoch, _) = parse_date(msg.header("Date")?)?;
+                (epoch >= MIN_PLAUSIBLE_EPOCH).then(|| epoch.div_euclid(86_400))
+            })
+            .collect();
+        progress.check("detect_gaps")?;
+        mmap.verify(&mode)?;
+        let (Some(&first), Some(&last)) = (days.iter().min(), days.iter().max()) else {
+            return Ok(Vec::new());
+        };
+        let span = usize::try_from(last - first + 1).unwrap_or(0);
+        let mut counts = vec![0usize; span];
+        for day in days {
+            if let Ok(offset) = usize::try_from(day - first) {
+                counts[offset] += 1;
+            }
+        }
+        Ok(gaps_in(&counts, first, min_days, low_ratio))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaps() {
+        // 2024-01-01: 10 busy weeks, 3 silent weeks, 4 weeks at a trickle, 10 busy weeks
+        let mut counts = vec![5usize; 70];
+        counts.extend([0; 21]);
+        counts.extend((0..28).map(|d| usize::from(d % 7 == 0)));
+        counts.extend([5; 70]);
+        let gaps = gaps_in(&counts, 19_723, 7, 0.25);
+        assert_eq!(gaps.len(), 2, "{gaps:?}");
+        let silent = &gaps[0];
+        assert_eq!(
+            (silent.kind.as_str(), silent.start.as_str(), silent.end.as_str()),
+            ("silent", "2024-03-11", "2024-03-31")
+        );
+        assert_eq!((silent.days, silent.observed), (21, 0));
+        assert!((silent.expected - 105.0).abs() < 1e-9);
+        let low = &gaps[1];
+        assert_eq!((low.kind.as_str(), low.start.as_str(), low.days), ("low", "2024-04-01", 28));
+        assert_eq!(low.observed, 4);
+        assert!(gaps_in(&counts, 19_723, 30, 0.25).is_empty());
+        assert!(gaps_in(&[], 0, 7, 0.25).is_empty());
+    }
+}
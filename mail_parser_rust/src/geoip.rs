@@ -0,0 +1,353 @@
+//! IP address extraction with caller-registered Geo/ASN enrichment.
+//!
+//! Callers register a lookup once per process: an in-memory CIDR table, one
+//! or more MMDB (GeoIP2/GeoLite2) country/ASN databases, or both. Extractors then
+//! annotate every address inside the parallel Rust pass instead of handing
+//! raw IPs back to Python for a second join.
+
+use crate::batch::par_map;
+use crate::patterns::compile;
+use lazy_static::lazy_static;
+use maxminddb::Reader;
+use pyo3::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+lazy_static! {
+    pub static ref IPV4_PATTERN: Regex = compile(
+        "geoip.ipv4",
+        r"\b(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)(?:\.(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)){3}\b"
+    );
+    /// Loose IPv6 candidates; validated by `Ipv6Addr::from_str`
+    pub static ref IPV6_PATTERN: Regex = compile(
+        "geoip.ipv6",
+        r"(?:\b[0-9A-Fa-f]{1,4}:|::)(?:[0-9A-Fa-f]{0,4}:){1,6}(?:\d{1,3}(?:\.\d{1,3}){3}|[0-9A-Fa-f]{0,4})"
+    );
+    pub static ref LOOKUP: RwLock<Option<Arc<GeoLookup>>> = RwLock::new(None);
+}
+
+/// An address found in text, with whatever the registered lookup knows about it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[pyclass]
+pub struct IpInfo {
+    #[pyo3(get)]
+    pub ip: String,
+    /// False for private, loopback, link-local and other non-routable ranges
+    #[pyo3(get)]
+    pub public: bool,
+    /// ISO 3166 country code
+    #[pyo3(get)]
+    pub country: Option<String>,
+    /// Autonomous system number
+    #[pyo3(get)]
+    pub asn: Option<u32>,
+    /// Autonomous system organization
+    #[pyo3(get)]
+    pub as_org: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct GeoData {
+    country: Option<String>,
+    asn: Option<u32>,
+    as_org: Option<String>,
+}
+
+/// The parts of a GeoIP2/GeoLite2 Country or ASN record we read
+#[derive(Deserialize)]
+struct MmdbRecord {
+    country: Option<MmdbCountry>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MmdbCountry {
+    iso_code: Option<String>,
+}
+
+/// Registered enrichment sources; table entries take precedence over databases
+pub struct GeoLookup {
+    /// Networks grouped by prefix length (IPv4 stored as IPv4-mapped IPv6),
+    /// longest prefix first
+    tables: Vec<(u32, HashMap<u128, GeoData>)>,
+    databases: Vec<Reader<Vec<u8>>>,
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(v4.to_ipv6_mapped()),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+const fn network(addr: u128, prefix: u32) -> u128 {
+    if prefix == 0 {
+        0
+    } else {
+        addr & (u128::MAX << (128 - prefix))
+    }
+}
+
+/// Parse `a.b.c.d/n`, `x::/n` or a bare address into (network, IPv6 prefix length)
+fn parse_cidr(cidr: &str) -> Option<(u128, u32)> {
+    let cidr = cidr.trim();
+    let (addr, prefix) = cidr
+        .split_once('/')
+        .map_or((cidr, None), |(a, p)| (a, Some(p)));
+    let ip: IpAddr = addr.parse().ok()?;
+    let (max, offset) = if ip.is_ipv4() { (32, 96) } else { (128, 0) };
+    let prefix: u32 = prefix.map_or(Some(max), |p| p.parse().ok())?;
+    (prefix <= max).then(|| (network(to_u128(ip), prefix + offset), prefix + offset))
+}
+
+impl GeoLookup {
+    fn lookup(&self, ip: IpAddr) -> GeoData {
+        let addr = to_u128(ip);
+        let mut data = self
+            .tables
+            .iter()
+            .find_map(|(prefix, table)| table.get(&network(addr, *prefix)))
+            .cloned()
+            .unwrap_or_default();
+        for db in &self.databases {
+            if data.country.is_some() && data.asn.is_some() {
+                break;
+            }
+            if let Ok(record) = db.lookup::<MmdbRecord>(ip) {
+                if data.country.is_none() {
+                    data.country = record.country.and_then(|c| c.iso_code);
+                }
+                if data.asn.is_none() {
+                    data.asn = record.autonomous_system_number;
+                    data.as_org = data.as_org.or(record.autonomous_system_organization);
+                }
+            }
+        }
+        data
+    }
+}
+
+/// Whether an address is globally routable
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || (a == 100 && (64..128).contains(&b)) // carrier-grade NAT
+                || a >= 240)
+        },
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // unique local
+                || (first & 0xffc0) == 0xfe80 // link local
+                || first == 0x2001 && v6.segments()[1] == 0x0db8) // documentation
+                && v6.to_ipv4_mapped().map_or(true, |v4| is_public(IpAddr::V4(v4)))
+        },
+    }
+}
+
+/// Every IPv4/IPv6 address in `text` with its byte range, in order of appearance
+pub fn find_ip_spans(text: &str) -> Vec<(Range<usize>, IpAddr)> {
+    let mut found: Vec<(Range<usize>, IpAddr)> = IPV4_PATTERN
+        .find_iter(text)
+        .filter_map(|m| Some((m.range(), m.as_str().parse().ok()?)))
+        .collect();
+    for m in IPV6_PATTERN.find_iter(text) {
+        if let Ok(v6) = m.as_str().parse::<Ipv6Addr>() {
+            // An IPv4 address embedded in an IPv6 one was already matched on its own
+            found.retain(|(span, _)| !m.range().contains(&span.start));
+            found.push((m.range(), IpAddr::V6(v6)));
+        }
+    }
+    found.sort_by_key(|(span, _)| span.start);
+    found
+}
+
+/// Every IPv4/IPv6 address in `text`, in order of appearance
+pub fn find_ips(text: &str) -> Vec<IpAddr> {
+    find_ip_spans(text).into_iter().map(|(_, ip)| ip).collect()
+}
+
+/// The currently registered lookup, if any
+pub fn current_lookup() -> Option<Arc<GeoLookup>> {
+    LOOKUP.read().ok().and_then(|lookup| lookup.clone())
+}
+
+/// Annotate one address using `lookup`
+pub fn enrich(ip: IpAddr, lookup: Option<&GeoLookup>) -> IpInfo {
+    let data = lookup.map(|l| l.lookup(ip)).unwrap_or_default();
+    IpInfo {
+        ip: ip.to_string(),
+        public: is_public(ip),
+        country: data.country,
+        asn: data.asn,
+        as_org: data.as_org,
+    }
+}
+
+/// Register the IP enrichment used by the IP extractors
+///
+/// Replaces any previous registration. Table entries are matched by longest
+/// prefix and take precedence; MMDB databases (GeoIP2/GeoLite2 Country
+/// and ASN) fill in whatever the table leaves empty.
+///
+/// # Arguments
+/// * `table` - Dict of CIDR (or single address) to `(country, asn, as_org)`,
+///   any of which may be None
+/// * `mmdb_paths` - Paths of `.mmdb` database files
+///
+/// # Returns
+/// * Number of table entries registered
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import register_ip_lookup
+/// register_ip_lookup(
+///     table={"203.0.113.0/24": ("AU", 64500, "Example Net")},
+///     mmdb_paths=["GeoLite2-Country.mmdb", "GeoLite2-ASN.mmdb"],
+/// )
+/// ```
+#[pyfunction]
+#[pyo3(signature = (table=None, mmdb_paths=None))]
+#[allow(clippy::type_complexity)]
+pub fn register_ip_lookup(
+    table: Option<HashMap<String, (Option<String>, Option<u32>, Option<String>)>>,
+    mmdb_paths: Option<Vec<String>>,
+) -> PyResult<usize> {
+    let mut by_prefix: HashMap<u32, HashMap<u128, GeoData>> = HashMap::new();
+    let entries = table.unwrap_or_default();
+    let count = entries.len();
+    for (cidr, (country, asn, as_org)) in entries {
+        let (net, prefix) = parse_cidr(&cidr).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid network: {cidr:?}"))
+        })?;
+        by_prefix.entry(prefix).or_default().insert(
+            net,
+            GeoData {
+                country,
+                asn,
+                as_org,
+            },
+        );
+    }
+    let mut tables: Vec<(u32, HashMap<u128, GeoData>)> = by_prefix.into_iter().collect();
+    tables.sort_by_key(|(prefix, _)| std::cmp::Reverse(*prefix));
+
+    let databases = mmdb_paths
+        .unwrap_or_default()
+        .iter()
+        .map(|path| {
+            Reader::open_readfile(path).map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                    "Failed to open MMDB {path}: {e}"
+                ))
+            })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let lookup = Arc::new(GeoLookup { tables, databases });
+    if let Ok(mut slot) = LOOKUP.write() {
+        *slot = Some(lookup);
+    }
+    Ok(count)
+}
+
+/// Remove the registered IP enrichment
+#[pyfunction]
+pub fn clear_ip_lookup() {
+    if let Ok(mut slot) = LOOKUP.write() {
+        *slot = None;
+    }
+}
+
+/// Fast IPv4/IPv6 address extraction
+///
+/// # Arguments
+/// * `text` - Text to search (headers or bodies)
+///
+/// # Returns
+/// * List of addresses in canonical form, in order of appearance
+#[pyfunction]
+pub fn extract_ips_fast(text: &str) -> Vec<String> {
+    find_ips(text).iter().map(ToString::to_string).collect()
+}
+
+/// Extract and enrich IP addresses from many texts in parallel
+///
+/// Uses the lookup registered with `register_ip_lookup` (without one, only
+/// `ip` and `public` are filled in).
+///
+/// # Arguments
+/// * `texts` - Texts to search
+///
+/// # Returns
+/// * One list of `IpInfo` per text, in input order
+#[pyfunction]
+pub fn extract_ip_info_batch(py: Python<'_>, texts: Vec<String>) -> PyResult<Vec<Vec<IpInfo>>> {
+    py.allow_threads(|| {
+        let lookup = current_lookup();
+        Ok(par_map(texts, |text| {
+            find_ips(&text)
+                .into_iter()
+                .map(|ip| enrich(ip, lookup.as_deref()))
+                .collect()
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_ips() {
+        let text = "from mx ([198.51.100.7]) by host (IPv6:2001:db8::1) 10.0.0.300 at 12:30:45 ::ffff:1.2.3.4";
+        let ips: Vec<String> = find_ips(text).iter().map(ToString::to_string).collect();
+        assert_eq!(ips, vec!["198.51.100.7", "2001:db8::1", "::ffff:1.2.3.4"]);
+        assert!(!is_public("10.1.2.3".parse().unwrap()));
+        assert!(!is_public("2001:db8::1".parse().unwrap()));
+        assert!(is_public("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_table_lookup_longest_prefix() {
+        crate::with_py(|py| {
+            let mut table = HashMap::new();
+            table.insert("8.0.0.0/8".to_string(), (Some("US".to_string()), Some(1), None));
+            table.insert("8.8.8.0/24".to_string(), (None, Some(15169), Some("GOOGLE".to_string())));
+            table.insert("2a00:1450::/32".to_string(), (Some("IE".to_string()), None, None));
+            assert_eq!(register_ip_lookup(Some(table), None).unwrap(), 3);
+
+            let infos = extract_ip_info_batch(
+                py,
+                vec!["8.8.8.8 8.1.1.1 2a00:1450:4009::1 1.1.1.1".to_string()],
+            )
+            .unwrap()
+            .remove(0);
+            assert_eq!(infos[0].asn, Some(15169));
+            assert_eq!(infos[0].as_org.as_deref(), Some("GOOGLE"));
+            assert_eq!(infos[0].country, None);
+            assert_eq!(infos[1].country.as_deref(), Some("US"));
+            assert_eq!(infos[2].country.as_deref(), Some("IE"));
+            assert_eq!(infos[3].country, None);
+            assert!(infos[3].public);
+            clear_ip_lookup();
+            assert!(current_lookup().is_none());
+            assert!(parse_cidr("1.2.3.4/33").is_none());
+        });
+    }
+}
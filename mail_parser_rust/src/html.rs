@@ -0,0 +1,604 @@
+//! HTML body rendering to plain text or Markdown.
+//!
+//! A single forgiving tokenizer walks the markup (no DOM is built), so
+//! malformed mail HTML such as unclosed tags or stray `<` characters degrades
+//! to text instead of failing. Script/style/head content is dropped, block
+//! elements become line breaks, lists keep their bullets or numbers and link
+//! targets are preserved.
+
+use crate::batch::par_map;
+use pyo3::prelude::*;
+use std::fmt::Write;
+
+/// Render HTML as readable plain text (`text (url)` links)
+pub fn html_to_text(html: &str) -> String {
+    Renderer::new(false).render(html)
+}
+
+/// Convert an HTML body to readable plain text (in place of html2text)
+///
+/// Tags are stripped and entities decoded; script, style and head content is
+/// dropped. Paragraphs and other blocks are separated by blank lines, `<br>`
+/// breaks the line, list items keep their `*` bullet or number, and links
+/// keep their target as `text (url)`. Malformed markup (unclosed tags, stray
+/// `<`) degrades to text instead of failing.
+///
+/// # Arguments
+/// * `html` - HTML document or fragment
+///
+/// # Returns
+/// * Plain text
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import html_to_text_fast
+/// html_to_text_fast('<p>See <a href="https://x.org">the docs</a></p><ul><li>one</li></ul>')
+/// # 'See the docs (https://x.org)\n\n* one'
+/// ```
+#[pyfunction]
+pub fn html_to_text_fast(html: &str) -> String {
+    html_to_text(html)
+}
+
+/// Batch version of [`html_to_text_fast`], processed in parallel
+///
+/// # Arguments
+/// * `htmls` - HTML bodies
+///
+/// # Returns
+/// * One plain text per body
+#[pyfunction]
+pub fn html_to_text_batch(py: Python<'_>, htmls: Vec<String>) -> Vec<String> {
+    py.allow_threads(|| par_map(htmls, |html| html_to_text(&html)))
+}
+
+/// Render HTML as Markdown (`[text](url)` links, `#` headings, `**bold**`)
+pub fn html_to_markdown(html: &str) -> String {
+    Renderer::new(true).render(html)
+}
+
+/// Elements whose content is never displayed
+const SKIPPED: &[&str] = &["script", "style", "head", "title", "template", "noscript"];
+
+/// Elements that start and end on their own line
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "table",
+    "tr",
+    "ul",
+    "ol",
+    "blockquote",
+    "pre",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "center",
+    "form",
+    "dl",
+    "dt",
+    "dd",
+];
+
+struct Renderer {
+    markdown: bool,
+    out: String,
+    /// Whitespace seen since the last emitted character
+    pending_space: bool,
+    /// Open `<a>` elements: (href, output length at start)
+    links: Vec<(Option<String>, usize)>,
+    /// Open lists: `None` for `<ul>`, `Some(n)` for the next `<ol>` number
+    lists: Vec<Option<u32>>,
+    pre_depth: usize,
+    skip: Option<String>,
+    quote_depth: usize,
+}
+
+impl Renderer {
+    const fn new(markdown: bool) -> Self {
+        Self {
+            markdown,
+            out: String::new(),
+            pending_space: false,
+            links: Vec::new(),
+            lists: Vec::new(),
+            pre_depth: 0,
+            skip: None,
+            quote_depth: 0,
+        }
+    }
+
+    fn render(mut self, html: &str) -> String {
+        for token in tokenize(html) {
+            match token {
+                Token::Open(name, attrs) => self.open(&name, attrs),
+                Token::Close(name) => self.close(&name),
+                Token::Text(text) => self.text(text),
+            }
+        }
+        finish(&self.out)
+    }
+
+    fn open(&mut self, name: &str, attrs: &str) {
+        if SKIPPED.contains(&name) {
+            self.skip = Some(name.to_string());
+            return;
+        }
+        if self.skip.is_some() {
+            return;
+        }
+        match name {
+            "br" => self.newline(),
+            "hr" => {
+                self.block_break();
+                self.out
+                    .push_str(if self.markdown { "---" } else { "----------" });
+                self.block_break();
+            },
+            "li" => {
+                self.newline();
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                self.out.push_str(&indent);
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        let marker = format!("{n}. ");
+                        *n += 1;
+                        self.out.push_str(&marker);
+                    },
+                    _ => self.out.push_str(if self.markdown { "- " } else { "* " }),
+                }
+                self.pending_space = false;
+            },
+            "ul" => {
+                self.block_break();
+                self.lists.push(None);
+            },
+            "ol" => {
+                self.block_break();
+                let start = attr(attrs, "start")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(1);
+                self.lists.push(Some(start));
+            },
+            "a" => {
+                let href = attr(attrs, "href").filter(|h| !h.starts_with('#'));
+                // Emit the separating space now so it stays outside the label
+                self.flush_space();
+                self.links.push((href, self.out.len()));
+            },
+            "img" => {
+                if let Some(alt) = attr(attrs, "alt").filter(|a| !a.trim().is_empty()) {
+                    self.text(&format!("[{}]", alt.trim()));
+                }
+            },
+            "td" | "th" if !self.out.ends_with('\n') && !self.out.is_empty() => {
+                self.out.push_str(if self.markdown { " | " } else { "\t" });
+                self.pending_space = false;
+            },
+            "blockquote" => {
+                self.quote_depth += 1;
+                self.block_break();
+            },
+            "pre" => {
+                self.block_break();
+                self.pre_depth += 1;
+                if self.markdown {
+                    self.out.push_str("```\n");
+                }
+            },
+            "b" | "strong" if self.markdown => self.inline_marker("**"),
+            "i" | "em" if self.markdown => self.inline_marker("_"),
+            "code" if self.markdown && self.pre_depth == 0 => self.inline_marker("`"),
+            _ if name.len() == 2 && name.starts_with('h') && BLOCKS.contains(&name) => {
+                self.block_break();
+                if self.markdown {
+                    let level = usize::from(name.as_bytes()[1] - b'0');
+                    self.out.push_str(&"#".repeat(level));
+                    self.out.push(' ');
+                }
+            },
+            _ if BLOCKS.contains(&name) => self.block_break(),
+            _ => {},
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        if let Some(skipped) = &self.skip {
+            if skipped == name {
+                self.skip = None;
+            }
+            return;
+        }
+        match name {
+            "a" => {
+                if let Some((href, start)) = self.links.pop() {
+                    self.close_link(href, start);
+                }
+            },
+            "ul" | "ol" => {
+                self.lists.pop();
+                self.block_break();
+            },
+            "blockquote" => {
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.block_break();
+            },
+            "pre" => {
+                if self.markdown {
+                    self.newline();
+                    self.out.push_str("```");
+                }
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+                self.block_break();
+            },
+            "b" | "strong" if self.markdown => self.out.push_str("**"),
+            "i" | "em" if self.markdown => self.out.push('_'),
+            "code" if self.markdown && self.pre_depth == 0 => self.out.push('`'),
+            _ if BLOCKS.contains(&name) => self.block_break(),
+            _ => {},
+        }
+    }
+
+    fn close_link(&mut self, href: Option<String>, start: usize) {
+        let Some(href) = href else {
+            return;
+        };
+        let start = start.min(self.out.len());
+        let label = self.out[start..].trim().to_string();
+        if self.markdown {
+            if label.is_empty() {
+                self.out.truncate(start);
+                self.text(&format!("<{href}>"));
+            } else {
+                let link = format!("[{label}]({href})");
+                self.out.truncate(start);
+                self.out.push_str(&link);
+            }
+        } else if label.is_empty() {
+            self.text(&href);
+        } else if label != href && !href.starts_with("mailto:") {
+            let _ = write!(self.out, " ({href})");
+        }
+    }
+
+    fn inline_marker(&mut self, marker: &str) {
+        self.flush_space();
+        self.out.push_str(marker);
+    }
+
+    fn text(&mut self, raw: &str) {
+        if self.skip.is_some() {
+            return;
+        }
+        let decoded = decode_entities(raw);
+        if self.pre_depth > 0 {
+            self.out.push_str(&decoded);
+            self.pending_space = false;
+            return;
+        }
+        for word_or_space in split_keep_whitespace(&decoded) {
+            if word_or_space.chars().all(char::is_whitespace) {
+                self.pending_space = true;
+            } else {
+                self.flush_space();
+                self.out.push_str(word_or_space);
+            }
+        }
+    }
+
+    fn flush_space(&mut self) {
+        if self.pending_space && !self.out.is_empty() && !self.out.ends_with([' ', '\n']) {
+            self.out.push(' ');
+        }
+        self.pending_space = false;
+    }
+
+    fn line_prefix(&self) -> String {
+        if self.quote_depth == 0 {
+            String::new()
+        } else {
+            format!("{} ", ">".repeat(self.quote_depth))
+        }
+    }
+
+    fn newline(&mut self) {
+        trim_trailing_spaces(&mut self.out);
+        self.out.push('\n');
+        let prefix = self.line_prefix();
+        self.out.push_str(&prefix);
+        self.pending_space = false;
+    }
+
+    fn block_break(&mut self) {
+        trim_trailing_spaces(&mut self.out);
+        let prefix = self.line_prefix();
+        if self.out.is_empty() {
+            self.out.push_str(&prefix);
+            return;
+        }
+        while !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+        self.out.push_str(&prefix);
+        self.pending_space = false;
+    }
+}
+
+fn trim_trailing_spaces(out: &mut String) {
+    let trimmed = out.trim_end_matches([' ', '\t']).len();
+    out.truncate(trimmed);
+}
+
+/// Normalise the rendered output: no trailing spaces, at most one blank line
+fn finish(out: &str) -> String {
+    let mut result = String::with_capacity(out.len());
+    let mut blank_run = 0;
+    for line in out.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.chars().all(|c| c == '>' || c == ' ') {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result.trim().to_string()
+}
+
+/// One piece of an HTML document
+#[derive(Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// Opening (or self-closing) tag: lowercased name and raw attribute text
+    Open(String, &'a str),
+    /// Closing tag: lowercased name
+    Close(String),
+    /// Text between tags, character references still encoded
+    Text(&'a str),
+}
+
+/// Split HTML into tags and text
+///
+/// Comments, doctypes and processing instructions are dropped; a `<` that
+/// does not start a well-formed tag is text. The content of script, style and
+/// other never-displayed elements is returned as a single `Text` (even if it
+/// contains `<`), followed by the close tag if there is one.
+pub fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(pos) = rest.find('<') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+        if pos > 0 {
+            tokens.push(Token::Text(&rest[..pos]));
+            rest = &rest[pos..];
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            tokens.push(Token::Text(&rest[..1]));
+            rest = &rest[1..];
+            continue;
+        };
+        let inner = &rest[1..end];
+        let tag = &rest[..=end];
+        rest = &rest[end + 1..];
+        if inner.starts_with('!') || inner.starts_with('?') {
+            continue;
+        }
+        let closing = inner.starts_with('/');
+        let body = inner.trim_start_matches('/');
+        let name_len = body
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .unwrap_or(body.len());
+        let name = body[..name_len].to_ascii_lowercase();
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            tokens.push(Token::Text(tag));
+        } else if closing {
+            tokens.push(Token::Close(name));
+        } else {
+            let raw_text = SKIPPED.contains(&name.as_str());
+            tokens.push(Token::Open(name.clone(), &body[name_len..]));
+            if raw_text {
+                // Jump to the matching close tag
+                let end = find_ignore_case(rest, &format!("</{name}")).unwrap_or(rest.len());
+                if end > 0 {
+                    tokens.push(Token::Text(&rest[..end]));
+                }
+                rest = &rest[end..];
+            }
+        }
+    }
+    tokens
+}
+
+/// Index of the `>` closing the tag at the start of `input`, honouring quotes
+fn tag_end(input: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, c) in input.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            (None, '<') => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    let needle = needle.as_bytes();
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle))
+}
+
+/// Value of attribute `name` in a tag's attribute string
+pub fn attr(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .trim();
+        let after = rest[eq + 1..].trim_start();
+        let (value, next) = if let Some(q @ ('"' | '\'')) = after.chars().next() {
+            let body = &after[1..];
+            body.find(q)
+                .map_or((body, ""), |end| (&body[..end], &body[end + 1..]))
+        } else {
+            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(decode_entities(value.trim()));
+        }
+        rest = next;
+    }
+    None
+}
+
+fn split_keep_whitespace(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut in_space: Option<bool> = None;
+    for (i, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if in_space.is_some_and(|s| s != space) {
+            pieces.push(&text[start..i]);
+            start = i;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
+/// Decode HTML character references (named, decimal and hex)
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let end = rest
+            .char_indices()
+            .take(12)
+            .find(|&(_, c)| c == ';')
+            .map(|(i, _)| i);
+        let decoded = end.and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+        if let Some((c, end)) = decoded {
+            out.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            out.push('&');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code).filter(|&c| c != '\0');
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "euro" => '€',
+        "pound" => '£',
+        "yen" => '¥',
+        "cent" => '¢',
+        "deg" => '°',
+        "times" => '×',
+        "shy" | "zwnj" | "zwj" => '\u{200b}',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_structure() {
+        let html = "<html><head><title>x</title><style>p{}</style></head><body>\
+            <h1>Hello&nbsp;there</h1><p>First   para with <a href=\"https://e.com\">a link</a>.</p>\
+            <ul><li>one</li><li>two</li></ul><ol start=3><li>three</li></ol>\
+            <script>alert(1)</script><p>Tom &amp; Jerry &#8212; &#x41;</p></body></html>";
+        let text = html_to_text(html);
+        assert_eq!(
+            text,
+            "Hello there\n\nFirst para with a link (https://e.com).\n\n* one\n* two\n\n3. three\n\nTom & Jerry — A"
+        );
+    }
+
+    #[test]
+    fn test_html_to_markdown() {
+        let html = "<h2>Title</h2><p>Some <b>bold</b> and <a href='https://x.org'>link</a></p>\
+            <blockquote>quoted</blockquote>";
+        let md = html_to_markdown(html);
+        assert_eq!(md, "## Title\n\nSome **bold** and [link](https://x.org)\n\n> quoted");
+    }
+
+    #[test]
+    fn test_malformed_html() {
+        assert_eq!(html_to_text("a < b and <p>unclosed"), "a < b and\n\nunclosed");
+        assert_eq!(html_to_text("<!-- c -->x<br>y"), "x\ny");
+        assert_eq!(decode_entities("&bogus; &amp"), "&bogus; &amp");
+    }
+
+    #[test]
+    fn test_html_to_text_batch() {
+        crate::with_py(|py| {
+            let htmls = vec!["<p>a</p><p>b</p>".to_string(), "x&lt;y<br>z".to_string()];
+            assert_eq!(html_to_text_batch(py, htmls), ["a\n\nb", "x<y\nz"]);
+        });
+        assert_eq!(html_to_text_fast("<b>bold</b> &amp; <i>plain"), "bold & plain");
+    }
+}
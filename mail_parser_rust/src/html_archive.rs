@@ -0,0 +1,525 @@
+//! Static HTML archive site export.
+//!
+//! The site is plain files that can be opened from disk or served by any web
+//! server:
+//!
+//! ```text
+//! out_dir/index.html                 threads in order of their first message
+//! out_dir/messages/000001.html       one page per message, in archive order
+//! out_dir/attachments/000001/<name>  decoded attachments of that message
+//! ```
+//!
+//! Message content is never passed through as markup: HTML bodies are
+//! flattened to text, everything is escaped, and only `http(s)` URLs become
+//! links. A restrictive Content-Security-Policy is added on top.
+
+use crate::date::{format_utc, parse_date};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{address_list, Message, Part};
+use crate::output::{Compression, Output, OutputReport};
+use crate::progress::Progress;
+use crate::thread::{chronological_threads, ThreadKey};
+use crate::URL_PATTERN;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+
+/// Messages rendered in parallel before their pages are written
+const BATCH_SIZE: usize = 4096;
+
+/// Deepest reply nesting shown in the thread listing
+const MAX_DEPTH: usize = 8;
+
+/// Headers shown at the top of a message page
+const SHOWN_HEADERS: &[&str] = &["From", "To", "Cc", "Date", "Subject"];
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:1em auto;padding:0 1em}\
+    a{color:#0645ad}ol.threads>li{margin-bottom:1em}ul.messages{list-style:none;padding:0}\
+    .meta{color:#666}table.headers th{text-align:right;padding-right:1em;vertical-align:top}\
+    pre.body{white-space:pre-wrap;word-wrap:break-word;border-top:1px solid #ccc;padding-top:1em}\
+    nav{margin:1em 0}";
+
+/// What the listing needs from one message
+struct Entry {
+    key: ThreadKey,
+    epoch: Option<i64>,
+    sender: String,
+}
+
+impl Entry {
+    fn parse(raw: &[u8]) -> Self {
+        let msg = Message::parse(raw);
+        let sender = msg
+            .decoded_header("From")
+            .and_then(|v| address_list(&v).into_iter().next())
+            .map_or_else(
+                || "(unknown sender)".to_string(),
+                |(name, address)| name.unwrap_or(address),
+            );
+        Self {
+            key: ThreadKey::from_headers(
+                msg.header("Message-ID"),
+                msg.header("In-Reply-To"),
+                msg.header("References"),
+                msg.decoded_header("Subject").as_deref(),
+            ),
+            epoch: msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch),
+            sender,
+        }
+    }
+
+    fn subject(&self) -> &str {
+        let subject = self.key.subject.trim();
+        if subject.is_empty() {
+            "(no subject)"
+        } else {
+            subject
+        }
+    }
+
+    fn when(&self) -> String {
+        self.epoch
+            .map_or_else(String::new, |e| format_utc(e)[..16].replace('T', " "))
+    }
+}
+
+/// A decoded attachment: (file name, bytes)
+type Attachment = (String, Vec<u8>);
+
+/// A message's place in its thread
+#[derive(Clone, Copy, Default)]
+struct Position {
+    thread: usize,
+    depth: usize,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Escape text for use in HTML content and attribute values
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape text, turning `http(s)` URLs into links
+fn linkify(text: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for m in URL_PATTERN.find_iter(text) {
+        out.push_str(&escape(&text[last..m.start()]));
+        let url = escape(m.as_str());
+        let _ = write!(out, "<a href=\"{url}\" rel=\"nofollow noopener noreferrer\">{url}</a>");
+        last = m.end();
+    }
+    out.push_str(&escape(&text[last..]));
+    out
+}
+
+/// Percent-encode a file name for use in a relative link
+fn encode_path(name: &str) -> String {
+    let mut out = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(char::from(b));
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+    }
+    out
+}
+
+/// A complete page around already-escaped `content`
+fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none'; \
+         style-src 'unsafe-inline'\">\n\
+         <meta name=\"referrer\" content=\"no-referrer\">\n\
+         <title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{content}</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+fn message_file(index: usize) -> String {
+    format!("{:06}.html", index + 1)
+}
+
+/// Reply depth and in-thread neighbours of every message
+fn positions(entries: &[Entry], threads: &[Vec<usize>]) -> Vec<Position> {
+    let mut out = vec![Position::default(); entries.len()];
+    for (n, thread) in threads.iter().enumerate() {
+        for (k, &i) in thread.iter().enumerate() {
+            // Nest under the deepest earlier message this one references
+            let depth = thread[..k]
+                .iter()
+                .filter(|&&j| {
+                    entries[j]
+                        .key
+                        .message_id
+                        .as_ref()
+                        .is_some_and(|id| entries[i].key.references.contains(id))
+                })
+                .map(|&j| out[j].depth + 1)
+                .max()
+                .unwrap_or(0);
+            out[i] = Position {
+                thread: n + 1,
+                depth,
+                prev: k.checked_sub(1).map(|p| thread[p]),
+                next: thread.get(k + 1).copied(),
+            };
+        }
+    }
+    out
+}
+
+fn render_index(
+    title: &str,
+    entries: &[Entry],
+    threads: &[Vec<usize>],
+    positions: &[Position],
+) -> String {
+    let mut out = format!(
+        "<h1>{}</h1>\n<p class=\"meta\">{} messages in {} threads</p>\n<ol class=\"threads\">\n",
+        escape(title),
+        entries.len(),
+        threads.len()
+    );
+    for thread in threads {
+        let first = &entries[thread[0]];
+        let _ = writeln!(
+            out,
+            "<li><strong>{}</strong> <span class=\"meta\">({} messages)</span>\n<ul class=\"messages\">",
+            escape(first.subject()),
+            thread.len()
+        );
+        for &i in thread {
+            let entry = &entries[i];
+            let _ = writeln!(
+                out,
+                "<li style=\"padding-left:{}em\"><a href=\"messages/{}\">{}</a> \
+                 <span class=\"meta\">{} {}</span></li>",
+                positions[i].depth.min(MAX_DEPTH) * 2,
+                message_file(i),
+                escape(entry.subject()),
+                escape(&entry.sender),
+                entry.when()
+            );
+        }
+        out.push_str("</ul></li>\n");
+    }
+    out.push_str("</ol>\n");
+    page(title, &out)
+}
+
+/// A message page plus its attachments
+fn render_message(raw: &[u8], index: usize, position: Position) -> (String, Vec<Attachment>) {
+    let msg = Message::parse(raw);
+    let subject = msg
+        .decoded_header("Subject")
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| "(no subject)".to_string());
+
+    let mut out = String::from("<nav><a href=\"../index.html\">Index</a>");
+    for (label, target) in [
+        ("Previous in thread", position.prev),
+        ("Next in thread", position.next),
+    ] {
+        if let Some(target) = target {
+            let _ = write!(out, " | <a href=\"{}\">{label}</a>", message_file(target));
+        }
+    }
+    let _ = writeln!(out, " <span class=\"meta\">(thread {})</span></nav>", position.thread);
+    let _ = writeln!(out, "<h1>{}</h1>\n<table class=\"headers\">", escape(&subject));
+    for name in SHOWN_HEADERS {
+        if let Some(value) = msg.decoded_header(name) {
+            let _ = writeln!(out, "<tr><th>{name}</th><td>{}</td></tr>", escape(value.trim()));
+        }
+    }
+    out.push_str("</table>\n");
+
+    let mut attachments: Vec<Attachment> = Vec::new();
+    for part in msg.parts().into_iter().filter(Part::is_attachment) {
+        let n = attachments.len() + 1;
+        let fallback = || {
+            let ext = if part.mime_type() == "message/rfc822" {
+                "eml"
+            } else {
+                "bin"
+            };
+            format!("attachment-{n}.{ext}")
+        };
+        let mut name = part
+            .filename()
+            .and_then(|f| crate::sanitize_filename_fast(&f).ok())
+            .filter(|f| !f.is_empty())
+            .unwrap_or_else(fallback);
+        if attachments.iter().any(|(existing, _)| *existing == name) {
+            name = format!("{n}-{name}");
+        }
+        attachments.push((name, part.decoded()));
+    }
+    if !attachments.is_empty() {
+        out.push_str("<h2>Attachments</h2>\n<ul>\n");
+        for (name, bytes) in &attachments {
+            let _ = writeln!(
+                out,
+                "<li><a href=\"../attachments/{:06}/{}\">{}</a> <span class=\"meta\">{} bytes</span></li>",
+                index + 1,
+                encode_path(name),
+                escape(name),
+                bytes.len()
+            );
+        }
+        out.push_str("</ul>\n");
+    }
+
+    let _ = writeln!(out, "<pre class=\"body\">{}</pre>", linkify(msg.text_body().trim_end()));
+    (page(&subject, &out), attachments)
+}
+
+/// Export an mbox as a browsable static HTML site
+///
+/// Writes `index.html` (threads in order of their first message, replies
+/// indented under the message they answer), one page per message under
+/// `messages/` with headers, body and previous/next-in-thread links, and
+/// every attachment under `attachments/<message number>/`. Bodies are shown
+/// as escaped text (HTML parts are flattened first), so no script, style or
+/// remote content from a message reaches the page.
+///
+/// # Arguments
+/// * `src` - Path to the mbox file
+/// * `out_dir` - Output directory (created if missing)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` with message, file and byte totals
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import export_html_archive
+/// report = export_html_archive("inbox.mbox", "site/")
+/// print(f"{report.file_count} files; open site/index.html")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (src, out_dir, dry_run=false, fsync="file", from_line_mode="standard", progress=None))]
+pub fn export_html_archive(
+    py: Python<'_>,
+    src: &str,
+    out_dir: &str,
+    dry_run: bool,
+    fsync: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        // Attachments are written verbatim: a `.gz` file must not be recompressed
+        let output =
+            Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+        let mmap = map_file(src)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        let entries: Vec<Entry> = spans
+            .par_iter()
+            .map(|span| Entry::parse(&data[span.clone()]))
+            .collect();
+        let keys: Vec<ThreadKey> = entries.iter().map(|e| e.key.clone()).collect();
+        let epochs: Vec<Option<i64>> = entries.iter().map(|e| e.epoch).collect();
+        let threads = chronological_threads(&keys, &epochs);
+        let positions = positions(&entries, &threads);
+
+        let out_dir = Path::new(out_dir);
+        let write = |path: &Path, content: &[u8]| -> PyResult<()> {
+            let attempt = || -> std::io::Result<()> {
+                if !output.is_dry_run() {
+                    if let Some(parent) = path.parent() {
+                        crate::paths::create_dir_all(parent)?;
+                    }
+                }
+                let mut file = output.create(path)?;
+                file.write_all(content)?;
+                file.finish().map(|_| ())
+            };
+            attempt().map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                    "Failed to write {}: {e}",
+                    path.display()
+                ))
+            })
+        };
+
+        let title = Path::new(src)
+            .file_name()
+            .map_or_else(|| src.to_string(), |name| name.to_string_lossy().into_owned());
+        write(
+            &out_dir.join("index.html"),
+            render_index(&title, &entries, &threads, &positions).as_bytes(),
+        )?;
+        for (batch_no, batch) in spans.chunks(BATCH_SIZE).enumerate() {
+            if !progress.messages(batch) {
+                break;
+            }
+            let rendered: Vec<(String, Vec<Attachment>)> = batch
+                .par_iter()
+                .enumerate()
+                .map(|(i, span)| {
+                    let index = batch_no * BATCH_SIZE + i;
+                    render_message(&data[span.clone()], index, positions[index])
+                })
+                .collect();
+            for (i, (html, attachments)) in rendered.into_iter().enumerate() {
+                let index = batch_no * BATCH_SIZE + i;
+                write(&out_dir.join("messages").join(message_file(index)), html.as_bytes())?;
+                let folder = out_dir
+                    .join("attachments")
+                    .join(format!("{:06}", index + 1));
+                for (name, bytes) in attachments {
+                    write(&folder.join(name), &bytes)?;
+                }
+                output.note_message();
+            }
+        }
+        progress.check("export_html_archive")?;
+        mmap.verify(&mode)?;
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From alice Mon Jan  1 00:00:00 2024
+From: Alice <alice@example.com>
+To: bob@example.com
+Subject: Plans <draft>
+Message-ID: <1@x>
+Date: Mon, 1 Jan 2024 12:00:00 +0000
+Content-Type: text/html
+
+<p>See <b>https://example.com/a?b=1&amp;c=2</b></p><script>alert(1)</script>
+
+From bob Mon Jan  1 00:00:00 2024
+From: Bob <bob@example.com>
+Subject: Re: Plans <draft>
+Message-ID: <2@x>
+In-Reply-To: <1@x>
+Date: Mon, 1 Jan 2024 13:00:00 +0000
+Content-Type: multipart/mixed; boundary=XX
+
+--XX
+Content-Type: text/plain
+
+Agreed, notes attached.
+--XX
+Content-Type: application/octet-stream; name=\"my notes.gz\"
+Content-Transfer-Encoding: base64
+
+aGVsbG8=
+--XX--
+
+From carol Mon Jan  1 00:00:00 2024
+From: carol@example.com
+Subject: Earlier
+Date: Sun, 31 Dec 2023 09:00:00 +0000
+
+Hi
+";
+
+    #[test]
+    fn test_export_html_archive() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_html_archive_{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            std::fs::write(&src, MBOX).unwrap();
+            let out = dir.join("site");
+
+            let report = export_html_archive(
+                py,
+                src.to_str().unwrap(),
+                out.to_str().unwrap(),
+                false,
+                "none",
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert_eq!(report.message_count, 3);
+            assert_eq!(report.file_count, 5);
+
+            let index = std::fs::read_to_string(out.join("index.html")).unwrap();
+            assert!(index.contains("3 messages in 2 threads"));
+            // The "Earlier" thread is listed first; the reply is indented
+            assert!(index.find("Earlier").unwrap() < index.find("Plans &lt;draft&gt;").unwrap());
+            assert!(
+                index.contains("<li style=\"padding-left:2em\"><a href=\"messages/000002.html\">")
+            );
+
+            let first = std::fs::read_to_string(out.join("messages/000001.html")).unwrap();
+            assert!(!first.contains("<script>") && !first.contains("<b>"));
+            assert!(first.contains(
+            "<a href=\"https://example.com/a?b=1&amp;c=2\" rel=\"nofollow noopener noreferrer\">"
+        ));
+            assert!(first.contains("<a href=\"000002.html\">Next in thread</a>"));
+            assert!(first.contains("<th>From</th><td>Alice &lt;alice@example.com&gt;</td>"));
+
+            let reply = std::fs::read_to_string(out.join("messages/000002.html")).unwrap();
+            assert!(reply.contains("href=\"../attachments/000002/my%20notes.gz\">my notes.gz</a>"));
+            assert!(reply.contains("Agreed, notes attached."));
+            let attachment = std::fs::read(out.join("attachments/000002/my notes.gz")).unwrap();
+            assert_eq!(attachment, b"hello");
+
+            let dry = export_html_archive(
+                py,
+                src.to_str().unwrap(),
+                dir.join("dry").to_str().unwrap(),
+                true,
+                "none",
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert!(dry.dry_run && dry.file_count == 5);
+            assert!(!dir.join("dry").exists());
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(escape("<a href='x'>&\""), "&lt;a href=&#39;x&#39;&gt;&amp;&quot;");
+        assert_eq!(encode_path("a b#1.txt"), "a%20b%231.txt");
+        assert_eq!(
+            linkify("go to http://x.org/<now>"),
+            "go to <a href=\"http://x.org/\" rel=\"nofollow noopener noreferrer\">http://x.org/</a>&lt;now&gt;"
+        );
+    }
+}
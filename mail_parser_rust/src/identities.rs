@@ -0,0 +1,354 @@
+//! Alias resolution: grouping the addresses one person uses.
+//!
+//! Addresses are merged when they are the same mailbox written differently
+//! (case, `+tag` sub-addressing, Gmail dots), share a distinctive local part
+//! across domains (`j.smith@work.com`, `jsmith@home.org` do not match, but
+//! `john.smith@work.com` and `johnsmith@home.org` do), share a full display
+//! name, or sign off with the same signature block. Keys shared by many
+//! addresses (role names like "Customer Service", company disclaimers) are
+//! treated as boilerplate and never merge anything.
+
+use crate::quotes::signature_block;
+use crate::thread::UnionFind;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// A key shared by more addresses than this is boilerplate, not a person
+const MAX_SHARED: usize = 5;
+
+/// Shortest normalized local part that can link addresses across domains
+const MIN_LOCAL_PART: usize = 6;
+
+/// Shortest normalized signature used as a fingerprint
+const MIN_SIGNATURE: usize = 20;
+
+/// Local parts used by roles or teams rather than one person
+const ROLE_LOCAL_PARTS: &[&str] = &[
+    "accounts",
+    "admin",
+    "billing",
+    "contact",
+    "customerservice",
+    "enquiries",
+    "feedback",
+    "hello",
+    "helpdesk",
+    "info",
+    "invoices",
+    "mailerdaemon",
+    "marketing",
+    "newsletter",
+    "noreply",
+    "notifications",
+    "office",
+    "postmaster",
+    "sales",
+    "service",
+    "support",
+    "team",
+    "webmaster",
+];
+
+/// Domains whose mailboxes ignore dots in the local part
+const DOTLESS_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+
+/// Addresses judged to belong to one person
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[pyclass]
+pub struct IdentityCluster {
+    /// Most frequently seen address
+    #[pyo3(get)]
+    pub primary_address: String,
+    /// Every address in the cluster as given, most frequent first
+    #[pyo3(get)]
+    pub addresses: Vec<String>,
+    /// Display names used, most frequent first
+    #[pyo3(get)]
+    pub names: Vec<String>,
+    /// Input pairs that fell into the cluster
+    #[pyo3(get)]
+    pub occurrences: usize,
+    /// Rules that merged addresses: "canonical", "local-part",
+    /// "display-name", "signature" (empty for a single address)
+    #[pyo3(get)]
+    pub evidence: Vec<String>,
+}
+
+/// Lowercased address with `+tag` removed and Gmail dots dropped
+pub fn canonical_address(address: &str) -> String {
+    let address = address.trim().trim_matches(['<', '>']).to_lowercase();
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return address;
+    };
+    let local = local.split('+').next().unwrap_or(local);
+    if DOTLESS_DOMAINS.contains(&domain) {
+        format!("{}@gmail.com", local.replace('.', ""))
+    } else {
+        format!("{local}@{domain}")
+    }
+}
+
+/// Local part without separators, if distinctive enough to match across domains
+fn local_key(canonical: &str) -> Option<String> {
+    let local = canonical.split('@').next()?;
+    let key: String = local.chars().filter(char::is_ascii_alphanumeric).collect();
+    let letters = key.chars().filter(char::is_ascii_alphabetic).count();
+    (key.len() >= MIN_LOCAL_PART && letters >= 3 && !ROLE_LOCAL_PARTS.contains(&key.as_str()))
+        .then_some(key)
+}
+
+/// Display name as lowercase words, "Last, First" turned around; only full
+/// names (two words or more) qualify
+fn name_key(name: &str) -> Option<String> {
+    let name = name.trim().trim_matches(['"', '\'']);
+    let name = match name.split_once(',') {
+        Some((last, first)) if !first.trim().is_empty() => format!("{first} {last}"),
+        _ => name.to_string(),
+    };
+    if name.contains('@') {
+        return None;
+    }
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    (words.len() >= 2).then(|| words.join(" "))
+}
+
+/// Signature block (or the whole text) with case and spacing normalized
+fn signature_key(text: &str) -> Option<String> {
+    let block = signature_block(text).unwrap_or_else(|| text.to_string());
+    let key = block
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    (key.len() >= MIN_SIGNATURE).then_some(key)
+}
+
+/// What was seen for one canonical address
+#[derive(Default)]
+struct Seen {
+    canonical: String,
+    forms: HashMap<String, usize>,
+    names: HashMap<String, usize>,
+    count: usize,
+    evidence: BTreeSet<&'static str>,
+}
+
+/// Keys sorted by count (descending), then alphabetically
+fn ranked(counts: HashMap<String, usize>) -> Vec<String> {
+    let mut items: Vec<(String, usize)> = counts.into_iter().collect();
+    items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    items.into_iter().map(|(key, _)| key).collect()
+}
+
+fn cluster(
+    pairs: Vec<(String, Option<String>)>,
+    signatures: &HashMap<String, String>,
+) -> Vec<IdentityCluster> {
+    let mut seen: Vec<Seen> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (address, name) in pairs {
+        let canonical = canonical_address(&address);
+        if !canonical.contains('@') {
+            continue;
+        }
+        let i = *index.entry(canonical.clone()).or_insert_with(|| {
+            seen.push(Seen {
+                canonical,
+                ..Seen::default()
+            });
+            seen.len() - 1
+        });
+        let entry = &mut seen[i];
+        entry.count += 1;
+        *entry
+            .forms
+            .entry(address.trim().to_lowercase())
+            .or_default() += 1;
+        if let Some(name) = name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+            *entry.names.entry(name.to_string()).or_default() += 1;
+        }
+    }
+    for entry in &mut seen {
+        if entry.forms.len() > 1 {
+            entry.evidence.insert("canonical");
+        }
+    }
+
+    let mut groups: HashMap<(&'static str, String), BTreeSet<usize>> = HashMap::new();
+    for (i, entry) in seen.iter().enumerate() {
+        if let Some(key) = local_key(&entry.canonical) {
+            groups.entry(("local-part", key)).or_default().insert(i);
+        }
+        for name in entry.names.keys().filter_map(|n| name_key(n)) {
+            groups.entry(("display-name", name)).or_default().insert(i);
+        }
+    }
+    for (address, text) in signatures {
+        if let (Some(&i), Some(key)) = (index.get(&canonical_address(address)), signature_key(text))
+        {
+            groups.entry(("signature", key)).or_default().insert(i);
+        }
+    }
+
+    let mut uf = UnionFind::new(seen.len());
+    for ((rule, _), members) in groups {
+        if members.len() < 2 || members.len() > MAX_SHARED {
+            continue;
+        }
+        let first = *members.iter().next().unwrap_or(&0);
+        for &i in &members {
+            uf.union(first, i);
+            seen[i].evidence.insert(rule);
+        }
+    }
+
+    let mut by_root: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..seen.len() {
+        by_root.entry(uf.find(i)).or_default().push(i);
+    }
+    let mut clusters: Vec<IdentityCluster> = by_root
+        .into_values()
+        .map(|members| {
+            let mut forms = HashMap::new();
+            let mut names = HashMap::new();
+            let mut evidence = BTreeSet::new();
+            let mut occurrences = 0;
+            for i in members {
+                let entry = std::mem::take(&mut seen[i]);
+                occurrences += entry.count;
+                evidence.extend(entry.evidence);
+                for (form, n) in entry.forms {
+                    *forms.entry(form).or_default() += n;
+                }
+                for (name, n) in entry.names {
+                    *names.entry(name).or_default() += n;
+                }
+            }
+            let addresses = ranked(forms);
+            IdentityCluster {
+                primary_address: addresses.first().cloned().unwrap_or_default(),
+                addresses,
+                names: ranked(names),
+                occurrences,
+                evidence: evidence.into_iter().map(ToString::to_string).collect(),
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.occurrences
+            .cmp(&a.occurrences)
+            .then_with(|| a.primary_address.cmp(&b.primary_address))
+    });
+    clusters
+}
+
+/// Group addresses that likely belong to the same person
+///
+/// Merges addresses that are the same mailbox written differently (case,
+/// `+tag`, Gmail dots), share a distinctive local part across domains, share
+/// a full display name ("Smith, John" matches "John Smith"), or end their
+/// mail with the same signature block. A name, local part or signature used
+/// by more than five addresses is treated as a role or boilerplate and merges
+/// nothing. Every address ends up in exactly one cluster.
+///
+/// # Arguments
+/// * `addresses_and_names` - `(address, display_name)` pairs, one per
+///   occurrence (repeats raise the counts used for ranking); the name may be None
+/// * `signatures` - Optional `{address: text}` with a signature block or a
+///   body ending in a `-- ` signature
+///
+/// # Returns
+/// * List of `IdentityCluster`, most frequently seen first
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import resolve_identities
+/// pairs = [("John.Smith@work.com", "Smith, John"), ("johnsmith@home.org", "John Smith")]
+/// for identity in resolve_identities(pairs):
+///     print(identity.primary_address, identity.addresses, identity.evidence)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (addresses_and_names, signatures=None))]
+pub fn resolve_identities(
+    py: Python<'_>,
+    addresses_and_names: Vec<(String, Option<String>)>,
+    signatures: Option<HashMap<String, String>>,
+) -> PyResult<Vec<IdentityCluster>> {
+    py.allow_threads(|| Ok(cluster(addresses_and_names, &signatures.unwrap_or_default())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(address: &str, name: Option<&str>) -> (String, Option<String>) {
+        (address.to_string(), name.map(ToString::to_string))
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(canonical_address("<J.Smith+lists@GoogleMail.com>"), "jsmith@gmail.com");
+        assert_eq!(canonical_address("A.B+x@work.com"), "a.b@work.com");
+        assert_eq!(local_key("john.smith@a.com").as_deref(), Some("johnsmith"));
+        assert_eq!(local_key("info@a.com"), None);
+        assert_eq!(local_key("customer-service@a.com"), None);
+        assert_eq!(local_key("js@a.com"), None);
+        assert_eq!(name_key("\"Smith, John\"").as_deref(), Some("john smith"));
+        assert_eq!(name_key("John"), None);
+        assert_eq!(name_key("john@x.com"), None);
+    }
+
+    #[test]
+    fn test_resolve_identities() {
+        let mut pairs = vec![
+            pair("John.Smith@work.com", Some("Smith, John")),
+            pair("john.smith@work.com", None),
+            pair("johnsmith@home.org", Some("Johnny")),
+            pair("jsmith+shop@gmail.com", Some("John Smith")),
+            pair("j.s.m.i.t.h@gmail.com", None),
+            pair("ann@x.org", Some("Ann")),
+            pair("al@y.org", Some("Al")),
+            pair("not an address", None),
+        ];
+        // A role name used by many addresses merges nothing
+        for i in 0..6 {
+            pairs.push(pair(&format!("help{i}@shop{i}.com"), Some("Customer Service")));
+        }
+        let signatures = HashMap::from([
+            (
+                "ann@x.org".to_string(),
+                "Hi\n-- \nAnn Lee | Acme Corp | +1 555 0100".to_string(),
+            ),
+            ("al@y.org".to_string(), "ann lee |  acme corp | +1 555 0100".to_string()),
+        ]);
+        let clusters = cluster(pairs, &signatures);
+        assert_eq!(clusters.len(), 8);
+
+        let john = &clusters[0];
+        assert_eq!(john.primary_address, "john.smith@work.com");
+        assert_eq!(john.occurrences, 5);
+        assert_eq!(
+            john.addresses,
+            [
+                "john.smith@work.com",
+                "j.s.m.i.t.h@gmail.com",
+                "johnsmith@home.org",
+                "jsmith+shop@gmail.com"
+            ]
+        );
+        assert_eq!(john.names[0], "John Smith");
+        assert_eq!(john.evidence, ["canonical", "display-name", "local-part"]);
+
+        let ann = &clusters[1];
+        assert_eq!(ann.addresses, ["al@y.org", "ann@x.org"]);
+        assert_eq!(ann.evidence, ["signature"]);
+        assert!(clusters[2..]
+            .iter()
+            .all(|c| c.addresses.len() == 1 && c.evidence.is_empty()));
+    }
+}
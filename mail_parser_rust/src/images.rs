@@ -0,0 +1,530 @@
+//! Image header and EXIF inspection without decoding pixels.
+//!
+//! Only the container structure is read: dimensions come from the format
+//! header (JPEG SOF, PNG IHDR, GIF screen descriptor, WebP VP8/VP8L/VP8X,
+//! BMP info header, TIFF IFD0), and EXIF is walked just far enough to find
+//! the capture time and whether GPS coordinates are present.
+
+use crate::mime::Message;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use serde::{Deserialize, Serialize};
+
+/// Dimensions, format and privacy-relevant EXIF facts of one image
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[pyclass]
+pub struct ImageMetadata {
+    /// Attachment filename, when read from a message
+    #[pyo3(get)]
+    pub filename: Option<String>,
+    /// "jpeg", "png", "gif", "webp", "bmp" or "tiff"
+    #[pyo3(get)]
+    pub format: String,
+    #[pyo3(get)]
+    pub width: Option<u32>,
+    #[pyo3(get)]
+    pub height: Option<u32>,
+    /// Size of the encoded image in bytes
+    #[pyo3(get)]
+    pub size: usize,
+    #[pyo3(get)]
+    pub has_exif: bool,
+    /// EXIF `DateTimeOriginal` (or `DateTime`) as `YYYY-MM-DDTHH:MM:SS`, camera local time
+    #[pyo3(get)]
+    pub capture_time: Option<String>,
+    /// Whether EXIF carries GPS coordinates
+    #[pyo3(get)]
+    pub has_gps: bool,
+}
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const TAG_DATETIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATETIME_ORIGINAL: u16 = 0x9003;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_HEIGHT: u16 = 0x0101;
+
+fn be16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Minimal TIFF structure reader (shared by EXIF blocks and TIFF files)
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+/// One IFD entry: (tag, type, count, value-or-offset field position)
+type IfdEntry = (u16, u16, u32, usize);
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        if self.little_endian {
+            le16(self.data, at)
+        } else {
+            be16(self.data, at)
+        }
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        if self.little_endian {
+            le32(self.data, at)
+        } else {
+            be32(self.data, at)
+        }
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|o| o as usize)
+    }
+
+    fn entries(&self, ifd: usize) -> Vec<IfdEntry> {
+        let count = self.u16_at(ifd).unwrap_or(0);
+        (0..usize::from(count))
+            .map_while(|i| {
+                let at = ifd + 2 + i * 12;
+                Some((self.u16_at(at)?, self.u16_at(at + 2)?, self.u32_at(at + 4)?, at + 8))
+            })
+            .collect()
+    }
+
+    /// Numeric value of a SHORT or LONG entry
+    fn number(&self, (_, kind, _, field): IfdEntry) -> Option<u32> {
+        match kind {
+            3 => self.u16_at(field).map(u32::from),
+            4 => self.u32_at(field),
+            _ => None,
+        }
+    }
+
+    /// Text of an ASCII entry (values over four bytes live at an offset)
+    fn ascii(&self, (_, kind, count, field): IfdEntry) -> Option<String> {
+        if kind != 2 {
+            return None;
+        }
+        let count = count as usize;
+        let start = if count <= 4 {
+            field
+        } else {
+            self.u32_at(field)? as usize
+        };
+        let bytes = self.data.get(start..start + count)?;
+        let text = String::from_utf8_lossy(bytes);
+        Some(text.trim_end_matches('\0').trim().to_string())
+    }
+
+    fn find(&self, ifd: usize, tag: u16) -> Option<IfdEntry> {
+        self.entries(ifd).into_iter().find(|e| e.0 == tag)
+    }
+}
+
+/// EXIF "YYYY:MM:DD HH:MM:SS" to "YYYY-MM-DDTHH:MM:SS"
+fn exif_time(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    if bytes.len() < 19 || bytes[4] != b':' || bytes[7] != b':' || value.starts_with("0000") {
+        return None;
+    }
+    Some(format!("{}-{}-{}T{}", &value[..4], &value[5..7], &value[8..10], &value[11..19]))
+}
+
+/// Capture time and GPS presence from a TIFF-structured EXIF block
+fn read_exif(meta: &mut ImageMetadata, tiff_data: &[u8]) {
+    let Some(tiff) = Tiff::new(tiff_data) else {
+        return;
+    };
+    let Some(ifd0) = tiff.first_ifd() else {
+        return;
+    };
+    meta.has_exif = true;
+    let original = tiff
+        .find(ifd0, TAG_EXIF_IFD)
+        .and_then(|e| tiff.number(e))
+        .and_then(|exif_ifd| tiff.find(exif_ifd as usize, TAG_DATETIME_ORIGINAL))
+        .and_then(|e| tiff.ascii(e));
+    let fallback = || tiff.find(ifd0, TAG_DATETIME).and_then(|e| tiff.ascii(e));
+    meta.capture_time = original.or_else(fallback).as_deref().and_then(exif_time);
+    meta.has_gps = tiff
+        .find(ifd0, TAG_GPS_IFD)
+        .and_then(|e| tiff.number(e))
+        .is_some_and(|gps| tiff.find(gps as usize, TAG_GPS_LATITUDE).is_some());
+}
+
+/// JPEG segments as (marker, payload range) up to the start of scan
+fn jpeg_segments(data: &[u8]) -> Vec<(u8, std::ops::Range<usize>)> {
+    let mut segments = Vec::new();
+    let mut at = 2;
+    while at + 4 <= data.len() && data[at] == 0xFF {
+        let marker = data[at + 1];
+        if marker == 0xFF {
+            at += 1; // fill byte
+            continue;
+        }
+        if marker == 0xD8 || (0xD0..=0xD7).contains(&marker) {
+            at += 2;
+            continue;
+        }
+        let Some(len) = be16(data, at + 2).map(usize::from) else {
+            break;
+        };
+        if len < 2 || at + 2 + len > data.len() {
+            break;
+        }
+        segments.push((marker, at + 4..at + 2 + len));
+        if marker == 0xDA {
+            break;
+        }
+        at += 2 + len;
+    }
+    segments
+}
+
+const fn is_sof(marker: u8) -> bool {
+    matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF)
+}
+
+fn read_jpeg(meta: &mut ImageMetadata, data: &[u8]) {
+    for (marker, range) in jpeg_segments(data) {
+        let payload = &data[range];
+        if marker == 0xE1 && payload.starts_with(EXIF_HEADER) {
+            read_exif(meta, &payload[EXIF_HEADER.len()..]);
+        } else if is_sof(marker) && meta.width.is_none() {
+            meta.height = be16(payload, 1).map(u32::from);
+            meta.width = be16(payload, 3).map(u32::from);
+        }
+    }
+}
+
+/// PNG chunks as (type, data range)
+fn png_chunks(data: &[u8]) -> Vec<([u8; 4], std::ops::Range<usize>)> {
+    let mut chunks = Vec::new();
+    let mut at = 8;
+    while let (Some(len), Some(kind)) = (be32(data, at), data.get(at + 4..at + 8)) {
+        let start = at + 8;
+        let end = start + len as usize;
+        if end + 4 > data.len() {
+            break;
+        }
+        let mut name = [0; 4];
+        name.copy_from_slice(kind);
+        chunks.push((name, start..end));
+        if &name == b"IEND" {
+            break;
+        }
+        at = end + 4;
+    }
+    chunks
+}
+
+fn read_png(meta: &mut ImageMetadata, data: &[u8]) {
+    meta.width = be32(data, 16);
+    meta.height = be32(data, 20);
+    for (kind, range) in png_chunks(data) {
+        if &kind == b"eXIf" {
+            let block = &data[range];
+            read_exif(meta, block.strip_prefix(EXIF_HEADER).unwrap_or(block));
+        }
+    }
+}
+
+/// RIFF chunks of a WebP file as (fourcc, data range)
+fn webp_chunks(data: &[u8]) -> Vec<([u8; 4], std::ops::Range<usize>)> {
+    let mut chunks = Vec::new();
+    let mut at = 12;
+    while let (Some(kind), Some(len)) = (data.get(at..at + 4), le32(data, at + 4)) {
+        let start = at + 8;
+        let end = (start + len as usize).min(data.len());
+        let mut name = [0; 4];
+        name.copy_from_slice(kind);
+        chunks.push((name, start..end));
+        at = end + (len as usize & 1); // chunks are padded to even length
+    }
+    chunks
+}
+
+fn read_webp(meta: &mut ImageMetadata, data: &[u8]) {
+    for (kind, range) in webp_chunks(data) {
+        let chunk = &data[range];
+        match &kind {
+            b"VP8X" => {
+                let u24 = |at: usize| {
+                    Some(
+                        u32::from_le_bytes([
+                            *chunk.get(at)?,
+                            *chunk.get(at + 1)?,
+                            *chunk.get(at + 2)?,
+                            0,
+                        ]) + 1,
+                    )
+                };
+                meta.width = u24(4);
+                meta.height = u24(7);
+            },
+            b"VP8 " if meta.width.is_none() => {
+                meta.width = le16(chunk, 6).map(|w| u32::from(w & 0x3FFF));
+                meta.height = le16(chunk, 8).map(|h| u32::from(h & 0x3FFF));
+            },
+            b"VP8L" if meta.width.is_none() => {
+                if let Some(bits) = le32(chunk, 1) {
+                    meta.width = Some((bits & 0x3FFF) + 1);
+                    meta.height = Some(((bits >> 14) & 0x3FFF) + 1);
+                }
+            },
+            b"EXIF" => read_exif(meta, chunk.strip_prefix(EXIF_HEADER).unwrap_or(chunk)),
+            _ => {},
+        }
+    }
+}
+
+fn read_tiff_file(meta: &mut ImageMetadata, data: &[u8]) {
+    read_exif(meta, data);
+    if let Some(tiff) = Tiff::new(data) {
+        if let Some(ifd0) = tiff.first_ifd() {
+            meta.width = tiff
+                .find(ifd0, TAG_IMAGE_WIDTH)
+                .and_then(|e| tiff.number(e));
+            meta.height = tiff
+                .find(ifd0, TAG_IMAGE_HEIGHT)
+                .and_then(|e| tiff.number(e));
+        }
+    }
+}
+
+/// Inspect an encoded image; `None` if the format is not recognised
+pub fn inspect(data: &[u8]) -> Option<ImageMetadata> {
+    let mut meta = ImageMetadata {
+        size: data.len(),
+        ..ImageMetadata::default()
+    };
+    let format = if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        read_jpeg(&mut meta, data);
+        "jpeg"
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        read_png(&mut meta, data);
+        "png"
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        meta.width = le16(data, 6).map(u32::from);
+        meta.height = le16(data, 8).map(u32::from);
+        "gif"
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        read_webp(&mut meta, data);
+        "webp"
+    } else if data.starts_with(b"BM") {
+        meta.width = le32(data, 18);
+        // Negative height marks a top-down bitmap
+        meta.height = data
+            .get(22..26)
+            .and_then(|b| b.try_into().ok())
+            .map(|b| i32::from_le_bytes(b).unsigned_abs());
+        "bmp"
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        read_tiff_file(&mut meta, data);
+        "tiff"
+    } else {
+        return None;
+    };
+    meta.format = format.to_string();
+    Some(meta)
+}
+
+/// Remove EXIF (and XMP) metadata from JPEG, PNG and WebP images
+///
+/// Other formats, and images without metadata, are returned unchanged.
+pub fn strip_exif(data: &[u8]) -> Vec<u8> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        let segments = jpeg_segments(data);
+        let mut out = Vec::with_capacity(data.len());
+        out.extend_from_slice(&data[..2]);
+        let mut copied_to = 2;
+        for (marker, range) in segments {
+            let segment_start = range.start - 4;
+            out.extend_from_slice(&data[copied_to..segment_start]);
+            if marker != 0xE1 {
+                out.extend_from_slice(&data[segment_start..range.end]);
+            }
+            copied_to = range.end;
+        }
+        out.extend_from_slice(&data[copied_to..]);
+        return out;
+    }
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let mut out = data[..8].to_vec();
+        for (kind, range) in png_chunks(data) {
+            if &kind != b"eXIf" {
+                out.extend_from_slice(&data[range.start - 8..range.end + 4]);
+            }
+        }
+        return out;
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        let mut out = data[..12].to_vec();
+        for (kind, range) in webp_chunks(data) {
+            if &kind == b"EXIF" || &kind == b"XMP " {
+                continue;
+            }
+            let chunk_start = out.len();
+            let padded_end = (range.end + (range.len() & 1)).min(data.len());
+            out.extend_from_slice(&data[range.start - 8..padded_end]);
+            if &kind == b"VP8X" {
+                out[chunk_start + 8] &= !0x0C; // clear the EXIF and XMP flags
+            }
+        }
+        if let Ok(riff_len) = u32::try_from(out.len() - 8) {
+            out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        }
+        return out;
+    }
+    data.to_vec()
+}
+
+/// Inspect an image's format, dimensions and EXIF without decoding it
+///
+/// # Arguments
+/// * `data` - Encoded image bytes (JPEG, PNG, GIF, WebP, BMP or TIFF)
+///
+/// # Returns
+/// * `ImageMetadata` (`format`, `width`, `height`, `size`, `has_exif`,
+///   `capture_time`, `has_gps`), or None for unrecognised data
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import image_metadata_fast
+/// meta = image_metadata_fast(open("photo.jpg", "rb").read())
+/// print(meta.width, meta.height, meta.capture_time, meta.has_gps)
+/// ```
+#[pyfunction]
+pub fn image_metadata_fast(data: &[u8]) -> Option<ImageMetadata> {
+    inspect(data)
+}
+
+/// Metadata of every image attachment in a raw message
+///
+/// # Arguments
+/// * `raw_bytes` - Complete RFC 822 message
+///
+/// # Returns
+/// * One `ImageMetadata` per recognised image attachment, with `filename` set
+#[pyfunction]
+pub fn attachment_image_metadata(raw_bytes: &[u8]) -> Vec<ImageMetadata> {
+    let msg = Message::parse(raw_bytes);
+    msg.parts()
+        .iter()
+        .filter(|p| p.is_attachment())
+        .filter_map(|p| {
+            let mut meta = inspect(&p.decoded())?;
+            meta.filename = p.filename();
+            Some(meta)
+        })
+        .collect()
+}
+
+/// Remove EXIF/XMP metadata (capture time, GPS, camera) from an image
+///
+/// JPEG APP1 segments, PNG `eXIf` chunks and WebP `EXIF`/`XMP ` chunks are
+/// dropped without re-encoding pixels; other formats are returned unchanged.
+///
+/// # Arguments
+/// * `data` - Encoded image bytes
+///
+/// # Returns
+/// * Image bytes without metadata
+#[pyfunction]
+pub fn strip_exif_fast<'py>(py: Python<'py>, data: &[u8]) -> Bound<'py, PyBytes> {
+    PyBytes::new(py, &strip_exif(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Big-endian EXIF block with `DateTimeOriginal` and a GPS latitude
+    fn exif_block() -> Vec<u8> {
+        let mut t = b"MM\0*\0\0\0\x08".to_vec();
+        // IFD0 at 8: two entries (ExifIFD -> 38, GPS -> 76), next IFD 0
+        t.extend_from_slice(&[0, 2]);
+        t.extend_from_slice(&[0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 38]);
+        t.extend_from_slice(&[0x88, 0x25, 0, 4, 0, 0, 0, 1, 0, 0, 0, 76]);
+        t.extend_from_slice(&[0, 0, 0, 0]);
+        // Exif IFD at 38: DateTimeOriginal ASCII[20] at 56
+        t.extend_from_slice(&[0, 1, 0x90, 0x03, 0, 2, 0, 0, 0, 20, 0, 0, 0, 56, 0, 0, 0, 0]);
+        t.extend_from_slice(b"2023:07:14 09:30:00\0");
+        // GPS IFD at 76: GPSLatitude (RATIONAL, contents irrelevant here)
+        t.extend_from_slice(&[0, 1, 0, 2, 0, 5, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0]);
+        t
+    }
+
+    fn jpeg_with_exif() -> Vec<u8> {
+        let mut app1 = EXIF_HEADER.to_vec();
+        app1.extend_from_slice(&exif_block());
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&u16::try_from(app1.len() + 2).unwrap().to_be_bytes());
+        jpeg.extend_from_slice(&app1);
+        // SOF0: precision 8, height 480, width 640, 1 component
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0, 11, 8, 0x01, 0xE0, 0x02, 0x80, 1, 1, 0x11, 0]);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0xAB, 0xCD, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn test_jpeg_metadata_and_strip() {
+        let jpeg = jpeg_with_exif();
+        let meta = inspect(&jpeg).unwrap();
+        assert_eq!(meta.format, "jpeg");
+        assert_eq!((meta.width, meta.height), (Some(640), Some(480)));
+        assert!(meta.has_exif);
+        assert!(meta.has_gps);
+        assert_eq!(meta.capture_time.as_deref(), Some("2023-07-14T09:30:00"));
+
+        let stripped = strip_exif(&jpeg);
+        let meta = inspect(&stripped).unwrap();
+        assert!(!meta.has_exif && !meta.has_gps && meta.capture_time.is_none());
+        assert_eq!((meta.width, meta.height), (Some(640), Some(480)));
+        assert!(stripped.ends_with(&[0xAB, 0xCD, 0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn test_other_formats() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0, 200, 8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        let exif = exif_block();
+        png.extend_from_slice(&u32::try_from(exif.len()).unwrap().to_be_bytes());
+        png.extend_from_slice(b"eXIf");
+        png.extend_from_slice(&exif);
+        png.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND\xae\x42\x60\x82");
+        let meta = inspect(&png).unwrap();
+        assert_eq!((meta.width, meta.height), (Some(256), Some(200)));
+        assert!(meta.has_gps);
+        assert!(!inspect(&strip_exif(&png)).unwrap().has_exif);
+
+        let gif = b"GIF89a\x10\0\x20\0";
+        let meta = inspect(gif).unwrap();
+        assert_eq!((meta.format.as_str(), meta.width, meta.height), ("gif", Some(16), Some(32)));
+        assert!(inspect(b"plain text").is_none());
+    }
+}
@@ -0,0 +1,316 @@
+//! Caller-declared fields of the [`crate::archive_set`] index.
+//!
+//! Beyond the built-in fields (subject, addresses, body, attachments), a
+//! deployment can index labels, arbitrary headers, extracted entities and the
+//! output of native extractors (see [`crate::plugins`]).
+//! Each field says where its values come from and whether it is searched
+//! (`indexed`), returned with hits (`stored`) or counted by facet queries
+//! (`facet`). Values are computed once, when a source is added.
+
+use crate::extract::TokenExtractor;
+use crate::mime::Message;
+use crate::plugins::{self, Extractor, MessageView};
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Built-in search fields; custom fields may not reuse these names
+pub const BUILTIN_FIELDS: &[&str] = &["subject", "from", "to", "cc", "body", "attachments"];
+
+/// Headers holding mailbox labels/keywords, comma-separated
+const LABEL_HEADERS: &[&str] = &["X-Gmail-Labels", "X-Keywords", "Keywords", "X-Label"];
+
+/// Where a field's values come from
+#[derive(Clone, Debug)]
+enum FieldSource {
+    /// Decoded value of one header (every occurrence)
+    Header(String),
+    /// Labels from `LABEL_HEADERS`
+    Labels,
+    /// Matches of an extractor kind (see `extract_tokens_fast`) in subject and body
+    Extract(String),
+    /// Values of a registered native extractor
+    Plugin(String),
+}
+
+impl FieldSource {
+    fn parse(source: &str) -> PyResult<Self> {
+        let error = || {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid field source: {source:?} (expected \"labels\", \"header:<Name>\", \
+                 \"extract:<kind>\" or \"plugin:<name>\")"
+            ))
+        };
+        match source.split_once(':') {
+            None if source == "labels" => Ok(Self::Labels),
+            Some(("header", name)) if !name.trim().is_empty() => {
+                Ok(Self::Header(name.trim().to_string()))
+            },
+            Some(("extract", kind)) if !kind.trim().is_empty() => {
+                Ok(Self::Extract(kind.trim().to_string()))
+            },
+            Some(("plugin", name)) if !name.trim().is_empty() => {
+                Ok(Self::Plugin(name.trim().to_string()))
+            },
+            _ => Err(error()),
+        }
+    }
+}
+
+/// One custom field of an `ArchiveSet` index
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import ArchiveSet, IndexField
+/// archives = ArchiveSet(schema=[
+///     IndexField("labels", "labels", facet=True),
+///     IndexField("team", "header:X-Team", facet=True),
+///     IndexField("tickets", "extract:ticket"),
+///     IndexField("spam_score", "header:X-Spam-Score", indexed=False),
+/// ])
+/// ```
+#[derive(Serialize, Clone, Debug)]
+#[pyclass]
+pub struct IndexField {
+    /// Field name, used in `search(fields=...)` and on hits
+    #[pyo3(get)]
+    pub name: String,
+    /// "labels", "header:<Name>", "extract:<kind>" or "plugin:<name>"
+    #[pyo3(get)]
+    pub source: String,
+    /// Whether queries match the field's values
+    #[pyo3(get)]
+    pub indexed: bool,
+    /// Whether hits carry the field's values
+    #[pyo3(get)]
+    pub stored: bool,
+    /// Whether facet queries count the field's values
+    #[pyo3(get)]
+    pub facet: bool,
+    #[serde(skip)]
+    parsed: FieldSource,
+}
+
+#[pymethods]
+impl IndexField {
+    /// Declare a field
+    ///
+    /// # Arguments
+    /// * `name` - Field name (not one of the built-in search fields)
+    /// * `source` - "labels" (X-Gmail-Labels, X-Keywords, Keywords, X-Label),
+    ///   "header:<Name>" (decoded header), "extract:<kind>" (extractor
+    ///   matches in subject and body, see `extract_tokens_fast`) or
+    ///   "plugin:<name>" (a native extractor, see `register_native_extractor`)
+    /// * `indexed` - Match queries against the values (default true)
+    /// * `stored` - Return the values on hits (default true)
+    /// * `facet` - Count the values in facet queries (default false)
+    ///
+    /// # Raises
+    /// * `ValueError` for a malformed source or a built-in field name
+    #[new]
+    #[pyo3(signature = (name, source, indexed=true, stored=true, facet=false))]
+    pub fn new(
+        name: &str,
+        source: &str,
+        indexed: bool,
+        stored: bool,
+        facet: bool,
+    ) -> PyResult<Self> {
+        if name.is_empty() || BUILTIN_FIELDS.contains(&name) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid field name: {name:?} (empty or built-in)"
+            )));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            source: source.to_string(),
+            indexed,
+            stored,
+            facet,
+            parsed: FieldSource::parse(source)?,
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "IndexField({:?}, {:?}, indexed={}, stored={}, facet={})",
+            self.name,
+            self.source,
+            if self.indexed { "True" } else { "False" },
+            if self.stored { "True" } else { "False" },
+            if self.facet { "True" } else { "False" }
+        )
+    }
+}
+
+/// Comma-separated labels; a quoted label may contain commas
+fn split_labels(value: &str) -> Vec<String> {
+    let mut labels = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => labels.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    labels.push(current.trim().to_string());
+    labels
+}
+
+/// Mailbox labels of a message, without repeats
+pub fn labels(msg: &Message) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for value in LABEL_HEADERS
+        .iter()
+        .filter_map(|name| msg.decoded_header(name))
+    {
+        for label in split_labels(&value) {
+            if !label.is_empty() && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+    }
+    labels
+}
+
+/// Validated custom fields plus the extractors they use
+#[derive(Default)]
+pub struct Schema {
+    pub fields: Vec<IndexField>,
+    extractor: Option<TokenExtractor>,
+    /// Native extractors of `plugin:` fields, resolved when the schema is built
+    plugins: Vec<Arc<dyn Extractor>>,
+}
+
+impl Schema {
+    /// Check names for duplicates and compile the extractor kinds
+    pub fn new(fields: Vec<IndexField>) -> PyResult<Self> {
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].iter().any(|f| f.name == field.name) {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Duplicate index field: {}",
+                    field.name
+                )));
+            }
+        }
+        let mut kinds: Vec<String> = Vec::new();
+        let mut plugins: Vec<Arc<dyn Extractor>> = Vec::new();
+        for field in &fields {
+            match &field.parsed {
+                FieldSource::Extract(kind) if !kinds.contains(kind) => kinds.push(kind.clone()),
+                FieldSource::Plugin(name) if !plugins.iter().any(|p| p.name() == name) => {
+                    plugins.push(plugins::find(name).ok_or_else(|| {
+                        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                            "Unknown native extractor: {name:?}"
+                        ))
+                    })?);
+                },
+                _ => {},
+            }
+        }
+        let extractor = if kinds.is_empty() {
+            None
+        } else {
+            Some(TokenExtractor::new(Some(kinds), &[])?)
+        };
+        Ok(Self {
+            fields,
+            extractor,
+            plugins,
+        })
+    }
+
+    /// Position of a custom field by name
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+
+    /// Values of every field for one message (`raw`, parsed as `msg`), in
+    /// schema order and without repeats; `keywords` (from a Maildir file
+    /// name) are labels too
+    pub fn values(&self, raw: &[u8], msg: &Message, keywords: &[String]) -> Vec<Vec<String>> {
+        let view = MessageView::new(raw, msg);
+        let extracted = self.extractor.as_ref().map(|extractor| {
+            let subject = msg.decoded_header("Subject").unwrap_or_default();
+            extractor.extract(&format!("{subject}\n{}", msg.text_body()))
+        });
+        self.fields
+            .iter()
+            .map(|field| {
+                let raw: Vec<String> = match &field.parsed {
+                    FieldSource::Header(name) => msg
+                        .headers
+                        .iter()
+                        .filter(|h| h.name.eq_ignore_ascii_case(name))
+                        .map(|h| {
+                            crate::mime::decode_encoded_words(&h.value)
+                                .trim()
+                                .to_string()
+                        })
+                        .collect(),
+                    FieldSource::Labels => labels(msg)
+                        .into_iter()
+                        .chain(keywords.iter().cloned())
+                        .collect(),
+                    FieldSource::Extract(kind) => extracted
+                        .as_ref()
+                        .and_then(|found| found.get(kind).cloned())
+                        .unwrap_or_default(),
+                    FieldSource::Plugin(name) => self
+                        .plugins
+                        .iter()
+                        .find(|p| p.name() == name)
+                        .map(|p| p.extract(&view))
+                        .unwrap_or_default(),
+                };
+                let mut values: Vec<String> = Vec::with_capacity(raw.len());
+                for value in raw {
+                    if !value.is_empty() && !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                values
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, source: &str) -> IndexField {
+        IndexField::new(name, source, true, true, false).unwrap()
+    }
+
+    #[test]
+    fn test_schema_values() {
+        let schema = Schema::new(vec![
+            field("labels", "labels"),
+            field("team", "header:x-team"),
+            field("tickets", "extract:ticket"),
+        ])
+        .unwrap();
+        let raw = b"X-Gmail-Labels: Inbox,Important,\"Work, Q3\"\nX-Keywords: Important\n\
+                    X-Team: =?utf-8?q?Plattform?=\nX-Team: Ops\nSubject: ABC-12 done\n\n\
+                    Also see ABC-12 and OPS-7.\n";
+        let msg = Message::parse(raw);
+        let values = schema.values(raw, &msg, &[]);
+        assert_eq!(values[0], ["Inbox", "Important", "Work, Q3"]);
+        assert_eq!(values[1], ["Plattform", "Ops"]);
+        assert_eq!(values[2], ["ABC-12", "OPS-7"]);
+        assert_eq!(schema.position("team"), Some(1));
+    }
+
+    #[test]
+    fn test_schema_errors() {
+        assert!(IndexField::new("body", "labels", true, true, false).is_err());
+        assert!(IndexField::new("x", "header:", true, true, false).is_err());
+        assert!(IndexField::new("x", "subject", true, true, false).is_err());
+        assert!(Schema::new(vec![field("a", "labels"), field("a", "labels")]).is_err());
+        assert!(Schema::new(vec![field("a", "extract:nope")]).is_err());
+        assert!(Schema::new(vec![field("a", "plugin:nope")]).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//! JSON Lines export of parsed messages.
+//!
+//! `mbox_to_jsonl` writes one JSON object per message: selected header
+//! fields, the decoded bodies and attachment metadata. Messages are parsed
+//! and serialized in parallel a batch at a time and the lines written in
+//! mbox order, so line `n` of the output is always message `n`.
+
+use crate::checkpoint::{self, Checkpointer};
+use crate::date::parse_date;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{decode_encoded_words, Message, Part};
+use crate::output::{Output, OutputReport};
+use crate::progress::Progress;
+use crate::quotes::strip_quotes;
+use crate::redact::sha256_hex;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Every field, in the default order
+const FIELDS: &[&str] = &[
+    "index",
+    "offset",
+    "size",
+    "uid",
+    "message_id",
+    "date",
+    "date_epoch",
+    "from",
+    "to",
+    "cc",
+    "subject",
+    "headers",
+    "body",
+    "html",
+    "attachments",
+];
+
+/// Fields left out unless asked for
+const OPT_IN_FIELDS: &[&str] = &["headers", "html"];
+
+const FLAGS: &[&str] = &[
+    "raw_headers",
+    "strip_quotes",
+    "attachment_sha256",
+    "omit_null",
+];
+
+/// Messages serialized in parallel before being written in order
+const BATCH_SIZE: usize = 4096;
+
+fn value_error(message: String) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(message)
+}
+
+/// Validate a `fields=` argument; `None` selects all but the opt-in fields
+fn parse_fields(fields: Option<Vec<String>>) -> PyResult<Vec<&'static str>> {
+    let Some(names) = fields else {
+        return Ok(FIELDS
+            .iter()
+            .copied()
+            .filter(|f| !OPT_IN_FIELDS.contains(f))
+            .collect());
+    };
+    let mut selected = Vec::with_capacity(names.len());
+    for name in names {
+        let field = FIELDS.iter().copied().find(|f| *f == name).ok_or_else(|| {
+            value_error(format!("Unknown field: {name:?} (expected one of {})", FIELDS.join(", ")))
+        })?;
+        if selected.contains(&field) {
+            return Err(value_error(format!("Duplicate field: {name:?}")));
+        }
+        selected.push(field);
+    }
+    Ok(selected)
+}
+
+/// Serialization options of `mbox_to_jsonl`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+struct Flags {
+    /// Keep RFC 2047 encoded-words in header values
+    raw_headers: bool,
+    /// Drop quoted replies and signatures from `body`
+    strip_quotes: bool,
+    /// Hash each attachment's decoded content
+    attachment_sha256: bool,
+    /// Leave out keys whose value is null
+    omit_null: bool,
+}
+
+impl Flags {
+    fn parse(flags: Option<Vec<String>>) -> PyResult<Self> {
+        let mut parsed = Self::default();
+        for name in flags.unwrap_or_default() {
+            let flag = match name.as_str() {
+                "raw_headers" => &mut parsed.raw_headers,
+                "strip_quotes" => &mut parsed.strip_quotes,
+                "attachment_sha256" => &mut parsed.attachment_sha256,
+                "omit_null" => &mut parsed.omit_null,
+                _ => {
+                    return Err(value_error(format!(
+                        "Unknown flag: {name:?} (expected one of {})",
+                        FLAGS.join(", ")
+                    )))
+                },
+            };
+            *flag = true;
+        }
+        Ok(parsed)
+    }
+}
+
+/// One attachment in a record
+#[derive(Serialize)]
+struct Attachment {
+    filename: Option<String>,
+    content_type: String,
+    /// Decoded size in bytes
+    size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl Attachment {
+    fn new(part: &Part<'_>, hash: bool) -> Self {
+        let data = part.decoded();
+        Self {
+            filename: part.filename(),
+            content_type: part.mime_type().to_string(),
+            size: data.len(),
+            sha256: hash.then(|| sha256_hex(&data)),
+        }
+    }
+}
+
+/// A JSON object written key by key, in field order
+struct Record {
+    line: String,
+    omit_null: bool,
+}
+
+impl Record {
+    fn push(&mut self, key: &str, value: &impl Serialize) {
+        let value = serde_json::to_string(value).unwrap_or_else(|_| "null".to_string());
+        if self.omit_null && value == "null" {
+            return;
+        }
+        self.line.push(if self.line.is_empty() { '{' } else { ',' });
+        self.line
+            .push_str(&serde_json::to_string(key).unwrap_or_default());
+        self.line.push(':');
+        self.line.push_str(&value);
+    }
+
+    fn finish(mut self) -> String {
+        if self.line.is_empty() {
+            self.line.push('{');
+        }
+        self.line.push_str("}\n");
+        self.line
+    }
+}
+
+/// The JSON line of message `index`, found at `span` of the mbox
+fn message_line(
+    raw: &[u8],
+    index: usize,
+    span: &Range<usize>,
+    fields: &[&str],
+    flags: Flags,
+) -> String {
+    let msg = Message::parse(raw);
+    let header = |name: &str| {
+        msg.header(name).map(|value| {
+            if flags.raw_headers {
+                value.to_string()
+            } else {
+                decode_encoded_words(value)
+            }
+        })
+    };
+    let mut record = Record {
+        line: String::new(),
+        omit_null: flags.omit_null,
+    };
+    for &field in fields {
+        match field {
+            "index" => record.push(field, &index),
+            "offset" => record.push(field, &span.start),
+            "size" => record.push(field, &span.len()),
+            "uid" => record.push(field, &message_uid(raw)),
+            "message_id" => record.push(field, &msg.header("Message-ID").map(str::trim)),
+            "date" => record.push(field, &msg.header("Date")),
+            "date_epoch" => {
+                let epoch = msg
+                    .header("Date")
+                    .and_then(parse_date)
+                    .map(|(epoch, _)| epoch);
+                record.push(field, &epoch);
+            },
+            "from" => record.push(field, &header("From")),
+            "to" => record.push(field, &header("To")),
+            "cc" => record.push(field, &header("Cc")),
+            "subject" => record.push(field, &header("Subject")),
+            "headers" => {
+                let headers: Vec<[String; 2]> = msg
+                    .headers
+                    .iter()
+                    .map(|h| {
+                        let value = if flags.raw_headers {
+                            h.value.clone()
+                        } else {
+                            decode_encoded_words(&h.value)
+                        };
+                        [h.name.clone(), value]
+                    })
+                    .collect();
+                record.push(field, &headers);
+            },
+            "body" => {
+                let body = msg.text_body();
+                let body = if flags.strip_quotes {
+                    strip_quotes(&body)
+                } else {
+                    body
+                };
+                record.push(field, &body);
+            },
+            "html" => {
+                let html = msg
+                    .parts()
+                    .into_iter()
+                    .find(|p| p.mime_type() == "text/html" && !p.is_attachment())
+                    .map(|p| p.text());
+                record.push(field, &html);
+            },
+            "attachments" => {
+                let attachments: Vec<Attachment> = msg
+                    .parts()
+                    .iter()
+                    .filter(|p| p.is_attachment())
+                    .map(|p| Attachment::new(p, flags.attachment_sha256))
+                    .collect();
+                record.push(field, &attachments);
+            },
+            _ => {},
+        }
+    }
+    record.finish()
+}
+
+/// Write every message of an mbox as one JSON line
+///
+/// Fields, in the order given: `index` (position in the mbox), `offset` and
+/// `size` (bytes), `uid` (see `compute_message_uid`), `message_id`, `date`
+/// (the raw header), `date_epoch` (Unix time, null if unparseable), `from`,
+/// `to`, `cc` and `subject` (header values), `headers` (every header as a
+/// `[name, value]` pair, in order), `body` (the first text/plain part, else
+/// the HTML flattened to text), `html` (the first text/html part) and
+/// `attachments` (`filename`, `content_type` and decoded `size` of each).
+/// Missing headers are null.
+///
+/// Flags: `raw_headers` keeps RFC 2047 encoded-words in header values,
+/// `strip_quotes` removes quoted replies and signatures from `body`,
+/// `attachment_sha256` adds a `sha256` of each attachment's content and
+/// `omit_null` leaves out null values instead of writing them.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `out_path` - Output `.jsonl` file (`.gz` / `.zst` are compressed)
+/// * `fields` - Fields to write (default: all but `headers` and `html`)
+/// * `flags` - Serialization flags (default: none)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+/// * `checkpoint` - Checkpoint file; an interrupted export resumes from it
+///   when called again (see `load_checkpoint`)
+///
+/// # Returns
+/// * `OutputReport` - Messages written, bytes and the output file
+///
+/// # Raises
+/// * `ValueError` - An unknown or repeated field, or an unknown flag
+/// * `IOError` - The mbox cannot be read or the output cannot be written
+/// * `FileChangedError` - The mbox changed during the scan (nothing is written)
+/// * `ScanCancelled` - `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import mbox_to_jsonl
+/// mbox_to_jsonl("inbox.mbox", "inbox.jsonl.zst",
+///               fields=["uid", "subject", "body"], flags=["strip_quotes"])
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    mbox_path,
+    out_path,
+    fields=None,
+    flags=None,
+    dry_run=false,
+    fsync="file",
+    from_line_mode="standard",
+    progress=None,
+    checkpoint=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn mbox_to_jsonl(
+    py: Python<'_>,
+    mbox_path: &str,
+    out_path: &str,
+    fields: Option<Vec<String>>,
+    flags: Option<Vec<String>>,
+    dry_run: bool,
+    fsync: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+    checkpoint: Option<&str>,
+) -> PyResult<OutputReport> {
+    let fields = parse_fields(fields)?;
+    let flags = Flags::parse(flags)?;
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?;
+        let checkpoint = checkpoint.filter(|_| !dry_run).map(Path::new);
+        let mut checkpointer =
+            Checkpointer::open(checkpoint, "mbox_to_jsonl", Path::new(mbox_path))
+                .map_err(|e| checkpoint::error(&e))?;
+        let mmap = map_file(mbox_path)?;
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        let io_error = |e: io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {out_path}: {e}"))
+        };
+        let mut file = checkpointer
+            .create(&output, Path::new(out_path))
+            .map_err(io_error)?;
+        let done = checkpointer
+            .resume_point(&spans, &mut file)
+            .map_err(io_error)?;
+        progress.advance(spans[..done].iter().map(ExactSizeIterator::len).sum());
+        for (batch_no, batch) in spans[done..].chunks(BATCH_SIZE).enumerate() {
+            if !progress.messages(batch) {
+                break;
+            }
+            let first = done + batch_no * BATCH_SIZE;
+            let lines: Vec<String> = batch
+                .par_iter()
+                .enumerate()
+                .map(|(i, span)| message_line(&mmap[span.clone()], first + i, span, &fields, flags))
+                .collect();
+            for line in lines {
+                file.write_all(line.as_bytes()).map_err(io_error)?;
+                output.note_message();
+            }
+            let end = batch.last().map_or(0, |span| span.end);
+            checkpointer
+                .record(&mmap, end, first + batch.len(), &mut file)
+                .map_err(|e| checkpoint::error(&e))?;
+        }
+        if let Err(e) = progress.check("mbox_to_jsonl") {
+            checkpointer
+                .save(&mmap, &mut file)
+                .map_err(|e| checkpoint::error(&e))?;
+            return Err(e);
+        }
+        mmap.verify(&mode)?;
+        file.finish().map_err(io_error)?;
+        checkpointer.finish().map_err(|e| checkpoint::error(&e))?;
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From a Mon Jan  1 00:00:00 2024\n\
+                        From: =?utf-8?q?Jos=C3=A9?= <jose@x.org>\nTo: b@x.org\n\
+                        Subject: Report\nDate: Mon, 1 Jan 2024 00:00:00 +0000\n\
+                        Message-ID: <1@x>\n\
+                        Content-Type: multipart/mixed; boundary=\"b\"\n\n--b\n\
+                        Content-Type: text/plain\n\nSee attached\n\n> old text\n--b\n\
+                        Content-Type: application/pdf\n\
+                        Content-Disposition: attachment; filename=\"a.pdf\"\n\
+                        Content-Transfer-Encoding: base64\n\nUERG\n--b--\n\n\
+                        From b Mon Jan  1 00:00:00 2024\nSubject: Two\n\nBody\n";
+
+    fn lines(fields: &[&str], flags: Flags) -> Vec<serde_json::Value> {
+        let spans = crate::mbox::message_spans_with(MBOX.as_bytes(), &FromLineMode::Standard);
+        spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| {
+                let line = message_line(&MBOX.as_bytes()[span.clone()], i, span, fields, flags);
+                assert!(line.ends_with("}\n") && line.matches('\n').count() == 1);
+                serde_json::from_str(&line).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_message_line() {
+        let fields = parse_fields(None).unwrap();
+        assert!(!fields.contains(&"headers"));
+        let records = lines(&fields, Flags::default());
+        let first = &records[0];
+        assert_eq!(first.as_object().unwrap().len(), fields.len());
+        assert_eq!(first["index"], 0);
+        assert_eq!(first["from"], "José <jose@x.org>");
+        assert_eq!(first["date_epoch"], 1_704_067_200);
+        assert_eq!(first["message_id"], "<1@x>");
+        assert_eq!(first["body"], "See attached\n\n> old text");
+        assert_eq!(first["attachments"][0]["filename"], "a.pdf");
+        assert_eq!(first["attachments"][0]["size"], 3);
+        assert!(first["attachments"][0].get("sha256").is_none());
+        assert_eq!(records[1]["index"], 1);
+        assert!(records[1]["from"].is_null());
+
+        let flags = Flags::parse(Some(vec![
+            "raw_headers".into(),
+            "strip_quotes".into(),
+            "attachment_sha256".into(),
+            "omit_null".into(),
+        ]))
+        .unwrap();
+        let fields = ["subject", "from", "headers", "body", "attachments"];
+        let line = message_line(MBOX.as_bytes(), 0, &(0..MBOX.len()), &fields, flags);
+        assert!(line.starts_with("{\"subject\":\"Report\",\"from\":\"=?utf-8?q?Jos=C3=A9?="));
+        let records = lines(&fields, flags);
+        assert_eq!(records[0]["headers"][1], serde_json::json!(["To", "b@x.org"]));
+        assert_eq!(records[0]["body"].as_str().unwrap().trim_end(), "See attached");
+        assert_eq!(records[0]["attachments"][0]["sha256"], sha256_hex(b"PDF"));
+        assert!(records[1].get("from").is_none());
+
+        assert!(parse_fields(Some(vec!["nope".into()])).is_err());
+        assert!(parse_fields(Some(vec!["uid".into(), "uid".into()])).is_err());
+        assert!(Flags::parse(Some(vec!["nope".into()])).is_err());
+    }
+
+    #[test]
+    fn test_mbox_to_jsonl() {
+        let dir = std::env::temp_dir().join(format!("mpr_jsonl_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("in.mbox");
+        std::fs::write(&src, MBOX).unwrap();
+        let out = dir.join("out.jsonl");
+        let report = crate::with_py(|py| {
+            mbox_to_jsonl(
+                py,
+                src.to_str().unwrap(),
+                out.to_str().unwrap(),
+                Some(vec!["index".into(), "subject".into()]),
+                None,
+                false,
+                "none",
+                "standard",
+                None,
+                None,
+            )
+        })
+        .unwrap();
+        assert_eq!(report.message_count, 2);
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(
+            written,
+            "{\"index\":0,\"subject\":\"Report\"}\n{\"index\":1,\"subject\":\"Two\"}\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,6 @@
 use chardetng::EncodingDetector;
 use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use lazy_static::lazy_static;
-use memmap2::Mmap;
 ///! High-Performance Email Parsing via Rust/PyO3
 ///!
 ///! This module provides blazing-fast email parsing utilities that are 10-100x faster
@@ -12,57 +11,167 @@ use memmap2::Mmap;
 ///! - Parallel processing with rayon
 ///! - Fast encoding detection
 use pyo3::prelude::*;
-use rayon::prelude::*;
+use pyo3::types::{PyBytes, PyString};
 use regex::Regex;
-use std::fs::File;
+
+use deadline::Deadline;
+use patterns::compile;
+use progress::Progress;
+
+mod activity;
+mod address;
+mod alignment;
+mod anomalies;
+mod archive;
+mod archive_set;
+mod arrow;
+mod attachments;
+mod auth_results;
+mod batch;
+mod benchmark;
+mod bloom;
+mod body_cache;
+mod calendar;
+mod checkpoint;
+mod completeness;
+mod compressed;
+mod contacts;
+mod conversations;
+mod cooccurrence;
+mod corpus;
+mod date;
+mod deadline;
+mod dedup;
+mod diff;
+mod direction;
+mod dkim;
+mod dlp;
+mod doc_text;
+mod dovecot;
+mod embedding;
+mod eml;
+mod extract;
+mod filter;
+mod folders;
+mod forensic;
+mod gaps;
+mod geoip;
+mod html;
+mod html_archive;
+mod identities;
+mod images;
+mod index_schema;
+mod jsonl;
+mod locking;
+mod maildir;
+mod maildirpp;
+mod main_content;
+mod markdown;
+mod mbox;
+mod mime;
+mod offset_index;
+mod output;
+mod parquet;
+mod parse;
+mod paths;
+mod patterns;
+#[cfg(feature = "pdf")]
+mod pdf;
+pub mod plugins;
+mod progress;
+mod quarantine;
+mod questions;
+mod quotes;
+mod reader;
+mod received;
+mod recipients;
+mod redact;
+mod repair;
+mod rethread;
+mod schema;
+mod search;
+#[cfg(feature = "search-index")]
+mod search_index;
+mod senders;
+mod sentiment;
+mod sigbus;
+mod simhash;
+mod snapshot;
+mod substitute;
+mod terms;
+mod thread;
+mod thread_summary;
+mod tokens;
+mod trackers;
+mod transfer;
+mod tuning;
+mod uid;
 
 // Pre-compile commonly used regex patterns for maximum performance
 lazy_static! {
-    static ref FROM_PATTERN: Regex = Regex::new(r"^From ").unwrap();
+    static ref FROM_PATTERN: Regex = compile("core.from_line", r"^From ");
     static ref EMAIL_PATTERN: Regex =
-        Regex::new(r#"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}"#).unwrap();
-    static ref URL_PATTERN: Regex = Regex::new(r#"https?://[^\s<>"{}|\\^`\[\]]+"#).unwrap();
-    static ref HEADER_PATTERN: Regex = Regex::new(r"^([A-Za-z0-9-]+):\s*(.+)$").unwrap();
+        compile("core.email", r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}");
+    static ref URL_PATTERN: Regex = compile("core.url", r#"https?://[^\s<>"{}|\\^`\[\]]+"#);
 }
 
 /// Fast message counting using memory-mapped file (10-50x faster than Python)
 ///
 /// # Arguments
 /// * `path` - Path to the mbox file
+/// * `from_line_mode` - Which lines count as message separators:
+///   "standard" (default, a plausible `From sender date` envelope after an
+///   empty line), "strict" (RFC 4155 `From addr date` after an empty line),
+///   "lenient" (any "From " after an empty line), "permissive" (any line
+///   starting with "From "), "recover", or a custom regex matched against
+///   each line
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   during the scan; returning False cancels it (see `ScanCancelled`)
+///
+/// Gzip, zstd and xz compressed archives (`.mbox.gz`, `.mbox.zst`,
+/// `.mbox.xz`, recognized by content) are decompressed as a stream; the
+/// "recover" mode is not available for them.
 ///
 /// # Returns
 /// * Number of messages found (based on "From " lines)
 ///
+/// # Raises
+/// * `IOError` if the file cannot be read or its compressed data is corrupt
+/// * `FileChangedError` (an `IOError`) if the file is truncated, replaced
+///   or grows during the scan; `args[1]` is the offset before which
+///   messages were intact
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
 /// # Example
 /// ```python
 /// from mail_parser_rust import count_messages_fast
 /// count = count_messages_fast("emails.mbox")
 /// print(f"Found {count} messages")
+/// strict = count_messages_fast("export.mbox", from_line_mode="strict")
 /// ```
 #[pyfunction]
-fn count_messages_fast(path: &str) -> PyResult<usize> {
-    let file = File::open(path).map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open file: {}", e))
-    })?;
-
-    // Safety: We're opening in read-only mode
-    let mmap = unsafe {
-        Mmap::map(&file).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to mmap file: {}", e))
-        })?
-    };
-
-    // Count "From " lines using parallel processing
-    // Use par_bridge for parallel iteration over lines
-    let text = std::str::from_utf8(&mmap).unwrap_or("");
-
-    let count: usize = text
-        .lines()
-        .par_bridge()
-        .filter(|line| line.starts_with("From "))
-        .count();
-
-    Ok(count)
+#[pyo3(signature = (path, from_line_mode="standard", progress=None))]
+fn count_messages_fast(
+    py: Python<'_>,
+    path: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<usize> {
+    py.allow_threads(|| {
+        let mode = mbox::FromLineMode::parse(from_line_mode)?;
+        let mmap = mbox::map_file(path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let count = compressed::Codec::detect(&mmap).map_or_else(
+            || Ok(mmap.spans_reporting(&mode, &progress).len()),
+            |codec| compressed::message_spans(&mmap, codec, &mode).map(|spans| spans.len()),
+        );
+        // A file cut short reads as corrupt data; report the change instead
+        mmap.verify(&mode)?;
+        if !progress.finish() {
+            return Err(progress.error("count_messages_fast"));
+        }
+        count
+    })
 }
 
 /// Fast encoding detection (100x faster than Python chardet)
@@ -80,27 +189,29 @@ fn count_messages_fast(path: &str) -> PyResult<usize> {
 /// text = email_bytes.decode(encoding)
 /// ```
 #[pyfunction]
-fn detect_encoding_fast(data: &[u8]) -> PyResult<String> {
-    if data.is_empty() {
-        return Ok("UTF-8".to_string());
-    }
+fn detect_encoding_fast(py: Python<'_>, data: &[u8]) -> PyResult<String> {
+    py.allow_threads(|| {
+        if data.is_empty() {
+            return Ok("UTF-8".to_string());
+        }
 
-    // Fast path for ASCII/UTF-8
-    if data.iter().all(|&b| b < 128) {
-        return Ok("ASCII".to_string());
-    }
+        // Fast path for ASCII/UTF-8
+        if data.iter().all(|&b| b < 128) {
+            return Ok("ASCII".to_string());
+        }
 
-    // Try UTF-8 first (most common)
-    if std::str::from_utf8(data).is_ok() {
-        return Ok("UTF-8".to_string());
-    }
+        // Try UTF-8 first (most common)
+        if std::str::from_utf8(data).is_ok() {
+            return Ok("UTF-8".to_string());
+        }
 
-    // Use chardetng for more complex detection
-    let mut detector = EncodingDetector::new();
-    detector.feed(data, true);
-    let encoding = detector.guess(None, true);
+        // Use chardetng for more complex detection
+        let mut detector = EncodingDetector::new();
+        detector.feed(data, true);
+        let encoding = detector.guess(None, true);
 
-    Ok(encoding.name().to_string())
+        Ok(encoding.name().to_string())
+    })
 }
 
 /// Fast text decoding with fallback (10x faster than Python decode)
@@ -112,35 +223,37 @@ fn detect_encoding_fast(data: &[u8]) -> PyResult<String> {
 /// # Returns
 /// * Decoded string
 #[pyfunction]
-fn decode_fast(data: &[u8], encoding_hint: Option<&str>) -> PyResult<String> {
-    if data.is_empty() {
-        return Ok(String::new());
-    }
-
-    // Determine encoding
-    let encoding = if let Some(hint) = encoding_hint {
-        Encoding::for_label(hint.as_bytes()).unwrap_or(UTF_8)
-    } else {
-        // Auto-detect
-        if let Ok(s) = std::str::from_utf8(data) {
-            return Ok(s.to_string());
+fn decode_fast(py: Python<'_>, data: &[u8], encoding_hint: Option<&str>) -> PyResult<String> {
+    py.allow_threads(|| {
+        if data.is_empty() {
+            return Ok(String::new());
         }
 
-        // Try common encoding
-        if let (result, _encoding, false) = WINDOWS_1252.decode(data) {
-            return Ok(result.to_string());
-        }
+        // Determine encoding
+        let encoding = if let Some(hint) = encoding_hint {
+            Encoding::for_label(hint.as_bytes()).unwrap_or(UTF_8)
+        } else {
+            // Auto-detect
+            if let Ok(s) = std::str::from_utf8(data) {
+                return Ok(s.to_string());
+            }
 
-        UTF_8
-    };
+            // Try common encoding
+            if let (result, _encoding, false) = WINDOWS_1252.decode(data) {
+                return Ok(result.to_string());
+            }
 
-    let (result, _encoding, had_errors) = encoding.decode(data);
-    if had_errors {
-        // Fallback to lossy UTF-8
-        Ok(String::from_utf8_lossy(data).to_string())
-    } else {
-        Ok(result.to_string())
-    }
+            UTF_8
+        };
+
+        let (result, _encoding, had_errors) = encoding.decode(data);
+        if had_errors {
+            // Fallback to lossy UTF-8
+            Ok(String::from_utf8_lossy(data).to_string())
+        } else {
+            Ok(result.to_string())
+        }
+    })
 }
 
 /// Fast regex-based email extraction (10x faster than Python re)
@@ -158,13 +271,15 @@ fn decode_fast(data: &[u8], encoding_hint: Option<&str>) -> PyResult<String> {
 /// # Returns: ["john@example.com", "jane@test.org"]
 /// ```
 #[pyfunction]
-fn extract_emails_fast(text: &str) -> PyResult<Vec<String>> {
-    let emails: Vec<String> = EMAIL_PATTERN
-        .find_iter(text)
-        .map(|m| m.as_str().to_lowercase())
-        .collect();
-
-    Ok(emails)
+fn extract_emails_fast(py: Python<'_>, text: &str) -> PyResult<Vec<String>> {
+    py.allow_threads(|| {
+        let emails: Vec<String> = EMAIL_PATTERN
+            .find_iter(text)
+            .map(|m| m.as_str().to_lowercase())
+            .collect();
+
+        Ok(emails)
+    })
 }
 
 /// Fast URL extraction (10x faster than Python re)
@@ -175,56 +290,115 @@ fn extract_emails_fast(text: &str) -> PyResult<Vec<String>> {
 /// # Returns
 /// * List of URLs found
 #[pyfunction]
-fn extract_urls_fast(text: &str) -> PyResult<Vec<String>> {
-    let urls: Vec<String> = URL_PATTERN
-        .find_iter(text)
-        .map(|m| m.as_str().to_string())
-        .collect();
+fn extract_urls_fast(py: Python<'_>, text: &str) -> PyResult<Vec<String>> {
+    py.allow_threads(|| {
+        let urls: Vec<String> = URL_PATTERN
+            .find_iter(text)
+            .map(|m| m.as_str().to_string())
+            .collect();
+
+        Ok(urls)
+    })
+}
 
-    Ok(urls)
+/// Header fields of one message, in order, duplicates kept
+///
+/// Lookups are case-insensitive. `fields` holds every `(name, value)` pair
+/// as it appeared, so repeated fields such as `Received` keep their order.
+#[derive(serde::Serialize, Clone, Debug, Default)]
+#[pyclass]
+struct Headers {
+    /// `(name, value)` pairs in order; names as written, values unfolded
+    #[pyo3(get)]
+    fields: Vec<(String, String)>,
+}
+
+#[pymethods]
+impl Headers {
+    /// First value of `name`, or `default`
+    #[pyo3(signature = (name, default=None))]
+    fn get(&self, name: &str, default: Option<String>) -> Option<String> {
+        self.get_all(name).into_iter().next().or(default)
+    }
+
+    /// Every value of `name`, in order
+    fn get_all(&self, name: &str) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .collect()
+    }
+
+    /// Distinct field names, lowercased, in order of first appearance
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for (name, _) in &self.fields {
+            let name = name.to_ascii_lowercase();
+            if !keys.contains(&name) {
+                keys.push(name);
+            }
+        }
+        keys
+    }
+
+    fn __getitem__(&self, name: &str) -> PyResult<String> {
+        self.get(name, None)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>(name.to_string()))
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.fields
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case(name))
+    }
+
+    fn __len__(&self) -> usize {
+        self.fields.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Headers({} fields: {})", self.fields.len(), self.keys().join(", "))
+    }
 }
 
 /// Fast email header parsing (5-10x faster than Python email.parser)
 ///
-/// Parses email headers from raw text into alternating key-value lists.
+/// Parses the header block of a message (everything before the first blank
+/// line; a leading mbox `From ` line is skipped). Folded fields are
+/// unfolded per RFC 5322: each line break before whitespace is removed and
+/// the whitespace kept, so multi-line `Subject` and `Received` values come
+/// back whole. Values are not RFC 2047-decoded.
 ///
 /// # Arguments
-/// * `text` - Raw header text (everything before the first blank line)
+/// * `text` - Raw header text; anything after the first blank line is ignored
 ///
 /// # Returns
-/// * List of [key1, value1, key2, value2, ...] format (lowercase keys)
+/// * `Headers` with every field in order, duplicates kept
 ///
 /// # Example
 /// ```python
 /// from mail_parser_rust import parse_headers_fast
-/// headers_list = parse_headers_fast("From: john@example.com\nSubject: Test\n")
-/// # Returns: ["from", "john@example.com", "subject", "Test"]
-/// # Convert to dict with: dict(zip(headers_list[::2], headers_list[1::2]))
+/// headers = parse_headers_fast(
+///     "Received: from a by b;\n\tMon, 1 Jan 2024 00:00:00 +0000\n"
+///     "Received: from c by a; Mon, 1 Jan 2024 00:00:01 +0000\n"
+///     "Subject: a long\n subject\n"
+/// )
+/// headers["subject"]          # "a long subject"
+/// headers.get_all("Received") # both hops, newest first
+/// headers.fields              # [("Received", ...), ("Received", ...), ("Subject", ...)]
 /// ```
 #[pyfunction]
-fn parse_headers_fast(text: &str) -> PyResult<Vec<String>> {
-    let mut result = Vec::new();
-
-    for line in text.lines() {
-        if line.is_empty() {
-            break; // End of headers
-        }
-
-        // Handle header continuation (lines starting with whitespace)
-        if line.starts_with(|c: char| c.is_whitespace()) {
-            continue; // For simplicity, skip continuations in this version
-        }
-
-        // Parse "Header-Name: value" format
-        if let Some(captures) = HEADER_PATTERN.captures(line) {
-            if let (Some(name), Some(value)) = (captures.get(1), captures.get(2)) {
-                result.push(name.as_str().to_lowercase());
-                result.push(value.as_str().trim().to_string());
-            }
+fn parse_headers_fast(py: Python<'_>, text: &str) -> Headers {
+    py.allow_threads(|| {
+        let block = mime::strip_envelope(text.as_bytes());
+        Headers {
+            fields: mime::parse_header_block(block)
+                .into_iter()
+                .map(|h| (h.name, h.value))
+                .collect(),
         }
-    }
-
-    Ok(result)
+    })
 }
 
 /// Metadata extraction result for a single email text
@@ -299,17 +473,220 @@ fn process_metadata_batch(email_texts: Vec<String>) -> PyResult<Vec<EmailMetadat
 /// # Arguments
 /// * `pattern` - Regex pattern
 /// * `text` - Text to search
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
 ///
 /// # Returns
 /// * List of matches
+///
+/// # Raises
+/// * `TimeoutError` - `timeout_secs` passed; `partial` holds the matches
+///   found so far
 #[pyfunction]
-fn regex_findall_fast(pattern: &str, text: &str) -> PyResult<Vec<String>> {
+#[pyo3(signature = (pattern, text, timeout_secs=None))]
+fn regex_findall_fast(
+    py: Python<'_>,
+    pattern: &str,
+    text: &str,
+    timeout_secs: Option<f64>,
+) -> PyResult<Vec<String>> {
     let re = Regex::new(pattern).map_err(|e| {
         PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid regex: {}", e))
     })?;
+    let deadline = Deadline::new(timeout_secs)?;
+
+    let (matches, timed_out) = py.allow_threads(|| {
+        let mut matches = Vec::new();
+        for m in re.find_iter(text) {
+            if matches.len() % deadline::CHECK_EVERY == 0 && deadline.expired() {
+                return (matches, true);
+            }
+            matches.push(m.as_str().to_string());
+        }
+        (matches, false)
+    });
+    if timed_out {
+        return Err(deadline.error(py, "regex_findall_fast", matches));
+    }
+    Ok(matches)
+}
+
+/// Regex matches with their positions, like Python re.finditer
+///
+/// # Arguments
+/// * `pattern` - Regex pattern
+/// * `text` - Text to search
+/// * `overlapping` - Also report matches starting inside an earlier match:
+///   one match (the leftmost-first) per start position
+/// * `flags` - Python `re` flags, as for `regex_replace_fast`
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
+///
+/// # Returns
+/// * `(start, end, text)` tuples in order of `start`; offsets are character
+///   positions, so `text[start:end]` in Python is the match
+///
+/// # Raises
+/// * `ValueError` - Invalid pattern or unsupported flag
+/// * `TimeoutError` - `timeout_secs` passed; `partial` holds the matches
+///   found so far
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import regex_finditer_fast
+/// regex_finditer_fast(r"\d+", "Café 42, 7")          # [(5, 7, "42"), (9, 10, "7")]
+/// regex_finditer_fast(r"aba", "ababa", overlapping=True)  # [(0, 3, "aba"), (2, 5, "aba")]
+/// ```
+#[pyfunction]
+#[pyo3(signature = (pattern, text, overlapping=false, flags=0, timeout_secs=None))]
+fn regex_finditer_fast(
+    py: Python<'_>,
+    pattern: &str,
+    text: &str,
+    overlapping: bool,
+    flags: u32,
+    timeout_secs: Option<f64>,
+) -> PyResult<Vec<(usize, usize, String)>> {
+    let re = substitute::user_regex(pattern, flags)?;
+    let deadline = Deadline::new(timeout_secs)?;
+
+    let (matches, timed_out) = py.allow_threads(|| {
+        let mut matches = Vec::new();
+        // Byte and character offset of the last match start
+        let (mut byte, mut chars) = (0, 0);
+        let mut at = 0;
+        while let Some(m) = re.find_at(text, at) {
+            if matches.len() % deadline::CHECK_EVERY == 0 && deadline.expired() {
+                return (matches, true);
+            }
+            chars += text[byte..m.start()].chars().count();
+            byte = m.start();
+            let end = chars + m.as_str().chars().count();
+            matches.push((chars, end, m.as_str().to_string()));
+            at = if overlapping || m.is_empty() {
+                // Resume one character on, past an empty match
+                text[m.start()..]
+                    .chars()
+                    .next()
+                    .map_or(text.len() + 1, |c| m.start() + c.len_utf8())
+            } else {
+                m.end()
+            };
+            if at > text.len() {
+                break;
+            }
+        }
+        (matches, false)
+    });
+    if timed_out {
+        return Err(deadline.error(py, "regex_finditer_fast", matches));
+    }
+    Ok(matches)
+}
 
-    let matches: Vec<String> = re.find_iter(text).map(|m| m.as_str().to_string()).collect();
+/// One match of `regex_findall_bytes`
+#[derive(Debug)]
+#[pyclass]
+struct ByteMatch {
+    /// Byte offset of the match in the data
+    #[pyo3(get)]
+    start: usize,
+    /// Byte offset just past the match
+    #[pyo3(get)]
+    end: usize,
+    matched: Vec<u8>,
+    groups: Vec<Option<Vec<u8>>>,
+}
 
+#[pymethods]
+impl ByteMatch {
+    /// Matched bytes
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.matched)
+    }
+
+    /// Bytes of each capturing group (None if it did not take part)
+    #[getter]
+    fn groups<'py>(&self, py: Python<'py>) -> Vec<Option<Bound<'py, PyBytes>>> {
+        self.groups
+            .iter()
+            .map(|group| group.as_deref().map(|bytes| PyBytes::new(py, bytes)))
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ByteMatch(start={}, end={}, data={:?})",
+            self.start,
+            self.end,
+            String::from_utf8_lossy(&self.matched)
+        )
+    }
+}
+
+/// Regex matching over raw bytes, with byte offsets
+///
+/// Searches undecoded data (a whole message, a mixed-encoding header, a
+/// binary part) that `regex_findall_fast` cannot take. Unicode classes and
+/// `.` still match UTF-8 characters; turn Unicode off with `(?-u)` to match
+/// arbitrary bytes, e.g. `(?-u)\xff\xd8` or `(?-u)[\x80-\xff]+`.
+///
+/// # Arguments
+/// * `pattern` - Regex pattern
+/// * `data` - Bytes to search
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
+///
+/// # Returns
+/// * `ByteMatch` list with `start`/`end` byte offsets, the matched `data`
+///   and capture `groups`, as bytes
+///
+/// # Raises
+/// * `ValueError` - Invalid pattern
+/// * `TimeoutError` - `timeout_secs` passed; `partial` holds the matches
+///   found so far
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import regex_findall_bytes
+/// raw = open("inbox.mbox", "rb").read()
+/// for m in regex_findall_bytes(r"(?m-u)^Subject: (.*)$", raw):
+///     print(m.start, m.groups[0])
+/// ```
+#[pyfunction]
+#[pyo3(signature = (pattern, data, timeout_secs=None))]
+fn regex_findall_bytes(
+    py: Python<'_>,
+    pattern: &str,
+    data: &[u8],
+    timeout_secs: Option<f64>,
+) -> PyResult<Vec<ByteMatch>> {
+    let re = regex::bytes::Regex::new(pattern).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid regex: {e}"))
+    })?;
+    let deadline = Deadline::new(timeout_secs)?;
+
+    let (matches, timed_out) = py.allow_threads(|| {
+        let mut matches = Vec::new();
+        for caps in re.captures_iter(data) {
+            if matches.len() % deadline::CHECK_EVERY == 0 && deadline.expired() {
+                return (matches, true);
+            }
+            let Some(m) = caps.get(0) else { continue };
+            matches.push(ByteMatch {
+                start: m.start(),
+                end: m.end(),
+                matched: m.as_bytes().to_vec(),
+                groups: caps
+                    .iter()
+                    .skip(1)
+                    .map(|group| group.map(|g| g.as_bytes().to_vec()))
+                    .collect(),
+            });
+        }
+        (matches, false)
+    });
+    if timed_out {
+        return Err(deadline.error(py, "regex_findall_bytes", matches));
+    }
     Ok(matches)
 }
 
@@ -317,18 +694,122 @@ fn regex_findall_fast(pattern: &str, text: &str) -> PyResult<Vec<String>> {
 ///
 /// # Arguments
 /// * `pattern` - Regex pattern
-/// * `replacement` - Replacement string
+/// * `replacement` - Template or callable. Templates refer to groups as
+///   `$1`, `${1}`, `$name`, `${name}`, `\1`, `\g<1>` or `\g<name>`; `$$`
+///   is a literal `$`, and backslash escapes (`\n`, `\\`, `\012`) follow
+///   `re.sub`, so an unknown letter escape such as `\x` raises. A callable gets a `RegexMatch`
+///   (`group()`, `groups()`, `groupdict()`, `start()`, `end()`, `span()`,
+///   `m[1]`, like `re.Match`) per match and returns its replacement
 /// * `text` - Text to process
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
+/// * `count` - Replace at most this many matches (0: all)
+/// * `flags` - Python `re` flags: `re.IGNORECASE`, `re.MULTILINE`,
+///   `re.DOTALL`, `re.VERBOSE` (`re.UNICODE` is always on)
 ///
 /// # Returns
 /// * Text with replacements applied
+///
+/// # Raises
+/// * `ValueError` - Invalid pattern, unsupported flag, or a template
+///   referring to a group the pattern does not have (`$1a` is the group
+///   named "1a"; write `${1}a`)
+/// * `TypeError` - `replacement` is neither a string nor callable, or the
+///   callable returned a non-string
+/// * `TimeoutError` - `timeout_secs` passed (`partial` is None)
+///
+/// # Example
+/// ```python
+/// import re
+/// from mail_parser_rust import regex_replace_fast
+/// regex_replace_fast(r"(?P<user>\w+)@", r"\g<user> at ", "ann@x.org")
+/// regex_replace_fast(r"\d+", lambda m: str(int(m[0]) * 2), "3 apples", count=1)
+/// regex_replace_fast(r"^re: ", "", subject, flags=re.IGNORECASE)
+/// ```
 #[pyfunction]
-fn regex_replace_fast(pattern: &str, replacement: &str, text: &str) -> PyResult<String> {
-    let re = Regex::new(pattern).map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid regex: {}", e))
-    })?;
+#[pyo3(signature = (pattern, replacement, text, timeout_secs=None, count=0, flags=0))]
+fn regex_replace_fast(
+    py: Python<'_>,
+    pattern: &str,
+    replacement: &Bound<'_, PyAny>,
+    text: &str,
+    timeout_secs: Option<f64>,
+    count: usize,
+    flags: u32,
+) -> PyResult<String> {
+    let re = substitute::user_regex(pattern, flags)?;
+    let deadline = Deadline::new(timeout_secs)?;
+    let replaced = if let Ok(template) = replacement.downcast::<PyString>() {
+        substitute::replace_template(py, &re, template.to_str()?, text, count, deadline)?
+    } else if replacement.is_callable() {
+        substitute::replace_callback(&re, replacement, text, count, deadline)?
+    } else {
+        return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+            "replacement must be a str or a callable",
+        ));
+    };
+    replaced.ok_or_else(|| deadline.error(py, "regex_replace_fast", None::<String>))
+}
 
-    Ok(re.replace_all(text, replacement).to_string())
+/// Split text by a regex, like Python re.split
+///
+/// # Arguments
+/// * `pattern` - Regex pattern
+/// * `text` - Text to split
+/// * `maxsplit` - Split at most this many times (0: no limit); the rest of
+///   the text is the last item
+/// * `flags` - Python `re` flags, as for `regex_replace_fast`
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
+///
+/// # Returns
+/// * The pieces between matches. When the pattern has capturing groups,
+///   each group's text follows the piece before its match (None for a group
+///   that did not take part), as in `re.split`
+///
+/// # Raises
+/// * `ValueError` - Invalid pattern or unsupported flag
+/// * `TimeoutError` - `timeout_secs` passed (`partial` is None)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import regex_split_fast
+/// regex_split_fast(r"\s*[,;]\s*", "a, b;c")      # ["a", "b", "c"]
+/// regex_split_fast(r"\s*([,;])\s*", "a, b;c")    # ["a", ",", "b", ";", "c"]
+/// regex_split_fast(r",", "a,b,c", maxsplit=1)     # ["a", "b,c"]
+/// ```
+#[pyfunction]
+#[pyo3(signature = (pattern, text, maxsplit=0, flags=0, timeout_secs=None))]
+fn regex_split_fast(
+    py: Python<'_>,
+    pattern: &str,
+    text: &str,
+    maxsplit: usize,
+    flags: u32,
+    timeout_secs: Option<f64>,
+) -> PyResult<Vec<Option<String>>> {
+    let re = substitute::user_regex(pattern, flags)?;
+    let deadline = Deadline::new(timeout_secs)?;
+    let limit = if maxsplit == 0 { usize::MAX } else { maxsplit };
+
+    let pieces = py.allow_threads(|| {
+        let mut pieces = Vec::new();
+        let mut last = 0;
+        for (i, caps) in re.captures_iter(text).take(limit).enumerate() {
+            if i % deadline::CHECK_EVERY == 0 && deadline.expired() {
+                return None;
+            }
+            let Some(m) = caps.get(0) else { continue };
+            pieces.push(Some(text[last..m.start()].to_string()));
+            pieces.extend(
+                caps.iter()
+                    .skip(1)
+                    .map(|group| group.map(|g| g.as_str().to_string())),
+            );
+            last = m.end();
+        }
+        pieces.push(Some(text[last..].to_string()));
+        Some(pieces)
+    });
+    pieces.ok_or_else(|| deadline.error(py, "regex_split_fast", None::<String>))
 }
 
 /// Sanitize filename for cross-platform compatibility (3x faster than Python)
@@ -337,127 +818,360 @@ fn regex_replace_fast(pattern: &str, replacement: &str, text: &str) -> PyResult<
 /// * `filename` - Filename to sanitize
 ///
 /// # Returns
-/// * Sanitized filename safe for all operating systems
+/// * Sanitized filename safe for all operating systems: invalid characters
+///   replaced, surrounding whitespace and dots trimmed, Windows device names
+///   (`CON`, `nul.txt`, ...) prefixed with `_`, at most 255 bytes
 #[pyfunction]
 fn sanitize_filename_fast(filename: &str) -> PyResult<String> {
-    lazy_static! {
-        static ref INVALID_CHARS: Regex = Regex::new(r#"[<>:"/\\|?*\x00-\x1f]"#).unwrap();
-    }
-
-    let mut sanitized = INVALID_CHARS.replace_all(filename, "_").to_string();
-
-    // Trim whitespace and dots
-    sanitized = sanitized.trim().trim_matches('.').to_string();
-
-    // Limit length (255 bytes max for most filesystems)
-    if sanitized.len() > 255 {
-        sanitized.truncate(255);
-    }
-
-    Ok(sanitized)
+    Ok(paths::sanitize_component(filename))
 }
 
 /// Python module definition
 #[pymodule]
+#[allow(clippy::too_many_lines)]
 fn mail_parser_rust(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     // Core high-performance functions
     m.add_function(wrap_pyfunction!(count_messages_fast, m)?)?;
+    m.add("FileChangedError", m.py().get_type::<mbox::FileChangedError>())?;
+    m.add("ScanCancelled", m.py().get_type::<progress::ScanCancelled>())?;
+    m.add_class::<reader::MboxReader>()?;
+    m.add_class::<reader::MboxMessage>()?;
+    m.add_function(wrap_pyfunction!(offset_index::build_index_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(offset_index::get_message_by_index, m)?)?;
+    m.add_class::<offset_index::MessageIndex>()?;
+    m.add_class::<maildir::MaildirReader>()?;
+    m.add_class::<maildir::MaildirMessage>()?;
+    m.add_class::<maildir::MaildirWriter>()?;
+    m.add_class::<maildirpp::MaildirQuota>()?;
+    m.add_class::<dovecot::DovecotFolder>()?;
+    m.add_function(wrap_pyfunction!(parse::parse_message_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_headers_fast, m)?)?;
+    m.add_class::<Headers>()?;
+    m.add_class::<parse::ParsedMessage>()?;
+    m.add_class::<parse::ParsedAttachment>()?;
+    m.add_function(wrap_pyfunction!(diff::diff_messages, m)?)?;
+    m.add_class::<diff::MessageDiff>()?;
+    m.add_class::<diff::HeaderChange>()?;
+    m.add_class::<diff::DiffHunk>()?;
+    m.add_class::<diff::AttachmentDigest>()?;
+    m.add_function(wrap_pyfunction!(date::parse_date_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(date::parse_date_batch, m)?)?;
     m.add_function(wrap_pyfunction!(detect_encoding_fast, m)?)?;
     m.add_function(wrap_pyfunction!(decode_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(transfer::decode_base64_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(transfer::decode_quoted_printable_fast, m)?)?;
     m.add_function(wrap_pyfunction!(extract_emails_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(address::parse_addresses_fast, m)?)?;
+    m.add_class::<address::Address>()?;
     m.add_function(wrap_pyfunction!(extract_urls_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::extract_hashtags_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::extract_ticket_ids_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::extract_invoice_numbers_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::extract_tokens_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::extract_tokens_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::register_extractor, m)?)?;
+    m.add_function(wrap_pyfunction!(extract::unregister_extractor, m)?)?;
+    m.add_function(wrap_pyfunction!(plugins::register_native_extractor, m)?)?;
+    m.add_function(wrap_pyfunction!(plugins::unregister_native_extractor, m)?)?;
+    m.add_function(wrap_pyfunction!(plugins::native_extractors, m)?)?;
+    m.add_function(wrap_pyfunction!(plugins::run_native_extractors, m)?)?;
+    m.add_function(wrap_pyfunction!(filter::filter_indices, m)?)?;
+    m.add_function(wrap_pyfunction!(questions::detect_questions_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(questions::detect_questions_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(terms::detect_language_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(terms::term_frequencies, m)?)?;
+    m.add_function(wrap_pyfunction!(terms::term_frequencies_batch, m)?)?;
+    m.add_class::<terms::TermFrequencies>()?;
+    m.add_function(wrap_pyfunction!(simhash::simhash_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(simhash::cluster_near_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(sentiment::sentiment_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(sentiment::sentiment_batch, m)?)?;
+    m.add_class::<sentiment::Sentiment>()?;
+    m.add_class::<questions::QuestionSignals>()?;
+
+    // Startup
+    m.add_function(wrap_pyfunction!(patterns::warmup, m)?)?;
+    m.add_function(wrap_pyfunction!(patterns::loaded_patterns, m)?)?;
+
+    // IP extraction and enrichment
+    m.add_function(wrap_pyfunction!(geoip::extract_ips_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(geoip::extract_ip_info_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(geoip::register_ip_lookup, m)?)?;
+    m.add_function(wrap_pyfunction!(geoip::clear_ip_lookup, m)?)?;
+    m.add_class::<geoip::IpInfo>()?;
+
+    // DLP scanning
+    m.add_function(wrap_pyfunction!(dlp::detect_payment_data_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(dlp::detect_payment_data_batch, m)?)?;
+    m.add_class::<dlp::PaymentMatch>()?;
     m.add_function(wrap_pyfunction!(regex_findall_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(regex_finditer_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(regex_findall_bytes, m)?)?;
+    m.add_class::<ByteMatch>()?;
     m.add_function(wrap_pyfunction!(regex_replace_fast, m)?)?;
+    m.add_class::<substitute::RegexMatch>()?;
+    m.add_function(wrap_pyfunction!(regex_split_fast, m)?)?;
     m.add_function(wrap_pyfunction!(sanitize_filename_fast, m)?)?;
-
-    // NOTE: The following functions are implemented but commented out due to PyO3 0.25.0 API issues
-    // They compile successfully but fail at runtime with "takes no arguments" error
-    // This appears to be a PyO3 bug with complex return types (Vec<(String, String)> and Vec<EmailMetadata>)
-    // Workaround: These functions can be implemented in pure Python using the existing regex functions
+    m.add_function(wrap_pyfunction!(paths::extended_path, m)?)?;
+
+    // Message rendering
+    m.add_function(wrap_pyfunction!(markdown::to_markdown_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(html::html_to_text_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(html::html_to_text_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(main_content::extract_main_content, m)?)?;
+    m.add_class::<main_content::MainContent>()?;
+
+    // Attachment inspection
+    m.add_function(wrap_pyfunction!(attachments::extract_attachments_fast, m)?)?;
+    m.add_class::<attachments::ExtractedAttachment>()?;
+    m.add_function(wrap_pyfunction!(images::image_metadata_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(images::attachment_image_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(images::strip_exif_fast, m)?)?;
+    m.add_class::<images::ImageMetadata>()?;
+    m.add_function(wrap_pyfunction!(archive::extract_zip_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(archive::extract_zip_attachments, m)?)?;
+    m.add_class::<archive::ZipReport>()?;
+    #[cfg(feature = "pdf")]
+    {
+        m.add_function(wrap_pyfunction!(pdf::pdf_text_fast, m)?)?;
+        m.add_function(wrap_pyfunction!(pdf::attachment_pdf_text, m)?)?;
+    }
+    m.add_function(wrap_pyfunction!(doc_text::attachment_text_fast, m)?)?;
+
+    // LLM preparation
+    m.add_function(wrap_pyfunction!(tokens::estimate_tokens_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(embedding::prepare_for_embedding, m)?)?;
+    m.add_class::<embedding::EmbeddingText>()?;
+
+    // Archive analysis
+    m.add_function(wrap_pyfunction!(folders::suggest_folders, m)?)?;
+    m.add_class::<folders::FolderSuggestion>()?;
+    m.add_function(wrap_pyfunction!(senders::sender_report, m)?)?;
+    m.add_class::<senders::SenderSummary>()?;
+    m.add_function(wrap_pyfunction!(activity::activity_matrix, m)?)?;
+    m.add_class::<activity::ActivityMatrix>()?;
+    m.add_function(wrap_pyfunction!(gaps::detect_gaps, m)?)?;
+    m.add_class::<gaps::TrafficGap>()?;
+    m.add_function(wrap_pyfunction!(completeness::verify_against_manifest, m)?)?;
+    m.add_class::<completeness::ManifestComparison>()?;
+    m.add_class::<completeness::UnlistedMessage>()?;
+    m.add_function(wrap_pyfunction!(trackers::detect_trackers_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(trackers::detect_trackers_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(trackers::register_tracker_domains, m)?)?;
+    m.add_function(wrap_pyfunction!(trackers::tracking_report, m)?)?;
+    m.add_class::<trackers::TrackingReport>()?;
+    m.add_class::<trackers::SenderTracking>()?;
+    m.add_function(wrap_pyfunction!(alignment::alignment_check, m)?)?;
+    m.add_function(wrap_pyfunction!(received::parse_received_chain_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(received::parse_received_chain_batch, m)?)?;
+    m.add_class::<received::ReceivedHop>()?;
+    m.add_function(wrap_pyfunction!(anomalies::header_anomalies, m)?)?;
+    m.add_function(wrap_pyfunction!(anomalies::header_anomalies_batch, m)?)?;
+    m.add_class::<anomalies::HeaderAnomaly>()?;
+    m.add_function(wrap_pyfunction!(dkim::verify_dkim_fast, m)?)?;
+    m.add_class::<dkim::DkimResult>()?;
+    m.add_function(wrap_pyfunction!(auth_results::parse_authentication_results_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(auth_results::parse_auth_headers_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(auth_results::parse_auth_headers_batch, m)?)?;
+    m.add_class::<auth_results::AuthResult>()?;
+    m.add_class::<auth_results::AuthenticationResults>()?;
+    m.add_class::<auth_results::ArcSeal>()?;
+    m.add_class::<auth_results::ArcMessageSignature>()?;
+    m.add_class::<auth_results::ArcSet>()?;
+    m.add_class::<auth_results::AuthHeaders>()?;
+    m.add_class::<alignment::AlignmentReport>()?;
+    m.add_class::<alignment::MessageAlignment>()?;
+    m.add_class::<alignment::DomainAlignment>()?;
+    m.add_function(wrap_pyfunction!(contacts::extract_address_book, m)?)?;
+    m.add_class::<contacts::Contact>()?;
+    m.add_function(wrap_pyfunction!(recipients::explode_recipients, m)?)?;
+    m.add_function(wrap_pyfunction!(cooccurrence::entity_cooccurrence, m)?)?;
+    m.add_function(wrap_pyfunction!(identities::resolve_identities, m)?)?;
+    m.add_class::<identities::IdentityCluster>()?;
+    m.add_function(wrap_pyfunction!(direction::classify_direction, m)?)?;
+    m.add_function(wrap_pyfunction!(direction::classify_direction_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(direction::infer_bcc, m)?)?;
+    m.add_function(wrap_pyfunction!(direction::infer_bcc_batch, m)?)?;
+    m.add_class::<direction::BccInference>()?;
+    m.add_function(wrap_pyfunction!(thread_summary::thread_summaries, m)?)?;
+    m.add_class::<thread_summary::ThreadSummary>()?;
+    m.add_function(wrap_pyfunction!(search::search_mbox_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(search::regex_scan_file, m)?)?;
+    m.add_class::<search::SearchHit>()?;
+    #[cfg(feature = "search-index")]
+    {
+        m.add_function(wrap_pyfunction!(search_index::build_search_index, m)?)?;
+        m.add_function(wrap_pyfunction!(search_index::query_index, m)?)?;
+        m.add_function(wrap_pyfunction!(search_index::aggregate_index, m)?)?;
+        m.add_class::<search_index::SearchIndexReport>()?;
+        m.add_class::<search_index::IndexHit>()?;
+    }
+    m.add_class::<archive_set::ArchiveSet>()?;
+    m.add_class::<archive_set::ArchiveHit>()?;
+    m.add_class::<archive_set::SourceStats>()?;
+    m.add_class::<index_schema::IndexField>()?;
+
+    // Exporters
+    m.add_function(wrap_pyfunction!(conversations::export_conversations_text, m)?)?;
+    m.add_function(wrap_pyfunction!(html_archive::export_html_archive, m)?)?;
+    m.add_function(wrap_pyfunction!(calendar::export_calendar, m)?)?;
+    m.add_function(wrap_pyfunction!(redact::redact_mbox, m)?)?;
+    m.add_function(wrap_pyfunction!(repair::repair_mbox, m)?)?;
+    m.add_function(wrap_pyfunction!(maildir::mbox_to_maildir, m)?)?;
+    m.add_function(wrap_pyfunction!(maildir::maildir_to_mbox, m)?)?;
+    m.add_function(wrap_pyfunction!(maildir::maildir_tree_to_mbox, m)?)?;
+    m.add_function(wrap_pyfunction!(maildirpp::maildir_folders, m)?)?;
+    m.add_function(wrap_pyfunction!(maildirpp::maildir_quota, m)?)?;
+    m.add_function(wrap_pyfunction!(dovecot::dovecot_folder, m)?)?;
+    m.add_function(wrap_pyfunction!(eml::split_mbox_to_eml, m)?)?;
+    m.add_function(wrap_pyfunction!(arrow::mbox_to_arrow, m)?)?;
+    m.add_function(wrap_pyfunction!(parquet::mbox_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(jsonl::mbox_to_jsonl, m)?)?;
+    m.add_function(wrap_pyfunction!(snapshot::create_snapshot, m)?)?;
+    m.add_function(wrap_pyfunction!(snapshot::open_snapshot, m)?)?;
+    m.add_class::<arrow::ArrowBatch>()?;
+    m.add_class::<snapshot::Snapshot>()?;
+    m.add_function(wrap_pyfunction!(thread::thread_messages_fast, m)?)?;
+    m.add_class::<thread::ThreadNode>()?;
+    m.add_function(wrap_pyfunction!(rethread::repair_threading, m)?)?;
+    m.add_function(wrap_pyfunction!(rethread::generate_message_id, m)?)?;
+    m.add_function(wrap_pyfunction!(uid::compute_message_uid, m)?)?;
+    m.add_function(wrap_pyfunction!(forensic::extract_original, m)?)?;
+    m.add_class::<forensic::Provenance>()?;
+    m.add_function(wrap_pyfunction!(forensic::canonical_text, m)?)?;
+    m.add_function(wrap_pyfunction!(forensic::canonical_sha256, m)?)?;
+    m.add_function(wrap_pyfunction!(dedup::merge_mboxes, m)?)?;
+    m.add_class::<dedup::DedupDb>()?;
+    m.add_class::<dedup::MergeReport>()?;
+    m.add_function(wrap_pyfunction!(dedup::dedupe_mbox, m)?)?;
+    m.add_class::<dedup::DedupeReport>()?;
+    m.add_function(wrap_pyfunction!(bloom::build_id_bloom, m)?)?;
+    m.add_function(wrap_pyfunction!(bloom::probably_contains, m)?)?;
+    m.add_class::<bloom::IdBloom>()?;
+    m.add_class::<body_cache::BodyCache>()?;
+    m.add_function(wrap_pyfunction!(quarantine::quarantine_mbox, m)?)?;
+    m.add_class::<quarantine::QuarantineReport>()?;
+
+    // Resumable operations
+    m.add_function(wrap_pyfunction!(checkpoint::load_checkpoint, m)?)?;
+    m.add_class::<checkpoint::Checkpoint>()?;
+    m.add_class::<output::OutputReport>()?;
+
+    // Result schemas
+    m.add_function(wrap_pyfunction!(schema::result_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(schema::schema_versions, m)?)?;
+
+    // Test corpora
+    m.add_function(wrap_pyfunction!(corpus::generate_test_mbox, m)?)?;
+    m.add_class::<corpus::CorpusSpec>()?;
+    m.add_class::<corpus::CorpusReport>()?;
+
+    // Benchmarks
+    m.add_function(wrap_pyfunction!(benchmark::benchmark, m)?)?;
+    m.add_class::<benchmark::BenchmarkResult>()?;
+    m.add_function(wrap_pyfunction!(tuning::scan_tuning, m)?)?;
+    m.add_class::<tuning::ScanTuning>()?;
+
+    // NOTE: process_metadata_batch is implemented but commented out due to PyO3 0.25.0 API issues
+    // It compiles successfully but fails at runtime with "takes no arguments" error
+    // This appears to be a PyO3 bug with complex return types (Vec<EmailMetadata>)
+    // Workaround: It can be implemented in pure Python using the existing regex functions
     // TODO: Re-enable when upgrading to PyO3 0.26+ or when bug is fixed
-    // m.add_function(wrap_pyfunction!(parse_headers_fast, m)?)?;
     // m.add_function(wrap_pyfunction!(process_metadata_batch, m)?)?;
 
     // Add module metadata
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
-    m.add(
-        "__doc__",
-        "High-performance email parsing utilities via Rust/PyO3 - 8 working functions",
-    )?;
+    m.add("__doc__", "High-performance email parsing utilities via Rust/PyO3")?;
 
     Ok(())
 }
 
+/// Run `f` with the GIL held, starting an embedded interpreter first; unit
+/// tests use it to call functions that release the GIL
+#[cfg(test)]
+pub fn with_py<R>(f: impl for<'py> FnOnce(Python<'py>) -> R) -> R {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(f)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_encoding_detection() {
-        let utf8_text = "Hello, world! 你好世界".as_bytes();
-        let result = detect_encoding_fast(utf8_text).unwrap();
-        assert_eq!(result, "UTF-8");
-
-        // Test ASCII fast path
-        let ascii_text = b"Hello, world!";
-        let result = detect_encoding_fast(ascii_text).unwrap();
-        assert_eq!(result, "ASCII");
-
-        // Test empty input
-        let empty = b"";
-        let result = detect_encoding_fast(empty).unwrap();
-        assert_eq!(result, "UTF-8");
+        crate::with_py(|py| {
+            let utf8_text = "Hello, world! 你好世界".as_bytes();
+            let result = detect_encoding_fast(py, utf8_text).unwrap();
+            assert_eq!(result, "UTF-8");
+
+            // Test ASCII fast path
+            let ascii_text = b"Hello, world!";
+            let result = detect_encoding_fast(py, ascii_text).unwrap();
+            assert_eq!(result, "ASCII");
+
+            // Test empty input
+            let empty = b"";
+            let result = detect_encoding_fast(py, empty).unwrap();
+            assert_eq!(result, "UTF-8");
+        });
     }
 
     #[test]
     fn test_decode_fast() {
-        // Test UTF-8
-        let utf8_text = "Hello, world! 你好世界".as_bytes();
-        let decoded = decode_fast(utf8_text, None).unwrap();
-        assert!(decoded.contains("你好世界"));
-
-        // Test with encoding hint
-        let text = b"Hello";
-        let decoded = decode_fast(text, Some("utf-8")).unwrap();
-        assert_eq!(decoded, "Hello");
-
-        // Test empty input
-        let empty = b"";
-        let decoded = decode_fast(empty, None).unwrap();
-        assert_eq!(decoded, "");
+        crate::with_py(|py| {
+            // Test UTF-8
+            let utf8_text = "Hello, world! 你好世界".as_bytes();
+            let decoded = decode_fast(py, utf8_text, None).unwrap();
+            assert!(decoded.contains("你好世界"));
+
+            // Test with encoding hint
+            let text = b"Hello";
+            let decoded = decode_fast(py, text, Some("utf-8")).unwrap();
+            assert_eq!(decoded, "Hello");
+
+            // Test empty input
+            let empty = b"";
+            let decoded = decode_fast(py, empty, None).unwrap();
+            assert_eq!(decoded, "");
+        });
     }
 
     #[test]
     fn test_email_extraction() {
-        let text = "Contact john@example.com or jane@test.org";
-        let emails = extract_emails_fast(text).unwrap();
-        assert_eq!(emails.len(), 2);
-        assert!(emails.contains(&"john@example.com".to_string()));
-        assert!(emails.contains(&"jane@test.org".to_string()));
-
-        // Test case insensitivity (should lowercase)
-        let text = "Email: JOHN@EXAMPLE.COM";
-        let emails = extract_emails_fast(text).unwrap();
-        assert_eq!(emails[0], "john@example.com");
-
-        // Test no emails
-        let text = "No emails here";
-        let emails = extract_emails_fast(text).unwrap();
-        assert_eq!(emails.len(), 0);
+        crate::with_py(|py| {
+            let text = "Contact john@example.com or jane@test.org";
+            let emails = extract_emails_fast(py, text).unwrap();
+            assert_eq!(emails.len(), 2);
+            assert!(emails.contains(&"john@example.com".to_string()));
+            assert!(emails.contains(&"jane@test.org".to_string()));
+
+            // Test case insensitivity (should lowercase)
+            let text = "Email: JOHN@EXAMPLE.COM";
+            let emails = extract_emails_fast(py, text).unwrap();
+            assert_eq!(emails[0], "john@example.com");
+
+            // Test no emails
+            let text = "No emails here";
+            let emails = extract_emails_fast(py, text).unwrap();
+            assert_eq!(emails.len(), 0);
+        });
     }
 
     #[test]
     fn test_url_extraction() {
-        let text = "Visit https://example.com or http://test.org/path";
-        let urls = extract_urls_fast(text).unwrap();
-        assert_eq!(urls.len(), 2);
-        assert!(urls.contains(&"https://example.com".to_string()));
-        assert!(urls.contains(&"http://test.org/path".to_string()));
-
-        // Test no URLs
-        let text = "No URLs here";
-        let urls = extract_urls_fast(text).unwrap();
-        assert_eq!(urls.len(), 0);
+        crate::with_py(|py| {
+            let text = "Visit https://example.com or http://test.org/path";
+            let urls = extract_urls_fast(py, text).unwrap();
+            assert_eq!(urls.len(), 2);
+            assert!(urls.contains(&"https://example.com".to_string()));
+            assert!(urls.contains(&"http://test.org/path".to_string()));
+
+            // Test no URLs
+            let text = "No URLs here";
+            let urls = extract_urls_fast(py, text).unwrap();
+            assert_eq!(urls.len(), 0);
+        });
     }
 
     #[test]
@@ -488,33 +1202,191 @@ mod tests {
 
     #[test]
     fn test_regex_findall() {
-        let pattern = r"\d+";
-        let text = "I have 42 apples and 123 oranges";
-        let matches = regex_findall_fast(pattern, text).unwrap();
-        assert_eq!(matches.len(), 2);
-        assert!(matches.contains(&"42".to_string()));
-        assert!(matches.contains(&"123".to_string()));
-
-        // Test invalid regex
-        let invalid = r"[invalid(";
-        let result = regex_findall_fast(invalid, text);
-        assert!(result.is_err());
+        with_py(|py| {
+            let pattern = r"\d+";
+            let text = "I have 42 apples and 123 oranges";
+            let matches = regex_findall_fast(py, pattern, text, None).unwrap();
+            assert_eq!(matches.len(), 2);
+            assert!(matches.contains(&"42".to_string()));
+            assert!(matches.contains(&"123".to_string()));
+            assert_eq!(regex_findall_fast(py, pattern, text, Some(60.0)).unwrap(), matches);
+
+            // Test invalid regex
+            let invalid = r"[invalid(";
+            let result = regex_findall_fast(py, invalid, text, None);
+            assert!(result.is_err());
+
+            let err = regex_findall_fast(py, pattern, text, Some(0.0)).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+            assert!(regex_findall_fast(py, pattern, text, Some(-1.0)).is_err());
+        });
+    }
+
+    #[test]
+    fn test_regex_finditer() {
+        with_py(|py| {
+            let find = |pattern, text, overlapping| {
+                regex_finditer_fast(py, pattern, text, overlapping, 0, None).unwrap()
+            };
+            let spans = |matches: Vec<(usize, usize, String)>| {
+                matches
+                    .into_iter()
+                    .map(|(start, end, _)| (start, end))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                find(r"\d+", "Café 42, 7", false),
+                [(5, 7, "42".to_string()), (9, 10, "7".to_string())]
+            );
+            assert_eq!(spans(find("aba", "ababa", false)), [(0, 3)]);
+            assert_eq!(spans(find("aba", "ababa", true)), [(0, 3), (2, 5)]);
+            assert_eq!(spans(find(r"\d+", "123", true)), [(0, 3), (1, 3), (2, 3)]);
+            assert_eq!(spans(find("x*", "éx", false)), [(0, 0), (1, 2), (2, 2)]);
+            assert_eq!(spans(find("z", "abc", true)), []);
+            let ci = regex_finditer_fast(py, "é", "É", false, 2, Some(60.0)).unwrap();
+            assert_eq!(ci, [(0, 1, "É".to_string())]);
+
+            assert!(regex_finditer_fast(py, "(", "a", false, 0, None).is_err());
+            let err = regex_finditer_fast(py, "a", "a", false, 0, Some(0.0)).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+        });
+    }
+
+    #[test]
+    fn test_regex_findall_bytes() {
+        with_py(|py| {
+            // Latin-1 and UTF-8 headers plus a JPEG signature in one buffer
+            let data = b"Subject: caf\xe9\nSubject: caf\xc3\xa9\n\xff\xd8\xff\xe0";
+            // `.` matches UTF-8 characters only, unless Unicode is off
+            let utf8 = regex_findall_bytes(py, r"(?m)^Subject: (.*)$", data, None).unwrap();
+            assert_eq!((utf8.len(), utf8[0].start, utf8[0].end), (1, 14, 28));
+            let matches = regex_findall_bytes(py, r"(?m-u)^Subject: (.*)$", data, None).unwrap();
+            let found: Vec<(usize, usize, Vec<u8>)> = matches
+                .iter()
+                .map(|m| (m.start, m.end, m.matched.clone()))
+                .collect();
+            assert_eq!(
+                found,
+                [
+                    (0, 13, b"Subject: caf\xe9".to_vec()),
+                    (14, 28, b"Subject: caf\xc3\xa9".to_vec())
+                ]
+            );
+            assert_eq!(matches[1].groups, [Some(b"caf\xc3\xa9".to_vec())]);
+
+            let jpeg = regex_findall_bytes(py, r"(?-u)\xff\xd8\xff", data, None).unwrap();
+            assert_eq!((jpeg[0].start, jpeg[0].end), (29, 32));
+            let high = regex_findall_bytes(py, r"(?-u)[\x80-\xff]+", data, Some(60.0)).unwrap();
+            assert_eq!(high.len(), 3);
+
+            assert!(regex_findall_bytes(py, "(", data, None).is_err());
+            let err = regex_findall_bytes(py, "S", data, Some(0.0)).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+        });
     }
 
     #[test]
     fn test_regex_replace() {
-        let pattern = r"\d+";
-        let replacement = "NUM";
-        let text = "I have 42 apples and 123 oranges";
-        let result = regex_replace_fast(pattern, replacement, text).unwrap();
-        assert_eq!(result, "I have NUM apples and NUM oranges");
-
-        // Test invalid regex
-        let invalid = r"[invalid(";
-        let result = regex_replace_fast(invalid, "X", text);
-        assert!(result.is_err());
+        with_py(|py| {
+            let pattern = r"(\d)(\d*)";
+            let replacement = PyString::new(py, "<$1>$2");
+            let text = "I have 42 apples and 123 oranges";
+            let replace = |pattern, replacement: &Bound<'_, PyAny>, timeout, count, flags| {
+                regex_replace_fast(py, pattern, replacement, text, timeout, count, flags)
+            };
+            let result = replace(pattern, &replacement, None, 0, 0).unwrap();
+            assert_eq!(result, "I have <4>2 apples and <1>23 oranges");
+            let timed = replace(pattern, &replacement, Some(60.0), 0, 0).unwrap();
+            assert_eq!(timed, result);
+            let first = replace(pattern, &replacement, None, 1, 0).unwrap();
+            assert_eq!(first, "I have <4>2 apples and 123 oranges");
+            let upper = PyString::new(py, "[$0]");
+            let flagged = replace("I HAVE", &upper, None, 0, 2).unwrap();
+            assert_eq!(flagged, "[I have] 42 apples and 123 oranges");
+
+            let double = py
+                .eval(c"lambda m: str(int(m[0]) * 2)", None, None)
+                .unwrap();
+            let doubled = replace(r"\d+", &double, None, 0, 0).unwrap();
+            assert_eq!(doubled, "I have 84 apples and 246 oranges");
+
+            // Test invalid regex
+            let x = PyString::new(py, "X");
+            let result = replace(r"[invalid(", &x, None, 0, 0);
+            assert!(result.is_err());
+            assert!(replace(pattern, &PyString::new(py, "$3"), None, 0, 0).is_err());
+            let number = 1_i64.into_pyobject(py).unwrap().into_any();
+            let err = replace(pattern, &number, None, 0, 0).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTypeError>(py));
+
+            let err = replace(pattern, &x, Some(0.0), 0, 0).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+        });
+    }
+
+    #[test]
+    fn test_regex_split() {
+        with_py(|py| {
+            let split = |pattern, text, maxsplit| {
+                regex_split_fast(py, pattern, text, maxsplit, 0, None).unwrap()
+            };
+            let some = |pieces: &[&str]| {
+                pieces
+                    .iter()
+                    .map(|p| Some((*p).to_string()))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(split(r"\s*[,;]\s*", "a, b;c", 0), some(&["a", "b", "c"]));
+            assert_eq!(split(r"\s*([,;])\s*", "a, b;c", 0), some(&["a", ",", "b", ";", "c"]));
+            assert_eq!(split(",", "a,b,c", 1), some(&["a", "b,c"]));
+            assert_eq!(split(",", ",a,", 0), some(&["", "a", ""]));
+            assert_eq!(split("x", "abc", 0), some(&["abc"]));
+            assert_eq!(
+                split("(a)|(b)", "1a2", 0),
+                [
+                    Some("1".to_string()),
+                    Some("a".to_string()),
+                    None,
+                    Some("2".to_string())
+                ]
+            );
+            assert_eq!(regex_split_fast(py, "X", "axb", 0, 2, None).unwrap(), some(&["a", "b"]));
+
+            assert!(regex_split_fast(py, "(", "a", 0, 0, None).is_err());
+            let err = regex_split_fast(py, ",", "a,b", 0, 0, Some(0.0)).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+        });
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        crate::with_py(|py| {
+            let headers = parse_headers_fast(
+                py,
+                "From a@x Mon Jan  1 00:00:00 2024\r\n\
+                 Received: from a by b;\r\n\tMon, 1 Jan 2024 00:00:00 +0000\r\n\
+                 Subject: a long\r\n  subject\r\n\
+                 received: from c by a; Mon, 1 Jan 2024 00:00:01 +0000\r\n\
+                 \r\n\
+                 Not-A-Header: body\r\n",
+            );
+            assert_eq!(headers.__len__(), 3);
+            assert_eq!(headers.__getitem__("SUBJECT").unwrap(), "a long  subject");
+            assert_eq!(
+                headers.get_all("Received"),
+                [
+                    "from a by b;\tMon, 1 Jan 2024 00:00:00 +0000",
+                    "from c by a; Mon, 1 Jan 2024 00:00:01 +0000"
+                ]
+            );
+            assert_eq!(headers.keys(), ["received", "subject"]);
+            assert_eq!(headers.fields[2].0, "received");
+            assert!(!headers.__contains__("not-a-header"));
+            assert!(headers.__getitem__("To").is_err());
+            assert_eq!(headers.get("To", Some("-".into())).as_deref(), Some("-"));
+        });
     }
 
-    // Note: parse_headers_fast and process_metadata_batch require Python runtime
-    // and are tested via Python integration tests in tests/test_mail_parser.py
+    // Note: process_metadata_batch requires Python runtime
+    // and is tested via Python integration tests in tests/test_mail_parser.py
 }
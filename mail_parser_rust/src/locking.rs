@@ -0,0 +1,312 @@
+//! Mailbox locking compatible with MDAs and mail clients.
+//!
+//! Three mechanisms are in common use and a mailbox is only safe when every
+//! tool touching it agrees on at least one: a `<mbox>.lock` dot file created
+//! with `O_EXCL` (procmail, mutt, mailx), POSIX `fcntl` record locks
+//! (Postfix, Dovecot, mutt) and BSD `flock` (Exim, some Dovecot setups).
+//! [`LockSpec`] picks any combination; [`MailboxLock`] holds them until it is
+//! dropped. Kernel locks are taken before the dot lock, as mutt and Postfix
+//! do, and all attempts are retried until [`LOCK_TIMEOUT`].
+//!
+//! A locked mailbox is rewritten by renaming the new file over it with
+//! [`MailboxLock::replace`], which takes the same kernel locks on the new
+//! file first, so the mailbox is never seen half-written or unlocked. A
+//! process that opened the old file and waits on its lock gets the replaced
+//! file once the lock moves; MDAs and clients that compare the locked file
+//! with the path afterwards (as mutt and Dovecot do) reopen it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long to keep retrying a held lock before giving up
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay between attempts
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Age after which a dot lock is presumed left behind by a crashed process
+/// (the five minutes mutt and mailx use)
+const STALE_DOTLOCK: Duration = Duration::from_secs(300);
+
+/// Which locks to take, parsed from a `lock=` argument
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockSpec {
+    pub dotlock: bool,
+    pub fcntl: bool,
+    pub flock: bool,
+}
+
+impl LockSpec {
+    /// Parse `"none"` or a `+`/`,`-separated combination of `"dotlock"`,
+    /// `"fcntl"` and `"flock"` (e.g. `"dotlock+fcntl"`)
+    pub fn parse(value: &str) -> pyo3::PyResult<Self> {
+        let mut spec = Self::default();
+        for method in value.split(['+', ',']).map(str::trim) {
+            match method.to_ascii_lowercase().as_str() {
+                "none" | "" => {},
+                "dotlock" | "dot" => spec.dotlock = true,
+                "fcntl" if cfg!(unix) => spec.fcntl = true,
+                "flock" if cfg!(unix) => spec.flock = true,
+                other => {
+                    return Err(pyo3::PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid lock method {other:?} (expected \"none\", \"dotlock\", \"fcntl\" \
+                         or \"flock\", combined with \"+\"; kernel locks need a Unix platform)"
+                    )))
+                },
+            }
+        }
+        Ok(spec)
+    }
+
+    pub const fn is_none(self) -> bool {
+        !(self.dotlock || self.fcntl || self.flock)
+    }
+
+    /// Lock `path` for writing. Kernel locks apply only when the file
+    /// exists; a missing mailbox is guarded by the dot lock alone.
+    pub fn exclusive(self, path: &Path) -> io::Result<MailboxLock> {
+        self.acquire(path, true)
+    }
+
+    /// Lock `path` for reading: writers wait, other readers do not (the dot
+    /// lock, having no shared form, still excludes everyone)
+    pub fn shared(self, path: &Path) -> io::Result<MailboxLock> {
+        self.acquire(path, false)
+    }
+
+    fn acquire(self, path: &Path, exclusive: bool) -> io::Result<MailboxLock> {
+        let path = crate::paths::extended(path);
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        let mut lock = MailboxLock {
+            file: None,
+            dotlock: None,
+            spec: self,
+        };
+        if self.fcntl || self.flock {
+            let opened = OpenOptions::new().read(true).write(exclusive).open(&path);
+            match opened {
+                Ok(file) => {
+                    retry(&path, deadline, || kernel_lock(&file, self, exclusive))?;
+                    lock.file = Some(file);
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e),
+            }
+        }
+        if self.dotlock {
+            let dotlock = dotlock_path(&path);
+            retry(&path, deadline, || create_dotlock(&dotlock, STALE_DOTLOCK))?;
+            lock.dotlock = Some(dotlock);
+        }
+        Ok(lock)
+    }
+}
+
+/// Locks held on one mailbox; released on drop
+#[derive(Debug)]
+pub struct MailboxLock {
+    file: Option<File>,
+    dotlock: Option<PathBuf>,
+    spec: LockSpec,
+}
+
+impl MailboxLock {
+    /// Rename `new` over the locked mailbox at `path`, moving the kernel
+    /// locks to the new file before it appears under the name; the dot lock
+    /// is held throughout
+    pub fn replace(&mut self, new: &Path, path: &Path) -> io::Result<()> {
+        if self.file.is_some() {
+            let file = OpenOptions::new().read(true).write(true).open(new)?;
+            let deadline = Instant::now() + LOCK_TIMEOUT;
+            retry(new, deadline, || kernel_lock(&file, self.spec, true))?;
+            fs::rename(new, path)?;
+            // Closing the old descriptor releases the locks on the old file
+            self.file = Some(file);
+        } else {
+            fs::rename(new, path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MailboxLock {
+    fn drop(&mut self) {
+        // Closing the descriptor releases fcntl and flock locks
+        self.file = None;
+        if let Some(dotlock) = self.dotlock.take() {
+            let _ = fs::remove_file(dotlock);
+        }
+    }
+}
+
+fn dotlock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Run `attempt` until it returns true or `deadline` passes
+fn retry(
+    path: &Path,
+    deadline: Instant,
+    mut attempt: impl FnMut() -> io::Result<bool>,
+) -> io::Result<()> {
+    loop {
+        if attempt()? {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("timed out waiting for the lock on {}", path.display()),
+            ));
+        }
+        std::thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// Create the dot lock; false while another process holds it. A lock older
+/// than `stale_after` is removed so the next attempt can take it.
+fn create_dotlock(dotlock: &Path, stale_after: Duration) -> io::Result<bool> {
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dotlock)
+    {
+        Ok(mut file) => {
+            io::Write::write_all(&mut file, format!("{}\n", std::process::id()).as_bytes())?;
+            Ok(true)
+        },
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let stale = fs::metadata(dotlock)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age > stale_after);
+            if stale {
+                let _ = fs::remove_file(dotlock);
+            }
+            Ok(false)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Try the kernel locks of `spec` once; false if another process holds one
+#[cfg(unix)]
+#[allow(unsafe_code)]
+fn kernel_lock(file: &File, spec: LockSpec, exclusive: bool) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    let fd = file.as_raw_fd();
+    let busy = |e: &io::Error| matches!(e.raw_os_error(), Some(code) if code == libc::EAGAIN || code == libc::EACCES || code == libc::EWOULDBLOCK);
+    if spec.fcntl {
+        let kind = if exclusive {
+            libc::F_WRLCK
+        } else {
+            libc::F_RDLCK
+        };
+        // Safety: zeroed `flock` is a valid whole-file request once the
+        // type and whence are set; `fd` is open for the call's duration
+        let result = unsafe {
+            let mut request: libc::flock = std::mem::zeroed();
+            request.l_type = libc::c_short::try_from(kind).unwrap_or_default();
+            request.l_whence = libc::c_short::try_from(libc::SEEK_SET).unwrap_or_default();
+            libc::fcntl(fd, libc::F_SETLK, &request)
+        };
+        if result == -1 {
+            let e = io::Error::last_os_error();
+            return if busy(&e) { Ok(false) } else { Err(e) };
+        }
+    }
+    if spec.flock {
+        let kind = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        // Safety: plain syscall on a descriptor we own
+        if unsafe { libc::flock(fd, kind | libc::LOCK_NB) } == -1 {
+            let e = io::Error::last_os_error();
+            return if busy(&e) { Ok(false) } else { Err(e) };
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn kernel_lock(_file: &File, _spec: LockSpec, _exclusive: bool) -> io::Result<bool> {
+    // `LockSpec::parse` rejects kernel locks here
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(LockSpec::parse("none").unwrap().is_none());
+        let spec = LockSpec::parse("dotlock+fcntl").unwrap();
+        assert!(spec.dotlock && spec.fcntl && !spec.flock);
+        assert!(LockSpec::parse("dotlock,flock").unwrap().flock);
+        assert!(LockSpec::parse("lockf").is_err());
+    }
+
+    #[test]
+    fn test_dotlock_excludes_and_releases() {
+        let dir = std::env::temp_dir().join(format!("mpr_locking_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mbox = dir.join("inbox");
+        fs::write(&mbox, "").unwrap();
+        let spec = LockSpec::parse("dotlock").unwrap();
+        let held = spec.exclusive(&mbox).unwrap();
+        assert!(dir.join("inbox.lock").exists());
+        assert!(!create_dotlock(&dir.join("inbox.lock"), STALE_DOTLOCK).unwrap());
+        drop(held);
+        assert!(!dir.join("inbox.lock").exists());
+
+        // A dot lock from a crashed process is broken after a while
+        let stale = dir.join("stale.lock");
+        File::create(&stale).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!create_dotlock(&stale, STALE_DOTLOCK).unwrap());
+        assert!(!create_dotlock(&stale, Duration::from_millis(10)).unwrap());
+        assert!(create_dotlock(&stale, STALE_DOTLOCK).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_flock_conflicts_between_descriptors() {
+        let dir = std::env::temp_dir().join(format!("mpr_flock_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mbox = dir.join("inbox");
+        fs::write(&mbox, "x").unwrap();
+        let spec = LockSpec::parse("flock").unwrap();
+        let held = spec.shared(&mbox).unwrap();
+        assert!(held.file.is_some());
+        let other = File::options().read(true).write(true).open(&mbox).unwrap();
+        assert!(kernel_lock(&other, spec, false).unwrap());
+        let writer = File::options().read(true).write(true).open(&mbox).unwrap();
+        assert!(!kernel_lock(&writer, spec, true).unwrap());
+        drop((held, other));
+        assert!(kernel_lock(&writer, spec, true).unwrap());
+        // No kernel lock on a mailbox that does not exist yet
+        assert!(spec.exclusive(&dir.join("new")).unwrap().file.is_none());
+
+        // A replaced mailbox stays locked under its name
+        drop(writer);
+        let mut held = spec.exclusive(&mbox).unwrap();
+        let new = dir.join(".inbox.partial");
+        fs::write(&new, "y").unwrap();
+        held.replace(&new, &mbox).unwrap();
+        assert_eq!(fs::read(&mbox).unwrap(), b"y");
+        let reader = File::open(&mbox).unwrap();
+        assert!(!kernel_lock(&reader, spec, false).unwrap());
+        drop(held);
+        assert!(kernel_lock(&reader, spec, false).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
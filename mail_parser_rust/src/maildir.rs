@@ -0,0 +1,998 @@
+//! Maildir reading, writing and conversion to and from mbox.
+//!
+//! A Maildir holds one file per message in `new/` (delivered, not yet seen
+//! by a client) and `cur/`, plus `tmp/` for deliveries in progress. Files in
+//! `cur/` carry their flags after the unique name, as `:2,` followed by
+//! `D`raft, `F`lagged, `P`assed, `R`eplied, `S`een and `T`rashed in ASCII
+//! order; Windows clients write `!` or `;` instead of the `:` their file
+//! systems reject. Lowercase letters are custom keywords, named per folder
+//! in `dovecot-keywords` (see [`crate::maildirpp`]). Dotfiles are never
+//! messages. Converting to mbox carries the flags over as mutt-style
+//! `Status:` / `X-Status:` headers and the keywords as `X-Keywords:`, and
+//! converting back reads them again. IMAP UIDs from a Dovecot
+//! `dovecot-uidlist` (see [`crate::dovecot`]) are exposed on messages and
+//! written as `X-UID:`.
+//!
+//! Every function takes an optional Maildir++ `folder` (such as
+//! "Sent/2023") within the tree rooted at the given path, and deliveries are
+//! counted in the tree's Courier quota file when it has one.
+
+use crate::date::parse_date;
+use crate::dovecot::DovecotFolder;
+use crate::locking::LockSpec;
+use crate::maildirpp::{folder_dir, folders, keyword_header_names, record_usage, Keywords};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{decode_encoded_words, split_header_body, strip_envelope, Message};
+use crate::output::{Compression, Output, OutputFile, OutputReport};
+use crate::progress::Progress;
+use crate::repair::repair_message;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Messages read in parallel before they are appended to an mbox in order
+const BATCH_SIZE: usize = 4096;
+
+/// Flags a Maildir file name may carry, in the order they are written
+const FLAGS: &str = "DFPRST";
+
+/// Separators between the unique name and the `2,<flags>` info
+const INFO_SEPARATORS: [char; 3] = [':', '!', ';'];
+
+/// Separator used for the names this module writes
+#[cfg(windows)]
+const INFO_SEPARATOR: char = '!';
+#[cfg(not(windows))]
+const INFO_SEPARATOR: char = ':';
+
+/// Per-process sequence number that keeps generated names unique
+static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// A file name split into its unique part and its flags (`None` without an
+/// info suffix)
+pub fn split_info(name: &str) -> (&str, Option<&str>) {
+    name.rfind(|c| INFO_SEPARATORS.contains(&c))
+        .and_then(|at| Some((&name[..at], name[at + 1..].strip_prefix("2,")?)))
+        .map_or((name, None), |(unique, flags)| (unique, Some(flags)))
+}
+
+/// Known flags and keyword letters among `flags`, deduplicated and in
+/// canonical (ASCII) order
+fn normalize_flags(flags: &str) -> String {
+    FLAGS
+        .chars()
+        .chain('a'..='z')
+        .filter(|&f| flags.contains(f))
+        .collect()
+}
+
+/// Message files of a Maildir: `new/`, then `cur/`, then (if asked) `tmp/`,
+/// each sorted by name; dotfiles are skipped and missing subdirectories
+/// count as empty
+pub fn message_files(dir: &Path, include_tmp: bool) -> std::io::Result<Vec<PathBuf>> {
+    if !crate::paths::extended(dir).is_dir() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not a Maildir directory"));
+    }
+    let subdirs: &[&str] = if include_tmp {
+        &["new", "cur", "tmp"]
+    } else {
+        &["new", "cur"]
+    };
+    let mut files = Vec::new();
+    for sub in subdirs {
+        let entries = match fs::read_dir(crate::paths::extended(&dir.join(sub))) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut names: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .map(|e| e.path())
+            .collect();
+        names.sort();
+        files.extend(names);
+    }
+    Ok(files)
+}
+
+/// Host part of generated names, with `/` and `:` escaped as the spec asks
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+        .replace('/', "\\057")
+        .replace(':', "\\072")
+}
+
+/// A fresh unique name, `<secs>.M<micros>P<pid>Q<seq>.<host>`
+fn unique_name(epoch: Option<i64>) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = epoch.map_or_else(|| now.as_secs().to_string(), |e| e.max(0).to_string());
+    format!(
+        "{secs}.M{}P{}Q{}.{}",
+        now.subsec_micros(),
+        std::process::id(),
+        DELIVERIES.fetch_add(1, Ordering::Relaxed),
+        hostname()
+    )
+}
+
+/// Where a message with `flags` goes: `new/<unique>` for a message no client
+/// has seen (`flags == None`), else `cur/<unique>:2,<flags>`
+fn delivery_path(root: &Path, unique: &str, flags: Option<&str>) -> PathBuf {
+    flags.map_or_else(
+        || root.join("new").join(unique),
+        |flags| {
+            root.join("cur")
+                .join(format!("{unique}{INFO_SEPARATOR}2,{}", normalize_flags(flags)))
+        },
+    )
+}
+
+/// Maildir flags from mbox `Status:` / `X-Status:` headers; `None` when the
+/// message was never seen by a client (no `O` or `R` status)
+fn flags_from_status(msg: &Message) -> Option<String> {
+    let status = msg.header("Status").unwrap_or_default();
+    let x_status = msg.header("X-Status").unwrap_or_default();
+    if !status.contains(['O', 'R']) && x_status.trim().is_empty() {
+        return None;
+    }
+    let mut flags = String::new();
+    for (present, flag) in [
+        (x_status.contains('T'), 'D'),
+        (x_status.contains('F'), 'F'),
+        (x_status.contains('A'), 'R'),
+        (status.contains('R'), 'S'),
+        (x_status.contains('D'), 'T'),
+    ] {
+        if present {
+            flags.push(flag);
+        }
+    }
+    Some(flags)
+}
+
+/// `Status:` / `X-Status:` header lines for Maildir `flags`
+fn status_headers(subdir_new: bool, flags: &str) -> String {
+    let mut status = String::new();
+    if flags.contains('S') {
+        status.push('R');
+    }
+    if !subdir_new {
+        status.push('O');
+    }
+    let x_status: String = [('R', 'A'), ('F', 'F'), ('D', 'T'), ('T', 'D')]
+        .iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, letter)| letter)
+        .collect();
+    let mut lines = String::new();
+    for (name, value) in [("Status", status), ("X-Status", x_status)] {
+        if !value.is_empty() {
+            let _ = writeln!(lines, "{name}: {value}");
+        }
+    }
+    lines
+}
+
+/// One Maildir file as an mbox entry: separator, status and keyword headers
+/// from the file name and the IMAP UID from the folder's `dovecot` metadata
+/// (unless the message has its own), quoted body `From ` lines
+fn mbox_entry(path: &Path, raw: &[u8], dovecot: &DovecotFolder) -> Vec<u8> {
+    let entry = repair_message(raw);
+    let msg = Message::parse(raw);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let flags = split_info(&name).1.unwrap_or_default();
+    let mut headers = if msg.header("Status").is_none() && msg.header("X-Status").is_none() {
+        let in_new = path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|d| d == "new");
+        status_headers(in_new, flags)
+    } else {
+        String::new()
+    };
+    let names = dovecot.keywords.names(flags);
+    if msg.header("X-Keywords").is_none() && !names.is_empty() {
+        let _ = writeln!(headers, "X-Keywords: {}", names.join(" "));
+    }
+    if let Some(uid) = dovecot
+        .uid_of(path)
+        .filter(|_| msg.header("X-UID").is_none())
+    {
+        let _ = writeln!(headers, "X-UID: {uid}");
+    }
+    if headers.is_empty() {
+        return entry;
+    }
+    let separator_end = entry
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(entry.len(), |nl| nl + 1);
+    let mut out = Vec::with_capacity(entry.len() + headers.len());
+    out.extend_from_slice(&entry[..separator_end]);
+    out.extend_from_slice(headers.as_bytes());
+    out.extend_from_slice(&entry[separator_end..]);
+    out
+}
+
+fn io_error(path: &Path, e: &std::io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {}: {e}", path.display()))
+}
+
+/// Create `tmp/`, `new/` and `cur/` under `dir`, plus the Maildir++ folder
+/// marker unless `dir` is the tree's `root` (not in dry-run mode)
+fn create_maildir(root: &Path, dir: &Path, output: &Output) -> PyResult<()> {
+    if output.is_dry_run() {
+        return Ok(());
+    }
+    for sub in ["tmp", "new", "cur"] {
+        crate::paths::create_dir_all(&dir.join(sub)).map_err(|e| io_error(dir, &e))?;
+    }
+    if dir != root {
+        let marker = dir.join(crate::maildirpp::FOLDER_MARKER);
+        fs::write(crate::paths::extended(&marker), "").map_err(|e| io_error(&marker, &e))?;
+    }
+    Ok(())
+}
+
+/// Write `raw` (an mbox envelope line is dropped) as a new Maildir file in
+/// `dir`, named with its size as Maildir++ asks; returns the path and size
+fn deliver(
+    dir: &Path,
+    raw: &[u8],
+    flags: Option<&str>,
+    epoch: Option<i64>,
+    output: &Output,
+) -> PyResult<(PathBuf, u64)> {
+    let content = strip_envelope(raw);
+    let size = content.len() as u64;
+    let unique = format!("{},S={size}", unique_name(epoch));
+    let path = delivery_path(dir, &unique, flags);
+    let mut file = output.create(&path).map_err(|e| io_error(&path, &e))?;
+    file.write_all(content).map_err(|e| io_error(&path, &e))?;
+    file.finish().map_err(|e| io_error(&path, &e))?;
+    output.note_message();
+    Ok((path, size))
+}
+
+/// `flags` with keyword `letters` added; a message with
+/// keywords is filed in `cur/` even when no client has seen it
+fn with_keywords(flags: Option<String>, letters: &str) -> Option<String> {
+    if letters.is_empty() {
+        flags
+    } else {
+        Some(flags.unwrap_or_default() + letters)
+    }
+}
+
+fn quota_error(root: &Path, e: &std::io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+        "Failed to update the quota of {}: {e}",
+        root.display()
+    ))
+}
+
+/// Iterate the messages of a Maildir
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import MaildirReader
+/// for msg in MaildirReader("~/Maildir", folder="Work"):
+///     if "S" not in msg.flags:
+///         print(msg.subdir, msg.header("Subject"), msg.keywords)
+/// ```
+#[pyclass]
+pub struct MaildirReader {
+    files: Vec<PathBuf>,
+    position: usize,
+    dovecot: Arc<DovecotFolder>,
+}
+
+#[pymethods]
+impl MaildirReader {
+    /// Open a Maildir for iteration
+    ///
+    /// Messages come from `new/`, then `cur/`, each in file name order.
+    ///
+    /// # Arguments
+    /// * `path` - Maildir directory (the one holding `cur/`, `new/`, `tmp/`)
+    /// * `include_tmp` - Also return deliveries still in `tmp/` (default False)
+    /// * `folder` - Maildir++ folder under `path`, e.g. "Sent/2023" (default:
+    ///   `path` itself; see `maildir_folders`)
+    ///
+    /// # Raises
+    /// * `ValueError` for an invalid folder name
+    /// * `IOError` if the directory cannot be listed
+    #[new]
+    #[pyo3(signature = (path, include_tmp=false, folder=None))]
+    fn new(path: &str, include_tmp: bool, folder: Option<&str>) -> PyResult<Self> {
+        let dir = folder_dir(Path::new(path), folder)?;
+        let read_error = |e: std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to read {}: {e}",
+                dir.display()
+            ))
+        };
+        let files = message_files(&dir, include_tmp).map_err(read_error)?;
+        let dovecot = DovecotFolder::load(&dir).map_err(read_error)?;
+        Ok(Self {
+            files,
+            position: 0,
+            dovecot: Arc::new(dovecot),
+        })
+    }
+
+    const fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<MaildirMessage> {
+        let path = self.files.get(self.position)?.clone();
+        let message = MaildirMessage {
+            path,
+            index: self.position,
+            dovecot: Arc::clone(&self.dovecot),
+        };
+        self.position += 1;
+        Some(message)
+    }
+
+    /// Number of message files found when the reader was opened
+    fn __len__(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Start over from the first message
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+}
+
+/// One message file of a `MaildirReader`, read when its content is asked for
+#[pyclass]
+pub struct MaildirMessage {
+    path: PathBuf,
+    index: usize,
+    /// Dovecot UIDs and keyword names of the message's folder
+    dovecot: Arc<DovecotFolder>,
+}
+
+impl MaildirMessage {
+    fn bytes(&self) -> PyResult<Vec<u8>> {
+        fs::read(crate::paths::extended(&self.path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to read {}: {e}",
+                self.path.display()
+            ))
+        })
+    }
+
+    fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[pymethods]
+impl MaildirMessage {
+    /// Position of the message in the reader (0-based)
+    #[getter]
+    const fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Path of the message file
+    #[getter]
+    fn path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    /// "new", "cur" or "tmp"
+    #[getter]
+    fn subdir(&self) -> String {
+        self.path
+            .parent()
+            .and_then(Path::file_name)
+            .map_or_else(String::new, |d| d.to_string_lossy().into_owned())
+    }
+
+    /// File name without the flag suffix
+    #[getter]
+    fn unique(&self) -> String {
+        split_info(&self.name()).0.to_string()
+    }
+
+    /// Flags from the file name, e.g. "RS" (empty for messages in `new/`);
+    /// lowercase letters are keywords
+    #[getter]
+    fn flags(&self) -> String {
+        normalize_flags(split_info(&self.name()).1.unwrap_or_default())
+    }
+
+    /// Custom keywords (from the folder's `dovecot-keywords`), in flag order
+    #[getter]
+    fn keywords(&self) -> Vec<String> {
+        self.dovecot.keywords.names(&self.flags())
+    }
+
+    /// IMAP UID from the folder's `dovecot-uidlist` (None if not listed)
+    #[getter]
+    fn imap_uid(&self) -> Option<u32> {
+        self.dovecot.uid_of(&self.path)
+    }
+
+    /// The message file's bytes
+    fn raw<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.bytes()?))
+    }
+
+    /// Header fields in order, unfolded and RFC 2047-decoded
+    #[getter]
+    fn headers(&self) -> PyResult<Vec<(String, String)>> {
+        Ok(Message::parse(&self.bytes()?)
+            .headers
+            .into_iter()
+            .map(|h| {
+                let value = decode_encoded_words(&h.value);
+                (h.name, value)
+            })
+            .collect())
+    }
+
+    /// First header named `name` (case-insensitive), decoded
+    fn header(&self, name: &str) -> PyResult<Option<String>> {
+        Ok(Message::parse(&self.bytes()?).decoded_header(name))
+    }
+
+    /// Body bytes after the header block, transfer encoding not undone
+    fn body<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let raw = self.bytes()?;
+        let (_, body) = split_header_body(&raw);
+        Ok(PyBytes::new(py, body))
+    }
+
+    /// Readable text body (first text part, decoded; HTML converted)
+    fn text(&self) -> PyResult<String> {
+        Ok(Message::parse(&self.bytes()?).text_body())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("MaildirMessage(index={}, path={:?})", self.index, self.path())
+    }
+}
+
+/// Deliver messages into a Maildir
+///
+/// Directories are created on first use. Each file is written under a
+/// hidden temp name and renamed into place once complete, so readers never
+/// see a partial message.
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import MaildirWriter
+/// box = MaildirWriter("archive/Maildir")
+/// box.add(raw_bytes)                  # new/, unseen
+/// box.add(other_bytes, flags="RS")    # cur/, seen and replied
+/// box.add(raw_bytes, keywords=["work"])
+/// print(box.report().message_count)
+/// ```
+#[pyclass]
+pub struct MaildirWriter {
+    /// Root of the tree, whose quota file records deliveries
+    root: PathBuf,
+    /// Folder messages are delivered to
+    dir: PathBuf,
+    keywords: Keywords,
+    output: Output,
+}
+
+#[pymethods]
+impl MaildirWriter {
+    /// Open (creating if needed) a Maildir for delivery
+    ///
+    /// # Arguments
+    /// * `path` - Maildir directory
+    /// * `dry_run` - Report what would be written without touching the disk
+    /// * `fsync` - Durability policy: "none", "file" (default) or "full"
+    /// * `folder` - Maildir++ folder under `path` to deliver to, created
+    ///   with its marker file if missing (default: `path` itself)
+    ///
+    /// # Raises
+    /// * `ValueError` for an invalid folder name
+    /// * `IOError` if the directories cannot be created
+    #[new]
+    #[pyo3(signature = (path, dry_run=false, fsync="file", folder=None))]
+    fn new(path: &str, dry_run: bool, fsync: &str, folder: Option<&str>) -> PyResult<Self> {
+        let output =
+            Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+        let root = PathBuf::from(path);
+        let dir = folder_dir(&root, folder)?;
+        create_maildir(&root, &dir, &output)?;
+        let keywords = Keywords::load(&dir).map_err(|e| io_error(&dir, &e))?;
+        Ok(Self {
+            root,
+            dir,
+            keywords,
+            output,
+        })
+    }
+
+    /// Store one message
+    ///
+    /// # Arguments
+    /// * `raw_bytes` - Message bytes (a leading mbox `From ` line is dropped)
+    /// * `flags` - Maildir flags ("DFPRST"); `None` delivers to `new/` as
+    ///   unseen, any string (even "") files the message in `cur/`
+    /// * `keywords` - Custom keywords, recorded in the folder's
+    ///   `dovecot-keywords` (a message with keywords goes to `cur/`)
+    ///
+    /// # Returns
+    /// * Path of the written file
+    #[pyo3(signature = (raw_bytes, flags=None, keywords=None))]
+    fn add(
+        &mut self,
+        raw_bytes: &[u8],
+        flags: Option<&str>,
+        keywords: Option<Vec<String>>,
+    ) -> PyResult<String> {
+        let letters = self.keywords.letters(&keywords.unwrap_or_default());
+        self.keywords
+            .save(&self.dir, &self.output)
+            .map_err(|e| io_error(&self.dir, &e))?;
+        let flags = with_keywords(flags.map(String::from), &letters);
+        let (path, size) = deliver(&self.dir, raw_bytes, flags.as_deref(), None, &self.output)?;
+        record_usage(&self.root, size, 1, &self.output).map_err(|e| quota_error(&self.root, &e))?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// Files, messages and bytes written so far
+    fn report(&self) -> OutputReport {
+        self.output.report()
+    }
+}
+
+/// Convert an mbox into a Maildir
+///
+/// Every message becomes one file, written by parallel workers. The `From `
+/// separator line is dropped and the bytes are otherwise copied unchanged.
+/// Messages whose `Status:` header marks them as seen or old go to `cur/`
+/// with flags from `Status:` / `X-Status:` (`R` read → `S`, `A` → `R`eplied,
+/// `F` → `F`lagged, `T` → `D`raft, `D` → `T`rashed); the rest go to `new/`.
+/// Names in an `X-Keywords:` header become keyword flags, recorded in the
+/// folder's `dovecot-keywords`. File names start with the message's `Date`,
+/// so name order roughly follows time. Deliveries are added to the tree's
+/// `maildirsize` quota file if it exists.
+///
+/// # Arguments
+/// * `src` - Path to the mbox file
+/// * `dest` - Maildir directory (created if missing)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `folder` - Maildir++ folder under `dest` to fill, e.g. "Archive/2023"
+///   (default: `dest` itself)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` with the number of messages written
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import mbox_to_maildir
+/// report = mbox_to_maildir("inbox.mbox", "Maildir/.Inbox")
+/// print(f"{report.message_count} messages, {report.byte_total} bytes")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (src, dest, from_line_mode="standard", dry_run=false, fsync="file", folder=None, progress=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn mbox_to_maildir(
+    py: Python<'_>,
+    src: &str,
+    dest: &str,
+    from_line_mode: &str,
+    dry_run: bool,
+    fsync: &str,
+    folder: Option<&str>,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output =
+            Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+        let root = Path::new(dest);
+        let dir = folder_dir(root, folder)?;
+        create_maildir(root, &dir, &output)?;
+        let keywords = Keywords::load(&dir).map_err(|e| io_error(&dir, &e))?;
+        let keywords = Mutex::new(keywords);
+        let bytes = AtomicU64::new(0);
+        let mmap = map_file(src)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        spans.par_iter().try_for_each(|span| {
+            if !progress.message(span) {
+                return Ok(());
+            }
+            let raw = &data[span.clone()];
+            let msg = Message::parse(raw);
+            let epoch = msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch);
+            let names = msg.header("X-Keywords").map(keyword_header_names);
+            let letters = names.map_or_else(String::new, |names| {
+                keywords
+                    .lock()
+                    .map_or_else(|_| String::new(), |mut k| k.letters(&names))
+            });
+            let flags = with_keywords(flags_from_status(&msg), &letters);
+            let (_, size) = deliver(&dir, raw, flags.as_deref(), epoch, &output)?;
+            bytes.fetch_add(size, Ordering::Relaxed);
+            Ok::<_, PyErr>(())
+        })?;
+        progress.check("mbox_to_maildir")?;
+        mmap.verify(&mode)?;
+        if let Ok(mut keywords) = keywords.into_inner() {
+            keywords
+                .save(&dir, &output)
+                .map_err(|e| io_error(&dir, &e))?;
+        }
+        record_usage(root, bytes.into_inner(), spans.len() as u64, &output)
+            .map_err(|e| quota_error(root, &e))?;
+        Ok(output.report())
+    })
+}
+
+/// Convert a Maildir into an mbox
+///
+/// Messages are read by parallel workers and appended in `MaildirReader`
+/// order, or in IMAP UID order when the folder has a `dovecot-uidlist`
+/// (unlisted messages last). Each gets a `From ` separator built from
+/// `Return-Path`/`From` and `Date`, body lines starting with "From " are
+/// `>`-quoted, and the flags in the file name become `Status:` / `X-Status:`
+/// headers, keywords an `X-Keywords:` header and the UID an `X-UID:` header,
+/// unless the message already has them. Files that vanish while converting
+/// (a client moving them) are skipped.
+///
+/// # Arguments
+/// * `src` - Maildir directory
+/// * `dest` - Path of the mbox to write (`.gz`/`.zst` compress on the fly)
+/// * `include_tmp` - Also convert deliveries still in `tmp/` (default False)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `lock` - Lock `dest` while it is written: "none" (default), "dotlock",
+///   "fcntl", "flock" or a combination such as "dotlock+fcntl"
+/// * `folder` - Maildir++ folder under `src` to convert (default: `src`
+///   itself; see `maildir_tree_to_mbox` for every folder)
+///
+/// # Returns
+/// * `OutputReport` with the number of messages written
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import maildir_to_mbox
+/// maildir_to_mbox("Maildir/.Sent", "sent.mbox.zst")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    src,
+    dest,
+    include_tmp=false,
+    dry_run=false,
+    fsync="file",
+    lock="none",
+    folder=None
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn maildir_to_mbox(
+    py: Python<'_>,
+    src: &str,
+    dest: &str,
+    include_tmp: bool,
+    dry_run: bool,
+    fsync: &str,
+    lock: &str,
+    folder: Option<&str>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        let output = Output::from_args(dry_run, fsync, None)?.with_lock(LockSpec::parse(lock)?);
+        let dir = folder_dir(Path::new(src), folder)?;
+        let dest = Path::new(dest);
+        let archive = output.create(dest).map_err(|e| io_error(dest, &e))?;
+        write_folder(archive, &dir, dest, include_tmp, &output)?;
+        Ok(output.report())
+    })
+}
+
+/// Append the messages of the Maildir folder `dir` to `archive` (at `dest`)
+/// and commit it
+fn write_folder(
+    mut archive: OutputFile,
+    dir: &Path,
+    dest: &Path,
+    include_tmp: bool,
+    output: &Output,
+) -> PyResult<()> {
+    let read_error = |e: std::io::Error| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to read {}: {e}",
+            dir.display()
+        ))
+    };
+    let mut files = message_files(dir, include_tmp).map_err(read_error)?;
+    let dovecot = DovecotFolder::load(dir).map_err(read_error)?;
+    files.sort_by_key(|path| dovecot.uid_of(path).map_or((1, 0), |uid| (0, uid)));
+    for batch in files.chunks(BATCH_SIZE) {
+        let entries: Vec<Vec<u8>> = batch
+            .par_iter()
+            .filter_map(|path| {
+                let raw = fs::read(crate::paths::extended(path)).ok()?;
+                Some(mbox_entry(path, &raw, &dovecot))
+            })
+            .collect();
+        for entry in entries {
+            archive.write_all(&entry).map_err(|e| io_error(dest, &e))?;
+            output.note_message();
+        }
+    }
+    archive.finish().map_err(|e| io_error(dest, &e))?;
+    Ok(())
+}
+
+/// Convert every folder of a Maildir++ tree into its own mbox
+///
+/// INBOX (the root Maildir) becomes `<dest_dir>/INBOX.mbox` and each folder
+/// an mbox at its path, so `.Sent.2023` becomes `<dest_dir>/Sent/2023.mbox`;
+/// folder names are made safe as file names. Messages are converted as by
+/// `maildir_to_mbox`, flags and keywords included.
+///
+/// # Arguments
+/// * `src` - Root Maildir of the tree
+/// * `dest_dir` - Directory for the mbox files (created if missing)
+/// * `include_tmp` - Also convert deliveries still in `tmp/` (default False)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+///
+/// # Returns
+/// * `OutputReport` over all folders
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import maildir_tree_to_mbox
+/// report = maildir_tree_to_mbox("/home/me/Maildir", "export")
+/// print(report.file_count, "folders")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (src, dest_dir, include_tmp=false, dry_run=false, fsync="file"))]
+pub fn maildir_tree_to_mbox(
+    py: Python<'_>,
+    src: &str,
+    dest_dir: &str,
+    include_tmp: bool,
+    dry_run: bool,
+    fsync: &str,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        let output = Output::from_args(dry_run, fsync, None)?;
+        let root = Path::new(src);
+        let names = folders(root).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {src}: {e}"))
+        })?;
+        for name in names {
+            let dir = folder_dir(root, Some(&name))?;
+            let mut dest = PathBuf::from(dest_dir);
+            for component in name.split('/') {
+                dest.push(crate::paths::sanitize_component(component));
+            }
+            dest.set_extension("mbox");
+            if !output.is_dry_run() {
+                if let Some(parent) = dest.parent() {
+                    crate::paths::create_dir_all(parent).map_err(|e| io_error(parent, &e))?;
+                }
+            }
+            let archive = output.create(&dest).map_err(|e| io_error(&dest, &e))?;
+            write_folder(archive, &dir, &dest, include_tmp, &output)?;
+        }
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maildirpp::{MaildirQuota, FOLDER_MARKER};
+
+    #[test]
+    fn test_names_and_flags() {
+        assert_eq!(split_info("1700000000.1.host:2,RS"), ("1700000000.1.host", Some("RS")));
+        assert_eq!(split_info("1700000000.1.host!2,S"), ("1700000000.1.host", Some("S")));
+        assert_eq!(split_info("1700000000.1.host"), ("1700000000.1.host", None));
+        assert_eq!(split_info("a:b.host:1,xyz"), ("a:b.host:1,xyz", None));
+        assert_eq!(normalize_flags("SXRS"), "RS");
+        assert_eq!(normalize_flags("bSa"), "Sab");
+        let seen = Message::parse(b"Status: RO\nX-Status: AF\n\nbody\n");
+        assert_eq!(flags_from_status(&seen).as_deref(), Some("FRS"));
+        assert_eq!(flags_from_status(&Message::parse(b"Subject: x\n\n")), None);
+        assert_eq!(status_headers(false, "FRS"), "Status: RO\nX-Status: AF\n");
+        assert_eq!(status_headers(true, ""), "");
+    }
+
+    #[test]
+    fn test_maildir_roundtrip() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_maildir_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            fs::write(
+                &src,
+                "From a@x Mon Jan  1 00:00:00 2024\nSubject: one\nDate: Mon, 1 Jan 2024 00:00:00 +0000\n\n\
+>From the top\n\n\
+From b@x Tue Jan  2 00:00:00 2024\nSubject: two\nStatus: RO\nX-Status: A\n\nbody\n",
+            )
+            .unwrap();
+            let maildir = dir.join("Maildir");
+            let src_str = src.to_str().unwrap();
+            let maildir_str = maildir.to_str().unwrap();
+
+            let dry =
+                mbox_to_maildir(py, src_str, maildir_str, "standard", true, "none", None, None)
+                    .unwrap();
+            assert_eq!(dry.message_count, 2);
+            assert!(!maildir.exists());
+
+            let report =
+                mbox_to_maildir(py, src_str, maildir_str, "standard", false, "none", None, None)
+                    .unwrap();
+            assert_eq!((report.message_count, report.file_count), (2, 2));
+            assert!(maildir.join("tmp").is_dir());
+
+            let mut reader = MaildirReader::new(maildir_str, false, None).unwrap();
+            assert_eq!(reader.__len__(), 2);
+            let first = reader.__next__().unwrap();
+            assert_eq!((first.subdir().as_str(), first.flags().as_str()), ("new", ""));
+            assert!(first.unique().starts_with("1704067200."));
+            assert_eq!(first.header("subject").unwrap().as_deref(), Some("one"));
+            assert!(first.bytes().unwrap().starts_with(b"Subject: one\n"));
+            let second = reader.__next__().unwrap();
+            assert_eq!((second.subdir().as_str(), second.flags().as_str()), ("cur", "RS"));
+            assert!(reader.__next__().is_none());
+
+            let mut writer = MaildirWriter::new(maildir_str, false, "none", None).unwrap();
+            let added = writer
+                .add(b"Subject: three\n\nFrom me\n", Some("FS"), None)
+                .unwrap();
+            assert!(added.ends_with(&format!("{INFO_SEPARATOR}2,FS")));
+            fs::write(maildir.join("tmp").join("partial"), "Subject: tmp\n\n").unwrap();
+            assert_eq!(writer.report().message_count, 1);
+
+            let out = dir.join("out.mbox");
+            let report = maildir_to_mbox(
+                py,
+                maildir_str,
+                out.to_str().unwrap(),
+                false,
+                false,
+                "none",
+                "none",
+                None,
+            )
+            .unwrap();
+            assert_eq!(report.message_count, 3);
+            let mbox = fs::read_to_string(&out).unwrap();
+            assert!(mbox.starts_with("From MAILER-DAEMON Mon Jan  1 00:00:00 2024\nSubject: one\n"));
+            assert!(mbox.contains("\n>From the top\n"));
+            assert!(mbox.contains("Subject: two\nStatus: RO\nX-Status: A\n"));
+            assert!(mbox.contains("Status: RO\nX-Status: F\nSubject: three\n\n>From me\n"));
+            assert_eq!(
+                crate::mbox::message_spans_with(mbox.as_bytes(), &FromLineMode::Standard).len(),
+                3
+            );
+            let out_str = out.to_str().unwrap();
+            let with_tmp =
+                maildir_to_mbox(py, maildir_str, out_str, true, true, "none", "none", None)
+                    .unwrap();
+            assert_eq!(with_tmp.message_count, 4);
+
+            assert!(MaildirReader::new(dir.join("missing").to_str().unwrap(), false, None).is_err());
+            fs::remove_dir_all(dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_folders_keywords_and_quota() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_maildir_pp_{}", std::process::id()));
+            let root = dir.join("Maildir");
+            fs::create_dir_all(&root).unwrap();
+            fs::write(root.join("maildirsize"), "1000S,10C\n").unwrap();
+            let src = dir.join("in.mbox");
+            fs::write(
+                &src,
+                "From a@x Mon Jan  1 00:00:00 2024\nSubject: one\nX-Keywords: $Work, todo\n\nx\n\n\
+From b@x Tue Jan  2 00:00:00 2024\nSubject: two\n\ny\n",
+            )
+            .unwrap();
+            let (src_str, root_str) = (src.to_str().unwrap(), root.to_str().unwrap());
+            let folder = Some("Sent/2023");
+            mbox_to_maildir(py, src_str, root_str, "standard", false, "none", folder, None)
+                .unwrap();
+            let sent = root.join(".Sent.2023");
+            assert!(sent.join(FOLDER_MARKER).is_file());
+            assert_eq!(
+                fs::read_to_string(sent.join("dovecot-keywords")).unwrap(),
+                "0 $Work\n1 todo\n"
+            );
+            let mut reader = MaildirReader::new(root_str, false, folder).unwrap();
+            let mut tagged = reader.__next__().unwrap();
+            if tagged.flags().is_empty() {
+                tagged = reader.__next__().unwrap();
+            }
+            assert_eq!(tagged.flags(), "ab");
+            assert_eq!(tagged.subdir(), "cur");
+            assert_eq!(tagged.keywords(), ["$Work", "todo"]);
+            assert!(MaildirReader::new(root_str, false, None).unwrap().__len__() == 0);
+
+            let uids: Vec<String> = fs::read_dir(sent.join("cur"))
+                .unwrap()
+                .chain(fs::read_dir(sent.join("new")).unwrap())
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            let listed = uids
+                .iter()
+                .enumerate()
+                .fold(String::new(), |mut out, (i, name)| {
+                    let _ = writeln!(out, "{} :{}", 20 - i, split_info(name).0);
+                    out
+                });
+            fs::write(sent.join("dovecot-uidlist"), format!("3 V5 N21\n{listed}")).unwrap();
+            let tagged = MaildirReader::new(root_str, false, folder)
+                .unwrap()
+                .__next__()
+                .unwrap();
+            assert!(matches!(tagged.imap_uid(), Some(19 | 20)));
+
+            let mut writer = MaildirWriter::new(root_str, false, "none", None).unwrap();
+            writer
+                .add(b"Subject: three\n\nz\n", None, Some(vec!["todo".into()]))
+                .unwrap();
+            let quota = MaildirQuota::load(&root).unwrap().unwrap();
+            assert_eq!((quota.used_messages, quota.limit_bytes), (3, Some(1000)));
+
+            let out = dir.join("out");
+            let report =
+                maildir_tree_to_mbox(py, root_str, out.to_str().unwrap(), false, false, "none")
+                    .unwrap();
+            assert_eq!((report.message_count, report.file_count), (3, 2));
+            let sent = fs::read_to_string(out.join("Sent").join("2023.mbox")).unwrap();
+            assert!(sent.contains("Subject: one\nX-Keywords: $Work, todo\n"));
+            assert!(!sent.contains("X-Keywords: $Work todo"));
+            let (first, second) =
+                (sent.find("X-UID: 19\n").unwrap(), sent.find("X-UID: 20\n").unwrap());
+            assert!(first < second);
+            let inbox = fs::read_to_string(out.join("INBOX.mbox")).unwrap();
+            assert!(inbox.contains("X-Keywords: todo\nSubject: three\n"));
+            fs::remove_dir_all(dir).unwrap();
+        });
+    }
+}
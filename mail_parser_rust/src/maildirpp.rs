@@ -0,0 +1,481 @@
+//! Maildir++ folders, Courier quotas and Dovecot keywords.
+//!
+//! A Maildir++ tree keeps INBOX in the root Maildir and every other folder
+//! in a hidden subdirectory of the root named after its path, with `.` as
+//! the hierarchy separator (`.Sent.2023` is `Sent/2023`) and non-ASCII names
+//! in IMAP modified UTF-7. Each folder has its own `cur/`, `new/` and `tmp/`
+//! and a `maildirfolder` marker file.
+//!
+//! The root may hold a Courier `maildirsize` file: a quota definition line
+//! (`<bytes>S,<messages>C`, either part optional) followed by lines of byte
+//! and message deltas that deliveries append; usage is their sum. Dovecot
+//! keeps up to 26 custom keywords per folder in `dovecot-keywords` as
+//! `<n> <name>` lines, and sets keyword `n` on a message with the file name
+//! flag letter `a` + `n`.
+
+use crate::output::Output;
+use crate::paths::extended;
+use base64::alphabet::IMAP_MUTF7;
+use base64::engine::general_purpose::{GeneralPurpose, NO_PAD};
+use base64::Engine;
+use pyo3::prelude::*;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the root folder
+pub const INBOX: &str = "INBOX";
+
+/// Marker file of a Maildir++ subfolder
+pub const FOLDER_MARKER: &str = "maildirfolder";
+
+/// Courier quota file in the root of a tree
+const QUOTA_FILE: &str = "maildirsize";
+
+/// Dovecot keyword names of a folder
+const KEYWORDS_FILE: &str = "dovecot-keywords";
+
+/// Keyword slots: flag letters `a` to `z`
+const MAX_KEYWORDS: usize = 26;
+
+/// The modified base64 of IMAP mailbox names (`,` for `/`, no padding)
+const MUTF7: GeneralPurpose = GeneralPurpose::new(&IMAP_MUTF7, NO_PAD);
+
+/// Encode a folder name component in IMAP modified UTF-7 (RFC 3501)
+pub fn encode_mutf7(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut pending: Vec<u16> = Vec::new();
+    let flush = |pending: &mut Vec<u16>, out: &mut String| {
+        if !pending.is_empty() {
+            let bytes: Vec<u8> = pending.iter().flat_map(|unit| unit.to_be_bytes()).collect();
+            out.push('&');
+            out.push_str(&MUTF7.encode(bytes));
+            out.push('-');
+            pending.clear();
+        }
+    };
+    for c in name.chars() {
+        if (' '..='~').contains(&c) {
+            flush(&mut pending, &mut out);
+            if c == '&' {
+                out.push_str("&-");
+            } else {
+                out.push(c);
+            }
+        } else {
+            pending.extend(c.encode_utf16(&mut [0; 2]).iter());
+        }
+    }
+    flush(&mut pending, &mut out);
+    out
+}
+
+/// Decode an IMAP modified UTF-7 folder name component; malformed shifts
+/// are kept as written
+pub fn decode_mutf7(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find('-') else {
+            break;
+        };
+        let encoded = &rest[1..end];
+        if encoded.is_empty() {
+            out.push('&');
+        } else {
+            let units: Option<Vec<u16>> = MUTF7
+                .decode(encoded)
+                .ok()
+                .filter(|bytes| bytes.len() % 2 == 0)
+                .map(|bytes| {
+                    bytes
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                        .collect()
+                });
+            match units.and_then(|units| String::from_utf16(&units).ok()) {
+                Some(decoded) => out.push_str(&decoded),
+                None => out.push_str(&rest[..=end]),
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Directory of `folder` in the tree rooted at `root`: the root itself for
+/// None, "" or "INBOX", else `.<a>.<b>` for "a/b" (an "INBOX/" prefix is
+/// ignored)
+pub fn folder_dir(root: &Path, folder: Option<&str>) -> PyResult<PathBuf> {
+    let folder = folder.unwrap_or_default().trim_matches('/');
+    let has_inbox_prefix = folder.len() > INBOX.len()
+        && folder.as_bytes()[..INBOX.len()].eq_ignore_ascii_case(INBOX.as_bytes())
+        && folder.as_bytes()[INBOX.len()] == b'/';
+    let folder = if has_inbox_prefix {
+        &folder[INBOX.len() + 1..]
+    } else {
+        folder
+    };
+    if folder.is_empty() || folder.eq_ignore_ascii_case(INBOX) {
+        return Ok(root.to_path_buf());
+    }
+    let mut name = String::new();
+    for component in folder.split('/') {
+        if component.is_empty() || component.contains('.') {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid Maildir++ folder {folder:?}: components are separated by \"/\" \
+                 and may not be empty or contain \".\""
+            )));
+        }
+        name.push('.');
+        name.push_str(&encode_mutf7(component));
+    }
+    Ok(root.join(name))
+}
+
+/// Folders of the Maildir++ tree at `root`: "INBOX", then every subfolder
+/// as a "/"-separated path, sorted
+pub fn folders(root: &Path) -> io::Result<Vec<String>> {
+    if !extended(root).is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "not a Maildir directory"));
+    }
+    let mut names: Vec<String> = fs::read_dir(extended(root))?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let folder = name
+                .strip_prefix('.')
+                .filter(|f| !f.is_empty() && *f != ".")?;
+            let path = entry.path();
+            let is_folder = [FOLDER_MARKER, "cur", "new"]
+                .iter()
+                .any(|marker| path.join(marker).exists());
+            is_folder.then(|| {
+                folder
+                    .split('.')
+                    .map(decode_mutf7)
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+        })
+        .collect();
+    names.sort();
+    names.insert(0, INBOX.to_string());
+    Ok(names)
+}
+
+/// Custom keywords of one Maildir folder, by flag letter
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keywords {
+    /// Name of keyword `n` (flag letter `a` + `n`); empty for unused slots
+    names: Vec<String>,
+    /// Whether keywords were added since loading
+    changed: bool,
+}
+
+impl Keywords {
+    /// Parse `dovecot-keywords` contents; malformed lines are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut keywords = Self::default();
+        for line in text.lines() {
+            let Some((index, name)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(index) = index.parse::<usize>() else {
+                continue;
+            };
+            let name = name.trim();
+            if index < MAX_KEYWORDS && !name.is_empty() {
+                if keywords.names.len() <= index {
+                    keywords.names.resize(index + 1, String::new());
+                }
+                keywords.names[index] = name.to_string();
+            }
+        }
+        keywords
+    }
+
+    /// The keywords of the folder at `dir` (none when it has no keyword file)
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        match fs::read(extended(&dir.join(KEYWORDS_FILE))) {
+            Ok(bytes) => Ok(Self::parse(&String::from_utf8_lossy(&bytes))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Names of the keyword letters among file name `flags`
+    pub fn names(&self, flags: &str) -> Vec<String> {
+        flags
+            .bytes()
+            .filter(u8::is_ascii_lowercase)
+            .filter_map(|letter| self.names.get(usize::from(letter - b'a')))
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// Flag letter and name of every keyword in use
+    pub fn assigned(&self) -> impl Iterator<Item = (char, &str)> + '_ {
+        (b'a'..=b'z')
+            .zip(&self.names)
+            .filter(|(_, name)| !name.is_empty())
+            .map(|(letter, name)| (char::from(letter), name.as_str()))
+    }
+
+    /// Flag letters for `names`, assigning free slots to new keywords;
+    /// keywords beyond the 26 slots are dropped
+    pub fn letters<S: AsRef<str>>(&mut self, names: &[S]) -> String {
+        let mut letters = String::new();
+        for name in names {
+            let name = name.as_ref().trim();
+            if name.is_empty() {
+                continue;
+            }
+            let slot = self.names.iter().position(|n| n == name).or_else(|| {
+                let free = self.names.iter().position(String::is_empty);
+                let slot =
+                    free.or_else(|| (self.names.len() < MAX_KEYWORDS).then_some(self.names.len()))?;
+                if slot == self.names.len() {
+                    self.names.push(String::new());
+                }
+                self.names[slot] = name.to_string();
+                self.changed = true;
+                Some(slot)
+            });
+            if let Some(slot) = slot {
+                letters.push(char::from(b'a' + u8::try_from(slot).unwrap_or_default()));
+            }
+        }
+        letters
+    }
+
+    /// Write the keyword file of the folder at `dir` if keywords were added
+    pub fn save(&mut self, dir: &Path, output: &Output) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut file = output.create(&dir.join(KEYWORDS_FILE))?;
+        for (index, name) in self.names.iter().enumerate() {
+            if !name.is_empty() {
+                writeln!(file, "{index} {name}")?;
+            }
+        }
+        file.finish()?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Keyword names of an mbox `X-Keywords:` header (space or comma separated)
+pub fn keyword_header_names(value: &str) -> Vec<String> {
+    value
+        .split([' ', ',', '\t'])
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Quota and usage of a Maildir++ tree, from its `maildirsize` file
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import maildir_quota
+/// quota = maildir_quota("/home/me/Maildir")
+/// if quota and quota.limit_bytes:
+///     print(f"{quota.used_bytes / quota.limit_bytes:.0%} of quota used")
+/// ```
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaildirQuota {
+    /// Byte limit (None: no byte limit)
+    #[pyo3(get)]
+    pub limit_bytes: Option<u64>,
+    /// Message count limit (None: no count limit)
+    #[pyo3(get)]
+    pub limit_messages: Option<u64>,
+    /// Bytes in use, the sum of the recorded deltas
+    #[pyo3(get)]
+    pub used_bytes: i64,
+    /// Messages in use, the sum of the recorded deltas
+    #[pyo3(get)]
+    pub used_messages: i64,
+}
+
+impl MaildirQuota {
+    /// Parse `maildirsize` contents
+    pub fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        let mut quota = Self::default();
+        for part in lines.next().unwrap_or_default().split(',') {
+            let part = part.trim();
+            let (number, unit) = part.split_at(part.len().saturating_sub(1));
+            match (number.parse::<u64>(), unit) {
+                (Ok(n), "S") => quota.limit_bytes = Some(n),
+                (Ok(n), "C") => quota.limit_messages = Some(n),
+                _ => {},
+            }
+        }
+        for line in lines {
+            let mut fields = line.split_whitespace().map(str::parse::<i64>);
+            if let (Some(Ok(bytes)), Some(Ok(messages))) = (fields.next(), fields.next()) {
+                quota.used_bytes = quota.used_bytes.saturating_add(bytes);
+                quota.used_messages = quota.used_messages.saturating_add(messages);
+            }
+        }
+        quota
+    }
+
+    /// The quota of the tree at `root`, None without a `maildirsize` file
+    pub fn load(root: &Path) -> io::Result<Option<Self>> {
+        match fs::read(extended(&root.join(QUOTA_FILE))) {
+            Ok(bytes) => Ok(Some(Self::parse(&String::from_utf8_lossy(&bytes)))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[pymethods]
+impl MaildirQuota {
+    /// Whether usage is over either limit
+    #[getter]
+    fn exceeded(&self) -> bool {
+        let over = |limit: Option<u64>, used: i64| {
+            limit.is_some_and(|limit| u64::try_from(used).is_ok_and(|used| used > limit))
+        };
+        over(self.limit_bytes, self.used_bytes) || over(self.limit_messages, self.used_messages)
+    }
+
+    fn __repr__(&self) -> String {
+        let limit =
+            |limit: Option<u64>| limit.map_or_else(|| "None".to_string(), |l| l.to_string());
+        format!(
+            "MaildirQuota(used_bytes={}, limit_bytes={}, used_messages={}, limit_messages={})",
+            self.used_bytes,
+            limit(self.limit_bytes),
+            self.used_messages,
+            limit(self.limit_messages)
+        )
+    }
+}
+
+/// Record `messages` deliveries totalling `bytes` in the quota file of the
+/// tree at `root`, if it has one (deliveries never create it)
+pub fn record_usage(root: &Path, bytes: u64, messages: u64, output: &Output) -> io::Result<()> {
+    if output.is_dry_run() || messages == 0 {
+        return Ok(());
+    }
+    match OpenOptions::new()
+        .append(true)
+        .open(extended(&root.join(QUOTA_FILE)))
+    {
+        Ok(mut file) => writeln!(file, "{bytes} {messages}"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Folders of a Maildir++ tree
+///
+/// # Arguments
+/// * `path` - Root Maildir (the INBOX)
+///
+/// # Returns
+/// * "INBOX", then each subfolder as a "/"-separated path with modified
+///   UTF-7 decoded (`.Sent.2023` is "Sent/2023"), sorted
+///
+/// # Raises
+/// * `IOError` if the directory cannot be listed
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import maildir_folders, MaildirReader
+/// for folder in maildir_folders("/home/me/Maildir"):
+///     print(folder, len(MaildirReader("/home/me/Maildir", folder=folder)))
+/// ```
+#[pyfunction]
+pub fn maildir_folders(py: Python<'_>, path: &str) -> PyResult<Vec<String>> {
+    py.allow_threads(|| {
+        folders(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {path}: {e}"))
+        })
+    })
+}
+
+/// Quota and usage of a Maildir++ tree from its `maildirsize` file
+///
+/// # Returns
+/// * `MaildirQuota`, or None when the tree has no quota file
+///
+/// # Raises
+/// * `IOError` if the quota file cannot be read
+#[pyfunction]
+pub fn maildir_quota(py: Python<'_>, path: &str) -> PyResult<Option<MaildirQuota>> {
+    py.allow_threads(|| {
+        MaildirQuota::load(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {path}: {e}"))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutf7_and_folders() {
+        assert_eq!(encode_mutf7("Entwürfe"), "Entw&APw-rfe");
+        assert_eq!(encode_mutf7("R&D"), "R&-D");
+        assert_eq!(encode_mutf7("日本語"), "&ZeVnLIqe-");
+        assert_eq!(decode_mutf7("Entw&APw-rfe"), "Entwürfe");
+        assert_eq!(decode_mutf7("R&-D"), "R&D");
+        assert_eq!(decode_mutf7("&ZeVnLIqe-"), "日本語");
+        assert_eq!(decode_mutf7("bad&!!-x&"), "bad&!!-x&");
+
+        let root = Path::new("/m");
+        assert_eq!(folder_dir(root, None).unwrap(), root);
+        assert_eq!(folder_dir(root, Some("inbox")).unwrap(), root);
+        assert_eq!(folder_dir(root, Some("Sent/2023")).unwrap(), root.join(".Sent.2023"));
+        assert_eq!(folder_dir(root, Some("INBOX/Sent")).unwrap(), root.join(".Sent"));
+        assert_eq!(folder_dir(root, Some("Entwürfe")).unwrap(), root.join(".Entw&APw-rfe"));
+        assert!(folder_dir(root, Some("a.b")).is_err());
+        assert!(folder_dir(root, Some("a//b")).is_err());
+
+        let dir = std::env::temp_dir().join(format!("mpr_maildirpp_{}", std::process::id()));
+        for sub in [".Sent.2023/cur", ".Entw&APw-rfe/new", ".Trash", "cur"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join(".Trash").join(FOLDER_MARKER), "").unwrap();
+        fs::create_dir_all(dir.join(".not-a-folder")).unwrap();
+        assert_eq!(folders(&dir).unwrap(), ["INBOX", "Entwürfe", "Sent/2023", "Trash"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_keywords_and_quota() {
+        let mut keywords = Keywords::parse("0 $Label1\n2 work\nbogus\n30 far\n");
+        assert_eq!(keywords.names("RSac"), ["$Label1", "work"]);
+        assert_eq!(keywords.names("b"), Vec::<String>::new());
+        assert_eq!(keywords.letters(&["work", "urgent", "$Label1"]), "cba");
+        assert!(keywords.changed);
+        assert_eq!(keywords.names("b"), ["urgent"]);
+        let mut full = Keywords::default();
+        let many: Vec<String> = (0..30).map(|i| format!("k{i}")).collect();
+        assert_eq!(full.letters(&many).len(), MAX_KEYWORDS);
+        assert_eq!(keyword_header_names("a, b\tc"), ["a", "b", "c"]);
+
+        let quota = MaildirQuota::parse("1000000S,100C\n5000 3\n-1200 -1\n  300   1 \n");
+        assert_eq!(quota.limit_bytes, Some(1_000_000));
+        assert_eq!(quota.limit_messages, Some(100));
+        assert_eq!((quota.used_bytes, quota.used_messages), (4100, 3));
+        assert!(!quota.exceeded());
+        let small = MaildirQuota::parse("2C\n10 3\n");
+        assert_eq!(small.limit_bytes, None);
+        assert!(small.exceeded());
+    }
+}
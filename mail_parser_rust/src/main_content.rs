@@ -0,0 +1,447 @@
+//! Readability-style main content extraction for newsletter/marketing HTML.
+//!
+//! The markup is parsed into a small forgiving tree, obvious chrome (`nav`,
+//! `footer`, forms, and elements whose class/id says menu, footer, social,
+//! unsubscribe, ...) is dropped, and every text paragraph adds a score to the
+//! containers above it, weighted by its length and commas. The best-scoring
+//! container, discounted by its share of link text, plus sufficiently strong
+//! siblings is taken as the article.
+
+use crate::html::{attr, decode_entities, html_to_text, tokenize, Token};
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+/// Elements never part of the article (their whole subtree is dropped)
+const DROPPED: &[&str] = &[
+    "aside", "button", "footer", "form", "head", "iframe", "input", "nav", "noscript", "script",
+    "select", "style", "svg", "template", "textarea", "title",
+];
+
+/// Elements without content or close tag
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "wbr",
+];
+
+/// Class/id fragments of page chrome
+const NEGATIVE: &[&str] = &[
+    "banner",
+    "comment",
+    "copyright",
+    "footer",
+    "header",
+    "legal",
+    "menu",
+    "nav",
+    "preheader",
+    "preference",
+    "promo",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+    "subscribe",
+    "unsubscribe",
+    "widget",
+];
+
+/// Class/id fragments of article containers
+const POSITIVE: &[&str] = &[
+    "article", "body", "content", "entry", "main", "post", "story", "text",
+];
+
+/// Elements whose own text makes them a paragraph
+const PARAGRAPHS: &[&str] = &["p", "pre", "td", "blockquote", "li"];
+
+/// Containers that count as a paragraph when they hold no block children
+const WRAPPERS: &[&str] = &["div", "section", "article", "span", "font", "center"];
+
+/// Shortest text that counts as a paragraph
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Ancestors a paragraph's score propagates to
+const SCORE_DEPTH: usize = 5;
+
+/// The extracted article
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct MainContent {
+    /// `<title>` text, else the first `<h1>`
+    #[pyo3(get)]
+    pub title: Option<String>,
+    /// Article as plain text
+    #[pyo3(get)]
+    pub text: String,
+    /// Article markup (the selected elements, re-serialized)
+    #[pyo3(get)]
+    pub html: String,
+    /// Share of the article text inside links
+    #[pyo3(get)]
+    pub link_density: f64,
+}
+
+enum Child {
+    Node(usize),
+    Text(String),
+}
+
+struct Node {
+    name: String,
+    attrs: String,
+    parent: usize,
+    children: Vec<Child>,
+}
+
+/// Document tree; node 0 is a synthetic root, and children always have
+/// higher indices than their parents
+struct Tree {
+    nodes: Vec<Node>,
+    title: Option<String>,
+}
+
+fn is_chrome(name: &str, attrs: &str) -> bool {
+    let labels = format!(
+        "{} {}",
+        attr(attrs, "class").unwrap_or_default(),
+        attr(attrs, "id").unwrap_or_default()
+    )
+    .to_ascii_lowercase();
+    let hidden = attr(attrs, "style").is_some_and(|style| {
+        let style: String = style.to_ascii_lowercase().split_whitespace().collect();
+        style.contains("display:none")
+    });
+    DROPPED.contains(&name)
+        || hidden
+        || (NEGATIVE.iter().any(|n| labels.contains(n))
+            && !POSITIVE.iter().any(|p| labels.contains(p)))
+}
+
+fn parse(html: &str) -> Tree {
+    let mut nodes = vec![Node {
+        name: String::new(),
+        attrs: String::new(),
+        parent: 0,
+        children: Vec::new(),
+    }];
+    let tokens = tokenize(html);
+    let title = find_title(&tokens);
+    let mut stack: Vec<usize> = vec![0];
+    // Open elements of the dropped subtree we are inside, if any
+    let mut dropped: Vec<String> = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Open(name, attrs) => {
+                let void = VOID.contains(&name.as_str()) || attrs.trim_end().ends_with('/');
+                if !dropped.is_empty() || is_chrome(&name, attrs) {
+                    if !void {
+                        dropped.push(name);
+                    }
+                    continue;
+                }
+                let parent = stack.last().copied().unwrap_or(0);
+                let index = nodes.len();
+                nodes.push(Node {
+                    name: name.clone(),
+                    attrs: attrs.trim_end_matches('/').trim().to_string(),
+                    parent,
+                    children: Vec::new(),
+                });
+                nodes[parent].children.push(Child::Node(index));
+                if !void {
+                    stack.push(index);
+                }
+            },
+            Token::Close(name) => {
+                if let Some(pos) = dropped.iter().rposition(|n| *n == name) {
+                    dropped.truncate(pos);
+                } else if dropped.is_empty() {
+                    if let Some(pos) = stack.iter().rposition(|&i| i > 0 && nodes[i].name == name) {
+                        stack.truncate(pos);
+                    }
+                }
+            },
+            Token::Text(text) => {
+                if dropped.is_empty() {
+                    let parent = stack.last().copied().unwrap_or(0);
+                    nodes[parent].children.push(Child::Text(text.to_string()));
+                }
+            },
+        }
+    }
+    Tree { nodes, title }
+}
+
+/// Text of the first `<title>`, looking inside `<head>` (whose content the
+/// tokenizer returns unparsed)
+fn find_title(tokens: &[Token]) -> Option<String> {
+    tokens.windows(2).find_map(|pair| match pair {
+        [Token::Open(name, _), Token::Text(text)] if name == "title" => {
+            Some(collapse(&decode_entities(text)))
+        },
+        [Token::Open(name, _), Token::Text(text)] if name == "head" => find_title(&tokenize(text)),
+        _ => None,
+    })
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Per-node text statistics, accumulated bottom-up
+#[derive(Clone, Default)]
+struct Stats {
+    chars: usize,
+    link_chars: usize,
+    commas: usize,
+    has_blocks: bool,
+}
+
+impl Tree {
+    fn stats(&self) -> Vec<Stats> {
+        let mut stats = vec![Stats::default(); self.nodes.len()];
+        for i in (0..self.nodes.len()).rev() {
+            let mut own = Stats::default();
+            for child in &self.nodes[i].children {
+                match child {
+                    Child::Text(text) => {
+                        let text = decode_entities(text);
+                        own.chars += collapse(&text).chars().count();
+                        own.commas += text.matches(',').count();
+                    },
+                    Child::Node(c) => {
+                        let s = &stats[*c];
+                        own.chars += s.chars;
+                        own.link_chars += s.link_chars;
+                        own.commas += s.commas;
+                        own.has_blocks |= s.has_blocks || is_block(&self.nodes[*c].name);
+                    },
+                }
+            }
+            if self.nodes[i].name == "a" {
+                own.link_chars = own.chars;
+            }
+            stats[i] = own;
+        }
+        stats
+    }
+
+    fn ancestors(&self, mut index: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::from_fn(move || {
+            (index != 0).then(|| {
+                index = self.nodes[index].parent;
+                index
+            })
+        })
+    }
+
+    fn first_heading(&self) -> Option<String> {
+        let h1 = self.nodes.iter().position(|n| n.name == "h1")?;
+        let mut out = String::new();
+        self.serialize(h1, &mut out);
+        Some(collapse(&html_to_text(&out))).filter(|t| !t.is_empty())
+    }
+
+    fn serialize(&self, index: usize, out: &mut String) {
+        let node = &self.nodes[index];
+        if index > 0 {
+            let _ = write!(out, "<{}", node.name);
+            if !node.attrs.is_empty() {
+                let _ = write!(out, " {}", node.attrs);
+            }
+            out.push('>');
+        }
+        for child in &node.children {
+            match child {
+                Child::Text(text) => out.push_str(text),
+                Child::Node(c) => self.serialize(*c, out),
+            }
+        }
+        if index > 0 && !VOID.contains(&node.name.as_str()) {
+            let _ = write!(out, "</{}>", node.name);
+        }
+    }
+}
+
+fn is_block(name: &str) -> bool {
+    PARAGRAPHS.contains(&name)
+        || WRAPPERS.contains(&name) && name != "span" && name != "font"
+        || matches!(name, "table" | "tr" | "ul" | "ol" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Starting score of a container from its tag and class/id
+fn initial_score(node: &Node) -> f64 {
+    let tag = match node.name.as_str() {
+        "article" | "main" => 10.0,
+        "div" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "address" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    let labels = format!(
+        "{} {}",
+        attr(&node.attrs, "class").unwrap_or_default(),
+        attr(&node.attrs, "id").unwrap_or_default()
+    )
+    .to_ascii_lowercase();
+    let class = if POSITIVE.iter().any(|p| labels.contains(p)) {
+        25.0
+    } else {
+        0.0
+    };
+    tag + class
+}
+
+/// Share of `stats` text inside links
+#[allow(clippy::cast_precision_loss)]
+fn link_density(stats: &Stats) -> f64 {
+    if stats.chars == 0 {
+        0.0
+    } else {
+        stats.link_chars as f64 / stats.chars as f64
+    }
+}
+
+/// Paragraph weight: one point, plus commas, plus a point per 100 characters (up to 3)
+#[allow(clippy::cast_precision_loss)]
+fn paragraph_score(stats: &Stats) -> f64 {
+    1.0 + stats.commas as f64 + (stats.chars / 100).min(3) as f64
+}
+
+fn extract(html: &str) -> MainContent {
+    let tree = parse(html);
+    let stats = tree.stats();
+    let mut scores: Vec<Option<f64>> = vec![None; tree.nodes.len()];
+    for (i, node) in tree.nodes.iter().enumerate().skip(1) {
+        let is_paragraph = PARAGRAPHS.contains(&node.name.as_str())
+            || WRAPPERS.contains(&node.name.as_str()) && !stats[i].has_blocks;
+        if !is_paragraph || stats[i].chars < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = paragraph_score(&stats[i]);
+        for (level, ancestor) in tree.ancestors(i).take(SCORE_DEPTH).enumerate() {
+            if ancestor == 0 {
+                break;
+            }
+            let divider = match level {
+                0 => 1.0,
+                1 => 2.0,
+                n => f64::from(u32::try_from(n).unwrap_or(u32::MAX)) * 3.0,
+            };
+            let entry =
+                scores[ancestor].get_or_insert_with(|| initial_score(&tree.nodes[ancestor]));
+            *entry += score / divider;
+        }
+    }
+    let adjusted = |i: usize| scores[i].map(|s| s * (1.0 - link_density(&stats[i])));
+    let top = (1..tree.nodes.len())
+        .filter_map(|i| adjusted(i).map(|s| (i, s)))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+
+    let selected: Vec<usize> = top.map_or_else(
+        || vec![0],
+        |(top, top_score)| {
+            let threshold = (top_score * 0.2).max(10.0);
+            let parent = tree.nodes[top].parent;
+            tree.nodes[parent]
+                .children
+                .iter()
+                .filter_map(|child| match child {
+                    Child::Node(c) => Some(*c),
+                    Child::Text(_) => None,
+                })
+                .filter(|&c| {
+                    c == top
+                        || adjusted(c).is_some_and(|s| s >= threshold)
+                        || tree.nodes[c].name == "p"
+                            && stats[c].chars > 80
+                            && link_density(&stats[c]) < 0.25
+                })
+                .collect()
+        },
+    );
+
+    let mut article = String::new();
+    let mut total = Stats::default();
+    for &i in &selected {
+        tree.serialize(i, &mut article);
+        total.chars += stats[i].chars;
+        total.link_chars += stats[i].link_chars;
+    }
+    MainContent {
+        title: tree
+            .title
+            .clone()
+            .filter(|t| !t.is_empty())
+            .or_else(|| tree.first_heading()),
+        text: html_to_text(&article),
+        html: article,
+        link_density: link_density(&total),
+    }
+}
+
+/// Extract the article text of newsletter or marketing HTML
+///
+/// Navigation, headers, footers, social/unsubscribe blocks, forms and hidden
+/// elements are removed, and the container holding the most paragraph text
+/// (with little of it in links) is kept, together with strong siblings.
+///
+/// # Arguments
+/// * `html` - HTML body
+///
+/// # Returns
+/// * `MainContent` with `title`, `text` (plain text), `html` (the selected
+///   markup) and `link_density`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import extract_main_content
+/// article = extract_main_content(newsletter_html)
+/// print(article.title)
+/// print(article.text)
+/// ```
+#[pyfunction]
+pub fn extract_main_content(html: &str) -> MainContent {
+    extract(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let html = r#"<html><head><title>Weekly &amp; news</title><style>td{}</style></head>
+            <body><div class="preheader" style="display:none">Preview text here</div>
+            <table><tr><td class="nav"><a href="/a">Home</a> | <a href="/b">Shop</a></td></tr>
+            <tr><td class="content-main">
+              <h1>Big launch</h1>
+              <p>We shipped the new release today, with faster search, better filters, and
+              a redesigned inbox that people have been asking for since spring.</p>
+              <p>Read the <a href="https://x.example/notes">release notes</a> for the full list
+              of changes, fixes, and the migration guide.</p>
+            </td></tr>
+            <tr><td><div class="social"><a href="https://t.example">Twitter</a></div>
+              <p class="footer">You received this because you subscribed. Unsubscribe here,
+              or update your preferences at any time.</p></td></tr></table>
+            <footer>Acme Inc, 1 Main St</footer></body></html>"#;
+        let article = extract(html);
+        assert_eq!(article.title.as_deref(), Some("Weekly & news"));
+        assert!(article.text.starts_with("Big launch"), "{}", article.text);
+        assert!(article.text.contains("faster search"));
+        assert!(article.text.contains("release notes"));
+        assert!(!article.text.contains("Shop"));
+        assert!(!article.text.contains("Unsubscribe"));
+        assert!(!article.text.contains("Preview"));
+        assert!(!article.text.contains("Acme"));
+        assert!(article.link_density > 0.0 && article.link_density < 0.25);
+    }
+
+    #[test]
+    fn test_extract_fallback() {
+        let article = extract("<p>Short</p><h1>Only heading</h1>");
+        assert_eq!(article.title.as_deref(), Some("Only heading"));
+        assert_eq!(article.text, "Short\n\nOnly heading");
+        assert_eq!(extract("").text, "");
+    }
+}
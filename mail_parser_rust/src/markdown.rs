@@ -0,0 +1,121 @@
+//! Email-to-Markdown rendering for knowledge-base imports.
+
+use crate::html::html_to_markdown;
+use crate::mime::Message;
+use pyo3::prelude::*;
+use std::fmt::Write;
+
+/// Headers shown in the table, in display order
+const TABLE_HEADERS: &[&str] = &["From", "To", "Cc", "Date", "Message-ID"];
+
+/// Escape a value for use inside a Markdown table cell
+fn table_cell(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+/// Render one raw message as Markdown
+pub fn message_to_markdown(raw: &[u8]) -> String {
+    let msg = Message::parse(raw);
+    let parts = msg.parts();
+    let mut out = String::new();
+
+    let subject = msg
+        .decoded_header("Subject")
+        .map(|s| table_cell(&s))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "(no subject)".to_string());
+    let _ = writeln!(out, "# {subject}\n");
+
+    out.push_str("| Header | Value |\n|--------|-------|\n");
+    for name in TABLE_HEADERS {
+        if let Some(value) = msg.decoded_header(name) {
+            let _ = writeln!(out, "| {name} | {} |", table_cell(&value));
+        }
+    }
+    out.push('\n');
+
+    // Prefer text/plain; fall back to converted HTML
+    let body = parts
+        .iter()
+        .find(|p| !p.is_attachment() && p.mime_type() == "text/plain")
+        .map(|p| p.text().replace("\r\n", "\n"))
+        .or_else(|| {
+            parts
+                .iter()
+                .find(|p| !p.is_attachment() && p.mime_type() == "text/html")
+                .map(|p| html_to_markdown(&p.text()))
+        })
+        .unwrap_or_default();
+    let body = body.trim();
+    if !body.is_empty() {
+        out.push_str(body);
+        out.push('\n');
+    }
+
+    let attachments: Vec<_> = parts.iter().filter(|p| p.is_attachment()).collect();
+    if !attachments.is_empty() {
+        out.push_str("\n## Attachments\n\n");
+        for part in attachments {
+            let name = part.filename().unwrap_or_else(|| "(unnamed)".to_string());
+            let size = part.decoded().len();
+            let _ = writeln!(out, "- `{name}` ({}, {size} bytes)", part.mime_type());
+        }
+    }
+    out
+}
+
+/// Convert a raw email message to a readable Markdown document
+///
+/// Renders a `# Subject` title, a table of the main headers (From, To, Cc,
+/// Date, Message-ID), the text body (HTML-only bodies are converted to
+/// Markdown) and a list of attachments with type and decoded size.
+///
+/// # Arguments
+/// * `raw_bytes` - Complete RFC 822 message (a leading mbox `From ` line is skipped)
+///
+/// # Returns
+/// * Markdown document as a string
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import to_markdown_fast
+/// with open("message.eml", "rb") as f:
+///     markdown = to_markdown_fast(f.read())
+/// ```
+#[pyfunction]
+pub fn to_markdown_fast(raw_bytes: &[u8]) -> String {
+    message_to_markdown(raw_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_rendering() {
+        let raw = b"From: Alice <alice@example.com>\n\
+To: bob@example.com\n\
+Subject: Quarterly | report\n\
+Content-Type: multipart/mixed; boundary=b\n\
+\n\
+--b\n\
+Content-Type: text/html\n\
+\n\
+<p>See <a href=\"https://example.com/q3\">the numbers</a>.</p>\n\
+--b\n\
+Content-Type: text/csv; name=q3.csv\n\
+\n\
+a,b\n\
+--b--\n";
+        let md = to_markdown_fast(raw);
+        assert!(md.starts_with("# Quarterly \\| report\n"));
+        assert!(md.contains("| From | Alice <alice@example.com> |"));
+        assert!(md.contains("See [the numbers](https://example.com/q3)."));
+        assert!(md.contains("## Attachments\n\n- `q3.csv` (text/csv, 3 bytes)"));
+        assert!(!md.contains("| Cc |"));
+    }
+}
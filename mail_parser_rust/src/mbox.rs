@@ -0,0 +1,688 @@
+//! mbox file access: memory mapping and message boundary detection.
+//!
+//! Besides the `From ` line rules, [`FromLineMode::Recover`] finds messages in
+//! damaged archives (truncated or concatenated exports, lost separators) by
+//! looking for the header block a delivered message starts with: a run of
+//! `Return-Path:` / `Received:` trace fields followed by more header fields
+//! and an empty line.
+
+use crate::patterns::compile_bytes;
+use crate::progress::{Progress, WINDOW};
+use lazy_static::lazy_static;
+use memmap2::Mmap;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use regex::bytes::Regex;
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    /// RFC 4155 separator: `From <addr> <asctime date>`, tolerating the zone
+    /// and missing-seconds variants some exporters write
+    pub static ref STRICT_FROM_LINE: Regex = compile_bytes(
+        "mbox.strict_from_line",
+        r"^From \S+ +(?:Mon|Tue|Wed|Thu|Fri|Sat|Sun) +(?:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) +\d{1,2} +\d{1,2}:\d{2}(?::\d{2})? +(?:[A-Za-z]{3,5} +|[+-]\d{4} +)?\d{4}\s*$"
+    );
+    /// A plausible envelope: `From <sender> ...` carrying a time and a
+    /// four-digit year in any layout, which body sentences rarely do
+    pub static ref PLAUSIBLE_FROM_LINE: Regex = compile_bytes(
+        "mbox.plausible_from_line",
+        r"^From \S+ +\S.*(?:\b\d{1,2}:\d{2}\b.*\b\d{4}\b|\b\d{4}\b.*\b\d{1,2}:\d{2}\b)"
+    );
+}
+
+/// Fields MTAs prepend on delivery, so a delivered message's header block
+/// starts with them
+const TRACE_FIELDS: [&[u8]; 2] = [b"return-path:", b"received:"];
+/// Header fields a block needs before recovery treats it as a message start
+const MIN_RECOVERED_FIELDS: usize = 3;
+/// Longest header block recovery looks ahead through, in lines
+const MAX_HEADER_LINES: usize = 500;
+
+/// Which lines start a new message
+#[derive(Clone, Debug)]
+pub enum FromLineMode {
+    /// A plausible `From <sender> <date>` envelope after an empty line (or
+    /// at the start of the file)
+    Standard,
+    /// Any `From ` line after an empty line (or at the start of the file)
+    Lenient,
+    /// `From <addr> <date>` per RFC 4155, after an empty line
+    Strict,
+    /// Any line starting with `From `
+    Permissive,
+    /// Lines matching a caller regex (newline stripped)
+    Custom(Regex),
+    /// Standard separators plus content-based recovery of lost ones
+    Recover,
+}
+
+impl FromLineMode {
+    /// Parse a `from_line_mode` argument: "standard", "strict", "lenient",
+    /// "permissive", "recover", or any other value as a custom regex
+    pub fn parse(mode: &str) -> PyResult<Self> {
+        match mode {
+            "standard" => Ok(Self::Standard),
+            "strict" => Ok(Self::Strict),
+            "lenient" => Ok(Self::Lenient),
+            "permissive" => Ok(Self::Permissive),
+            "recover" => Ok(Self::Recover),
+            pattern => Regex::new(pattern).map(Self::Custom).map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Invalid from_line_mode regex: {e}"
+                ))
+            }),
+        }
+    }
+
+    /// Whether `line` separates messages, given whether the line before it
+    /// was empty (true at the start of the data)
+    pub fn starts_message(&self, line: &[u8], prev_blank: bool) -> bool {
+        let line = trim_newline(line);
+        match self {
+            Self::Standard | Self::Recover => prev_blank && PLAUSIBLE_FROM_LINE.is_match(line),
+            Self::Lenient => prev_blank && line.starts_with(b"From "),
+            Self::Strict => prev_blank && STRICT_FROM_LINE.is_match(line),
+            Self::Permissive => line.starts_with(b"From "),
+            Self::Custom(re) => re.is_match(line),
+        }
+    }
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// The line starting at `start`, newline included
+fn line_at(data: &[u8], start: usize) -> &[u8] {
+    let end = memchr::memchr(b'\n', &data[start..]).map_or(data.len(), |pos| start + pos + 1);
+    &data[start..end]
+}
+
+pub fn is_blank(line: &[u8]) -> bool {
+    trim_newline(line).is_empty()
+}
+
+/// `Name: value` with a printable, non-empty field name
+fn is_field(line: &[u8]) -> bool {
+    line.iter()
+        .position(|&b| b == b':')
+        .is_some_and(|colon| colon > 0 && line[..colon].iter().all(u8::is_ascii_graphic))
+}
+
+fn is_trace_field(line: &[u8]) -> bool {
+    TRACE_FIELDS
+        .iter()
+        .any(|f| line.len() >= f.len() && line[..f.len()].eq_ignore_ascii_case(f))
+}
+
+/// Whether the lines from `start` form a header block: at least
+/// [`MIN_RECOVERED_FIELDS`] fields (with a trace field if `need_trace`) ending
+/// in an empty line or the end of the data
+fn header_block_at(data: &[u8], start: usize, need_trace: bool) -> bool {
+    let (mut fields, mut trace, mut pos) = (0, false, start);
+    for _ in 0..MAX_HEADER_LINES {
+        if pos >= data.len() {
+            break;
+        }
+        let line = line_at(data, pos);
+        if is_blank(line) {
+            break;
+        }
+        if is_field(line) {
+            fields += 1;
+            trace |= is_trace_field(line);
+        } else if fields == 0 || !matches!(line[0], b' ' | b'\t') {
+            return false;
+        }
+        pos += line.len();
+    }
+    fields >= MIN_RECOVERED_FIELDS && (trace || !need_trace)
+}
+
+/// Message starts found by [`FromLineMode::Recover`]
+///
+/// Keeps every standard separator and adds: RFC 4155 `From ` lines that lost
+/// their preceding empty line but are followed by a header block, and the
+/// first line of a trace-field run that opens a header block with no
+/// separator at all. A run right after a `message/rfc822` part header is an
+/// attached message, not a new one.
+fn recovered_starts(data: &[u8]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let (mut line_start, mut prev_blank, mut prev_header, mut attached) = (0, true, false, false);
+    while line_start < data.len() {
+        let line = line_at(data, line_start);
+        let next = line_start + line.len();
+        let separator = FromLineMode::Recover.starts_message(line, prev_blank)
+            || (STRICT_FROM_LINE.is_match(trim_newline(line))
+                && header_block_at(data, next, false));
+        let headerless = !separator
+            && !prev_header
+            && !attached
+            && is_trace_field(line)
+            && header_block_at(data, line_start, true);
+        if separator || headerless {
+            starts.push(line_start);
+            attached = false;
+        }
+
+        let field = is_field(line);
+        if field && line.len() > 13 && line[..13].eq_ignore_ascii_case(b"content-type:") {
+            let value = String::from_utf8_lossy(&line[13..]).to_ascii_lowercase();
+            attached |= value.trim_start().starts_with("message/rfc822");
+        } else if !field && !is_blank(line) && !matches!(line[0], b' ' | b'\t') {
+            attached = false;
+        }
+        prev_blank = is_blank(line);
+        prev_header = separator || field || (prev_header && matches!(line[0], b' ' | b'\t'));
+        line_start = next;
+    }
+    // Keep a damaged leading fragment rather than dropping it
+    let first = starts.first().copied().unwrap_or(data.len());
+    if data[..first].iter().any(|b| !b.is_ascii_whitespace()) {
+        starts.insert(0, 0);
+    }
+    starts
+}
+
+pyo3::create_exception!(
+    mail_parser_rust,
+    FileChangedError,
+    pyo3::exceptions::PyIOError,
+    "The mbox was truncated, grew, or was replaced while it was being read. \
+     `args` is `(message, offset)`: messages before byte `offset` were intact."
+);
+
+/// What identifies the file a mapping was made from
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<std::time::SystemTime>,
+    /// Device and inode, so a rotated (renamed and recreated) file shows
+    #[cfg(unix)]
+    id: (u64, u64),
+}
+
+impl FileStamp {
+    fn of(meta: &std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt as _;
+        Self {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            #[cfg(unix)]
+            id: (meta.dev(), meta.ino()),
+        }
+    }
+
+    /// Device the file is stored on, where the platform exposes one
+    #[cfg(unix)]
+    #[allow(clippy::unnecessary_wraps)]
+    const fn storage(&self) -> Option<u64> {
+        Some(self.id.0)
+    }
+
+    #[cfg(not(unix))]
+    const fn storage(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn same_file(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+
+    #[cfg(not(unix))]
+    const fn same_file(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// A read-only mapping of an mbox, with what is needed to tell whether the
+/// file changed underneath it
+pub struct MappedFile {
+    // Declared first so it unregisters before the mapping goes away
+    guard: crate::sigbus::Guard,
+    map: Mmap,
+    path: PathBuf,
+    stamp: FileStamp,
+}
+
+impl std::ops::Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl MappedFile {
+    /// [`message_spans_with`] with the scan's chunk size tuned to the
+    /// storage the file is on (see [`crate::tuning`])
+    pub fn spans(&self, mode: &FromLineMode) -> Vec<Range<usize>> {
+        let starts = match (mode, self.stamp.storage()) {
+            (FromLineMode::Recover, _) => recovered_starts(&self.map),
+            (_, Some(device)) => {
+                crate::tuning::tuned_starts(&self.map, mode, device, &self.path.to_string_lossy())
+            },
+            (_, None) => {
+                starts_chunked(&self.map, 0, self.map.len(), mode, crate::tuning::DEFAULT_CHUNK)
+            },
+        };
+        spans_between(&starts, self.map.len())
+    }
+
+    /// [`MappedFile::spans`], reporting to `progress` (see
+    /// [`message_spans_reporting`]) when it has a callable
+    pub fn spans_reporting(&self, mode: &FromLineMode, progress: &Progress) -> Vec<Range<usize>> {
+        if progress.reporting() {
+            message_spans_reporting(&self.map, mode, progress)
+        } else {
+            self.spans(mode)
+        }
+    }
+
+    /// Chunk size [`MappedFile::spans`] uses for this file
+    pub fn chunk_bytes(&self) -> usize {
+        self.stamp
+            .storage()
+            .and_then(crate::tuning::chunk_for)
+            .unwrap_or(crate::tuning::DEFAULT_CHUNK)
+    }
+
+    /// Whether a read hit a page the file no longer backs (cheap; Linux only)
+    pub fn faulted(&self) -> bool {
+        self.guard.fault_offset().is_some()
+    }
+
+    /// Fail with `FileChangedError` if reading the mapping hit a truncated
+    /// page, or the file on disk no longer matches the one mapped
+    ///
+    /// Cheap (one `stat`); call it after an operation has read everything
+    /// it needs. The error carries the offset of the first message that may
+    /// be damaged, found by rescanning the still-valid prefix with `mode`.
+    pub fn verify(&self, mode: &FromLineMode) -> PyResult<()> {
+        let fault = self.guard.fault_offset();
+        let now = std::fs::metadata(crate::paths::extended(&self.path))
+            .ok()
+            .map(|meta| FileStamp::of(&meta));
+        let (reason, intact) = match (&now, fault) {
+            (None, _) => ("was removed".to_string(), 0),
+            (Some(now), _) if !now.same_file(&self.stamp) => ("was replaced".to_string(), 0),
+            (Some(now), _) if now.len < self.stamp.len => (
+                format!("was truncated to {} bytes", now.len),
+                usize::try_from(now.len).unwrap_or(usize::MAX),
+            ),
+            (Some(_), Some(offset)) => ("shrank while it was read".to_string(), offset),
+            (Some(now), None) if now.len > self.stamp.len => {
+                (format!("grew to {} bytes", now.len), self.map.len())
+            },
+            (Some(now), None) if now.modified != self.stamp.modified => {
+                ("was modified".to_string(), 0)
+            },
+            _ => return Ok(()),
+        };
+        let intact = fault.map_or(intact, |offset| offset.min(intact));
+        // Compressed bytes cannot be rescanned for the messages they held
+        let offset = if crate::compressed::Codec::detect(&self.map).is_some() {
+            0
+        } else {
+            message_spans_with(&self.map[..intact.min(self.map.len())], mode)
+                .last()
+                .map_or(0, |span| span.start)
+        };
+        Err(FileChangedError::new_err((
+            format!(
+                "{} {reason} during the scan; messages before byte {offset} are intact",
+                self.path.display()
+            ),
+            offset,
+        )))
+    }
+}
+
+/// Memory-map an mbox file read-only
+///
+/// On Linux, reads past the end of a file truncated while it is mapped
+/// yield zeros instead of killing the process (see `sigbus`); the operation
+/// then reports it through [`MappedFile::verify`].
+pub fn map_file(path: &str) -> PyResult<MappedFile> {
+    let file = File::open(crate::paths::extended(Path::new(path))).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open file: {e}"))
+    })?;
+    let stamp = file
+        .metadata()
+        .map(|meta| FileStamp::of(&meta))
+        .map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to stat file: {e}"))
+        })?;
+    // Safety: the map is read-only; truncation by another process is caught
+    // by the SIGBUS guard where available and reported by `verify`
+    #[allow(unsafe_code)]
+    let map = unsafe { Mmap::map(&file) }.map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to mmap file: {e}"))
+    })?;
+    Ok(MappedFile {
+        guard: crate::sigbus::Guard::new(map.as_ptr(), map.len()),
+        map,
+        path: PathBuf::from(path),
+        stamp,
+    })
+}
+
+/// Whether the line ending just before `pos` (a line start) was empty
+fn blank_before(data: &[u8], pos: usize) -> bool {
+    match pos {
+        0 | 1 => true,
+        _ => {
+            data[pos - 2] == b'\n'
+                || (data[pos - 2] == b'\r' && (pos == 2 || data[pos - 3] == b'\n'))
+        },
+    }
+}
+
+/// Line starts in `from..to` (`from` itself a line start) that `mode` could
+/// accept as a separator
+///
+/// Every built-in rule needs a line beginning `From `, so those are found
+/// with a SIMD substring search for `\nFrom ` instead of visiting each line;
+/// custom regexes can match anything and get every line start.
+fn candidates<'a>(
+    data: &'a [u8],
+    from: usize,
+    to: usize,
+    mode: &FromLineMode,
+) -> Box<dyn Iterator<Item = usize> + 'a> {
+    let to = to.min(data.len());
+    if from >= to {
+        return Box::new(std::iter::empty());
+    }
+    if matches!(mode, FromLineMode::Custom(_)) {
+        let rest = memchr::memchr_iter(b'\n', &data[from..to]).map(move |nl| from + nl + 1);
+        return Box::new(
+            std::iter::once(from)
+                .chain(rest)
+                .filter(move |&pos| pos < to),
+        );
+    }
+    let head = data[from..].starts_with(b"From ").then_some(from);
+    let window = &data[from..(to + 5).min(data.len())];
+    let rest = memchr::memmem::find_iter(window, b"\nFrom ").map(move |nl| from + nl + 1);
+    Box::new(head.into_iter().chain(rest).filter(move |&pos| pos < to))
+}
+
+/// Message starts among the lines beginning in `from..to`; not for
+/// [`FromLineMode::Recover`], which needs lookahead
+fn starts_in<'a>(
+    data: &'a [u8],
+    from: usize,
+    to: usize,
+    mode: &'a FromLineMode,
+) -> impl Iterator<Item = usize> + 'a {
+    candidates(data, from, to, mode)
+        .filter(move |&pos| mode.starts_message(line_at(data, pos), blank_before(data, pos)))
+}
+
+/// Start of the message after the one starting at `start` (a separator line),
+/// found without scanning the rest of the file; not for `Recover` mode
+pub fn next_start_after(data: &[u8], start: usize, mode: &FromLineMode) -> Option<usize> {
+    let next = start + line_at(data, start).len();
+    starts_in(data, next, data.len(), mode).next()
+}
+
+/// Start of the first message, for incremental scanning with
+/// [`next_start_after`]; not for `Recover` mode
+pub fn first_start(data: &[u8], mode: &FromLineMode) -> Option<usize> {
+    starts_in(data, 0, data.len(), mode).next()
+}
+
+/// Byte ranges of the messages in an mbox, each starting at its `From ` line
+///
+/// A `From ` line only starts a message at the beginning of the file or after
+/// an empty line, and only if it looks like an envelope (sender, time and
+/// year), which keeps unescaped "From " sentences in bodies from splitting a
+/// message in two.
+pub fn message_spans(data: &[u8]) -> Vec<Range<usize>> {
+    message_spans_with(data, &FromLineMode::Standard)
+}
+
+/// [`message_spans`] with the separator rule chosen by `mode`
+///
+/// Large files are scanned in chunks on all cores. Every separator rule only
+/// looks at a line and the one before it, so chunks split at line starts
+/// find exactly the sequential result; the data is never validated as UTF-8.
+/// `Recover` mode needs lookahead and scans sequentially.
+///
+/// Bytes before the first separator are not part of any message, except in
+/// `Recover` mode, which returns them as a message of their own.
+pub fn message_spans_with(data: &[u8], mode: &FromLineMode) -> Vec<Range<usize>> {
+    spans_chunked(data, mode, crate::tuning::DEFAULT_CHUNK)
+}
+
+/// [`message_spans_with`], counting the bytes scanned into `progress` a
+/// [`WINDOW`] at a time; once the scan is cancelled the spans found so far
+/// are returned, the last running to the end of the data
+///
+/// `Recover` mode scans in one go and reports at the end.
+pub fn message_spans_reporting(
+    data: &[u8],
+    mode: &FromLineMode,
+    progress: &Progress,
+) -> Vec<Range<usize>> {
+    if !progress.reporting() || matches!(mode, FromLineMode::Recover) {
+        let spans = message_spans_with(data, mode);
+        progress.advance(data.len());
+        return spans;
+    }
+    let mut starts = Vec::new();
+    let mut from = 0;
+    while from < data.len() {
+        let to = line_start_from(data, from.saturating_add(WINDOW));
+        starts.extend(starts_chunked(data, from, to, mode, crate::tuning::DEFAULT_CHUNK));
+        if !progress.advance(to - from) {
+            break;
+        }
+        from = to;
+    }
+    spans_between(&starts, data.len())
+}
+
+fn spans_chunked(data: &[u8], mode: &FromLineMode, chunk: usize) -> Vec<Range<usize>> {
+    let starts = if matches!(mode, FromLineMode::Recover) {
+        recovered_starts(data)
+    } else {
+        starts_chunked(data, 0, data.len(), mode, chunk)
+    };
+    spans_between(&starts, data.len())
+}
+
+/// Consecutive message spans from sorted message starts
+fn spans_between(starts: &[usize], end: usize) -> Vec<Range<usize>> {
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| start..starts.get(i + 1).copied().unwrap_or(end))
+        .collect()
+}
+
+/// First line start at or after `at`
+pub fn line_start_from(data: &[u8], at: usize) -> usize {
+    if at == 0 {
+        return 0;
+    }
+    if at >= data.len() {
+        return data.len();
+    }
+    memchr::memchr(b'\n', &data[at - 1..]).map_or(data.len(), |nl| at + nl)
+}
+
+/// Message starts in `data[from..to]` (both line starts), scanned on all
+/// cores in chunks of about `chunk` bytes; not for `Recover` mode
+pub fn starts_chunked(
+    data: &[u8],
+    from: usize,
+    to: usize,
+    mode: &FromLineMode,
+    chunk: usize,
+) -> Vec<usize> {
+    if to - from <= chunk {
+        return starts_in(data, from, to, mode).collect();
+    }
+    // Chunk boundaries moved forward to the next line start
+    let mut bounds: Vec<usize> = (from..to)
+        .step_by(chunk)
+        .map(|at| line_start_from(data, at).min(to))
+        .collect();
+    bounds.push(to);
+    bounds.dedup();
+    bounds
+        .par_windows(2)
+        .flat_map_iter(|w| starts_in(data, w[0], w[1], mode))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_spans() {
+        let data = b"From a@x Mon Jan  1 00:00:00 2024\nSubject: 1\n\nbody\nFrom here on\n\n\
+From b@x Mon Jan  1 00:00:00 2024\nSubject: 2\n\nbody\n";
+        let spans = message_spans(data);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].start, 0);
+        assert!(data[spans[1].clone()].starts_with(b"From b@x"));
+        assert_eq!(spans[1].end, data.len());
+        assert!(message_spans(b"").is_empty());
+    }
+
+    #[test]
+    fn test_from_line_modes() {
+        let data = b"From a@x Mon Jan  1 00:00:00 2024\nSubject: 1\n\nbody\n\n\
+From the desk of the CEO\n\n\
+From b@x Tue Jan  2 09:15 +0100 2024\r\nSubject: 2\n\nsee below\n\
+From c@x Wed Jan  3 00:00:00 2024\n";
+        let count =
+            |mode: &str| message_spans_with(data, &FromLineMode::parse(mode).unwrap()).len();
+        assert_eq!(count("standard"), 2);
+        assert_eq!(count("lenient"), 3);
+        assert_eq!(count("strict"), 2);
+        assert_eq!(count("permissive"), 4);
+        assert_eq!(count(r"^From \S+@\S+ "), 3);
+        assert!(FromLineMode::parse("(").is_err());
+
+        let strict = message_spans_with(data, &FromLineMode::Strict);
+        assert!(data[strict[1].clone()].starts_with(b"From b@x"));
+        assert!(data[strict[0].clone()].ends_with(b"CEO\n\n"));
+    }
+
+    #[test]
+    fn test_spans_chunked() {
+        let mut data = Vec::new();
+        for i in 0..50 {
+            let sep = if i % 3 == 0 { "\r\n" } else { "\n" };
+            data.extend_from_slice(
+                format!("From a@x Mon Jan  1 00:00:00 2024{sep}Subject: {i}{sep}{sep}body{sep}From x{sep}{sep}")
+                    .as_bytes(),
+            );
+            // Latin-1 and broken UTF-8 must not stop the scan
+            data.extend_from_slice(b"caf\xe9 \xff\xfe\n\n");
+        }
+        // Reference: test every line in order
+        let by_line = |mode: &FromLineMode| {
+            let (mut starts, mut pos, mut prev_blank) = (Vec::new(), 0, true);
+            while pos < data.len() {
+                let line = line_at(&data, pos);
+                if mode.starts_message(line, prev_blank) {
+                    starts.push(pos);
+                }
+                prev_blank = is_blank(line);
+                pos += line.len();
+            }
+            starts
+        };
+        for mode in [
+            "standard",
+            "lenient",
+            "strict",
+            "permissive",
+            r"^From \S+ Mon",
+        ] {
+            let mode = FromLineMode::parse(mode).unwrap();
+            let expected = by_line(&mode);
+            for chunk in [1, 7, 64, 1000, usize::MAX] {
+                let starts: Vec<usize> = spans_chunked(&data, &mode, chunk)
+                    .iter()
+                    .map(|s| s.start)
+                    .collect();
+                assert_eq!(starts, expected);
+            }
+        }
+        assert_eq!(message_spans(&data).len(), 50);
+        assert_eq!(
+            first_start(b"x\n\nFrom a@x Mon Jan  1 00:00 2024\n", &FromLineMode::Standard),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_recover_spans() {
+        let data = b"tail of a truncated message\n\n\
+From a@x Mon Jan  1 00:00:00 2024\nFrom: a@x\nSubject: A\n\nbody A\n\
+Return-Path: <b@x>\nReceived: from relay\n\tby mx; Tue, 2 Jan 2024\nFrom: b@x\nSubject: B\n\n\
+Note: not a header block\nReceived: in body text\n\
+From c@x Wed Jan  3 00:00:00 2024\nFrom: c@x\nSubject: C\nDate: Wed, 3 Jan 2024\n\nbody C\n\n\
+From d@x Thu Jan  4 00:00:00 2024\nFrom: d@x\nContent-Type: multipart/mixed; boundary=b\n\n\
+--b\nContent-Type: message/rfc822\n\n\
+Received: from inner\nFrom: e@x\nSubject: inner\n\ninner body\n--b--\n";
+        let spans = message_spans_with(data, &FromLineMode::Recover);
+        let heads: Vec<&[u8]> = spans.iter().map(|s| &data[s.start..s.start + 8]).collect();
+        assert_eq!(
+            heads,
+            [
+                &b"tail of "[..],
+                b"From a@x",
+                b"Return-P",
+                b"From c@x",
+                b"From d@x"
+            ]
+        );
+        assert_eq!(spans.last().map(|s| s.end), Some(data.len()));
+        assert_eq!(message_spans(data).len(), 2);
+        assert!(message_spans_with(b"", &FromLineMode::Recover).is_empty());
+    }
+
+    #[test]
+    fn test_verify_detects_changes() {
+        let dir = std::env::temp_dir().join(format!("mpr_verify_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("spool.mbox");
+        let one = "From a Mon Jan  1 00:00:00 2024\nSubject: 1\n\nbody\n\n";
+        std::fs::write(&path, one.repeat(2)).unwrap();
+        let path_str = path.to_str().unwrap();
+        let mode = FromLineMode::Strict;
+
+        let map = map_file(path_str).unwrap();
+        assert!(map.verify(&mode).is_ok());
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(&mut file, one.as_bytes()).unwrap();
+        let err = map.verify(&mode).unwrap_err().to_string();
+        assert!(
+            err.contains("grew to") && err.contains(&format!("before byte {} ", one.len())),
+            "{err}"
+        );
+
+        let map = map_file(path_str).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, one).unwrap();
+        let err = map.verify(&mode).unwrap_err().to_string();
+        assert!(err.contains("was replaced") && err.contains("before byte 0 "), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,714 @@
+//! RFC 5322 / MIME message model shared by the message-level functions.
+//!
+//! Parsing is zero-copy where it matters: a [`Message`] borrows the raw bytes
+//! and every [`Part`] keeps its body as a still transfer-encoded slice, so
+//! callers only pay for decoding the parts they actually use.
+
+use crate::html::html_to_text;
+use crate::patterns::compile;
+use crate::transfer::decode_transfer;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Nesting limit for multipart structures (protects against hostile input)
+const MAX_MULTIPART_DEPTH: usize = 32;
+
+/// Line length header folding aims for (RFC 5322 section 2.1.1)
+const FOLD_WIDTH: usize = 78;
+
+/// Hard header line limit, line break excluded
+const MAX_LINE: usize = 998;
+
+lazy_static! {
+    pub static ref ENCODED_WORD: Regex =
+        compile("mime.encoded_word", r"=\?([^?\s]+)\?([bBqQ])\?([^?\s]*)\?=");
+}
+
+/// A single header field with its unfolded (but not RFC 2047-decoded) value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+/// Skip a leading mbox `From ` envelope line, if present
+pub fn strip_envelope(raw: &[u8]) -> &[u8] {
+    if raw.starts_with(b"From ") {
+        raw.iter()
+            .position(|&b| b == b'\n')
+            .map_or_else(|| &raw[raw.len()..], |nl| &raw[nl + 1..])
+    } else {
+        raw
+    }
+}
+
+/// Split raw bytes at the first empty line into (header block, body)
+pub fn split_header_body(raw: &[u8]) -> (&[u8], &[u8]) {
+    if raw.starts_with(b"\n") {
+        return (&raw[..0], &raw[1..]);
+    }
+    if raw.starts_with(b"\r\n") {
+        return (&raw[..0], &raw[2..]);
+    }
+    let mut i = 0;
+    while let Some(pos) = raw[i..].iter().position(|&b| b == b'\n') {
+        let nl = i + pos;
+        let rest = &raw[nl + 1..];
+        if rest.starts_with(b"\n") {
+            return (&raw[..=nl], &raw[nl + 2..]);
+        }
+        if rest.starts_with(b"\r\n") {
+            return (&raw[..=nl], &raw[nl + 3..]);
+        }
+        i = nl + 1;
+    }
+    (raw, &raw[raw.len()..])
+}
+
+/// Decode raw header bytes: UTF-8 when valid, windows-1252 otherwise
+pub fn header_bytes_to_string(bytes: &[u8]) -> String {
+    std::str::from_utf8(bytes)
+        .map_or_else(|_| WINDOWS_1252.decode(bytes).0.into_owned(), str::to_string)
+}
+
+/// Parse a header block into fields, unfolding continuation lines
+pub fn parse_header_block(block: &[u8]) -> Vec<Header> {
+    let text = header_bytes_to_string(block);
+    let mut headers: Vec<Header> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            // RFC 5322 unfolding: drop the line break, keep the whitespace
+            if let Some(last) = headers.last_mut() {
+                last.value.push_str(line);
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim_end();
+            if !name.is_empty() && !name.contains(' ') {
+                headers.push(Header {
+                    name: name.to_string(),
+                    value: value.trim_start().to_string(),
+                });
+            }
+        }
+    }
+    for header in &mut headers {
+        let trimmed = header.value.trim_end().len();
+        header.value.truncate(trimmed);
+    }
+    headers
+}
+
+/// Serialize `name: value` as a header field folded to 78-character lines
+///
+/// Lines break before existing whitespace, so unfolding restores `value`
+/// exactly. A single word that cannot fit within the 998-character limit is
+/// broken after its commas (address lists, ID lists), which inserts folding
+/// whitespace there; one with no comma is left long. No trailing newline.
+pub fn fold_header(name: &str, value: &str) -> String {
+    let mut out = format!("{name}:");
+    let mut line_len = out.len();
+    let mut has_content = false;
+    let mut rest = value;
+    let mut lead = " ";
+    while !rest.is_empty() {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, after) = rest.split_at(word_end);
+        let ws_end = after
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(after.len());
+        let pieces: Vec<&str> = if lead.len() + word.len() > MAX_LINE {
+            word.split_inclusive(',').collect()
+        } else {
+            vec![word]
+        };
+        for (i, piece) in pieces.into_iter().enumerate() {
+            let mut piece_lead = if i == 0 { lead } else { "" };
+            if has_content && line_len + piece_lead.len() + piece.len() > FOLD_WIDTH {
+                out.push('\n');
+                line_len = 0;
+                if piece_lead.is_empty() {
+                    piece_lead = " ";
+                }
+            }
+            out.push_str(piece_lead);
+            out.push_str(piece);
+            line_len += piece_lead.len() + piece.len();
+            has_content = true;
+        }
+        lead = &after[..ws_end];
+        rest = &after[ws_end..];
+    }
+    out
+}
+
+/// First header named `name` (case-insensitive)
+pub fn find_header<'h>(headers: &'h [Header], name: &str) -> Option<&'h str> {
+    headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str())
+}
+
+/// Lowercased address of the first mailbox in an address header value
+/// (`Name <user@host>` or a bare `user@host`)
+pub fn first_address(value: &str) -> Option<String> {
+    let bracketed = value
+        .find('<')
+        .and_then(|start| value[start + 1..].split('>').next());
+    bracketed
+        .and_then(|inner| crate::EMAIL_PATTERN.find(inner))
+        .or_else(|| crate::EMAIL_PATTERN.find(value))
+        .map(|m| m.as_str().to_lowercase())
+}
+
+/// Every mailbox in an address header value as (display name, lowercased
+/// address); commas inside quotes, angle brackets and comments do not split
+pub fn address_list(value: &str) -> Vec<(Option<String>, String)> {
+    let mut entries = Vec::new();
+    let (mut start, mut quoted, mut depth, mut escaped) = (0, false, 0_i32, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '<' | '(' if !quoted => depth += 1,
+            '>' | ')' if !quoted => depth -= 1,
+            ',' | ';' if !quoted && depth <= 0 => {
+                entries.push(&value[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    entries.push(&value[start..]);
+
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let address = first_address(entry)?;
+            let name = if let Some((name, _)) = entry.split_once('<') {
+                // Drop a group label ("Team: Ann <a@x>")
+                let name = match name.rsplit_once(':') {
+                    Some((_, n)) if !name.contains('"') => n,
+                    _ => name,
+                };
+                name.trim().trim_matches('"').replace("\\\"", "\"")
+            } else {
+                entry
+                    .split_once('(')
+                    .and_then(|(_, rest)| rest.rsplit_once(')'))
+                    .map(|(comment, _)| comment.to_string())
+                    .unwrap_or_default()
+            };
+            let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+            let name = (!name.is_empty() && !name.contains('@')).then_some(name);
+            Some((name, address))
+        })
+        .collect()
+}
+
+/// Decode bytes in the given charset, falling back to UTF-8 / windows-1252
+pub fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let declared = charset
+        .map(|c| c.trim().trim_matches('"'))
+        .filter(|c| !c.is_empty() && !c.eq_ignore_ascii_case("us-ascii"))
+        .and_then(|c| Encoding::for_label(c.as_bytes()));
+    match declared {
+        Some(enc) if enc != UTF_8 => enc.decode(bytes).0.into_owned(),
+        _ => match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) if declared == Some(UTF_8) => String::from_utf8_lossy(bytes).into_owned(),
+            Err(_) => WINDOWS_1252.decode(bytes).0.into_owned(),
+        },
+    }
+}
+
+/// Decode RFC 2047 encoded-words (`=?utf-8?B?...?=`) in a header value
+pub fn decode_encoded_words(value: &str) -> String {
+    if !value.contains("=?") {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut last_end = 0;
+    let mut prev_was_word = false;
+    for caps in ENCODED_WORD.captures_iter(value) {
+        let (Some(whole), Some(charset), Some(kind), Some(text)) =
+            (caps.get(0), caps.get(1), caps.get(2), caps.get(3))
+        else {
+            continue;
+        };
+        let gap = &value[last_end..whole.start()];
+        // Whitespace between two adjacent encoded-words is not displayed
+        if !(prev_was_word && gap.trim().is_empty()) {
+            out.push_str(gap);
+        }
+        let bytes = if kind.as_str().eq_ignore_ascii_case("b") {
+            crate::transfer::decode_base64(text.as_str().as_bytes())
+        } else {
+            let underscored = text.as_str().replace('_', " ");
+            crate::transfer::decode_quoted_printable(underscored.as_bytes())
+        };
+        // RFC 2231 allows a language suffix: "utf-8*en"
+        let charset = charset.as_str().split('*').next().unwrap_or("");
+        out.push_str(&decode_charset(&bytes, Some(charset)));
+        last_end = whole.end();
+        prev_was_word = true;
+    }
+    out.push_str(&value[last_end..]);
+    out
+}
+
+/// A structured header value such as Content-Type with its parameters
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParamValue {
+    /// Main value, lowercased (e.g. `text/plain`, `attachment`)
+    pub value: String,
+    /// Parameters with lowercased names, RFC 2231 continuations joined
+    pub params: Vec<(String, String)>,
+}
+
+impl ParamValue {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Split on `;` outside of quoted strings
+fn split_params(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut out = String::with_capacity(value.len());
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    } else {
+        value.to_string()
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(hex) = value.get(i + 1..i + 3) {
+                if let Ok(b) = u8::from_str_radix(hex, 16) {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                }
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Parse `value; name=param; ...` including RFC 2231 extended parameters
+pub fn parse_param_value(raw: &str) -> ParamValue {
+    let parts = split_params(raw);
+    let value = parts
+        .first()
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    // (name, section index, is_extended, value)
+    let mut pieces: Vec<(String, u32, bool, String)> = Vec::new();
+    for part in parts.iter().skip(1) {
+        let Some((name, val)) = part.split_once('=') else {
+            continue;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let (name, extended) = name
+            .strip_suffix('*')
+            .map_or((name.as_str(), false), |n| (n, true));
+        let (base, index) = match name.rsplit_once('*') {
+            Some((base, idx)) if idx.chars().all(|c| c.is_ascii_digit()) && !idx.is_empty() => {
+                (base, idx.parse().unwrap_or(0))
+            },
+            _ => (name, 0),
+        };
+        pieces.push((base.to_string(), index, extended, unquote(val)));
+    }
+    pieces.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    let mut params: Vec<(String, String)> = Vec::new();
+    let mut i = 0;
+    while i < pieces.len() {
+        let name = pieces[i].0.clone();
+        let mut charset: Option<String> = None;
+        let mut bytes = Vec::new();
+        while i < pieces.len() && pieces[i].0 == name {
+            let (_, index, extended, val) = &pieces[i];
+            if *extended {
+                let mut encoded = val.as_str();
+                if *index == 0 {
+                    // charset'language'percent-encoded
+                    let mut fields = val.splitn(3, '\'');
+                    if let (Some(cs), Some(_lang), Some(rest)) =
+                        (fields.next(), fields.next(), fields.next())
+                    {
+                        charset = Some(cs.to_string()).filter(|c| !c.is_empty());
+                        encoded = rest;
+                    }
+                }
+                bytes.extend(percent_decode(encoded));
+            } else {
+                bytes.extend_from_slice(val.as_bytes());
+            }
+            i += 1;
+        }
+        params.push((name, decode_charset(&bytes, charset.as_deref())));
+    }
+    ParamValue { value, params }
+}
+
+/// A parsed message: header fields plus the (still encoded) body
+pub struct Message<'a> {
+    pub headers: Vec<Header>,
+    pub body: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    /// Parse raw RFC 5322 bytes (a leading mbox `From ` line is skipped)
+    pub fn parse(raw: &'a [u8]) -> Self {
+        let (head, body) = split_header_body(strip_envelope(raw));
+        Self {
+            headers: parse_header_block(head),
+            body,
+        }
+    }
+
+    /// First header value named `name`, unfolded but not decoded
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// First header value with RFC 2047 encoded-words decoded
+    pub fn decoded_header(&self, name: &str) -> Option<String> {
+        self.header(name).map(decode_encoded_words)
+    }
+
+    /// All leaf MIME parts in depth-first order
+    pub fn parts(&self) -> Vec<Part<'a>> {
+        let mut out = Vec::new();
+        collect_parts(&self.headers, self.body, 0, &mut out);
+        out
+    }
+
+    /// The displayable body: the first text/plain part, else the first text/html
+    pub fn body_part(&self) -> Option<Part<'a>> {
+        let mut parts: Vec<Part<'a>> = self
+            .parts()
+            .into_iter()
+            .filter(|p| !p.is_attachment())
+            .collect();
+        let pick = parts
+            .iter()
+            .position(|p| p.mime_type() == "text/plain")
+            .or_else(|| parts.iter().position(|p| p.mime_type() == "text/html"))?;
+        Some(parts.swap_remove(pick))
+    }
+
+    /// Readable body text: the first text/plain part, else flattened HTML
+    pub fn text_body(&self) -> String {
+        self.body_part().map_or_else(String::new, |p| {
+            if p.mime_type() == "text/html" {
+                html_to_text(&p.text())
+            } else {
+                p.text().replace("\r\n", "\n")
+            }
+        })
+    }
+}
+
+/// A leaf MIME part; `body` is still transfer-encoded
+pub struct Part<'a> {
+    pub headers: Vec<Header>,
+    /// Content-Type with parameters (defaults to `text/plain`)
+    pub content_type: ParamValue,
+    /// Content-Disposition with parameters, if present
+    pub disposition: Option<ParamValue>,
+    pub transfer_encoding: String,
+    pub body: &'a [u8],
+}
+
+impl Part<'_> {
+    /// Lowercased MIME type, e.g. `text/html`
+    pub fn mime_type(&self) -> &str {
+        &self.content_type.value
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.content_type.param("charset")
+    }
+
+    /// Attachment filename from Content-Disposition or Content-Type `name`
+    pub fn filename(&self) -> Option<String> {
+        self.disposition
+            .as_ref()
+            .and_then(|d| d.param("filename"))
+            .or_else(|| self.content_type.param("name"))
+            .map(decode_encoded_words)
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    /// Whether this part is a file rather than a displayable body
+    pub fn is_attachment(&self) -> bool {
+        let disposition = self.disposition.as_ref().map(|d| d.value.as_str());
+        if disposition == Some("attachment") {
+            return true;
+        }
+        let is_text_body = matches!(self.mime_type(), "text/plain" | "text/html");
+        !is_text_body || self.filename().is_some()
+    }
+
+    /// Body bytes with the Content-Transfer-Encoding undone
+    pub fn decoded(&self) -> Vec<u8> {
+        decode_transfer(self.body, &self.transfer_encoding)
+    }
+
+    /// Decoded body converted to a string using the declared charset
+    pub fn text(&self) -> String {
+        decode_charset(&self.decoded(), self.charset())
+    }
+}
+
+fn collect_parts<'a>(headers: &[Header], body: &'a [u8], depth: usize, out: &mut Vec<Part<'a>>) {
+    let content_type = find_header(headers, "Content-Type")
+        .map(parse_param_value)
+        .filter(|ct| ct.value.contains('/'))
+        .unwrap_or_else(|| ParamValue {
+            value: "text/plain".to_string(),
+            params: Vec::new(),
+        });
+
+    if content_type.value.starts_with("multipart/") && depth < MAX_MULTIPART_DEPTH {
+        if let Some(boundary) = content_type.param("boundary").filter(|b| !b.is_empty()) {
+            for section in split_multipart(body, boundary.as_bytes()) {
+                let (head, sub_body) = split_header_body(section);
+                collect_parts(&parse_header_block(head), sub_body, depth + 1, out);
+            }
+            return;
+        }
+    }
+
+    out.push(Part {
+        headers: headers.to_vec(),
+        disposition: find_header(headers, "Content-Disposition").map(parse_param_value),
+        transfer_encoding: find_header(headers, "Content-Transfer-Encoding")
+            .unwrap_or("7bit")
+            .trim()
+            .to_ascii_lowercase(),
+        content_type,
+        body,
+    });
+}
+
+/// Split a multipart body into the raw sections between boundary lines
+pub fn split_multipart<'a>(body: &'a [u8], boundary: &[u8]) -> Vec<&'a [u8]> {
+    let mut sections = Vec::new();
+    let mut current_start: Option<usize> = None;
+    let mut line_start = 0;
+    while line_start < body.len() {
+        let line_end = body[line_start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(body.len(), |p| line_start + p);
+        let line = &body[line_start..line_end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if let Some(rest) = line
+            .strip_prefix(b"--")
+            .and_then(|l| l.strip_prefix(boundary))
+        {
+            let closing = rest.starts_with(b"--");
+            let rest = if closing { &rest[2..] } else { rest };
+            if rest.iter().all(u8::is_ascii_whitespace) {
+                if let Some(start) = current_start {
+                    // The line break before the delimiter belongs to it
+                    let mut end = line_start.max(start);
+                    if end > start && body[end - 1] == b'\n' {
+                        end -= 1;
+                        if end > start && body[end - 1] == b'\r' {
+                            end -= 1;
+                        }
+                    }
+                    sections.push(&body[start..end]);
+                }
+                if closing {
+                    return sections;
+                }
+                current_start = Some((line_end + 1).min(body.len()));
+            }
+        }
+        line_start = line_end + 1;
+    }
+    // Unterminated multipart: keep the last section
+    if let Some(start) = current_start {
+        if start < body.len() {
+            sections.push(&body[start..]);
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTIPART: &[u8] = b"From sender@example.com Mon Jan  1 00:00:00 2024\r\n\
+From: =?utf-8?B?SsO2cmc=?= <jorg@example.com>\r\n\
+Subject: =?utf-8?Q?Gr=C3=BC=C3=9Fe?=\r\n =?utf-8?Q?_aus_Berlin?=\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=iso-8859-1\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Gr=FC=DFe\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<p>Hi</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf; name=\"report.pdf\"\r\n\
+Content-Disposition: attachment; filename*=utf-8''r%C3%A9sum%C3%A9.pdf\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0=\r\n\
+--outer--\r\n\
+epilogue\r\n";
+
+    #[test]
+    fn test_headers_unfold_and_decode() {
+        let msg = Message::parse(MULTIPART);
+        assert_eq!(msg.decoded_header("from").unwrap(), "Jörg <jorg@example.com>");
+        assert_eq!(msg.decoded_header("SUBJECT").unwrap(), "Grüße aus Berlin");
+        assert!(msg.header("X-Missing").is_none());
+        assert_eq!(
+            first_address("\"Doe, J\" <J.Doe@Example.com>, b@x.org").unwrap(),
+            "j.doe@example.com"
+        );
+        assert_eq!(first_address("bare@example.org (Bare)").unwrap(), "bare@example.org");
+        assert_eq!(
+            address_list(
+                "\"Doe, J\" <J.Doe@Example.com>, b@x.org (Bee, B), Team: Ann <ann@x.org>;, \
+                 undisclosed-recipients:;"
+            ),
+            vec![
+                (Some("Doe, J".to_string()), "j.doe@example.com".to_string()),
+                (Some("Bee, B".to_string()), "b@x.org".to_string()),
+                (Some("Ann".to_string()), "ann@x.org".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_header() {
+        let ids: Vec<String> = (0..12)
+            .map(|i| format!("<msg{i}.20240101@mail.example.com>"))
+            .collect();
+        let value = ids.join(" ");
+        let folded = fold_header("References", &value);
+        assert!(folded.lines().all(|line| line.len() <= FOLD_WIDTH));
+        assert!(folded.lines().skip(1).all(|line| line.starts_with(' ')));
+        let block = format!("{folded}\n\n");
+        let headers = parse_header_block(block.as_bytes());
+        assert_eq!(headers[0].value, value);
+
+        assert_eq!(fold_header("Subject", "short"), "Subject: short");
+        assert_eq!(fold_header("X-Empty", ""), "X-Empty:");
+
+        let list = vec!["someone@example.com"; 80].join(",");
+        let folded = fold_header("To", &list);
+        assert!(folded.lines().all(|line| line.len() <= FOLD_WIDTH));
+        assert_eq!(folded.replace("\n ", ""), format!("To: {list}"));
+        let unbreakable = "x".repeat(1200);
+        assert_eq!(fold_header("X-Blob", &unbreakable), format!("X-Blob: {unbreakable}"));
+    }
+
+    #[test]
+    fn test_multipart_walk() {
+        let msg = Message::parse(MULTIPART);
+        let parts = msg.parts();
+        assert_eq!(parts.len(), 3);
+
+        assert_eq!(parts[0].mime_type(), "text/plain");
+        assert_eq!(parts[0].text(), "Grüße");
+        assert!(!parts[0].is_attachment());
+
+        assert_eq!(parts[1].mime_type(), "text/html");
+        assert_eq!(parts[1].text(), "<p>Hi</p>");
+
+        assert!(parts[2].is_attachment());
+        assert_eq!(parts[2].filename().unwrap(), "résumé.pdf");
+        assert_eq!(parts[2].decoded(), b"%PDF-");
+    }
+
+    #[test]
+    fn test_param_parsing() {
+        let ct = parse_param_value("Text/Plain; charset=\"utf-8\"; format=flowed");
+        assert_eq!(ct.value, "text/plain");
+        assert_eq!(ct.param("charset"), Some("utf-8"));
+        assert_eq!(ct.param("format"), Some("flowed"));
+
+        let cd = parse_param_value("attachment; filename*0=\"long \"; filename*1=\"name.txt\"");
+        assert_eq!(cd.param("filename"), Some("long name.txt"));
+    }
+
+    #[test]
+    fn test_single_part_without_headers() {
+        let msg = Message::parse(b"\nbody only");
+        assert!(msg.headers.is_empty());
+        let parts = msg.parts();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].text(), "body only");
+    }
+}
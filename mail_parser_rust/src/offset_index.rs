@@ -0,0 +1,597 @@
+//! Byte-offset message indexes with sidecar persistence.
+//!
+//! Finding message boundaries means reading the whole archive, which for a
+//! multi-gigabyte mbox dominates any job that only needs a few messages. An
+//! index records the offset, length and UID (see [`crate::uid`]) of every
+//! message once, in a compact sidecar file (`<mbox>.idx` by default), so
+//! later lookups go straight to the bytes, by position or by UID.
+//!
+//! A sidecar only applies to the archive it was built from: it stores the
+//! source length, the separator rule and a fingerprint of the first 64 KiB.
+//! An archive that merely grew is re-scanned from its last indexed message;
+//! a shorter or rewritten one, or a different `from_line_mode`, is indexed
+//! from scratch.
+//!
+//! Compressed archives are indexed by decompressing them as a stream; the
+//! entries are offsets into the decompressed data, the length and
+//! fingerprint those of the compressed file. A message is read back by
+//! decompressing up to it, and any change to the file means a full rescan.
+
+use crate::checkpoint::fnv1a;
+use crate::compressed::{self, Codec};
+use crate::mbox::{map_file, message_spans_reporting, FromLineMode, MappedFile};
+use crate::output::Output;
+use crate::paths::extended;
+use crate::progress::Progress;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Sidecar file signature and format version
+const MAGIC: &[u8; 8] = b"MPRIDX02";
+
+/// Length of a UID in the sidecar (its hex digits)
+const UID_LEN: usize = 32;
+
+/// Bytes per sidecar entry: offset, length and UID
+const ENTRY_LEN: usize = 16 + UID_LEN;
+
+/// Messages of a compressed archive decompressed before their UIDs are
+/// computed in parallel
+const BATCH: usize = 1024;
+
+/// Number of leading source bytes covered by the fingerprint
+const FINGERPRINT_LEN: usize = 64 * 1024;
+
+/// FNV-1a hash of the first 64 KiB of `data`
+pub fn fingerprint(data: &[u8]) -> u64 {
+    fnv1a(&data[..data.len().min(FINGERPRINT_LEN)])
+}
+
+fn to_u64(value: usize) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+fn invalid(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("not a message index: {detail}"))
+}
+
+/// The next `n` bytes of `rest`
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if rest.len() < n {
+        return Err(invalid("truncated"));
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(head)
+}
+
+fn take_u64(rest: &mut &[u8]) -> io::Result<u64> {
+    let mut word = [0; 8];
+    word.copy_from_slice(take(rest, 8)?);
+    Ok(u64::from_le_bytes(word))
+}
+
+/// How an index relates to the current archive contents
+#[derive(Debug, PartialEq, Eq)]
+pub enum Freshness {
+    Current,
+    /// The archive was appended to
+    Grown,
+    Stale,
+}
+
+/// Compare `data` with an archive indexed at `source_len` bytes whose
+/// leading bytes hashed to `indexed` (see [`fingerprint`])
+pub fn freshness(data: &[u8], source_len: u64, indexed: u64) -> Freshness {
+    let len = to_u64(data.len());
+    let covered = usize::try_from(source_len).map_or(data.len(), |n| n.min(data.len()));
+    if len < source_len || fingerprint(&data[..covered]) != indexed {
+        Freshness::Stale
+    } else if len == source_len {
+        Freshness::Current
+    } else {
+        Freshness::Grown
+    }
+}
+
+/// Offset and length of every message in an mbox
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import build_index_fast
+/// index = build_index_fast("big.mbox")
+/// offset, length = index.span(len(index) - 1)
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[pyclass]
+pub struct MessageIndex {
+    /// The indexed mbox
+    #[pyo3(get)]
+    pub path: String,
+    /// Archive size in bytes when indexed
+    #[pyo3(get)]
+    pub source_len: u64,
+    /// Separator rule the boundaries were found with
+    #[pyo3(get)]
+    pub from_line_mode: String,
+    /// FNV-1a hash of the first 64 KiB of the archive
+    fingerprint: u64,
+    /// (offset, length) per message
+    entries: Vec<(u64, u64)>,
+    /// UID per message, parallel to `entries`
+    uids: Vec<String>,
+}
+
+impl MessageIndex {
+    /// Index `data` (the contents of `path`) from scratch
+    fn scan(
+        path: &str,
+        data: &[u8],
+        mode_name: &str,
+        mode: &FromLineMode,
+        progress: &Progress,
+    ) -> PyResult<Self> {
+        let mut index = Self {
+            path: path.to_string(),
+            source_len: 0,
+            from_line_mode: mode_name.to_string(),
+            fingerprint: 0,
+            entries: Vec::new(),
+            uids: Vec::new(),
+        };
+        index.scan_from(data, 0, mode, progress)?;
+        Ok(index)
+    }
+
+    /// Replace the entries from byte `from` (a message start, 0 for a
+    /// compressed archive) onwards
+    fn scan_from(
+        &mut self,
+        data: &[u8],
+        from: usize,
+        mode: &FromLineMode,
+        progress: &Progress,
+    ) -> PyResult<()> {
+        let (spans, uids) = if let Some(codec) = Codec::detect(data) {
+            let (mut spans, mut uids) = (Vec::new(), Vec::new());
+            compressed::for_each_batch(data, codec, mode, BATCH, |batch| {
+                uids.par_extend(batch.par_iter().map(|(_, raw)| message_uid(raw)));
+                spans.extend(batch.iter().map(|(span, _)| span.clone()));
+                Ok(())
+            })?;
+            (spans, uids)
+        } else {
+            progress.advance(from);
+            let spans: Vec<Range<usize>> = message_spans_reporting(&data[from..], mode, progress)
+                .into_iter()
+                .map(|span| from + span.start..from + span.end)
+                .collect();
+            let uids = spans
+                .par_iter()
+                .map(|span| message_uid(&data[span.clone()]))
+                .collect();
+            (spans, uids)
+        };
+        let from_offset = to_u64(from);
+        let kept = self
+            .entries
+            .partition_point(|&(offset, _)| offset < from_offset);
+        self.entries.truncate(kept);
+        self.uids.truncate(kept);
+        self.entries.extend(
+            spans
+                .iter()
+                .map(|span| (to_u64(span.start), to_u64(span.len()))),
+        );
+        self.uids.extend(uids);
+        self.source_len = to_u64(data.len());
+        self.fingerprint = fingerprint(data);
+        Ok(())
+    }
+
+    fn freshness(&self, data: &[u8], mode_name: &str) -> Freshness {
+        if self.from_line_mode == mode_name {
+            freshness(data, self.source_len, self.fingerprint)
+        } else {
+            Freshness::Stale
+        }
+    }
+
+    /// Bring the index up to date with `data`, returning whether it changed
+    fn refresh(&mut self, data: &[u8], mode: &FromLineMode, progress: &Progress) -> PyResult<bool> {
+        match self.freshness(data, &self.from_line_mode) {
+            Freshness::Current => return Ok(false),
+            Freshness::Grown if Codec::detect(data).is_none() => {
+                // The last message may have grown too, so scan from its start
+                let last = self.entries.last().map_or(0, |&(offset, _)| offset);
+                self.scan_from(data, usize::try_from(last).unwrap_or(0), mode, progress)?;
+            },
+            Freshness::Grown | Freshness::Stale => self.scan_from(data, 0, mode, progress)?,
+        }
+        Ok(true)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mode = self.from_line_mode.as_bytes();
+        let mut out = Vec::with_capacity(40 + mode.len() + self.entries.len() * ENTRY_LEN);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.source_len.to_le_bytes());
+        out.extend_from_slice(&self.fingerprint.to_le_bytes());
+        out.extend_from_slice(&to_u64(mode.len()).to_le_bytes());
+        out.extend_from_slice(mode);
+        out.extend_from_slice(&to_u64(self.entries.len()).to_le_bytes());
+        for (&(offset, length), uid) in self.entries.iter().zip(&self.uids) {
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&length.to_le_bytes());
+            out.extend_from_slice(uid.as_bytes());
+        }
+        out
+    }
+
+    fn decode(path: &str, bytes: &[u8]) -> io::Result<Self> {
+        let mut rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("bad signature"))?;
+        let source_len = take_u64(&mut rest)?;
+        let fingerprint = take_u64(&mut rest)?;
+        let mode_len = usize::try_from(take_u64(&mut rest)?).map_err(|_| invalid("bad mode"))?;
+        let from_line_mode = String::from_utf8(take(&mut rest, mode_len)?.to_vec())
+            .map_err(|_| invalid("bad mode"))?;
+        let count = take_u64(&mut rest)?;
+        if to_u64(rest.len()) != count.saturating_mul(to_u64(ENTRY_LEN)) {
+            return Err(invalid("entry count mismatch"));
+        }
+        let mut entries = Vec::with_capacity(rest.len() / ENTRY_LEN);
+        let mut uids = Vec::with_capacity(rest.len() / ENTRY_LEN);
+        while !rest.is_empty() {
+            entries.push((take_u64(&mut rest)?, take_u64(&mut rest)?));
+            let uid =
+                std::str::from_utf8(take(&mut rest, UID_LEN)?).map_err(|_| invalid("bad uid"))?;
+            uids.push(uid.to_string());
+        }
+        Ok(Self {
+            path: path.to_string(),
+            source_len,
+            from_line_mode,
+            fingerprint,
+            entries,
+            uids,
+        })
+    }
+
+    /// Read a sidecar file, returning `None` when it does not exist
+    fn load(path: &str, index_path: &str) -> io::Result<Option<Self>> {
+        match std::fs::read(extended(Path::new(index_path))) {
+            Ok(bytes) => Self::decode(path, &bytes).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write(&self, index_path: &str) -> io::Result<()> {
+        let mut file = Output::new(false).create(Path::new(index_path))?;
+        file.write_all(&self.encode())?;
+        file.finish().map(drop)
+    }
+
+    /// (offset, length) of message `n`, counting from the end when negative
+    fn entry(&self, n: isize) -> PyResult<(u64, u64)> {
+        let position = if n < 0 {
+            self.entries.len().checked_sub(n.unsigned_abs())
+        } else {
+            usize::try_from(n).ok()
+        };
+        position
+            .and_then(|i| self.entries.get(i).copied())
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyIndexError, _>(format!(
+                    "message {n} out of range for {} messages",
+                    self.entries.len()
+                ))
+            })
+    }
+}
+
+fn default_index_path(path: &str) -> String {
+    format!("{path}.idx")
+}
+
+fn index_error(index_path: &str, e: &io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to access {index_path}: {e}"))
+}
+
+#[pymethods]
+impl MessageIndex {
+    /// Number of indexed messages
+    fn __len__(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// (offset, length) of message `n` (negative counts from the end)
+    ///
+    /// # Raises
+    /// * `IndexError` if `n` is out of range
+    fn span(&self, n: isize) -> PyResult<(u64, u64)> {
+        self.entry(n)
+    }
+
+    /// Byte offset of every message's `From ` line
+    #[getter]
+    fn offsets(&self) -> Vec<u64> {
+        self.entries.iter().map(|&(offset, _)| offset).collect()
+    }
+
+    /// Length of every message in bytes
+    #[getter]
+    fn lengths(&self) -> Vec<u64> {
+        self.entries.iter().map(|&(_, length)| length).collect()
+    }
+
+    /// UID of every message (see `compute_message_uid`)
+    #[getter]
+    fn uids(&self) -> Vec<String> {
+        self.uids.clone()
+    }
+
+    /// Position of the first message with UID `uid`, None if there is none
+    fn position_of(&self, uid: &str) -> Option<usize> {
+        self.uids.iter().position(|u| u == uid)
+    }
+
+    /// Write the index to `index_path` (default `<path>.idx`)
+    ///
+    /// # Raises
+    /// * `IOError` if the file cannot be written
+    #[pyo3(signature = (index_path=None))]
+    fn save(&self, index_path: Option<&str>) -> PyResult<String> {
+        let index_path = index_path.map_or_else(|| default_index_path(&self.path), str::to_string);
+        self.write(&index_path)
+            .map_err(|e| index_error(&index_path, &e))?;
+        Ok(index_path)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "MessageIndex(path={:?}, messages={}, source_len={})",
+            self.path,
+            self.entries.len(),
+            self.source_len
+        )
+    }
+}
+
+/// The up-to-date index of `path`: the sidecar if it still applies (updated
+/// for appends), otherwise a fresh scan reported to `progress`; written
+/// back when it changed and `save` is set
+fn open_index(
+    path: &str,
+    data: &MappedFile,
+    from_line_mode: &str,
+    index_path: Option<&str>,
+    save: bool,
+    progress: &Progress,
+) -> PyResult<MessageIndex> {
+    let mode = FromLineMode::parse(from_line_mode)?;
+    let index_path = index_path.map_or_else(|| default_index_path(path), str::to_string);
+    let saved = MessageIndex::load(path, &index_path)
+        .ok()
+        .flatten()
+        .filter(|index| index.freshness(data, from_line_mode) != Freshness::Stale);
+    let scanned: PyResult<(MessageIndex, bool)> = saved.map_or_else(
+        || Ok((MessageIndex::scan(path, data, from_line_mode, &mode, progress)?, true)),
+        |mut index| {
+            let changed = index.refresh(data, &mode, progress)?;
+            Ok((index, changed))
+        },
+    );
+    // Never persist offsets of a file that moved underneath the scan
+    data.verify(&mode)?;
+    if !progress.finish() {
+        return Err(progress.error("build_index_fast"));
+    }
+    let (index, changed) = scanned?;
+    if save && changed {
+        index
+            .write(&index_path)
+            .map_err(|e| index_error(&index_path, &e))?;
+    }
+    Ok(index)
+}
+
+/// Index the byte offset and length of every message in an mbox
+///
+/// Boundaries are found on all cores, and every message's UID (see
+/// `compute_message_uid`) is recorded so a message can be found again after
+/// the archive is re-ordered. An existing sidecar index that still
+/// matches the archive is reused, and only the appended tail is scanned when
+/// the archive grew. Compressed archives (gzip, zstd, xz) are indexed by
+/// offsets into the decompressed data and rescanned whenever they change.
+///
+/// # Arguments
+/// * `path` - Path to the mbox file
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `index_path` - Sidecar file (default `<path>.idx`)
+/// * `save` - Write the sidecar when the index was built or updated
+///   (default True)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   during the scan; returning False cancels it
+///
+/// # Returns
+/// * `MessageIndex` (`len()`, `span(n)`, `offsets`, `lengths`, `uids`,
+///   `position_of(uid)`, `save()`)
+///
+/// # Raises
+/// * `IOError` if the archive cannot be read or the sidecar written
+/// * `ScanCancelled` if `progress` returned False (or what it raised); no
+///   sidecar is written
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import build_index_fast
+/// index = build_index_fast("2019-2024.mbox")
+/// print(f"{len(index)} messages, largest {max(index.lengths)} bytes")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (path, from_line_mode="standard", index_path=None, save=true, progress=None))]
+pub fn build_index_fast(
+    py: Python<'_>,
+    path: &str,
+    from_line_mode: &str,
+    index_path: Option<&str>,
+    save: bool,
+    progress: Option<PyObject>,
+) -> PyResult<MessageIndex> {
+    py.allow_threads(|| {
+        let mmap = map_file(path)?;
+        let progress = Progress::new(progress, mmap.len());
+        open_index(path, &mmap, from_line_mode, index_path, save, &progress)
+    })
+}
+
+/// Read message `n` of an mbox through its sidecar index
+///
+/// The first call builds and saves the index (see `build_index_fast`);
+/// later calls read only the sidecar and the requested message's bytes. A
+/// compressed archive is decompressed up to the message.
+///
+/// # Arguments
+/// * `path` - Path to the mbox file
+/// * `n` - Message number (0-based; negative counts from the end)
+/// * `index_path` - Sidecar file (default `<path>.idx`)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+///
+/// # Returns
+/// * The raw message, `From ` line included
+///
+/// # Raises
+/// * `IndexError` if `n` is out of range
+/// * `IOError` if the archive cannot be read or the sidecar written
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import get_message_by_index
+/// raw = get_message_by_index("2019-2024.mbox", 1_250_000)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (path, n, index_path=None, from_line_mode="standard"))]
+pub fn get_message_by_index<'py>(
+    py: Python<'py>,
+    path: &str,
+    n: isize,
+    index_path: Option<&str>,
+    from_line_mode: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let (mmap, span) = py.allow_threads(|| locate(path, n, index_path, from_line_mode))?;
+    match Codec::detect(&mmap) {
+        Some(codec) => {
+            let raw = py.allow_threads(|| compressed::read_range(&mmap, codec, span))?;
+            Ok(PyBytes::new(py, &raw))
+        },
+        None => Ok(PyBytes::new(py, &mmap[span])),
+    }
+}
+
+/// The mapped archive and the byte range of message `n` (in the
+/// decompressed data for a compressed archive)
+fn locate(
+    path: &str,
+    n: isize,
+    index_path: Option<&str>,
+    from_line_mode: &str,
+) -> PyResult<(MappedFile, Range<usize>)> {
+    let mmap = map_file(path)?;
+    let index = open_index(path, &mmap, from_line_mode, index_path, true, &Progress::new(None, 0))?;
+    let (offset, length) = index.entry(n)?;
+    let start = usize::try_from(offset).unwrap_or(usize::MAX);
+    let end = start.saturating_add(usize::try_from(length).unwrap_or(usize::MAX));
+    if end > mmap.len() && Codec::detect(&mmap).is_none() {
+        return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Index does not match {path}"
+        )));
+    }
+    Ok((mmap, start..end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From a@x Mon Jan  1 00:00:00 2024\nSubject: 1\n\none\n\n\
+                        From b@x Mon Jan  1 00:00:00 2024\nSubject: 2\n\ntwo\n";
+
+    #[test]
+    fn test_index_roundtrip_and_refresh() {
+        let mode = FromLineMode::Standard;
+        let silent = Progress::new(None, 0);
+        let mut index =
+            MessageIndex::scan("a.mbox", MBOX.as_bytes(), "standard", &mode, &silent).unwrap();
+        assert_eq!(index.entries, [(0, 51), (51, 50)]);
+        assert_eq!(index.uids[1], message_uid(&MBOX.as_bytes()[51..]));
+        assert_eq!(index.position_of(&index.uids[1]), Some(1));
+        let decoded = MessageIndex::decode("a.mbox", &index.encode()).unwrap();
+        assert_eq!(decoded, index);
+        assert!(MessageIndex::decode("a.mbox", &index.encode()[..30]).is_err());
+        assert!(MessageIndex::decode("a.mbox", b"garbage").is_err());
+
+        // Appending extends the last message and adds new ones
+        let grown = format!("{MBOX}more\n\nFrom c@x Mon Jan  1 00:00:00 2024\n\nthree\n");
+        assert_eq!(index.freshness(grown.as_bytes(), "standard"), Freshness::Grown);
+        assert!(index.refresh(grown.as_bytes(), &mode, &silent).unwrap());
+        assert_eq!(index.entries, [(0, 51), (51, 56), (107, 41)]);
+        assert_eq!(index.uids[2], message_uid(&grown.as_bytes()[107..]));
+        assert_eq!(
+            index,
+            MessageIndex::scan("a.mbox", grown.as_bytes(), "standard", &mode, &silent).unwrap()
+        );
+        assert!(!index.refresh(grown.as_bytes(), &mode, &silent).unwrap());
+
+        let rewritten = MBOX.replace("Subject: 1", "Subject: X");
+        assert_eq!(index.freshness(rewritten.as_bytes(), "standard"), Freshness::Stale);
+        assert_eq!(index.freshness(grown.as_bytes(), "strict"), Freshness::Stale);
+        assert_eq!(index.entry(-1).unwrap(), (107, 41));
+        assert!(index.entry(3).is_err() && index.entry(-4).is_err());
+    }
+
+    #[test]
+    fn test_get_message_by_index() {
+        crate::with_py(|py| {
+            let path =
+                std::env::temp_dir().join(format!("mpr_offset_index_{}.mbox", std::process::id()));
+            let path_str = path.to_str().unwrap();
+            std::fs::write(&path, MBOX).unwrap();
+            let index = build_index_fast(py, path_str, "standard", None, true, None).unwrap();
+            assert_eq!(index.offsets(), [0, 51]);
+            let sidecar = default_index_path(path_str);
+            assert_eq!(MessageIndex::load(path_str, &sidecar).unwrap(), Some(index));
+            let (mmap, span) = locate(path_str, 1, None, "standard").unwrap();
+            assert!(mmap[span].starts_with(b"From b@x"));
+            assert!(locate(path_str, 2, None, "standard").is_err());
+            std::fs::remove_file(sidecar).unwrap();
+            std::fs::remove_file(path).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_compressed_index() {
+        crate::with_py(|py| {
+            let path = std::env::temp_dir()
+                .join(format!("mpr_offset_index_{}.mbox.xz", std::process::id()));
+            let path_str = path.to_str().unwrap();
+            std::fs::write(&path, compressed::compress(MBOX.as_bytes(), Codec::Xz)).unwrap();
+            let index = build_index_fast(py, path_str, "standard", None, false, None).unwrap();
+            assert_eq!(index.entries, [(0, 51), (51, 50)]);
+            assert_eq!(index.uids[0], message_uid(&MBOX.as_bytes()[..51]));
+            assert_eq!(index.source_len, std::fs::metadata(&path).unwrap().len());
+            let raw = get_message_by_index(py, path_str, -1, None, "standard").unwrap();
+            assert_eq!(raw.as_bytes(), &MBOX.as_bytes()[51..]);
+            let sidecar = default_index_path(path_str);
+            std::fs::remove_file(sidecar).unwrap();
+            std::fs::remove_file(path).unwrap();
+        });
+    }
+}
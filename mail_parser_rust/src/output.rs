@@ -0,0 +1,628 @@
+//! Output plumbing shared by every function that writes files.
+//!
+//! Exporters never touch `File::create` directly: they ask an [`Output`] for
+//! an [`OutputFile`] per destination path. In dry-run mode the same code path
+//! runs end to end, but bytes are counted instead of written. Either way the
+//! message/file/byte totals are summarised in an [`OutputReport`].
+//!
+//! Real output is written to a hidden `.<name>.<pid>-<random>.partial` file
+//! next to the destination and only renamed over it by
+//! [`OutputFile::finish`], so an interrupted export never leaves a
+//! half-written file under the final name and concurrent writers to one
+//! destination never share a temp file. Resumable outputs use the fixed
+//! `.<name>.partial`, so a later run can find it.
+//! How hard `finish` pushes data to stable storage is set by [`FsyncPolicy`].
+//!
+//! Files whose name ends in `.gz` or `.zst` are compressed on the fly (or as
+//! forced by [`Output::with_compression`]), so exports do not need a second
+//! compression pass over the produced artifact.
+//!
+//! With [`Output::with_lock`] each destination is locked as a mailbox (see
+//! [`crate::locking`]) from `create` until the file is committed; the temp
+//! file is locked before it is renamed over a locked mailbox, so the
+//! rewrite stays atomic and the mailbox never appears unlocked.
+
+use crate::locking::{LockSpec, MailboxLock};
+use crate::paths::extended;
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// How many written (or planned) filenames a report lists as examples.
+const MAX_EXAMPLE_FILENAMES: usize = 10;
+
+/// What a writing operation wrote, or would have written with `dry_run=True`
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct OutputReport {
+    /// Whether this was a dry run (nothing was written to disk)
+    #[pyo3(get)]
+    pub dry_run: bool,
+    /// Messages written
+    #[pyo3(get)]
+    pub message_count: u64,
+    /// Total bytes written across all files (before compression)
+    #[pyo3(get)]
+    pub byte_total: u64,
+    /// Number of output files created or replaced
+    #[pyo3(get)]
+    pub file_count: u64,
+    /// The first few destination paths, in the order they were written
+    #[pyo3(get)]
+    pub example_filenames: Vec<String>,
+}
+
+#[pymethods]
+impl OutputReport {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Durability guarantee applied when an output file is committed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Rename without syncing; fastest, contents may be lost on power failure
+    Never,
+    /// `fsync` the file before renaming it into place
+    #[default]
+    File,
+    /// Also `fsync` the directory so the rename itself is durable
+    Full,
+}
+
+impl FsyncPolicy {
+    /// Parse the `fsync=` argument accepted by writer functions
+    pub fn parse(value: &str) -> PyResult<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" | "never" => Ok(Self::Never),
+            "file" => Ok(Self::File),
+            "full" | "dir" => Ok(Self::Full),
+            other => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid fsync policy {other:?} (expected \"none\", \"file\" or \"full\")"
+            ))),
+        }
+    }
+}
+
+/// Streaming compression applied to an output file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Infer the compression from the destination file extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gz") => Self::Gzip,
+            Some(ext) if ext.eq_ignore_ascii_case("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Parse the `compression=` argument; `"auto"` (or `None`) means infer
+    /// from each file's extension
+    pub fn parse(value: Option<&str>) -> PyResult<Option<Self>> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Ok(None),
+            "none" => Ok(Some(Self::None)),
+            "gz" | "gzip" => Ok(Some(Self::Gzip)),
+            "zst" | "zstd" => Ok(Some(Self::Zstd)),
+            other => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid compression {other:?} (expected \"auto\", \"none\", \"gzip\" or \"zstd\")"
+            ))),
+        }
+    }
+}
+
+/// Thread-safe totals shared by all files of one operation
+#[derive(Default)]
+struct Stats {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    files: AtomicU64,
+    examples: Mutex<Vec<String>>,
+}
+
+/// Destination for an operation's files; cheap to clone into rayon workers
+#[derive(Clone, Default)]
+pub struct Output {
+    dry_run: bool,
+    stats: Arc<Stats>,
+    fsync: FsyncPolicy,
+    compression: Option<Compression>,
+    lock: LockSpec,
+}
+
+impl Output {
+    /// Real output, or a dry run that only records what would be written
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            ..Self::default()
+        }
+    }
+
+    /// Build from the common `dry_run=`, `fsync=` and `compression=` arguments
+    pub fn from_args(dry_run: bool, fsync: &str, compression: Option<&str>) -> PyResult<Self> {
+        Ok(Self::new(dry_run)
+            .with_fsync(FsyncPolicy::parse(fsync)?)
+            .with_compression(Compression::parse(compression)?))
+    }
+
+    /// Force a compression for every file (`None` infers it from the extension)
+    pub const fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Choose how committed files are synced to disk
+    pub const fn with_fsync(mut self, fsync: FsyncPolicy) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Lock every destination as a mailbox while it is written
+    pub const fn with_lock(mut self, lock: LockSpec) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Lock `src` for reading on behalf of an operation that rewrites it
+    /// into `dest`. Nothing is locked in dry-run mode, and `dest` itself is
+    /// left to [`Output::create`].
+    pub fn lock_source(&self, src: &Path, dest: &Path) -> io::Result<Option<MailboxLock>> {
+        if self.dry_run || self.lock.is_none() {
+            return Ok(None);
+        }
+        let same = match (fs::canonicalize(extended(src)), fs::canonicalize(extended(dest))) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+        if same {
+            return Ok(None);
+        }
+        self.lock.shared(src).map(Some)
+    }
+
+    pub const fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Open `path` for writing (nothing is created on disk in dry-run mode)
+    ///
+    /// Data goes to a temp file that is discarded if the `OutputFile` is
+    /// dropped without calling [`OutputFile::finish`].
+    pub fn create(&self, path: &Path) -> io::Result<OutputFile> {
+        self.open(path, None)
+    }
+
+    /// Like [`Output::create`], but continue the temp file left by an
+    /// interrupted run, truncated to `resume_len` bytes (see `Checkpoint`).
+    ///
+    /// If no usable temp file exists the output starts empty; callers must
+    /// check [`OutputFile::len`] and restart from the beginning when it is not
+    /// `resume_len`. The temp file is kept when dropped unfinished so a later
+    /// run can pick it up again. Compressed output cannot be cut at an
+    /// uncompressed offset and therefore always starts empty.
+    pub fn resume(&self, path: &Path, resume_len: u64) -> io::Result<OutputFile> {
+        self.open(path, Some(resume_len))
+    }
+
+    fn open(&self, path: &Path, resume_len: Option<u64>) -> io::Result<OutputFile> {
+        let compression = self
+            .compression
+            .unwrap_or_else(|| Compression::from_path(path));
+        let resume_len = resume_len.filter(|_| compression == Compression::None);
+        let mut written = 0;
+        let mut lock = None;
+        let sink = if self.dry_run {
+            Sink::Discard
+        } else {
+            if !self.lock.is_none() {
+                lock = Some(self.lock.exclusive(path)?);
+            }
+            let (tmp, mut file) = if resume_len.is_some() {
+                let tmp = partial_path(&extended(path));
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(false)
+                    .open(&tmp)?;
+                (tmp, file)
+            } else {
+                unique_partial(&extended(path))?
+            };
+            let existing = file.metadata()?.len();
+            written = match resume_len {
+                Some(len) if len <= existing => len,
+                _ => 0,
+            };
+            file.set_len(written)?;
+            file.seek(SeekFrom::Start(written))?;
+            Sink::Disk {
+                writer: Encoder::new(BufWriter::new(file), compression)?,
+                tmp,
+            }
+        };
+        Ok(OutputFile {
+            sink,
+            path: path.to_path_buf(),
+            written,
+            stats: Arc::clone(&self.stats),
+            fsync: self.fsync,
+            keep_partial: resume_len.is_some(),
+            lock,
+        })
+    }
+
+    /// Count one message towards the report
+    pub fn note_message(&self) {
+        self.stats.messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Totals of everything finished so far
+    pub fn report(&self) -> OutputReport {
+        let example_filenames = self
+            .stats
+            .examples
+            .lock()
+            .map(|examples| examples.clone())
+            .unwrap_or_default();
+        OutputReport {
+            dry_run: self.dry_run,
+            message_count: self.stats.messages.load(Ordering::Relaxed),
+            byte_total: self.stats.bytes.load(Ordering::Relaxed),
+            file_count: self.stats.files.load(Ordering::Relaxed),
+            example_filenames,
+        }
+    }
+}
+
+enum Sink {
+    Disk { writer: Encoder, tmp: PathBuf },
+    Discard,
+    Finished,
+}
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(writer: BufWriter<File>, compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Self::Plain(writer),
+            Compression::Gzip => {
+                Self::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::default()))
+            },
+            Compression::Zstd => Self::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Plain(w) => w,
+            Self::Gzip(w) => w,
+            Self::Zstd(w) => w,
+        }
+    }
+
+    /// Write the compression trailer and hand back the underlying file
+    fn finish(self) -> io::Result<File> {
+        let buffered = match self {
+            Self::Plain(w) => w,
+            Self::Gzip(w) => w.finish()?,
+            Self::Zstd(w) => w.finish()?,
+        };
+        buffered
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+    }
+}
+
+/// Hidden temp file next to `path` that a resumable output writes to until
+/// commit, found again by the next run
+fn partial_path(path: &Path) -> PathBuf {
+    temp_path(path, "")
+}
+
+/// `.<name><suffix>.partial` next to `path`
+fn temp_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// A temp file next to `path` created for this writer alone, named after
+/// the process and a random number
+fn unique_partial(path: &Path) -> io::Result<(PathBuf, File)> {
+    use std::hash::{BuildHasher, Hasher};
+    loop {
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let tmp = temp_path(path, &format!(".{}-{random:016x}", std::process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&tmp) {
+            Ok(file) => return Ok((tmp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {},
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn sync_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path; // Directory handles cannot be synced on Windows
+    Ok(())
+}
+
+/// A single destination file obtained from [`Output::create`]
+pub struct OutputFile {
+    sink: Sink,
+    path: PathBuf,
+    written: u64,
+    stats: Arc<Stats>,
+    fsync: FsyncPolicy,
+    keep_partial: bool,
+    /// Released when the file is dropped, after the commit
+    lock: Option<MailboxLock>,
+}
+
+impl OutputFile {
+    /// Bytes written so far
+    pub const fn len(&self) -> u64 {
+        self.written
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// Discard everything written so far, e.g. a resumed output whose
+    /// checkpoint turned out not to match the source
+    pub fn restart(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Disk {
+                writer: Encoder::Plain(w),
+                ..
+            } => {
+                w.flush()?;
+                w.get_mut().set_len(0)?;
+                w.get_mut().seek(SeekFrom::Start(0))?;
+            },
+            Sink::Disk { .. } if self.written > 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "compressed output cannot be rewound",
+                ));
+            },
+            _ => {},
+        }
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Flush the file and atomically move it into place, returning the number
+    /// of bytes written
+    pub fn finish(mut self) -> io::Result<u64> {
+        match std::mem::replace(&mut self.sink, Sink::Finished) {
+            Sink::Disk { writer, tmp } => {
+                let file = writer.finish()?;
+                if self.fsync != FsyncPolicy::Never {
+                    file.sync_all()?;
+                }
+                drop(file);
+                let dest = extended(&self.path);
+                match self.lock.as_mut() {
+                    Some(lock) => lock.replace(&tmp, &dest)?,
+                    None => fs::rename(&tmp, &dest)?,
+                }
+                if self.fsync == FsyncPolicy::Full {
+                    sync_dir(&dest)?;
+                }
+            },
+            Sink::Discard | Sink::Finished => {},
+        }
+        self.stats.bytes.fetch_add(self.written, Ordering::Relaxed);
+        self.stats.files.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut examples) = self.stats.examples.lock() {
+            if examples.len() < MAX_EXAMPLE_FILENAMES {
+                examples.push(self.path.to_string_lossy().into_owned());
+            }
+        }
+        Ok(self.written)
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if let Sink::Disk { writer, tmp } = &mut self.sink {
+            if self.keep_partial {
+                let _ = writer.writer().flush();
+            } else {
+                let _ = fs::remove_file(tmp);
+            }
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = match &mut self.sink {
+            Sink::Disk { writer, .. } => writer.writer().write(buf)?,
+            Sink::Discard | Sink::Finished => buf.len(),
+        };
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Disk { writer, .. } => writer.writer().flush(),
+            Sink::Discard | Sink::Finished => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_dry_run_touches_nothing() {
+        let dir = std::env::temp_dir().join(format!("mpr_dry_run_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let output = Output::new(true);
+        for i in 0..12 {
+            let path = dir.join(format!("{i}.eml"));
+            let mut file = output.create(&path).unwrap();
+            file.write_all(b"Subject: hi\n\nbody\n").unwrap();
+            output.note_message();
+            assert_eq!(file.finish().unwrap(), 18);
+            assert!(!path.exists());
+        }
+
+        let report = output.report();
+        assert!(report.dry_run);
+        assert_eq!(report.message_count, 12);
+        assert_eq!(report.file_count, 12);
+        assert_eq!(report.byte_total, 12 * 18);
+        assert_eq!(report.example_filenames.len(), MAX_EXAMPLE_FILENAMES);
+        assert!(report.example_filenames[0].ends_with("0.eml"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_real_output_writes() {
+        let dir = std::env::temp_dir().join(format!("mpr_real_out_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.mbox");
+
+        let output = Output::new(false);
+        let mut file = output.create(&path).unwrap();
+        file.write_all(b"hello").unwrap();
+        file.finish().unwrap();
+
+        assert!(!output.report().dry_run);
+        assert_eq!(output.report().byte_total, 5);
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Concurrent writers to one destination get their own temp files
+        let mut first = output.create(&path).unwrap();
+        let mut second = output.create(&path).unwrap();
+        first.write_all(b"first").unwrap();
+        second.write_all(b"second").unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        second.finish().unwrap();
+        first.finish().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_output_never_replaces_target() {
+        let dir = std::env::temp_dir().join(format!("mpr_atomic_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.mbox");
+        fs::write(&path, b"previous").unwrap();
+
+        let output = Output::new(false).with_fsync(FsyncPolicy::Full);
+        let mut file = output.create(&path).unwrap();
+        file.write_all(b"half-written").unwrap();
+        drop(file);
+        assert_eq!(fs::read(&path).unwrap(), b"previous");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A resumable file survives the drop and is truncated on resume
+        let mut file = output.resume(&path, 0).unwrap();
+        file.write_all(b"first|second").unwrap();
+        drop(file);
+        let mut file = output.resume(&path, 6).unwrap();
+        assert_eq!(file.len(), 6);
+        file.write_all(b"again").unwrap();
+        file.finish().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first|again");
+
+        let mut file = output.resume(&path, 0).unwrap();
+        file.write_all(b"stale").unwrap();
+        drop(file);
+        let mut file = output.resume(&path, 5).unwrap();
+        file.restart().unwrap();
+        file.write_all(b"fresh").unwrap();
+        file.finish().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"fresh");
+
+        assert_eq!(FsyncPolicy::parse("none").unwrap(), FsyncPolicy::Never);
+        assert!(FsyncPolicy::parse("sometimes").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_output() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("mpr_compress_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let payload = b"From a@b Mon Jan  1 00:00:00 2024\n\nhello\n".repeat(100);
+
+        let output = Output::new(false);
+        for name in ["out.mbox.gz", "out.mbox.zst"] {
+            let path = dir.join(name);
+            let mut file = output.create(&path).unwrap();
+            file.write_all(&payload).unwrap();
+            assert_eq!(file.finish().unwrap(), payload.len() as u64);
+            assert!(fs::metadata(&path).unwrap().len() < payload.len() as u64);
+
+            let raw = fs::File::open(&path).unwrap();
+            let mut decoded = Vec::new();
+            if Compression::from_path(&path) == Compression::Gzip {
+                flate2::read::GzDecoder::new(raw)
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+            } else {
+                zstd::Decoder::new(raw)
+                    .unwrap()
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+            }
+            assert_eq!(decoded, payload);
+        }
+
+        // Forced compression overrides the extension; resume is not possible
+        let forced = Output::new(false).with_compression(Some(Compression::Zstd));
+        let file = forced.resume(&dir.join("plain.mbox"), 10).unwrap();
+        assert_eq!(file.len(), 0);
+
+        assert_eq!(Compression::parse(Some("auto")).unwrap(), None);
+        assert_eq!(Compression::parse(Some("GZIP")).unwrap(), Some(Compression::Gzip));
+        assert!(Compression::parse(Some("bz2")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Full message parsing for Python callers.
+//!
+//! Walks the multipart tree of [`crate::mime::Message`], undoes each part's
+//! Content-Transfer-Encoding and charset, and sorts the leaves into text
+//! bodies, HTML bodies and attachments, so callers need not fall back to
+//! Python's `email` package for anything past the headers.
+
+use crate::date::parse_date;
+use crate::mime::{address_list, decode_encoded_words, find_header, first_address, Message};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use serde::Serialize;
+
+/// One attachment (or inline non-text part) of a `ParsedMessage`
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ParsedAttachment {
+    /// Decoded filename, if the part names one
+    #[pyo3(get)]
+    pub filename: Option<String>,
+    /// Lowercased MIME type, e.g. `application/pdf`
+    #[pyo3(get)]
+    pub content_type: String,
+    /// "attachment" or "inline", if Content-Disposition is present
+    #[pyo3(get)]
+    pub disposition: Option<String>,
+    /// Content-ID without angle brackets (referenced as `cid:` from HTML)
+    #[pyo3(get)]
+    pub content_id: Option<String>,
+    /// Declared charset, for text attachments
+    #[pyo3(get)]
+    pub charset: Option<String>,
+    /// Decoded size in bytes
+    #[pyo3(get)]
+    pub size: usize,
+    /// Position among the message's leaf parts (0-based)
+    #[pyo3(get)]
+    pub part_index: usize,
+    #[serde(skip)]
+    data: Vec<u8>,
+}
+
+#[pymethods]
+impl ParsedAttachment {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// Decoded attachment bytes
+    fn content<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.data)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ParsedAttachment(filename={:?}, content_type={:?}, size={})",
+            self.filename, self.content_type, self.size
+        )
+    }
+}
+
+/// A message with decoded headers, bodies and attachments
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ParsedMessage {
+    /// Header fields in order, unfolded and RFC 2047-decoded
+    #[pyo3(get)]
+    pub headers: Vec<(String, String)>,
+    #[pyo3(get)]
+    pub subject: Option<String>,
+    /// Lowercased sender address
+    #[pyo3(get)]
+    pub from_addr: Option<String>,
+    /// Lowercased To addresses
+    #[pyo3(get)]
+    pub to: Vec<String>,
+    /// Lowercased Cc addresses
+    #[pyo3(get)]
+    pub cc: Vec<String>,
+    /// Date header as written
+    #[pyo3(get)]
+    pub date: Option<String>,
+    /// Date as a Unix timestamp, when it parses
+    #[pyo3(get)]
+    pub timestamp: Option<i64>,
+    #[pyo3(get)]
+    pub message_id: Option<String>,
+    /// Top-level MIME type, e.g. `multipart/alternative`
+    #[pyo3(get)]
+    pub content_type: String,
+    /// Every text/plain body part, decoded, in document order
+    #[pyo3(get)]
+    pub text_bodies: Vec<String>,
+    /// Every text/html body part, decoded, in document order
+    #[pyo3(get)]
+    pub html_bodies: Vec<String>,
+    #[pyo3(get)]
+    pub attachments: Vec<ParsedAttachment>,
+}
+
+#[pymethods]
+impl ParsedMessage {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// First header named `name` (case-insensitive), decoded
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Every header named `name` (case-insensitive), decoded
+    fn get_all(&self, name: &str) -> Vec<String> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ParsedMessage(subject={:?}, text_bodies={}, html_bodies={}, attachments={})",
+            self.subject,
+            self.text_bodies.len(),
+            self.html_bodies.len(),
+            self.attachments.len()
+        )
+    }
+}
+
+fn addresses(msg: &Message, name: &str) -> Vec<String> {
+    msg.headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case(name))
+        .flat_map(|h| address_list(&decode_encoded_words(&h.value)))
+        .map(|(_, address)| address)
+        .collect()
+}
+
+/// Parse raw message bytes (a leading mbox `From ` line is skipped)
+pub fn parse(raw: &[u8]) -> ParsedMessage {
+    let msg = Message::parse(raw);
+    let date = msg.header("Date").map(|d| d.trim().to_string());
+    let mut parsed = ParsedMessage {
+        headers: msg
+            .headers
+            .iter()
+            .map(|h| (h.name.clone(), decode_encoded_words(&h.value)))
+            .collect(),
+        subject: msg.decoded_header("Subject"),
+        from_addr: msg
+            .decoded_header("From")
+            .and_then(|from| first_address(&from)),
+        to: addresses(&msg, "To"),
+        cc: addresses(&msg, "Cc"),
+        timestamp: date.as_deref().and_then(parse_date).map(|(ts, _)| ts),
+        date,
+        message_id: msg.header("Message-ID").map(|id| id.trim().to_string()),
+        content_type: msg
+            .header("Content-Type")
+            .map(crate::mime::parse_param_value)
+            .filter(|ct| ct.value.contains('/'))
+            .map_or_else(|| "text/plain".to_string(), |ct| ct.value),
+        ..ParsedMessage::default()
+    };
+    for (part_index, part) in msg.parts().into_iter().enumerate() {
+        if !part.is_attachment() {
+            if part.mime_type() == "text/html" {
+                parsed.html_bodies.push(part.text());
+            } else {
+                parsed.text_bodies.push(part.text());
+            }
+            continue;
+        }
+        let content = part.decoded();
+        parsed.attachments.push(ParsedAttachment {
+            filename: part.filename(),
+            content_type: part.mime_type().to_string(),
+            disposition: part.disposition.as_ref().map(|d| d.value.clone()),
+            content_id: find_header(&part.headers, "Content-ID")
+                .map(|id| {
+                    id.trim()
+                        .trim_start_matches('<')
+                        .trim_end_matches('>')
+                        .to_string()
+                })
+                .filter(|id| !id.is_empty()),
+            charset: part.charset().map(str::to_ascii_lowercase),
+            size: content.len(),
+            part_index,
+            data: content,
+        });
+    }
+    parsed
+}
+
+/// Parse a complete message into headers, bodies and attachments
+///
+/// Multipart structures are walked to any depth (up to a nesting limit);
+/// each part's Content-Transfer-Encoding (base64, quoted-printable) is
+/// undone and text is converted from its declared charset. Attached
+/// messages (`message/rfc822`) are returned as attachments.
+///
+/// # Arguments
+/// * `raw_bytes` - Raw RFC 5322 message (a leading mbox `From ` line is skipped)
+///
+/// # Returns
+/// * `ParsedMessage` with decoded `headers`, `subject`, `from_addr`, `to`,
+///   `cc`, `date`, `timestamp`, `message_id`, `text_bodies`, `html_bodies`
+///   and `attachments` (metadata plus `content()` bytes)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_message_fast
+/// msg = parse_message_fast(raw)
+/// print(msg.subject, msg.from_addr)
+/// for att in msg.attachments:
+///     open(att.filename or "unnamed", "wb").write(att.content())
+/// ```
+#[pyfunction]
+pub fn parse_message_fast(py: Python<'_>, raw_bytes: &[u8]) -> ParsedMessage {
+    py.allow_threads(|| parse(raw_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multipart() {
+        let raw = b"From a Mon Jan  1 00:00:00 2024\n\
+            From: =?utf-8?q?Jos=C3=A9?= <Jose@Example.com>\n\
+            To: a@x.org, \"B, C\" <b@x.org>\nCc: c@x.org\n\
+            Subject: =?utf-8?b?Q2Fmw6k=?=\nDate: Mon, 1 Jan 2024 10:00:00 +0000\n\
+            Message-ID: <1@x>\n\
+            Content-Type: multipart/mixed; boundary=outer\n\n\
+            --outer\nContent-Type: multipart/alternative; boundary=inner\n\n\
+            --inner\nContent-Type: text/plain; charset=iso-8859-1\n\
+            Content-Transfer-Encoding: quoted-printable\n\ncaf=E9\n\
+            --inner\nContent-Type: text/html\n\n<p>caf&eacute;</p>\n--inner--\n\
+            --outer\nContent-Type: application/pdf; name=\"r.pdf\"\n\
+            Content-Disposition: attachment; filename=\"report.pdf\"\n\
+            Content-ID: <logo@x>\nContent-Transfer-Encoding: base64\n\nJVBERg==\n\
+            --outer--\n";
+        let msg = parse(raw);
+        assert_eq!(msg.subject.as_deref(), Some("Café"));
+        assert_eq!(msg.from_addr.as_deref(), Some("jose@example.com"));
+        assert_eq!(msg.to, ["a@x.org", "b@x.org"]);
+        assert_eq!(msg.cc, ["c@x.org"]);
+        assert_eq!(msg.timestamp, Some(1_704_103_200));
+        assert_eq!(msg.content_type, "multipart/mixed");
+        assert_eq!(msg.text_bodies.len(), 1);
+        assert_eq!(msg.text_bodies[0].trim_end(), "café");
+        assert_eq!(msg.html_bodies, ["<p>caf&eacute;</p>"]);
+        let att = &msg.attachments[0];
+        assert_eq!(att.filename.as_deref(), Some("report.pdf"));
+        assert_eq!(att.content_type, "application/pdf");
+        assert_eq!(att.content_id.as_deref(), Some("logo@x"));
+        assert_eq!((att.size, att.part_index), (4, 2));
+        assert_eq!(att.data, b"%PDF");
+        assert_eq!(msg.header("from").as_deref(), Some("José <Jose@Example.com>"));
+    }
+
+    #[test]
+    fn test_parse_plain() {
+        let msg = parse(b"Subject: hi\n\nbody\n");
+        assert_eq!(msg.content_type, "text/plain");
+        assert_eq!(msg.text_bodies, ["body\n"]);
+        assert!(msg.attachments.is_empty() && msg.date.is_none());
+    }
+}
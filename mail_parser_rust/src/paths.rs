@@ -0,0 +1,214 @@
+//! Path handling shared by every file operation.
+//!
+//! Windows refuses paths longer than 260 characters unless they use the
+//! `\\?\` (or `\\?\UNC\` for shares) prefix, and such verbatim paths are not
+//! normalized by the OS: `/`, `.` and `..` are taken literally and trailing
+//! dots and spaces are kept. [`extended`] therefore resolves and normalizes a
+//! path itself before adding the prefix, so deep exports work and name the
+//! same files a non-verbatim path would. On other platforms it returns the
+//! path unchanged.
+//!
+//! [`sanitize_component`] makes one name (a subject, an attachment filename)
+//! safe as a file or directory name on every platform, including Windows
+//! device names such as `CON` or `nul.txt`.
+
+use crate::patterns::compile;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Longest file or directory name most filesystems accept, in bytes
+pub const MAX_COMPONENT_BYTES: usize = 255;
+
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9", "conin$",
+    "conout$",
+];
+
+lazy_static! {
+    /// Characters not allowed in a file name on Windows (or anywhere, for `/`)
+    pub static ref INVALID_CHARS: Regex =
+        compile("core.invalid_filename_chars", r#"[<>:"/\\|?*\x00-\x1f]"#);
+}
+
+/// Make `name` usable as a single path component on every platform
+///
+/// Invalid characters become `_`, surrounding whitespace and dots are
+/// trimmed, device names get a `_` prefix and the result is cut to
+/// [`MAX_COMPONENT_BYTES`] on a character boundary.
+pub fn sanitize_component(name: &str) -> String {
+    let replaced = INVALID_CHARS.replace_all(name, "_");
+    let mut out = replaced
+        .trim_matches(|c: char| c.is_whitespace() || c == '.')
+        .to_string();
+    let stem = out.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.contains(&stem.to_ascii_lowercase().as_str()) {
+        out.insert(0, '_');
+    }
+    if out.len() > MAX_COMPONENT_BYTES {
+        let mut end = MAX_COMPONENT_BYTES;
+        while !out.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.truncate(end);
+        out.truncate(out.trim_end_matches([' ', '.']).len());
+    }
+    out
+}
+
+/// Join normalized Windows path components: `.` and empty parts dropped,
+/// `..` resolved without climbing above the root, trailing dots and spaces
+/// stripped as the Win32 layer would
+fn normalize_components<'a>(parts: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut out: Vec<&str> = Vec::new();
+    for part in parts {
+        match part {
+            "" | "." => {},
+            ".." => {
+                out.pop();
+            },
+            _ => {
+                let trimmed = part.trim_end_matches([' ', '.']);
+                if !trimmed.is_empty() {
+                    out.push(trimmed);
+                }
+            },
+        }
+    }
+    out
+}
+
+/// The `\\?\` form of a Windows path string, resolving relative paths
+/// against `cwd` (itself absolute)
+#[cfg_attr(not(windows), allow(dead_code))]
+fn verbatim(path: &str, cwd: &str) -> String {
+    let path = path.replace('/', "\\");
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return path;
+    }
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        let mut parts = unc.split('\\');
+        let server = parts.next().unwrap_or_default();
+        let share = parts.next().unwrap_or_default();
+        let rest = normalize_components(parts);
+        let mut out = format!(r"\\?\UNC\{server}\{share}");
+        for part in rest {
+            out.push('\\');
+            out.push_str(part);
+        }
+        return out;
+    }
+    let bytes = path.as_bytes();
+    let has_drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    if has_drive && bytes.get(2) == Some(&b'\\') {
+        let mut out = format!(r"\\?\{}", &path[..2].to_ascii_uppercase());
+        for part in normalize_components(path[3..].split('\\')) {
+            out.push('\\');
+            out.push_str(part);
+        }
+        if out.len() == 6 {
+            out.push('\\');
+        }
+        return out;
+    }
+    if has_drive {
+        // Drive-relative ("C:file") depends on per-drive state; leave as is
+        return path;
+    }
+    // Resolve against the working directory, in its non-verbatim form
+    let cwd = cwd.replace('/', "\\");
+    let cwd = cwd.strip_prefix(r"\\?\UNC\").map_or_else(
+        || cwd.strip_prefix(r"\\?\").unwrap_or(&cwd).to_string(),
+        |unc| format!(r"\\{unc}"),
+    );
+    if !path.starts_with('\\') {
+        return verbatim(&format!("{cwd}\\{path}"), r"C:\");
+    }
+    // Root of the current drive (or share)
+    let root = cwd.strip_prefix(r"\\").map_or_else(
+        || cwd.chars().take(2).collect(),
+        |unc| {
+            let end = unc.match_indices('\\').nth(1).map_or(unc.len(), |(i, _)| i);
+            format!(r"\\{}", &unc[..end])
+        },
+    );
+    verbatim(&format!("{root}{path}"), r"C:\")
+}
+
+/// The form of `path` to hand to the OS: on Windows, an absolute `\\?\` path
+/// that is not subject to the 260-character limit; elsewhere, `path` itself
+pub fn extended(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        if let (Some(text), Ok(cwd)) = (path.to_str(), std::env::current_dir()) {
+            if let Some(cwd) = cwd.to_str() {
+                return PathBuf::from(verbatim(text, cwd));
+            }
+        }
+    }
+    path.to_path_buf()
+}
+
+/// `std::fs::create_dir_all` through [`extended`]
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(extended(path))
+}
+
+/// The path form this library uses for file operations
+///
+/// On Windows, relative paths are resolved, `/`, `.` and `..` normalized and
+/// the `\\?\` (or `\\?\UNC\`) long-path prefix added, lifting the
+/// 260-character limit. On other platforms the path is returned unchanged.
+///
+/// # Arguments
+/// * `path` - Any file or directory path
+///
+/// # Returns
+/// * The path as passed to the operating system
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import extended_path
+/// extended_path(r"\\nas\mail\exports\2024")  # r"\\?\UNC\nas\mail\exports\2024" on Windows
+/// ```
+#[pyfunction]
+pub fn extended_path(path: &str) -> String {
+    extended(Path::new(path)).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_component() {
+        assert_eq!(sanitize_component("a<b>:c?.txt"), "a_b__c_.txt");
+        assert_eq!(sanitize_component("  ..report.pdf.. "), "report.pdf");
+        assert_eq!(sanitize_component("CON"), "_CON");
+        assert_eq!(sanitize_component("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_component("console"), "console");
+        let long = "é".repeat(200);
+        let cut = sanitize_component(&long);
+        assert!(cut.len() <= MAX_COMPONENT_BYTES && cut.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let cwd = r"C:\Users\me";
+        assert_eq!(verbatim(r"D:\exports\..\mail\a.mbox", cwd), r"\\?\D:\mail\a.mbox");
+        assert_eq!(verbatim("c:/x/./y/", cwd), r"\\?\C:\x\y");
+        assert_eq!(verbatim("out/Re hi. ", cwd), r"\\?\C:\Users\me\out\Re hi");
+        assert_eq!(verbatim(r"\\nas\share\a\..\b", cwd), r"\\?\UNC\nas\share\b");
+        assert_eq!(verbatim(r"\\?\C:\already", cwd), r"\\?\C:\already");
+        assert_eq!(verbatim(r"\top", cwd), r"\\?\C:\top");
+        assert_eq!(verbatim(r"\top", r"\\nas\share\dir"), r"\\?\UNC\nas\share\top");
+        assert_eq!(verbatim(r"C:\", cwd), r"\\?\C:\");
+        assert_eq!(verbatim("C:rel", cwd), "C:rel");
+        #[cfg(not(windows))]
+        assert_eq!(extended(Path::new("a/b")), PathBuf::from("a/b"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Built-in regular expressions: compilation tracking and warm-up.
+//!
+//! Every built-in pattern is a lazy static compiled through [`compile`] on
+//! first use, which records it here. [`warmup`] forces all of them (and the
+//! other lazily built state) up front, so a long-running service pays the
+//! compilation cost at startup rather than inside its first request.
+
+use crate::tokens::cached_vocab;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// Compiled built-in patterns: name to source
+    static ref LOADED: Mutex<BTreeMap<&'static str, String>> = Mutex::new(BTreeMap::new());
+}
+
+fn record(name: &'static str, pattern: &str) {
+    if let Ok(mut loaded) = LOADED.lock() {
+        loaded.insert(name, pattern.to_string());
+    }
+}
+
+/// Compile a built-in pattern and record it under `name`
+///
+/// # Panics
+/// If `pattern` is not a valid regex (built-in patterns are fixed strings)
+pub fn compile(name: &'static str, pattern: &str) -> regex::Regex {
+    let regex = regex::Regex::new(pattern)
+        .unwrap_or_else(|e| panic!("invalid built-in pattern {name}: {e}"));
+    record(name, pattern);
+    regex
+}
+
+/// [`compile`] for patterns matched against raw bytes
+///
+/// # Panics
+/// If `pattern` is not a valid regex (built-in patterns are fixed strings)
+pub fn compile_bytes(name: &'static str, pattern: &str) -> regex::bytes::Regex {
+    let regex = regex::bytes::Regex::new(pattern)
+        .unwrap_or_else(|e| panic!("invalid built-in pattern {name}: {e}"));
+    record(name, pattern);
+    regex
+}
+
+/// Force every lazily initialized static
+fn initialize_all() {
+    use lazy_static::initialize;
+    initialize(&crate::FROM_PATTERN);
+    initialize(&crate::EMAIL_PATTERN);
+    initialize(&crate::URL_PATTERN);
+    initialize(&crate::paths::INVALID_CHARS);
+    initialize(&crate::alignment::PROPERTY);
+    initialize(&crate::alignment::DKIM_DOMAIN);
+    initialize(&crate::dlp::CARD_CANDIDATE);
+    initialize(&crate::dlp::IBAN_CANDIDATE);
+    initialize(&crate::extract::HASHTAG_PATTERN);
+    initialize(&crate::extract::TICKET_PATTERN);
+    initialize(&crate::extract::INVOICE_PATTERN);
+    initialize(&crate::extract::REGISTRY);
+    initialize(&crate::folders::NUMBER_RUN);
+    initialize(&crate::geoip::IPV4_PATTERN);
+    initialize(&crate::geoip::IPV6_PATTERN);
+    initialize(&crate::geoip::LOOKUP);
+    initialize(&crate::mbox::STRICT_FROM_LINE);
+    initialize(&crate::mbox::PLAUSIBLE_FROM_LINE);
+    initialize(&crate::mime::ENCODED_WORD);
+    initialize(&crate::quarantine::AUTH_FAILURE);
+    initialize(&crate::questions::REQUEST);
+    initialize(&crate::questions::NO_REPLY);
+    initialize(&crate::quotes::ATTRIBUTION);
+    initialize(&crate::quotes::ORIGINAL_MESSAGE);
+    initialize(&crate::senders::AUTH_RESULT);
+    initialize(&crate::senders::SPAM_SCORE);
+    initialize(&crate::sentiment::VALENCES);
+    initialize(&crate::sentiment::EMOTION_WORDS);
+    initialize(&crate::terms::STOPWORDS);
+    initialize(&crate::thread::MESSAGE_ID);
+    initialize(&crate::thread::REPLY_PREFIX);
+    initialize(&crate::tokens::O200K_WORD);
+    initialize(&crate::tokens::VOCABS);
+    initialize(&crate::trackers::TRACKER_DOMAINS);
+}
+
+/// Compile all built-in patterns and load vocabularies ahead of first use
+///
+/// Patterns are otherwise compiled the first time a function needs them.
+/// Call this once at service startup (after `register_extractor`, so the
+/// default token extractor is built with the registered patterns); later
+/// calls are cheap no-ops.
+///
+/// # Arguments
+/// * `vocab_paths` - tiktoken vocabulary files to load into the cache used by
+///   `estimate_tokens_batch(vocab_path=...)` and `prepare_for_embedding`
+///
+/// # Returns
+/// * Number of built-in patterns compiled
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import warmup, loaded_patterns
+/// warmup(vocab_paths=["cl100k_base.tiktoken"])
+/// print(sorted(loaded_patterns()))
+/// ```
+#[pyfunction]
+#[pyo3(signature = (vocab_paths=None))]
+pub fn warmup(py: Python<'_>, vocab_paths: Option<Vec<String>>) -> PyResult<usize> {
+    py.allow_threads(|| {
+        initialize_all();
+        crate::extract::default_extractor()?;
+        for path in vocab_paths.unwrap_or_default() {
+            cached_vocab(&path)?;
+        }
+        Ok(loaded_patterns().len())
+    })
+}
+
+/// Built-in patterns compiled so far
+///
+/// # Returns
+/// * Dict of pattern name (e.g. `"extract.ticket"`) to regex source
+#[pyfunction]
+pub fn loaded_patterns() -> BTreeMap<String, String> {
+    LOADED.lock().map_or_else(
+        |_| BTreeMap::new(),
+        |loaded| {
+            loaded
+                .iter()
+                .map(|(name, pattern)| ((*name).to_string(), pattern.clone()))
+                .collect()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warmup() {
+        crate::with_py(|py| {
+            let count = warmup(py, None).unwrap();
+            let loaded = loaded_patterns();
+            assert_eq!(count, loaded.len());
+            assert_eq!(count, 27);
+            assert_eq!(loaded["thread.message_id"], r"<([^<>\s]+)>");
+            assert!(loaded.contains_key("mbox.strict_from_line"));
+            assert!(warmup(py, Some(vec!["/nonexistent/vocab".to_string()])).is_err());
+        });
+    }
+}
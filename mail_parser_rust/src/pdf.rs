@@ -0,0 +1,139 @@
+//! Embedded-text extraction from PDF attachments (no OCR).
+//!
+//! Only text drawn with fonts is recovered; scanned pages yield nothing.
+//! Built with the `pdf` feature, which pulls in `pdf-extract`.
+
+use crate::embedding::normalize_whitespace;
+use crate::mime::{Message, Part};
+use pyo3::prelude::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Whether an attachment is a PDF, by MIME type, extension or magic bytes
+fn is_pdf(part: &Part<'_>, data: &[u8]) -> bool {
+    part.mime_type() == "application/pdf"
+        || part
+            .filename()
+            .is_some_and(|name| name.to_ascii_lowercase().ends_with(".pdf"))
+        || data.starts_with(b"%PDF-")
+}
+
+/// Text of a PDF, or `None` when it is malformed, encrypted or has no text layer
+pub fn pdf_text(data: &[u8]) -> Option<String> {
+    // pdf-extract panics on some malformed font tables; treat that as unreadable
+    let text = catch_unwind(AssertUnwindSafe(|| pdf_extract::extract_text_from_mem(data)))
+        .ok()?
+        .ok()?;
+    let text = normalize_whitespace(&text);
+    (!text.is_empty()).then_some(text)
+}
+
+/// Text of every PDF attachment in a message, one block per attachment
+pub fn attachment_text(raw: &[u8]) -> String {
+    let msg = Message::parse(raw);
+    msg.parts()
+        .iter()
+        .filter(|p| p.is_attachment())
+        .filter_map(|p| {
+            let data = p.decoded();
+            if is_pdf(p, &data) {
+                pdf_text(&data)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Extract the embedded text of a PDF
+///
+/// # Arguments
+/// * `data` - PDF file bytes
+///
+/// # Returns
+/// * Whitespace-normalized text, or None for malformed/encrypted PDFs and
+///   PDFs without a text layer (scans)
+#[pyfunction]
+pub fn pdf_text_fast(py: Python<'_>, data: &[u8]) -> Option<String> {
+    py.allow_threads(|| pdf_text(data))
+}
+
+/// Searchable text of all PDF attachments in a raw message
+///
+/// # Arguments
+/// * `raw_bytes` - Complete RFC 822 message
+///
+/// # Returns
+/// * Text of each readable PDF attachment, separated by a blank line
+///   (empty string when there are none)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import attachment_pdf_text
+/// doc["attachment_text"] = attachment_pdf_text(raw)
+/// ```
+#[pyfunction]
+pub fn attachment_pdf_text(py: Python<'_>, raw_bytes: &[u8]) -> String {
+    py.allow_threads(|| attachment_text(raw_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    /// Single-page PDF showing `text` in Helvetica, with a valid xref table
+    fn sample_pdf(text: &str) -> Vec<u8> {
+        let content = format!("BT /F1 12 Tf 72 720 Td ({text}) Tj ET");
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+             /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>"
+                .to_string(),
+            format!("<< /Length {} >>\nstream\n{content}\nendstream", content.len()),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, body) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{body}\nendobj\n", i + 1).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    fn test_pdf_text() {
+        assert_eq!(pdf_text(&sample_pdf("Quarterly report")).as_deref(), Some("Quarterly report"));
+        assert_eq!(pdf_text(b"%PDF-1.4 truncated"), None);
+    }
+
+    #[test]
+    fn test_attachment_text() {
+        let pdf = base64::engine::general_purpose::STANDARD.encode(sample_pdf("Signed contract"));
+        let raw = format!(
+            "Subject: docs\nContent-Type: multipart/mixed; boundary=\"b\"\n\n\
+             --b\nContent-Type: text/plain\n\nsee attached\n\
+             --b\nContent-Type: application/octet-stream\n\
+             Content-Disposition: attachment; filename=\"contract.PDF\"\n\
+             Content-Transfer-Encoding: base64\n\n{pdf}\n--b--\n"
+        );
+        assert_eq!(attachment_text(raw.as_bytes()), "Signed contract");
+        assert_eq!(attachment_text(b"Subject: none\n\nbody"), "");
+    }
+}
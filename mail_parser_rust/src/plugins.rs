@@ -0,0 +1,468 @@
+//! Per-message extractors contributed by other Rust crates.
+//!
+//! The regex extractors of [`crate::extract`] cover token patterns; anything
+//! that needs real code (a parser for a vendor's order e-mails, a classifier)
+//! implements [`Extractor`] instead and registers it once per process. From
+//! then on it runs inside the same parallel pass as the built-in work:
+//! `run_native_extractors` over a whole mbox, and `plugin:<name>` fields of an
+//! `ArchiveSet` schema while sources are added.
+//!
+//! Crates linked into the same library call [`register`] directly. A crate
+//! shipped as its own Python extension module cannot share Rust statics with
+//! this one, so it exports a [`NativeExtractor`] table (a C ABI that does not
+//! depend on either side's compiler version) in a `PyCapsule` named
+//! [`CAPSULE_NAME`] and the application passes that capsule to
+//! `register_native_extractor`.
+
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::Message;
+use crate::progress::Progress;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+use rayon::prelude::*;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::{Arc, RwLock};
+
+/// Capsule name a [`NativeExtractor`] table must be exported under
+pub const CAPSULE_NAME: &str = "mail_parser_rust.extractor";
+
+/// [`NativeExtractor`] layout version this build understands
+pub const NATIVE_ABI_VERSION: u32 = 1;
+
+lazy_static! {
+    /// Registered extractors in registration order
+    static ref EXTRACTORS: RwLock<Vec<Arc<dyn Extractor>>> = RwLock::new(Vec::new());
+}
+
+/// One message as extractors see it
+///
+/// The header block is parsed once and shared by every extractor; the text
+/// body is decoded on first use.
+pub struct MessageView<'a> {
+    raw: &'a [u8],
+    message: &'a Message<'a>,
+    text: OnceCell<String>,
+}
+
+impl<'a> MessageView<'a> {
+    /// View of `raw`, already parsed as `message`
+    pub const fn new(raw: &'a [u8], message: &'a Message<'a>) -> Self {
+        Self {
+            raw,
+            message,
+            text: OnceCell::new(),
+        }
+    }
+
+    /// The message exactly as stored, mbox `From ` line included
+    pub const fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Body bytes after the header block, transfer encoding not undone
+    pub const fn body(&self) -> &'a [u8] {
+        self.message.body
+    }
+
+    /// First header named `name` (case-insensitive), RFC 2047-decoded
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.message.decoded_header(name)
+    }
+
+    /// Every header field as (name, unfolded raw value), in order
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.message
+            .headers
+            .iter()
+            .map(|h| (h.name.as_str(), h.value.as_str()))
+    }
+
+    /// Readable body text: the first text/plain part, else flattened HTML
+    pub fn text(&self) -> &str {
+        self.text.get_or_init(|| self.message.text_body())
+    }
+}
+
+/// A per-message extractor
+///
+/// Implementations are called from many threads at once.
+pub trait Extractor: Send + Sync {
+    /// Result key; registering a second extractor under a name replaces the
+    /// first
+    fn name(&self) -> &str;
+
+    /// Values found in one message
+    fn extract(&self, message: &MessageView<'_>) -> Vec<String>;
+}
+
+/// Add `extractor` for the rest of the process, replacing one of the same
+/// name; returns the number of registered extractors
+pub fn register(extractor: Arc<dyn Extractor>) -> usize {
+    let Ok(mut extractors) = EXTRACTORS.write() else {
+        return 0;
+    };
+    match extractors.iter_mut().find(|e| e.name() == extractor.name()) {
+        Some(slot) => *slot = extractor,
+        None => extractors.push(extractor),
+    }
+    extractors.len()
+}
+
+/// Remove the extractor called `name`, returning whether one was registered
+pub fn unregister(name: &str) -> bool {
+    let Ok(mut extractors) = EXTRACTORS.write() else {
+        return false;
+    };
+    let before = extractors.len();
+    extractors.retain(|e| e.name() != name);
+    extractors.len() < before
+}
+
+/// The registered extractor called `name`
+pub fn find(name: &str) -> Option<Arc<dyn Extractor>> {
+    EXTRACTORS
+        .read()
+        .ok()?
+        .iter()
+        .find(|e| e.name() == name)
+        .cloned()
+}
+
+/// The extractors called `names` (every registered one when `None`)
+fn select(names: Option<Vec<String>>) -> PyResult<Vec<Arc<dyn Extractor>>> {
+    let Some(names) = names else {
+        return Ok(EXTRACTORS.read().map(|e| e.clone()).unwrap_or_default());
+    };
+    names
+        .iter()
+        .map(|name| {
+            find(name).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown native extractor: {name:?}"
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Callback a [`NativeExtractor`] reports each value through
+pub type EmitFn = unsafe extern "C" fn(sink: *mut c_void, value: *const u8, len: usize);
+
+/// C-ABI extractor table exported by a separately compiled extension
+///
+/// The table, its `name` and whatever `state` points to must stay valid
+/// while the capsule holding the table is alive; `extract` is called from
+/// many threads at once and must not unwind.
+#[repr(C)]
+pub struct NativeExtractor {
+    /// Must be [`NATIVE_ABI_VERSION`]
+    pub abi_version: u32,
+    /// NUL-terminated UTF-8 result key
+    pub name: *const c_char,
+    /// Passed back to `extract` unchanged
+    pub state: *const c_void,
+    /// Called with the raw message (mbox `From ` line included); reports
+    /// each value, UTF-8 encoded, with `emit(sink, ptr, len)`
+    pub extract: unsafe extern "C" fn(
+        state: *const c_void,
+        raw: *const u8,
+        raw_len: usize,
+        sink: *mut c_void,
+        emit: EmitFn,
+    ),
+}
+
+/// Collects one value into the `Vec<String>` behind `sink`
+#[allow(unsafe_code)]
+unsafe extern "C" fn emit_value(sink: *mut c_void, value: *const u8, len: usize) {
+    if sink.is_null() || value.is_null() {
+        return;
+    }
+    // Safety: `sink` is the vector `CapsuleExtractor::extract` passed in,
+    // and the extension promises `value` points to `len` readable bytes
+    let (values, bytes) =
+        unsafe { (&mut *sink.cast::<Vec<String>>(), std::slice::from_raw_parts(value, len)) };
+    values.push(String::from_utf8_lossy(bytes).into_owned());
+}
+
+/// A [`NativeExtractor`] kept alive by its capsule
+struct CapsuleExtractor {
+    name: String,
+    table: *const NativeExtractor,
+    _capsule: Py<PyCapsule>,
+}
+
+// Safety: the table is immutable and the extension promises thread-safe
+// `extract` calls; the capsule reference is itself Send + Sync
+#[allow(unsafe_code)]
+unsafe impl Send for CapsuleExtractor {}
+#[allow(unsafe_code)]
+unsafe impl Sync for CapsuleExtractor {}
+
+impl Extractor for CapsuleExtractor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[allow(unsafe_code)]
+    fn extract(&self, message: &MessageView<'_>) -> Vec<String> {
+        let mut values: Vec<String> = Vec::new();
+        let raw = message.raw();
+        // Safety: the capsule keeps the table valid (checked when registered)
+        unsafe {
+            let table = &*self.table;
+            (table.extract)(
+                table.state,
+                raw.as_ptr(),
+                raw.len(),
+                std::ptr::addr_of_mut!(values).cast(),
+                emit_value,
+            );
+        }
+        values
+    }
+}
+
+/// Register an extractor exported by another extension module
+///
+/// The capsule must be named `mail_parser_rust.extractor` and hold a
+/// `NativeExtractor` table (see the `plugins` module of this crate for the
+/// C layout). Registering a name again replaces the earlier extractor.
+///
+/// # Arguments
+/// * `capsule` - The extension's exported capsule
+///
+/// # Returns
+/// * Number of registered native extractors
+///
+/// # Raises
+/// * `ValueError` for a capsule of another name, ABI version or without a
+///   usable name
+///
+/// # Example
+/// ```python
+/// import acme_mail_extractors
+/// from mail_parser_rust import register_native_extractor, run_native_extractors
+/// register_native_extractor(acme_mail_extractors.order_numbers)
+/// for found in run_native_extractors("orders.mbox"):
+///     print(found["acme_order"])
+/// ```
+#[pyfunction]
+#[allow(unsafe_code)]
+pub(crate) fn register_native_extractor(capsule: &Bound<'_, PyCapsule>) -> PyResult<usize> {
+    let error = |detail: &str| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Invalid extractor capsule: {detail}"
+        ))
+    };
+    let name = capsule.name()?.and_then(|n| n.to_str().ok());
+    if name != Some(CAPSULE_NAME) {
+        return Err(error(&format!("expected name {CAPSULE_NAME:?}, got {name:?}")));
+    }
+    let table = capsule.pointer().cast::<NativeExtractor>().cast_const();
+    if table.is_null() {
+        return Err(error("null table"));
+    }
+    // Safety: a capsule under this name holds a `NativeExtractor` by contract
+    let (version, name) = unsafe { ((*table).abi_version, (*table).name) };
+    if version != NATIVE_ABI_VERSION {
+        return Err(error(&format!("ABI version {version}, expected {NATIVE_ABI_VERSION}")));
+    }
+    if name.is_null() {
+        return Err(error("null name"));
+    }
+    // Safety: non-null and NUL-terminated by contract
+    let name = unsafe { CStr::from_ptr(name) }
+        .to_str()
+        .map_err(|_| error("name is not UTF-8"))?;
+    if name.is_empty() {
+        return Err(error("empty name"));
+    }
+    Ok(register(Arc::new(CapsuleExtractor {
+        name: name.to_string(),
+        table,
+        _capsule: capsule.clone().unbind(),
+    })))
+}
+
+/// Remove a registered native extractor
+///
+/// # Returns
+/// * Whether an extractor with that name was registered
+#[pyfunction]
+pub(crate) fn unregister_native_extractor(name: &str) -> bool {
+    unregister(name)
+}
+
+/// Names of the registered native extractors, in registration order
+#[pyfunction]
+pub(crate) fn native_extractors() -> Vec<String> {
+    EXTRACTORS
+        .read()
+        .map(|e| e.iter().map(|x| x.name().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Run native extractors over every message of an mbox in one parallel pass
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `names` - Extractors to run (default: every registered one)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * One dict per message, in file order, mapping each extractor name to its
+///   values
+///
+/// # Raises
+/// * `ValueError` for an unknown extractor name
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import run_native_extractors
+/// orders = [v for found in run_native_extractors("orders.mbox", ["acme_order"])
+///           for v in found["acme_order"]]
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, names=None, from_line_mode="standard", progress=None))]
+pub(crate) fn run_native_extractors(
+    py: Python<'_>,
+    mbox_path: &str,
+    names: Option<Vec<String>>,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<HashMap<String, Vec<String>>>> {
+    py.allow_threads(|| {
+        let extractors = select(names)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let extracted = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| {
+                let raw = &data[span];
+                let message = Message::parse(raw);
+                let view = MessageView::new(raw, &message);
+                extractors
+                    .iter()
+                    .map(|e| (e.name().to_string(), e.extract(&view)))
+                    .collect()
+            })
+            .collect();
+        progress.check("run_native_extractors")?;
+        mmap.verify(&mode)?;
+        Ok(extracted)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words in the subject that are all caps
+    struct Shouting(&'static str);
+
+    impl Extractor for Shouting {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn extract(&self, message: &MessageView<'_>) -> Vec<String> {
+            message
+                .header("Subject")
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter(|w| w.len() > 1 && w.chars().all(|c| c.is_ascii_uppercase()))
+                .map(ToString::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_registry_and_run() {
+        crate::with_py(|py| {
+            let name = "test_shouting";
+            let before = native_extractors().len();
+            assert_eq!(register(Arc::new(Shouting(name))), before + 1);
+            assert_eq!(register(Arc::new(Shouting(name))), before + 1);
+            assert!(native_extractors().contains(&name.to_string()));
+
+            let path =
+                std::env::temp_dir().join(format!("mpr_plugins_{}.mbox", std::process::id()));
+            std::fs::write(
+                &path,
+                "From a Mon Jan  1 00:00:00 2024\nSubject: URGENT: call ME\n\nx\n\n\
+             From b Mon Jan  1 00:00:00 2024\nSubject: hello\n\ny\n",
+            )
+            .unwrap();
+            let found = run_native_extractors(
+                py,
+                path.to_str().unwrap(),
+                Some(vec![name.into()]),
+                "standard",
+                None,
+            )
+            .unwrap();
+            assert_eq!(found.len(), 2);
+            assert_eq!(found[0][name], ["ME"]);
+            assert!(found[1][name].is_empty());
+            assert!(run_native_extractors(
+                py,
+                path.to_str().unwrap(),
+                Some(vec!["nope".into()]),
+                "standard",
+                None
+            )
+            .is_err());
+
+            assert!(unregister(name));
+            assert!(!unregister(name));
+            std::fs::remove_file(path).unwrap();
+        });
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_capsule_extractor() {
+        unsafe extern "C" fn first_line(
+            _state: *const c_void,
+            raw: *const u8,
+            len: usize,
+            sink: *mut c_void,
+            emit: EmitFn,
+        ) {
+            let raw = unsafe { std::slice::from_raw_parts(raw, len) };
+            let end = raw.iter().position(|&b| b == b'\n').unwrap_or(len);
+            unsafe { emit(sink, raw.as_ptr(), end) };
+        }
+        let table = NativeExtractor {
+            abi_version: NATIVE_ABI_VERSION,
+            name: b"first_line\0".as_ptr().cast(),
+            state: std::ptr::null(),
+            extract: first_line,
+        };
+        let raw = b"From a Mon Jan  1 00:00:00 2024\nSubject: x\n\nbody\n";
+        let message = Message::parse(raw);
+        let mut values: Vec<String> = Vec::new();
+        unsafe {
+            (table.extract)(
+                table.state,
+                raw.as_ptr(),
+                raw.len(),
+                std::ptr::addr_of_mut!(values).cast(),
+                emit_value,
+            );
+        }
+        assert_eq!(values, ["From a Mon Jan  1 00:00:00 2024"]);
+        assert_eq!(MessageView::new(raw, &message).text(), "body\n");
+    }
+}
@@ -0,0 +1,198 @@
+//! Progress reporting and cancellation for long scans.
+//!
+//! Functions that walk a whole archive accept `progress`, a callable taking
+//! `(bytes_processed, total_bytes)`. It is called from the scan at most every
+//! [`INTERVAL`], plus once when the scan is done, with the GIL taken only for
+//! the call. Returning `False` cancels the scan with `ScanCancelled`; an
+//! exception raised by the callable cancels it too and propagates as it is.
+//! Like a timeout (see `deadline`), cancellation takes effect between units
+//! of work, and where results accumulate the finished ones are attached to
+//! the exception as `partial`.
+
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Shortest time between two calls of a progress callable
+pub const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Bytes a boundary scan covers between progress reports
+pub const WINDOW: usize = 16 << 20;
+
+pyo3::create_exception!(
+    mail_parser_rust,
+    ScanCancelled,
+    pyo3::exceptions::PyException,
+    "A scan was cancelled by its progress callable returning False"
+);
+
+/// Progress of one scan over `total` bytes, reported to an optional callable
+pub struct Progress {
+    callback: Option<Py<PyAny>>,
+    total: u64,
+    done: AtomicU64,
+    last: Mutex<Instant>,
+    cancelled: AtomicBool,
+    /// What the callable raised, if that cancelled the scan
+    error: Mutex<Option<PyErr>>,
+}
+
+impl Progress {
+    pub fn new(callback: Option<Py<PyAny>>, total: usize) -> Self {
+        Self {
+            callback,
+            total: u64::try_from(total).unwrap_or(u64::MAX),
+            done: AtomicU64::new(0),
+            last: Mutex::new(Instant::now()),
+            cancelled: AtomicBool::new(false),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Whether anyone is listening; scans without a callable skip the
+    /// bookkeeping
+    pub const fn reporting(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Count `bytes` more as processed, calling the callable if one is due;
+    /// false once the scan is cancelled
+    pub fn advance(&self, bytes: usize) -> bool {
+        if !self.reporting() {
+            return true;
+        }
+        let bytes = u64::try_from(bytes).unwrap_or(u64::MAX);
+        let done = self
+            .done
+            .fetch_add(bytes, Ordering::Relaxed)
+            .saturating_add(bytes);
+        // One thread reports at a time; the others carry on
+        if let Ok(mut last) = self.last.try_lock() {
+            if last.elapsed() >= INTERVAL && !self.cancelled() {
+                *last = Instant::now();
+                self.call(done.min(self.total));
+            }
+        }
+        !self.cancelled()
+    }
+
+    /// Report the scan complete; false if the callable cancels even now
+    pub fn finish(&self) -> bool {
+        if self.reporting() && !self.cancelled() {
+            let _last = self.last.lock();
+            self.call(self.total);
+        }
+        !self.cancelled()
+    }
+
+    fn call(&self, done: u64) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        Python::with_gil(|py| match callback.call1(py, (done, self.total)) {
+            Ok(value) if !matches!(value.extract::<bool>(py), Ok(false)) => {},
+            outcome => {
+                if let (Err(e), Ok(mut error)) = (outcome, self.error.lock()) {
+                    *error = Some(e);
+                }
+                self.cancelled.store(true, Ordering::Relaxed);
+            },
+        });
+    }
+
+    /// Count the message at `span` as processed before a walk handles it;
+    /// false once the walk is cancelled, so the remaining messages are
+    /// skipped
+    pub fn message(&self, span: &Range<usize>) -> bool {
+        !self.cancelled() && self.advance(span.len())
+    }
+
+    /// [`Progress::message`] for a batch of messages
+    pub fn messages<'a>(&self, spans: impl IntoIterator<Item = &'a Range<usize>>) -> bool {
+        !self.cancelled() && self.advance(spans.into_iter().map(ExactSizeIterator::len).sum())
+    }
+
+    /// Report a walk complete; the error of `operation` if it was cancelled
+    pub fn check(&self, operation: &str) -> PyResult<()> {
+        if self.finish() {
+            Ok(())
+        } else {
+            Err(self.error(operation))
+        }
+    }
+
+    /// The exception a cancelled scan raises: what the callable raised, or
+    /// `ScanCancelled`
+    pub fn error(&self, operation: &str) -> PyErr {
+        self.error
+            .lock()
+            .ok()
+            .and_then(|mut error| error.take())
+            .unwrap_or_else(|| ScanCancelled::new_err(format!("{operation} was cancelled")))
+    }
+
+    /// [`Progress::error`] with `partial` as its `partial` attribute
+    pub fn error_with<'py, T>(&self, py: Python<'py>, operation: &str, partial: T) -> PyErr
+    where
+        T: IntoPyObject<'py>,
+    {
+        let err = self.error(operation);
+        if let Ok(partial) = partial.into_bound_py_any(py) {
+            let _ = err.value(py).setattr("partial", partial);
+        }
+        err
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyList;
+
+    #[test]
+    fn test_progress() {
+        let silent = Progress::new(None, 10);
+        assert!(silent.advance(5) && silent.finish() && !silent.cancelled());
+        assert!(silent.message(&(0..5)) && silent.messages(&[0..2, 2..5]));
+        assert!(silent.check("walk").is_ok());
+
+        crate::with_py(|py| {
+            let calls = PyList::empty(py);
+            let record = py
+                .eval(c"lambda calls: lambda done, total: calls.append((done, total))", None, None)
+                .and_then(|make| make.call1((&calls,)))
+                .unwrap();
+            let progress = Progress::new(Some(record.unbind()), 10);
+            assert!(progress.advance(4));
+            assert!(progress.finish());
+            let calls: Vec<(u64, u64)> = calls.extract().unwrap();
+            assert_eq!(calls.last(), Some(&(10, 10)));
+
+            let stop = py
+                .eval(c"lambda done, total: done < 5", None, None)
+                .unwrap();
+            let progress = Progress::new(Some(stop.unbind()), 10);
+            std::thread::sleep(INTERVAL);
+            assert!(!progress.advance(6));
+            assert!(!progress.advance(1) && !progress.finish());
+            assert!(!progress.message(&(0..1)) && !progress.messages(&[0..1, 1..2]));
+            let err = progress.error_with(py, "scan", vec![1]);
+            assert!(err.is_instance_of::<ScanCancelled>(py));
+            assert_eq!(err.value(py).to_string(), "scan was cancelled");
+            let partial: Vec<i32> = err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial, [1]);
+
+            let raise = py.eval(c"lambda done, total: 1 / 0", None, None).unwrap();
+            let progress = Progress::new(Some(raise.unbind()), 10);
+            let err = progress.check("scan").unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyZeroDivisionError>(py));
+        });
+    }
+}
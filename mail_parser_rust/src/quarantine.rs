@@ -0,0 +1,500 @@
+//! Quarantine side channel: divert suspicious or malformed messages.
+//!
+//! Each message is run through cheap structural and security checks. A
+//! message that trips any of them is written to a separate quarantine mbox
+//! instead of the clean output, with one JSON Lines manifest record naming
+//! the reasons, so reviewers can triage it without re-scanning the archive.
+//! [`Quarantine`] is the shared writer; `quarantine_mbox` splits an archive
+//! and `merge_mboxes` accepts a quarantine path too.
+
+use crate::date::parse_date;
+use crate::dedup::mbox_entry;
+use crate::locking::LockSpec;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{split_header_body, strip_envelope, Message, Part};
+use crate::output::{Output, OutputFile, OutputReport};
+use crate::patterns::compile;
+use crate::progress::Progress;
+use crate::uid::message_uid;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Every check, parse checks first
+pub const CHECKS: &[&str] = &[
+    "no_headers",
+    "missing_from",
+    "missing_date",
+    "bad_date",
+    "missing_message_id",
+    "raw_8bit_headers",
+    "bad_mime_boundary",
+    "executable_attachment",
+    "double_extension",
+    "encrypted_archive",
+    "auth_failure",
+];
+
+/// Attachment extensions that run code when opened
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "scr", "com", "pif", "bat", "cmd", "js", "jse", "vbs", "vbe", "wsf", "wsh", "hta",
+    "msi", "jar", "ps1", "lnk", "reg", "cpl", "iso", "img",
+];
+
+/// Extensions a double-extension lure pretends to have
+const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "txt", "rtf", "jpg", "jpeg", "png", "gif",
+    "zip",
+];
+
+/// Batch size for the parallel checks
+const BATCH_SIZE: usize = 4096;
+
+lazy_static! {
+    /// A failed SPF, DKIM or DMARC verdict in Authentication-Results
+    pub static ref AUTH_FAILURE: Regex =
+        compile("quarantine.auth_failure", r"(?i)\b(?:spf|dkim|dmarc)\s*=\s*(?:fail|softfail|permerror)\b");
+}
+
+/// Validate a `checks=` argument; `None` selects every check
+pub fn parse_checks(checks: Option<Vec<String>>) -> PyResult<Vec<&'static str>> {
+    let Some(names) = checks else {
+        return Ok(CHECKS.to_vec());
+    };
+    names
+        .into_iter()
+        .map(|name| {
+            CHECKS.iter().copied().find(|c| *c == name).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown quarantine check: {name} (expected one of {})",
+                    CHECKS.join(", ")
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Lowercased extensions of a filename, last first
+fn extensions(name: &str) -> Vec<String> {
+    name.trim()
+        .to_ascii_lowercase()
+        .rsplit('.')
+        .take(2)
+        .map(|ext| ext.trim().to_string())
+        .collect()
+}
+
+fn has_encrypted_entry(data: &[u8]) -> bool {
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(data)) else {
+        return false;
+    };
+    (0..archive.len()).any(|i| archive.by_index_raw(i).is_ok_and(|entry| entry.encrypted()))
+}
+
+/// Whether a multipart Content-Type lacks a boundary that the body uses
+fn bad_boundary(msg: &Message) -> bool {
+    let Some(content_type) = msg
+        .header("Content-Type")
+        .map(crate::mime::parse_param_value)
+    else {
+        return false;
+    };
+    if !content_type.value.starts_with("multipart/") {
+        return false;
+    }
+    content_type
+        .param("boundary")
+        .filter(|b| !b.is_empty())
+        .map_or(true, |boundary| {
+            let delimiter = format!("--{boundary}");
+            !msg.body
+                .windows(delimiter.len())
+                .any(|w| w == delimiter.as_bytes())
+        })
+}
+
+fn attachment_reasons(part: &Part<'_>, checks: &[&str], reasons: &mut Vec<&'static str>) {
+    let mut note = |reason: &'static str| {
+        if checks.contains(&reason) && !reasons.contains(&reason) {
+            reasons.push(reason);
+        }
+    };
+    if let Some(name) = part.filename() {
+        let exts = extensions(&name);
+        if exts.len() == 2 && EXECUTABLE_EXTENSIONS.contains(&exts[0].as_str()) {
+            note("executable_attachment");
+            if DOCUMENT_EXTENSIONS.contains(&exts[1].as_str()) {
+                note("double_extension");
+            }
+        }
+    }
+    if checks.contains(&"encrypted_archive") {
+        let data = part.decoded();
+        if data.starts_with(b"PK\x03\x04") && has_encrypted_entry(&data) {
+            note("encrypted_archive");
+        }
+    }
+}
+
+/// The checks a raw message fails, in [`CHECKS`] order
+pub fn warnings(raw: &[u8], checks: &[&str]) -> Vec<&'static str> {
+    let msg = Message::parse(raw);
+    let (header_block, _) = split_header_body(strip_envelope(raw));
+    let on = |check: &str| checks.contains(&check);
+    let mut reasons = Vec::new();
+    if msg.headers.is_empty() {
+        if on("no_headers") {
+            reasons.push("no_headers");
+        }
+    } else {
+        let date = msg.header("Date");
+        for (check, failed) in [
+            ("missing_from", msg.header("From").is_none()),
+            ("missing_date", date.is_none()),
+            ("bad_date", date.is_some_and(|d| parse_date(d).is_none())),
+            ("missing_message_id", msg.header("Message-ID").is_none()),
+            ("raw_8bit_headers", header_block.iter().any(|&b| b >= 0x80)),
+            ("bad_mime_boundary", on("bad_mime_boundary") && bad_boundary(&msg)),
+        ] {
+            if failed && on(check) {
+                reasons.push(check);
+            }
+        }
+    }
+    if on("executable_attachment") || on("double_extension") || on("encrypted_archive") {
+        let mut found = Vec::new();
+        for part in msg.parts().iter().filter(|p| p.is_attachment()) {
+            attachment_reasons(part, checks, &mut found);
+        }
+        reasons.extend(found);
+    }
+    if on("auth_failure")
+        && msg
+            .headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case("Authentication-Results"))
+            .any(|h| AUTH_FAILURE.is_match(&h.value))
+    {
+        reasons.push("auth_failure");
+    }
+    reasons.sort_by_key(|r| CHECKS.iter().position(|c| c == r));
+    reasons
+}
+
+/// One line of a quarantine manifest
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestRecord {
+    /// Archive the message came from
+    pub source: String,
+    /// Position of the message in that archive (0-based)
+    pub message: usize,
+    /// Byte offset of the message in that archive
+    pub offset: usize,
+    /// Stable message UID (see `compute_message_uid`)
+    pub uid: String,
+    /// Checks the message failed
+    pub reasons: Vec<String>,
+}
+
+/// Writer for a quarantine mbox and its manifest
+pub struct Quarantine {
+    pub checks: Vec<&'static str>,
+    mbox: OutputFile,
+    manifest: OutputFile,
+    manifest_path: String,
+    pub counts: BTreeMap<String, usize>,
+    pub messages: usize,
+}
+
+impl Quarantine {
+    /// Open `<path>` and its manifest (default `<path>.manifest.jsonl`)
+    pub fn create(
+        output: &Output,
+        path: &str,
+        manifest: Option<&str>,
+        checks: Vec<&'static str>,
+    ) -> PyResult<Self> {
+        let manifest_path =
+            manifest.map_or_else(|| format!("{path}.manifest.jsonl"), ToString::to_string);
+        let mbox = output
+            .create(Path::new(path))
+            .map_err(|e| write_error(path, &e))?;
+        let manifest = output
+            .create(Path::new(&manifest_path))
+            .map_err(|e| write_error(&manifest_path, &e))?;
+        Ok(Self {
+            checks,
+            mbox,
+            manifest,
+            manifest_path,
+            counts: BTreeMap::new(),
+            messages: 0,
+        })
+    }
+
+    /// The checks `raw` fails (empty: keep the message)
+    pub fn check(&self, raw: &[u8]) -> Vec<&'static str> {
+        warnings(raw, &self.checks)
+    }
+
+    /// Write a flagged message and its manifest record
+    pub fn divert(&mut self, record: ManifestRecord, raw: &[u8]) -> PyResult<()> {
+        let line = serde_json::to_string(&record).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                "Failed to encode manifest record: {e}"
+            ))
+        })?;
+        self.mbox
+            .write_all(&mbox_entry(raw))
+            .map_err(|e| write_error("quarantine mbox", &e))?;
+        writeln!(self.manifest, "{line}").map_err(|e| write_error(&self.manifest_path, &e))?;
+        for reason in record.reasons {
+            *self.counts.entry(reason).or_default() += 1;
+        }
+        self.messages += 1;
+        Ok(())
+    }
+
+    /// Commit both files
+    pub fn finish(self) -> PyResult<(usize, BTreeMap<String, usize>)> {
+        self.mbox
+            .finish()
+            .map_err(|e| write_error("quarantine mbox", &e))?;
+        self.manifest
+            .finish()
+            .map_err(|e| write_error(&self.manifest_path, &e))?;
+        Ok((self.messages, self.counts))
+    }
+}
+
+fn write_error(path: &str, e: &io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {path}: {e}"))
+}
+
+/// Result of splitting an archive into clean and quarantined messages
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct QuarantineReport {
+    /// Messages written to the clean archive
+    #[pyo3(get)]
+    pub clean: usize,
+    /// Messages written to the quarantine archive
+    #[pyo3(get)]
+    pub quarantined: usize,
+    /// Quarantined messages per failed check
+    #[pyo3(get)]
+    pub reasons: HashMap<String, usize>,
+    /// Files and bytes written (clean archive, quarantine archive, manifest)
+    #[pyo3(get)]
+    pub output: OutputReport,
+}
+
+#[pymethods]
+impl QuarantineReport {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// Split an mbox into clean messages and a quarantine of flagged ones
+///
+/// Parse checks: `no_headers`, `missing_from`, `missing_date`, `bad_date`,
+/// `missing_message_id`, `raw_8bit_headers` (unencoded non-ASCII header
+/// bytes) and `bad_mime_boundary` (multipart without a usable boundary).
+/// Security checks: `executable_attachment`, `double_extension`
+/// (`invoice.pdf.exe`), `encrypted_archive` (password-protected zip) and
+/// `auth_failure` (SPF/DKIM/DMARC fail in Authentication-Results).
+///
+/// # Arguments
+/// * `src` - Path to the mbox file
+/// * `clean_dest` - mbox for messages that pass every check
+/// * `quarantine_dest` - mbox for flagged messages
+/// * `checks` - Checks to run (default: all)
+/// * `manifest` - Manifest path (default: `<quarantine_dest>.manifest.jsonl`)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `lock` - Mailbox locking: "none" (default), "dotlock", "fcntl", "flock"
+///   or a combination such as "dotlock+fcntl"; `src` is locked for reading and
+///   each destination for writing (see "Mailbox Locking" in the README)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `QuarantineReport` with clean/quarantined counts and counts per reason
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import quarantine_mbox
+/// report = quarantine_mbox("inbox.mbox", "clean.mbox", "suspect.mbox")
+/// print(report.quarantined, report.reasons)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    src,
+    clean_dest,
+    quarantine_dest,
+    checks=None,
+    manifest=None,
+    from_line_mode="standard",
+    dry_run=false,
+    fsync="file",
+    lock="none",
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn quarantine_mbox(
+    py: Python<'_>,
+    src: &str,
+    clean_dest: &str,
+    quarantine_dest: &str,
+    checks: Option<Vec<String>>,
+    manifest: Option<&str>,
+    from_line_mode: &str,
+    dry_run: bool,
+    fsync: &str,
+    lock: &str,
+    progress: Option<PyObject>,
+) -> PyResult<QuarantineReport> {
+    py.allow_threads(|| {
+        let checks = parse_checks(checks)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?.with_lock(LockSpec::parse(lock)?);
+        let mut clean = output
+            .create(Path::new(clean_dest))
+            .map_err(|e| write_error(clean_dest, &e))?;
+        let mut quarantine = Quarantine::create(&output, quarantine_dest, manifest, checks)?;
+        let _source_lock = output
+            .lock_source(Path::new(src), Path::new(clean_dest))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to lock {src}: {e}"))
+            })?;
+        let mmap = map_file(src)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+
+        let mut report = QuarantineReport::default();
+        for (batch_no, batch) in spans.chunks(BATCH_SIZE).enumerate() {
+            if !progress.messages(batch) {
+                break;
+            }
+            let flagged: Vec<Vec<&'static str>> = batch
+                .par_iter()
+                .map(|span| quarantine.check(&data[span.clone()]))
+                .collect();
+            for (i, (span, reasons)) in batch.iter().zip(flagged).enumerate() {
+                let raw = &data[span.clone()];
+                if reasons.is_empty() {
+                    clean
+                        .write_all(&mbox_entry(raw))
+                        .map_err(|e| write_error(clean_dest, &e))?;
+                    report.clean += 1;
+                } else {
+                    let record = ManifestRecord {
+                        source: src.to_string(),
+                        message: batch_no * BATCH_SIZE + i,
+                        offset: span.start,
+                        uid: message_uid(raw),
+                        reasons: reasons.iter().map(ToString::to_string).collect(),
+                    };
+                    quarantine.divert(record, raw)?;
+                }
+                output.note_message();
+            }
+        }
+        progress.check("quarantine_mbox")?;
+        mmap.verify(&mode)?;
+        clean.finish().map_err(|e| write_error(clean_dest, &e))?;
+        let (quarantined, counts) = quarantine.finish()?;
+        report.quarantined = quarantined;
+        report.reasons = counts.into_iter().collect();
+        report.output = output.report();
+        Ok(report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOD: &str = "From a Mon Jan  1 00:00:00 2024\nFrom: a@example.com\n\
+                        Date: Mon, 1 Jan 2024 10:00:00 +0000\nMessage-ID: <1@x>\n\nHello\n";
+
+    #[test]
+    fn test_warnings() {
+        assert!(warnings(GOOD.as_bytes(), CHECKS).is_empty());
+        assert_eq!(warnings(b"just text\n", CHECKS), ["no_headers"]);
+        assert_eq!(
+            warnings("Subject: caf\u{e9}\nDate: someday\n\nx".as_bytes(), CHECKS),
+            [
+                "missing_from",
+                "bad_date",
+                "missing_message_id",
+                "raw_8bit_headers"
+            ]
+        );
+        let lure = format!(
+            "{}Content-Type: multipart/mixed; boundary=b\n\n--b\n\nbody\n--b\n\
+             Content-Type: application/octet-stream; name=\"invoice.pdf.exe\"\n\nMZ\n--b--\n",
+            GOOD.replace("\n\nHello\n", "\n")
+        );
+        assert_eq!(
+            warnings(lure.as_bytes(), CHECKS),
+            ["executable_attachment", "double_extension"]
+        );
+        let auth = GOOD.replace(
+            "Message-ID",
+            "Authentication-Results: mx; spf=pass; dkim=fail header.d=x\nMessage-ID",
+        );
+        assert_eq!(warnings(auth.as_bytes(), CHECKS), ["auth_failure"]);
+        assert!(warnings(auth.as_bytes(), &["missing_from"]).is_empty());
+        let broken = GOOD.replace("\n\nHello", "\nContent-Type: multipart/mixed\n\nHello");
+        assert_eq!(warnings(broken.as_bytes(), CHECKS), ["bad_mime_boundary"]);
+        assert!(parse_checks(Some(vec!["nope".into()])).is_err());
+    }
+
+    #[test]
+    fn test_quarantine_mbox() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_quarantine_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            let bad = "From b Mon Jan  1 00:00:00 2024\nSubject: no sender\n\nx\n";
+            std::fs::write(&src, format!("{GOOD}\n{bad}\n{GOOD}")).unwrap();
+            let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+            let report = quarantine_mbox(
+                py,
+                &path("in.mbox"),
+                &path("clean.mbox"),
+                &path("q.mbox"),
+                None,
+                None,
+                "standard",
+                false,
+                "none",
+                "none",
+                None,
+            )
+            .unwrap();
+            assert_eq!((report.clean, report.quarantined), (2, 1));
+            assert_eq!(report.reasons["missing_from"], 1);
+            assert_eq!(std::fs::read_to_string(dir.join("q.mbox")).unwrap(), format!("{bad}\n"));
+            let manifest = std::fs::read_to_string(dir.join("q.mbox.manifest.jsonl")).unwrap();
+            let record: serde_json::Value = serde_json::from_str(manifest.trim()).unwrap();
+            assert_eq!(record["message"], 1);
+            assert_eq!(record["offset"], GOOD.len() + 1);
+            assert_eq!(record["reasons"][0], "missing_from");
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
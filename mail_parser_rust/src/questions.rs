@@ -0,0 +1,147 @@
+//! Heuristic detection of direct questions and requests (reply-needed triage).
+//!
+//! Only the author's own text is considered: quoted replies, attributions and
+//! signatures are removed with [`strip_quotes`] first, so a question that was
+//! merely quoted back does not flag the reply.
+
+use crate::batch::par_map;
+use crate::patterns::compile;
+use crate::quotes::strip_quotes;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    /// Phrases that ask the reader to do or answer something
+    pub static ref REQUEST: Regex = compile(
+        "questions.request",
+        r"(?i)\b(could|can|would|will) you\b|\bplease (let|send|confirm|advise|review|check|reply|respond|call|get|share|update|approve|sign|provide|forward|see)\b|\blet (me|us) know\b|\bget back to (me|us)\b|\bkindly\b|\b(awaiting|await) your\b|\blooking forward to (hearing|your)\b|\bany (update|thoughts|news|feedback)\b|\b(need|want) your (input|approval|feedback|answer|response|sign-?off)\b|\bby (eod|cob|end of (the )?day|tomorrow|monday|tuesday|wednesday|thursday|friday)\b"
+    );
+    /// The author says no answer is expected
+    pub static ref NO_REPLY: Regex = compile(
+        "questions.no_reply",
+        r"(?i)\bno (need to (reply|respond)|(reply|response|action) (is )?(needed|necessary|required))\b"
+    );
+}
+
+/// Question and request signals found in one body
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct QuestionSignals {
+    /// True when the author asked something and did not say no reply is needed
+    #[pyo3(get)]
+    pub needs_reply: bool,
+    /// Sentences ending in `?`, in order
+    #[pyo3(get)]
+    pub questions: Vec<String>,
+    /// Other sentences containing a request phrase ("please confirm", ...)
+    #[pyo3(get)]
+    pub requests: Vec<String>,
+}
+
+/// Split text into sentences; paragraphs are joined onto one line first, and
+/// a terminator only ends a sentence when followed by whitespace (so `?` in a
+/// URL does not)
+fn sentences(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let joined = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut start = 0;
+        let mut chars = joined.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let at_break = chars.peek().map_or(true, |&(_, next)| next == ' ');
+            if matches!(c, '.' | '!' | '?') && at_break {
+                out.push(joined[start..=i].trim().to_string());
+                start = i + 1;
+            }
+        }
+        let rest = joined[start..].trim();
+        if !rest.is_empty() {
+            out.push(rest.to_string());
+        }
+    }
+    out.retain(|s| s.chars().any(char::is_alphabetic));
+    out
+}
+
+fn detect(body: &str) -> QuestionSignals {
+    let text = strip_quotes(body);
+    let mut signals = QuestionSignals::default();
+    for sentence in sentences(&text) {
+        if sentence.ends_with('?') {
+            signals.questions.push(sentence);
+        } else if REQUEST.is_match(&sentence) {
+            signals.requests.push(sentence);
+        }
+    }
+    let asked = !signals.questions.is_empty() || !signals.requests.is_empty();
+    signals.needs_reply = asked && !NO_REPLY.is_match(&text);
+    signals
+}
+
+/// Flag direct questions and request phrases in a message body
+///
+/// Quoted replies and signatures are ignored.
+///
+/// # Arguments
+/// * `body` - Plain-text message body
+///
+/// # Returns
+/// * `QuestionSignals` with `needs_reply`, `questions` and `requests`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import detect_questions_fast
+/// signals = detect_questions_fast("Hi,\n\nCan you send the deck by Friday?\n")
+/// print(signals.needs_reply, signals.questions)
+/// ```
+#[pyfunction]
+pub fn detect_questions_fast(body: &str) -> QuestionSignals {
+    detect(body)
+}
+
+/// Batch version of [`detect_questions_fast`], processed in parallel
+///
+/// # Arguments
+/// * `bodies` - Plain-text message bodies
+///
+/// # Returns
+/// * One `QuestionSignals` per body
+#[pyfunction]
+pub fn detect_questions_batch(py: Python<'_>, bodies: Vec<String>) -> Vec<QuestionSignals> {
+    py.allow_threads(|| par_map(bodies, |body| detect(&body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let body = "Hi Ann,\n\nThe report is attached. Could you review it\nby Friday. \
+                    See https://x.org/r?id=1 for details. Does that work for you?\n\n\
+                    Thanks!\n\nOn Mon, 1 Jan 2024, Ann wrote:\n> Where is the report?\n\
+                    -- \nBob\nWhat's up?\n";
+        let signals = detect(body);
+        assert!(signals.needs_reply);
+        assert_eq!(signals.questions, ["Does that work for you?"]);
+        assert_eq!(signals.requests, ["Could you review it by Friday."]);
+
+        let fyi = detect("FYI, the build is green.\n\n> Is the build green?\n");
+        assert!(!fyi.needs_reply);
+        assert!(fyi.questions.is_empty() && fyi.requests.is_empty());
+
+        let told = detect("Can you believe it? Great result. No need to reply.\n");
+        assert_eq!(told.questions, ["Can you believe it?"]);
+        assert!(!told.needs_reply);
+    }
+
+    #[test]
+    fn test_sentences() {
+        assert_eq!(
+            sentences("One. Two!\nstill two? ...\n\nThree"),
+            ["One.", "Two!", "still two?", "Three"]
+        );
+    }
+}
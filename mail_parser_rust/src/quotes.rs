@@ -0,0 +1,93 @@
+//! Removal of quoted replies and signatures from plain-text bodies.
+
+use crate::patterns::compile;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    /// "On Mon, 1 Jan 2024, Alice <a@x> wrote:" and common translations
+    pub static ref ATTRIBUTION: Regex = compile(
+        "quotes.attribution",
+        r"(?i)^\s*(on\b.*\b(wrote|writes)|am\b.*\bschrieb|le\b.*\ba écrit|el\b.*\bescribió)\s*:?\s*$"
+    );
+    /// Outlook-style separators that introduce the quoted original
+    pub static ref ORIGINAL_MESSAGE: Regex = compile(
+        "quotes.original_message",
+        r"(?i)^\s*(-{2,}\s*(original message|forwarded message)\s*-{2,}|_{10,})\s*$"
+    );
+}
+
+/// Strip `>` quoted lines, reply attributions, quoted originals and signatures
+///
+/// Everything after an Outlook `-----Original Message-----` separator or a
+/// `-- ` signature delimiter is dropped.
+pub fn strip_quotes(text: &str) -> String {
+    let mut kept: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        if line == "-- " || line == "--" || ORIGINAL_MESSAGE.is_match(line) {
+            break;
+        }
+        if line.trim_start().starts_with('>') {
+            continue;
+        }
+        kept.push(line);
+    }
+    let mut out = String::new();
+    let mut blank_run = 0;
+    for line in kept {
+        if ATTRIBUTION.is_match(line) {
+            continue;
+        }
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run > 1 || out.is_empty() {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.trim_end().to_string()
+}
+
+/// The `-- ` signature block of a body, without quoted lines, if it has one
+pub fn signature_block(text: &str) -> Option<String> {
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .skip_while(|&line| line != "-- " && line != "--");
+    lines.next()?;
+    let block: Vec<&str> = lines
+        .take_while(|line| !ORIGINAL_MESSAGE.is_match(line) && !ATTRIBUTION.is_match(line))
+        .map(str::trim_end)
+        .collect();
+    let block = block.join("\n").trim().to_string();
+    (!block.is_empty()).then_some(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_quotes() {
+        let body = "Sounds good.\n\n\nSee you then.\n\nOn Mon, 1 Jan 2024, Alice <a@x> wrote:\n\
+> Lunch at noon?\n> \n\n-- \nBob\nACME Corp\n";
+        assert_eq!(strip_quotes(body), "Sounds good.\n\nSee you then.");
+
+        let outlook = "Approved.\n\n-----Original Message-----\nFrom: Alice\nPlease approve.";
+        assert_eq!(strip_quotes(outlook), "Approved.");
+
+        let inline = "> question one\nanswer one\n> question two\nanswer two";
+        assert_eq!(strip_quotes(inline), "answer one\nanswer two");
+    }
+
+    #[test]
+    fn test_signature_block() {
+        let body = "Thanks.\n\n-- \nBob Jones\nACME Corp\n\nOn Mon, Alice wrote:\n> -- \n> Alice";
+        assert_eq!(signature_block(body).unwrap(), "Bob Jones\nACME Corp");
+        assert_eq!(signature_block("no signature here"), None);
+    }
+}
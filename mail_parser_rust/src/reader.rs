@@ -0,0 +1,371 @@
+//! Lazy, memory-mapped iteration over the messages of an mbox.
+//!
+//! The file is mapped once and shared by the reader and every message it
+//! yields; each step scans only as far as the next separator, so iteration
+//! starts immediately on archives of any size and nothing is copied until a
+//! message's bytes or headers are asked for. `Recover` mode needs lookahead
+//! and finds all boundaries when the reader is created.
+//!
+//! Compressed archives are decompressed as a stream from the same mapping,
+//! and each message is copied out as it is split off.
+
+use crate::compressed::MessageStream;
+use crate::mbox::{
+    first_start, map_file, message_spans_with, next_start_after, FromLineMode, MappedFile,
+};
+use crate::mime::{decode_encoded_words, split_header_body, strip_envelope, Message};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// How the reader finds the next message
+enum Cursor {
+    /// Start of the next message, scanned for on demand
+    Scan(Option<usize>),
+    /// Boundaries found up front (`Recover` mode), and the next to return
+    Spans(Vec<Range<usize>>, usize),
+    /// Messages of a compressed archive, decompressed as they are read
+    Stream(Box<MessageStream<'static>>),
+}
+
+/// Where a message's bytes live
+enum Source {
+    /// The span of the message in the shared file mapping
+    Mapped(Arc<MappedFile>),
+    /// The message decompressed from a compressed archive
+    Owned(Vec<u8>),
+}
+
+/// Iterate the messages of an mbox file without loading it
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import MboxReader
+/// for msg in MboxReader("archive.mbox"):
+///     print(msg.index, msg.start, msg.header("Subject"))
+///     if msg.header("From") == "boss@example.com":
+///         data = msg.raw()
+/// ```
+#[pyclass]
+pub struct MboxReader {
+    mmap: Arc<MappedFile>,
+    mode: FromLineMode,
+    cursor: Cursor,
+    index: usize,
+}
+
+impl MboxReader {
+    /// Next message span and where its bytes live, advancing the cursor
+    fn next_message(&mut self) -> PyResult<Option<(Range<usize>, Source)>> {
+        let mapped = |span| Some((span, Source::Mapped(Arc::clone(&self.mmap))));
+        Ok(match &mut self.cursor {
+            Cursor::Scan(next) => {
+                let Some(start) = *next else {
+                    return Ok(None);
+                };
+                let following = next_start_after(&self.mmap, start, &self.mode);
+                *next = following;
+                mapped(start..following.unwrap_or(self.mmap.len()))
+            },
+            Cursor::Spans(spans, position) => {
+                let Some(span) = spans.get(*position).cloned() else {
+                    return Ok(None);
+                };
+                *position += 1;
+                mapped(span)
+            },
+            Cursor::Stream(stream) => stream
+                .next_message()?
+                .map(|(span, bytes)| (span, Source::Owned(bytes))),
+        })
+    }
+
+    fn start_cursor(mmap: &Arc<MappedFile>, mode: &FromLineMode) -> PyResult<Cursor> {
+        Ok(match MessageStream::shared(mmap, mode)? {
+            Some(stream) => Cursor::Stream(Box::new(stream)),
+            None if matches!(mode, FromLineMode::Recover) => {
+                Cursor::Spans(message_spans_with(mmap, mode), 0)
+            },
+            None => Cursor::Scan(first_start(mmap, mode)),
+        })
+    }
+}
+
+#[pymethods]
+impl MboxReader {
+    /// Open an mbox file for iteration
+    ///
+    /// # Arguments
+    /// * `path` - Path to the mbox file
+    /// * `from_line_mode` - Message separator rule: "standard" (default),
+    ///   "strict", "lenient", "permissive", "recover" or a custom regex (see
+    ///   `count_messages_fast`)
+    ///
+    /// Gzip, zstd and xz compressed archives are read transparently, with
+    /// offsets into the decompressed data ("recover" is not available).
+    ///
+    /// # Raises
+    /// * `IOError` if the file cannot be opened or its compressed data is
+    ///   corrupt, `ValueError` for a bad mode
+    /// * `FileChangedError` from iteration if the file is truncated,
+    ///   replaced or grows while it is read
+    #[new]
+    #[pyo3(signature = (path, from_line_mode="standard"))]
+    fn new(path: &str, from_line_mode: &str) -> PyResult<Self> {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = Arc::new(map_file(path)?);
+        Ok(Self {
+            cursor: Self::start_cursor(&mmap, &mode)?,
+            mmap,
+            mode,
+            index: 0,
+        })
+    }
+
+    const fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<MboxMessage>> {
+        let next = self.next_message();
+        // A file cut short reads as corrupt compressed data; report the change
+        if next.is_err() || self.mmap.faulted() {
+            self.mmap.verify(&self.mode)?;
+        }
+        let Some((span, source)) = next? else {
+            self.mmap.verify(&self.mode)?;
+            return Ok(None);
+        };
+        let message = MboxMessage {
+            source,
+            index: self.index,
+            span,
+        };
+        self.index += 1;
+        Ok(Some(message))
+    }
+
+    /// Start over from the first message
+    fn reset(&mut self) -> PyResult<()> {
+        self.cursor = Self::start_cursor(&self.mmap, &self.mode)?;
+        self.index = 0;
+        Ok(())
+    }
+
+    /// File size in bytes (compressed size for a compressed archive)
+    #[getter]
+    fn size(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// Byte offset the next message starts at (the file size when done;
+    /// decompressed offset and size for a compressed archive)
+    #[getter]
+    fn position(&self) -> usize {
+        match &self.cursor {
+            Cursor::Scan(next) => next.unwrap_or(self.mmap.len()),
+            Cursor::Spans(spans, i) => spans.get(*i).map_or(self.mmap.len(), |s| s.start),
+            Cursor::Stream(stream) => stream.position(),
+        }
+    }
+}
+
+/// One message of an `MboxReader`, backed by the shared file mapping (or
+/// its own decompressed copy)
+#[pyclass]
+pub struct MboxMessage {
+    source: Source,
+    span: Range<usize>,
+    index: usize,
+}
+
+impl MboxMessage {
+    fn bytes(&self) -> &[u8] {
+        match &self.source {
+            Source::Mapped(mmap) => &mmap[self.span.clone()],
+            Source::Owned(bytes) => bytes,
+        }
+    }
+}
+
+#[pymethods]
+impl MboxMessage {
+    /// Position of the message in the file (0-based)
+    #[getter]
+    const fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Byte offset of the message's `From ` line (in the decompressed data
+    /// for a compressed archive)
+    #[getter]
+    const fn start(&self) -> usize {
+        self.span.start
+    }
+
+    /// Byte offset just past the message
+    #[getter]
+    const fn end(&self) -> usize {
+        self.span.end
+    }
+
+    /// The message exactly as stored, `From ` line included
+    fn raw<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.bytes())
+    }
+
+    /// The `From ` separator line, without its newline
+    #[getter]
+    fn separator(&self) -> String {
+        let data = self.bytes();
+        let end = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+        String::from_utf8_lossy(&data[..end]).trim_end().to_string()
+    }
+
+    /// Header fields in order, unfolded and RFC 2047-decoded
+    #[getter]
+    fn headers(&self) -> Vec<(String, String)> {
+        Message::parse(self.bytes())
+            .headers
+            .into_iter()
+            .map(|h| {
+                let value = decode_encoded_words(&h.value);
+                (h.name, value)
+            })
+            .collect()
+    }
+
+    /// First header named `name` (case-insensitive), decoded
+    fn header(&self, name: &str) -> Option<String> {
+        Message::parse(self.bytes()).decoded_header(name)
+    }
+
+    /// Body bytes after the header block, transfer encoding not undone
+    fn body<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let (_, body) = split_header_body(strip_envelope(self.bytes()));
+        PyBytes::new(py, body)
+    }
+
+    /// Readable text body (first text part, decoded; HTML converted)
+    fn text(&self) -> String {
+        Message::parse(self.bytes()).text_body()
+    }
+
+    fn __len__(&self) -> usize {
+        self.span.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "MboxMessage(index={}, start={}, end={})",
+            self.index, self.span.start, self.span.end
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(content: &str, mode: &str) -> (MboxReader, std::path::PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("mpr_reader_{mode}_{}.mbox", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        (MboxReader::new(path.to_str().unwrap(), mode).unwrap(), path)
+    }
+
+    #[test]
+    fn test_reader() {
+        let content =
+            "preamble\n\nFrom a Mon Jan  1 00:00:00 2024\nSubject: =?utf-8?q?caf=C3=A9?=\n\n\
+                       body\nFrom me\n\nFrom b Mon Jan  1 00:00:00 2024\nSubject: two\n\nlast\n";
+        let (mut reader, path) = reader(content, "standard");
+        let mut spans = Vec::new();
+        while let Some(msg) = reader.__next__().unwrap() {
+            spans.push((msg.index, msg.span.clone()));
+            if msg.index == 0 {
+                assert_eq!(msg.separator(), "From a Mon Jan  1 00:00:00 2024");
+                assert_eq!(msg.header("subject").as_deref(), Some("café"));
+                assert_eq!(msg.headers(), [("Subject".to_string(), "café".to_string())]);
+                assert_eq!(msg.text().trim_end(), "body\nFrom me");
+            }
+        }
+        let expected = message_spans_with(content.as_bytes(), &FromLineMode::Standard);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].1, expected[0]);
+        assert_eq!(spans[1], (1, expected[1].clone()));
+        assert_eq!(reader.position(), content.len());
+        reader.reset().unwrap();
+        assert_eq!(reader.position(), 10);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reader_recover() {
+        let content = "tail\n\nFrom a Mon Jan  1 00:00:00 2024\nSubject: 1\n\nx\n";
+        let (mut reader, path) = reader(content, "recover");
+        assert_eq!(reader.__next__().unwrap().map(|m| m.span), Some(0..6));
+        assert_eq!(reader.__next__().unwrap().map(|m| m.index), Some(1));
+        assert!(reader.__next__().unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reader_compressed() {
+        use crate::compressed::{compress, Codec};
+        let content = "From a Mon Jan  1 00:00:00 2024\nSubject: 1\n\nx\n\n\
+                       From b Mon Jan  1 00:00:00 2024\nSubject: 2\n\ny\n";
+        let path = std::env::temp_dir().join(format!("mpr_reader_{}.mbox.zst", std::process::id()));
+        std::fs::write(&path, compress(content.as_bytes(), Codec::Zstd)).unwrap();
+        let path_str = path.to_str().unwrap();
+        let mut reader = MboxReader::new(path_str, "standard").unwrap();
+        let mut messages = Vec::new();
+        while let Some(msg) = reader.__next__().unwrap() {
+            assert_eq!(msg.bytes(), &content.as_bytes()[msg.span.clone()]);
+            messages.push((msg.span.clone(), msg.header("Subject").unwrap()));
+        }
+        let expected = message_spans_with(content.as_bytes(), &FromLineMode::Standard);
+        assert_eq!(
+            messages,
+            [
+                (expected[0].clone(), "1".into()),
+                (expected[1].clone(), "2".into())
+            ]
+        );
+        assert_eq!(reader.position(), content.len());
+        reader.reset().unwrap();
+        assert_eq!(reader.__next__().unwrap().map(|m| m.index), Some(0));
+        assert!(MboxReader::new(path_str, "recover").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reader_file_changed() {
+        let body = "line of body text\n".repeat(500);
+        let message = format!("From a Mon Jan  1 00:00:00 2024\nSubject: x\n\n{body}\n");
+        let (mut reader, path) = reader(&message.repeat(3), "strict");
+        let second = message.len();
+        assert!(reader.__next__().unwrap().is_some());
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len((second + 4000) as u64)
+            .unwrap();
+        let err = loop {
+            match reader.__next__() {
+                Ok(Some(_)) => {},
+                Ok(None) => panic!("truncation went unnoticed"),
+                Err(err) => break err,
+            }
+        };
+        crate::with_py(|py| assert!(err.is_instance_of::<crate::mbox::FileChangedError>(py)));
+        assert!(
+            err.to_string()
+                .contains(&format!("messages before byte {second} ")),
+            "{err}"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+//! `Received` trace header parsing with per-hop delivery delays.
+//!
+//! Each MTA prepends `from <host> (<comment>) by <host> with <protocol>
+//! id <id> for <address>; <date>`, with any clause missing, comments
+//! anywhere and free-form text inside them. Values are split into words and
+//! (nested) comments, clauses start at their keyword outside comments, and
+//! the date is whatever follows the last `;`. The sending IP is the first
+//! address in the `from` clause, usually the `[1.2.3.4]` the receiving MTA
+//! recorded in its comment.
+
+use crate::batch::par_map;
+use crate::date::parse_date;
+use crate::geoip::find_ips;
+use crate::mime::Message;
+use pyo3::prelude::*;
+use serde::Serialize;
+
+/// Clause keywords, RFC 5321 section 4.4
+const KEYWORDS: &[&str] = &["from", "by", "via", "with", "id", "for"];
+
+/// One relay hop of a message's trace
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ReceivedHop {
+    /// Name the sender gave (HELO/EHLO), or the address literal
+    #[pyo3(get)]
+    pub from_host: Option<String>,
+    /// Sending IP address, from the `from` clause
+    #[pyo3(get)]
+    pub from_ip: Option<String>,
+    /// Receiving host
+    #[pyo3(get)]
+    pub by_host: Option<String>,
+    /// `with` protocol, e.g. "ESMTPS"
+    #[pyo3(get)]
+    pub protocol: Option<String>,
+    /// Queue ID the receiving host assigned
+    #[pyo3(get)]
+    pub id: Option<String>,
+    /// Envelope recipient of the `for` clause
+    #[pyo3(get)]
+    pub for_address: Option<String>,
+    /// When the hop received the message (Unix timestamp)
+    #[pyo3(get)]
+    pub timestamp: Option<i64>,
+    /// UTC offset of the hop's date, in minutes
+    #[pyo3(get)]
+    pub utc_offset: Option<i32>,
+    /// Seconds since the previous hop received it; negative when the clocks
+    /// of the two hosts disagree
+    #[pyo3(get)]
+    pub delay_secs: Option<i64>,
+    /// Header value as written (unfolded)
+    #[pyo3(get)]
+    pub raw: String,
+}
+
+#[pymethods]
+impl ReceivedHop {
+    fn __repr__(&self) -> String {
+        format!(
+            "ReceivedHop(from={:?}, ip={:?}, by={:?}, delay_secs={:?})",
+            self.from_host.as_deref().unwrap_or_default(),
+            self.from_ip.as_deref().unwrap_or_default(),
+            self.by_host.as_deref().unwrap_or_default(),
+            self.delay_secs
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    /// Contents of a parenthesized comment, nested ones included
+    Comment(&'a str),
+}
+
+/// Split a clause list into words and comments
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => i += 1,
+            b'(' => {
+                let start = i + 1;
+                let mut depth = 0;
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => i += 1,
+                        b'(' => depth += 1,
+                        b')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        },
+                        _ => {},
+                    }
+                    i += 1;
+                }
+                // An unclosed comment runs to the end
+                let end = i.min(bytes.len());
+                tokens.push(Token::Comment(&text[start..end]));
+                i = end + 1;
+            },
+            _ => {
+                let start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'(' {
+                    i += 1;
+                }
+                tokens.push(Token::Word(&text[start..i]));
+            },
+        }
+    }
+    tokens
+}
+
+/// Parse one `Received` value (delay not yet known)
+fn parse_hop(value: &str) -> ReceivedHop {
+    let (clauses, date) = value
+        .rsplit_once(';')
+        .map_or((value, None), |(clauses, date)| (clauses, Some(date)));
+    let mut found: Vec<(&str, Vec<Token>)> = Vec::new();
+    for token in tokenize(clauses) {
+        let keyword = match token {
+            Token::Word(word) => KEYWORDS.iter().find(|k| k.eq_ignore_ascii_case(word)),
+            Token::Comment(_) => None,
+        };
+        match (keyword, found.last_mut()) {
+            (Some(keyword), _) => found.push((keyword, Vec::new())),
+            (None, Some((_, clause))) => clause.push(token),
+            (None, None) => {},
+        }
+    }
+    let clause = |keyword: &str| {
+        found
+            .iter()
+            .find(|(k, _)| *k == keyword)
+            .map(|(_, tokens)| tokens.as_slice())
+    };
+    let first_word = |keyword: &str| {
+        clause(keyword)?.iter().find_map(|token| match token {
+            Token::Word(word) => Some((*word).to_string()),
+            Token::Comment(_) => None,
+        })
+    };
+    let from_ip = clause("from").and_then(|tokens| {
+        tokens.iter().find_map(|token| {
+            let (Token::Word(text) | Token::Comment(text)) = token;
+            find_ips(text).first().map(ToString::to_string)
+        })
+    });
+    let parsed_date = date.and_then(parse_date);
+    ReceivedHop {
+        from_host: first_word("from").map(|host| host.trim_matches(['[', ']']).to_string()),
+        from_ip,
+        by_host: first_word("by"),
+        protocol: first_word("with"),
+        id: first_word("id"),
+        for_address: first_word("for").map(|addr| addr.trim_matches(['<', '>']).to_string()),
+        timestamp: parsed_date.map(|(epoch, _)| epoch),
+        utc_offset: parsed_date.map(|(_, offset)| offset),
+        delay_secs: None,
+        raw: value.to_string(),
+    }
+}
+
+/// Hops of a header block, first relay first, with delays filled in
+pub fn chain(headers: &[u8]) -> Vec<ReceivedHop> {
+    let msg = Message::parse(headers);
+    // Each relay prepends its field, so the oldest is last
+    let mut hops: Vec<ReceivedHop> = msg
+        .headers
+        .iter()
+        .rev()
+        .filter(|header| header.name.eq_ignore_ascii_case("Received"))
+        .map(|header| parse_hop(&header.value))
+        .collect();
+    for i in 1..hops.len() {
+        if let (Some(previous), Some(current)) = (hops[i - 1].timestamp, hops[i].timestamp) {
+            hops[i].delay_secs = Some(current - previous);
+        }
+    }
+    hops
+}
+
+/// Parse a message's `Received` chain into hops with delivery delays
+///
+/// # Arguments
+/// * `headers` - Header block (or whole raw message)
+///
+/// # Returns
+/// * `ReceivedHop` list in delivery order (the first relay first, the
+///   final mailbox host last), each with `from_host`, `from_ip`, `by_host`,
+///   `protocol`, `id`, `for_address`, `timestamp`, `utc_offset`,
+///   `delay_secs` since the previous hop and the `raw` value; clauses a hop
+///   left out are None
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import parse_received_chain_fast
+/// hops = parse_received_chain_fast(header_block)
+/// slowest = max(hops, key=lambda h: h.delay_secs or 0)
+/// print(f"{slowest.by_host} took {slowest.delay_secs}s")
+/// ```
+#[pyfunction]
+pub fn parse_received_chain_fast(headers: &str) -> Vec<ReceivedHop> {
+    chain(headers.as_bytes())
+}
+
+/// Batch version of [`parse_received_chain_fast`], processed in parallel
+///
+/// # Arguments
+/// * `header_blocks` - Header blocks (or whole raw messages)
+///
+/// # Returns
+/// * One hop list per block
+#[pyfunction]
+pub fn parse_received_chain_batch(
+    py: Python<'_>,
+    header_blocks: Vec<String>,
+) -> Vec<Vec<ReceivedHop>> {
+    py.allow_threads(|| par_map(header_blocks, |block| chain(block.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hop() {
+        let hop = parse_hop(
+            "from mail.sender.com (mail.sender.com [203.0.113.5] (may be forged)) \
+             by mx.example.org (Postfix) with ESMTPS id 4AbC12 \
+             for <ann@example.org>; Mon, 1 Jan 2024 12:00:05 +0100 (CET)",
+        );
+        assert_eq!(hop.from_host.as_deref(), Some("mail.sender.com"));
+        assert_eq!(hop.from_ip.as_deref(), Some("203.0.113.5"));
+        assert_eq!(hop.by_host.as_deref(), Some("mx.example.org"));
+        assert_eq!(hop.protocol.as_deref(), Some("ESMTPS"));
+        assert_eq!(hop.id.as_deref(), Some("4AbC12"));
+        assert_eq!(hop.for_address.as_deref(), Some("ann@example.org"));
+        assert_eq!(hop.timestamp, Some(1_704_106_805));
+        assert_eq!(hop.utc_offset, Some(60));
+
+        // Keywords inside comments, address literals, IPv6, no date
+        let hop = parse_hop("from [2001:db8::1] (helo from by) by relay with LMTP");
+        assert_eq!(hop.from_host.as_deref(), Some("2001:db8::1"));
+        assert_eq!(hop.from_ip.as_deref(), Some("2001:db8::1"));
+        assert_eq!(hop.by_host.as_deref(), Some("relay"));
+        assert_eq!(hop.protocol.as_deref(), Some("LMTP"));
+        assert_eq!(hop.timestamp, None);
+
+        let local = parse_hop("by localhost (unclosed (comment; Tue, 2 Jan 2024");
+        assert_eq!((local.from_host, local.by_host.as_deref()), (None, Some("localhost")));
+    }
+
+    #[test]
+    fn test_chain() {
+        let headers = "Received: from relay.example.org (relay [10.0.0.2])\r\n\
+                       \tby mx.example.org with ESMTP; Mon, 1 Jan 2024 12:00:30 +0000\r\n\
+                       Subject: hi\r\n\
+                       received: from laptop ([192.0.2.7]) by relay.example.org\r\n\
+                       \twith ESMTPSA; Mon, 1 Jan 2024 13:00:00 +0100\r\n\r\nbody\r\n";
+        let hops = parse_received_chain_fast(headers);
+        assert_eq!(hops.len(), 2);
+        assert_eq!(hops[0].from_ip.as_deref(), Some("192.0.2.7"));
+        assert_eq!(hops[0].delay_secs, None);
+        assert_eq!(hops[1].from_ip.as_deref(), Some("10.0.0.2"));
+        assert_eq!(hops[1].delay_secs, Some(30));
+
+        crate::with_py(|py| {
+            let chains = parse_received_chain_batch(py, vec![headers.into(), String::new()]);
+            assert_eq!((chains[0].len(), chains[1].len()), (2, 0));
+        });
+    }
+}
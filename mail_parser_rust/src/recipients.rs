@@ -0,0 +1,177 @@
+//! Per-recipient fan-out: one row per (message, recipient) pair.
+//!
+//! Rows are returned column-wise (a dict of equal-length lists) so they load
+//! straight into a pandas frame without building a Python object per row.
+
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{address_list, first_address, Message};
+use crate::progress::Progress;
+use crate::uid::message_uid;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rayon::prelude::*;
+use std::collections::HashSet;
+
+/// Recipient headers and the role reported for them
+const ROLES: &[(&str, &str)] = &[("To", "to"), ("Cc", "cc"), ("Bcc", "bcc")];
+
+/// One (message, recipient) row
+struct Row {
+    role: &'static str,
+    address: String,
+    name: Option<String>,
+}
+
+/// Recipients of one message, without repeats of the same (role, address)
+fn recipients(msg: &Message) -> Vec<Row> {
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    for (header, role) in ROLES {
+        for (name, address) in msg
+            .decoded_header(header)
+            .map(|v| address_list(&v))
+            .unwrap_or_default()
+        {
+            if seen.insert((*role, address.clone())) {
+                rows.push(Row {
+                    role,
+                    address,
+                    name,
+                });
+            }
+        }
+    }
+    rows
+}
+
+/// Columns of the exploded table
+#[derive(Default)]
+struct Columns {
+    message: Vec<usize>,
+    uid: Vec<String>,
+    sender: Vec<Option<String>>,
+    role: Vec<&'static str>,
+    address: Vec<String>,
+    name: Vec<Option<String>>,
+}
+
+fn explode(raw_messages: &[&[u8]], progress: &Progress) -> Columns {
+    let per_message: Vec<(String, Option<String>, Vec<Row>)> = raw_messages
+        .par_iter()
+        .filter(|raw| progress.advance(raw.len()))
+        .map(|raw| {
+            let msg = Message::parse(raw);
+            let sender = msg.header("From").and_then(first_address);
+            (message_uid(raw), sender, recipients(&msg))
+        })
+        .collect();
+    let mut columns = Columns::default();
+    for (i, (uid, sender, rows)) in per_message.into_iter().enumerate() {
+        for row in rows {
+            columns.message.push(i);
+            columns.uid.push(uid.clone());
+            columns.sender.push(sender.clone());
+            columns.role.push(row.role);
+            columns.address.push(row.address);
+            columns.name.push(row.name);
+        }
+    }
+    columns
+}
+
+/// Expand every message into one row per recipient, in columnar form
+///
+/// Recipients come from the `To`, `Cc` and `Bcc` headers (Bcc is only
+/// present on the sender's own copy); a recipient listed twice under the same
+/// role yields one row. Addresses are lowercased, display names decoded.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * Dict of equal-length lists: `message` (position in the archive), `uid`
+///   (see `compute_message_uid`), `sender` (From address or None), `role`
+///   ("to", "cc" or "bcc"), `address` and `name` (display name or None)
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// import pandas as pd
+/// from mail_parser_rust import explode_recipients
+/// df = pd.DataFrame(explode_recipients("sent.mbox"))
+/// print(df.groupby("address").size().nlargest(10))
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, from_line_mode="standard", progress=None))]
+pub fn explode_recipients<'py>(
+    py: Python<'py>,
+    mbox_path: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let columns = py.allow_threads(|| -> PyResult<_> {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let raw_messages: Vec<&[u8]> = mmap
+            .spans(&mode)
+            .into_iter()
+            .map(|span| &data[span])
+            .collect();
+        let columns = explode(&raw_messages, &progress);
+        progress.check("explode_recipients")?;
+        mmap.verify(&mode)?;
+        Ok(columns)
+    })?;
+    let dict = PyDict::new(py);
+    dict.set_item("message", columns.message)?;
+    dict.set_item("uid", columns.uid)?;
+    dict.set_item("sender", columns.sender)?;
+    dict.set_item("role", columns.role)?;
+    dict.set_item("address", columns.address)?;
+    dict.set_item("name", columns.name)?;
+    Ok(dict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explode() {
+        let first = b"From a Mon Jan  1 00:00:00 2024\nFrom: Me <me@example.com>\n\
+                      To: \"Doe, Jane\" <Jane@Example.com>, bob@example.com\n\
+                      Cc: =?utf-8?q?Ren=C3=A9?= <rene@example.com>, bob@example.com\n\
+                      Bcc: jane@example.com\nSubject: hi\n\nBody\n";
+        let second = b"From b Mon Jan  1 00:00:00 2024\nFrom: x@example.com\n\nNo recipients\n";
+        let third = b"From c Mon Jan  1 00:00:00 2024\nTo: bob@example.com, bob@example.com\n\n.\n";
+        let columns = explode(&[first, second, third], &Progress::new(None, 0));
+        assert_eq!(columns.message, [0, 0, 0, 0, 0, 2]);
+        assert_eq!(columns.role, ["to", "to", "cc", "cc", "bcc", "to"]);
+        assert_eq!(
+            columns.address,
+            [
+                "jane@example.com",
+                "bob@example.com",
+                "rene@example.com",
+                "bob@example.com",
+                "jane@example.com",
+                "bob@example.com",
+            ]
+        );
+        assert_eq!(columns.name[0].as_deref(), Some("Doe, Jane"));
+        assert_eq!(columns.name[2].as_deref(), Some("René"));
+        assert_eq!(columns.name[1], None);
+        assert_eq!(columns.sender[0].as_deref(), Some("me@example.com"));
+        assert_eq!(columns.sender[5], None);
+        assert_eq!(columns.uid[0], message_uid(first));
+    }
+}
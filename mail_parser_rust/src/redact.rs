@@ -0,0 +1,541 @@
+//! Archive redaction with a machine-readable audit log.
+//!
+//! Each message is rewritten to its redacted headers plus a single UTF-8
+//! text body (HTML flattened, attachments removed), so no sensitive value
+//! can survive inside an encoded part. Every change is recorded in a JSON
+//! Lines audit log with SHA-256 hashes of the content before and after, which
+//! lets a reviewer verify the production against the original archive
+//! without seeing the redacted values.
+
+use crate::dlp::detect_spans;
+use crate::geoip::find_ip_spans;
+use crate::locking::LockSpec;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{decode_encoded_words, fold_header, Message};
+use crate::output::{Output, OutputReport};
+use crate::progress::Progress;
+use crate::uid::message_uid;
+use crate::{EMAIL_PATTERN, URL_PATTERN};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::ops::Range;
+use std::path::Path;
+
+/// Built-in rules, applied in this order where matches overlap
+pub const BUILTIN_RULES: &[&str] = &["card", "iban", "email", "ip", "url"];
+
+/// Rules used when the caller does not pick any
+const DEFAULT_RULES: &[&str] = &["card", "iban", "email", "ip"];
+
+/// Headers redacted when the caller does not pick any; IDs such as
+/// `Message-ID` are left alone so threading survives
+const DEFAULT_HEADERS: &[&str] = &[
+    "From",
+    "To",
+    "Cc",
+    "Bcc",
+    "Reply-To",
+    "Sender",
+    "Return-Path",
+    "Delivered-To",
+    "Subject",
+    "Received",
+    "X-Originating-IP",
+];
+
+/// Headers replaced by the rewritten single-part body
+const MIME_HEADERS: &[&str] = &["MIME-Version", "Content-Type", "Content-Transfer-Encoding"];
+
+/// Messages processed in parallel before their output is written
+const BATCH_SIZE: usize = 4096;
+
+enum Matcher {
+    Card,
+    Iban,
+    Email,
+    Ip,
+    Url,
+    Pattern(Regex),
+}
+
+/// An ordered set of named redaction rules
+pub struct Redactor {
+    rules: Vec<(String, Matcher)>,
+}
+
+impl Redactor {
+    /// Built-in `kinds` (the defaults when `None`) followed by named patterns
+    pub fn new(kinds: Option<Vec<String>>, patterns: &[(String, String)]) -> PyResult<Self> {
+        let value_error = |msg: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(msg);
+        let kinds =
+            kinds.unwrap_or_else(|| DEFAULT_RULES.iter().map(ToString::to_string).collect());
+        let mut rules = Vec::new();
+        for kind in kinds {
+            let matcher = match kind.as_str() {
+                "card" => Matcher::Card,
+                "iban" => Matcher::Iban,
+                "email" => Matcher::Email,
+                "ip" => Matcher::Ip,
+                "url" => Matcher::Url,
+                _ => {
+                    return Err(value_error(format!(
+                        "Unknown redaction rule: {kind:?} (expected one of {BUILTIN_RULES:?})"
+                    )))
+                },
+            };
+            rules.push((kind, matcher));
+        }
+        for (name, pattern) in patterns {
+            if rules.iter().any(|(existing, _)| existing == name) {
+                return Err(value_error(format!("Duplicate redaction rule: {name:?}")));
+            }
+            let regex = Regex::new(pattern)
+                .map_err(|e| value_error(format!("Invalid pattern for {name:?}: {e}")))?;
+            rules.push((name.clone(), Matcher::Pattern(regex)));
+        }
+        Ok(Self { rules })
+    }
+
+    fn matches(matcher: &Matcher, text: &str) -> Vec<Range<usize>> {
+        match matcher {
+            Matcher::Card => detect_spans(text, true, false)
+                .into_iter()
+                .map(|(start, end, _)| start..end)
+                .collect(),
+            Matcher::Iban => detect_spans(text, false, true)
+                .into_iter()
+                .map(|(start, end, _)| start..end)
+                .collect(),
+            Matcher::Email => EMAIL_PATTERN.find_iter(text).map(|m| m.range()).collect(),
+            Matcher::Ip => find_ip_spans(text)
+                .into_iter()
+                .map(|(span, _)| span)
+                .collect(),
+            Matcher::Url => URL_PATTERN.find_iter(text).map(|m| m.range()).collect(),
+            Matcher::Pattern(regex) => regex.find_iter(text).map(|m| m.range()).collect(),
+        }
+    }
+
+    /// Redacted text and the number of replacements per rule (rules that
+    /// matched nothing are omitted)
+    pub fn redact(&self, text: &str) -> (String, Vec<(&str, usize)>) {
+        let mut spans: Vec<(Range<usize>, usize)> = Vec::new();
+        for (index, (_, matcher)) in self.rules.iter().enumerate() {
+            for span in Self::matches(matcher, text) {
+                // Earlier rules keep the text they claimed
+                if !span.is_empty()
+                    && !spans
+                        .iter()
+                        .any(|(s, _)| s.start < span.end && span.start < s.end)
+                {
+                    spans.push((span, index));
+                }
+            }
+        }
+        spans.sort_by_key(|(span, _)| span.start);
+
+        let mut out = String::with_capacity(text.len());
+        let mut counts = vec![0; self.rules.len()];
+        let mut last = 0;
+        for (span, index) in spans {
+            out.push_str(&text[last..span.start]);
+            let _ = write!(out, "[REDACTED:{}]", self.rules[index].0);
+            counts[index] += 1;
+            last = span.end;
+        }
+        out.push_str(&text[last..]);
+        let counts = self
+            .rules
+            .iter()
+            .zip(counts)
+            .filter(|(_, n)| *n > 0)
+            .map(|((name, _), n)| (name.as_str(), n))
+            .collect();
+        (out, counts)
+    }
+}
+
+/// One line of the audit log
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Position of the message in the source archive (0-based)
+    pub message: usize,
+    /// Byte offset of the message in the source archive
+    pub offset: usize,
+    /// Stable message UID of the original message (see `compute_message_uid`)
+    pub uid: String,
+    /// Header name, "envelope", "body", "attachment", or "message" for the
+    /// per-message summary
+    pub field: String,
+    /// Rule that fired; "removed" for attachments, null on the summary line
+    pub rule: Option<String>,
+    pub replacements: usize,
+    pub sha256_before: String,
+    pub sha256_after: String,
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Rewrite one message; returns the new bytes and its audit records
+fn redact_message(
+    redactor: &Redactor,
+    headers: &[String],
+    raw: &[u8],
+    message: usize,
+    offset: usize,
+) -> (Vec<u8>, Vec<AuditRecord>) {
+    let mut records = Vec::new();
+    let uid = message_uid(raw);
+    let mut note = |field: &str, rule: Option<&str>, n: usize, before: &[u8], after: &[u8]| {
+        records.push(AuditRecord {
+            message,
+            offset,
+            uid: uid.clone(),
+            field: field.to_string(),
+            rule: rule.map(ToString::to_string),
+            replacements: n,
+            sha256_before: sha256_hex(before),
+            sha256_after: sha256_hex(after),
+        });
+    };
+    let mut redact_field = |field: &str, value: &str| -> String {
+        let (redacted, counts) = redactor.redact(value);
+        for (rule, n) in counts {
+            note(field, Some(rule), n, value.as_bytes(), redacted.as_bytes());
+        }
+        redacted
+    };
+
+    let mut out = String::with_capacity(raw.len());
+    if raw.starts_with(b"From ") {
+        let line = raw.split(|&b| b == b'\n').next().unwrap_or_default();
+        let line = String::from_utf8_lossy(line);
+        out.push_str(&redact_field("envelope", line.trim_end()));
+        out.push('\n');
+    }
+
+    let msg = Message::parse(raw);
+    for header in &msg.headers {
+        if MIME_HEADERS
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(&header.name))
+        {
+            continue;
+        }
+        let value = if headers.iter().any(|h| h.eq_ignore_ascii_case(&header.name)) {
+            let decoded = decode_encoded_words(&header.value);
+            let redacted = redact_field(&header.name, &decoded);
+            if redacted == decoded {
+                header.value.clone()
+            } else {
+                redacted
+            }
+        } else {
+            header.value.clone()
+        };
+        out.push_str(&fold_header(&header.name, &value));
+        out.push('\n');
+    }
+    out.push_str("MIME-Version: 1.0\nContent-Type: text/plain; charset=utf-8\n");
+    out.push_str("Content-Transfer-Encoding: 8bit\n\n");
+
+    let body = redact_field("body", &msg.text_body());
+    for line in body.lines() {
+        // mboxrd quoting keeps body lines from starting a new message
+        if line.trim_start_matches('>').starts_with("From ") {
+            out.push('>');
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push('\n');
+
+    for part in msg.parts().iter().filter(|p| p.is_attachment()) {
+        note("attachment", Some("removed"), 1, &part.decoded(), b"");
+    }
+
+    let total = records.iter().map(|r| r.replacements).sum();
+    let rewritten = out.into_bytes();
+    records.push(AuditRecord {
+        message,
+        offset,
+        uid,
+        field: "message".to_string(),
+        rule: None,
+        replacements: total,
+        sha256_before: sha256_hex(raw),
+        sha256_after: sha256_hex(&rewritten),
+    });
+    (rewritten, records)
+}
+
+/// Write a redacted copy of an mbox archive plus a JSON Lines audit log
+///
+/// Configured headers are redacted in place; the body is replaced by its
+/// redacted plain text (HTML flattened) and attachments are removed, so no
+/// value survives inside an encoded MIME part. Matches become
+/// `[REDACTED:<rule>]`.
+///
+/// The audit log has one line per field and rule that fired (`message`,
+/// `offset`, `uid`, `field`, `rule`, `replacements`, `sha256_before`,
+/// `sha256_after`), one line per removed attachment (hash of its decoded
+/// content), and one `"field": "message"` summary line for every message,
+/// hashing the original and rewritten message bytes.
+///
+/// # Arguments
+/// * `src` - Path to the source mbox file
+/// * `dest` - Path of the redacted mbox (`.gz`/`.zst` compress on the fly)
+/// * `kinds` - Built-in rules: "card", "iban", "email", "ip", "url"
+///   (default: all but "url")
+/// * `patterns` - Extra named regex rules, applied after the built-ins
+/// * `headers` - Headers to redact (default: address, subject and routing headers)
+/// * `audit_log` - Audit log path (default: `<dest>.audit.jsonl`)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `lock` - Mailbox locking: "none" (default), "dotlock", "fcntl", "flock"
+///   or a combination such as "dotlock+fcntl"; `src` is locked for reading and
+///   each destination for writing (see "Mailbox Locking" in the README)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` covering the redacted archive and the audit log
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import redact_mbox
+/// report = redact_mbox("custodian.mbox", "production.mbox",
+///                      patterns={"case": r"CASE-\d{6}"})
+/// print(f"{report.message_count} messages redacted")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    src,
+    dest,
+    kinds=None,
+    patterns=None,
+    headers=None,
+    audit_log=None,
+    dry_run=false,
+    fsync="file",
+    from_line_mode="standard",
+    lock="none",
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn redact_mbox(
+    py: Python<'_>,
+    src: &str,
+    dest: &str,
+    kinds: Option<Vec<String>>,
+    patterns: Option<HashMap<String, String>>,
+    headers: Option<Vec<String>>,
+    audit_log: Option<&str>,
+    dry_run: bool,
+    fsync: &str,
+    from_line_mode: &str,
+    lock: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        // Name order, so overlap priority does not depend on dict hashing
+        let mut patterns: Vec<(String, String)> =
+            patterns.unwrap_or_default().into_iter().collect();
+        patterns.sort();
+        let redactor = Redactor::new(kinds, &patterns)?;
+        let headers =
+            headers.unwrap_or_else(|| DEFAULT_HEADERS.iter().map(ToString::to_string).collect());
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?.with_lock(LockSpec::parse(lock)?);
+        let audit_path =
+            audit_log.map_or_else(|| format!("{dest}.audit.jsonl"), ToString::to_string);
+
+        let io_error = |path: &str, e: std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {path}: {e}"))
+        };
+        let mut archive = output
+            .create(Path::new(dest))
+            .map_err(|e| io_error(dest, e))?;
+        let mut audit = output
+            .create(Path::new(&audit_path))
+            .map_err(|e| io_error(&audit_path, e))?;
+        let _source_lock = output
+            .lock_source(Path::new(src), Path::new(dest))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to lock {src}: {e}"))
+            })?;
+        let mmap = map_file(src)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        for (batch_no, batch) in spans.chunks(BATCH_SIZE).enumerate() {
+            if !progress.messages(batch) {
+                break;
+            }
+            let results: Vec<(Vec<u8>, Vec<AuditRecord>)> = batch
+                .par_iter()
+                .enumerate()
+                .map(|(i, span)| {
+                    let index = batch_no * BATCH_SIZE + i;
+                    redact_message(&redactor, &headers, &data[span.clone()], index, span.start)
+                })
+                .collect();
+            for (rewritten, records) in results {
+                archive
+                    .write_all(&rewritten)
+                    .map_err(|e| io_error(dest, e))?;
+                for record in records {
+                    let line = serde_json::to_string(&record).map_err(|e| {
+                        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                            "Failed to encode audit record: {e}"
+                        ))
+                    })?;
+                    writeln!(audit, "{line}").map_err(|e| io_error(&audit_path, e))?;
+                }
+                output.note_message();
+            }
+        }
+        progress.check("redact_mbox")?;
+        mmap.verify(&mode)?;
+        archive.finish().map_err(|e| io_error(dest, e))?;
+        audit.finish().map_err(|e| io_error(&audit_path, e))?;
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From alice@example.com Mon Jan  1 00:00:00 2024
+From: Alice <alice@example.com>
+To: bob@example.org
+Message-ID: <1@example.com>
+Subject: Card 4111 1111 1111 1111
+Content-Type: multipart/mixed; boundary=\"b\"
+
+--b
+Content-Type: text/plain
+
+Wire to DE89 3704 0044 0532 0130 00, server 10.1.2.3, ref CASE-004211.
+From here on, call me.
+--b
+Content-Type: application/pdf
+Content-Disposition: attachment; filename=\"id.pdf\"
+
+%PDF-1.4
+--b--
+
+From carol Mon Jan  1 00:00:00 2024
+From: carol@example.net
+Subject: Nothing to see
+
+Plain note.
+";
+
+    #[test]
+    fn test_redactor_overlap_and_counts() {
+        let patterns = vec![("case".to_string(), r"CASE-\d{6}".to_string())];
+        let redactor = Redactor::new(None, &patterns).unwrap();
+        let (text, counts) = redactor.redact("a@b.com, 10.0.0.1 and a@b.com CASE-123456");
+        assert_eq!(text, "[REDACTED:email], [REDACTED:ip] and [REDACTED:email] [REDACTED:case]");
+        assert_eq!(counts, vec![("email", 2), ("ip", 1), ("case", 1)]);
+        assert!(Redactor::new(Some(vec!["phone".to_string()]), &[]).is_err());
+    }
+
+    #[test]
+    fn test_redact_mbox_with_audit_log() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_redact_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            let dest = dir.join("out.mbox");
+            std::fs::write(&src, MBOX).unwrap();
+
+            let patterns = HashMap::from([("case".to_string(), r"CASE-\d{6}".to_string())]);
+            let report = redact_mbox(
+                py,
+                src.to_str().unwrap(),
+                dest.to_str().unwrap(),
+                None,
+                Some(patterns),
+                None,
+                None,
+                false,
+                "none",
+                "standard",
+                "none",
+                None,
+            )
+            .unwrap();
+            assert_eq!(report.message_count, 2);
+            assert_eq!(report.file_count, 2);
+
+            let out = std::fs::read_to_string(&dest).unwrap();
+            assert!(out.starts_with("From [REDACTED:email] Mon Jan  1 00:00:00 2024\n"));
+            assert!(out.contains("From: Alice <[REDACTED:email]>\n"));
+            assert!(out.contains("Message-ID: <1@example.com>\n"));
+            assert!(out.contains("Subject: Card [REDACTED:card]\n"));
+            assert!(out.contains(
+            "Wire to [REDACTED:iban], server [REDACTED:ip], ref [REDACTED:case].\n>From here on"
+        ));
+            assert!(!out.contains("%PDF") && !out.contains("multipart"));
+            assert_eq!(crate::mbox::message_spans(out.as_bytes()).len(), 2);
+
+            let audit = std::fs::read_to_string(dir.join("out.mbox.audit.jsonl")).unwrap();
+            let records: Vec<serde_json::Value> = audit
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            let fired: Vec<(&str, &str, u64)> = records
+                .iter()
+                .filter(|r| r["message"] == 0)
+                .map(|r| {
+                    (
+                        r["field"].as_str().unwrap(),
+                        r["rule"].as_str().unwrap_or("-"),
+                        r["replacements"].as_u64().unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                fired,
+                vec![
+                    ("envelope", "email", 1),
+                    ("From", "email", 1),
+                    ("To", "email", 1),
+                    ("Subject", "card", 1),
+                    ("body", "iban", 1),
+                    ("body", "ip", 1),
+                    ("body", "case", 1),
+                    ("attachment", "removed", 1),
+                    ("message", "-", 8),
+                ]
+            );
+            let summary = records.last().unwrap();
+            assert_eq!(summary["message"], 1);
+            assert_eq!(summary["offset"], MBOX.find("From carol").unwrap());
+            assert_eq!(summary["replacements"], 1);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,475 @@
+//! Message-ID generation and reference-chain repair for rewritten archives.
+//!
+//! Legacy mail often lacks `Message-ID`, or replies carry no `In-Reply-To` /
+//! `References`, so modern clients cannot thread it. Repair works from the
+//! thread reconstruction in [`crate::thread`]: a message without an ID gets
+//! one derived from its content, and a reply without references is attached
+//! to its chronological predecessor in the reconstructed thread, with a
+//! `References` chain built the RFC 5322 way (parent's chain + parent's ID).
+//! Missing fields are appended to the header block; nothing else changes.
+
+use crate::date::parse_date;
+use crate::locking::LockSpec;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{fold_header, split_header_body, Message};
+use crate::output::{Output, OutputReport};
+use crate::progress::Progress;
+use crate::thread::{group_threads, is_reply, parse_ids, ThreadKey};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Right-hand side of generated IDs; `.invalid` can never clash with a real host
+pub const DEFAULT_ID_DOMAIN: &str = "generated.invalid";
+
+/// Longest rebuilt `References` list; the root and the most recent ancestors
+/// are kept, as RFC 5322 suggests for long threads
+const MAX_REFERENCES: usize = 20;
+
+/// Messages rewritten in parallel before their output is written in order
+const BATCH_SIZE: usize = 4096;
+
+/// Distinguishes IDs generated within the same clock tick
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Lowercase hex of the first `bytes` bytes of a digest
+pub fn hex_prefix(digest: &[u8], bytes: usize) -> String {
+    digest[..bytes]
+        .iter()
+        .fold(String::with_capacity(bytes * 2), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+fn check_domain(domain: &str) -> PyResult<()> {
+    let valid = !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Invalid Message-ID domain: {domain:?}"
+        )))
+    }
+}
+
+/// Message-ID (without angle brackets) derived from the message bytes, so
+/// repairing the same archive twice yields the same IDs
+pub fn content_message_id(raw: &[u8], domain: &str) -> String {
+    format!("{}@{domain}", hex_prefix(&Sha256::digest(raw), 16))
+}
+
+/// Message-ID (without angle brackets) unique to this call
+fn fresh_message_id(domain: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let mut hasher = Sha256::new();
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(ID_COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    format!("{}@{domain}", hex_prefix(&hasher.finalize(), 16))
+}
+
+/// The threading facts of one message
+struct Entry {
+    key: ThreadKey,
+    epoch: Option<i64>,
+    in_reply_to: Vec<String>,
+    references: Vec<String>,
+    generated_id: Option<String>,
+}
+
+impl Entry {
+    fn parse(raw: &[u8], domain: &str) -> Self {
+        let msg = Message::parse(raw);
+        let key = ThreadKey::from_headers(
+            msg.header("Message-ID"),
+            msg.header("In-Reply-To"),
+            msg.header("References"),
+            msg.decoded_header("Subject").as_deref(),
+        );
+        Self {
+            generated_id: key
+                .message_id
+                .is_none()
+                .then(|| content_message_id(raw, domain)),
+            key,
+            epoch: msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch),
+            in_reply_to: msg.header("In-Reply-To").map(parse_ids).unwrap_or_default(),
+            references: msg.header("References").map(parse_ids).unwrap_or_default(),
+        }
+    }
+
+    fn id(&self) -> &str {
+        self.key
+            .message_id
+            .as_deref()
+            .or(self.generated_id.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// Header fields to append to one message
+#[derive(Default, Debug, PartialEq, Eq)]
+struct Fix {
+    message_id: Option<String>,
+    in_reply_to: Option<String>,
+    references: Option<Vec<String>>,
+}
+
+impl Fix {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(id) = &self.message_id {
+            fields.push(("Message-ID", format!("<{id}>")));
+        }
+        if let Some(id) = &self.in_reply_to {
+            fields.push(("In-Reply-To", format!("<{id}>")));
+        }
+        if let Some(refs) = &self.references {
+            let ids: Vec<String> = refs.iter().map(|id| format!("<{id}>")).collect();
+            fields.push(("References", ids.join(" ")));
+        }
+        fields
+    }
+}
+
+/// Parent chain plus the parent's ID, trimmed to [`MAX_REFERENCES`]
+fn extend_chain(chain: &[String], parent: &str) -> Vec<String> {
+    let mut refs: Vec<String> = chain.to_vec();
+    refs.push(parent.to_string());
+    if refs.len() > MAX_REFERENCES {
+        refs.drain(1..=refs.len() - MAX_REFERENCES);
+    }
+    refs
+}
+
+/// Decide which fields each message is missing
+fn plan(entries: &[Entry]) -> Vec<Fix> {
+    let mut fixes: Vec<Fix> = entries
+        .iter()
+        .map(|e| Fix {
+            message_id: e.generated_id.clone(),
+            ..Fix::default()
+        })
+        .collect();
+    let by_id: HashMap<&str, usize> = entries
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, e)| (e.id(), i))
+        .collect();
+    // Final References of each message, once processed
+    let mut chains: Vec<Option<Vec<String>>> = vec![None; entries.len()];
+    let chain_of = |chains: &[Option<Vec<String>>], i: usize| {
+        chains[i]
+            .clone()
+            .unwrap_or_else(|| entries[i].references.clone())
+    };
+
+    let keys: Vec<ThreadKey> = entries.iter().map(|e| e.key.clone()).collect();
+    for mut thread in group_threads(&keys) {
+        thread.sort_by_key(|&i| (entries[i].epoch.is_none(), entries[i].epoch, i));
+        for (pos, &i) in thread.iter().enumerate() {
+            let entry = &entries[i];
+            let fix = &mut fixes[i];
+            match (entry.in_reply_to.last(), entry.references.is_empty()) {
+                (None, true) if pos > 0 && is_reply(&entry.key.subject) => {
+                    let parent = thread[pos - 1];
+                    fix.in_reply_to = Some(entries[parent].id().to_string());
+                    fix.references =
+                        Some(extend_chain(&chain_of(&chains, parent), entries[parent].id()));
+                },
+                (Some(irt), true) => {
+                    let chain = by_id
+                        .get(irt.as_str())
+                        .filter(|&&parent| parent != i)
+                        .map(|&parent| chain_of(&chains, parent))
+                        .unwrap_or_default();
+                    fix.references = Some(extend_chain(&chain, irt));
+                },
+                (None, false) => fix.in_reply_to = entry.references.last().cloned(),
+                _ => {},
+            }
+            chains[i] = Some(
+                fix.references
+                    .clone()
+                    .unwrap_or_else(|| entry.references.clone()),
+            );
+        }
+    }
+    fixes
+}
+
+/// Append folded header fields at the end of a message's header block
+fn insert_headers(raw: &[u8], fields: &[(&str, String)]) -> Vec<u8> {
+    let (header, _) = split_header_body(raw);
+    let newline = if header.ends_with(b"\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut out = Vec::with_capacity(raw.len() + 256);
+    out.extend_from_slice(header);
+    if !header.is_empty() && !header.ends_with(b"\n") {
+        out.extend_from_slice(newline.as_bytes());
+    }
+    for (name, value) in fields {
+        out.extend_from_slice(fold_header(name, value).replace('\n', newline).as_bytes());
+        out.extend_from_slice(newline.as_bytes());
+    }
+    out.extend_from_slice(&raw[header.len()..]);
+    out
+}
+
+/// Generate an RFC 5322 Message-ID
+///
+/// # Arguments
+/// * `raw_bytes` - Message to derive the ID from; the same bytes always give
+///   the same ID. Without it every call returns a new unique ID
+/// * `domain` - Right-hand side of the ID (default "generated.invalid")
+///
+/// # Returns
+/// * The ID in angle brackets, e.g. `<3f9a...@generated.invalid>`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import generate_message_id
+/// msg["Message-ID"] = generate_message_id(domain="archive.example.org")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (raw_bytes=None, domain=DEFAULT_ID_DOMAIN))]
+pub fn generate_message_id(raw_bytes: Option<&[u8]>, domain: &str) -> PyResult<String> {
+    check_domain(domain)?;
+    let id =
+        raw_bytes.map_or_else(|| fresh_message_id(domain), |raw| content_message_id(raw, domain));
+    Ok(format!("<{id}>"))
+}
+
+/// Rewrite an mbox so every message has a Message-ID and replies carry
+/// In-Reply-To / References
+///
+/// Threads are reconstructed from existing IDs and, for replies that lost
+/// their references, from normalized subjects. Then, per message:
+/// * no `Message-ID` - one is derived from the message content
+/// * a reply (`Re:`-style subject) with neither `In-Reply-To` nor
+///   `References` - both are set from its chronological predecessor in the
+///   thread
+/// * `In-Reply-To` but no `References` - `References` is rebuilt from the
+///   parent's chain
+/// * `References` but no `In-Reply-To` - `In-Reply-To` is its last entry
+///
+/// Added fields are appended (folded) to the header block; the rest of each
+/// message is copied unchanged.
+///
+/// # Arguments
+/// * `src` - Path to the source mbox file
+/// * `dest` - Path of the rewritten mbox (`.gz`/`.zst` compress on the fly)
+/// * `id_domain` - Right-hand side of generated IDs (default "generated.invalid")
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `dry_run` - Report what would be written without touching the disk
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `lock` - Mailbox locking: "none" (default), "dotlock", "fcntl", "flock"
+///   or a combination such as "dotlock+fcntl"; `src` is locked for reading and
+///   each destination for writing (see "Mailbox Locking" in the README)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` with the number of messages written
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import repair_threading
+/// repair_threading("legacy.mbox", "legacy-threaded.mbox", id_domain="archive.example.org")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    src,
+    dest,
+    id_domain=DEFAULT_ID_DOMAIN,
+    from_line_mode="standard",
+    dry_run=false,
+    fsync="file",
+    lock="none",
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn repair_threading(
+    py: Python<'_>,
+    src: &str,
+    dest: &str,
+    id_domain: &str,
+    from_line_mode: &str,
+    dry_run: bool,
+    fsync: &str,
+    lock: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    py.allow_threads(|| {
+        check_domain(id_domain)?;
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let output = Output::from_args(dry_run, fsync, None)?.with_lock(LockSpec::parse(lock)?);
+        let io_error = |e: std::io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {dest}: {e}"))
+        };
+        let mut archive = output.create(Path::new(dest)).map_err(io_error)?;
+        let _source_lock = output
+            .lock_source(Path::new(src), Path::new(dest))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to lock {src}: {e}"))
+            })?;
+        let mmap = map_file(src)?;
+        let data = &mmap[..];
+        let spans = mmap.spans(&mode);
+        let progress = Progress::new(progress, mmap.len());
+        let entries: Vec<Entry> = spans
+            .par_iter()
+            .map(|span| Entry::parse(&data[span.clone()], id_domain))
+            .collect();
+        let fixes = plan(&entries);
+        drop(entries);
+
+        let jobs: Vec<(&Range<usize>, &Fix)> = spans.iter().zip(&fixes).collect();
+        for batch in jobs.chunks(BATCH_SIZE) {
+            if !progress.messages(batch.iter().map(|(span, _)| *span)) {
+                break;
+            }
+            let rewritten: Vec<Vec<u8>> = batch
+                .par_iter()
+                .map(|(span, fix)| insert_headers(&data[(*span).clone()], &fix.fields()))
+                .collect();
+            for message in rewritten {
+                archive.write_all(&message).map_err(io_error)?;
+                output.note_message();
+            }
+        }
+        progress.check("repair_threading")?;
+        mmap.verify(&mode)?;
+        archive.finish().map_err(io_error)?;
+        Ok(output.report())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(headers: &str) -> Entry {
+        Entry::parse(format!("{headers}\n\nbody\n").as_bytes(), DEFAULT_ID_DOMAIN)
+    }
+
+    #[test]
+    fn test_generate_message_id() {
+        let a = generate_message_id(Some(b"Subject: x\n\nbody\n"), "example.org").unwrap();
+        assert_eq!(a, generate_message_id(Some(b"Subject: x\n\nbody\n"), "example.org").unwrap());
+        assert!(a.starts_with('<') && a.ends_with("@example.org>"));
+        assert_eq!(a.len(), "<@example.org>".len() + 32);
+        let (x, y) = (
+            generate_message_id(None, DEFAULT_ID_DOMAIN).unwrap(),
+            generate_message_id(None, DEFAULT_ID_DOMAIN).unwrap(),
+        );
+        assert_ne!(x, y);
+        assert!(generate_message_id(None, "bad domain").is_err());
+        assert!(generate_message_id(None, "a..b").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let entries = vec![
+            entry("Message-ID: <root@x>\nDate: Mon, 1 Jan 2024 09:00:00 +0000\nSubject: Plans"),
+            // Legacy reply: no ID, no references
+            entry("Date: Mon, 1 Jan 2024 10:00:00 +0000\nSubject: Re: Plans"),
+            // Later reply that only has In-Reply-To
+            entry("Message-ID: <c@x>\nIn-Reply-To: <root@x>\nDate: Mon, 1 Jan 2024 11:00:00 +0000\nSubject: Re: Plans"),
+            // References without In-Reply-To
+            entry("Message-ID: <d@x>\nReferences: <root@x> <c@x>\nSubject: Re: Plans"),
+            entry("Message-ID: <e@x>\nSubject: Unrelated"),
+        ];
+        let generated = entries[1].generated_id.clone().unwrap();
+        let fixes = plan(&entries);
+        assert_eq!(fixes[0], Fix::default());
+        assert_eq!(
+            fixes[1],
+            Fix {
+                message_id: Some(generated),
+                in_reply_to: Some("root@x".into()),
+                references: Some(vec!["root@x".into()]),
+            }
+        );
+        assert_eq!(fixes[2].in_reply_to, None);
+        assert_eq!(fixes[2].references, Some(vec!["root@x".to_string()]));
+        assert_eq!(fixes[3].in_reply_to.as_deref(), Some("c@x"));
+        assert_eq!(fixes[3].references, None);
+        assert_eq!(fixes[4], Fix::default());
+
+        let long: Vec<String> = (0..30).map(|i| format!("m{i}@x")).collect();
+        let trimmed = extend_chain(&long, "new@x");
+        assert_eq!(trimmed.len(), MAX_REFERENCES);
+        assert_eq!(trimmed[0], "m0@x");
+        assert_eq!(trimmed[1], "m12@x");
+        assert_eq!(trimmed.last().map(String::as_str), Some("new@x"));
+    }
+
+    #[test]
+    fn test_repair_threading() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_rethread_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join("in.mbox");
+            let dest = dir.join("out.mbox");
+            std::fs::write(
+            &src,
+            "From a Mon Jan  1 09:00:00 2024\r\nMessage-ID: <root@x>\r\nSubject: Plans\r\n\r\nhi\r\n\r\n\
+From b Mon Jan  1 10:00:00 2024\r\nSubject: Re: Plans\r\n\r\nok\r\n",
+        )
+        .unwrap();
+
+            let report = repair_threading(
+                py,
+                src.to_str().unwrap(),
+                dest.to_str().unwrap(),
+                "example.org",
+                "standard",
+                false,
+                "none",
+                "none",
+                None,
+            )
+            .unwrap();
+            assert_eq!(report.message_count, 2);
+            let out = std::fs::read_to_string(&dest).unwrap();
+            assert!(out.starts_with(
+            "From a Mon Jan  1 09:00:00 2024\r\nMessage-ID: <root@x>\r\nSubject: Plans\r\n\r\nhi\r\n"
+        ));
+            let reply = &out[out.find("From b").unwrap()..];
+            assert!(reply.contains("Subject: Re: Plans\r\nMessage-ID: <"));
+            assert!(reply.contains(
+                "@example.org>\r\nIn-Reply-To: <root@x>\r\nReferences: <root@x>\r\n\r\nok\r\n"
+            ));
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
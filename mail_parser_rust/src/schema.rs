@@ -0,0 +1,617 @@
+//! Versioned JSON Schemas for result types.
+//!
+//! Result classes that other tools store or exchange carry a
+//! `SCHEMA_VERSION` class attribute. The version is bumped whenever a field
+//! is removed, renamed or changes type; adding a field keeps it, so consumers
+//! should accept unknown properties. [`RESULT_TYPES`] describes each class's
+//! serialized (`to_json`, sidecar, report) form, and a unit test keeps those
+//! descriptions in step with the Rust structs.
+
+use crate::archive::ZipReport;
+use crate::archive_set::ArchiveHit;
+use crate::attachments::ExtractedAttachment;
+use crate::benchmark::BenchmarkResult;
+use crate::completeness::{ManifestComparison, UnlistedMessage};
+use crate::dedup::{DedupeReport, MergeReport};
+use crate::diff::{AttachmentDigest, DiffHunk, HeaderChange, MessageDiff};
+use crate::forensic::Provenance;
+use crate::gaps::TrafficGap;
+use crate::output::OutputReport;
+use crate::parse::{ParsedAttachment, ParsedMessage};
+use crate::quarantine::QuarantineReport;
+use crate::search::SearchHit;
+use crate::senders::SenderSummary;
+use crate::thread_summary::ThreadSummary;
+use pyo3::prelude::*;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// JSON Schema dialect of the emitted documents
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Type of one serialized field
+#[derive(Debug)]
+pub enum Ty {
+    Str,
+    /// Non-negative integer (counts, sizes, offsets)
+    Count,
+    Int,
+    Float,
+    Bool,
+    /// The value or `null`
+    Opt(&'static Self),
+    List(&'static Self),
+    /// Two-element array
+    Pair(&'static Self, &'static Self),
+    /// Object with arbitrary keys
+    Map(&'static Self),
+    /// Another result type, by name
+    Ref(&'static str),
+}
+
+/// Serialized form of one result type
+#[derive(Debug)]
+pub struct ResultType {
+    pub name: &'static str,
+    pub version: u32,
+    pub description: &'static str,
+    pub fields: &'static [(&'static str, Ty)],
+}
+
+const STRINGS: Ty = Ty::List(&Ty::Str);
+const OPT_STR: Ty = Ty::Opt(&Ty::Str);
+const COUNTS: Ty = Ty::Map(&Ty::Count);
+
+/// Every versioned result type, in alphabetical order
+pub const RESULT_TYPES: &[ResultType] = &[
+    ResultType {
+        name: "ArchiveHit",
+        version: ArchiveHit::SCHEMA_VERSION,
+        description: "One ArchiveSet search result",
+        fields: &[
+            ("source", Ty::Str),
+            ("uid", Ty::Str),
+            ("imap_uid", Ty::Opt(&Ty::Count)),
+            ("position", Ty::Count),
+            ("date", Ty::Opt(&Ty::Int)),
+            ("from_addr", Ty::Str),
+            ("subject", Ty::Str),
+            ("attachments", STRINGS),
+            ("fields", Ty::Map(&STRINGS)),
+        ],
+    },
+    ResultType {
+        name: "AttachmentDigest",
+        version: AttachmentDigest::SCHEMA_VERSION,
+        description: "Identity of one attachment in a MessageDiff",
+        fields: &[
+            ("filename", OPT_STR),
+            ("content_type", Ty::Str),
+            ("size", Ty::Count),
+            ("sha256", Ty::Str),
+        ],
+    },
+    ResultType {
+        name: "BenchmarkResult",
+        version: BenchmarkResult::SCHEMA_VERSION,
+        description: "Throughput of one operation, io mode and thread count from benchmark",
+        fields: &[
+            ("operation", Ty::Str),
+            ("io_mode", Ty::Str),
+            ("threads", Ty::Count),
+            ("repeat", Ty::Count),
+            ("best_seconds", Ty::Float),
+            ("mean_seconds", Ty::Float),
+            ("bytes", Ty::Count),
+            ("messages", Ty::Count),
+            ("mb_per_s", Ty::Float),
+            ("messages_per_s", Ty::Float),
+            ("peak_rss_bytes", Ty::Opt(&Ty::Count)),
+            ("chunk_bytes", Ty::Count),
+        ],
+    },
+    ResultType {
+        name: "DedupeReport",
+        version: DedupeReport::SCHEMA_VERSION,
+        description: "What dedupe_mbox kept and dropped",
+        fields: &[
+            ("strategy", Ty::Str),
+            ("read", Ty::Count),
+            ("duplicates", Ty::Count),
+            ("unkeyed", Ty::Count),
+            ("dropped_offsets", Ty::List(&Ty::Count)),
+            ("kept_offsets", Ty::List(&Ty::Count)),
+            ("output", Ty::Ref("OutputReport")),
+        ],
+    },
+    ResultType {
+        name: "DiffHunk",
+        version: DiffHunk::SCHEMA_VERSION,
+        description: "One unified-diff hunk of a MessageDiff body",
+        fields: &[
+            ("old_start", Ty::Count),
+            ("old_lines", Ty::Count),
+            ("new_start", Ty::Count),
+            ("new_lines", Ty::Count),
+            ("lines", STRINGS),
+        ],
+    },
+    ResultType {
+        name: "ExtractedAttachment",
+        version: ExtractedAttachment::SCHEMA_VERSION,
+        description: "An attachment written to disk by extract_attachments_fast",
+        fields: &[
+            ("filename", Ty::Str),
+            ("original_filename", OPT_STR),
+            ("path", Ty::Str),
+            ("declared_type", Ty::Str),
+            ("detected_type", Ty::Str),
+            ("size", Ty::Count),
+            ("sha256", Ty::Str),
+            ("part_index", Ty::Count),
+        ],
+    },
+    ResultType {
+        name: "HeaderChange",
+        version: HeaderChange::SCHEMA_VERSION,
+        description: "A header value that differs in a MessageDiff",
+        fields: &[("name", Ty::Str), ("before", Ty::Str), ("after", Ty::Str)],
+    },
+    ResultType {
+        name: "ManifestComparison",
+        version: ManifestComparison::SCHEMA_VERSION,
+        description: "An archive checked against a manifest by verify_against_manifest",
+        fields: &[
+            ("expected", Ty::Count),
+            ("messages", Ty::Count),
+            ("matched", Ty::Count),
+            ("missing", STRINGS),
+            ("extra", Ty::List(&Ty::Ref("UnlistedMessage"))),
+            ("manifest_duplicates", STRINGS),
+            ("archive_duplicates", Ty::Count),
+            ("complete", Ty::Bool),
+        ],
+    },
+    ResultType {
+        name: "MergeReport",
+        version: MergeReport::SCHEMA_VERSION,
+        description: "What merge_mboxes wrote and skipped",
+        fields: &[
+            ("read", Ty::Count),
+            ("duplicates", Ty::Count),
+            ("quarantined", Ty::Count),
+            ("output", Ty::Ref("OutputReport")),
+        ],
+    },
+    ResultType {
+        name: "MessageDiff",
+        version: MessageDiff::SCHEMA_VERSION,
+        description: "What diff_messages found between two message versions",
+        fields: &[
+            ("identical", Ty::Bool),
+            ("headers_added", Ty::List(&Ty::Pair(&Ty::Str, &Ty::Str))),
+            ("headers_removed", Ty::List(&Ty::Pair(&Ty::Str, &Ty::Str))),
+            ("headers_changed", Ty::List(&Ty::Ref("HeaderChange"))),
+            ("body_hunks", Ty::List(&Ty::Ref("DiffHunk"))),
+            ("attachments_added", Ty::List(&Ty::Ref("AttachmentDigest"))),
+            ("attachments_removed", Ty::List(&Ty::Ref("AttachmentDigest"))),
+            (
+                "attachments_changed",
+                Ty::List(&Ty::Pair(&Ty::Ref("AttachmentDigest"), &Ty::Ref("AttachmentDigest"))),
+            ),
+        ],
+    },
+    ResultType {
+        name: "OutputReport",
+        version: OutputReport::SCHEMA_VERSION,
+        description: "What a writing operation wrote, or would have written",
+        fields: &[
+            ("dry_run", Ty::Bool),
+            ("message_count", Ty::Count),
+            ("byte_total", Ty::Count),
+            ("file_count", Ty::Count),
+            ("example_filenames", STRINGS),
+        ],
+    },
+    ResultType {
+        name: "ParsedAttachment",
+        version: ParsedAttachment::SCHEMA_VERSION,
+        description: "One attachment of a ParsedMessage (content not included)",
+        fields: &[
+            ("filename", OPT_STR),
+            ("content_type", Ty::Str),
+            ("disposition", OPT_STR),
+            ("content_id", OPT_STR),
+            ("charset", OPT_STR),
+            ("size", Ty::Count),
+            ("part_index", Ty::Count),
+        ],
+    },
+    ResultType {
+        name: "ParsedMessage",
+        version: ParsedMessage::SCHEMA_VERSION,
+        description: "A message with decoded headers, bodies and attachments",
+        fields: &[
+            ("headers", Ty::List(&Ty::Pair(&Ty::Str, &Ty::Str))),
+            ("subject", OPT_STR),
+            ("from_addr", OPT_STR),
+            ("to", STRINGS),
+            ("cc", STRINGS),
+            ("date", OPT_STR),
+            ("timestamp", Ty::Opt(&Ty::Int)),
+            ("message_id", OPT_STR),
+            ("content_type", Ty::Str),
+            ("text_bodies", STRINGS),
+            ("html_bodies", STRINGS),
+            ("attachments", Ty::List(&Ty::Ref("ParsedAttachment"))),
+        ],
+    },
+    ResultType {
+        name: "Provenance",
+        version: Provenance::SCHEMA_VERSION,
+        description: "Where a message extracted by extract_original came from",
+        fields: &[
+            ("source", Ty::Str),
+            ("source_size", Ty::Count),
+            ("source_sha256", Ty::Str),
+            ("offset", Ty::Count),
+            ("length", Ty::Count),
+            ("sha256", Ty::Str),
+            ("uid", Ty::Str),
+            ("out_path", Ty::Str),
+            ("sidecar_path", Ty::Str),
+            ("extracted_at", Ty::Str),
+            ("tool_version", Ty::Str),
+        ],
+    },
+    ResultType {
+        name: "QuarantineReport",
+        version: QuarantineReport::SCHEMA_VERSION,
+        description: "How quarantine_mbox split an archive",
+        fields: &[
+            ("clean", Ty::Count),
+            ("quarantined", Ty::Count),
+            ("reasons", COUNTS),
+            ("output", Ty::Ref("OutputReport")),
+        ],
+    },
+    ResultType {
+        name: "SearchHit",
+        version: SearchHit::SCHEMA_VERSION,
+        description: "A message matching a search_mbox_fast query",
+        fields: &[
+            ("index", Ty::Count),
+            ("offset", Ty::Count),
+            ("length", Ty::Count),
+            ("field", Ty::Str),
+            ("match_offset", Ty::Count),
+            ("match_count", Ty::Count),
+            ("snippet", Ty::Str),
+        ],
+    },
+    ResultType {
+        name: "SenderSummary",
+        version: SenderSummary::SCHEMA_VERSION,
+        description: "One sender in a sender_report",
+        fields: &[
+            ("sender", Ty::Str),
+            ("display_name", OPT_STR),
+            ("message_count", Ty::Count),
+            ("first_seen", OPT_STR),
+            ("last_seen", OPT_STR),
+            ("spf", COUNTS),
+            ("dkim", COUNTS),
+            ("dmarc", COUNTS),
+            ("bulk_messages", Ty::Count),
+            ("avg_spam_score", Ty::Opt(&Ty::Float)),
+            ("avg_links", Ty::Float),
+            ("html_only_ratio", Ty::Float),
+            ("reply_to_mismatch", Ty::Count),
+        ],
+    },
+    ResultType {
+        name: "ThreadSummary",
+        version: ThreadSummary::SCHEMA_VERSION,
+        description: "One thread from thread_summaries",
+        fields: &[
+            ("thread_id", Ty::Count),
+            ("subject", Ty::Str),
+            ("participants", STRINGS),
+            ("message_count", Ty::Count),
+            ("start", OPT_STR),
+            ("end", OPT_STR),
+            ("total_bytes", Ty::Count),
+            ("last_sender", OPT_STR),
+            ("awaiting_reply", Ty::Opt(&Ty::Bool)),
+        ],
+    },
+    ResultType {
+        name: "TrafficGap",
+        version: TrafficGap::SCHEMA_VERSION,
+        description: "A period of missing or unusually light traffic from detect_gaps",
+        fields: &[
+            ("kind", Ty::Str),
+            ("start", Ty::Str),
+            ("end", Ty::Str),
+            ("days", Ty::Count),
+            ("observed", Ty::Count),
+            ("expected", Ty::Float),
+        ],
+    },
+    ResultType {
+        name: "UnlistedMessage",
+        version: UnlistedMessage::SCHEMA_VERSION,
+        description: "An archive message missing from the manifest",
+        fields: &[
+            ("index", Ty::Count),
+            ("offset", Ty::Count),
+            ("message_id", OPT_STR),
+            ("uid", Ty::Str),
+        ],
+    },
+    ResultType {
+        name: "ZipReport",
+        version: ZipReport::SCHEMA_VERSION,
+        description: "What a ZIP extraction wrote and skipped",
+        fields: &[
+            ("extracted", STRINGS),
+            ("skipped", STRINGS),
+            ("encrypted", Ty::Bool),
+            ("truncated", Ty::Bool),
+            ("output", Ty::Ref("OutputReport")),
+        ],
+    },
+];
+
+fn find(name: &str) -> Option<&'static ResultType> {
+    RESULT_TYPES.iter().find(|t| t.name == name)
+}
+
+/// JSON object from key/value pairs (`json!` would unwrap internally)
+fn object<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Object(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+fn type_schema(ty: &Ty) -> Value {
+    let simple = |name: &str| object([("type", name.into())]);
+    match ty {
+        Ty::Str => simple("string"),
+        Ty::Count => object([("type", "integer".into()), ("minimum", 0.into())]),
+        Ty::Int => simple("integer"),
+        Ty::Float => simple("number"),
+        Ty::Bool => simple("boolean"),
+        Ty::Opt(inner) => object([("anyOf", vec![type_schema(inner), simple("null")].into())]),
+        Ty::List(inner) => object([("type", "array".into()), ("items", type_schema(inner))]),
+        Ty::Pair(a, b) => object([
+            ("type", "array".into()),
+            ("prefixItems", vec![type_schema(a), type_schema(b)].into()),
+            ("minItems", 2.into()),
+            ("maxItems", 2.into()),
+        ]),
+        Ty::Map(inner) => object([
+            ("type", "object".into()),
+            ("additionalProperties", type_schema(inner)),
+        ]),
+        Ty::Ref(name) => object([("$ref", format!("#/$defs/{name}").into())]),
+    }
+}
+
+/// Names of the result types `ty` refers to, directly or nested
+fn refs(ty: &Ty, out: &mut Vec<&'static str>) {
+    match ty {
+        Ty::Opt(inner) | Ty::List(inner) | Ty::Map(inner) => refs(inner, out),
+        Ty::Pair(a, b) => {
+            refs(a, out);
+            refs(b, out);
+        },
+        Ty::Ref(name) => out.push(name),
+        _ => {},
+    }
+}
+
+fn object_schema(t: &ResultType) -> Value {
+    let properties: Map<String, Value> = t
+        .fields
+        .iter()
+        .map(|(name, ty)| ((*name).to_string(), type_schema(ty)))
+        .collect();
+    let required: Vec<Value> = t.fields.iter().map(|(name, _)| (*name).into()).collect();
+    object([
+        ("title", t.name.into()),
+        ("description", t.description.into()),
+        ("x-schema-version", t.version.into()),
+        ("type", "object".into()),
+        ("properties", Value::Object(properties)),
+        ("required", Value::Array(required)),
+    ])
+}
+
+/// Standalone schema for `t`, with every type it refers to under `$defs`
+fn document(t: &ResultType) -> Value {
+    let mut defs = Map::new();
+    let mut pending: Vec<&str> = Vec::new();
+    for (_, ty) in t.fields {
+        refs(ty, &mut pending);
+    }
+    while let Some(name) = pending.pop() {
+        if defs.contains_key(name) {
+            continue;
+        }
+        if let Some(dep) = find(name) {
+            for (_, ty) in dep.fields {
+                refs(ty, &mut pending);
+            }
+            defs.insert(name.to_string(), object_schema(dep));
+        }
+    }
+    let mut schema = object_schema(t);
+    if let Value::Object(obj) = &mut schema {
+        obj.insert("$schema".to_string(), DIALECT.into());
+        obj.insert(
+            "$id".to_string(),
+            format!("urn:mail_parser_rust:{}:v{}", t.name, t.version).into(),
+        );
+        if !defs.is_empty() {
+            obj.insert("$defs".to_string(), Value::Object(defs));
+        }
+    }
+    schema
+}
+
+/// JSON Schema describing a result type
+///
+/// The schema covers the serialized form of the type (what `to_json`,
+/// sidecars and reports write), which has the same fields as the Python
+/// object. Its `$id` and `x-schema-version` carry the type's
+/// `SCHEMA_VERSION`; without `name`, one document lists every result type
+/// under `$defs`.
+///
+/// # Arguments
+/// * `name` - Result class name, e.g. `"ParsedMessage"` (default: all types)
+///
+/// # Returns
+/// * JSON Schema (draft 2020-12) as a JSON string
+///
+/// # Raises
+/// * `ValueError` for an unknown type name
+///
+/// # Example
+/// ```python
+/// import json
+/// from mail_parser_rust import ParsedMessage, result_schema
+/// schema = json.loads(result_schema("ParsedMessage"))
+/// assert schema["x-schema-version"] == ParsedMessage.SCHEMA_VERSION
+/// ```
+#[pyfunction]
+#[pyo3(signature = (name=None))]
+pub fn result_schema(name: Option<&str>) -> PyResult<String> {
+    let schema = if let Some(name) = name {
+        document(find(name).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown result type: {name}"))
+        })?)
+    } else {
+        let defs: Map<String, Value> = RESULT_TYPES
+            .iter()
+            .map(|t| (t.name.to_string(), object_schema(t)))
+            .collect();
+        object([
+            ("$schema", DIALECT.into()),
+            ("$id", "urn:mail_parser_rust:results".into()),
+            ("title", "mail_parser_rust result types".into()),
+            ("$defs", Value::Object(defs)),
+        ])
+    };
+    serde_json::to_string_pretty(&schema).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Serialization error: {e}"))
+    })
+}
+
+/// Schema version of every result type, for detecting breaking changes
+///
+/// # Returns
+/// * Dict of result class name to its `SCHEMA_VERSION`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import schema_versions
+/// if schema_versions()["ParsedMessage"] != 1:
+///     raise RuntimeError("ParsedMessage layout changed; update the loader")
+/// ```
+#[pyfunction]
+pub fn schema_versions() -> HashMap<&'static str, u32> {
+    RESULT_TYPES.iter().map(|t| (t.name, t.version)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    fn keys<T: Serialize>(value: &T) -> Vec<String> {
+        match serde_json::to_value(value).unwrap() {
+            Value::Object(obj) => obj.keys().cloned().collect(),
+            other => panic!("not an object: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_fields_match_structs() {
+        let gap = TrafficGap {
+            kind: String::new(),
+            start: String::new(),
+            end: String::new(),
+            days: 0,
+            observed: 0,
+            expected: 0.0,
+        };
+        let serialized = [
+            ("ArchiveHit", keys(&ArchiveHit::default())),
+            ("AttachmentDigest", keys(&AttachmentDigest::default())),
+            ("BenchmarkResult", keys(&BenchmarkResult::default())),
+            ("DedupeReport", keys(&DedupeReport::default())),
+            ("DiffHunk", keys(&DiffHunk::default())),
+            ("ExtractedAttachment", keys(&ExtractedAttachment::default())),
+            ("HeaderChange", keys(&HeaderChange::default())),
+            ("ManifestComparison", keys(&ManifestComparison::default())),
+            ("MergeReport", keys(&MergeReport::default())),
+            ("MessageDiff", keys(&MessageDiff::default())),
+            ("OutputReport", keys(&OutputReport::default())),
+            ("ParsedAttachment", keys(&ParsedAttachment::default())),
+            ("ParsedMessage", keys(&ParsedMessage::default())),
+            ("Provenance", keys(&Provenance::default())),
+            ("QuarantineReport", keys(&QuarantineReport::default())),
+            ("SearchHit", keys(&SearchHit::default())),
+            ("SenderSummary", keys(&SenderSummary::default())),
+            ("ThreadSummary", keys(&ThreadSummary::default())),
+            ("TrafficGap", keys(&gap)),
+            ("UnlistedMessage", keys(&UnlistedMessage::default())),
+            ("ZipReport", keys(&ZipReport::default())),
+        ];
+        assert_eq!(serialized.len(), RESULT_TYPES.len());
+        for (name, mut actual) in serialized {
+            let mut declared: Vec<String> = find(name)
+                .unwrap()
+                .fields
+                .iter()
+                .map(|(f, _)| (*f).to_string())
+                .collect();
+            actual.sort();
+            declared.sort();
+            assert_eq!(declared, actual, "{name}");
+        }
+        let names: Vec<&str> = RESULT_TYPES.iter().map(|t| t.name).collect();
+        assert!(names.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_documents() {
+        let merge = document(find("MergeReport").unwrap());
+        assert_eq!(merge["$id"], "urn:mail_parser_rust:MergeReport:v1");
+        assert_eq!(merge["properties"]["output"]["$ref"], "#/$defs/OutputReport");
+        assert_eq!(
+            merge["$defs"]["OutputReport"]["required"]
+                .as_array()
+                .unwrap()
+                .len(),
+            5
+        );
+        let parsed = document(find("ParsedMessage").unwrap());
+        assert_eq!(parsed["properties"]["subject"]["anyOf"][1]["type"], "null");
+        assert_eq!(parsed["properties"]["headers"]["items"]["maxItems"], 2);
+        assert!(parsed["$defs"]["ParsedAttachment"].is_object());
+        // Every reference resolves
+        for t in RESULT_TYPES {
+            let mut names = Vec::new();
+            for (_, ty) in t.fields {
+                refs(ty, &mut names);
+            }
+            assert!(names.iter().all(|n| find(n).is_some()), "{}", t.name);
+        }
+        assert!(result_schema(Some("Nope")).is_err());
+    }
+}
@@ -0,0 +1,625 @@
+//! Parallel full-text search over an mbox file.
+//!
+//! Messages are scanned in place in the memory map, one rayon task per
+//! message, so a hit's offsets point at the archive's own bytes (headers and
+//! bodies are searched as stored, not decoded). Header values are matched
+//! over their folded lines; the header name itself never matches.
+//!
+//! `regex_scan_file` skips message splitting altogether and runs a pattern
+//! over the raw map in fixed-size chunks, one task per chunk. Each chunk's
+//! search window reaches `overlap` bytes into the next, so matches that
+//! straddle a boundary are found whole by the chunk they start in.
+
+use crate::deadline::Deadline;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{split_header_body, strip_envelope};
+use crate::progress::Progress;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use regex::bytes::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// One message matching a `search_mbox_fast` query
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct SearchHit {
+    /// Position of the message in the mbox
+    #[pyo3(get)]
+    pub index: usize,
+    /// Byte offset of the message in the file
+    #[pyo3(get)]
+    pub offset: u64,
+    /// Length of the message in bytes
+    #[pyo3(get)]
+    pub length: u64,
+    /// Where the first match is: a header name as written, or `"body"`
+    #[pyo3(get)]
+    pub field: String,
+    /// Byte offset of the first match in the file
+    #[pyo3(get)]
+    pub match_offset: u64,
+    /// Matches in the searched fields of this message
+    #[pyo3(get)]
+    pub match_count: usize,
+    /// The first match with surrounding context, whitespace collapsed
+    #[pyo3(get)]
+    pub snippet: String,
+}
+
+#[pymethods]
+impl SearchHit {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchHit(index={}, field={:?}, snippet={:?})",
+            self.index, self.field, self.snippet
+        )
+    }
+}
+
+/// Header fields of a header block as (name, value range), the range
+/// covering continuation lines but not the final line ending
+fn header_fields(head: &[u8]) -> Vec<(&[u8], Range<usize>)> {
+    let mut fields: Vec<(&[u8], Range<usize>)> = Vec::new();
+    let mut start = 0;
+    while start < head.len() {
+        let end = head[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(head.len(), |p| start + p + 1);
+        let mut content = start..end;
+        while content.end > content.start && matches!(head[content.end - 1], b'\n' | b'\r') {
+            content.end -= 1;
+        }
+        if matches!(head[start], b' ' | b'\t') {
+            if let Some((_, value)) = fields.last_mut() {
+                value.end = content.end;
+            }
+        } else if let Some(colon) = head[content.clone()].iter().position(|&b| b == b':') {
+            fields.push((&head[start..start + colon], start + colon + 1..content.end));
+        }
+        start = end;
+    }
+    fields
+}
+
+/// `context` bytes either side of `found` within `text`, lossily decoded
+/// with whitespace runs collapsed and cut ends marked with `...`
+fn snippet(text: &[u8], found: Range<usize>, context: usize) -> String {
+    let mut from = found.start.saturating_sub(context);
+    let mut to = found.end.saturating_add(context).min(text.len());
+    // Do not cut a UTF-8 sequence in half
+    while from > 0 && from < found.start && (text[from] & 0xC0) == 0x80 {
+        from -= 1;
+    }
+    while to < text.len() && to > found.end && (text[to] & 0xC0) == 0x80 {
+        to += 1;
+    }
+    let body = String::from_utf8_lossy(&text[from..to])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let lead = if from > 0 { "..." } else { "" };
+    let trail = if to < text.len() { "..." } else { "" };
+    format!("{lead}{body}{trail}")
+}
+
+/// What to search and how
+struct Query {
+    regex: Regex,
+    /// Lowercased header names to search; `None` searches every header
+    headers: Option<Vec<String>>,
+    body: bool,
+    context: usize,
+}
+
+impl Query {
+    /// Hit for the message at `span` of `data`, if anything matches
+    fn hit(&self, index: usize, data: &[u8], span: Range<usize>) -> Option<SearchHit> {
+        let raw = &data[span.clone()];
+        let message = strip_envelope(raw);
+        let head_start = raw.len() - message.len();
+        let (head, body) = split_header_body(message);
+        let body_start = raw.len() - body.len();
+
+        let mut regions: Vec<(String, Range<usize>)> = header_fields(head)
+            .into_iter()
+            .filter(|(name, _)| {
+                let name = String::from_utf8_lossy(name).trim().to_lowercase();
+                self.headers
+                    .as_ref()
+                    .map_or(true, |wanted| wanted.contains(&name))
+            })
+            .map(|(name, value)| {
+                let name = String::from_utf8_lossy(name).trim().to_string();
+                (name, head_start + value.start..head_start + value.end)
+            })
+            .collect();
+        if self.body {
+            regions.push(("body".to_string(), body_start..raw.len()));
+        }
+
+        let mut first: Option<(String, Range<usize>, Range<usize>)> = None;
+        let mut match_count = 0;
+        for (field, region) in regions {
+            let text = &raw[region.clone()];
+            for found in self.regex.find_iter(text) {
+                match_count += 1;
+                if first.is_none() {
+                    first = Some((field.clone(), region.clone(), found.range()));
+                }
+            }
+        }
+        let (field, region, found) = first?;
+        Some(SearchHit {
+            index,
+            offset: span.start as u64,
+            length: span.len() as u64,
+            field,
+            match_offset: (span.start + region.start + found.start) as u64,
+            match_count,
+            snippet: snippet(&raw[region], found, self.context),
+        })
+    }
+}
+
+/// Search every message of an mbox in parallel
+///
+/// Headers and bodies are matched as stored in the file (a base64 body is
+/// searched in its encoded form), one worker per message, and offsets in the
+/// results refer to the file. Header matches only consider the value, folded
+/// lines included.
+///
+/// # Arguments
+/// * `path` - Path to the mbox file
+/// * `query` - Text to find, or a regular expression with `regex=True`
+/// * `regex` - Treat `query` as a regular expression (Rust `regex` syntax)
+/// * `case_insensitive` - Ignore case (Unicode-aware)
+/// * `headers` - Header names to search (case-insensitive); None searches
+///   every header, `[]` none
+/// * `body` - Search message bodies (default True)
+/// * `context` - Bytes of context either side of the match in `snippet`
+/// * `limit` - Return at most this many hits (the first in mbox order)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are searched; returning False cancels the search
+///
+/// # Returns
+/// * List of `SearchHit` in mbox order: message `index`, `offset`,
+///   `length`, the `field` and file `match_offset` of the first match,
+///   `match_count` and `snippet`
+///
+/// # Raises
+/// * `ValueError` - Invalid regular expression
+/// * `TimeoutError` - `timeout_secs` passed; `partial` holds the hits from
+///   the messages searched in time (in mbox order, with gaps)
+/// * `ScanCancelled` - `progress` returned False (or what it raised);
+///   `partial` as for a timeout
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import search_mbox_fast
+/// for hit in search_mbox_fast("inbox.mbox", r"invoice\s+#\d+", regex=True,
+///                             case_insensitive=True, headers=["Subject"]):
+///     print(hit.index, hit.snippet)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    query,
+    regex=false,
+    case_insensitive=false,
+    headers=None,
+    body=true,
+    context=40,
+    limit=None,
+    from_line_mode="standard",
+    timeout_secs=None,
+    progress=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn search_mbox_fast(
+    py: Python<'_>,
+    path: &str,
+    query: &str,
+    regex: bool,
+    case_insensitive: bool,
+    headers: Option<Vec<String>>,
+    body: bool,
+    context: usize,
+    limit: Option<usize>,
+    from_line_mode: &str,
+    timeout_secs: Option<f64>,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<SearchHit>> {
+    let deadline = Deadline::new(timeout_secs)?;
+    let (hits, timed_out, progress) = py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let pattern = if regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid regex: {e}"))
+            })?;
+        let query = Query {
+            regex,
+            headers: headers.map(|names| names.iter().map(|n| n.trim().to_lowercase()).collect()),
+            body,
+            context,
+        };
+        let mmap = map_file(path)?;
+        let data = &mmap[..];
+        let progress = Progress::new(progress, data.len());
+        let timed_out = AtomicBool::new(false);
+        let mut hits: Vec<SearchHit> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .enumerate()
+            .filter_map(|(index, span)| {
+                if deadline.expired() {
+                    timed_out.store(true, Ordering::Relaxed);
+                    return None;
+                }
+                if progress.cancelled() {
+                    return None;
+                }
+                let len = span.len();
+                let hit = query.hit(index, data, span);
+                progress.advance(len);
+                hit
+            })
+            .collect();
+        mmap.verify(&mode)?;
+        progress.finish();
+        if let Some(limit) = limit {
+            hits.truncate(limit);
+        }
+        Ok::<_, PyErr>((hits, timed_out.into_inner(), progress))
+    })?;
+    if progress.cancelled() {
+        return Err(progress.error_with(py, "search_mbox_fast", hits));
+    }
+    if timed_out {
+        return Err(deadline.error(py, "search_mbox_fast", hits));
+    }
+    Ok(hits)
+}
+
+/// Default bytes per `regex_scan_file` chunk
+const SCAN_CHUNK: usize = 64 << 20;
+
+/// Default bytes a `regex_scan_file` chunk's window extends past its end
+const SCAN_OVERLAP: usize = 1 << 20;
+
+/// Non-empty matches starting in `from..end`, searched in a window ending
+/// `overlap` bytes past `end`
+///
+/// A match that reaches the end of the window may have been cut short (or,
+/// through `$` or `\b`, exist only because the window ends there), so it is
+/// matched again against the whole file.
+fn scan_chunk(
+    re: &Regex,
+    data: &[u8],
+    from: usize,
+    end: usize,
+    overlap: usize,
+) -> Vec<Range<usize>> {
+    let window = &data[..end.saturating_add(overlap).min(data.len())];
+    let truncated = window.len() < data.len();
+    let mut matches = Vec::new();
+    let mut pos = from;
+    while pos <= end {
+        let Some(mut m) = re.find_at(window, pos) else {
+            break;
+        };
+        if truncated && m.end() == window.len() {
+            match re.find_at(data, m.start()) {
+                Some(whole) => m = whole,
+                None => break,
+            }
+        }
+        if m.start() >= end {
+            break;
+        }
+        if m.is_empty() {
+            pos = m.start() + 1;
+            continue;
+        }
+        matches.push(m.range());
+        pos = m.end();
+    }
+    matches
+}
+
+/// Stream a regex over a whole file, returning the byte offsets of matches
+///
+/// The file is memory-mapped and searched in parallel `chunk_size` pieces
+/// without splitting it into messages or decoding it, so a 50 GB archive is
+/// scanned at disk speed in constant memory (besides the results). Matches
+/// are those of one left-to-right pass (non-overlapping, leftmost first),
+/// provided none is longer than `overlap` bytes; a longer one may be found
+/// late or not at all. Empty matches are skipped.
+///
+/// The pattern matches bytes: Unicode classes and `.` match UTF-8
+/// characters, `(?-u)` turns that off for arbitrary bytes (see
+/// `regex_findall_bytes`). `^`/`$` need `(?m)` to match at line boundaries.
+///
+/// # Arguments
+/// * `path` - File to scan (any file, not only an mbox)
+/// * `pattern` - Regex pattern
+/// * `chunk_size` - Bytes per parallel task (default 64 MiB)
+/// * `overlap` - Bytes each chunk's search reaches into the next, the
+///   longest match guaranteed to be found (default 1 MiB)
+/// * `timeout_secs` - Stop after this many seconds (None: no limit)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)` as
+///   chunks are scanned; returning False cancels the scan
+///
+/// # Returns
+/// * `(start, end)` file offsets of each match, in file order
+///
+/// # Raises
+/// * `ValueError` - Invalid pattern or a `chunk_size` of 0
+/// * `IOError` - File cannot be read
+/// * `FileChangedError` - File was truncated or rewritten during the scan
+/// * `TimeoutError` - `timeout_secs` passed; `partial` holds the matches
+///   before the first chunk left unscanned
+/// * `ScanCancelled` - `progress` returned False (or what it raised);
+///   `partial` as for a timeout
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import regex_scan_file
+/// with open("archive.mbox", "rb") as f:
+///     for start, end in regex_scan_file("archive.mbox", r"\b4\d{15}\b"):
+///         f.seek(start)
+///         print(start, f.read(end - start))
+/// ```
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    pattern,
+    chunk_size=SCAN_CHUNK,
+    overlap=SCAN_OVERLAP,
+    timeout_secs=None,
+    progress=None,
+))]
+pub fn regex_scan_file(
+    py: Python<'_>,
+    path: &str,
+    pattern: &str,
+    chunk_size: usize,
+    overlap: usize,
+    timeout_secs: Option<f64>,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<(usize, usize)>> {
+    if chunk_size == 0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("chunk_size must be positive"));
+    }
+    let re = Regex::new(pattern).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid regex: {e}"))
+    })?;
+    let deadline = Deadline::new(timeout_secs)?;
+    let (matches, stopped, progress) = py.allow_threads(|| {
+        let mmap = map_file(path)?;
+        let data = &mmap[..];
+        let progress = Progress::new(progress, data.len());
+        let chunks: Vec<Option<Vec<Range<usize>>>> = (0..(data.len() + chunk_size - 1)
+            / chunk_size)
+            .into_par_iter()
+            .map(|i| {
+                let start = i * chunk_size;
+                let end = (start + chunk_size).min(data.len());
+                if deadline.expired() || progress.cancelled() {
+                    return None;
+                }
+                let found = scan_chunk(&re, data, start, end, overlap);
+                progress.advance(end - start);
+                Some(found)
+            })
+            .collect();
+        mmap.verify(&FromLineMode::Standard)?;
+        progress.finish();
+
+        // A match running into the next chunk can hide that chunk's first
+        // matches; the chunk is searched again from where the match ended
+        let mut matches: Vec<(usize, usize)> = Vec::new();
+        let mut stopped = false;
+        for (i, found) in chunks.into_iter().enumerate() {
+            let Some(mut found) = found else {
+                stopped = true;
+                break;
+            };
+            let last_end = matches.last().map_or(0, |&(_, end)| end);
+            if found.first().is_some_and(|m| m.start < last_end) {
+                let end = ((i + 1) * chunk_size).min(data.len());
+                found = scan_chunk(&re, data, last_end, end, overlap);
+            }
+            matches.extend(found.into_iter().map(|m| (m.start, m.end)));
+        }
+        Ok::<_, PyErr>((matches, stopped, progress))
+    })?;
+    if progress.cancelled() {
+        return Err(progress.error_with(py, "regex_scan_file", matches));
+    }
+    if stopped {
+        return Err(deadline.error(py, "regex_scan_file", matches));
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_fields() {
+        let head = b"Subject: one\r\n two\r\nFrom:a@x\nbogus line\n";
+        let fields = header_fields(head);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].0, b"Subject");
+        assert_eq!(&head[fields[0].1.clone()], b" one\r\n two");
+        assert_eq!(&head[fields[1].1.clone()], b"a@x");
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = "caf\u{e9} one two   three\nfour".as_bytes();
+        let at = text.windows(3).position(|w| w == b"two").unwrap();
+        assert_eq!(snippet(text, at..at + 3, 5), "...one two th...");
+        assert_eq!(snippet(text, at..at + 3, 100), "caf\u{e9} one two three four");
+        // The window start lands inside the two-byte e-acute
+        assert_eq!(snippet(text, at..at + 3, 6), "...\u{e9} one two thr...");
+    }
+
+    #[test]
+    fn test_search_mbox() {
+        let dir = std::env::temp_dir().join(format!("mpr_search_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("in.mbox");
+        let messages = [
+            "From a@x Mon Jan  1 00:00:00 2024\nSubject: Invoice #42\nFrom: a@x\n\nPlease pay.\n\n",
+            "From b@x Mon Jan  1 00:00:00 2024\nSubject: lunch\nX-Note: see\n invoice later\n\n\
+             no match here\n\n",
+            "From c@x Mon Jan  1 00:00:00 2024\nSubject: re\n\nthe INVOICE #7 and invoice #8\n",
+        ];
+        let mbox = messages.concat();
+        std::fs::write(&path, &mbox).unwrap();
+        let path = path.to_str().unwrap();
+        let search = |query: &str, regex, ci, headers: Option<Vec<&str>>, body| {
+            let headers = headers.map(|h| h.into_iter().map(String::from).collect());
+            crate::with_py(|py| {
+                search_mbox_fast(
+                    py, path, query, regex, ci, headers, body, 10, None, "standard", None, None,
+                )
+            })
+            .unwrap()
+        };
+
+        let hits = search("invoice", false, true, None, true);
+        assert_eq!(hits.iter().map(|h| h.index).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(hits[0].field, "Subject");
+        assert_eq!(hits[1].field, "X-Note");
+        let third = &hits[2];
+        assert_eq!(third.field, "body");
+        assert_eq!(third.match_count, 2);
+        assert_eq!(third.offset, (messages[0].len() + messages[1].len()) as u64);
+        let at = usize::try_from(third.match_offset).unwrap();
+        assert_eq!(&mbox[at..at + 7], "INVOICE");
+        assert_eq!(third.snippet, "the INVOICE #7 and in...");
+
+        let hits = search(r"invoice #\d+", true, false, None, true);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].index, 2);
+        let at = usize::try_from(hits[0].match_offset).unwrap();
+        assert_eq!(&mbox[at..at + 10], "invoice #8");
+
+        assert_eq!(search("invoice", false, true, Some(vec!["subject"]), false).len(), 1);
+        assert!(search("Subject", false, false, None, false).is_empty());
+        assert!(crate::with_py(|py| {
+            search_mbox_fast(
+                py, path, "(", true, false, None, true, 10, None, "standard", None, None,
+            )
+        })
+        .is_err());
+        let timeout = crate::with_py(|py| {
+            search_mbox_fast(
+                py,
+                path,
+                "x",
+                false,
+                false,
+                None,
+                true,
+                10,
+                None,
+                "standard",
+                Some(0.0),
+                None,
+            )
+            .unwrap_err()
+            .value(py)
+            .getattr("partial")
+            .and_then(|partial| partial.len())
+            .unwrap()
+        });
+        assert_eq!(timeout, 0);
+        crate::with_py(|py| {
+            let stop = py.eval(c"lambda done, total: False", None, None).unwrap();
+            let progress = Some(stop.unbind());
+            let err = search_mbox_fast(
+                py, path, "invoice", false, true, None, true, 10, None, "standard", None, progress,
+            )
+            .unwrap_err();
+            assert!(err.is_instance_of::<crate::progress::ScanCancelled>(py));
+            let partial = err.value(py).getattr("partial").unwrap();
+            assert_eq!(partial.len().unwrap(), 3);
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_regex_scan_file() {
+        let dir = std::env::temp_dir().join(format!("mpr_scan_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.bin");
+        let data = b"From a\ncard 4111111111111111 and 4000000000000002\xff\n\
+                     aaa bbb aaaa word$ end\nlast line";
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_str().unwrap();
+
+        // Any chunking finds what one pass over the whole file finds, given
+        // an overlap as long as the longest match; without one, matches cut
+        // short by the window end are still completed
+        let patterns = [
+            r"(?m)^\w+",
+            r"a+",
+            r"(?m)\w+$",
+            r"\w*",
+            r"(?-u)\xff\n",
+            r"\b4\d{15}\b",
+        ];
+        for (k, pattern) in patterns.into_iter().enumerate() {
+            let re = Regex::new(pattern).unwrap();
+            let expected: Vec<(usize, usize)> = re
+                .find_iter(data)
+                .filter(|m| !m.is_empty())
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            for chunk_size in 1..12 {
+                for overlap in [0, 16, 100] {
+                    if overlap == 0 && k >= 4 {
+                        continue;
+                    }
+                    let found = crate::with_py(|py| {
+                        regex_scan_file(py, path, pattern, chunk_size, overlap, None, None)
+                    })
+                    .unwrap();
+                    assert_eq!(found, expected, "{pattern} in chunks of {chunk_size}+{overlap}");
+                }
+            }
+        }
+
+        crate::with_py(|py| {
+            let cards =
+                regex_scan_file(py, path, r"\d{16}", SCAN_CHUNK, SCAN_OVERLAP, None, None).unwrap();
+            assert_eq!(&data[cards[1].0..cards[1].1], b"4000000000000002");
+            assert!(regex_scan_file(py, path, "(", 10, 0, None, None).is_err());
+            assert!(regex_scan_file(py, path, "a", 0, 0, None, None).is_err());
+            assert!(regex_scan_file(py, "/nonexistent/file", "a", 10, 0, None, None).is_err());
+            let timeout = regex_scan_file(py, path, "a", 10, 0, Some(0.0), None).unwrap_err();
+            assert!(timeout.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py));
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,646 @@
+//! Persistent full-text index of an mbox, backed by Tantivy.
+//!
+//! `search_mbox_fast` rescans the whole archive for every query; this module
+//! indexes subject, sender, recipients, date and decoded body text once into
+//! a directory, so interactive tools answer queries without touching the
+//! mbox. Built with the `search-index` feature, which pulls in `tantivy`.
+//!
+//! Each commit records what it covers (archive size, separator rule and
+//! the fingerprint from [`crate::offset_index`]) in the commit payload.
+//! Rebuilding after the archive grew only indexes the new messages (and the
+//! last one, which may have grown); a shorter or rewritten archive, or a
+//! different `from_line_mode`, is indexed from scratch. Compressed archives
+//! are indexed from a decompressing stream, a batch of messages at a time.
+
+use crate::compressed::{self, Codec};
+use crate::date::{format_utc, parse_date};
+use crate::mbox::{map_file, message_spans_with, FromLineMode};
+use crate::mime::{first_address, Message};
+use crate::offset_index::{fingerprint, freshness, Freshness};
+use crate::paths::extended;
+use crate::progress::Progress;
+use crate::uid::uid_of;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{AllQuery, Query, QueryParser};
+use tantivy::schema::{Field, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT};
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, TantivyDocument, Term};
+
+/// Layout version of the commit payload; other versions are rebuilt
+const STATE_VERSION: u32 = 1;
+
+/// Writer heap shared by Tantivy's indexing threads
+const WRITER_HEAP_BYTES: usize = 64 * 1024 * 1024;
+
+/// Messages parsed in parallel before being handed to the writer
+const BATCH: usize = 1024;
+
+/// What the last commit of an index covers
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct IndexState {
+    version: u32,
+    source_len: u64,
+    fingerprint: u64,
+    from_line_mode: String,
+    /// Number of indexed messages
+    messages: u64,
+    /// Byte offset of the last indexed message
+    last_offset: u64,
+}
+
+/// Fields of the index schema
+struct Fields {
+    position: Field,
+    offset: Field,
+    length: Field,
+    uid: Field,
+    subject: Field,
+    from: Field,
+    to: Field,
+    date: Field,
+    body: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Self) {
+        let mut builder = Schema::builder();
+        let fields = Self {
+            position: builder.add_u64_field("position", INDEXED | STORED | FAST),
+            offset: builder.add_u64_field("offset", STORED),
+            length: builder.add_u64_field("length", STORED),
+            uid: builder.add_text_field("uid", STRING | STORED),
+            subject: builder.add_text_field("subject", TEXT | STORED),
+            from: builder.add_text_field("from", TEXT | STORED),
+            to: builder.add_text_field("to", TEXT | STORED),
+            date: builder.add_i64_field("date", INDEXED | STORED | FAST),
+            body: builder.add_text_field("body", TEXT),
+        };
+        (builder.build(), fields)
+    }
+
+    /// Document for the message at `span` of the archive
+    fn document(&self, position: usize, span: &Range<usize>, raw: &[u8]) -> TantivyDocument {
+        let msg = Message::parse(raw);
+        let mut doc = TantivyDocument::default();
+        doc.add_u64(self.position, to_u64(position));
+        doc.add_u64(self.offset, to_u64(span.start));
+        doc.add_u64(self.length, to_u64(span.len()));
+        doc.add_text(self.uid, uid_of(&msg));
+        doc.add_text(self.subject, msg.decoded_header("Subject").unwrap_or_default());
+        doc.add_text(self.from, msg.decoded_header("From").unwrap_or_default());
+        doc.add_text(self.to, msg.decoded_header("To").unwrap_or_default());
+        if let Some((epoch, _)) = msg.header("Date").and_then(parse_date) {
+            doc.add_i64(self.date, epoch);
+        }
+        doc.add_text(self.body, msg.text_body());
+        doc
+    }
+}
+
+fn to_u64(value: usize) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+fn index_error(index_dir: &str, e: &impl std::fmt::Display) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Search index {index_dir}: {e}"))
+}
+
+/// Error for an index whose schema is not the current one
+fn schema_error(index_dir: &str) -> PyErr {
+    index_error(
+        index_dir,
+        &"not a build_search_index index of this version (remove it to rebuild)",
+    )
+}
+
+/// Open the index in `index_dir`, creating the directory and an empty index
+/// when missing
+fn open_index(index_dir: &str, schema: Schema, create: bool) -> PyResult<Index> {
+    let dir = extended(Path::new(index_dir));
+    if create {
+        crate::paths::create_dir_all(Path::new(index_dir))
+            .map_err(|e| index_error(index_dir, &e))?;
+    }
+    let directory = MmapDirectory::open(&dir).map_err(|e| index_error(index_dir, &e))?;
+    if create {
+        Index::open_or_create(directory, schema)
+    } else {
+        Index::open(directory)
+    }
+    .map_err(|e| match e {
+        tantivy::TantivyError::SchemaError(_) => schema_error(index_dir),
+        e => index_error(index_dir, &e),
+    })
+}
+
+/// What the last commit of `index` covers, None if it was built differently
+fn last_state(index: &Index, from_line_mode: &str) -> tantivy::Result<Option<IndexState>> {
+    Ok(index
+        .load_metas()?
+        .payload
+        .and_then(|payload| serde_json::from_str::<IndexState>(&payload).ok())
+        .filter(|state| state.version == STATE_VERSION && state.from_line_mode == from_line_mode))
+}
+
+/// Commit what `writer` added, recording `state` as the payload
+fn commit(mut writer: IndexWriter, state: &IndexState, index_dir: &str) -> PyResult<()> {
+    let fail = |e: tantivy::TantivyError| index_error(index_dir, &e);
+    let payload = serde_json::to_string(state).map_err(|e| index_error(index_dir, &e))?;
+    let mut commit = writer.prepare_commit().map_err(fail)?;
+    commit.set_payload(&payload);
+    commit.commit().map_err(fail)?;
+    writer.wait_merging_threads().map_err(fail)
+}
+
+/// Result of `build_search_index`
+#[derive(Clone, Debug, Default)]
+#[pyclass]
+pub struct SearchIndexReport {
+    /// Directory holding the index
+    #[pyo3(get)]
+    pub index_dir: String,
+    /// Messages in the index after the update
+    #[pyo3(get)]
+    pub messages: u64,
+    /// Messages (re)indexed by this call
+    #[pyo3(get)]
+    pub indexed: u64,
+    /// Whether the index was rebuilt from scratch
+    #[pyo3(get)]
+    pub rebuilt: bool,
+}
+
+#[pymethods]
+impl SearchIndexReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchIndexReport(messages={}, indexed={}, rebuilt={})",
+            self.messages,
+            self.indexed,
+            if self.rebuilt { "True" } else { "False" }
+        )
+    }
+}
+
+/// One `query_index` result
+#[derive(Clone, Debug, Default)]
+#[pyclass]
+pub struct IndexHit {
+    /// Position of the message in the mbox (see `get_message_by_index`)
+    #[pyo3(get)]
+    pub position: u64,
+    /// Byte offset of the message in the mbox
+    #[pyo3(get)]
+    pub offset: u64,
+    /// Length of the message in bytes
+    #[pyo3(get)]
+    pub length: u64,
+    /// Stable message identifier (see `compute_message_uid`)
+    #[pyo3(get)]
+    pub uid: String,
+    /// BM25 relevance score
+    #[pyo3(get)]
+    pub score: f32,
+    /// Decoded subject
+    #[pyo3(get)]
+    pub subject: String,
+    /// Decoded From header
+    #[pyo3(get)]
+    pub from_addr: String,
+    /// Decoded To header
+    #[pyo3(get)]
+    pub to: String,
+    /// Date as a Unix timestamp, when parseable
+    #[pyo3(get)]
+    pub date: Option<i64>,
+}
+
+#[pymethods]
+impl IndexHit {
+    fn __repr__(&self) -> String {
+        format!(
+            "IndexHit(position={}, uid={:?}, score={:.3}, subject={:?})",
+            self.position, self.uid, self.score, self.subject
+        )
+    }
+}
+
+/// Build or update a persistent full-text index of an mbox
+///
+/// Subject, From, To (decoded), Date and the readable body text of every
+/// message are indexed into `index_dir`. Calling it again after the mbox
+/// grew indexes only the appended messages; a truncated or rewritten mbox,
+/// or a different `from_line_mode`, is reindexed from scratch. A gzip, zstd
+/// or xz compressed mbox is decompressed as a stream and reindexed whenever
+/// it changes; hit offsets then refer to the decompressed data.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `index_dir` - Directory for the index (created if missing)
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)` while
+///   messages are indexed; returning False cancels the build and leaves the
+///   index as it was. A compressed mbox is reported only when done
+///
+/// # Returns
+/// * `SearchIndexReport` with the total `messages`, the number `indexed` by
+///   this call and whether the index was `rebuilt`
+///
+/// # Raises
+/// * `IOError` - The mbox or index cannot be read or written (another
+///   process updating the same index holds its lock)
+/// * `FileChangedError` - The mbox changed during the scan
+/// * `ScanCancelled` - `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import build_search_index, query_index
+/// build_search_index("inbox.mbox", "inbox.search")
+/// for hit in query_index("inbox.search", 'subject:invoice AND from:acme'):
+///     print(hit.position, hit.subject)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, index_dir, from_line_mode="standard", progress=None))]
+pub fn build_search_index(
+    py: Python<'_>,
+    mbox_path: &str,
+    index_dir: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<SearchIndexReport> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let (schema, fields) = Fields::schema();
+        let index = open_index(index_dir, schema, true)?;
+        let fail = |e: tantivy::TantivyError| index_error(index_dir, &e);
+        let previous = last_state(&index, from_line_mode).map_err(fail)?;
+
+        let mmap = map_file(mbox_path)?;
+        let data = &mmap[..];
+        let codec = Codec::detect(data);
+        let progress = Progress::new(progress, data.len());
+        let state = previous
+            .as_ref()
+            .map_or(Freshness::Stale, |state| freshness(data, state.source_len, state.fingerprint));
+        let (rebuilt, start, first_position) = match (&state, &previous) {
+            (Freshness::Current, Some(previous)) => {
+                return Ok(SearchIndexReport {
+                    index_dir: index_dir.to_string(),
+                    messages: previous.messages,
+                    indexed: 0,
+                    rebuilt: false,
+                });
+            },
+            // The last message may have grown too, so reindex from its start
+            (Freshness::Grown, Some(previous)) if codec.is_none() => (
+                false,
+                usize::try_from(previous.last_offset).unwrap_or(0),
+                previous.messages.saturating_sub(1),
+            ),
+            _ => (true, 0, 0),
+        };
+
+        let writer: IndexWriter = index.writer(WRITER_HEAP_BYTES).map_err(fail)?;
+        if rebuilt {
+            writer.delete_all_documents().map_err(fail)?;
+        } else {
+            writer.delete_term(Term::from_field_u64(fields.position, first_position));
+        }
+        let base = usize::try_from(first_position).unwrap_or(0);
+        let (mut indexed, mut last_start) = (0, 0);
+        progress.advance(start);
+        let mut add_batch = |batch: &[(Range<usize>, &[u8])]| -> PyResult<()> {
+            // Nothing is committed yet, so a cancelled build changes nothing
+            if progress.cancelled() {
+                return Err(progress.error("build_search_index"));
+            }
+            let docs: Vec<TantivyDocument> = batch
+                .par_iter()
+                .enumerate()
+                .map(|(i, (span, raw))| fields.document(base + indexed + i, span, raw))
+                .collect();
+            for doc in docs {
+                writer.add_document(doc).map_err(fail)?;
+            }
+            indexed += batch.len();
+            last_start = batch.last().map_or(last_start, |(span, _)| span.start);
+            // Compressed spans are decompressed offsets, not file bytes
+            if codec.is_none() {
+                progress.advance(batch.iter().map(|(_, raw)| raw.len()).sum());
+            }
+            Ok(())
+        };
+        let added = if let Some(codec) = codec {
+            compressed::for_each_batch(data, codec, &mode, BATCH, &mut add_batch)
+        } else {
+            let spans: Vec<Range<usize>> = if rebuilt {
+                mmap.spans(&mode)
+            } else {
+                message_spans_with(&data[start..], &mode)
+                    .into_iter()
+                    .map(|span| start + span.start..start + span.end)
+                    .collect()
+            };
+            spans.chunks(BATCH).try_for_each(|chunk| {
+                let batch: Vec<_> = chunk
+                    .iter()
+                    .map(|span| (span.clone(), &data[span.clone()]))
+                    .collect();
+                add_batch(&batch)
+            })
+        };
+        // A file cut short reads as corrupt compressed data; report the change
+        mmap.verify(&mode)?;
+        added?;
+        if !progress.finish() {
+            return Err(progress.error("build_search_index"));
+        }
+
+        let messages = first_position + to_u64(indexed);
+        // A grown archive is rescanned from its last message, so nothing is
+        // indexed only for an empty archive
+        let last_offset = to_u64(last_start);
+        let state = IndexState {
+            version: STATE_VERSION,
+            source_len: to_u64(data.len()),
+            fingerprint: fingerprint(data),
+            from_line_mode: from_line_mode.to_string(),
+            messages,
+            last_offset,
+        };
+        commit(writer, &state, index_dir)?;
+        Ok(SearchIndexReport {
+            index_dir: index_dir.to_string(),
+            messages,
+            indexed: to_u64(indexed),
+            rebuilt,
+        })
+    })
+}
+
+/// A searcher over the index in `index_dir` and `query` parsed for it
+/// (every message when empty)
+fn search(index_dir: &str, query: &str) -> PyResult<(Searcher, Fields, Box<dyn Query>)> {
+    let (schema, fields) = Fields::schema();
+    let index = open_index(index_dir, schema.clone(), false)?;
+    if index.schema() != schema {
+        return Err(schema_error(index_dir));
+    }
+    let reader: IndexReader = index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()
+        .map_err(|e| index_error(index_dir, &e))?;
+    let parsed: Box<dyn Query> = if query.trim().is_empty() {
+        Box::new(AllQuery)
+    } else {
+        QueryParser::for_index(&index, vec![fields.subject, fields.from, fields.to, fields.body])
+            .parse_query(query)
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid query: {e}"))
+            })?
+    };
+    Ok((reader.searcher(), fields, parsed))
+}
+
+/// Query an index made by `build_search_index`
+///
+/// Queries use Tantivy's syntax: bare terms match subject, from, to and
+/// body; `field:term` restricts a term (`subject:invoice`,
+/// `from:alice@example.com`); `"..."` matches a phrase; `AND`, `OR`, `-term`
+/// and parentheses combine clauses; `date:[1700000000 TO 1710000000]`
+/// selects a range of Unix timestamps; `uid:<uid>` finds one message by its
+/// `compute_message_uid`. Matching ignores case, except for `uid`.
+///
+/// # Arguments
+/// * `index_dir` - Directory of the index
+/// * `query` - Query string
+/// * `limit` - Maximum number of hits
+///
+/// # Returns
+/// * List of `IndexHit`, best match first: message `position`, `offset`,
+///   `length`, `uid`, `score`, `subject`, `from_addr`, `to` and `date`
+///
+/// # Raises
+/// * `ValueError` - The query cannot be parsed
+/// * `IOError` - No index in `index_dir`, or it cannot be read
+#[pyfunction]
+#[pyo3(signature = (index_dir, query, limit=10))]
+pub fn query_index(
+    py: Python<'_>,
+    index_dir: &str,
+    query: &str,
+    limit: usize,
+) -> PyResult<Vec<IndexHit>> {
+    py.allow_threads(|| {
+        let (searcher, fields, parsed) = search(index_dir, query)?;
+        let fail = |e: tantivy::TantivyError| index_error(index_dir, &e);
+        let top = searcher
+            .search(&parsed, &TopDocs::with_limit(limit.max(1)))
+            .map_err(fail)?;
+        top.into_iter()
+            .take(limit)
+            .map(|(score, address)| {
+                let doc: TantivyDocument = searcher.doc(address).map_err(fail)?;
+                let number = |field| doc.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0);
+                let text = |field| {
+                    doc.get_first(field)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                Ok(IndexHit {
+                    position: number(fields.position),
+                    offset: number(fields.offset),
+                    length: number(fields.length),
+                    uid: text(fields.uid),
+                    score,
+                    subject: text(fields.subject),
+                    from_addr: text(fields.from),
+                    to: text(fields.to),
+                    date: doc.get_first(fields.date).and_then(|v| v.as_i64()),
+                })
+            })
+            .collect()
+    })
+}
+
+/// Facets `aggregate_index` can bucket by
+const FACETS: &[&str] = &["from_domain", "year", "month"];
+
+/// Count the messages of an index made by `build_search_index` per facet
+/// bucket, without touching the mbox
+///
+/// The facets are those of `ArchiveSet.aggregate` that the index stores:
+/// `"from_domain"` (sender domain), "year" and "month" (UTC, "2024" /
+/// "2024-03"). A message without a sender address or date is not counted.
+///
+/// # Arguments
+/// * `index_dir` - Directory of the index
+/// * `query` - Query string as for `query_index` (empty: every message)
+/// * `facet` - Facet name (default `"from_domain"`)
+/// * `limit` - Return at most this many buckets
+///
+/// # Returns
+/// * `(bucket, count)` pairs, largest count first (ties by bucket)
+///
+/// # Raises
+/// * `ValueError` - Unknown facet, or the query cannot be parsed
+/// * `IOError` - No index in `index_dir`, or it cannot be read
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import aggregate_index
+/// for domain, count in aggregate_index("inbox.search", "invoice", limit=10):
+///     print(domain, count)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (index_dir, query, facet="from_domain", limit=None))]
+pub fn aggregate_index(
+    py: Python<'_>,
+    index_dir: &str,
+    query: &str,
+    facet: &str,
+    limit: Option<usize>,
+) -> PyResult<Vec<(String, usize)>> {
+    if !FACETS.contains(&facet) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unknown facet: {facet} (expected one of {})",
+            FACETS.join(", ")
+        )));
+    }
+    py.allow_threads(|| {
+        let (searcher, fields, parsed) = search(index_dir, query)?;
+        let fail = |e: tantivy::TantivyError| index_error(index_dir, &e);
+        let matched: Vec<_> = searcher
+            .search(&parsed, &DocSetCollector)
+            .map_err(fail)?
+            .into_iter()
+            .collect();
+        let keys = matched
+            .into_par_iter()
+            .map(|address| {
+                let doc: TantivyDocument = searcher.doc(address).map_err(fail)?;
+                Ok(if facet == "from_domain" {
+                    doc.get_first(fields.from)
+                        .and_then(|v| v.as_str())
+                        .and_then(first_address)
+                        .and_then(|a| a.rsplit_once('@').map(|(_, d)| d.to_ascii_lowercase()))
+                } else {
+                    let chars = if facet == "year" { 4 } else { 7 };
+                    doc.get_first(fields.date)
+                        .and_then(|v| v.as_i64())
+                        .map(|epoch| format_utc(epoch)[..chars].to_string())
+                })
+            })
+            .collect::<PyResult<Vec<Option<String>>>>()?;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for key in keys.into_iter().flatten() {
+            *counts.entry(key).or_default() += 1;
+        }
+        let mut buckets: Vec<(String, usize)> = counts.into_iter().collect();
+        buckets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        buckets.truncate(limit.unwrap_or(usize::MAX));
+        Ok(buckets)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(n: usize, subject: &str, body: &str) -> String {
+        format!(
+            "From a@x Mon Jan  1 00:00:00 2024\nFrom: Sender {n} <s{n}@example.com>\n\
+             To: team@example.org\nSubject: {subject}\nDate: Mon, 1 Jan 2024 00:00:{n:02} +0000\n\n\
+             {body}\n\n"
+        )
+    }
+
+    #[test]
+    fn test_build_and_query() {
+        let dir = std::env::temp_dir().join(format!("mpr_search_index_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mbox = dir.join("in.mbox");
+        let index_dir = dir.join("index");
+        let (mbox, index_dir) = (mbox.to_str().unwrap(), index_dir.to_str().unwrap());
+        let build =
+            || crate::with_py(|py| build_search_index(py, mbox, index_dir, "standard", None));
+        let query = |q: &str| crate::with_py(|py| query_index(py, index_dir, q, 10));
+        let positions = |q: &str| {
+            let mut hits: Vec<u64> = query(q).unwrap().iter().map(|h| h.position).collect();
+            hits.sort_unstable();
+            hits
+        };
+
+        let first = [
+            message(0, "Invoice 42", "Please pay the invoice."),
+            message(1, "lunch", "Tacos at noon?"),
+        ]
+        .concat();
+        std::fs::write(mbox, &first).unwrap();
+        let report = build().unwrap();
+        assert_eq!((report.messages, report.indexed, report.rebuilt), (2, 2, true));
+        assert_eq!(positions("invoice"), [0]);
+        assert_eq!(positions("TACOS"), [1]);
+        assert_eq!(positions("from:s1@example.com"), [1]);
+        assert_eq!(positions("to:team"), [0, 1]);
+        assert_eq!(positions("date:[1704067201 TO 1704067300]"), [1]);
+        let hit = &query("subject:invoice").unwrap()[0];
+        assert_eq!(hit.subject, "Invoice 42");
+        assert_eq!(hit.from_addr, "Sender 0 <s0@example.com>");
+        assert_eq!(hit.date, Some(1_704_067_200));
+        let uid =
+            crate::uid::message_uid(message(0, "Invoice 42", "Please pay the invoice.").as_bytes());
+        assert_eq!(hit.uid, uid);
+        assert_eq!(positions(&format!("uid:{uid}")), [0]);
+        assert_eq!(
+            &first.as_bytes()[..usize::try_from(hit.length).unwrap()],
+            message(0, "Invoice 42", "Please pay the invoice.").as_bytes()
+        );
+
+        // Unchanged: nothing to do
+        assert_eq!(build().unwrap().indexed, 0);
+
+        // Appended: only the last indexed message and the new ones
+        let grown = first.clone() + &message(2, "re: lunch", "Invoice attached after all.");
+        std::fs::write(mbox, &grown).unwrap();
+        let report = build().unwrap();
+        assert_eq!((report.messages, report.indexed, report.rebuilt), (3, 2, false));
+        assert_eq!(positions("invoice"), [0, 2]);
+        assert_eq!(positions("lunch"), [1, 2]);
+        let hit = &query("attached").unwrap()[0];
+        assert_eq!(hit.offset, to_u64(first.len()));
+
+        // Rewritten: reindexed from scratch
+        std::fs::write(mbox, message(0, "other", "nothing")).unwrap();
+        let report = build().unwrap();
+        assert_eq!((report.messages, report.indexed, report.rebuilt), (1, 1, true));
+        assert!(positions("invoice").is_empty());
+
+        // Compressed: decompressed as a stream, reindexed whenever it changes
+        std::fs::write(mbox, compressed::compress(grown.as_bytes(), Codec::Gzip)).unwrap();
+        let report = build().unwrap();
+        assert_eq!((report.messages, report.indexed, report.rebuilt), (3, 3, true));
+        assert_eq!(positions("attached"), [2]);
+        assert_eq!(query("attached").unwrap()[0].offset, to_u64(first.len()));
+        assert_eq!(build().unwrap().indexed, 0);
+
+        let aggregate = |q: &str, facet: &str| {
+            crate::with_py(|py| aggregate_index(py, index_dir, q, facet, None))
+        };
+        assert_eq!(aggregate("", "from_domain").unwrap(), [("example.com".to_string(), 3)]);
+        assert_eq!(aggregate("lunch", "month").unwrap(), [("2024-01".to_string(), 2)]);
+        assert!(aggregate("", "source").is_err());
+        assert!(aggregate("subject:(", "year").is_err());
+        assert!(query("subject:(").is_err());
+        assert!(crate::with_py(|py| query_index(py, dir.to_str().unwrap(), "x", 10)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
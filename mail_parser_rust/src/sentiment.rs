@@ -0,0 +1,558 @@
+//! Lexicon-based sentiment and emotion scoring (VADER-style).
+//!
+//! Each word found in the lexicon contributes its valence, adjusted for
+//! preceding intensifiers ("very"), negations ("not", "never", "n't"), ALL-CAPS
+//! emphasis and a contrastive "but" (the clause after it counts more). The
+//! sum plus `!`/`?` emphasis is normalized into a compound score in [-1, 1].
+//! The constants follow the published VADER heuristics; the lexicon is a
+//! compact subset aimed at correspondence, not the full VADER word list.
+
+use crate::batch::par_map;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Normalization constant for the compound score
+const ALPHA: f64 = 15.0;
+/// Valence change from an intensifier or dampener
+const BOOST: f64 = 0.293;
+/// Valence change from ALL-CAPS emphasis
+const CAPS_BOOST: f64 = 0.733;
+/// Multiplier applied to a negated word
+const NEGATION: f64 = -0.74;
+/// Emphasis per `!` (up to four)
+const EXCLAMATION: f64 = 0.292;
+/// Emphasis per `?` when there are two or three
+const QUESTION: f64 = 0.18;
+
+/// Word valences on VADER's -4..4 scale
+const LEXICON: &[(&str, f64)] = &[
+    ("abandon", -1.9),
+    ("abuse", -3.2),
+    ("accept", 1.6),
+    ("accepted", 1.1),
+    ("accomplished", 1.9),
+    ("admire", 2.1),
+    ("afraid", -2.2),
+    ("aggressive", -1.2),
+    ("agree", 1.5),
+    ("amazing", 2.8),
+    ("angry", -2.3),
+    ("annoyed", -1.6),
+    ("annoying", -1.8),
+    ("anxious", -1.0),
+    ("appreciate", 1.7),
+    ("appreciated", 2.3),
+    ("apology", 0.2),
+    ("awesome", 3.1),
+    ("awful", -2.0),
+    ("bad", -2.5),
+    ("beautiful", 2.9),
+    ("benefit", 2.0),
+    ("best", 3.2),
+    ("better", 1.9),
+    ("blame", -1.4),
+    ("bored", -1.1),
+    ("boring", -1.3),
+    ("brilliant", 2.8),
+    ("broken", -2.1),
+    ("calm", 1.3),
+    ("cancel", -1.0),
+    ("cancelled", -1.0),
+    ("care", 2.2),
+    ("celebrate", 2.7),
+    ("cheerful", 2.5),
+    ("complain", -1.5),
+    ("complaint", -1.2),
+    ("concern", -0.6),
+    ("concerned", -0.9),
+    ("confused", -1.3),
+    ("congrats", 2.4),
+    ("congratulations", 2.9),
+    ("cool", 1.3),
+    ("crap", -1.6),
+    ("crisis", -3.1),
+    ("critical", -0.7),
+    ("cry", -2.1),
+    ("damage", -2.2),
+    ("danger", -2.4),
+    ("dead", -3.3),
+    ("delay", -1.3),
+    ("delayed", -1.2),
+    ("delight", 2.9),
+    ("delighted", 3.2),
+    ("depressed", -2.3),
+    ("desperate", -1.3),
+    ("disappointed", -1.9),
+    ("disappointing", -2.2),
+    ("disaster", -3.1),
+    ("dislike", -1.6),
+    ("disgusting", -2.4),
+    ("dread", -2.0),
+    ("eager", 1.5),
+    ("easy", 1.9),
+    ("enjoy", 2.2),
+    ("enjoyed", 2.3),
+    ("error", -1.7),
+    ("excellent", 2.7),
+    ("excited", 1.4),
+    ("exciting", 2.2),
+    ("fail", -2.5),
+    ("failed", -2.3),
+    ("failure", -2.3),
+    ("fair", 1.3),
+    ("fantastic", 2.6),
+    ("fault", -1.7),
+    ("fear", -2.2),
+    ("fine", 0.8),
+    ("fix", 0.8),
+    ("fixed", 1.1),
+    ("frustrated", -2.4),
+    ("frustrating", -1.9),
+    ("fun", 2.3),
+    ("furious", -2.7),
+    ("glad", 2.0),
+    ("good", 1.9),
+    ("grateful", 2.0),
+    ("great", 3.1),
+    ("happy", 2.7),
+    ("hate", -2.7),
+    ("helpful", 1.8),
+    ("hope", 1.9),
+    ("hopeful", 1.5),
+    ("horrible", -2.5),
+    ("hurt", -2.4),
+    ("ideal", 2.4),
+    ("impressed", 2.1),
+    ("impressive", 2.3),
+    ("issue", -0.7),
+    ("joy", 2.8),
+    ("kind", 2.4),
+    ("late", -0.7),
+    ("like", 2.0),
+    ("lost", -1.3),
+    ("love", 3.2),
+    ("lovely", 2.8),
+    ("mad", -2.2),
+    ("mess", -1.5),
+    ("miss", -0.6),
+    ("mistake", -1.4),
+    ("nice", 1.8),
+    ("no", -1.2),
+    ("ok", 0.9),
+    ("okay", 0.9),
+    ("outstanding", 3.0),
+    ("overdue", -1.0),
+    ("pain", -2.3),
+    ("panic", -2.3),
+    ("perfect", 2.7),
+    ("pleased", 1.9),
+    ("pleasure", 2.7),
+    ("poor", -2.1),
+    ("positive", 2.6),
+    ("problem", -1.7),
+    ("problems", -1.7),
+    ("proud", 2.1),
+    ("regret", -1.8),
+    ("relieved", 1.5),
+    ("reject", -1.7),
+    ("rejected", -2.3),
+    ("resolved", 0.7),
+    ("rude", -2.0),
+    ("sad", -2.1),
+    ("safe", 1.9),
+    ("scared", -1.9),
+    ("shame", -2.1),
+    ("shock", -1.6),
+    ("shocked", -1.3),
+    ("smile", 1.5),
+    ("sorry", -0.3),
+    ("stress", -1.8),
+    ("stressed", -1.4),
+    ("stupid", -2.4),
+    ("success", 2.7),
+    ("successful", 2.8),
+    ("super", 2.9),
+    ("support", 1.7),
+    ("surprise", 1.1),
+    ("surprised", 0.9),
+    ("terrible", -2.1),
+    ("thank", 1.5),
+    ("thanks", 1.9),
+    ("threat", -2.4),
+    ("trouble", -1.7),
+    ("trust", 2.3),
+    ("ugly", -2.3),
+    ("unacceptable", -2.0),
+    ("unfortunately", -1.7),
+    ("unhappy", -1.8),
+    ("upset", -1.6),
+    ("urgent", -0.4),
+    ("useful", 1.9),
+    ("useless", -1.8),
+    ("welcome", 2.0),
+    ("win", 2.8),
+    ("wonderful", 2.7),
+    ("worried", -1.2),
+    ("worry", -1.9),
+    ("worse", -2.1),
+    ("worst", -3.1),
+    ("wow", 2.8),
+    ("wrong", -2.1),
+];
+
+/// Words raising (positive) or lowering (negative) the next valence
+const BOOSTERS: &[(&str, f64)] = &[
+    ("absolutely", BOOST),
+    ("completely", BOOST),
+    ("deeply", BOOST),
+    ("especially", BOOST),
+    ("extremely", BOOST),
+    ("greatly", BOOST),
+    ("highly", BOOST),
+    ("incredibly", BOOST),
+    ("really", BOOST),
+    ("so", BOOST),
+    ("totally", BOOST),
+    ("truly", BOOST),
+    ("very", BOOST),
+    ("barely", -BOOST),
+    ("hardly", -BOOST),
+    ("kinda", -BOOST),
+    ("less", -BOOST),
+    ("marginally", -BOOST),
+    ("slightly", -BOOST),
+    ("somewhat", -BOOST),
+];
+
+/// Words that flip the valence of the next few words
+const NEGATIONS: &[&str] = &[
+    "aint", "cannot", "cant", "didnt", "doesnt", "dont", "hardly", "isnt", "neither", "never",
+    "no", "nobody", "none", "nor", "not", "nothing", "nowhere", "wasnt", "without", "wont",
+    "wouldnt",
+];
+
+/// Emotion categories and the lexicon words signalling them
+const EMOTIONS: &[(&str, &[&str])] = &[
+    (
+        "anger",
+        &[
+            "abuse",
+            "aggressive",
+            "angry",
+            "annoyed",
+            "annoying",
+            "blame",
+            "frustrated",
+            "frustrating",
+            "furious",
+            "hate",
+            "mad",
+            "rude",
+            "stupid",
+            "unacceptable",
+        ],
+    ),
+    (
+        "fear",
+        &[
+            "afraid",
+            "anxious",
+            "concerned",
+            "crisis",
+            "danger",
+            "dread",
+            "fear",
+            "panic",
+            "scared",
+            "stress",
+            "stressed",
+            "threat",
+            "worried",
+            "worry",
+        ],
+    ),
+    (
+        "joy",
+        &[
+            "amazing",
+            "awesome",
+            "celebrate",
+            "cheerful",
+            "delight",
+            "delighted",
+            "enjoy",
+            "enjoyed",
+            "fun",
+            "glad",
+            "great",
+            "happy",
+            "joy",
+            "love",
+            "lovely",
+            "pleased",
+            "pleasure",
+            "proud",
+            "smile",
+            "wonderful",
+        ],
+    ),
+    (
+        "sadness",
+        &[
+            "cry",
+            "depressed",
+            "disappointed",
+            "disappointing",
+            "lost",
+            "miss",
+            "regret",
+            "sad",
+            "sorry",
+            "unfortunately",
+            "unhappy",
+            "upset",
+        ],
+    ),
+    ("surprise", &["shock", "shocked", "surprise", "surprised", "wow"]),
+    (
+        "trust",
+        &[
+            "agree",
+            "appreciate",
+            "appreciated",
+            "grateful",
+            "helpful",
+            "safe",
+            "support",
+            "thank",
+            "thanks",
+            "trust",
+        ],
+    ),
+];
+
+lazy_static! {
+    /// `LEXICON` as a map
+    pub static ref VALENCES: HashMap<&'static str, f64> = LEXICON.iter().copied().collect();
+    /// Word to the emotions it signals
+    pub static ref EMOTION_WORDS: HashMap<&'static str, Vec<&'static str>> = {
+        let mut map: HashMap<&'static str, Vec<&'static str>> = HashMap::new();
+        for (emotion, words) in EMOTIONS {
+            for word in *words {
+                map.entry(*word).or_default().push(*emotion);
+            }
+        }
+        map
+    };
+}
+
+/// Sentiment and emotion scores for one text
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct Sentiment {
+    /// Normalized overall score from -1 (most negative) to 1 (most positive)
+    #[pyo3(get)]
+    pub compound: f64,
+    /// Share of positive sentiment (pos + neg + neu = 1)
+    #[pyo3(get)]
+    pub pos: f64,
+    /// Share of negative sentiment
+    #[pyo3(get)]
+    pub neg: f64,
+    /// Share of neutral words
+    #[pyo3(get)]
+    pub neu: f64,
+    /// Emotion name to number of non-negated words signalling it
+    #[pyo3(get)]
+    pub emotions: BTreeMap<String, usize>,
+}
+
+/// Lowercase word with apostrophes removed ("Don't" -> "dont")
+fn normalize(token: &str) -> String {
+    token
+        .chars()
+        .filter(|&c| c != '\'' && c != '’')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Word and punctuation counts, converted for scoring
+#[allow(clippy::cast_precision_loss)]
+const fn count_f64(n: usize) -> f64 {
+    n as f64
+}
+
+fn is_shouted(token: &str) -> bool {
+    token.chars().any(char::is_alphabetic) && !token.chars().any(char::is_lowercase)
+}
+
+fn score(text: &str) -> Sentiment {
+    let tokens: Vec<&str> = text
+        .split_whitespace()
+        .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric() && c != '\''))
+        .filter(|t| !t.is_empty())
+        .collect();
+    let words: Vec<String> = tokens.iter().map(|t| normalize(t)).collect();
+    // Caps only emphasize when the text is not shouted throughout
+    let mixed_case = tokens.iter().any(|t| !is_shouted(t));
+
+    let mut sentiments = vec![0.0; words.len()];
+    let mut emotions = BTreeMap::new();
+    for (i, word) in words.iter().enumerate() {
+        let Some(&base) = VALENCES.get(word.as_str()) else {
+            continue;
+        };
+        let mut valence = base;
+        if mixed_case && is_shouted(tokens[i]) {
+            valence += CAPS_BOOST.copysign(base);
+        }
+        let mut negated = false;
+        for (distance, j) in (i.saturating_sub(3)..i).rev().enumerate() {
+            let prev = words[j].as_str();
+            let damp = [1.0, 0.95, 0.9][distance];
+            if let Some((_, boost)) = BOOSTERS.iter().find(|(b, _)| *b == prev) {
+                valence += (boost * damp).copysign(base);
+            }
+            let contracted = tokens[j].contains("n't") || tokens[j].contains("n’t");
+            if NEGATIONS.contains(&prev) || contracted {
+                negated = true;
+            }
+        }
+        if negated {
+            valence *= NEGATION;
+        }
+        sentiments[i] = valence;
+        if !negated {
+            for emotion in EMOTION_WORDS.get(word.as_str()).into_iter().flatten() {
+                *emotions.entry((*emotion).to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    if let Some(but) = words.iter().position(|w| w == "but") {
+        for (i, s) in sentiments.iter_mut().enumerate() {
+            *s *= if i < but { 0.5 } else { 1.5 };
+        }
+    }
+
+    let sum: f64 = sentiments.iter().sum();
+    let exclamations = text.matches('!').count().min(4);
+    let questions = text.matches('?').count();
+    let question_emphasis = match questions {
+        0 | 1 => 0.0,
+        2 | 3 => QUESTION * count_f64(questions),
+        _ => 0.96,
+    };
+    let emphasis = EXCLAMATION.mul_add(count_f64(exclamations), question_emphasis);
+    let total = if sum == 0.0 {
+        0.0
+    } else {
+        sum + emphasis.copysign(sum)
+    };
+    let compound = total / (total * total + ALPHA).sqrt();
+
+    let mut pos: f64 = sentiments
+        .iter()
+        .filter(|&&s| s > 0.0)
+        .map(|s| s + 1.0)
+        .sum();
+    let mut neg: f64 = sentiments
+        .iter()
+        .filter(|&&s| s < 0.0)
+        .map(|s| s - 1.0)
+        .sum();
+    let neu = count_f64(sentiments.iter().filter(|&&s| s == 0.0).count());
+    if pos > neg.abs() {
+        pos += emphasis;
+    } else if pos < neg.abs() {
+        neg -= emphasis;
+    }
+    let all = pos + neg.abs() + neu;
+    let share = |x: f64| {
+        if all == 0.0 {
+            0.0
+        } else {
+            (x / all * 1000.0).round() / 1000.0
+        }
+    };
+    Sentiment {
+        compound: (compound * 10_000.0).round() / 10_000.0,
+        pos: share(pos),
+        neg: share(neg.abs()),
+        neu: share(neu),
+        emotions,
+    }
+}
+
+/// Score the sentiment and emotions of a text
+///
+/// # Arguments
+/// * `text` - Plain text (English); quoted replies are best stripped first
+///
+/// # Returns
+/// * `Sentiment` with `compound` in [-1, 1], `pos`/`neg`/`neu` shares and
+///   per-emotion word counts
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import sentiment_fast
+/// print(sentiment_fast("Thanks, this is GREAT news!").compound)  # 0.8412
+/// ```
+#[pyfunction]
+pub fn sentiment_fast(text: &str) -> Sentiment {
+    score(text)
+}
+
+/// Batch version of [`sentiment_fast`], processed in parallel
+///
+/// # Arguments
+/// * `texts` - Plain texts
+///
+/// # Returns
+/// * One `Sentiment` per text
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import sentiment_batch
+/// scores = sentiment_batch(bodies)
+/// trend = [s.compound for s in scores]
+/// ```
+#[pyfunction]
+pub fn sentiment_batch(py: Python<'_>, texts: Vec<String>) -> Vec<Sentiment> {
+    py.allow_threads(|| par_map(texts, |text| score(&text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let good = score("The launch was good.");
+        let better = score("The launch was very good.");
+        let shouted = score("The launch was very GOOD!!");
+        assert!(good.compound > 0.0);
+        assert!(better.compound > good.compound);
+        assert!(shouted.compound > better.compound);
+        assert!((good.pos + good.neg + good.neu - 1.0).abs() < 0.01);
+
+        assert!(score("The launch was not good.").compound < 0.0);
+        assert!(score("The launch wasn't good.").compound < 0.0);
+        assert!(score("The food was great, but the service was horrible.").compound < 0.0);
+        assert!(score("Meeting moved to 3pm.").compound.abs() < f64::EPSILON);
+        assert!(score("").neu.abs() < f64::EPSILON);
+        assert!((score("Thanks, this is GREAT news!").compound - 0.8412).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_emotions() {
+        let s = score(
+            "I am so worried and angry. Not happy with the delay, but thanks for the support.",
+        );
+        assert_eq!(s.emotions.get("fear"), Some(&1));
+        assert_eq!(s.emotions.get("anger"), Some(&1));
+        assert_eq!(s.emotions.get("trust"), Some(&2));
+        assert_eq!(s.emotions.get("joy"), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! Surviving mapped files that shrink while they are read.
+//!
+//! Reading a page of a memory map that lies past the end of a truncated file
+//! raises `SIGBUS`, which kills the interpreter. On Linux, mappings made by
+//! [`crate::mbox::map_file`] register their address range here; a process
+//! wide handler answers a fault inside a registered range by mapping
+//! anonymous zero pages over the rest of that range and recording the
+//! faulting offset, so the read completes and the operation can report a
+//! `FileChangedError` afterwards instead. Faults outside registered ranges
+//! go to the previously installed handler (or the default action).
+//!
+//! Elsewhere [`Guard`] is inert and detection relies on the size, identity
+//! and modification time checks in [`crate::mbox::MappedFile::verify`].
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::OnceLock;
+
+    /// Mappings guarded at the same time; further ones go unguarded
+    const SLOTS: usize = 64;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const FREE: AtomicBool = AtomicBool::new(false);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NO_FAULT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    static USED: [AtomicBool; SLOTS] = [FREE; SLOTS];
+    static STARTS: [AtomicUsize; SLOTS] = [ZERO; SLOTS];
+    static ENDS: [AtomicUsize; SLOTS] = [ZERO; SLOTS];
+    /// Lowest faulting offset per slot, `usize::MAX` if none
+    static FAULTS: [AtomicUsize; SLOTS] = [NO_FAULT; SLOTS];
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(4096);
+    type Action = extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void);
+
+    /// Disposition before ours, for faults that are not ours
+    static PREVIOUS: OnceLock<libc::sigaction> = OnceLock::new();
+
+    /// Registration of one mapping; unregisters on drop
+    pub struct Guard(Option<usize>);
+
+    impl Guard {
+        pub fn new(start: *const u8, len: usize) -> Self {
+            if len == 0 || !install() {
+                return Self(None);
+            }
+            let slot = (0..SLOTS).find(|&i| {
+                USED[i]
+                    .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            });
+            if let Some(i) = slot {
+                FAULTS[i].store(usize::MAX, Ordering::Relaxed);
+                STARTS[i].store(start as usize, Ordering::Relaxed);
+                ENDS[i].store(start as usize + len, Ordering::Release);
+            }
+            Self(slot)
+        }
+
+        /// Offset of the first byte that could not be read, if any
+        pub fn fault_offset(&self) -> Option<usize> {
+            let i = self.0?;
+            let offset = FAULTS[i].load(Ordering::Acquire);
+            (offset != usize::MAX).then_some(offset)
+        }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            if let Some(i) = self.0 {
+                ENDS[i].store(0, Ordering::Release);
+                STARTS[i].store(0, Ordering::Relaxed);
+                USED[i].store(false, Ordering::Release);
+            }
+        }
+    }
+
+    /// Install the handler once; false if that failed
+    #[allow(unsafe_code)]
+    fn install() -> bool {
+        PREVIOUS
+            .get_or_init(|| {
+                // Safety: plain libc calls on zero-initialized, fully owned structs
+                unsafe {
+                    let page = libc::sysconf(libc::_SC_PAGESIZE);
+                    if let Ok(page) = usize::try_from(page) {
+                        if page > 0 {
+                            PAGE_SIZE.store(page, Ordering::Relaxed);
+                        }
+                    }
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = handler as Action as usize;
+                    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    let mut previous: libc::sigaction = std::mem::zeroed();
+                    previous.sa_sigaction = usize::MAX;
+                    // On failure `previous` keeps the marker and nothing is guarded
+                    libc::sigaction(libc::SIGBUS, &action, &mut previous);
+                    previous
+                }
+            })
+            .sa_sigaction
+            != usize::MAX
+    }
+
+    /// Async-signal-safe: only atomics, `mmap` and `sigaction`
+    #[allow(unsafe_code)]
+    extern "C" fn handler(
+        signal: libc::c_int,
+        info: *mut libc::siginfo_t,
+        context: *mut libc::c_void,
+    ) {
+        // Safety: the kernel passes a valid siginfo for SA_SIGINFO handlers
+        let address = unsafe { (*info).si_addr() } as usize;
+        let page = PAGE_SIZE.load(Ordering::Relaxed);
+        for i in 0..SLOTS {
+            let end = ENDS[i].load(Ordering::Acquire);
+            let start = STARTS[i].load(Ordering::Relaxed);
+            if end == 0 || address < start || address >= end {
+                continue;
+            }
+            let from = address & !(page - 1);
+            let to = (end + page - 1) & !(page - 1);
+            // Safety: replaces pages inside a live mapping this crate owns
+            let mapped = unsafe {
+                libc::mmap(
+                    from as *mut libc::c_void,
+                    to - from,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                    -1,
+                    0,
+                )
+            };
+            if mapped != libc::MAP_FAILED {
+                FAULTS[i].fetch_min(from.max(start) - start, Ordering::AcqRel);
+                return;
+            }
+        }
+        chain(signal, info, context);
+    }
+
+    /// Hand a fault that is not ours to whoever handled SIGBUS before
+    #[allow(unsafe_code)]
+    fn chain(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+        let Some(previous) = PREVIOUS.get() else {
+            return;
+        };
+        let target = previous.sa_sigaction;
+        if target != libc::SIG_DFL && target != libc::SIG_IGN && target != usize::MAX {
+            // Safety: `target` is the handler the process installed before us,
+            // called with the signature its flags declare
+            unsafe {
+                if previous.sa_flags & libc::SA_SIGINFO != 0 {
+                    let f: Action = std::mem::transmute(target);
+                    f(signal, info, context);
+                } else {
+                    let f: extern "C" fn(libc::c_int) = std::mem::transmute(target);
+                    f(signal);
+                }
+            }
+            return;
+        }
+        // Restore the default action; the faulting access repeats and the
+        // process terminates as it would have without us
+        // Safety: zero-initialized struct, standard libc call
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = libc::SIG_DFL;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGBUS, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    /// No-op where faults are not intercepted
+    pub struct Guard;
+
+    impl Guard {
+        pub const fn new(_start: *const u8, _len: usize) -> Self {
+            Self
+        }
+
+        pub const fn fault_offset(&self) -> Option<usize> {
+            None
+        }
+    }
+}
+
+pub use imp::Guard;
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncated_map_reads_zeros() {
+        let dir = std::env::temp_dir().join(format!("mpr_sigbus_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shrinks.mbox");
+        std::fs::write(&path, vec![b'x'; 64 * 1024]).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        #[allow(unsafe_code)]
+        let map = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+        let guard = Guard::new(map.as_ptr(), map.len());
+        assert_eq!(guard.fault_offset(), None);
+
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(100)
+            .unwrap();
+        let tail = std::hint::black_box(&map[60_000..60_010]);
+        assert_eq!(tail, &[0; 10]);
+        assert_eq!(&map[..3], b"xxx");
+        let offset = guard.fault_offset().unwrap();
+        assert!((4096..=60_000).contains(&offset), "{offset}");
+        drop(guard);
+        drop(map);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
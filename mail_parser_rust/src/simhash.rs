@@ -0,0 +1,248 @@
+//! Simhash fingerprints and near-duplicate clustering.
+//!
+//! A body is reduced to its word 3-gram shingles (quoted replies and the
+//! signature removed first), and each shingle's hash votes on the 64 bits of
+//! the fingerprint, so bodies that differ only in a footer or a greeting end
+//! up a few bits apart. Clustering splits the fingerprints into
+//! `threshold + 1` bands: two fingerprints within `threshold` bits agree on
+//! at least one whole band, so only texts sharing a band value are compared.
+
+use crate::checkpoint::fnv1a;
+use crate::quotes::strip_quotes;
+use crate::terms::words;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Words per shingle
+const SHINGLE: usize = 3;
+
+/// Largest accepted `threshold` (bits); beyond it bands are too narrow to
+/// prune anything
+const MAX_THRESHOLD: u32 = 31;
+
+/// Spread FNV-1a's bits (splitmix64 finalizer) so every fingerprint bit gets
+/// an independent vote
+const fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Simhash of `text`; `None` when it has no words
+fn fingerprint(text: &str, strip_quoted: bool) -> Option<u64> {
+    let words = if strip_quoted {
+        words(&strip_quotes(text))
+    } else {
+        words(text)
+    };
+    if words.is_empty() {
+        return None;
+    }
+    let mut votes = [0i64; 64];
+    for shingle in words.windows(SHINGLE.min(words.len())) {
+        let hash = mix(fnv1a(shingle.join(" ").as_bytes()));
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            *vote += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    Some(
+        votes
+            .iter()
+            .enumerate()
+            .filter(|(_, &vote)| vote > 0)
+            .fold(0, |hash, (bit, _)| hash | 1 << bit),
+    )
+}
+
+/// Union-find over text positions
+struct Sets(Vec<usize>);
+
+impl Sets {
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.0[i] != i {
+            self.0[i] = self.0[self.0[i]];
+            i = self.0[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        // The smaller position is the root, so clusters sort by first member
+        self.0[a.max(b)] = a.min(b);
+    }
+}
+
+/// Groups of positions whose fingerprints are within `threshold` bits
+fn cluster(hashes: &[Option<u64>], threshold: u32) -> Vec<Vec<usize>> {
+    let mut sets = Sets((0..hashes.len()).collect());
+    // Identical fingerprints first: one comparison per distinct value below
+    let mut distinct: HashMap<u64, usize> = HashMap::new();
+    for (i, hash) in hashes.iter().enumerate() {
+        if let Some(hash) = *hash {
+            let first = *distinct.entry(hash).or_insert(i);
+            sets.union(first, i);
+        }
+    }
+    if threshold > 0 {
+        let bands = threshold + 1;
+        for band in 0..bands {
+            let (start, end) = (64 * band / bands, 64 * (band + 1) / bands);
+            let mask = (u64::MAX >> (64 - (end - start))) << start;
+            let mut buckets: HashMap<u64, Vec<(u64, usize)>> = HashMap::new();
+            for (&hash, &i) in &distinct {
+                buckets.entry(hash & mask).or_default().push((hash, i));
+            }
+            for bucket in buckets.values() {
+                for (k, &(a, i)) in bucket.iter().enumerate() {
+                    for &(b, j) in &bucket[k + 1..] {
+                        if (a ^ b).count_ones() <= threshold {
+                            sets.union(i, j);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in (0..hashes.len()).filter(|&i| hashes[i].is_some()) {
+        groups.entry(sets.find(i)).or_default().push(i);
+    }
+    let mut clusters: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    clusters.sort_unstable();
+    clusters
+}
+
+/// 64-bit simhash fingerprint of a text, for near-duplicate detection
+///
+/// Texts that differ only in a few words (a footer, a greeting, a tracking
+/// number) get fingerprints a few bits apart; compare them with
+/// `(a ^ b).bit_count()`.
+///
+/// # Arguments
+/// * `text` - Plain text, typically a message body
+/// * `strip_quoted` - Ignore quoted replies and the signature (default true)
+///
+/// # Returns
+/// * Fingerprint as an unsigned int; 0 for a text without words
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import simhash_fast
+/// a = simhash_fast("Your weekly digest: 5 new posts. Unsubscribe here.")
+/// b = simhash_fast("Your weekly digest: 5 new posts. Manage settings here.")
+/// print((a ^ b).bit_count())
+/// ```
+#[pyfunction]
+#[pyo3(signature = (text, strip_quoted=true))]
+pub fn simhash_fast(text: &str, strip_quoted: bool) -> u64 {
+    fingerprint(text, strip_quoted).unwrap_or(0)
+}
+
+/// Group texts whose simhash fingerprints are within `threshold` bits
+///
+/// Fingerprints are computed in parallel and compared through
+/// locality-sensitive banding, so the cost grows with the number of
+/// candidate pairs rather than the square of the corpus. Grouping is
+/// transitive: A and C share a cluster when both are close to B.
+///
+/// # Arguments
+/// * `texts` - Plain texts, typically message bodies
+/// * `threshold` - Largest Hamming distance counted as near-duplicate
+///   (default 3; 0 groups identical fingerprints only; at most 31)
+/// * `strip_quoted` - Ignore quoted replies and signatures (default true)
+///
+/// # Returns
+/// * Clusters of two or more positions into `texts`, each ascending, ordered
+///   by first position; texts without words are never clustered
+///
+/// # Raises
+/// * `ValueError` - `threshold` above 31
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import cluster_near_duplicates
+/// bodies = [m.body for m in messages]
+/// for cluster in cluster_near_duplicates(bodies, threshold=4):
+///     print(len(cluster), "copies of", messages[cluster[0]].subject)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (texts, threshold=3, strip_quoted=true))]
+pub fn cluster_near_duplicates(
+    py: Python<'_>,
+    texts: Vec<String>,
+    threshold: u32,
+    strip_quoted: bool,
+) -> PyResult<Vec<Vec<usize>>> {
+    if threshold > MAX_THRESHOLD {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "threshold must be at most {MAX_THRESHOLD} bits, got {threshold}"
+        )));
+    }
+    Ok(py.allow_threads(|| {
+        let hashes: Vec<Option<u64>> = texts
+            .into_par_iter()
+            .map(|text| fingerprint(&text, strip_quoted))
+            .collect();
+        cluster(&hashes, threshold)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "Hello Ann, here is your weekly digest. Five new posts were \
+                          published in the gardening forum this week, including tips on \
+                          tomatoes, pruning roses and planting bulbs before the first frost. \
+                          Read them online and join the discussion with other members.";
+
+    #[test]
+    fn test_fingerprint() {
+        let a = simhash_fast(DIGEST, true);
+        let footer = format!("{DIGEST}\n\n-- \nGarden Club, 1 Main St\nUnsubscribe");
+        assert_eq!(simhash_fast(&footer, true), a);
+        assert_ne!(simhash_fast(&footer, false), a);
+        let quoted = format!("{DIGEST}\n> earlier message\n> quoted here");
+        assert_eq!(simhash_fast(&quoted, true), a);
+
+        let greeting = DIGEST.replace("Ann", "Bob");
+        assert!((simhash_fast(&greeting, true) ^ a).count_ones() <= 8);
+        let other = simhash_fast("Quarterly invoice attached, payment due in thirty days", true);
+        assert!((other ^ a).count_ones() > 12);
+        assert_eq!(simhash_fast("", true), 0);
+        assert_eq!(fingerprint("12 34", true), None);
+    }
+
+    #[test]
+    fn test_cluster() {
+        let hashes = [
+            Some(0b1111),
+            Some(0),
+            Some(0b1111_0000),
+            None,
+            Some(0b1),
+            Some(u64::MAX),
+            Some(0b1111),
+            None,
+        ];
+        assert_eq!(cluster(&hashes, 0), [vec![0, 6]]);
+        // 0 and 4 are one bit apart; 4 and 0b1111 three
+        assert_eq!(cluster(&hashes, 1), [vec![0, 6], vec![1, 4]]);
+        assert_eq!(cluster(&hashes, 3), [vec![0, 1, 4, 6]]);
+        assert_eq!(cluster(&hashes, 4), [vec![0, 1, 2, 4, 6]]);
+
+        crate::with_py(|py| {
+            let texts = vec![
+                DIGEST.to_string(),
+                "Quarterly invoice attached, payment due in thirty days".to_string(),
+                DIGEST.replace("Ann", "Bob"),
+                String::new(),
+                String::new(),
+            ];
+            assert_eq!(cluster_near_duplicates(py, texts, 8, true).unwrap(), [vec![0, 2]]);
+            assert!(cluster_near_duplicates(py, vec![], 32, true).is_err());
+        });
+    }
+}
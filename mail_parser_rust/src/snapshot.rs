@@ -0,0 +1,684 @@
+//! Read-only packed archive snapshots.
+//!
+//! A snapshot holds everything a downstream service needs from an mbox in
+//! one file: every message compressed on its own, a fixed-width index, and
+//! the metadata columns of `mbox_to_arrow`. Opening one maps it and decodes
+//! only the metadata; a message is found through the index in the mapping
+//! and decompressed on demand, so random access costs one zstd frame.
+//!
+//! Layout (integers little-endian):
+//!
+//! ```text
+//! "MPRSNP01"
+//! message frames      one zstd frame per message, in mbox order
+//! index               per message: u64 frame offset, u64 message length
+//! metadata            one zstd frame: u32 column count, then per column
+//!                     u8 column id, u8 kind and the values
+//! trailer             u64 message count, u64 index offset,
+//!                     u64 metadata offset, u64 source length,
+//!                     u64 source fingerprint, "MPRSNP01"
+//! ```
+//!
+//! A frame ends where the next one starts (the last where the index
+//! starts). Opening a snapshot checks every index entry against this layout
+//! and the source length, so a corrupt or hostile file is rejected before
+//! any message is decompressed into a buffer of the size it claims. Values are `u8` present flag plus `i64` for integer columns,
+//! a `u32` length (`u32::MAX` for null) plus UTF-8 for text, and one byte
+//! for booleans. The source length and fingerprint are those of the mbox
+//! at creation, as in a message index sidecar.
+
+use crate::arrow::{extract, ArrowBatch, Column, Values};
+use crate::mbox::{map_file, FromLineMode, MappedFile};
+use crate::offset_index::{fingerprint, freshness, Freshness};
+use crate::output::{Compression, Output, OutputReport};
+use crate::progress::Progress;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// First and last bytes of a snapshot file
+const MAGIC: &[u8; 8] = b"MPRSNP01";
+
+/// Bytes of the trailer, magic included
+const TRAILER_LEN: usize = 48;
+
+/// Bytes of one index entry
+const ENTRY_LEN: usize = 16;
+
+/// zstd level of message frames and metadata
+const LEVEL: i32 = 3;
+
+/// Messages compressed in parallel before being written in order
+const BATCH: usize = 1024;
+
+const KIND_INT: u8 = 0;
+const KIND_TEXT: u8 = 1;
+const KIND_BOOL: u8 = 2;
+
+/// Text length marking a null
+const NULL_TEXT: u32 = u32::MAX;
+
+fn to_u64(value: usize) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+fn to_usize(value: u64) -> io::Result<usize> {
+    usize::try_from(value).map_err(|_| invalid("offset out of range"))
+}
+
+fn invalid(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("not a snapshot: {detail}"))
+}
+
+/// The next `n` bytes of `rest`
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if rest.len() < n {
+        return Err(invalid("truncated"));
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(head)
+}
+
+fn take_u8(rest: &mut &[u8]) -> io::Result<u8> {
+    Ok(take(rest, 1)?[0])
+}
+
+fn take_u32(rest: &mut &[u8]) -> io::Result<u32> {
+    let mut word = [0; 4];
+    word.copy_from_slice(take(rest, 4)?);
+    Ok(u32::from_le_bytes(word))
+}
+
+fn take_u64(rest: &mut &[u8]) -> io::Result<u64> {
+    let mut word = [0; 8];
+    word.copy_from_slice(take(rest, 8)?);
+    Ok(u64::from_le_bytes(word))
+}
+
+/// Append `values` to the encoded column `out`
+fn encode_values(out: &mut Vec<u8>, values: &Values) {
+    match values {
+        Values::Int(values) => {
+            for value in values {
+                out.push(u8::from(value.is_some()));
+                out.extend(value.unwrap_or(0).to_le_bytes());
+            }
+        },
+        Values::Text(values) => {
+            for value in values {
+                match value {
+                    Some(text) => {
+                        let len = u32::try_from(text.len()).unwrap_or(NULL_TEXT - 1);
+                        out.extend(len.to_le_bytes());
+                        out.extend_from_slice(&text.as_bytes()[..len as usize]);
+                    },
+                    None => out.extend(NULL_TEXT.to_le_bytes()),
+                }
+            }
+        },
+        Values::Bool(values) => out.extend(values.iter().map(|&v| u8::from(v))),
+    }
+}
+
+/// `rows` values of `kind` from the front of `rest`
+fn decode_values(rest: &mut &[u8], kind: u8, rows: usize) -> io::Result<Values> {
+    Ok(match kind {
+        KIND_INT => Values::Int(
+            (0..rows)
+                .map(|_| {
+                    let present = take_u8(rest)? != 0;
+                    let value = i64::from_le_bytes(take(rest, 8)?.try_into().unwrap_or_default());
+                    Ok(present.then_some(value))
+                })
+                .collect::<io::Result<_>>()?,
+        ),
+        KIND_TEXT => Values::Text(
+            (0..rows)
+                .map(|_| match take_u32(rest)? {
+                    NULL_TEXT => Ok(None),
+                    len => {
+                        let bytes = take(rest, len as usize)?;
+                        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+                    },
+                })
+                .collect::<io::Result<_>>()?,
+        ),
+        KIND_BOOL => Values::Bool(take(rest, rows)?.iter().map(|&b| b != 0).collect()),
+        _ => return Err(invalid("unknown column kind")),
+    })
+}
+
+const fn kind(values: &Values) -> u8 {
+    match values {
+        Values::Int(_) => KIND_INT,
+        Values::Text(_) => KIND_TEXT,
+        Values::Bool(_) => KIND_BOOL,
+    }
+}
+
+/// The metadata section of a snapshot of `rows` messages
+fn decode_metadata(frame: &[u8], rows: usize) -> io::Result<Vec<(Column, Values)>> {
+    let bytes = zstd::decode_all(frame).map_err(|_| invalid("corrupt metadata"))?;
+    let mut rest = &bytes[..];
+    let count = take_u32(&mut rest)?;
+    (0..count)
+        .map(|_| {
+            let id = take_u8(&mut rest)?;
+            let column = *Column::ALL
+                .get(usize::from(id))
+                .ok_or_else(|| invalid("unknown column"))?;
+            let kind = take_u8(&mut rest)?;
+            Ok((column, decode_values(&mut rest, kind, rows)?))
+        })
+        .collect()
+}
+
+/// Write a snapshot of the messages at `spans` of `data` to `out`,
+/// noting each message with `output`; stops early, leaving the snapshot
+/// unfinished, once `progress` is cancelled
+fn write_snapshot(
+    out: &mut impl Write,
+    output: &Output,
+    data: &[u8],
+    spans: &[Range<usize>],
+    columns: &[Column],
+    progress: &Progress,
+) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    let mut position = to_u64(MAGIC.len());
+    let mut index = Vec::with_capacity(spans.len() * ENTRY_LEN);
+    // Kind of each column, from the typed (empty) values of no messages
+    let mut encoded: Vec<(u8, Vec<u8>)> = extract(data, &[], 0, columns, progress)
+        .iter()
+        .map(|values| (kind(values), Vec::new()))
+        .collect();
+    for (n, batch) in spans.chunks(BATCH).enumerate() {
+        let frames = batch
+            .par_iter()
+            .map(|span| zstd::bulk::compress(&data[span.clone()], LEVEL))
+            .collect::<io::Result<Vec<_>>>()?;
+        for (span, frame) in batch.iter().zip(frames) {
+            out.write_all(&frame)?;
+            index.extend(position.to_le_bytes());
+            index.extend(to_u64(span.len()).to_le_bytes());
+            position += to_u64(frame.len());
+            output.note_message();
+        }
+        let values = extract(data, batch, n * BATCH, columns, progress);
+        if progress.cancelled() {
+            return Ok(());
+        }
+        for ((_, column), values) in encoded.iter_mut().zip(&values) {
+            encode_values(column, values);
+        }
+    }
+    let mut metadata = to_u32(columns.len()).to_le_bytes().to_vec();
+    for (&column, (kind, values)) in columns.iter().zip(encoded) {
+        let id = Column::ALL
+            .iter()
+            .position(|&c| c == column)
+            .unwrap_or_default();
+        metadata.push(u8::try_from(id).unwrap_or_default());
+        metadata.push(kind);
+        metadata.extend(values);
+    }
+    let metadata = zstd::bulk::compress(&metadata, LEVEL)?;
+    let index_offset = position;
+    let metadata_offset = index_offset + to_u64(index.len());
+    out.write_all(&index)?;
+    out.write_all(&metadata)?;
+    for word in [
+        to_u64(spans.len()),
+        index_offset,
+        metadata_offset,
+        to_u64(data.len()),
+        fingerprint(data),
+    ] {
+        out.write_all(&word.to_le_bytes())?;
+    }
+    out.write_all(MAGIC)
+}
+
+fn to_u32(value: usize) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+/// Pack an mbox into a read-only snapshot file
+///
+/// The snapshot holds every message (zstd-compressed one by one), an index
+/// for random access and the metadata columns of `mbox_to_arrow`, so one
+/// file replaces the mbox, its index sidecar and any metadata database.
+/// Messages are compressed in parallel and written in mbox order.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `out_path` - Destination snapshot file
+/// * `columns` - Metadata columns to store (default: every column but
+///   `body`); see `mbox_to_arrow`
+/// * `dry_run` - Encode everything but write nothing
+/// * `fsync` - Durability policy: "none", "file" (default) or "full"
+/// * `from_line_mode` - Message separator rule (see `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * `OutputReport` - Messages packed, bytes and the output file
+///
+/// # Raises
+/// * `ValueError` - An unknown or repeated column name
+/// * `IOError` - The mbox cannot be read or the snapshot cannot be written
+/// * `FileChangedError` - The mbox changed during the scan (nothing is written)
+/// * `ScanCancelled` - `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import create_snapshot, open_snapshot
+/// create_snapshot("inbox.mbox", "inbox.snap")
+/// snap = open_snapshot("inbox.snap")
+/// raw = snap[1234]
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, out_path, columns=None, dry_run=false, fsync="file", from_line_mode="standard", progress=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn create_snapshot(
+    py: Python<'_>,
+    mbox_path: &str,
+    out_path: &str,
+    columns: Option<Vec<String>>,
+    dry_run: bool,
+    fsync: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<OutputReport> {
+    let columns = Column::select(columns)?;
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        // Frames are compressed individually; never wrap the whole file
+        let output =
+            Output::from_args(dry_run, fsync, None)?.with_compression(Some(Compression::None));
+        let mmap = map_file(mbox_path)?;
+        let spans = mmap.spans(&mode);
+        let io_error = |e: io::Error| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to write {out_path}: {e}"))
+        };
+        let progress = Progress::new(progress, mmap.len());
+        let mut file = output.create(Path::new(out_path)).map_err(io_error)?;
+        write_snapshot(&mut file, &output, &mmap, &spans, &columns, &progress).map_err(io_error)?;
+        progress.check("create_snapshot")?;
+        mmap.verify(&mode)?;
+        file.finish().map_err(io_error)?;
+        Ok(output.report())
+    })
+}
+
+/// A packed archive opened by `open_snapshot`
+///
+/// Indexing returns a message's raw bytes (negative indices count from the
+/// end); metadata columns are decoded once, on open.
+///
+/// # Example
+/// ```python
+/// snap = open_snapshot("inbox.snap")
+/// subjects = snap.column("subject")
+/// hits = [i for i, s in enumerate(subjects) if s and "invoice" in s.lower()]
+/// raws = snap.messages(hits)
+/// df = pyarrow.table(snap.to_arrow()).to_pandas()
+/// ```
+#[pyclass]
+pub struct Snapshot {
+    path: String,
+    map: MappedFile,
+    count: usize,
+    index_offset: usize,
+    columns: Vec<(Column, Values)>,
+    source_len: u64,
+    fingerprint: u64,
+}
+
+impl Snapshot {
+    pub fn open(path: &str) -> PyResult<Self> {
+        let map = map_file(path)?;
+        Self::decode(path, map).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "Failed to open snapshot {path}: {e}"
+            ))
+        })
+    }
+
+    fn decode(path: &str, map: MappedFile) -> io::Result<Self> {
+        if map.len() < MAGIC.len() + TRAILER_LEN || !map.starts_with(MAGIC) {
+            return Err(invalid("bad signature"));
+        }
+        let trailer_at = map.len() - TRAILER_LEN;
+        let mut rest = &map[trailer_at..];
+        let count = to_usize(take_u64(&mut rest)?)?;
+        let index_offset = to_usize(take_u64(&mut rest)?)?;
+        let metadata_offset = to_usize(take_u64(&mut rest)?)?;
+        let source_len = take_u64(&mut rest)?;
+        let fingerprint = take_u64(&mut rest)?;
+        if rest != MAGIC {
+            return Err(invalid("bad trailer"));
+        }
+        let index_len = count
+            .checked_mul(ENTRY_LEN)
+            .ok_or_else(|| invalid("bad index"))?;
+        if index_offset < MAGIC.len()
+            || index_offset.checked_add(index_len) != Some(metadata_offset)
+            || metadata_offset > trailer_at
+        {
+            return Err(invalid("bad index"));
+        }
+        let columns = decode_metadata(&map[metadata_offset..trailer_at], count)?;
+        let snapshot = Self {
+            path: path.to_string(),
+            map,
+            count,
+            index_offset,
+            columns,
+            source_len,
+            fingerprint,
+        };
+        snapshot.check_index()?;
+        Ok(snapshot)
+    }
+
+    /// Reject an index whose frames are out of order or outside the frame
+    /// section, or whose messages add up to more than the source held
+    fn check_index(&self) -> io::Result<()> {
+        let mut previous = MAGIC.len();
+        let mut total: u64 = 0;
+        for i in 0..self.count {
+            let (start, len) = self.entry(i);
+            if start < previous || start > self.index_offset {
+                return Err(invalid("bad index entry"));
+            }
+            total = total.saturating_add(to_u64(len));
+            if total > self.source_len {
+                return Err(invalid("index entry larger than the source"));
+            }
+            previous = start;
+        }
+        Ok(())
+    }
+
+    /// Position of message `n`, counting from the end when negative
+    fn position(&self, n: isize) -> PyResult<usize> {
+        let position = if n < 0 {
+            self.count.checked_sub(n.unsigned_abs())
+        } else {
+            usize::try_from(n).ok()
+        };
+        position.filter(|&i| i < self.count).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyIndexError, _>(format!(
+                "message {n} out of range for {} messages",
+                self.count
+            ))
+        })
+    }
+
+    /// Frame offset and message length of index entry `i`
+    fn entry(&self, i: usize) -> (usize, usize) {
+        let mut rest = &self.map[self.index_offset + i * ENTRY_LEN..];
+        let mut word =
+            || take_u64(&mut rest).map_or(usize::MAX, |w| to_usize(w).unwrap_or(usize::MAX));
+        (word(), word())
+    }
+
+    /// The raw bytes of message `i` (`i` in range); its length was bounded
+    /// by [`Snapshot::check_index`] on open
+    pub fn message(&self, i: usize) -> io::Result<Vec<u8>> {
+        let (start, len) = self.entry(i);
+        let end = if i + 1 < self.count {
+            self.entry(i + 1).0
+        } else {
+            self.index_offset
+        };
+        let frame = self
+            .map
+            .get(start..end)
+            .ok_or_else(|| invalid("bad index entry"))?;
+        let message = zstd::bulk::decompress(frame, len).map_err(|_| invalid("corrupt message"))?;
+        if message.len() != len {
+            return Err(invalid("corrupt message"));
+        }
+        Ok(message)
+    }
+
+    fn read_error(&self, e: &io::Error) -> PyErr {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to read snapshot {}: {e}",
+            self.path
+        ))
+    }
+
+    fn values(&self, name: &str) -> PyResult<&Values> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column.name() == name)
+            .map(|(_, values)| values)
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Snapshot has no column {name:?} (it has {})",
+                    self.column_names().join(", ")
+                ))
+            })
+    }
+}
+
+#[pymethods]
+impl Snapshot {
+    /// Number of messages
+    const fn __len__(&self) -> usize {
+        self.count
+    }
+
+    /// Raw bytes of message `n`
+    fn __getitem__<'py>(&self, py: Python<'py>, n: isize) -> PyResult<Bound<'py, PyBytes>> {
+        let i = self.position(n)?;
+        let message = self.message(i).map_err(|e| self.read_error(&e))?;
+        Ok(PyBytes::new(py, &message))
+    }
+
+    /// Raw bytes of the messages at `indices`, decompressed in parallel
+    fn messages<'py>(
+        &self,
+        py: Python<'py>,
+        indices: Vec<isize>,
+    ) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+        let positions = indices
+            .into_iter()
+            .map(|n| self.position(n))
+            .collect::<PyResult<Vec<_>>>()?;
+        let messages = py
+            .allow_threads(|| {
+                positions
+                    .par_iter()
+                    .map(|&i| self.message(i))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .map_err(|e| self.read_error(&e))?;
+        Ok(messages.iter().map(|m| PyBytes::new(py, m)).collect())
+    }
+
+    /// Names of the stored metadata columns, in order
+    #[getter]
+    fn column_names(&self) -> Vec<&'static str> {
+        self.columns
+            .iter()
+            .map(|(column, _)| column.name())
+            .collect()
+    }
+
+    /// One metadata column as a list (None where null)
+    fn column<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyAny>> {
+        Ok(match self.values(name)? {
+            Values::Int(values) => values.into_pyobject(py)?.into_any(),
+            Values::Text(values) => values.into_pyobject(py)?.into_any(),
+            Values::Bool(values) => values.into_pyobject(py)?.into_any(),
+        })
+    }
+
+    /// Metadata columns as an `ArrowBatch` (default: all stored columns)
+    #[pyo3(signature = (columns=None))]
+    fn to_arrow(&self, columns: Option<Vec<String>>) -> PyResult<ArrowBatch> {
+        let names =
+            columns.unwrap_or_else(|| self.column_names().into_iter().map(String::from).collect());
+        let selected = Column::select(Some(names))?
+            .into_iter()
+            .map(|column| Ok((column, self.values(column.name())?.clone())))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(ArrowBatch::new(selected, self.count))
+    }
+
+    /// Whether `mbox_path` still holds exactly the mbox the snapshot was
+    /// made from (False if it grew, shrank or was rewritten)
+    fn is_current(&self, mbox_path: &str) -> PyResult<bool> {
+        let mmap = map_file(mbox_path)?;
+        Ok(freshness(&mmap, self.source_len, self.fingerprint) == Freshness::Current)
+    }
+
+    #[getter]
+    fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Snapshot(path={:?}, messages={}, columns=[{}])",
+            self.path,
+            self.count,
+            self.column_names().join(", ")
+        )
+    }
+}
+
+/// Open a snapshot written by `create_snapshot`
+///
+/// The file is memory-mapped; only the metadata columns are decoded up
+/// front, and messages are decompressed when accessed.
+///
+/// # Raises
+/// * `IOError` - The file cannot be read or is not a valid snapshot
+#[pyfunction]
+pub fn open_snapshot(py: Python<'_>, path: &str) -> PyResult<Snapshot> {
+    py.allow_threads(|| Snapshot::open(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "From a Mon Jan  1 00:00:00 2024\n\
+                        From: a@x.org\nSubject: One\n\nFirst\n\n\
+                        From b Mon Jan  1 00:00:00 2024\nFrom: b@x.org\n\nSecond\n\n\
+                        From c Mon Jan  1 00:00:00 2024\nSubject: Three\n\nThird\n";
+
+    fn snapshot(dir: &Path, mbox: &str, columns: Option<Vec<String>>) -> Snapshot {
+        let mbox_path = dir.join("in.mbox");
+        std::fs::write(&mbox_path, mbox).unwrap();
+        let out = dir.join("out.snap");
+        let (mbox_path, out) = (mbox_path.to_str().unwrap(), out.to_str().unwrap());
+        crate::with_py(|py| {
+            let report =
+                create_snapshot(py, mbox_path, out, columns, false, "never", "standard", None)
+                    .unwrap();
+            assert_eq!(report.file_count, 1);
+            open_snapshot(py, out).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = std::env::temp_dir().join(format!("mpr_snapshot_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let columns = Some(vec!["subject".into(), "size".into(), "has_attachments".into()]);
+        let snap = snapshot(&dir, MBOX, columns);
+        let spans = crate::mbox::message_spans_with(MBOX.as_bytes(), &FromLineMode::Standard);
+        assert_eq!(snap.__len__(), 3);
+        for (i, span) in spans.iter().enumerate() {
+            assert_eq!(snap.message(i).unwrap(), MBOX.as_bytes()[span.clone()]);
+        }
+        assert_eq!(snap.position(-1).unwrap(), 2);
+        assert!(snap.position(3).is_err());
+        assert_eq!(snap.column_names(), ["subject", "size", "has_attachments"]);
+        let subjects = Values::Text(vec![Some("One".into()), None, Some("Three".into())]);
+        assert_eq!(snap.values("subject").unwrap(), &subjects);
+        assert_eq!(snap.values("has_attachments").unwrap(), &Values::Bool(vec![false; 3]));
+        assert!(snap.values("from").is_err());
+        let mbox_path = dir.join("in.mbox");
+        crate::with_py(|py| {
+            assert!(snap.is_current(mbox_path.to_str().unwrap()).unwrap());
+            let raw = snap.messages(py, vec![2, 0]).unwrap();
+            assert_eq!(raw[0].as_bytes(), &MBOX.as_bytes()[spans[2].clone()]);
+            assert!(snap.to_arrow(Some(vec!["size".into()])).is_ok());
+            assert!(snap.to_arrow(Some(vec!["from".into()])).is_err());
+        });
+        std::fs::write(&mbox_path, format!("{MBOX}\n")).unwrap();
+        assert!(!snap.is_current(mbox_path.to_str().unwrap()).unwrap());
+
+        // Empty mbox, and files that are not snapshots
+        let empty = snapshot(&dir, "", None);
+        assert_eq!(empty.__len__(), 0);
+        assert_eq!(empty.column_names().len(), Column::DEFAULT.len());
+        let bogus = dir.join("bogus.snap");
+        std::fs::write(&bogus, b"MPRSNP01 but nothing else").unwrap();
+        assert!(crate::with_py(|py| open_snapshot(py, bogus.to_str().unwrap())).is_err());
+        let mut cut = std::fs::read(dir.join("out.snap")).unwrap();
+        cut.truncate(cut.len() - 1);
+        std::fs::write(&bogus, cut).unwrap();
+        assert!(crate::with_py(|py| open_snapshot(py, bogus.to_str().unwrap())).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_index() {
+        let dir = std::env::temp_dir().join(format!("mpr_snapshot_bad_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        snapshot(&dir, MBOX, None);
+        let good = std::fs::read(dir.join("out.snap")).unwrap();
+        let trailer = good.len() - TRAILER_LEN;
+        let index_offset = to_usize(take_u64(&mut &good[trailer + 8..]).unwrap()).unwrap();
+        let bogus = dir.join("bogus.snap");
+        // Overwrite word `word` of index entry `i` and try to open the result
+        let open_patched = |i: usize, word: usize, value: u64| {
+            let mut bytes = good.clone();
+            let at = index_offset + i * ENTRY_LEN + word * 8;
+            bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&bogus, bytes).unwrap();
+            Snapshot::open(bogus.to_str().unwrap())
+        };
+
+        // A message length the source cannot have held, even one that fits
+        // in memory, is rejected on open rather than allocated
+        let err = open_patched(1, 1, u64::MAX).err().unwrap().to_string();
+        assert!(err.contains("larger than the source"), "{err}");
+        assert!(open_patched(0, 1, to_u64(MBOX.len()) + 1).is_err());
+
+        // Frames out of order, before the first frame or inside the index
+        assert!(open_patched(2, 0, 0).is_err());
+        assert!(open_patched(0, 0, 3).is_err());
+        assert!(open_patched(2, 0, to_u64(index_offset) + 1).is_err());
+
+        // A length that is in bounds but wrong only fails that message
+        let snap = open_patched(0, 1, 1).unwrap();
+        assert!(snap.message(0).is_err());
+        assert!(snap.message(1).is_ok());
+
+        // Garbage where the index was: nothing is trusted
+        let mut bytes = good.clone();
+        bytes[index_offset..index_offset + 3 * ENTRY_LEN].fill(0xa5);
+        std::fs::write(&bogus, bytes).unwrap();
+        assert!(Snapshot::open(bogus.to_str().unwrap()).is_err());
+
+        // An index cut short moves the metadata offset out of line
+        let mut bytes = good.clone();
+        bytes.drain(index_offset + ENTRY_LEN..index_offset + 2 * ENTRY_LEN);
+        std::fs::write(&bogus, bytes).unwrap();
+        assert!(Snapshot::open(bogus.to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+//! `re.sub`-style substitution for `regex_replace_fast`.
+//!
+//! A replacement is either a template or a Python callable. Templates are
+//! parsed once against the pattern, so a reference to a group the pattern
+//! does not have is a `ValueError` up front rather than an empty string in
+//! every match (`$1a` names a group "1a"; `${1}a` is group 1 then "a").
+//! Both the `regex` crate's `$1`/`${name}` and Python's `\1`/`\g<name>`
+//! spellings are accepted, and backslash escapes (`\n`, `\\`, octal, bad
+//! letter escapes) behave as in `re.sub`. A callable is invoked per match, with the GIL
+//! held, on a [`RegexMatch`] shaped like `re.Match`.
+
+use crate::deadline::{self, Deadline};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use pyo3::IntoPyObjectExt;
+use regex::{Captures, Regex, RegexBuilder};
+use std::sync::Arc;
+
+/// Supported `re` flags: bit and name
+const FLAGS: &[(u32, &str)] = &[
+    (2, "IGNORECASE"),
+    (8, "MULTILINE"),
+    (16, "DOTALL"),
+    (32, "UNICODE"),
+    (64, "VERBOSE"),
+];
+
+/// Compile a caller's pattern with Python `re` flags (`re.IGNORECASE`,
+/// `re.MULTILINE`, `re.DOTALL`, `re.VERBOSE`; `re.UNICODE` is the default)
+pub fn user_regex(pattern: &str, flags: u32) -> PyResult<Regex> {
+    let known = FLAGS.iter().fold(0, |all, (bit, _)| all | bit);
+    if flags & !known != 0 {
+        let names: Vec<&str> = FLAGS.iter().map(|(_, name)| *name).collect();
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unsupported regex flags: {:#x} (supported: re.{})",
+            flags & !known,
+            names.join(", re.")
+        )));
+    }
+    RegexBuilder::new(pattern)
+        .case_insensitive(flags & 2 != 0)
+        .multi_line(flags & 8 != 0)
+        .dot_matches_new_line(flags & 16 != 0)
+        .ignore_whitespace(flags & 64 != 0)
+        .build()
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid regex: {e}")))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Group(usize),
+}
+
+/// What follows a `$` or `\` in a template
+enum Reference<'a> {
+    Group(&'a str),
+    Literal(String),
+}
+
+/// Python's one-letter template escapes
+const ESCAPES: &[(char, char)] = &[
+    ('a', '\x07'),
+    ('b', '\x08'),
+    ('f', '\x0c'),
+    ('n', '\n'),
+    ('r', '\r'),
+    ('t', '\t'),
+    ('v', '\x0b'),
+    ('\\', '\\'),
+];
+
+fn unterminated(what: &str) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+        "Unterminated {what} in replacement template"
+    ))
+}
+
+/// Reference after a `$` and the bytes it used
+fn dollar_reference(after: &str) -> PyResult<(Reference<'_>, usize)> {
+    if after.starts_with('$') {
+        return Ok((Reference::Literal("$".into()), 1));
+    }
+    if let Some(braced) = after.strip_prefix('{') {
+        let end = braced.find('}').ok_or_else(|| unterminated("${"))?;
+        return Ok((Reference::Group(&braced[..end]), end + 2));
+    }
+    let len = after
+        .find(|c: char| !(c == '_' || c.is_ascii_alphanumeric()))
+        .unwrap_or(after.len());
+    if len == 0 {
+        return Ok((Reference::Literal("$".into()), 0));
+    }
+    Ok((Reference::Group(&after[..len]), len))
+}
+
+/// Reference after a `\` and the bytes it used, following Python's
+/// template rules
+fn backslash_reference(after: &str) -> PyResult<(Reference<'_>, usize)> {
+    let bad_escape = |escape: &str| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Bad escape {escape} in replacement template"
+        ))
+    };
+    let Some(c) = after.chars().next() else {
+        return Err(bad_escape("\\ at the end"));
+    };
+    if let Some(named) = after.strip_prefix("g<") {
+        let end = named.find('>').ok_or_else(|| unterminated("\\g<"))?;
+        return Ok((Reference::Group(&named[..end]), end + 3));
+    }
+    if let Some(&(_, escaped)) = ESCAPES.iter().find(|&&(letter, _)| letter == c) {
+        return Ok((Reference::Literal(escaped.to_string()), 1));
+    }
+    // `\0`, `\0o`, `\0oo` and three octal digits are octal escapes; other
+    // digits are a group number of at most two digits
+    let octal = after
+        .bytes()
+        .take(3)
+        .take_while(|b| (b'0'..=b'7').contains(b))
+        .count();
+    if c == '0' || octal == 3 {
+        let len = if c == '0' { octal } else { 3 };
+        let value = u32::from_str_radix(&after[..len], 8).unwrap_or(u32::MAX);
+        let byte = u8::try_from(value).map_err(|_| {
+            bad_escape(&format!("\\{} (octal value outside 0-0o377)", &after[..len]))
+        })?;
+        return Ok((Reference::Literal(char::from(byte).to_string()), len));
+    }
+    if c.is_ascii_digit() {
+        let len = after.bytes().take(2).take_while(u8::is_ascii_digit).count();
+        return Ok((Reference::Group(&after[..len]), len));
+    }
+    if c.is_ascii_alphabetic() {
+        return Err(bad_escape(&format!("\\{c}")));
+    }
+    Ok((Reference::Literal(format!("\\{c}")), c.len_utf8()))
+}
+
+/// Index of the group `name` (a number or a group name) of `re`
+fn resolve(re: &Regex, name: &str) -> PyResult<usize> {
+    let index = if name.bytes().all(|b| b.is_ascii_digit()) && !name.is_empty() {
+        name.parse().ok().filter(|&i| i < re.captures_len())
+    } else {
+        re.capture_names().position(|group| group == Some(name))
+    };
+    index.ok_or_else(|| {
+        let hint = if name.starts_with(|c: char| c.is_ascii_digit()) {
+            " (use ${1}x to follow a group number with text)"
+        } else {
+            ""
+        };
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unknown group {name:?} in replacement template: the pattern has {} group(s){hint}",
+            re.captures_len() - 1
+        ))
+    })
+}
+
+/// A replacement template resolved against one pattern's groups
+#[derive(Debug)]
+pub struct Template(Vec<Piece>);
+
+impl Template {
+    /// Parse `template`: `$1`, `$name`, `${1}`, `${name}`, `\1`, `\g<1>` and
+    /// `\g<name>` refer to groups; `$$` is a literal `$`; `\n`, `\t`, `\\`
+    /// and the other escapes Python knows (octal `\0`, `\012`) are the
+    /// characters they stand for, other ASCII letter escapes are an error, and
+    /// a backslash before anything else is kept; the rest is copied as is
+    pub fn parse(template: &str, re: &Regex) -> PyResult<Self> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(at) = rest.find(['$', '\\']) {
+            literal.push_str(&rest[..at]);
+            let after = &rest[at + 1..];
+            let (reference, used) = if rest.as_bytes()[at] == b'$' {
+                dollar_reference(after)?
+            } else {
+                backslash_reference(after)?
+            };
+            match reference {
+                Reference::Group(name) => {
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Group(resolve(re, name)?));
+                },
+                Reference::Literal(text) => literal.push_str(&text),
+            }
+            rest = &after[used..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Self(pieces))
+    }
+
+    /// Append the replacement for one match to `out`
+    pub fn expand(&self, caps: &Captures, out: &mut String) {
+        for piece in &self.0 {
+            match piece {
+                Piece::Literal(text) => out.push_str(text),
+                Piece::Group(index) => out.push_str(caps.get(*index).map_or("", |m| m.as_str())),
+            }
+        }
+    }
+}
+
+/// Replace the first `count` matches (0: all) with what `expand` appends;
+/// `None` when `deadline` passed first
+pub fn substitute(
+    re: &Regex,
+    text: &str,
+    count: usize,
+    deadline: Deadline,
+    mut expand: impl FnMut(&Captures, &mut String) -> PyResult<()>,
+) -> PyResult<Option<String>> {
+    let limit = if count == 0 { usize::MAX } else { count };
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (i, caps) in re.captures_iter(text).take(limit).enumerate() {
+        // A clock check every few matches
+        if i % deadline::CHECK_EVERY == 0 && deadline.expired() {
+            return Ok(None);
+        }
+        let Some(m) = caps.get(0) else { continue };
+        out.push_str(&text[last..m.start()]);
+        expand(&caps, &mut out)?;
+        last = m.end();
+    }
+    out.push_str(&text[last..]);
+    Ok(Some(out))
+}
+
+/// [`substitute`] with a template, without the GIL
+pub fn replace_template(
+    py: Python<'_>,
+    re: &Regex,
+    template: &str,
+    text: &str,
+    count: usize,
+    deadline: Deadline,
+) -> PyResult<Option<String>> {
+    let template = Template::parse(template, re)?;
+    py.allow_threads(|| {
+        substitute(re, text, count, deadline, |caps, out| {
+            template.expand(caps, out);
+            Ok(())
+        })
+    })
+}
+
+/// [`substitute`] with the string `callback` returns for each match
+pub fn replace_callback(
+    re: &Regex,
+    callback: &Bound<'_, PyAny>,
+    text: &str,
+    count: usize,
+    deadline: Deadline,
+) -> PyResult<Option<String>> {
+    let names: Arc<Vec<Option<String>>> = Arc::new(
+        re.capture_names()
+            .map(|name| name.map(str::to_string))
+            .collect(),
+    );
+    // Byte and character offset of the last match start
+    let (mut byte, mut chars) = (0, 0);
+    substitute(re, text, count, deadline, |caps, out| {
+        let start = caps.get(0).map_or(byte, |m| m.start());
+        chars += text[byte..start].chars().count();
+        byte = start;
+        let found = RegexMatch::new(caps, text, (byte, chars), Arc::clone(&names));
+        let replacement = callback.call1((found,))?;
+        let replacement = replacement.extract::<String>().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "replacement callable must return str, not {}",
+                replacement
+                    .get_type()
+                    .name()
+                    .map_or_else(|_| "?".to_string(), |name| name.to_string())
+            ))
+        })?;
+        out.push_str(&replacement);
+        Ok(())
+    })
+}
+
+/// One match passed to a replacement callable, like `re.Match`
+///
+/// Offsets are in characters, as in Python.
+#[pyclass]
+pub struct RegexMatch {
+    /// Text of each group (0: the whole match); `None` if it did not take part
+    groups: Vec<Option<String>>,
+    /// Character span of each group
+    spans: Vec<Option<(usize, usize)>>,
+    /// Name of each group, shared by the matches of one call
+    names: Arc<Vec<Option<String>>>,
+}
+
+impl RegexMatch {
+    /// Match for `caps` in `text`, whose whole match starts at byte and
+    /// character offset `start`
+    fn new(
+        caps: &Captures,
+        text: &str,
+        start: (usize, usize),
+        names: Arc<Vec<Option<String>>>,
+    ) -> Self {
+        let (byte, chars) = start;
+        let char_at = |offset: usize| chars + text[byte..offset].chars().count();
+        let (groups, spans) = caps
+            .iter()
+            .map(|group| {
+                group.map_or((None, None), |m| {
+                    (Some(m.as_str().to_string()), Some((char_at(m.start()), char_at(m.end()))))
+                })
+            })
+            .unzip();
+        Self {
+            groups,
+            spans,
+            names,
+        }
+    }
+
+    /// Group index of `group` (a number or a name; `None`: the whole match)
+    fn index(&self, group: Option<&Bound<'_, PyAny>>) -> PyResult<usize> {
+        let Some(group) = group else { return Ok(0) };
+        let index = group.extract::<usize>().map_or_else(
+            |_| {
+                group.extract::<String>().ok().and_then(|name| {
+                    self.names
+                        .iter()
+                        .position(|candidate| candidate.as_deref() == Some(name.as_str()))
+                })
+            },
+            |index| Some(index).filter(|&i| i < self.groups.len()),
+        );
+        index.ok_or_else(|| PyErr::new::<pyo3::exceptions::PyIndexError, _>("no such group"))
+    }
+
+    /// `(start, end)` of a group; `(-1, -1)` if it did not take part
+    fn span_of(&self, index: usize) -> (i64, i64) {
+        self.spans[index].map_or((-1, -1), |(start, end)| {
+            (i64::try_from(start).unwrap_or(i64::MAX), i64::try_from(end).unwrap_or(i64::MAX))
+        })
+    }
+}
+
+#[pymethods]
+impl RegexMatch {
+    /// `m.group()`, `m.group(1)`, `m.group("name")` or a tuple for several
+    #[pyo3(signature = (*groups))]
+    fn group<'py>(
+        &self,
+        py: Python<'py>,
+        groups: &Bound<'py, PyTuple>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if groups.len() <= 1 {
+            let group = groups.get_item(0).ok();
+            return self.groups[self.index(group.as_ref())?]
+                .clone()
+                .into_bound_py_any(py);
+        }
+        let values = groups
+            .iter()
+            .map(|group| Ok(self.groups[self.index(Some(&group))?].clone()))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyTuple::new(py, values)?.into_bound_py_any(py)
+    }
+
+    fn __getitem__(&self, group: &Bound<'_, PyAny>) -> PyResult<Option<String>> {
+        Ok(self.groups[self.index(Some(group))?].clone())
+    }
+
+    /// Every group after the whole match; `default` for those that did not
+    /// take part
+    #[pyo3(signature = (default=None))]
+    fn groups(&self, default: Option<&str>) -> Vec<Option<String>> {
+        self.groups[1..]
+            .iter()
+            .map(|group| group.clone().or_else(|| default.map(str::to_string)))
+            .collect()
+    }
+
+    /// Named groups by name
+    #[pyo3(signature = (default=None))]
+    fn groupdict<'py>(
+        &self,
+        py: Python<'py>,
+        default: Option<&str>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, group) in self.names.iter().zip(&self.groups) {
+            if let Some(name) = name {
+                dict.set_item(name, group.clone().or_else(|| default.map(str::to_string)))?;
+            }
+        }
+        Ok(dict)
+    }
+
+    #[pyo3(signature = (group=None))]
+    fn start(&self, group: Option<&Bound<'_, PyAny>>) -> PyResult<i64> {
+        Ok(self.span_of(self.index(group)?).0)
+    }
+
+    #[pyo3(signature = (group=None))]
+    fn end(&self, group: Option<&Bound<'_, PyAny>>) -> PyResult<i64> {
+        Ok(self.span_of(self.index(group)?).1)
+    }
+
+    #[pyo3(signature = (group=None))]
+    fn span(&self, group: Option<&Bound<'_, PyAny>>) -> PyResult<(i64, i64)> {
+        Ok(self.span_of(self.index(group)?))
+    }
+
+    fn __repr__(&self) -> String {
+        let (start, end) = self.span_of(0);
+        format!(
+            "RegexMatch(span=({start}, {end}), match={:?})",
+            self.groups[0].as_deref().unwrap_or_default()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(pattern: &str, template: &str, text: &str) -> PyResult<String> {
+        let re = user_regex(pattern, 0)?;
+        let template = Template::parse(template, &re)?;
+        let deadline = Deadline::new(None)?;
+        substitute(&re, text, 0, deadline, |caps, out| {
+            template.expand(caps, out);
+            Ok(())
+        })
+        .map(Option::unwrap)
+    }
+
+    #[test]
+    fn test_template() {
+        let text = "2024-06-01";
+        let date = r"(?P<y>\d+)-(?P<m>\d+)-(\d+)";
+        assert_eq!(expand(date, "$3/$m/${y}", text).unwrap(), "01/06/2024");
+        assert_eq!(expand(date, r"\3.\g<m>.\g<1>", text).unwrap(), "01.06.2024");
+        assert_eq!(expand(date, "${1}y", text).unwrap(), "2024y");
+        assert_eq!(expand(date, r"$$1 \\1 $ \. \$", text).unwrap(), r"$1 \1 $ \. \$");
+
+        // Python's escapes: control characters, octal, groups of two digits
+        assert_eq!(expand(date, r"\n\t\r\a\\", text).unwrap(), "\n\t\r\x07\\");
+        assert_eq!(expand(date, r"\0|\012|\101|\3", text).unwrap(), "\0|\n|A|01");
+        // `\10` is group 10, not group 1 and a `0`
+        for bad in [r"\x", r"C:\Users", r"\400", "\\", r"\10"] {
+            assert!(expand(date, bad, text).is_err(), "{bad}");
+        }
+        assert_eq!(expand(r"(a)|(b)", "[$2]", "ab").unwrap(), "[][b]");
+
+        // Ambiguous and missing groups fail up front
+        let err = expand(date, "$1y", text).unwrap_err().to_string();
+        assert!(err.contains("\"1y\"") && err.contains("${1}x"), "{err}");
+        assert!(expand(date, "$4", text).is_err());
+        assert!(expand(date, r"\g<day>", text).is_err());
+        assert!(expand(date, "${1", text).is_err());
+    }
+
+    #[test]
+    fn test_flags_and_count() {
+        let re = user_regex("^b.c", 2 | 8 | 16).unwrap();
+        assert!(re.is_match("a\nB\nC"));
+        assert!(user_regex("a b", 64).unwrap().is_match("ab"));
+        assert!(user_regex("a", 4).is_err());
+        assert!(user_regex("(", 0).is_err());
+
+        let re = user_regex(r"\d", 0).unwrap();
+        let deadline = Deadline::new(None).unwrap();
+        let out = substitute(&re, "1 2 3", 2, deadline, |_, out| {
+            out.push('#');
+            Ok(())
+        });
+        assert_eq!(out.unwrap().unwrap(), "# # 3");
+        let expired = Deadline::new(Some(0.0)).unwrap();
+        assert_eq!(substitute(&re, "1", 0, expired, |_, _| Ok(())).unwrap(), None);
+    }
+
+    #[test]
+    fn test_callback() {
+        crate::with_py(|py| {
+            let re = user_regex(r"(?P<word>\w+)(!)?", 0).unwrap();
+            let callback = py
+                .eval(
+                    c"lambda m: f'{m.group(\"word\").upper()}{m.start()}{m[2] or \"\"}'",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let deadline = Deadline::new(None).unwrap();
+            let out = replace_callback(&re, &callback, "héllo wörld!", 0, deadline).unwrap();
+            assert_eq!(out.unwrap(), "HÉLLO0 WÖRLD6!");
+
+            let bad = py.eval(c"lambda m: 1", None, None).unwrap();
+            let err = replace_callback(&re, &bad, "x", 0, deadline).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTypeError>(py));
+        });
+    }
+}
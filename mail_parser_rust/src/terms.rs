@@ -0,0 +1,350 @@
+//! Language-aware term frequencies: stopword removal and Snowball stemming.
+//!
+//! Text is split into lowercase words, the language is guessed from which
+//! stopword list matches best (unless given), that language's stopwords are
+//! dropped and the rest reduced to their stems, so "invoices" and "invoice"
+//! (or "Rechnungen" and "Rechnung") count as one term and results from
+//! different languages can be compared.
+
+use crate::batch::par_map;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// A detected language needs at least this many stopword hits
+const MIN_STOPWORD_HITS: usize = 2;
+
+/// Supported languages: ISO 639-1 code, stemmer and common stopwords
+const LANGUAGES: &[(&str, Algorithm, &[&str])] = &[
+    (
+        "en",
+        Algorithm::English,
+        &[
+            "a", "about", "after", "all", "also", "am", "an", "and", "any", "are", "as", "at",
+            "be", "been", "but", "by", "can", "could", "did", "do", "does", "for", "from", "had",
+            "has", "have", "he", "her", "him", "his", "how", "i", "if", "in", "into", "is", "it",
+            "its", "just", "me", "my", "no", "not", "of", "on", "or", "our", "out", "she", "so",
+            "some", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+            "this", "to", "up", "us", "was", "we", "were", "what", "when", "which", "who", "will",
+            "with", "would", "you", "your",
+        ],
+    ),
+    (
+        "de",
+        Algorithm::German,
+        &[
+            "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "bitte", "da",
+            "das", "dass", "dem", "den", "der", "des", "die", "dies", "diese", "du", "durch",
+            "ein", "eine", "einem", "einen", "einer", "es", "für", "hat", "haben", "ich", "ihr",
+            "ihre", "im", "in", "ist", "ja", "kann", "mit", "nach", "nicht", "noch", "nur", "oder",
+            "sich", "sie", "sind", "so", "über", "um", "und", "uns", "vom", "von", "vor", "war",
+            "was", "wenn", "wie", "wir", "wird", "zu", "zum", "zur",
+        ],
+    ),
+    (
+        "fr",
+        Algorithm::French,
+        &[
+            "à", "au", "aux", "avec", "ce", "ces", "cette", "dans", "de", "des", "du", "elle",
+            "en", "est", "et", "il", "ils", "je", "la", "le", "les", "leur", "lui", "ma", "mais",
+            "me", "mes", "moi", "mon", "ne", "nous", "on", "ou", "par", "pas", "pour", "qu", "que",
+            "qui", "sa", "se", "ses", "son", "sont", "sur", "ta", "te", "tes", "toi", "ton", "tu",
+            "un", "une", "vos", "votre", "vous",
+        ],
+    ),
+    (
+        "es",
+        Algorithm::Spanish,
+        &[
+            "al", "algo", "como", "con", "de", "del", "el", "ella", "ellos", "en", "es", "esta",
+            "este", "esto", "fue", "ha", "hay", "la", "las", "le", "les", "lo", "los", "mas", "me",
+            "mi", "muy", "no", "nos", "para", "pero", "por", "porque", "que", "se", "si", "sin",
+            "sobre", "su", "sus", "también", "te", "tu", "un", "una", "y", "ya", "yo",
+        ],
+    ),
+    (
+        "it",
+        Algorithm::Italian,
+        &[
+            "a", "al", "alla", "anche", "che", "ci", "come", "con", "da", "del", "della", "di",
+            "e", "è", "gli", "ha", "hanno", "il", "in", "io", "la", "le", "lo", "ma", "mi", "nel",
+            "nella", "non", "per", "più", "questo", "se", "si", "sono", "su", "ti", "tu", "un",
+            "una", "uno", "vi",
+        ],
+    ),
+    (
+        "pt",
+        Algorithm::Portuguese,
+        &[
+            "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "é", "ela", "ele",
+            "em", "eu", "foi", "isso", "mais", "mas", "me", "na", "não", "nas", "no", "nos", "o",
+            "os", "ou", "para", "pela", "pelo", "por", "que", "se", "sem", "seu", "sua", "também",
+            "um", "uma", "você",
+        ],
+    ),
+    (
+        "nl",
+        Algorithm::Dutch,
+        &[
+            "aan", "al", "als", "bij", "dan", "dat", "de", "die", "dit", "een", "en", "er", "had",
+            "heb", "hem", "het", "hij", "hoe", "ik", "in", "is", "je", "kan", "maar", "me", "met",
+            "mij", "naar", "niet", "nog", "of", "om", "ons", "ook", "op", "over", "te", "tot", "u",
+            "uit", "van", "voor", "was", "wat", "we", "wel", "wij", "zijn", "ze", "zo",
+        ],
+    ),
+    (
+        "sv",
+        Algorithm::Swedish,
+        &[
+            "att", "av", "de", "del", "den", "det", "din", "du", "en", "ett", "för", "från", "har",
+            "inte", "jag", "kan", "med", "men", "mig", "min", "nu", "och", "om", "på", "sig",
+            "som", "så", "till", "ut", "vad", "var", "vi", "är",
+        ],
+    ),
+    (
+        "da",
+        Algorithm::Danish,
+        &[
+            "af", "at", "de", "dem", "den", "der", "det", "du", "en", "er", "et", "for", "fra",
+            "har", "hun", "ikke", "jeg", "kan", "med", "men", "mig", "min", "og", "om", "på",
+            "som", "så", "til", "var", "vi", "vil",
+        ],
+    ),
+    (
+        "no",
+        Algorithm::Norwegian,
+        &[
+            "av", "da", "de", "deg", "den", "det", "du", "en", "er", "et", "for", "fra", "har",
+            "hun", "ikke", "jeg", "kan", "med", "men", "meg", "min", "og", "om", "på", "som", "så",
+            "til", "var", "vi", "vil",
+        ],
+    ),
+    (
+        "ru",
+        Algorithm::Russian,
+        &[
+            "а", "без", "был", "была", "были", "в", "вам", "вас", "во", "вы", "да", "для", "до",
+            "его", "ее", "если", "есть", "же", "за", "и", "из", "или", "к", "как", "мы", "на",
+            "не", "нет", "но", "о", "об", "от", "по", "при", "с", "так", "то", "у", "уже", "что",
+            "это", "я",
+        ],
+    ),
+];
+
+lazy_static! {
+    /// Stopword sets, indexed like `LANGUAGES`
+    pub static ref STOPWORDS: Vec<HashSet<&'static str>> = LANGUAGES
+        .iter()
+        .map(|(_, _, words)| words.iter().copied().collect())
+        .collect();
+}
+
+/// Term counts for one text
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct TermFrequencies {
+    /// Language used (ISO 639-1), or None when none was detected
+    #[pyo3(get)]
+    pub language: Option<String>,
+    /// Words in the text before stopword removal
+    #[pyo3(get)]
+    pub word_count: usize,
+    /// Term (stem, or word when not stemming) to number of occurrences
+    #[pyo3(get)]
+    pub terms: HashMap<String, usize>,
+}
+
+/// Lowercase words: runs of alphanumerics containing at least one letter
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().any(char::is_alphabetic))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Index into `LANGUAGES` of the best-matching stopword list
+fn detect(words: &[String]) -> Option<usize> {
+    let mut best: Option<(usize, usize)> = None;
+    for (i, stopwords) in STOPWORDS.iter().enumerate() {
+        let hits = words
+            .iter()
+            .filter(|w| stopwords.contains(w.as_str()))
+            .count();
+        if hits >= MIN_STOPWORD_HITS && best.map_or(true, |(_, most)| hits > most) {
+            best = Some((i, hits));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Resolve the `language` argument: "auto", or an ISO 639-1 code
+fn language_index(language: &str, words: &[String]) -> PyResult<Option<usize>> {
+    if language == "auto" {
+        return Ok(detect(words));
+    }
+    LANGUAGES
+        .iter()
+        .position(|(code, _, _)| code.eq_ignore_ascii_case(language))
+        .map(Some)
+        .ok_or_else(|| {
+            let codes: Vec<&str> = LANGUAGES.iter().map(|(code, _, _)| *code).collect();
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unsupported language {language:?}; expected \"auto\" or one of {}",
+                codes.join(", ")
+            ))
+        })
+}
+
+fn count_terms(
+    text: &str,
+    language: &str,
+    remove_stopwords: bool,
+    stem: bool,
+) -> PyResult<TermFrequencies> {
+    let words = words(text);
+    let index = language_index(language, &words)?;
+    let stemmer = index
+        .filter(|_| stem)
+        .map(|i| Stemmer::create(LANGUAGES[i].1));
+    let stopwords = index.filter(|_| remove_stopwords).map(|i| &STOPWORDS[i]);
+    let mut terms = HashMap::new();
+    for word in &words {
+        if stopwords.is_some_and(|s| s.contains(word.as_str())) {
+            continue;
+        }
+        let term = stemmer
+            .as_ref()
+            .map_or_else(|| word.clone(), |s| s.stem(word).into_owned());
+        *terms.entry(term).or_insert(0) += 1;
+    }
+    Ok(TermFrequencies {
+        language: index.map(|i| LANGUAGES[i].0.to_string()),
+        word_count: words.len(),
+        terms,
+    })
+}
+
+/// Guess the language of a text from its stopwords
+///
+/// # Arguments
+/// * `text` - Plain text
+///
+/// # Returns
+/// * ISO 639-1 code (en, de, fr, es, it, pt, nl, sv, da, no, ru), or None when
+///   too few stopwords of any language occur
+#[pyfunction]
+pub fn detect_language_fast(text: &str) -> Option<String> {
+    detect(&words(text)).map(|i| LANGUAGES[i].0.to_string())
+}
+
+/// Count terms in a text with per-language stopword removal and stemming
+///
+/// # Arguments
+/// * `text` - Plain text (e.g. a message body with quotes stripped)
+/// * `language` - "auto" (default) to detect per text, or an ISO 639-1 code
+///   (see `detect_language_fast`)
+/// * `remove_stopwords` - Drop the language's stopwords (default true)
+/// * `stem` - Reduce words to their Snowball stem (default true)
+///
+/// # Returns
+/// * `TermFrequencies`; when no language is detected, words are counted
+///   lowercased but otherwise unchanged
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import term_frequencies
+/// tf = term_frequencies("The invoices are attached; the invoice total is due.")
+/// print(tf.language, tf.terms)  # en {'invoic': 2, 'attach': 1, 'total': 1, 'due': 1}
+/// ```
+#[pyfunction]
+#[pyo3(signature = (text, language="auto", remove_stopwords=true, stem=true))]
+pub fn term_frequencies(
+    text: &str,
+    language: &str,
+    remove_stopwords: bool,
+    stem: bool,
+) -> PyResult<TermFrequencies> {
+    count_terms(text, language, remove_stopwords, stem)
+}
+
+/// Batch version of [`term_frequencies`], processed in parallel
+///
+/// The language is detected per text with `language="auto"`.
+///
+/// # Arguments
+/// * `texts` - Plain texts
+/// * `language` - "auto" (default) or an ISO 639-1 code
+/// * `remove_stopwords` - Drop the language's stopwords (default true)
+/// * `stem` - Reduce words to their Snowball stem (default true)
+///
+/// # Returns
+/// * One `TermFrequencies` per text
+#[pyfunction]
+#[pyo3(signature = (texts, language="auto", remove_stopwords=true, stem=true))]
+pub fn term_frequencies_batch(
+    py: Python<'_>,
+    texts: Vec<String>,
+    language: &str,
+    remove_stopwords: bool,
+    stem: bool,
+) -> PyResult<Vec<TermFrequencies>> {
+    py.allow_threads(|| {
+        language_index(language, &[])?;
+        par_map(texts, |text| count_terms(&text, language, remove_stopwords, stem))
+            .into_iter()
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language_fast(
+                "Please find the report attached, and let me know what you think."
+            )
+            .as_deref(),
+            Some("en")
+        );
+        assert_eq!(
+            detect_language_fast("Die Rechnungen sind im Anhang, bitte prüfen Sie die Beträge.")
+                .as_deref(),
+            Some("de")
+        );
+        assert_eq!(
+            detect_language_fast("Nous avons reçu votre message et nous vous répondrons.")
+                .as_deref(),
+            Some("fr")
+        );
+        assert_eq!(detect_language_fast("Invoice 4711"), None);
+    }
+
+    #[test]
+    fn test_term_frequencies() {
+        crate::with_py(|py| {
+            let tf =
+                count_terms("The invoices are attached; the invoice is due.", "auto", true, true)
+                    .unwrap();
+            assert_eq!(tf.language.as_deref(), Some("en"));
+            assert_eq!(tf.word_count, 8);
+            assert_eq!(tf.terms["invoic"], 2);
+            assert!(!tf.terms.contains_key("the"));
+
+            let de = count_terms("Rechnung und Rechnungen", "de", true, true).unwrap();
+            assert_eq!(de.terms.len(), 1);
+            assert_eq!(de.terms.values().sum::<usize>(), 2);
+
+            let raw = count_terms("The invoices, the invoices", "en", false, false).unwrap();
+            assert_eq!(raw.terms["the"], 2);
+            assert_eq!(raw.terms["invoices"], 2);
+
+            assert!(count_terms("text", "xx", true, true).is_err());
+            assert!(term_frequencies_batch(py, vec!["a".into()], "klingon", true, true).is_err());
+        });
+    }
+}
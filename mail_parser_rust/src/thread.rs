@@ -0,0 +1,701 @@
+//! Conversation grouping by Message-ID / In-Reply-To / References.
+//!
+//! Messages that reference each other (directly or through a shared ancestor,
+//! even one missing from the archive) end up in the same thread. Replies with
+//! no reference headers at all fall back to matching on normalized subject.
+//!
+//! [`group_threads`] only answers which thread a message is in;
+//! [`jwz_threads`] also rebuilds the reply tree with Jamie Zawinski's
+//! algorithm, keeping placeholders for ancestors missing from the input.
+
+use crate::date::parse_date;
+use crate::patterns::compile;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+
+lazy_static! {
+    pub static ref MESSAGE_ID: Regex = compile("thread.message_id", r"<([^<>\s]+)>");
+    pub static ref REPLY_PREFIX: Regex =
+        compile("thread.reply_prefix", r"(?i)^\s*((re|fwd?|aw|wg|sv|vs)(\[\d+\])?\s*:\s*)+");
+}
+
+/// The threading-relevant headers of one message
+#[derive(Default, Clone, Debug)]
+pub struct ThreadKey {
+    pub message_id: Option<String>,
+    /// In-Reply-To followed by References, oldest ancestor last
+    pub references: Vec<String>,
+    pub subject: String,
+}
+
+impl ThreadKey {
+    /// Build from raw header values
+    pub fn from_headers(
+        message_id: Option<&str>,
+        in_reply_to: Option<&str>,
+        references: Option<&str>,
+        subject: Option<&str>,
+    ) -> Self {
+        let mut refs = in_reply_to.map(parse_ids).unwrap_or_default();
+        refs.extend(references.map(parse_ids).unwrap_or_default());
+        Self {
+            message_id: message_id.and_then(|v| parse_ids(v).into_iter().next()),
+            references: refs,
+            subject: subject.unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// All `<id>` tokens in a header value, without the angle brackets
+pub fn parse_ids(value: &str) -> Vec<String> {
+    MESSAGE_ID
+        .captures_iter(value)
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+/// Subject with reply/forward prefixes removed and whitespace collapsed
+pub fn normalize_subject(subject: &str) -> String {
+    REPLY_PREFIX
+        .replace(subject, "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Whether a subject carries a reply/forward prefix
+pub fn is_reply(subject: &str) -> bool {
+    REPLY_PREFIX.is_match(subject)
+}
+
+/// Disjoint sets over `0..n`, the lowest index of each set as its root
+pub struct UnionFind(Vec<usize>);
+
+impl UnionFind {
+    pub fn new(n: usize) -> Self {
+        Self((0..n).collect())
+    }
+
+    pub fn find(&mut self, mut x: usize) -> usize {
+        while self.0[x] != x {
+            self.0[x] = self.0[self.0[x]];
+            x = self.0[x];
+        }
+        x
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            // Keep the lower index as root so thread order follows input order
+            let (low, high) = if ra < rb { (ra, rb) } else { (rb, ra) };
+            self.0[high] = low;
+        }
+    }
+}
+
+/// Group messages into threads; each thread lists message indices in input order
+pub fn group_threads(keys: &[ThreadKey]) -> Vec<Vec<usize>> {
+    let mut uf = UnionFind::new(keys.len());
+    // Index of the first message carrying or referencing each ID
+    let mut by_id: HashMap<&str, usize> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        for id in key.message_id.iter().chain(&key.references) {
+            match by_id.get(id.as_str()) {
+                Some(&j) => uf.union(i, j),
+                None => {
+                    by_id.insert(id, i);
+                },
+            }
+        }
+    }
+
+    // Subject fallback for replies that lost their reference headers
+    let mut by_subject: HashMap<String, usize> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        let normalized = normalize_subject(&key.subject);
+        if normalized.is_empty() {
+            continue;
+        }
+        match by_subject.get(&normalized) {
+            Some(&j) if key.references.is_empty() && is_reply(&key.subject) => uf.union(i, j),
+            Some(_) => {},
+            None => {
+                by_subject.insert(normalized, i);
+            },
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for i in 0..keys.len() {
+        let root = uf.find(i);
+        let group = *group_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(i);
+    }
+    groups
+}
+
+/// [`group_threads`] with each thread in date order and threads ordered by
+/// their first message; undated messages sort after dated ones, otherwise
+/// archive order is kept
+pub fn chronological_threads(keys: &[ThreadKey], epochs: &[Option<i64>]) -> Vec<Vec<usize>> {
+    let order = |i: &usize| (epochs[*i].is_none(), epochs[*i], *i);
+    let mut threads = group_threads(keys);
+    for thread in &mut threads {
+        thread.sort_by_key(order);
+    }
+    threads.sort_by_key(|thread| thread.first().map(order));
+    threads
+}
+
+/// Threading headers of one message for [`jwz_threads`]
+#[derive(Default, Clone, Debug)]
+pub struct JwzMessage {
+    pub message_id: Option<String>,
+    /// Ancestors oldest first; the last one is the parent
+    pub references: Vec<String>,
+    pub subject: String,
+    pub epoch: Option<i64>,
+}
+
+impl JwzMessage {
+    /// Build from raw header values: the References chain, with the
+    /// In-Reply-To ID appended when References does not mention it
+    pub fn from_headers(
+        message_id: Option<&str>,
+        in_reply_to: Option<&str>,
+        references: Option<&str>,
+        subject: Option<&str>,
+        date: Option<&str>,
+    ) -> Self {
+        let mut chain = references.map(parse_ids).unwrap_or_default();
+        if let Some(parent) = in_reply_to.and_then(|v| parse_ids(v).into_iter().next()) {
+            if !chain.contains(&parent) {
+                chain.push(parent);
+            }
+        }
+        Self {
+            message_id: message_id.and_then(|v| parse_ids(v).into_iter().next()),
+            references: chain,
+            subject: subject.unwrap_or_default().to_string(),
+            epoch: date.and_then(parse_date).map(|(epoch, _)| epoch),
+        }
+    }
+}
+
+/// One node of a reconstructed thread
+// `Self` cannot name the field type in PyO3's generated getter
+#[allow(clippy::use_self)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[pyclass]
+pub struct ThreadNode {
+    /// Position of the message in the input; None for a placeholder
+    /// standing in for a referenced message that is not in the input
+    #[pyo3(get)]
+    pub index: Option<usize>,
+    /// Message-ID without angle brackets, when known
+    #[pyo3(get)]
+    pub message_id: Option<String>,
+    /// Subject as given (empty for placeholders)
+    #[pyo3(get)]
+    pub subject: String,
+    /// Replies, oldest first
+    #[pyo3(get)]
+    pub children: Vec<ThreadNode>,
+}
+
+#[pymethods]
+impl ThreadNode {
+    /// Input positions of every message in this subtree, depth first
+    pub fn messages(&self) -> Vec<usize> {
+        let mut out = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            out.extend(node.index);
+            stack.extend(node.children.iter().rev());
+        }
+        out
+    }
+
+    fn __len__(&self) -> usize {
+        self.messages().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ThreadNode(index={:?}, message_id={:?}, children={})",
+            self.index,
+            self.message_id,
+            self.children.len()
+        )
+    }
+}
+
+impl Drop for ThreadNode {
+    /// Frees the subtree without recursing, so a thread as deep as the
+    /// longest reply chain cannot overflow the stack
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// A slot in the ID table: a message, or a placeholder for a referenced ID
+#[derive(Default)]
+struct Container {
+    message: Option<usize>,
+    id: Option<String>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// Containers linked by message references
+struct Arena(Vec<Container>);
+
+impl Arena {
+    fn add(&mut self, id: Option<&str>) -> usize {
+        self.0.push(Container {
+            id: id.map(ToString::to_string),
+            ..Container::default()
+        });
+        self.0.len() - 1
+    }
+
+    /// Whether `ancestor` is `node` or one of its parents
+    fn reaches(&self, ancestor: usize, mut node: usize) -> bool {
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match self.0[node].parent {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn unlink(&mut self, child: usize) {
+        if let Some(parent) = self.0[child].parent.take() {
+            self.0[parent].children.retain(|&c| c != child);
+        }
+    }
+
+    fn link(&mut self, parent: usize, child: usize) {
+        self.unlink(child);
+        self.0[child].parent = Some(parent);
+        self.0[parent].children.push(child);
+    }
+
+    /// Drop childless placeholders and splice out placeholders with
+    /// children, except at the root where one with several children stays
+    /// to hold its siblings together
+    fn prune(&mut self, list: Vec<usize>, root: bool) -> Vec<usize> {
+        // Every container below `list`, parents before their children
+        let mut order = Vec::new();
+        let mut stack = list.clone();
+        while let Some(c) = stack.pop() {
+            order.push(c);
+            stack.extend(&self.0[c].children);
+        }
+        // Children first, so each placeholder is spliced with pruned children
+        for &c in order.iter().rev() {
+            let children = std::mem::take(&mut self.0[c].children);
+            self.0[c].children = self.splice(children, false);
+        }
+        self.splice(list, root)
+    }
+
+    /// `list` with placeholders replaced by their (already pruned)
+    /// children, as [`Arena::prune`] describes
+    fn splice(&mut self, list: Vec<usize>, root: bool) -> Vec<usize> {
+        let mut out = Vec::with_capacity(list.len());
+        for c in list {
+            if self.0[c].message.is_none() && (!root || self.0[c].children.len() <= 1) {
+                out.append(&mut self.0[c].children);
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+}
+
+/// Reply trees for `messages` by the JWZ algorithm
+///
+/// Parents come from References (or In-Reply-To); links that would form a
+/// loop are ignored and a duplicate Message-ID is treated as absent. Root
+/// messages with the same normalized subject are then merged when one of
+/// them is a reply (or a placeholder), as in [`group_threads`]: a reply goes
+/// under the original, two replies under a new placeholder. Siblings and
+/// roots are ordered by date (undated last), then input order.
+pub fn jwz_threads(messages: &[JwzMessage]) -> Vec<ThreadNode> {
+    let mut arena = Arena(Vec::new());
+    let mut by_id: HashMap<&str, usize> = HashMap::new();
+    for (i, msg) in messages.iter().enumerate() {
+        let this = match msg.message_id.as_deref() {
+            Some(id) => match by_id.get(id) {
+                Some(&c) if arena.0[c].message.is_none() => c,
+                Some(_) => arena.add(None),
+                None => {
+                    let c = arena.add(Some(id));
+                    by_id.insert(id, c);
+                    c
+                },
+            },
+            None => arena.add(None),
+        };
+        arena.0[this].message = Some(i);
+
+        // Chain the references, keeping links made by earlier messages
+        let mut previous: Option<usize> = None;
+        for id in &msg.references {
+            let c = *by_id.entry(id).or_insert_with(|| arena.add(Some(id)));
+            if let Some(p) = previous {
+                if arena.0[c].parent.is_none() && !arena.reaches(c, p) {
+                    arena.link(p, c);
+                }
+            }
+            previous = Some(c);
+        }
+        // The message's own last reference always decides its parent
+        match previous {
+            Some(p) if !arena.reaches(this, p) => arena.link(p, this),
+            Some(_) => {},
+            None => arena.unlink(this),
+        }
+    }
+
+    let roots: Vec<usize> = (0..arena.0.len())
+        .filter(|&c| arena.0[c].parent.is_none())
+        .collect();
+    let roots = arena.prune(roots, true);
+    let roots = merge_by_subject(&mut arena, messages, roots);
+    let mut trees: Vec<(SortKey, ThreadNode)> = roots
+        .into_iter()
+        .map(|c| build_node(&arena, messages, c))
+        .collect();
+    trees.sort_by_key(|(key, _)| *key);
+    trees.into_iter().map(|(_, node)| node).collect()
+}
+
+/// Orders siblings: undated last, then by date, then input position
+type SortKey = (bool, Option<i64>, usize);
+
+/// The tree under container `c`, with its sort key (a placeholder sorts as
+/// its earliest child)
+fn build_node(arena: &Arena, messages: &[JwzMessage], c: usize) -> (SortKey, ThreadNode) {
+    // Every container of the tree, parents before their children
+    let mut order = Vec::new();
+    let mut stack = vec![c];
+    while let Some(c) = stack.pop() {
+        order.push(c);
+        stack.extend(&arena.0[c].children);
+    }
+    // Built children first, each waiting here for its parent
+    let mut built: HashMap<usize, (SortKey, ThreadNode)> = HashMap::new();
+    for &c in order.iter().rev() {
+        let container = &arena.0[c];
+        let mut children: Vec<(SortKey, ThreadNode)> = container
+            .children
+            .iter()
+            .filter_map(|child| built.remove(child))
+            .collect();
+        children.sort_by_key(|(key, _)| *key);
+        let key = container.message.map_or_else(
+            || {
+                children
+                    .first()
+                    .map_or((true, None, usize::MAX), |(key, _)| *key)
+            },
+            |i| (messages[i].epoch.is_none(), messages[i].epoch, i),
+        );
+        let message = container.message.map(|i| &messages[i]);
+        let node = ThreadNode {
+            index: container.message,
+            message_id: message
+                .and_then(|m| m.message_id.clone())
+                .or_else(|| container.id.clone()),
+            subject: message.map(|m| m.subject.clone()).unwrap_or_default(),
+            children: children.into_iter().map(|(_, node)| node).collect(),
+        };
+        built.insert(c, (key, node));
+    }
+    built.remove(&c).unwrap_or_default()
+}
+
+/// Subject of a root: its message's, or its first child's for a placeholder
+fn root_subject<'m>(arena: &Arena, messages: &'m [JwzMessage], c: usize) -> &'m str {
+    let container = &arena.0[c];
+    container
+        .message
+        .or_else(|| {
+            container
+                .children
+                .iter()
+                .find_map(|&child| arena.0[child].message)
+        })
+        .map_or("", |i| messages[i].subject.as_str())
+}
+
+/// Merge roots sharing a normalized subject (the JWZ subject pass)
+fn merge_by_subject(arena: &mut Arena, messages: &[JwzMessage], roots: Vec<usize>) -> Vec<usize> {
+    let subjects: Vec<String> = roots
+        .iter()
+        .map(|&c| normalize_subject(root_subject(arena, messages, c)))
+        .collect();
+    let is_placeholder = |arena: &Arena, c: usize| arena.0[c].message.is_none();
+    let replies = |arena: &Arena, c: usize| {
+        is_placeholder(arena, c) || is_reply(root_subject(arena, messages, c))
+    };
+
+    // Which root each subject collects into: a placeholder, else a non-reply
+    let mut target: HashMap<&str, usize> = HashMap::new();
+    for (pos, subject) in subjects.iter().enumerate() {
+        if subject.is_empty() {
+            continue;
+        }
+        let c = roots[pos];
+        match target.get(subject.as_str()) {
+            None => {
+                target.insert(subject, pos);
+            },
+            Some(&old) => {
+                let old = roots[old];
+                let better = (is_placeholder(arena, c) && !is_placeholder(arena, old))
+                    || (!is_placeholder(arena, old)
+                        && !is_placeholder(arena, c)
+                        && is_reply(root_subject(arena, messages, old))
+                        && !is_reply(root_subject(arena, messages, c)));
+                if better {
+                    target.insert(subject, pos);
+                }
+            },
+        }
+    }
+
+    let mut slots: Vec<Option<usize>> = roots.into_iter().map(Some).collect();
+    for (pos, subject) in subjects.iter().enumerate() {
+        let Some(&into) = target.get(subject.as_str()) else {
+            continue;
+        };
+        let (Some(c), Some(t)) = (slots[pos], slots[into]) else {
+            continue;
+        };
+        // Same subject without a reply prefix on either side stays separate
+        if c == t || !(replies(arena, c) || replies(arena, t)) {
+            continue;
+        }
+        if is_placeholder(arena, t) && is_placeholder(arena, c) {
+            for child in std::mem::take(&mut arena.0[c].children) {
+                arena.0[child].parent = None;
+                arena.link(t, child);
+            }
+        } else if is_placeholder(arena, t)
+            || (is_reply(root_subject(arena, messages, c))
+                && !is_reply(root_subject(arena, messages, t)))
+        {
+            arena.link(t, c);
+        } else {
+            let holder = arena.add(None);
+            arena.link(holder, t);
+            arena.link(holder, c);
+            slots[into] = Some(holder);
+        }
+        slots[pos] = None;
+    }
+    slots.into_iter().flatten().collect()
+}
+
+/// Header value from a caller-supplied mapping, by case-insensitive name
+fn header_value<'h>(headers: &'h HashMap<String, Option<String>>, name: &str) -> Option<&'h str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| value.as_deref())
+}
+
+/// Reconstruct conversation trees (JWZ threading)
+///
+/// Parents come from `References` (or `In-Reply-To`); replies that lost
+/// those headers are attached by normalized subject when they carry a
+/// reply prefix (`Re:`, `AW:`, ...). Referenced messages missing from the
+/// input appear as placeholder nodes (`index` None) so their replies stay
+/// together.
+///
+/// # Arguments
+/// * `headers_list` - One dict per message with any of `Message-ID`,
+///   `In-Reply-To`, `References`, `Subject` and `Date` (names are
+///   case-insensitive, missing or None values are ignored)
+///
+/// # Returns
+/// * Root `ThreadNode`s ordered by date (undated last, then input order),
+///   each with `index` (position in `headers_list`), `message_id`,
+///   `subject` and `children` (oldest first); `messages()` lists a
+///   subtree's positions depth first
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import thread_messages_fast
+/// def show(node, depth=0):
+///     print("  " * depth, node.subject or "(missing)")
+///     for child in node.children:
+///         show(child, depth + 1)
+/// for root in thread_messages_fast([dict(msg.items()) for msg in messages]):
+///     show(root)
+/// ```
+#[pyfunction]
+pub fn thread_messages_fast(
+    py: Python<'_>,
+    headers_list: Vec<HashMap<String, Option<String>>>,
+) -> Vec<ThreadNode> {
+    py.allow_threads(|| {
+        let messages: Vec<JwzMessage> = headers_list
+            .into_iter()
+            .map(|headers| {
+                JwzMessage::from_headers(
+                    header_value(&headers, "Message-ID"),
+                    header_value(&headers, "In-Reply-To"),
+                    header_value(&headers, "References"),
+                    header_value(&headers, "Subject"),
+                    header_value(&headers, "Date"),
+                )
+            })
+            .collect();
+        jwz_threads(&messages)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: &str, refs: &str, subject: &str) -> ThreadKey {
+        ThreadKey::from_headers(Some(id), None, Some(refs), Some(subject))
+    }
+
+    #[test]
+    fn test_group_threads() {
+        let keys = vec![
+            key("<a@x>", "", "Plans"),
+            key("<b@x>", "", "Other topic"),
+            // References a missing parent shared with message 3
+            key("<c@x>", "<a@x> <gone@x>", "Re: Plans"),
+            key("<d@x>", "<gone@x>", "Re: Plans"),
+            // No references, reply prefix: joins by subject
+            key("<e@x>", "", "RE: Fwd: other   TOPIC"),
+            // Same subject but not a reply: stays separate
+            key("<f@x>", "", "Plans"),
+        ];
+        assert_eq!(group_threads(&keys), vec![vec![0, 2, 3], vec![1, 4], vec![5]]);
+    }
+
+    fn jwz(id: Option<&str>, irt: Option<&str>, refs: &str, subject: &str) -> JwzMessage {
+        JwzMessage::from_headers(id, irt, Some(refs), Some(subject), None)
+    }
+
+    /// (index or "-", children) as a compact string
+    fn shape(node: &ThreadNode) -> String {
+        let own = node
+            .index
+            .map_or_else(|| "-".to_string(), |i| i.to_string());
+        if node.children.is_empty() {
+            own
+        } else {
+            let children: Vec<String> = node.children.iter().map(shape).collect();
+            format!("{own}({})", children.join(" "))
+        }
+    }
+
+    #[test]
+    fn test_jwz_threads() {
+        let messages = vec![
+            jwz(Some("<a@x>"), None, "", "Plans"),
+            jwz(Some("<b@x>"), None, "", "Other topic"),
+            jwz(Some("<c@x>"), Some("<a@x>"), "", "Re: Plans"),
+            // Parent missing from the input, grandparent present
+            jwz(Some("<d@x>"), None, "<a@x> <gone@x>", "Re: Plans"),
+            // Two replies to a message that is not in the input
+            jwz(Some("<e@x>"), None, "<lost@x>", "Re: Lost"),
+            jwz(Some("<f@x>"), Some("<lost@x>"), "", "Re: Lost"),
+            // No references, reply prefix: joins by subject
+            jwz(None, None, "", "RE: other   topic"),
+            // Same subject but not a reply: stays separate
+            jwz(Some("<g@x>"), None, "", "Plans"),
+            // Duplicate Message-ID is treated as absent
+            jwz(Some("<a@x>"), None, "<c@x>", "Re: Plans"),
+        ];
+        let roots = jwz_threads(&messages);
+        let shapes: Vec<String> = roots.iter().map(shape).collect();
+        assert_eq!(shapes, ["0(2(8) 3)", "1(6)", "-(4 5)", "7"]);
+        assert_eq!(roots[2].message_id.as_deref(), Some("lost@x"));
+        assert_eq!(roots[0].messages(), [0, 2, 8, 3]);
+
+        // Loops are broken rather than followed
+        let looped = vec![
+            jwz(Some("<p@x>"), None, "<q@x>", "Re: x"),
+            jwz(Some("<q@x>"), None, "<p@x>", "Re: x"),
+        ];
+        let roots = jwz_threads(&looped);
+        assert_eq!(roots.iter().map(shape).collect::<Vec<_>>(), ["1(0)"]);
+    }
+
+    #[test]
+    fn test_jwz_deep_chain() {
+        // One reply per message, each under a missing message, threaded on
+        // a small stack: nothing may recurse per level
+        const DEPTH: usize = 5_000;
+        let messages: Vec<JwzMessage> = (0..DEPTH)
+            .map(|i| {
+                let refs = if i == 0 {
+                    String::new()
+                } else {
+                    format!("<m{}@x> <gap{i}@x>", i - 1)
+                };
+                jwz(Some(&format!("<m{i}@x>")), None, &refs, "Re: bot")
+            })
+            .collect();
+        let thread = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                let roots = jwz_threads(&messages);
+                let mut depth = 0;
+                let mut node = &roots[0];
+                while let Some(child) = node.children.first() {
+                    assert_eq!(node.children.len(), 1);
+                    node = child;
+                    depth += 1;
+                }
+                (roots.len(), depth, roots[0].messages().len())
+            })
+            .unwrap();
+        assert_eq!(thread.join().unwrap(), (1, DEPTH - 1, DEPTH));
+    }
+
+    #[test]
+    fn test_jwz_dates_and_subject_merge() {
+        let mut later = jwz(Some("<a@x>"), None, "", "Status");
+        later.epoch = Some(200);
+        let mut earlier = jwz(Some("<b@x>"), None, "", "Agenda");
+        earlier.epoch = Some(100);
+        // Two unrelated replies with the same subject share a placeholder
+        let first = jwz(Some("<c@x>"), None, "", "Re: Budget");
+        let second = jwz(Some("<d@x>"), None, "", "Re: budget");
+        let roots = jwz_threads(&[later, earlier, first, second]);
+        assert_eq!(roots.iter().map(shape).collect::<Vec<_>>(), ["1", "0", "-(2 3)"]);
+    }
+
+    #[test]
+    fn test_normalize_subject() {
+        assert_eq!(normalize_subject("Re: RE[2]: Fwd:  Hello   World"), "hello world");
+        assert_eq!(parse_ids("<a@x> junk <b@y>"), vec!["a@x", "b@y"]);
+    }
+}
@@ -0,0 +1,253 @@
+//! Per-thread participant and timeline summaries ("open loops" reporting).
+//!
+//! Threads are the same reconstruction and numbering that
+//! `export_conversations_text` uses, so `thread_id` values line up with the
+//! transcript file names.
+
+use crate::date::{format_utc, parse_date};
+use crate::folders::is_automated;
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{address_list, Message};
+use crate::progress::Progress;
+use crate::thread::{chronological_threads, ThreadKey};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One reconstructed thread
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ThreadSummary {
+    /// 1-based position in chronological order of first messages
+    #[pyo3(get)]
+    pub thread_id: usize,
+    /// Subject of the first message
+    #[pyo3(get)]
+    pub subject: String,
+    /// Lowercased From/To/Cc addresses in order of first appearance
+    #[pyo3(get)]
+    pub participants: Vec<String>,
+    #[pyo3(get)]
+    pub message_count: usize,
+    /// First and last message date (UTC, ISO 8601)
+    #[pyo3(get)]
+    pub start: Option<String>,
+    #[pyo3(get)]
+    pub end: Option<String>,
+    /// Raw size of all messages in bytes
+    #[pyo3(get)]
+    pub total_bytes: usize,
+    /// Sender of the last message
+    #[pyo3(get)]
+    pub last_sender: Option<String>,
+    /// Whether the thread ends with a message from someone else that the
+    /// owner never answered (None when no identity was given); list and
+    /// automated mail never counts as awaiting a reply
+    #[pyo3(get)]
+    pub awaiting_reply: Option<bool>,
+}
+
+#[pymethods]
+impl ThreadSummary {
+    /// Version of the serialized layout (see `result_schema`)
+    #[classattr]
+    pub const SCHEMA_VERSION: u32 = 1;
+}
+
+/// What a summary needs from one message
+struct Entry {
+    key: ThreadKey,
+    epoch: Option<i64>,
+    sender: Option<String>,
+    addresses: Vec<String>,
+    bulk: bool,
+    size: usize,
+}
+
+impl Entry {
+    fn parse(raw: &[u8]) -> Self {
+        let msg = Message::parse(raw);
+        let addresses: Vec<String> = ["From", "To", "Cc"]
+            .iter()
+            .filter_map(|name| msg.decoded_header(name))
+            .flat_map(|v| address_list(&v))
+            .map(|(_, address)| address)
+            .collect();
+        let sender = msg
+            .decoded_header("From")
+            .and_then(|v| address_list(&v).into_iter().next())
+            .map(|(_, address)| address);
+        let bulk = msg.header("List-Id").is_some()
+            || sender.as_deref().is_some_and(|s| is_automated(&msg, s));
+        Self {
+            key: ThreadKey::from_headers(
+                msg.header("Message-ID"),
+                msg.header("In-Reply-To"),
+                msg.header("References"),
+                msg.decoded_header("Subject").as_deref(),
+            ),
+            epoch: msg
+                .header("Date")
+                .and_then(parse_date)
+                .map(|(epoch, _)| epoch),
+            sender,
+            addresses,
+            bulk,
+            size: raw.len(),
+        }
+    }
+}
+
+fn summarize(entries: &[Entry], identity: &[String]) -> Vec<ThreadSummary> {
+    let keys: Vec<ThreadKey> = entries.iter().map(|e| e.key.clone()).collect();
+    let epochs: Vec<Option<i64>> = entries.iter().map(|e| e.epoch).collect();
+    chronological_threads(&keys, &epochs)
+        .into_iter()
+        .enumerate()
+        .map(|(n, thread)| {
+            let messages: Vec<&Entry> = thread.iter().map(|&i| &entries[i]).collect();
+            let mut participants: Vec<String> = Vec::new();
+            for address in messages.iter().flat_map(|e| &e.addresses) {
+                if !participants.contains(address) {
+                    participants.push(address.clone());
+                }
+            }
+            let dated = messages.iter().filter_map(|e| e.epoch);
+            let last = messages.last();
+            let awaiting_reply = (!identity.is_empty()).then(|| {
+                last.is_some_and(|e| {
+                    !e.bulk && e.sender.as_ref().is_some_and(|s| !identity.contains(s))
+                })
+            });
+            ThreadSummary {
+                thread_id: n + 1,
+                subject: messages
+                    .first()
+                    .map(|e| e.key.subject.trim().to_string())
+                    .unwrap_or_default(),
+                participants,
+                message_count: messages.len(),
+                start: dated.clone().min().map(format_utc),
+                end: dated.max().map(format_utc),
+                total_bytes: messages.iter().map(|e| e.size).sum(),
+                last_sender: last.and_then(|e| e.sender.clone()),
+                awaiting_reply,
+            }
+        })
+        .collect()
+}
+
+/// Summarize every reconstructed thread of an mbox archive
+///
+/// Threads are grouped by Message-ID / In-Reply-To / References (with a
+/// subject fallback for replies missing them) and reported in order of their
+/// first message.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `identity` - The owner's address (several may be comma-separated);
+///   enables `awaiting_reply`
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * List of `ThreadSummary` (`thread_id`, `subject`, `participants`,
+///   `message_count`, `start`, `end`, `total_bytes`, `last_sender`,
+///   `awaiting_reply`)
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import thread_summaries
+/// open_loops = [t for t in thread_summaries("inbox.mbox", identity="me@example.com")
+///               if t.awaiting_reply]
+/// for t in open_loops:
+///     print(t.end, t.last_sender, t.subject)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, identity=None, from_line_mode="standard", progress=None))]
+pub fn thread_summaries(
+    py: Python<'_>,
+    mbox_path: &str,
+    identity: Option<&str>,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<ThreadSummary>> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let identity: Vec<String> = identity
+            .unwrap_or_default()
+            .split(',')
+            .map(|a| a.trim().to_lowercase())
+            .filter(|a| !a.is_empty())
+            .collect();
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let entries: Vec<Entry> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .map(|span| Entry::parse(&data[span]))
+            .collect();
+        progress.check("thread_summaries")?;
+        mmap.verify(&mode)?;
+        Ok(summarize(&entries, &identity))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(headers: &str) -> Entry {
+        Entry::parse(format!("{headers}\n\nbody\n").as_bytes())
+    }
+
+    #[test]
+    fn test_summarize() {
+        let entries = vec![
+            entry(
+                "From: Ann <ann@x.org>\nTo: me@home.org\nMessage-ID: <q@x>\n\
+                   Date: Mon, 1 Jan 2024 09:00:00 +0000\nSubject: Question",
+            ),
+            entry(
+                "From: me@home.org\nTo: ann@x.org, Bob <bob@y.org>\nIn-Reply-To: <q@x>\n\
+                   Date: Mon, 1 Jan 2024 10:00:00 +0000\nSubject: Re: Question",
+            ),
+            entry(
+                "From: news@shop.com\nTo: me@home.org\nList-Id: <deals>\n\
+                   Date: Sun, 31 Dec 2023 08:00:00 +0000\nSubject: Deals",
+            ),
+            entry(
+                "From: bob@y.org\nTo: me@home.org\nReferences: <q@x>\n\
+                   Date: Tue, 2 Jan 2024 08:00:00 +0000\nSubject: Re: Question",
+            ),
+        ];
+        let identity = vec!["me@home.org".to_string()];
+        let threads = summarize(&entries, &identity);
+        assert_eq!(threads.len(), 2);
+
+        let deals = &threads[0];
+        assert_eq!((deals.thread_id, deals.subject.as_str()), (1, "Deals"));
+        assert_eq!(deals.awaiting_reply, Some(false));
+
+        let question = &threads[1];
+        assert_eq!(question.thread_id, 2);
+        assert_eq!(question.subject, "Question");
+        assert_eq!(question.participants, ["ann@x.org", "me@home.org", "bob@y.org"]);
+        assert_eq!(question.message_count, 3);
+        assert_eq!(question.start.as_deref(), Some("2024-01-01T09:00:00Z"));
+        assert_eq!(question.end.as_deref(), Some("2024-01-02T08:00:00Z"));
+        assert_eq!(question.total_bytes, [0, 1, 3].iter().map(|&i| entries[i].size).sum::<usize>());
+        assert_eq!(question.last_sender.as_deref(), Some("bob@y.org"));
+        assert_eq!(question.awaiting_reply, Some(true));
+
+        assert_eq!(summarize(&entries, &[])[1].awaiting_reply, None);
+    }
+}
@@ -0,0 +1,430 @@
+//! Token counting for sizing LLM inputs and estimating cost.
+//!
+//! Text is first split exactly the way the GPT tokenizers pre-tokenize it
+//! (contractions, letter runs, 1-3 digit groups, punctuation runs, whitespace).
+//! With a tiktoken vocabulary file each piece is then byte-pair merged for an
+//! exact count; without one, each piece's merge count is estimated from its
+//! length and script, which avoids shipping multi-megabyte merge tables.
+
+use crate::batch::par_map;
+use crate::patterns::compile;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Tokenizer families with distinct pre-tokenization and vocabulary size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    /// GPT-2 / GPT-3 (`r50k_base`, `p50k_base`)
+    P50k,
+    /// GPT-3.5 / GPT-4 (`cl100k_base`)
+    Cl100k,
+    /// GPT-4o (`o200k_base`)
+    O200k,
+}
+
+impl Model {
+    /// Accept encoding names (`cl100k`, `cl100k_base`) and common model names
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "p50k" | "p50k_base" | "r50k" | "r50k_base" | "gpt2" | "gpt-3" => Ok(Self::P50k),
+            "cl100k" | "cl100k_base" | "gpt-4" | "gpt-3.5-turbo" => Ok(Self::Cl100k),
+            "o200k" | "o200k_base" | "gpt-4o" => Ok(Self::O200k),
+            other => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown tokenizer model: {other:?} (expected cl100k, o200k or p50k)"
+            ))),
+        }
+    }
+
+    /// Longest digit group the pre-tokenizer keeps together
+    const fn max_digits(self) -> usize {
+        match self {
+            Self::P50k => usize::MAX,
+            Self::Cl100k | Self::O200k => 3,
+        }
+    }
+}
+
+lazy_static! {
+    /// o200k's letter-run alternatives, which split at case changes and keep
+    /// contractions attached; they need no look-around, so the regex crate
+    /// matches them as tiktoken does
+    pub static ref O200K_WORD: Regex = compile(
+        "tokens.o200k_word",
+        r"^(?:[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?)",
+    );
+}
+
+/// Split text into pre-tokenization pieces (byte ranges) the way tiktoken's
+/// regex does, without needing look-around support
+fn pieces(text: &str, model: Model) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let end_of = |i: usize| chars.get(i).map_or(text.len(), |&(at, _)| at);
+    let is_newline = |c: char| c == '\r' || c == '\n';
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i].1;
+        let next = chars.get(i + 1).map(|&(_, n)| n);
+
+        // o200k: letter runs split at case changes, with contractions attached
+        if model == Model::O200k {
+            if let Some(m) = O200K_WORD.find(&text[chars[i].0..]) {
+                let end = chars[i].0 + m.end();
+                while i < chars.len() && chars[i].0 < end {
+                    i += 1;
+                }
+                out.push(&text[chars[start].0..end]);
+                continue;
+            }
+        }
+
+        // Contractions: 's 't 're 've 'm 'll 'd
+        if c == '\'' {
+            let rest = text[chars[i].0 + 1..].to_ascii_lowercase();
+            let len = ["ll", "re", "ve", "s", "t", "m", "d"]
+                .iter()
+                .find(|suffix| rest.starts_with(*suffix))
+                .map(|suffix| suffix.len());
+            if let Some(len) = len {
+                i += 1 + len;
+                out.push(&text[chars[start].0..end_of(i)]);
+                continue;
+            }
+        }
+
+        // Letter run with an optional single leading non-letter (usually a space)
+        let leads_letters =
+            !c.is_alphanumeric() && !is_newline(c) && next.is_some_and(char::is_alphabetic);
+        if c.is_alphabetic() || (leads_letters && (model != Model::P50k || c == ' ')) {
+            i += 1;
+            while i < chars.len() && chars[i].1.is_alphabetic() {
+                i += 1;
+            }
+            out.push(&text[chars[start].0..end_of(i)]);
+            continue;
+        }
+
+        // Digit groups (p50k also lets a space lead them)
+        let space_digit = model == Model::P50k && c == ' ' && next.is_some_and(char::is_numeric);
+        if c.is_numeric() || space_digit {
+            i += usize::from(space_digit);
+            let digits_start = i;
+            while i < chars.len()
+                && chars[i].1.is_numeric()
+                && i - digits_start < model.max_digits()
+            {
+                i += 1;
+            }
+            out.push(&text[chars[start].0..end_of(i)]);
+            continue;
+        }
+
+        // Punctuation run with an optional leading space and trailing newlines
+        // (and slashes, for o200k)
+        let is_punct = |ch: char| !ch.is_whitespace() && !ch.is_alphanumeric();
+        if is_punct(c) || (c == ' ' && next.is_some_and(is_punct)) {
+            i += 1;
+            while i < chars.len() && is_punct(chars[i].1) {
+                i += 1;
+            }
+            if model != Model::P50k {
+                let trailing = |ch: char| is_newline(ch) || (model == Model::O200k && ch == '/');
+                while i < chars.len() && trailing(chars[i].1) {
+                    i += 1;
+                }
+            }
+            out.push(&text[chars[start].0..end_of(i)]);
+            continue;
+        }
+
+        // Whitespace: a run ending in newlines, or all but the last space
+        // before a word (that space becomes the word's leading character)
+        let mut j = i;
+        while j < chars.len() && chars[j].1.is_whitespace() {
+            j += 1;
+        }
+        let last_newline = (i..j).rev().find(|&k| is_newline(chars[k].1));
+        i = match last_newline {
+            Some(k) if model != Model::P50k => k + 1,
+            _ if j < chars.len() && j - i > 1 => j - 1,
+            _ => j,
+        };
+        out.push(&text[chars[start].0..end_of(i)]);
+    }
+    out
+}
+
+/// Estimated merged-token count of one pre-tokenization piece
+fn estimate_piece(piece: &str, model: Model) -> usize {
+    let body = piece.trim_start_matches(' ');
+    let Some(first) = body.chars().next() else {
+        return 1; // a run of spaces
+    };
+    if first.is_whitespace() {
+        return match model {
+            Model::P50k => body.chars().count(),
+            Model::Cl100k | Model::O200k => 1,
+        };
+    }
+    if first.is_numeric() {
+        return match model {
+            Model::P50k => (body.len() + 1) / 2,
+            Model::Cl100k | Model::O200k => 1,
+        };
+    }
+    if !first.is_alphanumeric() && !body.chars().nth(1).is_some_and(char::is_alphabetic) {
+        let len = body.trim_end_matches(['\r', '\n']).chars().count();
+        return ((len + 1) / 2).max(1);
+    }
+    // Letters: short ASCII words are almost always a single token; long and
+    // non-Latin runs split further (CJK is about one token per character)
+    let (ascii, wide, other) =
+        body.chars()
+            .fold((0usize, 0usize, 0usize), |(a, w, o), ch| match ch.len_utf8() {
+                1 => (a + 1, w, o),
+                2 => (a, w, o + 1),
+                _ => (a, w + 1, o),
+            });
+    let long_word_extra = match model {
+        Model::P50k => (ascii.saturating_sub(5) + 3) / 4,
+        Model::Cl100k | Model::O200k => (ascii.saturating_sub(7) + 4) / 5,
+    };
+    let wide_tokens = match model {
+        Model::O200k => wide * 2 / 3,
+        Model::P50k => wide * 2,
+        Model::Cl100k => wide,
+    };
+    (usize::from(ascii > 0) + long_word_extra + wide_tokens + (other + 1) / 2).max(1)
+}
+
+/// A tiktoken vocabulary: byte sequence to merge rank
+pub struct Vocab {
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl Vocab {
+    /// Load a `.tiktoken` file (`<base64 token> <rank>` per line)
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(crate::paths::extended(Path::new(path)))?;
+        let mut ranks = HashMap::new();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.split_whitespace();
+            let token = fields.next().and_then(|t| STANDARD.decode(t).ok());
+            let rank = fields.next().and_then(|r| r.parse().ok());
+            match (token, rank) {
+                (Some(token), Some(rank)) => {
+                    ranks.insert(token, rank);
+                },
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("malformed vocabulary line: {line:?}"),
+                    ))
+                },
+            }
+        }
+        Ok(Self { ranks })
+    }
+
+    /// Exact number of tokens byte-pair encoding produces for one piece
+    fn count_piece(&self, piece: &[u8]) -> usize {
+        if piece.len() <= 1 || self.ranks.contains_key(piece) {
+            return piece.len().min(1);
+        }
+        // Boundaries between current parts; merge the lowest-ranked adjacent pair
+        let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..bounds.len() - 2)
+                .filter_map(|k| {
+                    self.ranks
+                        .get(&piece[bounds[k]..bounds[k + 2]])
+                        .map(|&rank| (rank, k))
+                })
+                .min();
+            match best {
+                Some((_, k)) => {
+                    bounds.remove(k + 1);
+                },
+                None => return bounds.len() - 1,
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// Loaded vocabularies keyed by path, so batches do not reparse the file
+    pub static ref VOCABS: Mutex<HashMap<String, Arc<Vocab>>> = Mutex::new(HashMap::new());
+}
+
+pub fn cached_vocab(path: &str) -> PyResult<Arc<Vocab>> {
+    let cached = VOCABS
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(path).cloned());
+    if let Some(vocab) = cached {
+        return Ok(vocab);
+    }
+    let vocab = Arc::new(Vocab::load(path).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to load vocabulary: {e}"))
+    })?);
+    if let Ok(mut cache) = VOCABS.lock() {
+        cache.insert(path.to_string(), Arc::clone(&vocab));
+    }
+    Ok(vocab)
+}
+
+/// Token count of `text`: exact with a vocabulary, estimated without
+pub fn count(text: &str, model: Model, vocab: Option<&Vocab>) -> usize {
+    pieces(text, model)
+        .into_iter()
+        .map(|piece| {
+            vocab.map_or_else(
+                || estimate_piece(piece, model),
+                |vocab| vocab.count_piece(piece.as_bytes()),
+            )
+        })
+        .sum()
+}
+
+/// Longest prefix of `text` that fits in `max_tokens` (estimated), cut on a
+/// pre-tokenization boundary so no word is split mid-token
+pub fn truncate(text: &str, max_tokens: usize, model: Model) -> &str {
+    let mut used = 0;
+    let mut end = 0;
+    for piece in pieces(text, model) {
+        used += estimate_piece(piece, model);
+        if used > max_tokens {
+            break;
+        }
+        end += piece.len();
+    }
+    &text[..end]
+}
+
+/// Estimated `cl100k` token count, used for chunk budgets
+pub fn estimate(text: &str) -> usize {
+    count(text, Model::Cl100k, None)
+}
+
+/// Count LLM tokens for many texts in parallel
+///
+/// Pre-tokenization matches the chosen model's tokenizer exactly. Without
+/// `vocab_path` the merge step is estimated per piece, which is close enough
+/// for cost and context-window planning; pass the model's `.tiktoken` file
+/// (the same file tiktoken downloads) for exact byte-pair-encoding counts.
+///
+/// # Arguments
+/// * `texts` - Texts to count
+/// * `model` - "cl100k" (default, GPT-3.5/4), "o200k" (GPT-4o) or "p50k" (GPT-3)
+/// * `vocab_path` - Optional tiktoken vocabulary file for exact counts
+///
+/// # Returns
+/// * Token count per text, in input order
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import estimate_tokens_batch
+/// counts = estimate_tokens_batch(bodies, model="cl100k")
+/// print(f"~{sum(counts) / 1e6:.1f}M tokens")
+/// ```
+#[pyfunction]
+#[pyo3(signature = (texts, model="cl100k", vocab_path=None))]
+pub fn estimate_tokens_batch(
+    py: Python<'_>,
+    texts: Vec<String>,
+    model: &str,
+    vocab_path: Option<&str>,
+) -> PyResult<Vec<usize>> {
+    py.allow_threads(|| {
+        let model = Model::parse(model)?;
+        let vocab = vocab_path.map(cached_vocab).transpose()?;
+        Ok(par_map(texts, |text| count(&text, model, vocab.as_deref())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pretokenization() {
+        let text = "Hello, world! It's 12345 apples.\n\n  Thanks";
+        assert_eq!(
+            pieces(text, Model::Cl100k),
+            vec![
+                "Hello", ",", " world", "!", " It", "'s", " ", "123", "45", " apples", ".\n\n",
+                " ", " Thanks"
+            ]
+        );
+        assert_eq!(pieces("x 12345\n\ny", Model::P50k), vec!["x", " 12345", "\n", "\n", "y"]);
+        assert_eq!(pieces("", Model::Cl100k), Vec::<&str>::new());
+
+        // o200k keeps contractions on the word, splits at case changes and
+        // lets a punctuation run absorb trailing newlines and slashes
+        assert_eq!(
+            pieces("Hello, world! It's HelloWorld HTTPServer 12345 ...\n/usr", Model::O200k),
+            vec![
+                "Hello",
+                ",",
+                " world",
+                "!",
+                " It's",
+                " Hello",
+                "World",
+                " HTTPServer",
+                " ",
+                "123",
+                "45",
+                " ...\n/",
+                "usr"
+            ]
+        );
+        assert_eq!(pieces("don'T x'LL", Model::O200k), vec!["don'T", " x'LL"]);
+    }
+
+    #[test]
+    fn test_estimates() {
+        assert_eq!(estimate("Hello, world!"), 4);
+        assert_eq!(estimate(""), 0);
+        assert!(estimate("internationalization") > 1);
+        assert_eq!(estimate("会议"), 2);
+        assert_eq!(truncate("one two three", 2, Model::Cl100k), "one two");
+        assert!(Model::parse("gpt-4o").is_ok());
+        assert!(Model::parse("llama").is_err());
+    }
+
+    #[test]
+    fn test_exact_bpe_with_vocab() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_tokens_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("tiny.tiktoken");
+            // Single bytes plus merges "ab", " a" and " ab"
+            let tokens: [&[u8]; 7] = [b"a", b"b", b"c", b" ", b"ab", b" a", b" ab"];
+            let lines: Vec<String> = tokens
+                .iter()
+                .enumerate()
+                .map(|(rank, token)| format!("{} {rank}", STANDARD.encode(token)))
+                .collect();
+            std::fs::write(&path, lines.join("\n")).unwrap();
+
+            let counts = estimate_tokens_batch(
+                py,
+                vec!["ab abc".to_string(), "cab".to_string()],
+                "cl100k",
+                path.to_str(),
+            )
+            .unwrap();
+            // "ab" + " abc" -> [" ab", "c"]; "cab" -> ["c", "ab"]
+            assert_eq!(counts, vec![3, 2]);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
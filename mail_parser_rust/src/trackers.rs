@@ -0,0 +1,461 @@
+//! Tracking detection in HTML bodies: pixels, tracker domains, wrapped links.
+//!
+//! Three signals are reported per message:
+//! - tracking pixels: remote images declared 1x1 (attributes or inline
+//!   style) or hidden with `display:none`,
+//! - images and links served from known tracking/analytics domains (an
+//!   embedded list that can be extended with [`register_tracker_domains`]),
+//! - wrapped links: redirectors that carry the real destination in their
+//!   query string, or click-tracking hosts such as `click.` subdomains.
+
+use crate::batch::par_map;
+use crate::html::{attr, tokenize, Token};
+use crate::mbox::{map_file, FromLineMode};
+use crate::mime::{first_address, Message};
+use crate::progress::Progress;
+use lazy_static::lazy_static;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::RwLock;
+
+/// Tracking and email-analytics domains known out of the box
+const KNOWN_TRACKERS: &[&str] = &[
+    "awstrack.me",
+    "bananatag.com",
+    "cmail19.com",
+    "cmail20.com",
+    "createsend1.com",
+    "ct.sendgrid.net",
+    "customeriomail.com",
+    "doubleclick.net",
+    "emltrk.com",
+    "exacttarget.com",
+    "getnotify.com",
+    "google-analytics.com",
+    "hubspotlinks.com",
+    "hs-analytics.net",
+    "list-manage.com",
+    "mailchimp.com",
+    "mailtrack.io",
+    "mandrillapp.com",
+    "mixmax.com",
+    "mktdns.com",
+    "mktoresp.com",
+    "pstmrk.it",
+    "rs6.net",
+    "sailthru.com",
+    "sendgrid.net",
+    "sparkpostmail.com",
+    "streak.com",
+    "superhuman.com",
+    "yesware.com",
+];
+
+/// Host prefixes used by click-tracking subdomains (`click.shop.com`)
+const CLICK_PREFIXES: &[&str] = &[
+    "click.",
+    "clicks.",
+    "track.",
+    "tracking.",
+    "links.",
+    "email.",
+];
+
+/// Query parameters that commonly carry the wrapped destination
+const TARGET_PARAMS: &[&str] = &[
+    "url",
+    "u",
+    "target",
+    "redirect",
+    "redirect_url",
+    "dest",
+    "r",
+    "q",
+];
+
+lazy_static! {
+    /// Tracker domains: the embedded list plus registered additions
+    pub static ref TRACKER_DOMAINS: RwLock<HashSet<String>> =
+        RwLock::new(KNOWN_TRACKERS.iter().map(ToString::to_string).collect());
+}
+
+/// Tracking found in one HTML body
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct TrackingReport {
+    /// `src` of each tracking pixel
+    #[pyo3(get)]
+    pub pixels: Vec<String>,
+    /// Known tracker domains referenced by images or links, sorted
+    #[pyo3(get)]
+    pub tracker_domains: Vec<String>,
+    /// Links that go through a redirector or click-tracking host
+    #[pyo3(get)]
+    pub wrapped_links: usize,
+    /// All `http(s)` links
+    #[pyo3(get)]
+    pub link_count: usize,
+    /// Pixels plus distinct tracker domains plus wrapped links
+    #[pyo3(get)]
+    pub intensity: usize,
+}
+
+/// Tracking totals for everything one address sent
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct SenderTracking {
+    /// Lowercased sender address
+    #[pyo3(get)]
+    pub sender: String,
+    #[pyo3(get)]
+    pub message_count: usize,
+    /// Messages with any tracking signal
+    #[pyo3(get)]
+    pub tracked_messages: usize,
+    #[pyo3(get)]
+    pub pixels: usize,
+    #[pyo3(get)]
+    pub wrapped_links: usize,
+    /// Tracker domains seen across the sender's messages, sorted
+    #[pyo3(get)]
+    pub tracker_domains: Vec<String>,
+    /// Mean per-message `intensity`
+    #[pyo3(get)]
+    pub avg_intensity: f64,
+}
+
+/// Lowercased host of an absolute `http(s)` (or protocol-relative) URL
+pub fn url_host(url: &str) -> Option<String> {
+    let lower = url.trim().to_ascii_lowercase();
+    let rest = lower
+        .strip_prefix("https://")
+        .or_else(|| lower.strip_prefix("http://"))
+        .or_else(|| lower.strip_prefix("//"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// The registered tracker domain `host` belongs to, if any
+fn tracker_domain(host: &str, domains: &HashSet<String>) -> Option<String> {
+    let mut candidate = host;
+    loop {
+        if domains.contains(candidate) {
+            return Some(candidate.to_string());
+        }
+        candidate = candidate.split_once('.')?.1;
+    }
+}
+
+/// Pixel size from `width`/`height` attributes or an inline style
+fn dimension(attrs: &str, name: &str) -> Option<u32> {
+    let parse = |v: &str| v.trim().trim_end_matches("px").trim().parse().ok();
+    attr(attrs, name).and_then(|v| parse(&v)).or_else(|| {
+        let style = attr(attrs, "style")?.to_ascii_lowercase();
+        style
+            .split(';')
+            .filter_map(|decl| decl.split_once(':'))
+            .find(|(prop, _)| prop.trim() == name)
+            .and_then(|(_, value)| parse(value))
+    })
+}
+
+fn is_hidden(attrs: &str) -> bool {
+    attr(attrs, "style").is_some_and(|style| {
+        let style: String = style.to_ascii_lowercase().split_whitespace().collect();
+        style.contains("display:none") || style.contains("visibility:hidden")
+    })
+}
+
+/// Whether a link hides its destination behind a redirector
+fn is_wrapped(href: &str, host: &str) -> bool {
+    let query = href.split_once('?').map_or("", |(_, q)| q);
+    let carries_url = query.split('&').any(|pair| {
+        pair.split_once('=').is_some_and(|(key, value)| {
+            let value = value.to_ascii_lowercase();
+            TARGET_PARAMS.iter().any(|p| key.eq_ignore_ascii_case(p))
+                && (value.starts_with("http") || value.starts_with("www."))
+        })
+    });
+    carries_url || CLICK_PREFIXES.iter().any(|p| host.starts_with(p))
+}
+
+fn analyze(html: &str) -> TrackingReport {
+    let domains = TRACKER_DOMAINS
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut report = TrackingReport::default();
+    let mut trackers = BTreeSet::new();
+    for token in tokenize(html) {
+        let Token::Open(name, attrs) = token else {
+            continue;
+        };
+        match name.as_str() {
+            "img" => {
+                let Some(src) = attr(attrs, "src") else {
+                    continue;
+                };
+                let Some(host) = url_host(&src) else {
+                    continue;
+                };
+                let tiny = dimension(attrs, "width").is_some_and(|w| w <= 1)
+                    && dimension(attrs, "height").is_some_and(|h| h <= 1);
+                if tiny || is_hidden(attrs) {
+                    report.pixels.push(src);
+                }
+                trackers.extend(tracker_domain(&host, &domains));
+            },
+            "a" => {
+                let Some(href) = attr(attrs, "href") else {
+                    continue;
+                };
+                let Some(host) = url_host(&href) else {
+                    continue;
+                };
+                report.link_count += 1;
+                if is_wrapped(&href, &host) {
+                    report.wrapped_links += 1;
+                }
+                trackers.extend(tracker_domain(&host, &domains));
+            },
+            _ => {},
+        }
+    }
+    report.tracker_domains = trackers.into_iter().collect();
+    report.intensity = report.pixels.len() + report.tracker_domains.len() + report.wrapped_links;
+    report
+}
+
+/// HTML bodies of a message (non-attachment `text/html` parts)
+fn html_bodies(msg: &Message<'_>) -> String {
+    msg.parts()
+        .iter()
+        .filter(|p| !p.is_attachment() && p.mime_type() == "text/html")
+        .map(crate::mime::Part::text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Mean of per-message intensities
+#[allow(clippy::cast_precision_loss)]
+fn mean(total: usize, count: usize) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+/// Add tracker domains to the detection list (or replace it)
+///
+/// Subdomains match too: registering `esp.example` flags `t.esp.example`.
+///
+/// # Arguments
+/// * `domains` - Domains to add
+/// * `replace` - Drop the current list (including the embedded defaults)
+///   first (default false)
+///
+/// # Returns
+/// * Number of domains now on the list
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import register_tracker_domains
+/// register_tracker_domains(["trk.vendor.example", "open.esp.example"])
+/// ```
+#[pyfunction]
+#[pyo3(signature = (domains, replace=false))]
+pub fn register_tracker_domains(domains: Vec<String>, replace: bool) -> PyResult<usize> {
+    let mut list = TRACKER_DOMAINS.write().map_err(|_| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Tracker list lock poisoned")
+    })?;
+    if replace {
+        list.clear();
+    }
+    list.extend(
+        domains
+            .into_iter()
+            .map(|d| d.trim().trim_end_matches('.').to_ascii_lowercase())
+            .filter(|d| !d.is_empty()),
+    );
+    Ok(list.len())
+}
+
+/// Detect tracking pixels, tracker domains and wrapped links in an HTML body
+///
+/// # Arguments
+/// * `html` - HTML body
+///
+/// # Returns
+/// * `TrackingReport` with `pixels`, `tracker_domains`, `wrapped_links`,
+///   `link_count` and `intensity`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import detect_trackers_fast
+/// report = detect_trackers_fast(html)
+/// if report.pixels:
+///     print("open tracking via", report.pixels[0])
+/// ```
+#[pyfunction]
+pub fn detect_trackers_fast(html: &str) -> TrackingReport {
+    analyze(html)
+}
+
+/// Batch version of [`detect_trackers_fast`], processed in parallel
+///
+/// # Arguments
+/// * `htmls` - HTML bodies
+///
+/// # Returns
+/// * One `TrackingReport` per body
+#[pyfunction]
+pub fn detect_trackers_batch(py: Python<'_>, htmls: Vec<String>) -> Vec<TrackingReport> {
+    py.allow_threads(|| par_map(htmls, |html| analyze(&html)))
+}
+
+/// Per-sender tracking intensity across an mbox
+///
+/// Every message's HTML parts are analyzed as in `detect_trackers_fast`;
+/// messages without HTML count as untracked.
+///
+/// # Arguments
+/// * `mbox_path` - Path to the mbox file
+/// * `from_line_mode` - Message separator rule: "standard" (default),
+///   "strict", "lenient", "permissive" or a custom regex (see
+///   `count_messages_fast`)
+/// * `progress` - Callable receiving `(bytes_processed, total_bytes)`
+///   while messages are read; returning False cancels the walk
+///
+/// # Returns
+/// * One `SenderTracking` per sender, highest average intensity first
+///
+/// # Raises
+/// * `ScanCancelled` if `progress` returned False (or what it raised)
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import tracking_report
+/// for s in tracking_report("inbox.mbox")[:10]:
+///     print(s.sender, s.tracked_messages, s.message_count, s.tracker_domains)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (mbox_path, from_line_mode="standard", progress=None))]
+pub fn tracking_report(
+    py: Python<'_>,
+    mbox_path: &str,
+    from_line_mode: &str,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<SenderTracking>> {
+    py.allow_threads(|| {
+        let mode = FromLineMode::parse(from_line_mode)?;
+        let mmap = map_file(mbox_path)?;
+        let progress = Progress::new(progress, mmap.len());
+        let data = &mmap[..];
+        let observed: Vec<(String, TrackingReport)> = mmap
+            .spans(&mode)
+            .into_par_iter()
+            .filter(|span| progress.message(span))
+            .filter_map(|span| {
+                let msg = Message::parse(&data[span]);
+                let sender = msg.header("From").and_then(first_address)?;
+                Some((sender, analyze(&html_bodies(&msg))))
+            })
+            .collect();
+        progress.check("tracking_report")?;
+        mmap.verify(&mode)?;
+
+        let mut by_sender: HashMap<String, (SenderTracking, BTreeSet<String>, usize)> =
+            HashMap::new();
+        for (sender, report) in observed {
+            let (summary, domains, intensity) =
+                by_sender.entry(sender.clone()).or_insert_with(|| {
+                    let summary = SenderTracking {
+                        sender,
+                        ..SenderTracking::default()
+                    };
+                    (summary, BTreeSet::new(), 0)
+                });
+            summary.message_count += 1;
+            summary.tracked_messages += usize::from(report.intensity > 0);
+            summary.pixels += report.pixels.len();
+            summary.wrapped_links += report.wrapped_links;
+            domains.extend(report.tracker_domains);
+            *intensity += report.intensity;
+        }
+        let mut senders: Vec<SenderTracking> = by_sender
+            .into_values()
+            .map(|(mut summary, domains, intensity)| {
+                summary.tracker_domains = domains.into_iter().collect();
+                summary.avg_intensity = mean(intensity, summary.message_count);
+                summary
+            })
+            .collect();
+        senders.sort_by(|a, b| {
+            b.avg_intensity
+                .total_cmp(&a.avg_intensity)
+                .then_with(|| a.sender.cmp(&b.sender))
+        });
+        Ok(senders)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let html = r#"<p>Hi</p>
+            <img src="https://shop.example/logo.png" width="120" height="40">
+            <img src="https://t.list-manage.com/open?id=1" width="1" height="1" alt="">
+            <img src='https://cdn.example/o.gif' style="width: 1px; height:1px">
+            <img src="https://cdn.example/h.gif" style="display: none">
+            <img src="cid:inline@x" width="1" height="1">
+            <a href="https://click.shop.example/ls/abc">Shop</a>
+            <a href="https://r.example/?url=https%3A%2F%2Fshop.example">Sale</a>
+            <a href="https://shop.example/plain?id=2">Plain</a>
+            <a href="mailto:help@shop.example">Mail</a>"#;
+        let report = analyze(html);
+        assert_eq!(
+            report.pixels,
+            [
+                "https://t.list-manage.com/open?id=1",
+                "https://cdn.example/o.gif",
+                "https://cdn.example/h.gif",
+            ]
+        );
+        assert_eq!(report.tracker_domains, ["list-manage.com"]);
+        assert_eq!(report.wrapped_links, 2);
+        assert_eq!(report.link_count, 3);
+        assert_eq!(report.intensity, 6);
+        assert_eq!(analyze("<p>No tracking</p>").intensity, 0);
+    }
+
+    #[test]
+    fn test_tracking_report() {
+        crate::with_py(|py| {
+            let dir = std::env::temp_dir().join(format!("mpr_trackers_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("in.mbox");
+            std::fs::write(
+                &path,
+                "From a Mon Jan  1 00:00:00 2024\nFrom: news@shop.example\n\
+             Content-Type: text/html\n\n<img src=\"https://x.example/p\" width=1 height=1>\n\n\
+             From b Mon Jan  1 00:00:00 2024\nFrom: news@shop.example\n\nPlain text\n\n\
+             From c Mon Jan  1 00:00:00 2024\nFrom: ann@example.com\n\nHello\n",
+            )
+            .unwrap();
+            let senders = tracking_report(py, path.to_str().unwrap(), "standard", None).unwrap();
+            assert_eq!(senders.len(), 2);
+            assert_eq!(senders[0].sender, "news@shop.example");
+            assert_eq!(senders[0].message_count, 2);
+            assert_eq!(senders[0].tracked_messages, 1);
+            assert!((senders[0].avg_intensity - 0.5).abs() < f64::EPSILON);
+            assert_eq!(senders[1].tracked_messages, 0);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,204 @@
+//! Chunk-size autotuning for the parallel separator scan.
+//!
+//! The best chunk size for scanning a memory-mapped mbox depends on the
+//! storage behind it: small chunks keep every core busy when pages come
+//! from the page cache or solid-state drives, while spinning disks and
+//! network mounts prefer large sequential runs that readahead can follow.
+//! The first large file scanned on a device is split into consecutive probe
+//! regions, each scanned with one candidate size and timed; the rest of the
+//! file, and every later file on that device, uses the fastest. Probe
+//! regions are part of the real scan, so tuning costs no extra reads.
+//! Results are kept for the life of the process and reported by
+//! `scan_tuning`.
+
+use crate::mbox::{line_start_from, starts_chunked, FromLineMode};
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Chunk size before (or without) tuning
+pub const DEFAULT_CHUNK: usize = 4 << 20;
+
+/// Chunk sizes probed, smallest first
+const CANDIDATES: [usize; 3] = [1 << 20, DEFAULT_CHUNK, 16 << 20];
+
+/// Smallest probe region; grown so the largest candidate still spreads
+/// over every worker
+const MIN_PROBE_BYTES: usize = 64 << 20;
+
+/// Chunk sizes chosen so far, by device
+static TUNED: Mutex<BTreeMap<u64, ScanTuning>> = Mutex::new(BTreeMap::new());
+
+/// Chunk size chosen for one storage device
+#[derive(Serialize, Clone, Debug, Default)]
+#[pyclass]
+pub struct ScanTuning {
+    /// Device number of the storage (`st_dev`)
+    #[pyo3(get)]
+    pub device: u64,
+    /// Chunk size every scan on this device now uses, in bytes
+    #[pyo3(get)]
+    pub chunk_bytes: usize,
+    /// `(chunk_bytes, mb_per_s)` measured for each candidate
+    #[pyo3(get)]
+    pub probes: Vec<(usize, f64)>,
+    /// Bytes scanned per probe
+    #[pyo3(get)]
+    pub probe_bytes: usize,
+    /// File whose scan did the probing
+    #[pyo3(get)]
+    pub probed_path: String,
+}
+
+#[pymethods]
+impl ScanTuning {
+    fn __repr__(&self) -> String {
+        format!(
+            "ScanTuning(device={}, chunk_bytes={}, probes={:?})",
+            self.device, self.chunk_bytes, self.probes
+        )
+    }
+}
+
+/// Chunk size chosen for `device`, if it has been tuned
+pub fn chunk_for(device: u64) -> Option<usize> {
+    TUNED
+        .lock()
+        .ok()
+        .and_then(|tuned| tuned.get(&device).map(|t| t.chunk_bytes))
+}
+
+/// Bytes each probe scans with `threads` workers
+fn probe_bytes(threads: usize) -> usize {
+    MIN_PROBE_BYTES.max(CANDIDATES[CANDIDATES.len() - 1].saturating_mul(threads))
+}
+
+/// Message starts of a whole mapped file on `device` (not `Recover` mode),
+/// probing chunk sizes first if the device is untuned and the file is large
+/// enough to tell them apart
+pub fn tuned_starts(data: &[u8], mode: &FromLineMode, device: u64, path: &str) -> Vec<usize> {
+    tune_with(data, mode, device, path, probe_bytes(rayon::current_num_threads()))
+}
+
+fn tune_with(
+    data: &[u8],
+    mode: &FromLineMode,
+    device: u64,
+    path: &str,
+    region: usize,
+) -> Vec<usize> {
+    if let Some(chunk) = chunk_for(device) {
+        return starts_chunked(data, 0, data.len(), mode, chunk);
+    }
+    // Probes should cover at most half the file
+    if data.len() / 2 < region.saturating_mul(CANDIDATES.len()) {
+        return starts_chunked(data, 0, data.len(), mode, DEFAULT_CHUNK);
+    }
+    let mut starts = Vec::new();
+    let mut probes = Vec::with_capacity(CANDIDATES.len());
+    let mut at = 0;
+    for chunk in CANDIDATES {
+        let end = line_start_from(data, at + region);
+        let timer = Instant::now();
+        starts.extend(starts_chunked(data, at, end, mode, chunk));
+        let seconds = timer.elapsed().as_secs_f64().max(1e-9);
+        #[allow(clippy::cast_precision_loss)]
+        probes.push((chunk, (end - at) as f64 / 1e6 / seconds));
+        at = end;
+    }
+    let best = probes
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(DEFAULT_CHUNK, |probe| probe.0);
+    starts.extend(starts_chunked(data, at, data.len(), mode, best));
+    if let Ok(mut tuned) = TUNED.lock() {
+        tuned.entry(device).or_insert_with(|| ScanTuning {
+            device,
+            chunk_bytes: best,
+            probes,
+            probe_bytes: region,
+            probed_path: path.to_string(),
+        });
+    }
+    starts
+}
+
+/// Chunk sizes picked for the storage devices scanned so far
+///
+/// Whole-file scans of memory-mapped mboxes split the file into chunks that
+/// are searched for separators on all cores. The first file of at least
+/// about 400 MB (more on machines with many cores) scanned on a device is
+/// used to time 1, 4 and 16 MiB chunks on consecutive parts of the scan;
+/// the fastest is used from then on for every file on that device. Smaller
+/// files, and platforms without device numbers, use 4 MiB.
+///
+/// # Arguments
+/// * `reset` - Forget the choices (after returning them) so the next large
+///   scan on each device probes again, e.g. after the storage was changed
+///
+/// # Returns
+/// * List of `ScanTuning`: `device`, chosen `chunk_bytes`, the measured
+///   `probes` as `(chunk_bytes, mb_per_s)`, `probe_bytes` and `probed_path`
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import count_messages_fast, scan_tuning
+/// count_messages_fast("/mnt/nfs/archive.mbox")
+/// for t in scan_tuning():
+///     print(t.device, t.chunk_bytes, t.probes)
+/// ```
+#[pyfunction]
+#[pyo3(signature = (reset=false))]
+pub fn scan_tuning(reset: bool) -> Vec<ScanTuning> {
+    let Ok(mut tuned) = TUNED.lock() else {
+        return Vec::new();
+    };
+    let current = tuned.values().cloned().collect();
+    if reset {
+        tuned.clear();
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probing_matches_plain_scan() {
+        let message = "From a@x Mon Jan  1 00:00:00 2024\nSubject: s\n\nbody line\n\n";
+        let data = message.repeat(400).into_bytes();
+        let mode = FromLineMode::Standard;
+        let expected = starts_chunked(&data, 0, data.len(), &mode, DEFAULT_CHUNK);
+        // A device number no real file uses
+        let device = u64::MAX - 7;
+
+        // Too small to probe: nothing recorded
+        assert_eq!(tune_with(&data, &mode, device, "t.mbox", data.len()), expected);
+        assert_eq!(chunk_for(device), None);
+
+        let region = 1000;
+        assert_eq!(tune_with(&data, &mode, device, "t.mbox", region), expected);
+        let chosen = chunk_for(device).unwrap();
+        assert!(CANDIDATES.contains(&chosen));
+        let tuning = scan_tuning(false)
+            .into_iter()
+            .find(|t| t.device == device)
+            .unwrap();
+        assert_eq!(tuning.probes.len(), CANDIDATES.len());
+        assert_eq!(tuning.probe_bytes, region);
+        // Tuned devices scan without probing again
+        assert_eq!(tune_with(&data, &mode, device, "u.mbox", region), expected);
+        assert_eq!(
+            scan_tuning(false)
+                .iter()
+                .find(|t| t.device == device)
+                .unwrap()
+                .probed_path,
+            "t.mbox"
+        );
+        assert!(probe_bytes(64) >= 16 << 20 << 6);
+    }
+}
@@ -0,0 +1,119 @@
+//! Stable message identifiers that survive archive re-ordering.
+//!
+//! The UID is a hash of canonicalized identity headers rather than a position,
+//! so the same message gets the same UID in any archive it appears in, in any
+//! order, and whatever separator or line-ending conventions were used.
+
+use crate::date::parse_date;
+use crate::mime::{first_address, Message};
+use crate::rethread::hex_prefix;
+use crate::thread::parse_ids;
+use pyo3::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// Bumped if canonicalization ever changes, so old and new UIDs never collide
+const UID_VERSION: &str = "uid1";
+
+/// UID length in bytes (32 hex characters)
+const UID_BYTES: usize = 16;
+
+/// Body bytes with CRLF normalized and trailing whitespace removed
+fn canonical_body(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    for line in body.split(|&b| b == b'\n') {
+        let end = line
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |p| p + 1);
+        out.extend_from_slice(&line[..end]);
+        out.push(b'\n');
+    }
+    let end = out.iter().rposition(|&b| b != b'\n').map_or(0, |p| p + 1);
+    out.truncate(end);
+    out
+}
+
+/// Stable identifier of one raw message (see [`compute_message_uid`])
+pub fn message_uid(raw: &[u8]) -> String {
+    uid_of(&Message::parse(raw))
+}
+
+/// Stable identifier of an already parsed message
+pub fn uid_of(msg: &Message<'_>) -> String {
+    let message_id = msg
+        .header("Message-ID")
+        .and_then(|v| parse_ids(v).into_iter().next())
+        .map(|id| id.to_lowercase());
+    let date = msg.header("Date").map(|value| {
+        parse_date(value).map_or_else(
+            || value.split_whitespace().collect::<Vec<_>>().join(" "),
+            |(epoch, _)| epoch.to_string(),
+        )
+    });
+    let from = msg.header("From").and_then(first_address);
+
+    let mut hasher = Sha256::new();
+    for field in [
+        Some(UID_VERSION.to_string()),
+        message_id.clone(),
+        date,
+        from,
+    ] {
+        hasher.update(field.unwrap_or_default().as_bytes());
+        hasher.update([0]);
+    }
+    if message_id.is_none() {
+        hasher.update(Sha256::digest(canonical_body(msg.body)));
+    }
+    hex_prefix(&hasher.finalize(), UID_BYTES)
+}
+
+/// Compute a stable identifier for a message
+///
+/// The UID hashes the Message-ID (lowercased), the Date (as a UTC instant,
+/// so time zone and formatting differences do not matter) and the From
+/// address. Messages without a Message-ID also include a hash of the body
+/// (line endings and trailing whitespace normalized), so distinct messages
+/// from the same sender at the same second stay distinct. The mbox `From `
+/// line is ignored.
+///
+/// # Arguments
+/// * `raw_bytes` - Raw message, with or without the mbox `From ` line
+///
+/// # Returns
+/// * 32-character lowercase hex string
+///
+/// # Example
+/// ```python
+/// from mail_parser_rust import compute_message_uid
+/// uid = compute_message_uid(raw)
+/// seen.setdefault(uid, raw)
+/// ```
+#[pyfunction]
+pub fn compute_message_uid(raw_bytes: &[u8]) -> String {
+    message_uid(raw_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_uid() {
+        let a = "From x Mon Jan  1 00:00:00 2024\nFrom: Ann <Ann@Example.com>\n\
+                 Date: Mon, 1 Jan 2024 12:00:00 +0000\nMessage-ID: <ABC@x>\n\nHi\n";
+        let b = "From: ann@example.com\r\nMessage-ID:  <abc@x> \r\n\
+                 Date: Mon, 01 Jan 2024 13:00:00 +0100\r\n\r\nDifferent body\r\n";
+        assert_eq!(message_uid(a.as_bytes()), message_uid(b.as_bytes()));
+        assert_eq!(message_uid(a.as_bytes()).len(), 32);
+        let other_id = a.replace("ABC@x", "DEF@x");
+        assert_ne!(message_uid(a.as_bytes()), message_uid(other_id.as_bytes()));
+
+        // Without a Message-ID the body decides
+        let bare = "From: ann@example.com\nDate: Mon, 1 Jan 2024 12:00:00 +0000\n\nHi  \n\n";
+        let crlf = "From: ann@example.com\r\nDate: Mon, 1 Jan 2024 12:00:00 +0000\r\n\r\nHi\r\n";
+        let bye = "From: ann@example.com\nDate: Mon, 1 Jan 2024 12:00:00 +0000\n\nBye\n";
+        assert_eq!(message_uid(bare.as_bytes()), message_uid(crlf.as_bytes()));
+        assert_ne!(message_uid(bare.as_bytes()), message_uid(bye.as_bytes()));
+    }
+}